@@ -0,0 +1,243 @@
+//! Binary encoding of fixed-width columns straight from arrow buffers.
+//!
+//! The generic path in [`crate::encoder`] downcasts the array and goes
+//! through `ToSql` for every cell. For fixed-width types the postgres binary
+//! representation is just the big-endian value (with an epoch shift for
+//! dates and timestamps), so these columns are downcast once per batch and
+//! their values written directly into the row buffer.
+
+#[cfg(not(feature = "datafusion"))]
+use arrow::{array::*, buffer::*, datatypes::*};
+#[cfg(feature = "datafusion")]
+use datafusion::arrow::{array::*, buffer::*, datatypes::*};
+
+use bytes::{BufMut, BytesMut};
+use postgres_types::Type;
+
+// days and microseconds between the unix epoch and the postgres epoch
+// (2000-01-01)
+const PG_EPOCH_DAYS: i32 = 10_957;
+const PG_EPOCH_MICROS: i64 = 946_684_800_000_000;
+
+enum Values {
+    Bool(BooleanBuffer),
+    Int8(ScalarBuffer<i8>),
+    Int16(ScalarBuffer<i16>),
+    Int32(ScalarBuffer<i32>),
+    Int64(ScalarBuffer<i64>),
+    Float32(ScalarBuffer<f32>),
+    Float64(ScalarBuffer<f64>),
+    Date32(ScalarBuffer<i32>),
+    Timestamp(ScalarBuffer<i64>, TimeUnit),
+}
+
+/// A fixed-width column encoded in binary format without going through
+/// `ToSql`
+pub(crate) struct BinaryColumn {
+    nulls: Option<NullBuffer>,
+    values: Values,
+}
+
+impl BinaryColumn {
+    /// Prepare the fast path for `array`, `None` if its type has no fixed
+    /// binary representation as `pg_type`
+    pub(crate) fn try_new(array: &ArrayRef, pg_type: &Type) -> Option<Self> {
+        let values = match (array.data_type(), pg_type) {
+            (DataType::Boolean, &Type::BOOL) => Values::Bool(array.as_boolean().values().clone()),
+            (DataType::Int8, &Type::CHAR) => {
+                Values::Int8(array.as_primitive::<Int8Type>().values().clone())
+            }
+            (DataType::Int16, &Type::INT2) => {
+                Values::Int16(array.as_primitive::<Int16Type>().values().clone())
+            }
+            (DataType::Int32, &Type::INT4) => {
+                Values::Int32(array.as_primitive::<Int32Type>().values().clone())
+            }
+            (DataType::Int64, &Type::INT8) => {
+                Values::Int64(array.as_primitive::<Int64Type>().values().clone())
+            }
+            (DataType::Float32, &Type::FLOAT4) => {
+                Values::Float32(array.as_primitive::<Float32Type>().values().clone())
+            }
+            (DataType::Float64, &Type::FLOAT8) => {
+                Values::Float64(array.as_primitive::<Float64Type>().values().clone())
+            }
+            (DataType::Date32, &Type::DATE) => {
+                Values::Date32(array.as_primitive::<Date32Type>().values().clone())
+            }
+            (DataType::Timestamp(unit, None), &Type::TIMESTAMP)
+            | (DataType::Timestamp(unit, Some(_)), &Type::TIMESTAMPTZ) => {
+                let values = match unit {
+                    TimeUnit::Second => array.as_primitive::<TimestampSecondType>().values(),
+                    TimeUnit::Millisecond => {
+                        array.as_primitive::<TimestampMillisecondType>().values()
+                    }
+                    TimeUnit::Microsecond => {
+                        array.as_primitive::<TimestampMicrosecondType>().values()
+                    }
+                    TimeUnit::Nanosecond => {
+                        array.as_primitive::<TimestampNanosecondType>().values()
+                    }
+                };
+                Values::Timestamp(values.clone(), *unit)
+            }
+            _ => return None,
+        };
+
+        Some(BinaryColumn {
+            nulls: array.logical_nulls(),
+            values,
+        })
+    }
+
+    /// Write the length-prefixed value at `idx` into `buf`, returns false
+    /// without writing anything if the value is out of the range postgres
+    /// can represent
+    pub(crate) fn encode(&self, idx: usize, buf: &mut BytesMut) -> bool {
+        if self.nulls.as_ref().is_some_and(|nulls| nulls.is_null(idx)) {
+            buf.put_i32(-1);
+            return true;
+        }
+
+        match &self.values {
+            Values::Bool(values) => {
+                buf.put_i32(1);
+                buf.put_u8(values.value(idx) as u8);
+            }
+            Values::Int8(values) => {
+                buf.put_i32(1);
+                buf.put_i8(values[idx]);
+            }
+            Values::Int16(values) => {
+                buf.put_i32(2);
+                buf.put_i16(values[idx]);
+            }
+            Values::Int32(values) => {
+                buf.put_i32(4);
+                buf.put_i32(values[idx]);
+            }
+            Values::Int64(values) => {
+                buf.put_i32(8);
+                buf.put_i64(values[idx]);
+            }
+            Values::Float32(values) => {
+                buf.put_i32(4);
+                buf.put_f32(values[idx]);
+            }
+            Values::Float64(values) => {
+                buf.put_i32(8);
+                buf.put_f64(values[idx]);
+            }
+            Values::Date32(values) => {
+                let Some(days) = values[idx].checked_sub(PG_EPOCH_DAYS) else {
+                    return false;
+                };
+                buf.put_i32(4);
+                buf.put_i32(days);
+            }
+            Values::Timestamp(values, unit) => {
+                let value = values[idx];
+                // sub-microsecond precision is truncated towards zero, like
+                // chrono does for the generic path
+                let micros = match unit {
+                    TimeUnit::Second => value.checked_mul(1_000_000),
+                    TimeUnit::Millisecond => value.checked_mul(1_000),
+                    TimeUnit::Microsecond => Some(value),
+                    TimeUnit::Nanosecond => Some(value / 1_000),
+                };
+                let Some(micros) = micros.and_then(|m| m.checked_sub(PG_EPOCH_MICROS)) else {
+                    return false;
+                };
+                buf.put_i32(8);
+                buf.put_i64(micros);
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use pgwire::api::results::{DataRowEncoder, FieldFormat, FieldInfo};
+
+    use super::*;
+    use crate::datatypes::into_pg_type;
+    use crate::encoder::encode_value;
+
+    #[test]
+    fn test_same_bytes_as_generic_encoder() {
+        let arrays: Vec<ArrayRef> = vec![
+            Arc::new(BooleanArray::from(vec![Some(true), None, Some(false)])),
+            Arc::new(Int8Array::from(vec![Some(-3), Some(7), None])),
+            Arc::new(Int16Array::from(vec![Some(i16::MIN), None, Some(12)])),
+            Arc::new(Int32Array::from(vec![None, Some(-1), Some(i32::MAX)])),
+            Arc::new(Int64Array::from(vec![Some(1), Some(i64::MIN), None])),
+            Arc::new(Float32Array::from(vec![Some(1.5), Some(f32::NAN), None])),
+            Arc::new(Float64Array::from(vec![None, Some(-0.25), Some(1e300)])),
+            Arc::new(Date32Array::from(vec![Some(0), Some(-719_162), None])),
+            Arc::new(TimestampSecondArray::from(vec![
+                Some(-86_400),
+                None,
+                Some(1),
+            ])),
+            Arc::new(TimestampMillisecondArray::from(vec![
+                Some(1_700_000_000_123),
+                Some(-1),
+                None,
+            ])),
+            Arc::new(
+                TimestampMicrosecondArray::from(vec![Some(1_700_000_000_123_456), None, Some(0)])
+                    .with_timezone("+02:00"),
+            ),
+            Arc::new(TimestampNanosecondArray::from(vec![
+                Some(1_700_000_000_123_456_789),
+                Some(-1_500),
+                None,
+            ])),
+            // sliced arrays keep their offset
+            Arc::new(Int32Array::from(vec![Some(9), None, Some(10), Some(11)]).slice(1, 3)),
+        ];
+
+        for array in arrays {
+            let pg_type = into_pg_type(array.data_type()).unwrap();
+            let column = BinaryColumn::try_new(&array, &pg_type).expect("fixed-width type");
+            let fields = Arc::new(vec![FieldInfo::new(
+                "c".to_string(),
+                None,
+                None,
+                pg_type.clone(),
+                FieldFormat::Binary,
+            )]);
+
+            for idx in 0..array.len() {
+                let mut fast = BytesMut::new();
+                assert!(column.encode(idx, &mut fast));
+
+                let mut encoder = DataRowEncoder::new(fields.clone());
+                encode_value(&mut encoder, &array, idx, &pg_type, FieldFormat::Binary).unwrap();
+                let generic = encoder.finish().unwrap();
+
+                assert_eq!(fast, generic.data, "{} at {idx}", array.data_type());
+            }
+        }
+    }
+
+    #[test]
+    fn test_fallback_types() {
+        let utf8: ArrayRef = Arc::new(StringArray::from(vec!["a"]));
+        assert!(BinaryColumn::try_new(&utf8, &Type::TEXT).is_none());
+
+        // type overridden to something else than the arrow type maps to
+        let int: ArrayRef = Arc::new(Int32Array::from(vec![1]));
+        assert!(BinaryColumn::try_new(&int, &Type::INT8).is_none());
+
+        // out of the postgres range
+        let date: ArrayRef = Arc::new(Date32Array::from(vec![i32::MIN]));
+        let column = BinaryColumn::try_new(&date, &Type::DATE).unwrap();
+        let mut buf = BytesMut::new();
+        assert!(!column.encode(0, &mut buf));
+        assert!(buf.is_empty());
+    }
+}
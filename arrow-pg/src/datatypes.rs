@@ -12,11 +12,37 @@ use pgwire::error::{ErrorInfo, PgWireError, PgWireResult};
 use pgwire::messages::data::DataRow;
 use postgres_types::Kind;
 
-use crate::row_encoder::RowEncoder;
+use crate::row_encoder::{RowEncoder, DEFAULT_MAX_FIELD_SIZE};
 
 #[cfg(feature = "datafusion")]
 pub mod df;
 
+/// Key of the field metadata naming the postgres type of a column, for the
+/// types emulated over other arrow types
+///
+/// - `int2vector` over `List<Int16>` and `oidvector` over `List<UInt32>`,
+///   encoded in text as their elements separated by spaces
+/// - `anyarray` over `Utf8` holding the text of the array
+pub const PG_TYPE_METADATA_KEY: &str = "pg_type";
+
+/// The postgres type of `field`, the type named in its metadata under
+/// [`PG_TYPE_METADATA_KEY`] if emulated over its arrow type, and
+/// [`into_pg_type`] of its arrow type otherwise
+pub fn field_into_pg_type(field: &Field) -> PgWireResult<Type> {
+    let pg_type = field.metadata().get(PG_TYPE_METADATA_KEY);
+    Ok(match (pg_type.map(String::as_str), field.data_type()) {
+        (Some("int2vector"), DataType::List(item)) if item.data_type() == &DataType::Int16 => {
+            Type::INT2_VECTOR
+        }
+        (Some("oidvector"), DataType::List(item)) if item.data_type() == &DataType::UInt32 => {
+            Type::OID_VECTOR
+        }
+        (Some("anyarray"), DataType::Utf8) => Type::ANYARRAY,
+        (Some("oid"), DataType::Int32 | DataType::UInt32) => Type::OID,
+        (_, data_type) => into_pg_type(data_type)?,
+    })
+}
+
 pub fn into_pg_type(arrow_type: &DataType) -> PgWireResult<Type> {
     Ok(match arrow_type {
         DataType::Null => Type::UNKNOWN,
@@ -117,7 +143,7 @@ pub fn arrow_schema_to_pg_fields(schema: &Schema, format: &Format) -> PgWireResu
         .iter()
         .enumerate()
         .map(|(idx, f)| {
-            let pg_type = into_pg_type(f.data_type())?;
+            let pg_type = field_into_pg_type(f)?;
             Ok(FieldInfo::new(
                 f.name().into(),
                 None,
@@ -133,6 +159,16 @@ pub fn encode_recordbatch(
     fields: Arc<Vec<FieldInfo>>,
     record_batch: RecordBatch,
 ) -> Box<impl Iterator<Item = PgWireResult<DataRow>>> {
-    let mut row_stream = RowEncoder::new(record_batch, fields);
+    encode_recordbatch_with_max_field_size(fields, record_batch, DEFAULT_MAX_FIELD_SIZE)
+}
+
+/// Encode the rows of `record_batch` like `encode_recordbatch`, failing rows
+/// with a value of more than `max_field_size` bytes
+pub fn encode_recordbatch_with_max_field_size(
+    fields: Arc<Vec<FieldInfo>>,
+    record_batch: RecordBatch,
+    max_field_size: usize,
+) -> Box<impl Iterator<Item = PgWireResult<DataRow>>> {
+    let mut row_stream = RowEncoder::new(record_batch, fields).with_max_field_size(max_field_size);
     Box::new(std::iter::from_fn(move || row_stream.next_row()))
 }
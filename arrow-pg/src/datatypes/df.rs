@@ -2,45 +2,294 @@ use std::iter;
 use std::sync::Arc;
 
 use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, NaiveTime, Timelike};
-use datafusion::arrow::datatypes::{DataType, Date32Type};
+use datafusion::arrow::datatypes::{DataType, Date32Type, TimeUnit};
 use datafusion::arrow::record_batch::RecordBatch;
+use datafusion::common::runtime::SpawnedTask;
 use datafusion::common::ParamValues;
+use datafusion::execution::{SendableRecordBatchStream, TaskContext};
+use datafusion::physical_plan::{execute_stream, ExecutionPlan, ExecutionPlanProperties};
 use datafusion::prelude::*;
 use datafusion::scalar::ScalarValue;
-use futures::{stream, StreamExt};
+use futures::channel::mpsc;
+use futures::{stream, SinkExt, Stream, StreamExt};
 use pgwire::api::portal::{Format, Portal};
-use pgwire::api::results::QueryResponse;
+use pgwire::api::results::{FieldInfo, QueryResponse};
 use pgwire::api::Type;
 use pgwire::error::{PgWireError, PgWireResult};
 use pgwire::messages::data::DataRow;
-use rust_decimal::prelude::ToPrimitive;
+use pgwire::types::FromSqlText;
+use postgres_types::FromSqlOwned;
 use rust_decimal::Decimal;
 
-use super::{arrow_schema_to_pg_fields, encode_recordbatch, into_pg_type};
+use super::{arrow_schema_to_pg_fields, into_pg_type};
+use crate::decoder::{Inet, Interval, Json, MacAddr, Money, Uuid};
+use crate::encoder::TextFormat;
+use crate::row_encoder::{RowEncoder, DEFAULT_MAX_FIELD_SIZE};
+
+/// Options of encoding query results into data rows
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EncodeOptions {
+    /// Rows per record batch the query produces, and so encoded at once.
+    /// `None` keeps the batch size configured on the session.
+    pub batch_size: Option<usize>,
+    /// Bytes of encoded rows a partition hands over to the connection at
+    /// once when partitions are encoded concurrently
+    pub buffer_size: usize,
+    /// Bytes of the largest value sent in a field, rows with larger values
+    /// fail with an error
+    pub max_field_size: usize,
+    /// Session settings of how values are written in the text format
+    pub text_format: TextFormat,
+}
+
+impl Default for EncodeOptions {
+    fn default() -> Self {
+        EncodeOptions {
+            batch_size: None,
+            // the size pgwire flushes its write buffer at
+            buffer_size: 8 * 1024,
+            max_field_size: DEFAULT_MAX_FIELD_SIZE,
+            text_format: TextFormat::default(),
+        }
+    }
+}
 
 pub async fn encode_dataframe<'a>(
     df: DataFrame,
     format: &Format,
 ) -> PgWireResult<QueryResponse<'a>> {
+    encode_dataframe_with_options(df, format, &EncodeOptions::default()).await
+}
+
+pub async fn encode_dataframe_with_options<'a>(
+    df: DataFrame,
+    format: &Format,
+    options: &EncodeOptions,
+) -> PgWireResult<QueryResponse<'a>> {
+    let (resp, _) = encode_dataframe_with_plan(df, format, options).await?;
+    Ok(resp)
+}
+
+/// Encode the dataframe like `encode_dataframe_with_options`, also returning
+/// the physical plan that executes, so its metrics can be read while rows
+/// are sent
+pub async fn encode_dataframe_with_plan<'a>(
+    df: DataFrame,
+    format: &Format,
+    options: &EncodeOptions,
+) -> PgWireResult<(QueryResponse<'a>, Arc<dyn ExecutionPlan>)> {
     let fields = Arc::new(arrow_schema_to_pg_fields(df.schema().as_arrow(), format)?);
 
-    let recordbatch_stream = df
-        .execute_stream()
+    let df = match options.batch_size {
+        Some(batch_size) => {
+            let (mut state, plan) = df.into_parts();
+            state.config_mut().options_mut().execution.batch_size = batch_size.max(1);
+            DataFrame::new(state, plan)
+        }
+        None => df,
+    };
+
+    let task_ctx = Arc::new(df.task_ctx());
+    let plan = df
+        .create_physical_plan()
         .await
         .map_err(|e| PgWireError::ApiError(Box::new(e)))?;
 
-    let fields_ref = fields.clone();
-    let pg_row_stream = recordbatch_stream
+    // rows of an ordered plan come from a single sorted partition, others
+    // are sent in whatever order partitions produce them
+    if plan.output_partitioning().partition_count() > 1 && plan.output_ordering().is_none() {
+        let pg_row_stream = encode_partitions(fields.clone(), plan.clone(), task_ctx, options);
+        return Ok((QueryResponse::new(fields, pg_row_stream), plan));
+    }
+
+    let recordbatch_stream =
+        execute_stream(plan.clone(), task_ctx).map_err(|e| PgWireError::ApiError(Box::new(e)))?;
+
+    let pg_row_stream = encode_batches(fields.clone(), recordbatch_stream, *options);
+    Ok((QueryResponse::new(fields, pg_row_stream), plan))
+}
+
+/// Execute and encode each partition of `plan` on a task of its own.
+///
+/// Rows are interleaved in the order partitions produce them, in chunks of
+/// about `buffer_size` bytes. Each task waits for its previous chunk to be
+/// taken before encoding the next, and dropping the stream aborts all of
+/// them.
+fn encode_partitions(
+    fields: Arc<Vec<FieldInfo>>,
+    plan: Arc<dyn ExecutionPlan>,
+    task_ctx: Arc<TaskContext>,
+    options: &EncodeOptions,
+) -> impl Stream<Item = PgWireResult<DataRow>> + Send + 'static {
+    let options = *options;
+    let buffer_size = options.buffer_size;
+    let partition_count = plan.output_partitioning().partition_count();
+    let (tx, rx) = mpsc::channel::<PgWireResult<Vec<DataRow>>>(partition_count);
+
+    let tasks = (0..partition_count)
+        .map(|partition| {
+            let (fields, plan, task_ctx) = (fields.clone(), plan.clone(), task_ctx.clone());
+            let mut tx = tx.clone();
+            SpawnedTask::spawn(async move {
+                let mut recordbatch_stream = match plan.execute(partition, task_ctx) {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        let _ = tx.send(Err(PgWireError::ApiError(Box::new(e)))).await;
+                        return;
+                    }
+                };
+                while let Some(rb) = recordbatch_stream.next().await {
+                    let rb = match rb {
+                        Ok(rb) => rb,
+                        Err(e) => {
+                            let _ = tx.send(Err(PgWireError::ApiError(Box::new(e)))).await;
+                            return;
+                        }
+                    };
+
+                    let mut chunk = Vec::new();
+                    let mut chunk_size = 0;
+                    for row in encode_recordbatch_with_options(fields.clone(), rb, options) {
+                        let row = match row {
+                            Ok(row) => row,
+                            Err(e) => {
+                                let _ = tx.send(Ok(chunk)).await;
+                                let _ = tx.send(Err(e)).await;
+                                return;
+                            }
+                        };
+                        chunk_size += row.data.len();
+                        chunk.push(row);
+                        if chunk_size >= buffer_size {
+                            if tx.send(Ok(std::mem::take(&mut chunk))).await.is_err() {
+                                return;
+                            }
+                            chunk_size = 0;
+                        }
+                    }
+                    // don't hold rows back while waiting for the next batch
+                    if !chunk.is_empty() && tx.send(Ok(chunk)).await.is_err() {
+                        return;
+                    }
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+
+    rx.flat_map(move |rows| {
+        // keep the tasks running as long as the stream is alive
+        let _ = &tasks;
+        let rows: Box<dyn Iterator<Item = PgWireResult<DataRow>> + Send> = match rows {
+            Ok(rows) => Box::new(rows.into_iter().map(Ok)),
+            Err(e) => Box::new(iter::once(Err(e))),
+        };
+        stream::iter(rows)
+    })
+}
+
+/// Encode a record batch stream into data rows.
+///
+/// Batches are polled from `recordbatch_stream` only as rows are consumed, so
+/// clients receive the first rows before the query completes and a slow
+/// client holds back query execution instead of rows piling up in memory.
+pub fn encode_recordbatch_stream(
+    fields: Arc<Vec<FieldInfo>>,
+    recordbatch_stream: SendableRecordBatchStream,
+) -> impl Stream<Item = PgWireResult<DataRow>> + Send + 'static {
+    encode_batches(fields, recordbatch_stream, EncodeOptions::default())
+}
+
+/// Encode a record batch stream like `encode_recordbatch_stream`, with the
+/// field size limit and text format of `options`
+fn encode_batches(
+    fields: Arc<Vec<FieldInfo>>,
+    recordbatch_stream: SendableRecordBatchStream,
+    options: EncodeOptions,
+) -> impl Stream<Item = PgWireResult<DataRow>> + Send + 'static {
+    recordbatch_stream
         .map(move |rb: datafusion::error::Result<RecordBatch>| {
             let row_stream: Box<dyn Iterator<Item = PgWireResult<DataRow>> + Send + Sync> = match rb
             {
-                Ok(rb) => encode_recordbatch(fields_ref.clone(), rb),
+                Ok(rb) => Box::new(encode_recordbatch_with_options(fields.clone(), rb, options)),
                 Err(e) => Box::new(iter::once(Err(PgWireError::ApiError(e.into())))),
             };
             stream::iter(row_stream)
         })
-        .flatten();
-    Ok(QueryResponse::new(fields, pg_row_stream))
+        .flatten()
+}
+
+/// Encode the rows of `rb` with the field size limit and text format of
+/// `options`
+fn encode_recordbatch_with_options(
+    fields: Arc<Vec<FieldInfo>>,
+    rb: RecordBatch,
+    options: EncodeOptions,
+) -> impl Iterator<Item = PgWireResult<DataRow>> + Send + Sync {
+    let mut row_encoder = RowEncoder::new(rb, fields)
+        .with_max_field_size(options.max_field_size)
+        .with_text_format(options.text_format);
+    iter::from_fn(move || row_encoder.next_row())
+}
+
+/// Parameter `idx` of `portal`, decoded from the format it was bound in
+fn parameter<S, T>(portal: &Portal<S>, idx: usize, pg_type: &Type) -> PgWireResult<Option<T>>
+where
+    S: Clone,
+    T: FromSqlOwned + FromSqlText,
+{
+    if portal.parameter_format.is_binary(idx) {
+        return portal.parameter::<T>(idx, pg_type);
+    }
+    let param = portal
+        .parameters
+        .get(idx)
+        .ok_or(PgWireError::ParameterIndexOutOfBound(idx))?;
+    param
+        .as_deref()
+        .map(|text| T::from_sql_text(pg_type, text))
+        .transpose()
+        .map_err(PgWireError::FailedToParseParameter)
+}
+
+/// A list of `element_type` of the elements of an array parameter,
+/// converted by `into_scalar`
+fn list_scalar<T>(
+    elements: Option<Vec<Option<T>>>,
+    element_type: &DataType,
+    into_scalar: impl Fn(Option<T>) -> ScalarValue,
+) -> PgWireResult<ScalarValue> {
+    match elements {
+        Some(elements) => {
+            let elements = elements.into_iter().map(into_scalar).collect::<Vec<_>>();
+            Ok(ScalarValue::List(ScalarValue::new_list_nullable(
+                &elements,
+                element_type,
+            )))
+        }
+        None => ScalarValue::try_from(DataType::new_list(element_type.clone(), true))
+            .map_err(|e| PgWireError::ApiError(Box::new(e))),
+    }
+}
+
+fn numeric_scalar(value: Option<Decimal>) -> ScalarValue {
+    match value {
+        // the type of `numeric` columns without precision
+        None => ScalarValue::Decimal128(None, 38, 10),
+        Some(value) => {
+            // digits of the mantissa, at least those of the fraction
+            let digits = value.mantissa().unsigned_abs().to_string().len() as u8;
+            let scale = value.scale() as u8;
+            ScalarValue::Decimal128(Some(value.mantissa()), digits.max(scale), scale as i8)
+        }
+    }
+}
+
+fn timestamp_micros(value: NaiveDateTime) -> i64 {
+    value.and_utc().timestamp_micros()
+}
+
+fn time_micros(value: NaiveTime) -> i64 {
+    value.num_seconds_from_midnight() as i64 * 1_000_000 + value.nanosecond() as i64 / 1_000
 }
 
 /// Deserialize client provided parameter data.
@@ -50,6 +299,9 @@ pub async fn encode_dataframe<'a>(
 /// If the type is empty or unknown, we fallback to datafusion inferenced type
 /// from `inferenced_types`.
 /// An error will be raised when neither sources can provide type information.
+///
+/// Parameters are decoded from the binary or text format they were bound in,
+/// into the arrow types the encoder sends as the same postgres types.
 pub fn deserialize_parameters<S>(
     portal: &Portal<S>,
     inferenced_types: &[Option<&DataType>],
@@ -70,6 +322,7 @@ where
         }
     }
 
+    let utc: Arc<str> = "+00:00".into();
     let param_len = portal.parameter_len();
     let mut deserialized_params = Vec::with_capacity(param_len);
     for i in 0..param_len {
@@ -77,187 +330,165 @@ where
             portal.statement.parameter_types.get(i),
             inferenced_types.get(i).and_then(|v| v.to_owned()),
         )?;
-        match pg_type {
+        let value = match pg_type {
             // enumerate all supported parameter types and deserialize the
             // type to ScalarValue
-            Type::BOOL => {
-                let value = portal.parameter::<bool>(i, &pg_type)?;
-                deserialized_params.push(ScalarValue::Boolean(value));
-            }
-            Type::CHAR => {
-                let value = portal.parameter::<i8>(i, &pg_type)?;
-                deserialized_params.push(ScalarValue::Int8(value));
-            }
-            Type::INT2 => {
-                let value = portal.parameter::<i16>(i, &pg_type)?;
-                deserialized_params.push(ScalarValue::Int16(value));
-            }
-            Type::INT4 => {
-                let value = portal.parameter::<i32>(i, &pg_type)?;
-                deserialized_params.push(ScalarValue::Int32(value));
-            }
-            Type::INT8 => {
-                let value = portal.parameter::<i64>(i, &pg_type)?;
-                deserialized_params.push(ScalarValue::Int64(value));
-            }
-            Type::TEXT | Type::VARCHAR => {
-                let value = portal.parameter::<String>(i, &pg_type)?;
-                deserialized_params.push(ScalarValue::Utf8(value));
-            }
-            Type::BYTEA => {
-                let value = portal.parameter::<Vec<u8>>(i, &pg_type)?;
-                deserialized_params.push(ScalarValue::Binary(value));
-            }
-
-            Type::FLOAT4 => {
-                let value = portal.parameter::<f32>(i, &pg_type)?;
-                deserialized_params.push(ScalarValue::Float32(value));
-            }
-            Type::FLOAT8 => {
-                let value = portal.parameter::<f64>(i, &pg_type)?;
-                deserialized_params.push(ScalarValue::Float64(value));
-            }
-            Type::NUMERIC => {
-                let value = match portal.parameter::<Decimal>(i, &pg_type)? {
-                    None => ScalarValue::Decimal128(None, 0, 0),
-                    Some(value) => {
-                        let precision = match value.mantissa() {
-                            0 => 1,
-                            m => (m.abs() as f64).log10().floor() as u8 + 1,
-                        };
-                        let scale = value.scale() as i8;
-                        ScalarValue::Decimal128(value.to_i128(), precision, scale)
-                    }
-                };
-                deserialized_params.push(value);
+            Type::BOOL => ScalarValue::Boolean(parameter(portal, i, &pg_type)?),
+            Type::CHAR => ScalarValue::Int8(parameter(portal, i, &pg_type)?),
+            Type::INT2 => ScalarValue::Int16(parameter(portal, i, &pg_type)?),
+            Type::INT4 => ScalarValue::Int32(parameter(portal, i, &pg_type)?),
+            Type::INT8 => ScalarValue::Int64(parameter(portal, i, &pg_type)?),
+            Type::OID => ScalarValue::UInt32(parameter(portal, i, &pg_type)?),
+            Type::TEXT | Type::VARCHAR | Type::BPCHAR | Type::NAME => {
+                ScalarValue::Utf8(parameter(portal, i, &pg_type)?)
             }
+            Type::BYTEA => ScalarValue::Binary(parameter(portal, i, &pg_type)?),
+            Type::FLOAT4 => ScalarValue::Float32(parameter(portal, i, &pg_type)?),
+            Type::FLOAT8 => ScalarValue::Float64(parameter(portal, i, &pg_type)?),
+            Type::NUMERIC => numeric_scalar(parameter(portal, i, &pg_type)?),
             Type::TIMESTAMP => {
-                let value = portal.parameter::<NaiveDateTime>(i, &pg_type)?;
-                deserialized_params.push(ScalarValue::TimestampMicrosecond(
-                    value.map(|t| t.and_utc().timestamp_micros()),
-                    None,
-                ));
+                let value = parameter::<_, NaiveDateTime>(portal, i, &pg_type)?;
+                ScalarValue::TimestampMicrosecond(value.map(timestamp_micros), None)
             }
             Type::TIMESTAMPTZ => {
-                let value = portal.parameter::<DateTime<FixedOffset>>(i, &pg_type)?;
-                deserialized_params.push(ScalarValue::TimestampMicrosecond(
+                let value = parameter::<_, DateTime<FixedOffset>>(portal, i, &pg_type)?;
+                ScalarValue::TimestampMicrosecond(
                     value.map(|t| t.timestamp_micros()),
                     value.map(|t| t.offset().to_string().into()),
-                ));
+                )
             }
             Type::DATE => {
-                let value = portal.parameter::<NaiveDate>(i, &pg_type)?;
-                deserialized_params
-                    .push(ScalarValue::Date32(value.map(Date32Type::from_naive_date)));
+                let value = parameter::<_, NaiveDate>(portal, i, &pg_type)?;
+                ScalarValue::Date32(value.map(Date32Type::from_naive_date))
             }
             Type::TIME => {
-                let value = portal.parameter::<NaiveTime>(i, &pg_type)?;
-                deserialized_params.push(ScalarValue::Time64Microsecond(value.map(|t| {
-                    t.num_seconds_from_midnight() as i64 * 1_000_000 + t.nanosecond() as i64 / 1_000
-                })));
+                let value = parameter::<_, NaiveTime>(portal, i, &pg_type)?;
+                ScalarValue::Time64Microsecond(value.map(time_micros))
             }
+            // Stored as their text
             Type::UUID => {
-                let value = portal.parameter::<String>(i, &pg_type)?;
-                // Store UUID as string for now
-                deserialized_params.push(ScalarValue::Utf8(value));
+                ScalarValue::Utf8(parameter::<_, Uuid>(portal, i, &pg_type)?.map(|v| v.0))
             }
             Type::JSON | Type::JSONB => {
-                let value = portal.parameter::<String>(i, &pg_type)?;
-                // Store JSON as string for now
-                deserialized_params.push(ScalarValue::Utf8(value));
+                ScalarValue::Utf8(parameter::<_, Json>(portal, i, &pg_type)?.map(|v| v.0))
             }
-            Type::INTERVAL => {
-                let value = portal.parameter::<String>(i, &pg_type)?;
-                // Store interval as string for now (DataFusion has limited interval support)
-                deserialized_params.push(ScalarValue::Utf8(value));
-            }
-            // Array types support
-            Type::BOOL_ARRAY => {
-                let value = portal.parameter::<Vec<Option<bool>>>(i, &pg_type)?;
-                let scalar_values: Vec<ScalarValue> = value.map_or(Vec::new(), |v| {
-                    v.into_iter().map(ScalarValue::Boolean).collect()
-                });
-                deserialized_params.push(ScalarValue::List(ScalarValue::new_list_nullable(
-                    &scalar_values,
-                    &DataType::Boolean,
-                )));
+            Type::INET | Type::CIDR => {
+                ScalarValue::Utf8(parameter::<_, Inet>(portal, i, &pg_type)?.map(|v| v.0))
             }
-            Type::INT2_ARRAY => {
-                let value = portal.parameter::<Vec<Option<i16>>>(i, &pg_type)?;
-                let scalar_values: Vec<ScalarValue> = value.map_or(Vec::new(), |v| {
-                    v.into_iter().map(ScalarValue::Int16).collect()
-                });
-                deserialized_params.push(ScalarValue::List(ScalarValue::new_list_nullable(
-                    &scalar_values,
-                    &DataType::Int16,
-                )));
-            }
-            Type::INT4_ARRAY => {
-                let value = portal.parameter::<Vec<Option<i32>>>(i, &pg_type)?;
-                let scalar_values: Vec<ScalarValue> = value.map_or(Vec::new(), |v| {
-                    v.into_iter().map(ScalarValue::Int32).collect()
-                });
-                deserialized_params.push(ScalarValue::List(ScalarValue::new_list_nullable(
-                    &scalar_values,
-                    &DataType::Int32,
-                )));
-            }
-            Type::INT8_ARRAY => {
-                let value = portal.parameter::<Vec<Option<i64>>>(i, &pg_type)?;
-                let scalar_values: Vec<ScalarValue> = value.map_or(Vec::new(), |v| {
-                    v.into_iter().map(ScalarValue::Int64).collect()
-                });
-                deserialized_params.push(ScalarValue::List(ScalarValue::new_list_nullable(
-                    &scalar_values,
-                    &DataType::Int64,
-                )));
+            Type::MACADDR => {
+                ScalarValue::Utf8(parameter::<_, MacAddr>(portal, i, &pg_type)?.map(|v| v.0))
             }
-            Type::FLOAT4_ARRAY => {
-                let value = portal.parameter::<Vec<Option<f32>>>(i, &pg_type)?;
-                let scalar_values: Vec<ScalarValue> = value.map_or(Vec::new(), |v| {
-                    v.into_iter().map(ScalarValue::Float32).collect()
-                });
-                deserialized_params.push(ScalarValue::List(ScalarValue::new_list_nullable(
-                    &scalar_values,
-                    &DataType::Float32,
-                )));
+            Type::INTERVAL if portal.parameter_format.is_binary(i) => {
+                let value = portal.parameter::<Interval>(i, &pg_type)?;
+                ScalarValue::IntervalMonthDayNano(value.map(|v| v.0))
             }
-            Type::FLOAT8_ARRAY => {
-                let value = portal.parameter::<Vec<Option<f64>>>(i, &pg_type)?;
-                let scalar_values: Vec<ScalarValue> = value.map_or(Vec::new(), |v| {
-                    v.into_iter().map(ScalarValue::Float64).collect()
-                });
-                deserialized_params.push(ScalarValue::List(ScalarValue::new_list_nullable(
-                    &scalar_values,
-                    &DataType::Float64,
-                )));
+            // Store interval as string (DataFusion has limited interval support)
+            Type::INTERVAL => ScalarValue::Utf8(parameter(portal, i, &Type::TEXT)?),
+            // Store money as int64 (cents)
+            Type::MONEY if portal.parameter_format.is_binary(i) => {
+                ScalarValue::Int64(portal.parameter::<Money>(i, &pg_type)?.map(|v| v.0))
             }
-            Type::TEXT_ARRAY | Type::VARCHAR_ARRAY => {
-                let value = portal.parameter::<Vec<Option<String>>>(i, &pg_type)?;
-                let scalar_values: Vec<ScalarValue> = value.map_or(Vec::new(), |v| {
-                    v.into_iter().map(ScalarValue::Utf8).collect()
-                });
-                deserialized_params.push(ScalarValue::List(ScalarValue::new_list_nullable(
-                    &scalar_values,
+            Type::MONEY => ScalarValue::Utf8(parameter(portal, i, &Type::TEXT)?),
+            // Array types support
+            Type::BOOL_ARRAY => list_scalar(
+                portal.parameter(i, &pg_type)?,
+                &DataType::Boolean,
+                ScalarValue::Boolean,
+            )?,
+            Type::INT2_ARRAY => list_scalar(
+                portal.parameter(i, &pg_type)?,
+                &DataType::Int16,
+                ScalarValue::Int16,
+            )?,
+            Type::INT4_ARRAY => list_scalar(
+                portal.parameter(i, &pg_type)?,
+                &DataType::Int32,
+                ScalarValue::Int32,
+            )?,
+            Type::INT8_ARRAY => list_scalar(
+                portal.parameter(i, &pg_type)?,
+                &DataType::Int64,
+                ScalarValue::Int64,
+            )?,
+            Type::FLOAT4_ARRAY => list_scalar(
+                portal.parameter(i, &pg_type)?,
+                &DataType::Float32,
+                ScalarValue::Float32,
+            )?,
+            Type::FLOAT8_ARRAY => list_scalar(
+                portal.parameter(i, &pg_type)?,
+                &DataType::Float64,
+                ScalarValue::Float64,
+            )?,
+            Type::TEXT_ARRAY | Type::VARCHAR_ARRAY | Type::BPCHAR_ARRAY | Type::NAME_ARRAY => {
+                list_scalar(
+                    portal.parameter(i, &pg_type)?,
                     &DataType::Utf8,
-                )));
-            }
-            // Advanced types
-            Type::MONEY => {
-                let value = portal.parameter::<i64>(i, &pg_type)?;
-                // Store money as int64 (cents)
-                deserialized_params.push(ScalarValue::Int64(value));
+                    ScalarValue::Utf8,
+                )?
             }
-            Type::INET => {
-                let value = portal.parameter::<String>(i, &pg_type)?;
-                // Store IP addresses as strings for now
-                deserialized_params.push(ScalarValue::Utf8(value));
-            }
-            Type::MACADDR => {
-                let value = portal.parameter::<String>(i, &pg_type)?;
-                // Store MAC addresses as strings for now
-                deserialized_params.push(ScalarValue::Utf8(value));
+            Type::BYTEA_ARRAY => list_scalar(
+                portal.parameter(i, &pg_type)?,
+                &DataType::Binary,
+                ScalarValue::Binary,
+            )?,
+            Type::NUMERIC_ARRAY => {
+                // elements share the scale of the most precise one
+                let value = portal.parameter::<Vec<Option<Decimal>>>(i, &pg_type)?;
+                let scale = value
+                    .iter()
+                    .flatten()
+                    .flatten()
+                    .map(Decimal::scale)
+                    .max()
+                    .unwrap_or(0);
+                let element_type = DataType::Decimal128(38, scale as i8);
+                list_scalar(value, &element_type, |value| {
+                    let value = value.map(|mut value| {
+                        value.rescale(scale);
+                        value.mantissa()
+                    });
+                    ScalarValue::Decimal128(value, 38, scale as i8)
+                })?
             }
+            Type::DATE_ARRAY => list_scalar(
+                portal.parameter(i, &pg_type)?,
+                &DataType::Date32,
+                |v: Option<NaiveDate>| ScalarValue::Date32(v.map(Date32Type::from_naive_date)),
+            )?,
+            Type::TIME_ARRAY => list_scalar(
+                portal.parameter(i, &pg_type)?,
+                &DataType::Time64(TimeUnit::Microsecond),
+                |value: Option<NaiveTime>| ScalarValue::Time64Microsecond(value.map(time_micros)),
+            )?,
+            Type::TIMESTAMP_ARRAY => list_scalar(
+                portal.parameter(i, &pg_type)?,
+                &DataType::Timestamp(TimeUnit::Microsecond, None),
+                |value: Option<NaiveDateTime>| {
+                    ScalarValue::TimestampMicrosecond(value.map(timestamp_micros), None)
+                },
+            )?,
+            // the binary format of timestamptz is in UTC
+            Type::TIMESTAMPTZ_ARRAY => list_scalar(
+                portal.parameter(i, &pg_type)?,
+                &DataType::Timestamp(TimeUnit::Microsecond, Some(utc.clone())),
+                |value: Option<DateTime<FixedOffset>>| {
+                    ScalarValue::TimestampMicrosecond(
+                        value.map(|t| t.timestamp_micros()),
+                        Some(utc.clone()),
+                    )
+                },
+            )?,
+            Type::UUID_ARRAY => list_scalar(
+                portal.parameter(i, &pg_type)?,
+                &DataType::Utf8,
+                |v: Option<Uuid>| ScalarValue::Utf8(v.map(|v| v.0)),
+            )?,
+            Type::JSON_ARRAY | Type::JSONB_ARRAY => list_scalar(
+                portal.parameter(i, &pg_type)?,
+                &DataType::Utf8,
+                |v: Option<Json>| ScalarValue::Utf8(v.map(|v| v.0)),
+            )?,
             // TODO: add more advanced types (composite types, ranges, etc.)
             _ => {
                 // the client didn't provide type information and we are also
@@ -265,26 +496,243 @@ where
                 // supported:
                 //
                 // In this case we retry to resolve it as String or StringArray
-                let value = portal.parameter::<String>(i, &pg_type)?;
-                if let Some(value) = value {
-                    if value.starts_with('{') && value.ends_with('}') {
-                        // Looks like an array
-                        let items = value.trim_matches(|c| c == '{' || c == '}' || c == ' ');
-                        let items = items.split(',').map(|s| s.trim());
-                        let scalar_values: Vec<ScalarValue> = items
-                            .map(|s| ScalarValue::Utf8(Some(s.to_string())))
-                            .collect();
-
-                        deserialized_params.push(ScalarValue::List(
-                            ScalarValue::new_list_nullable(&scalar_values, &DataType::Utf8),
-                        ));
-                    } else {
-                        deserialized_params.push(ScalarValue::Utf8(Some(value)));
-                    }
+                let Some(value) = portal.parameter::<String>(i, &pg_type)? else {
+                    deserialized_params.push(ScalarValue::Utf8(None));
+                    continue;
+                };
+                if value.starts_with('{') && value.ends_with('}') {
+                    // Looks like an array
+                    let items = value.trim_matches(|c| c == '{' || c == '}' || c == ' ');
+                    let items = items.split(',').map(|s| s.trim());
+                    let scalar_values: Vec<ScalarValue> = items
+                        .map(|s| ScalarValue::Utf8(Some(s.to_string())))
+                        .collect();
+                    ScalarValue::List(ScalarValue::new_list_nullable(
+                        &scalar_values,
+                        &DataType::Utf8,
+                    ))
+                } else {
+                    ScalarValue::Utf8(Some(value))
                 }
             }
-        }
+        };
+        deserialized_params.push(value);
     }
 
     Ok(ParamValues::List(deserialized_params))
 }
+
+#[cfg(test)]
+mod tests {
+    use std::net::IpAddr;
+
+    use bytes::{Bytes, BytesMut};
+    use chrono::TimeZone;
+    use datafusion::arrow::datatypes::IntervalMonthDayNano;
+    use pgwire::api::results::FieldFormat;
+    use pgwire::api::stmt::StoredStatement;
+    use pgwire::messages::extendedquery::Bind;
+    use postgres_protocol::types as protocol;
+    use postgres_types::{IsNull, ToSql};
+
+    use super::*;
+    use crate::encoder::{encode_value, Encoder};
+
+    /// The binary format of `value`, as rust-postgres binds it
+    fn to_sql(value: &(dyn ToSql + Sync), pg_type: &Type) -> Option<Bytes> {
+        let mut buf = BytesMut::new();
+        match value.to_sql_checked(pg_type, &mut buf).unwrap() {
+            IsNull::Yes => None,
+            IsNull::No => Some(buf.freeze()),
+        }
+    }
+
+    fn portal(format: i16, params: Vec<(Option<Bytes>, Type)>) -> Portal<()> {
+        let (params, types): (Vec<_>, Vec<_>) = params.into_iter().unzip();
+        let statement = Arc::new(StoredStatement::new(String::new(), (), types));
+        Portal::try_new(
+            &Bind::new(None, None, vec![format], params, vec![]),
+            statement,
+        )
+        .unwrap()
+    }
+
+    fn decode(format: i16, params: Vec<(Option<Bytes>, Type)>) -> Vec<ScalarValue> {
+        let portal = portal(format, params);
+        match deserialize_parameters(&portal, &[]).unwrap() {
+            ParamValues::List(values) => values,
+            ParamValues::Map(_) => unreachable!(),
+        }
+    }
+
+    /// Encoder keeping the binary format of the value
+    #[derive(Default)]
+    struct BinaryEncoder(Option<Bytes>);
+
+    impl Encoder for BinaryEncoder {
+        fn encode_field_with_type_and_format<T>(
+            &mut self,
+            value: &T,
+            data_type: &Type,
+            _format: FieldFormat,
+        ) -> PgWireResult<()>
+        where
+            T: ToSql + pgwire::types::ToSqlText + Sized,
+        {
+            let mut buf = BytesMut::new();
+            self.0 = match value.to_sql(data_type, &mut buf).unwrap() {
+                IsNull::Yes => None,
+                IsNull::No => Some(buf.freeze()),
+            };
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn binary_parameters_round_trip() {
+        let timestamp = NaiveDate::from_ymd_opt(2024, 2, 29)
+            .unwrap()
+            .and_hms_micro_opt(13, 14, 15, 161718)
+            .unwrap();
+        let values: Vec<(Box<dyn ToSql + Sync>, Type)> = vec![
+            (Box::new(true), Type::BOOL),
+            (Box::new(-7i16), Type::INT2),
+            (Box::new(42i32), Type::INT4),
+            (Box::new(i64::MAX), Type::INT8),
+            (Box::new(1.5f32), Type::FLOAT4),
+            (Box::new(-2.25f64), Type::FLOAT8),
+            (Box::new(Decimal::new(-123456, 3)), Type::NUMERIC),
+            (Box::new("héllo"), Type::TEXT),
+            (Box::new(vec![0u8, 1, 255]), Type::BYTEA),
+            (Box::new(timestamp.date()), Type::DATE),
+            (Box::new(timestamp.time()), Type::TIME),
+            (Box::new(timestamp), Type::TIMESTAMP),
+            (
+                Box::new(chrono::Utc.from_utc_datetime(&timestamp)),
+                Type::TIMESTAMPTZ,
+            ),
+            (Box::new(vec![Some(1i32), None, Some(3)]), Type::INT4_ARRAY),
+            (Box::new(vec![Some(9i64)]), Type::INT8_ARRAY),
+            (Box::new(vec![Some(0.5f64), None]), Type::FLOAT8_ARRAY),
+            (Box::new(vec![Some(true), Some(false)]), Type::BOOL_ARRAY),
+            (Box::new(vec![Some("a"), None, Some("b")]), Type::TEXT_ARRAY),
+            (Box::new(vec![Some(timestamp.date())]), Type::DATE_ARRAY),
+            (Box::new(vec![Some(timestamp)]), Type::TIMESTAMP_ARRAY),
+        ];
+
+        for (value, pg_type) in values {
+            let raw = to_sql(value.as_ref(), &pg_type);
+            let decoded = decode(1, vec![(raw.clone(), pg_type.clone())]);
+            // the value is sent back as it was bound
+            let array = decoded[0].to_array().unwrap();
+            assert_eq!(into_pg_type(array.data_type()).unwrap(), pg_type);
+            let mut encoder = BinaryEncoder::default();
+            encode_value(&mut encoder, &array, 0, &pg_type, FieldFormat::Binary).unwrap();
+            assert_eq!(encoder.0, raw, "{pg_type}: {:?}", decoded[0]);
+        }
+    }
+
+    #[test]
+    fn decodes_binary_parameters_without_rust_types() {
+        let mut uuid = BytesMut::new();
+        protocol::uuid_to_sql(
+            *b"\x55\x0e\x84\x00\xe2\x9b\x41\xd4\xa7\x16\x44\x66\x55\x44\x00\x00",
+            &mut uuid,
+        );
+        let mut inet = BytesMut::new();
+        protocol::inet_to_sql("192.168.0.1".parse::<IpAddr>().unwrap(), 32, &mut inet);
+        let mut network = BytesMut::new();
+        protocol::inet_to_sql("10.0.0.0".parse::<IpAddr>().unwrap(), 8, &mut network);
+        let mut macaddr = BytesMut::new();
+        protocol::macaddr_to_sql([8, 0, 0x2b, 1, 2, 3], &mut macaddr);
+        let mut interval = BytesMut::new();
+        interval.extend_from_slice(&1_500_000i64.to_be_bytes());
+        interval.extend_from_slice(&3i32.to_be_bytes());
+        interval.extend_from_slice(&14i32.to_be_bytes());
+
+        let values = decode(
+            1,
+            vec![
+                (Some(uuid.freeze()), Type::UUID),
+                (Some(Bytes::from_static(b"{\"a\": 1}")), Type::JSON),
+                (Some(Bytes::from_static(b"\x01{\"a\": 1}")), Type::JSONB),
+                (Some(inet.freeze()), Type::INET),
+                (Some(network.freeze()), Type::INET),
+                (Some(macaddr.freeze()), Type::MACADDR),
+                (Some(interval.freeze()), Type::INTERVAL),
+                (
+                    Some(Bytes::copy_from_slice(&1234i64.to_be_bytes())),
+                    Type::MONEY,
+                ),
+                (to_sql(&16384u32, &Type::OID), Type::OID),
+                (None, Type::INT4),
+                (None, Type::TEXT_ARRAY),
+            ],
+        );
+        assert_eq!(
+            values,
+            vec![
+                ScalarValue::from("550e8400-e29b-41d4-a716-446655440000"),
+                ScalarValue::from("{\"a\": 1}"),
+                ScalarValue::from("{\"a\": 1}"),
+                ScalarValue::from("192.168.0.1"),
+                ScalarValue::from("10.0.0.0/8"),
+                ScalarValue::from("08:00:2b:01:02:03"),
+                ScalarValue::IntervalMonthDayNano(Some(IntervalMonthDayNano::new(
+                    14,
+                    3,
+                    1_500_000_000
+                ))),
+                ScalarValue::Int64(Some(1234)),
+                ScalarValue::UInt32(Some(16384)),
+                ScalarValue::Int32(None),
+                ScalarValue::try_from(DataType::new_list(DataType::Utf8, true)).unwrap(),
+            ]
+        );
+    }
+
+    #[test]
+    fn decodes_numeric_arrays_to_a_common_scale() {
+        let raw = to_sql(
+            &vec![Some(Decimal::new(15, 1)), None, Some(Decimal::new(2, 0))],
+            &Type::NUMERIC_ARRAY,
+        );
+        let values = decode(1, vec![(raw, Type::NUMERIC_ARRAY)]);
+        let expected = ScalarValue::new_list_nullable(
+            &[
+                ScalarValue::Decimal128(Some(15), 38, 1),
+                ScalarValue::Decimal128(None, 38, 1),
+                ScalarValue::Decimal128(Some(20), 38, 1),
+            ],
+            &DataType::Decimal128(38, 1),
+        );
+        assert_eq!(values, vec![ScalarValue::List(expected)]);
+    }
+
+    #[test]
+    fn decodes_text_parameters() {
+        let text = |value: &'static str| Some(Bytes::from_static(value.as_bytes()));
+        let values = decode(
+            0,
+            vec![
+                (text("42"), Type::INT4),
+                (text("t"), Type::BOOL),
+                (text("1.25"), Type::NUMERIC),
+                (text("2024-02-29"), Type::DATE),
+                (text("550e8400-e29b-41d4-a716-446655440000"), Type::UUID),
+                (text("1 day"), Type::INTERVAL),
+            ],
+        );
+        assert_eq!(
+            values,
+            vec![
+                ScalarValue::Int32(Some(42)),
+                ScalarValue::Boolean(Some(true)),
+                ScalarValue::Decimal128(Some(125), 3, 2),
+                ScalarValue::Date32(Some(19782)),
+                ScalarValue::from("550e8400-e29b-41d4-a716-446655440000"),
+                ScalarValue::from("1 day"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,163 @@
+//! Rust types of bound parameters of the postgres types postgres-types has
+//! none for, decoded into the values arrow stores them as.
+//!
+//! `uuid`, `json`, `jsonb`, `inet` and `macaddr` are decoded into their text,
+//! from the binary format as from the text format, `interval` into an
+//! [`IntervalMonthDayNano`] and `money` into its cents.
+
+use std::error::Error;
+use std::net::IpAddr;
+
+#[cfg(not(feature = "datafusion"))]
+use arrow::datatypes::IntervalMonthDayNano;
+#[cfg(feature = "datafusion")]
+use datafusion::arrow::datatypes::IntervalMonthDayNano;
+use pgwire::types::FromSqlText;
+use postgres_protocol::types;
+use postgres_types::{accepts, FromSql, Type};
+
+/// Version of the binary format of `jsonb`
+const JSONB_VERSION: u8 = 1;
+
+fn text(raw: &[u8]) -> Result<String, Box<dyn Error + Sync + Send>> {
+    Ok(std::str::from_utf8(raw)?.to_string())
+}
+
+/// A `uuid` as its hyphenated text
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Uuid(pub String);
+
+impl<'a> FromSql<'a> for Uuid {
+    fn from_sql(_ty: &Type, raw: &'a [u8]) -> Result<Self, Box<dyn Error + Sync + Send>> {
+        let hex = types::uuid_from_sql(raw)?
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect::<String>();
+        Ok(Uuid(format!(
+            "{}-{}-{}-{}-{}",
+            &hex[..8],
+            &hex[8..12],
+            &hex[12..16],
+            &hex[16..20],
+            &hex[20..]
+        )))
+    }
+
+    accepts!(UUID);
+}
+
+impl FromSqlText for Uuid {
+    fn from_sql_text(_ty: &Type, input: &[u8]) -> Result<Self, Box<dyn Error + Sync + Send>> {
+        text(input).map(Uuid)
+    }
+}
+
+/// A `json` or `jsonb` document as its text
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Json(pub String);
+
+impl<'a> FromSql<'a> for Json {
+    fn from_sql(ty: &Type, raw: &'a [u8]) -> Result<Self, Box<dyn Error + Sync + Send>> {
+        let raw = match (ty, raw) {
+            (&Type::JSONB, [JSONB_VERSION, document @ ..]) => document,
+            (&Type::JSONB, _) => return Err("unsupported jsonb version".into()),
+            _ => raw,
+        };
+        text(raw).map(Json)
+    }
+
+    accepts!(JSON, JSONB);
+}
+
+impl FromSqlText for Json {
+    fn from_sql_text(_ty: &Type, input: &[u8]) -> Result<Self, Box<dyn Error + Sync + Send>> {
+        text(input).map(Json)
+    }
+}
+
+/// An `inet` or `cidr` address in the text format of postgres, with the
+/// netmask only if the address is a network
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Inet(pub String);
+
+impl<'a> FromSql<'a> for Inet {
+    fn from_sql(ty: &Type, raw: &'a [u8]) -> Result<Self, Box<dyn Error + Sync + Send>> {
+        let inet = types::inet_from_sql(raw)?;
+        let host_netmask = match inet.addr() {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+        if ty == &Type::CIDR || inet.netmask() != host_netmask {
+            Ok(Inet(format!("{}/{}", inet.addr(), inet.netmask())))
+        } else {
+            Ok(Inet(inet.addr().to_string()))
+        }
+    }
+
+    accepts!(INET, CIDR);
+}
+
+impl FromSqlText for Inet {
+    fn from_sql_text(_ty: &Type, input: &[u8]) -> Result<Self, Box<dyn Error + Sync + Send>> {
+        text(input).map(Inet)
+    }
+}
+
+/// A `macaddr` like `08:00:2b:01:02:03`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MacAddr(pub String);
+
+impl<'a> FromSql<'a> for MacAddr {
+    fn from_sql(_ty: &Type, raw: &'a [u8]) -> Result<Self, Box<dyn Error + Sync + Send>> {
+        let bytes = types::macaddr_from_sql(raw)?;
+        let text = bytes
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect::<Vec<_>>()
+            .join(":");
+        Ok(MacAddr(text))
+    }
+
+    accepts!(MACADDR);
+}
+
+impl FromSqlText for MacAddr {
+    fn from_sql_text(_ty: &Type, input: &[u8]) -> Result<Self, Box<dyn Error + Sync + Send>> {
+        text(input).map(MacAddr)
+    }
+}
+
+/// An `interval`, of the months, days and microseconds of its binary format
+///
+/// Also encodes the intervals of results, see the `encoder` module.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Interval(pub IntervalMonthDayNano);
+
+impl<'a> FromSql<'a> for Interval {
+    fn from_sql(_ty: &Type, raw: &'a [u8]) -> Result<Self, Box<dyn Error + Sync + Send>> {
+        if raw.len() != 16 {
+            return Err("invalid interval length".into());
+        }
+        let micros = i64::from_be_bytes(raw[..8].try_into()?);
+        let days = i32::from_be_bytes(raw[8..12].try_into()?);
+        let months = i32::from_be_bytes(raw[12..].try_into()?);
+        // nanoseconds overflow for time parts over about 292 years
+        let nanos = micros.checked_mul(1_000).ok_or("interval out of range")?;
+        Ok(Interval(IntervalMonthDayNano::new(months, days, nanos)))
+    }
+
+    accepts!(INTERVAL);
+}
+
+/// A `money` amount in cents
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Money(pub i64);
+
+impl<'a> FromSql<'a> for Money {
+    fn from_sql(_ty: &Type, raw: &'a [u8]) -> Result<Self, Box<dyn Error + Sync + Send>> {
+        let raw: [u8; 8] = raw.try_into().map_err(|_| "invalid money length")?;
+        Ok(Money(i64::from_be_bytes(raw)))
+    }
+
+    accepts!(MONEY);
+}
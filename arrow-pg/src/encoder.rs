@@ -1,4 +1,5 @@
 use std::error::Error;
+use std::fmt::Write as _;
 use std::io::Write;
 use std::str::FromStr;
 use std::sync::Arc;
@@ -7,7 +8,7 @@ use std::sync::Arc;
 use arrow::{array::*, datatypes::*};
 use bytes::BufMut;
 use bytes::BytesMut;
-use chrono::{NaiveDate, NaiveDateTime};
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
 #[cfg(feature = "datafusion")]
 use datafusion::arrow::{array::*, datatypes::*};
 use pgwire::api::results::DataRowEncoder;
@@ -18,6 +19,7 @@ use postgres_types::{ToSql, Type};
 use rust_decimal::Decimal;
 use timezone::Tz;
 
+use crate::decoder::Interval;
 use crate::error::ToSqlError;
 use crate::list_encoder::encode_list;
 use crate::struct_encoder::encode_struct;
@@ -100,6 +102,281 @@ impl ToSqlText for EncodedValue {
     }
 }
 
+impl ToSql for Interval {
+    fn to_sql(
+        &self,
+        _ty: &Type,
+        out: &mut BytesMut,
+    ) -> Result<postgres_types::IsNull, Box<dyn Error + Send + Sync>> {
+        out.put_i64(self.0.nanoseconds / 1_000);
+        out.put_i32(self.0.days);
+        out.put_i32(self.0.months);
+        Ok(postgres_types::IsNull::No)
+    }
+
+    postgres_types::accepts!(INTERVAL);
+
+    postgres_types::to_sql_checked!();
+}
+
+impl ToSqlText for Interval {
+    fn to_sql_text(
+        &self,
+        _ty: &Type,
+        out: &mut BytesMut,
+    ) -> Result<postgres_types::IsNull, Box<dyn Error + Send + Sync>> {
+        out.put_slice(format_interval(self.0).as_bytes());
+        Ok(postgres_types::IsNull::No)
+    }
+}
+
+/// An interval in the `postgres` IntervalStyle, like
+/// `1 year 2 mons -3 days +04:05:06.5`
+fn format_interval(interval: IntervalMonthDayNano) -> String {
+    let mut text = String::new();
+    // positive fields after a negative one are signed
+    let mut is_before = false;
+    for (value, unit) in [
+        (interval.months / 12, "year"),
+        (interval.months % 12, "mon"),
+        (interval.days, "day"),
+    ] {
+        if value == 0 {
+            continue;
+        }
+        if !text.is_empty() {
+            text.push(' ');
+        }
+        let sign = if is_before && value > 0 { "+" } else { "" };
+        let plural = if value != 1 { "s" } else { "" };
+        let _ = write!(text, "{sign}{value} {unit}{plural}");
+        is_before = value < 0;
+    }
+
+    let micros = interval.nanoseconds / 1_000;
+    if text.is_empty() || micros != 0 {
+        if !text.is_empty() {
+            text.push(' ');
+        }
+        let sign = match micros {
+            micros if micros < 0 => "-",
+            _ if is_before => "+",
+            _ => "",
+        };
+        let micros = micros.unsigned_abs();
+        let seconds = micros / 1_000_000;
+        let _ = write!(
+            text,
+            "{sign}{:02}:{:02}:{:02}",
+            seconds / 3600,
+            seconds / 60 % 60,
+            seconds % 60
+        );
+        let fraction = micros % 1_000_000;
+        if fraction != 0 {
+            let fraction = format!("{fraction:06}");
+            text.push('.');
+            text.push_str(fraction.trim_end_matches('0'));
+        }
+    }
+    text
+}
+
+/// The fields of an interval postgres writes, years, months, days, hours,
+/// minutes, seconds and microseconds, each with the sign of its part
+fn interval_fields(interval: IntervalMonthDayNano) -> [i64; 7] {
+    let months = interval.months as i64;
+    let micros = interval.nanoseconds / 1_000;
+    [
+        months / 12,
+        months % 12,
+        interval.days as i64,
+        micros / 3_600_000_000,
+        micros / 60_000_000 % 60,
+        micros / 1_000_000 % 60,
+        micros % 1_000_000,
+    ]
+}
+
+/// Seconds and their fraction without the sign, like `05.25`
+fn format_seconds(seconds: i64, micros: i64, zero_padded: bool) -> String {
+    let mut text = if zero_padded {
+        format!("{:02}", seconds.abs())
+    } else {
+        seconds.abs().to_string()
+    };
+    if micros != 0 {
+        let fraction = format!("{:06}", micros.abs());
+        text.push('.');
+        text.push_str(fraction.trim_end_matches('0'));
+    }
+    text
+}
+
+/// An interval in `style`
+fn format_interval_in_style(interval: IntervalMonthDayNano, style: IntervalStyle) -> String {
+    match style {
+        IntervalStyle::Postgres => format_interval(interval),
+        IntervalStyle::PostgresVerbose => format_interval_verbose(interval),
+        IntervalStyle::SqlStandard => format_interval_sql_standard(interval),
+        IntervalStyle::Iso8601 => format_interval_iso_8601(interval),
+    }
+}
+
+/// An interval in the `postgres_verbose` IntervalStyle, like
+/// `@ 1 year 2 mons -3 days 4 hours 5.5 secs`, or with `ago` after its
+/// fields when the first is negative
+fn format_interval_verbose(interval: IntervalMonthDayNano) -> String {
+    let [years, months, days, hours, minutes, seconds, micros] = interval_fields(interval);
+    let mut text = "@".to_string();
+    let mut is_zero = true;
+    let mut is_before = false;
+    for (value, unit) in [
+        (years, "year"),
+        (months, "mon"),
+        (days, "day"),
+        (hours, "hour"),
+        (minutes, "min"),
+    ] {
+        if value == 0 {
+            continue;
+        }
+        // the first field sets whether the interval is `ago`
+        let value = if is_zero {
+            is_before = value < 0;
+            value.abs()
+        } else if is_before {
+            -value
+        } else {
+            value
+        };
+        let plural = if value != 1 { "s" } else { "" };
+        let _ = write!(text, " {value} {unit}{plural}");
+        is_zero = false;
+    }
+    if seconds != 0 || micros != 0 {
+        text.push(' ');
+        if seconds < 0 || (seconds == 0 && micros < 0) {
+            if is_zero {
+                is_before = true;
+            } else if !is_before {
+                text.push('-');
+            }
+        } else if is_before {
+            text.push('-');
+        }
+        text.push_str(&format_seconds(seconds, micros, false));
+        let plural = if seconds.abs() != 1 || micros != 0 {
+            "s"
+        } else {
+            ""
+        };
+        let _ = write!(text, " sec{plural}");
+        is_zero = false;
+    }
+    if is_zero {
+        text.push_str(" 0");
+    }
+    if is_before {
+        text.push_str(" ago");
+    }
+    text
+}
+
+/// An interval in the `sql_standard` IntervalStyle, like `-1-2` or
+/// `3 4:05:06.5`, and `+1-2 -3 +4:05:06` when it has fields of both kinds or
+/// of both signs, which the standard doesn't allow
+fn format_interval_sql_standard(interval: IntervalMonthDayNano) -> String {
+    let fields = interval_fields(interval);
+    let [years, months, days, hours, minutes, seconds, micros] = fields;
+    let has_negative = fields.iter().any(|field| *field < 0);
+    let has_positive = fields.iter().any(|field| *field > 0);
+    let has_year_month = years != 0 || months != 0;
+    let has_day_time = fields[2..].iter().any(|field| *field != 0);
+    if !has_negative && !has_positive {
+        return "0".to_string();
+    }
+    let seconds = format_seconds(seconds, micros, true);
+    if (has_negative && has_positive) || (has_year_month && has_day_time) {
+        let sign = |negative: bool| if negative { '-' } else { '+' };
+        return format!(
+            "{}{}-{} {}{} {}{}:{:02}:{seconds}",
+            sign(years < 0 || months < 0),
+            years.abs(),
+            months.abs(),
+            sign(days < 0),
+            days.abs(),
+            sign(interval.nanoseconds < 0),
+            hours.abs(),
+            minutes.abs(),
+        );
+    }
+    // a single sign before the whole interval
+    let sign = if has_negative { "-" } else { "" };
+    let (years, months, days, hours, minutes) = (
+        years.abs(),
+        months.abs(),
+        days.abs(),
+        hours.abs(),
+        minutes.abs(),
+    );
+    if has_year_month {
+        format!("{sign}{years}-{months}")
+    } else if days != 0 {
+        format!("{sign}{days} {hours}:{minutes:02}:{seconds}")
+    } else {
+        format!("{sign}{hours}:{minutes:02}:{seconds}")
+    }
+}
+
+/// An interval in the `iso_8601` IntervalStyle, a duration like
+/// `P1Y2M-3DT4H5M6.5S`
+fn format_interval_iso_8601(interval: IntervalMonthDayNano) -> String {
+    let [years, months, days, hours, minutes, seconds, micros] = interval_fields(interval);
+    if interval == IntervalMonthDayNano::new(0, 0, 0) {
+        return "PT0S".to_string();
+    }
+    let mut text = "P".to_string();
+    for (value, designator) in [(years, 'Y'), (months, 'M'), (days, 'D')] {
+        if value != 0 {
+            let _ = write!(text, "{value}{designator}");
+        }
+    }
+    if hours != 0 || minutes != 0 || seconds != 0 || micros != 0 {
+        text.push('T');
+    }
+    for (value, designator) in [(hours, 'H'), (minutes, 'M')] {
+        if value != 0 {
+            let _ = write!(text, "{value}{designator}");
+        }
+    }
+    if seconds != 0 || micros != 0 {
+        if seconds < 0 || micros < 0 {
+            text.push('-');
+        }
+        text.push_str(&format_seconds(seconds, micros, false));
+        text.push('S');
+    }
+    text
+}
+
+fn get_interval_value(arr: &Arc<dyn Array>, idx: usize) -> Option<Interval> {
+    if arr.is_null(idx) {
+        return None;
+    }
+    let interval = match arr.data_type() {
+        DataType::Interval(IntervalUnit::YearMonth) => {
+            IntervalMonthDayNano::new(arr.as_primitive::<IntervalYearMonthType>().value(idx), 0, 0)
+        }
+        DataType::Interval(IntervalUnit::DayTime) => {
+            let value = arr.as_primitive::<IntervalDayTimeType>().value(idx);
+            IntervalMonthDayNano::new(0, value.days, value.milliseconds as i64 * 1_000_000)
+        }
+        _ => arr.as_primitive::<IntervalMonthDayNanoType>().value(idx),
+    };
+    Some(Interval(interval))
+}
+
 fn get_bool_value(arr: &Arc<dyn Array>, idx: usize) -> Option<bool> {
     (!arr.is_null(idx)).then(|| {
         arr.as_any()
@@ -169,15 +446,12 @@ fn get_large_utf8_value(arr: &Arc<dyn Array>, idx: usize) -> Option<&str> {
     })
 }
 
-fn get_binary_value(arr: &Arc<dyn Array>, idx: usize) -> Option<String> {
+fn get_binary_value(arr: &Arc<dyn Array>, idx: usize) -> Option<&[u8]> {
     (!arr.is_null(idx)).then(|| {
-        String::from_utf8_lossy(
-            arr.as_any()
-                .downcast_ref::<BinaryArray>()
-                .unwrap()
-                .value(idx),
-        )
-        .to_string()
+        arr.as_any()
+            .downcast_ref::<BinaryArray>()
+            .unwrap()
+            .value(idx)
     })
 }
 
@@ -210,43 +484,43 @@ fn get_date64_value(arr: &Arc<dyn Array>, idx: usize) -> Option<NaiveDate> {
         .value_as_date(idx)
 }
 
-fn get_time32_second_value(arr: &Arc<dyn Array>, idx: usize) -> Option<NaiveDateTime> {
+fn get_time32_second_value(arr: &Arc<dyn Array>, idx: usize) -> Option<NaiveTime> {
     if arr.is_null(idx) {
         return None;
     }
     arr.as_any()
         .downcast_ref::<Time32SecondArray>()
         .unwrap()
-        .value_as_datetime(idx)
+        .value_as_time(idx)
 }
 
-fn get_time32_millisecond_value(arr: &Arc<dyn Array>, idx: usize) -> Option<NaiveDateTime> {
+fn get_time32_millisecond_value(arr: &Arc<dyn Array>, idx: usize) -> Option<NaiveTime> {
     if arr.is_null(idx) {
         return None;
     }
     arr.as_any()
         .downcast_ref::<Time32MillisecondArray>()
         .unwrap()
-        .value_as_datetime(idx)
+        .value_as_time(idx)
 }
 
-fn get_time64_microsecond_value(arr: &Arc<dyn Array>, idx: usize) -> Option<NaiveDateTime> {
+fn get_time64_microsecond_value(arr: &Arc<dyn Array>, idx: usize) -> Option<NaiveTime> {
     if arr.is_null(idx) {
         return None;
     }
     arr.as_any()
         .downcast_ref::<Time64MicrosecondArray>()
         .unwrap()
-        .value_as_datetime(idx)
+        .value_as_time(idx)
 }
-fn get_time64_nanosecond_value(arr: &Arc<dyn Array>, idx: usize) -> Option<NaiveDateTime> {
+fn get_time64_nanosecond_value(arr: &Arc<dyn Array>, idx: usize) -> Option<NaiveTime> {
     if arr.is_null(idx) {
         return None;
     }
     arr.as_any()
         .downcast_ref::<Time64NanosecondArray>()
         .unwrap()
-        .value_as_datetime(idx)
+        .value_as_time(idx)
 }
 
 fn get_numeric_128_value(
@@ -287,6 +561,92 @@ fn get_numeric_128_value(
         .map(Some)
 }
 
+/// `IntervalStyle`, the text format of intervals
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum IntervalStyle {
+    /// `1 year 2 mons 3 days 04:05:06`
+    #[default]
+    Postgres,
+    /// `@ 1 year 2 mons 3 days 4 hours 5 mins 6 secs`
+    PostgresVerbose,
+    /// `1-2` and `3 4:05:06`, or `+1-2 +3 +4:05:06` with fields of both
+    /// kinds or signs
+    SqlStandard,
+    /// `P1Y2M3DT4H5M6S`
+    Iso8601,
+}
+
+impl IntervalStyle {
+    /// The style of the setting value `name`, in any case
+    pub fn from_name(name: &str) -> Option<Self> {
+        [
+            IntervalStyle::Postgres,
+            IntervalStyle::PostgresVerbose,
+            IntervalStyle::SqlStandard,
+            IntervalStyle::Iso8601,
+        ]
+        .into_iter()
+        .find(|style| style.name().eq_ignore_ascii_case(name))
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            IntervalStyle::Postgres => "postgres",
+            IntervalStyle::PostgresVerbose => "postgres_verbose",
+            IntervalStyle::SqlStandard => "sql_standard",
+            IntervalStyle::Iso8601 => "iso_8601",
+        }
+    }
+}
+
+/// Session settings of how values are written in the text format
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextFormat {
+    /// `extra_float_digits`, floats are written in their shortest form that
+    /// reads back exactly when it is positive, and rounded to 15 digits for
+    /// `float8` and 6 for `float4` plus this many, like C's `%g`, otherwise
+    pub extra_float_digits: i8,
+    pub interval_style: IntervalStyle,
+}
+
+impl Default for TextFormat {
+    fn default() -> Self {
+        TextFormat {
+            extra_float_digits: 1,
+            interval_style: IntervalStyle::default(),
+        }
+    }
+}
+
+/// `value` like C's `printf("%.{digits}g")`, the output of floats in
+/// postgres before 12 and with `extra_float_digits` of 0 or less
+fn format_float_legacy(value: f64, digits: usize) -> String {
+    if value.is_nan() {
+        return "NaN".to_string();
+    }
+    if value.is_infinite() {
+        return if value > 0.0 { "Infinity" } else { "-Infinity" }.to_string();
+    }
+    let digits = digits.max(1);
+    let scientific = format!("{:.*e}", digits - 1, value);
+    let (mantissa, exponent) = scientific.split_once('e').unwrap_or((&scientific, "0"));
+    let exponent = exponent.parse::<i32>().unwrap_or(0);
+    let trim = |text: &str| {
+        if text.contains('.') {
+            text.trim_end_matches('0').trim_end_matches('.').to_string()
+        } else {
+            text.to_string()
+        }
+    };
+    if exponent < -4 || exponent >= digits as i32 {
+        let sign = if exponent < 0 { '-' } else { '+' };
+        format!("{}e{sign}{:02}", trim(mantissa), exponent.abs())
+    } else {
+        let decimals = (digits as i32 - 1 - exponent).max(0) as usize;
+        trim(&format!("{value:.decimals$}"))
+    }
+}
+
 pub fn encode_value<T: Encoder>(
     encoder: &mut T,
     arr: &Arc<dyn Array>,
@@ -305,6 +665,12 @@ pub fn encode_value<T: Encoder>(
         DataType::Int16 => {
             encoder.encode_field_with_type_and_format(&get_i16_value(arr, idx), type_, format)?
         }
+        // oids stored as int4 are sent unsigned
+        DataType::Int32 if type_ == &Type::OID => encoder.encode_field_with_type_and_format(
+            &(get_i32_value(arr, idx).map(|x| x as u32)),
+            type_,
+            format,
+        )?,
         DataType::Int32 => {
             encoder.encode_field_with_type_and_format(&get_i32_value(arr, idx), type_, format)?
         }
@@ -489,6 +855,11 @@ pub fn encode_value<T: Encoder>(
                 }
             }
         },
+        DataType::Interval(_) => encoder.encode_field_with_type_and_format(
+            &get_interval_value(arr, idx),
+            type_,
+            format,
+        )?,
         DataType::List(_) | DataType::FixedSizeList(_, _) | DataType::LargeList(_) => {
             if arr.is_null(idx) {
                 return encoder.encode_field_with_type_and_format(&None::<&[i8]>, type_, format);
@@ -551,35 +922,71 @@ pub fn encode_value<T: Encoder>(
     Ok(())
 }
 
+/// Encode the value like `encode_value`, writing text as set by the session
+/// settings of `text_format`
+pub fn encode_value_with_text_format<T: Encoder>(
+    encoder: &mut T,
+    arr: &Arc<dyn Array>,
+    idx: usize,
+    type_: &Type,
+    format: FieldFormat,
+    text_format: &TextFormat,
+) -> PgWireResult<()> {
+    if format == FieldFormat::Text {
+        let legacy_floats = text_format.extra_float_digits <= 0;
+        let digits = |precision: i8| (precision + text_format.extra_float_digits).max(1) as usize;
+        let text = match arr.data_type() {
+            DataType::Float32 if legacy_floats => {
+                get_f32_value(arr, idx).map(|value| format_float_legacy(value.into(), digits(6)))
+            }
+            DataType::Float64 if legacy_floats => {
+                get_f64_value(arr, idx).map(|value| format_float_legacy(value, digits(15)))
+            }
+            DataType::Interval(_) if text_format.interval_style != IntervalStyle::Postgres => {
+                get_interval_value(arr, idx).map(|interval| {
+                    format_interval_in_style(interval.0, text_format.interval_style)
+                })
+            }
+            _ => None,
+        };
+        if let Some(text) = text {
+            return encoder.encode_field_with_type_and_format(&text, type_, format);
+        }
+    }
+    encode_value(encoder, arr, idx, type_, format)
+}
+
 #[cfg(test)]
 mod tests {
+    use postgres_types::FromSql;
+
     use super::*;
 
-    #[test]
-    fn encodes_dictionary_array() {
-        #[derive(Default)]
-        struct MockEncoder {
-            encoded_value: String,
-        }
-
-        impl Encoder for MockEncoder {
-            fn encode_field_with_type_and_format<T>(
-                &mut self,
-                value: &T,
-                data_type: &Type,
-                _format: FieldFormat,
-            ) -> PgWireResult<()>
-            where
-                T: ToSql + ToSqlText + Sized,
-            {
-                let mut bytes = BytesMut::new();
-                let _sql_text = value.to_sql_text(data_type, &mut bytes);
-                let string = String::from_utf8(bytes.to_vec());
-                self.encoded_value = string.unwrap();
-                Ok(())
-            }
+    #[derive(Default)]
+    struct MockEncoder {
+        encoded_value: String,
+    }
+
+    impl Encoder for MockEncoder {
+        fn encode_field_with_type_and_format<T>(
+            &mut self,
+            value: &T,
+            data_type: &Type,
+            _format: FieldFormat,
+        ) -> PgWireResult<()>
+        where
+            T: ToSql + ToSqlText + Sized,
+        {
+            let mut bytes = BytesMut::new();
+            let _sql_text = value.to_sql_text(data_type, &mut bytes);
+            let string = String::from_utf8(bytes.to_vec());
+            self.encoded_value = string.unwrap();
+            Ok(())
         }
+    }
 
+    #[test]
+    fn encodes_dictionary_array() {
         let val = "~!@&$[]()@@!!";
         let value = StringArray::from_iter_values([val]);
         let keys = Int8Array::from_iter_values([0, 0, 0, 0]);
@@ -594,4 +1001,243 @@ mod tests {
 
         assert!(encoder.encoded_value == val);
     }
+
+    #[test]
+    fn encodes_vectors_as_text() {
+        let int2vector: Arc<dyn Array> =
+            Arc::new(ListArray::from_iter_primitive::<Int16Type, _, _>([Some([
+                Some(1),
+                Some(3),
+            ])]));
+        let mut encoder = MockEncoder::default();
+        encode_value(
+            &mut encoder,
+            &int2vector,
+            0,
+            &Type::INT2_VECTOR,
+            FieldFormat::Text,
+        )
+        .unwrap();
+        assert_eq!(encoder.encoded_value, "1 3");
+
+        let oidvector: Arc<dyn Array> = Arc::new(
+            ListArray::from_iter_primitive::<UInt32Type, _, _>([Some([Some(23), Some(25)])]),
+        );
+        encode_value(
+            &mut encoder,
+            &oidvector,
+            0,
+            &Type::OID_VECTOR,
+            FieldFormat::Text,
+        )
+        .unwrap();
+        assert_eq!(encoder.encoded_value, "23 25");
+
+        // other lists keep the array format
+        encode_value(
+            &mut encoder,
+            &int2vector,
+            0,
+            &Type::INT2_ARRAY,
+            FieldFormat::Text,
+        )
+        .unwrap();
+        assert_eq!(encoder.encoded_value, "{1,3}");
+    }
+
+    #[test]
+    fn encodes_intervals() {
+        const HOUR: i64 = 3_600_000_000_000;
+        let intervals: Arc<dyn Array> = Arc::new(IntervalMonthDayNanoArray::from(vec![
+            IntervalMonthDayNano::new(0, 0, 0),
+            IntervalMonthDayNano::new(14, 3, 4 * HOUR + 5_500_000_000),
+            IntervalMonthDayNano::new(-1, 1, -HOUR),
+            IntervalMonthDayNano::new(0, -3, HOUR),
+            IntervalMonthDayNano::new(0, 0, 25 * HOUR),
+        ]));
+        let mut encoder = MockEncoder::default();
+        let mut texts = vec![];
+        for idx in 0..intervals.len() {
+            encode_value(
+                &mut encoder,
+                &intervals,
+                idx,
+                &Type::INTERVAL,
+                FieldFormat::Text,
+            )
+            .unwrap();
+            texts.push(encoder.encoded_value.clone());
+        }
+        assert_eq!(
+            texts,
+            [
+                "00:00:00",
+                "1 year 2 mons 3 days 04:00:05.5",
+                "-1 mons +1 day -01:00:00",
+                "-3 days +01:00:00",
+                "25:00:00",
+            ]
+        );
+
+        let mut bytes = BytesMut::new();
+        Interval(IntervalMonthDayNano::new(14, 3, 5_000))
+            .to_sql(&Type::INTERVAL, &mut bytes)
+            .unwrap();
+        assert_eq!(
+            Interval::from_sql(&Type::INTERVAL, &bytes).unwrap(),
+            Interval(IntervalMonthDayNano::new(14, 3, 5_000))
+        );
+
+        // '3000000 hours' has more nanoseconds than fit an i64
+        let mut bytes = BytesMut::new();
+        bytes.extend_from_slice(&(3_000_000i64 * 3_600_000_000).to_be_bytes());
+        bytes.extend_from_slice(&[0; 8]);
+        let err = Interval::from_sql(&Type::INTERVAL, &bytes).unwrap_err();
+        assert_eq!(err.to_string(), "interval out of range");
+    }
+
+    #[test]
+    fn encodes_floats_with_extra_float_digits() {
+        let floats: Arc<dyn Array> = Arc::new(Float64Array::from(vec![
+            0.1 + 0.2,
+            1e100,
+            0.000012345,
+            -2.5,
+            f64::NAN,
+            f64::NEG_INFINITY,
+            100.0,
+        ]));
+        let mut encoder = MockEncoder::default();
+        let mut encode = |floats: &Arc<dyn Array>, idx, extra_float_digits| {
+            let text_format = TextFormat {
+                extra_float_digits,
+                ..TextFormat::default()
+            };
+            encode_value_with_text_format(
+                &mut encoder,
+                floats,
+                idx,
+                &Type::FLOAT8,
+                FieldFormat::Text,
+                &text_format,
+            )
+            .unwrap();
+            encoder.encoded_value.clone()
+        };
+        assert_eq!(encode(&floats, 0, 1), "0.30000000000000004");
+        assert_eq!(encode(&floats, 0, 3), "0.30000000000000004");
+        let legacy = (0..floats.len())
+            .map(|idx| encode(&floats, idx, 0))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            legacy,
+            [
+                "0.3",
+                "1e+100",
+                "1.2345e-05",
+                "-2.5",
+                "NaN",
+                "-Infinity",
+                "100"
+            ]
+        );
+        assert_eq!(encode(&floats, 0, -14), "0.3");
+        assert_eq!(encode(&floats, 3, -15), "-2");
+
+        let float4s: Arc<dyn Array> = Arc::new(Float32Array::from(vec![1.0 / 3.0]));
+        assert_eq!(encode(&float4s, 0, 1), "0.33333334");
+        assert_eq!(encode(&float4s, 0, 0), "0.333333");
+        assert_eq!(encode(&float4s, 0, -3), "0.333");
+    }
+
+    #[test]
+    fn encodes_intervals_in_style() {
+        const HOUR: i64 = 3_600_000_000_000;
+        let intervals: Arc<dyn Array> = Arc::new(IntervalMonthDayNanoArray::from(vec![
+            IntervalMonthDayNano::new(0, 0, 0),
+            IntervalMonthDayNano::new(14, 3, 4 * HOUR + 5_500_000_000),
+            IntervalMonthDayNano::new(-14, 0, 0),
+            IntervalMonthDayNano::new(0, 3, 4 * HOUR + 300_000_000_000),
+            IntervalMonthDayNano::new(0, -3, -HOUR - 1_000_000_000),
+            IntervalMonthDayNano::new(-1, 1, -HOUR),
+            IntervalMonthDayNano::new(0, 0, -500_000_000),
+        ]));
+        let mut encoder = MockEncoder::default();
+        let mut encode = |interval_style| {
+            let text_format = TextFormat {
+                interval_style,
+                ..TextFormat::default()
+            };
+            (0..intervals.len())
+                .map(|idx| {
+                    encode_value_with_text_format(
+                        &mut encoder,
+                        &intervals,
+                        idx,
+                        &Type::INTERVAL,
+                        FieldFormat::Text,
+                        &text_format,
+                    )
+                    .unwrap();
+                    encoder.encoded_value.clone()
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            encode(IntervalStyle::SqlStandard),
+            [
+                "0",
+                "+1-2 +3 +4:00:05.5",
+                "-1-2",
+                "3 4:05:00",
+                "-3 1:00:01",
+                "-0-1 +1 -1:00:00",
+                "-0:00:00.5",
+            ]
+        );
+        assert_eq!(
+            encode(IntervalStyle::Iso8601),
+            [
+                "PT0S",
+                "P1Y2M3DT4H5.5S",
+                "P-1Y-2M",
+                "P3DT4H5M",
+                "P-3DT-1H-1S",
+                "P-1M1DT-1H",
+                "PT-0.5S",
+            ]
+        );
+        assert_eq!(
+            encode(IntervalStyle::PostgresVerbose),
+            [
+                "@ 0",
+                "@ 1 year 2 mons 3 days 4 hours 5.5 secs",
+                "@ 1 year 2 mons ago",
+                "@ 3 days 4 hours 5 mins",
+                "@ 3 days 1 hour 1 sec ago",
+                "@ 1 mon -1 days 1 hour ago",
+                "@ 0.5 secs ago",
+            ]
+        );
+        assert_eq!(
+            encode(IntervalStyle::Postgres)[1],
+            "1 year 2 mons 3 days 04:00:05.5"
+        );
+
+        assert_eq!(
+            IntervalStyle::from_name("ISO_8601"),
+            Some(IntervalStyle::Iso8601)
+        );
+        assert_eq!(IntervalStyle::from_name("iso"), None);
+    }
+
+    #[test]
+    fn encodes_int4_oids_unsigned() {
+        let oids: Arc<dyn Array> = Arc::new(Int32Array::from(vec![16384, -1]));
+        let mut encoder = MockEncoder::default();
+        encode_value(&mut encoder, &oids, 0, &Type::OID, FieldFormat::Text).unwrap();
+        assert_eq!(encoder.encoded_value, "16384");
+        encode_value(&mut encoder, &oids, 1, &Type::OID, FieldFormat::Text).unwrap();
+        assert_eq!(encoder.encoded_value, "4294967295");
+    }
 }
@@ -3,7 +3,9 @@
 // #[cfg(all(feature = "arrow", feature = "datafusion"))]
 // compile_error!("Feature arrow and datafusion cannot be enabled at same time. Use no-default-features when activating datafusion");
 
+mod columnar_encoder;
 pub mod datatypes;
+pub mod decoder;
 pub mod encoder;
 mod error;
 pub mod list_encoder;
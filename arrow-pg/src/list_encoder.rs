@@ -106,11 +106,41 @@ fn encode_field<T: ToSql + ToSqlText>(
     Ok(EncodedValue { bytes })
 }
 
+/// Text of an `int2vector` or `oidvector`, its elements separated by spaces
+fn encode_vector_text(arr: &Arc<dyn Array>) -> PgWireResult<EncodedValue> {
+    let values = match arr.data_type() {
+        DataType::Int16 => get_i16_list_value(arr)
+            .into_iter()
+            .map(|value| value.map(|value| value.to_string()))
+            .collect::<Vec<_>>(),
+        DataType::UInt32 => get_u32_list_value(arr)
+            .into_iter()
+            .map(|value| value.map(|value| value.to_string()))
+            .collect::<Vec<_>>(),
+        list_type => {
+            return Err(PgWireError::ApiError(ToSqlError::from(format!(
+                "Unsupported vector Datatype {list_type}"
+            ))))
+        }
+    };
+    let text = values
+        .into_iter()
+        .map(|value| value.unwrap_or_else(|| "0".to_string()))
+        .collect::<Vec<_>>()
+        .join(" ");
+    Ok(EncodedValue {
+        bytes: BytesMut::from(text.as_bytes()),
+    })
+}
+
 pub(crate) fn encode_list(
     arr: Arc<dyn Array>,
     type_: &Type,
     format: FieldFormat,
 ) -> PgWireResult<EncodedValue> {
+    if format == FieldFormat::Text && (type_ == &Type::INT2_VECTOR || type_ == &Type::OID_VECTOR) {
+        return encode_vector_text(&arr);
+    }
     match arr.data_type() {
         DataType::Null => {
             let mut bytes = BytesMut::new();
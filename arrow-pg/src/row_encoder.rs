@@ -1,47 +1,293 @@
 use std::sync::Arc;
 
 #[cfg(not(feature = "datafusion"))]
-use arrow::array::RecordBatch;
+use arrow::{
+    array::{Array, ArrayRef, AsArray, RecordBatch},
+    datatypes::DataType,
+};
 #[cfg(feature = "datafusion")]
-use datafusion::arrow::array::RecordBatch;
+use datafusion::arrow::{
+    array::{Array, ArrayRef, AsArray, RecordBatch},
+    datatypes::DataType,
+};
 
+use bytes::{BufMut, BytesMut};
 use pgwire::{
-    api::results::{DataRowEncoder, FieldInfo},
-    error::PgWireResult,
+    api::results::{FieldFormat, FieldInfo},
+    error::{ErrorInfo, PgWireError, PgWireResult},
     messages::data::DataRow,
+    types::ToSqlText,
 };
+use postgres_types::{IsNull, ToSql, Type};
+
+use crate::columnar_encoder::BinaryColumn;
+use crate::encoder::{encode_value_with_text_format, Encoder, TextFormat};
 
-use crate::encoder::encode_value;
+/// Largest value postgres sends in a field, 1 GiB - 1
+pub const DEFAULT_MAX_FIELD_SIZE: usize = 0x3fff_ffff;
+
+/// Largest data of a row, the rest of its message being its length and
+/// number of columns
+const MAX_ROW_SIZE: usize = i32::MAX as usize - 6;
+
+/// Largest buffer allocated up front for the next row, so a single huge
+/// value doesn't make every following row allocate as much
+const MAX_ROW_SIZE_HINT: usize = 64 * 1024;
 
 pub struct RowEncoder {
     rb: RecordBatch,
     curr_idx: usize,
     fields: Arc<Vec<FieldInfo>>,
+    // fast path of each binary-format, fixed-width column
+    binary_columns: Vec<Option<BinaryColumn>>,
+    // size of the previous row, to allocate the next one at once
+    row_size_hint: usize,
+    max_field_size: usize,
+    text_format: TextFormat,
 }
 
 impl RowEncoder {
     pub fn new(rb: RecordBatch, fields: Arc<Vec<FieldInfo>>) -> Self {
         assert_eq!(rb.num_columns(), fields.len());
+        let binary_columns = rb
+            .columns()
+            .iter()
+            .zip(fields.iter())
+            .map(|(array, field)| {
+                (field.format() == FieldFormat::Binary)
+                    .then(|| BinaryColumn::try_new(array, field.datatype()))
+                    .flatten()
+            })
+            .collect();
         Self {
             rb,
             fields,
             curr_idx: 0,
+            binary_columns,
+            row_size_hint: 128,
+            max_field_size: DEFAULT_MAX_FIELD_SIZE,
+            text_format: TextFormat::default(),
         }
     }
 
+    /// Fail rows with a value of more than `max_field_size` bytes once
+    /// encoded, instead of sending them
+    pub fn with_max_field_size(mut self, max_field_size: usize) -> Self {
+        self.max_field_size = max_field_size;
+        self
+    }
+
+    /// Write values in the text format as set by the session settings of
+    /// `text_format`
+    pub fn with_text_format(mut self, text_format: TextFormat) -> Self {
+        self.text_format = text_format;
+        self
+    }
+
     pub fn next_row(&mut self) -> Option<PgWireResult<DataRow>> {
         if self.curr_idx == self.rb.num_rows() {
             return None;
         }
-        let mut encoder = DataRowEncoder::new(self.fields.clone());
+        let mut encoder = RowBuffer::with_capacity(self.row_size_hint);
         for col in 0..self.rb.num_columns() {
+            if let Some(column) = &self.binary_columns[col] {
+                if column.encode(self.curr_idx, &mut encoder.buf) {
+                    encoder.num_cols += 1;
+                    continue;
+                }
+            }
+
             let array = self.rb.column(col);
             let field = &self.fields[col];
             let type_ = field.datatype();
             let format = field.format();
-            encode_value(&mut encoder, array, self.curr_idx, type_, format).unwrap();
+            // strings and binaries are checked before they are copied, and
+            // written into a buffer of their size at once
+            if let Some(size) = encoded_size(array, self.curr_idx, format) {
+                if size > self.max_field_size {
+                    self.curr_idx += 1;
+                    return Some(Err(self.field_too_large(col, size)));
+                }
+                encoder.buf.reserve(4 + size);
+            }
+            let start = encoder.buf.len();
+            if let Err(e) = encode_value_with_text_format(
+                &mut encoder,
+                array,
+                self.curr_idx,
+                type_,
+                format,
+                &self.text_format,
+            ) {
+                self.curr_idx += 1;
+                return Some(Err(e));
+            }
+            let size = encoder.buf.len() - start - 4;
+            if size > self.max_field_size {
+                self.curr_idx += 1;
+                return Some(Err(self.field_too_large(col, size)));
+            }
         }
         self.curr_idx += 1;
-        Some(encoder.finish())
+        if encoder.buf.len() > MAX_ROW_SIZE {
+            return Some(Err(PgWireError::UserError(Box::new(ErrorInfo::new(
+                "ERROR".to_string(),
+                "54000".to_string(), // program_limit_exceeded
+                format!(
+                    "row of {} bytes exceeds the largest message of {MAX_ROW_SIZE} bytes, lower max_field_size to limit its fields",
+                    encoder.buf.len()
+                ),
+            )))));
+        }
+        self.row_size_hint = encoder.buf.len().min(MAX_ROW_SIZE_HINT);
+        Some(Ok(DataRow::new(encoder.buf, encoder.num_cols)))
+    }
+
+    fn field_too_large(&self, col: usize, size: usize) -> PgWireError {
+        PgWireError::UserError(Box::new(ErrorInfo::new(
+            "ERROR".to_string(),
+            "54000".to_string(), // program_limit_exceeded
+            format!(
+                "value of column \"{}\" of {size} bytes exceeds max_field_size of {} bytes",
+                self.fields[col].name(),
+                self.max_field_size
+            ),
+        )))
+    }
+}
+
+/// Encoded size of the string or binary value at `idx` of `array`, known
+/// before encoding it
+fn encoded_size(array: &ArrayRef, idx: usize, format: FieldFormat) -> Option<usize> {
+    if array.is_null(idx) {
+        return None;
+    }
+    let (size, binary) = match array.data_type() {
+        DataType::Utf8 => (array.as_string::<i32>().value_length(idx) as usize, false),
+        DataType::LargeUtf8 => (array.as_string::<i64>().value_length(idx) as usize, false),
+        DataType::Utf8View => (array.as_string_view().value(idx).len(), false),
+        DataType::Binary => (array.as_binary::<i32>().value_length(idx) as usize, true),
+        DataType::LargeBinary => (array.as_binary::<i64>().value_length(idx) as usize, true),
+        DataType::BinaryView => (array.as_binary_view().value(idx).len(), true),
+        _ => return None,
+    };
+    // bytea is sent in hex after `\x` in the text format
+    Some(if binary && format == FieldFormat::Text {
+        2 + 2 * size
+    } else {
+        size
+    })
+}
+
+/// Buffer of a data row, encoding values like pgwire's `DataRowEncoder` and
+/// also taking values written directly by the columnar fast path
+struct RowBuffer {
+    buf: BytesMut,
+    num_cols: i16,
+}
+
+impl RowBuffer {
+    fn with_capacity(capacity: usize) -> Self {
+        Self {
+            buf: BytesMut::with_capacity(capacity),
+            num_cols: 0,
+        }
+    }
+}
+
+impl Encoder for RowBuffer {
+    fn encode_field_with_type_and_format<T>(
+        &mut self,
+        value: &T,
+        data_type: &Type,
+        format: FieldFormat,
+    ) -> PgWireResult<()>
+    where
+        T: ToSql + ToSqlText + Sized,
+    {
+        // write the length as -1 (NULL) and fill it in once the value is
+        // written
+        let len_index = self.buf.len();
+        self.buf.put_i32(-1);
+
+        let is_null = if format == FieldFormat::Text {
+            value.to_sql_text(data_type, &mut self.buf)?
+        } else {
+            value.to_sql(data_type, &mut self.buf)?
+        };
+
+        if let IsNull::No = is_null {
+            let len = self.buf.len() - len_index - 4;
+            let mut len_bytes = &mut self.buf[len_index..len_index + 4];
+            len_bytes.put_i32(len as i32);
+        }
+
+        self.num_cols += 1;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(not(feature = "datafusion"))]
+    use arrow::{
+        array::{BinaryArray, StringArray},
+        datatypes::{Field, Schema},
+    };
+    #[cfg(feature = "datafusion")]
+    use datafusion::arrow::{
+        array::{BinaryArray, StringArray},
+        datatypes::{Field, Schema},
+    };
+
+    use super::*;
+
+    fn encode_rows(
+        array: ArrayRef,
+        pg_type: Type,
+        format: FieldFormat,
+    ) -> Vec<PgWireResult<DataRow>> {
+        let schema = Schema::new(vec![Field::new("c", array.data_type().clone(), true)]);
+        let rb = RecordBatch::try_new(Arc::new(schema), vec![array]).unwrap();
+        let fields = Arc::new(vec![FieldInfo::new(
+            "c".to_string(),
+            None,
+            None,
+            pg_type,
+            format,
+        )]);
+        let mut encoder = RowEncoder::new(rb, fields).with_max_field_size(1000);
+        std::iter::from_fn(|| encoder.next_row()).collect()
+    }
+
+    fn error_code(row: &PgWireResult<DataRow>) -> Option<&str> {
+        match row {
+            Err(PgWireError::UserError(info)) => Some(info.code.as_str()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_max_field_size() {
+        let text = "x".repeat(1001);
+        let array: ArrayRef = Arc::new(StringArray::from(vec![Some("a"), None, Some(&text)]));
+        let rows = encode_rows(array, Type::TEXT, FieldFormat::Text);
+        assert!(rows[0].is_ok() && rows[1].is_ok());
+        assert_eq!(error_code(&rows[2]), Some("54000"));
+        let Err(PgWireError::UserError(info)) = &rows[2] else {
+            unreachable!()
+        };
+        assert_eq!(
+            info.message,
+            "value of column \"c\" of 1001 bytes exceeds max_field_size of 1000 bytes"
+        );
+
+        // bytea doubles in size in hex
+        let bytes = vec![0u8; 600];
+        let array: ArrayRef = Arc::new(BinaryArray::from(vec![bytes.as_slice()]));
+        let text_rows = encode_rows(array.clone(), Type::BYTEA, FieldFormat::Text);
+        assert_eq!(error_code(&text_rows[0]), Some("54000"));
+        let binary_rows = encode_rows(array, Type::BYTEA, FieldFormat::Binary);
+        assert_eq!(binary_rows[0].as_ref().unwrap().data.len(), 604);
     }
 }
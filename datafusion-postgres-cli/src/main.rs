@@ -6,7 +6,12 @@ use datafusion::execution::options::{
     ArrowReadOptions, AvroReadOptions, CsvReadOptions, NdJsonReadOptions, ParquetReadOptions,
 };
 use datafusion::prelude::{SessionConfig, SessionContext};
+use datafusion_postgres::hba::HbaRule;
+use datafusion_postgres::jwt::JwtConfig;
 use datafusion_postgres::pg_catalog::setup_pg_catalog;
+use datafusion_postgres::pgbench::setup_pgbench_tables;
+use datafusion_postgres::tls::SniCertificate;
+use datafusion_postgres::wire_debug::WireDebug;
 use datafusion_postgres::{serve, ServerOptions};
 use env_logger::Env;
 use log::info;
@@ -36,6 +41,10 @@ struct Opt {
     /// Directory to serve, all supported files will be registered as tables
     #[structopt(long("dir"), short("d"))]
     directory: Option<String>,
+    /// Create the pgbench tables of this scale factor in memory, to load
+    /// test the server with `pgbench -S -n`
+    #[structopt(long("pgbench-scale"))]
+    pgbench_scale: Option<u32>,
     /// Port the server listens to, default to 5432
     #[structopt(short, default_value = "5432")]
     port: u16,
@@ -48,6 +57,41 @@ struct Opt {
     /// Path to TLS private key file
     #[structopt(long("tls-key"))]
     tls_key: Option<String>,
+    /// Certificate served to clients connecting to a hostname, using syntax
+    /// `hostname:cert_path:key_path`
+    #[structopt(long("tls-sni"))]
+    tls_sni_certificates: Vec<SniCertificate>,
+    /// Rule of the clients that may connect, like `hostssl 0.0.0.0/0` or
+    /// `host 127.0.0.1/32`. Clients no rule matches are refused.
+    #[structopt(long("hba"))]
+    hba_rules: Vec<HbaRule>,
+    /// File of the roles of the server, with a line `name:password:attributes`
+    /// per role. Written back when roles are changed over SQL.
+    #[structopt(long("credentials-file"))]
+    credentials_file: Option<String>,
+    /// Directory of the local files `COPY ... TO` may write
+    #[structopt(long("copy-export-root"))]
+    copy_export_root: Option<String>,
+    /// Directory of the local files `CREATE EXTERNAL TABLE` may read
+    #[structopt(long("import-root"))]
+    import_root: Option<String>,
+    /// Read the client address of connections from the PROXY protocol header
+    /// of a load balancer like HAProxy in front of the server
+    #[structopt(long("proxy-protocol"))]
+    proxy_protocol: bool,
+    /// JWKS endpoint to verify JSON Web Tokens sent as password with,
+    /// enables token authentication
+    #[structopt(long("jwks-url"))]
+    jwks_url: Option<String>,
+    /// Accepted issuer of JSON Web Tokens
+    #[structopt(long("jwt-issuer"))]
+    jwt_issuers: Vec<String>,
+    /// Accepted audience of JSON Web Tokens
+    #[structopt(long("jwt-audience"))]
+    jwt_audiences: Vec<String>,
+    /// Log every protocol message: `on`, or `hex` to add the message bytes
+    #[structopt(long("wire-debug"), default_value = "off")]
+    wire_debug: WireDebug,
 }
 
 fn parse_table_def(table_def: &str) -> (&str, &str) {
@@ -178,6 +222,13 @@ async fn setup_session_context(
         info!("Loaded {table_path} as table {table_name}");
     }
 
+    // Register pgbench tables
+    if let Some(scale) = opts.pgbench_scale {
+        setup_pgbench_tables(session_context, scale)
+            .map_err(|e| format!("Failed to create pgbench tables: {e}"))?;
+        info!("Created pgbench tables of scale {scale}");
+    }
+
     // Register pg_catalog
     setup_pg_catalog(session_context, "datafusion")?;
 
@@ -203,7 +254,19 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .with_host(opts.host)
         .with_port(opts.port)
         .with_tls_cert_path(opts.tls_cert)
-        .with_tls_key_path(opts.tls_key);
+        .with_tls_key_path(opts.tls_key)
+        .with_tls_sni_certificates(opts.tls_sni_certificates)
+        .with_proxy_protocol(opts.proxy_protocol)
+        .with_hba_rules(opts.hba_rules)
+        .with_credentials_file(opts.credentials_file)
+        .with_copy_export_root(opts.copy_export_root)
+        .with_import_root(opts.import_root)
+        .with_wire_debug(opts.wire_debug)
+        .with_jwt(opts.jwks_url.as_deref().map(|jwks_url| {
+            JwtConfig::new(jwks_url)
+                .with_issuers(opts.jwt_issuers)
+                .with_audiences(opts.jwt_audiences)
+        }));
 
     serve(Arc::new(session_context), &server_options)
         .await
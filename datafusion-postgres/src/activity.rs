@@ -0,0 +1,560 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Instant;
+
+use chrono::{DateTime, Utc};
+use datafusion::sql::sqlparser::ast::Statement;
+use futures::StreamExt;
+use pgwire::api::results::{QueryResponse, Response};
+use pgwire::api::{ClientInfo, METADATA_DATABASE, METADATA_USER};
+use pgwire::messages::response::TransactionStatus;
+
+use crate::sql::normalize;
+
+/// Default of postgres' `track_activity_query_size`
+pub const DEFAULT_TRACK_ACTIVITY_QUERY_SIZE: usize = 1024;
+
+/// State of a session as shown in `pg_stat_activity.state`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionState {
+    Active,
+    Idle,
+    IdleInTransaction,
+    IdleInTransactionAborted,
+}
+
+impl SessionState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SessionState::Active => "active",
+            SessionState::Idle => "idle",
+            SessionState::IdleInTransaction => "idle in transaction",
+            SessionState::IdleInTransactionAborted => "idle in transaction (aborted)",
+        }
+    }
+}
+
+/// Kind of a statement, as counted by `SessionStatistics`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatementKind {
+    Select,
+    Insert,
+    Update,
+    Delete,
+    Ddl,
+    /// Utility statements such as `SET`, `SHOW` and transaction control
+    Other,
+}
+
+impl StatementKind {
+    pub fn of(statement: &Statement) -> Self {
+        match statement {
+            Statement::Query(_) => StatementKind::Select,
+            Statement::Insert(_) => StatementKind::Insert,
+            Statement::Update { .. } => StatementKind::Update,
+            Statement::Delete(_) => StatementKind::Delete,
+            Statement::CreateTable(_)
+            | Statement::CreateView { .. }
+            | Statement::CreateSchema { .. }
+            | Statement::CreateDatabase { .. }
+            | Statement::CreateIndex(_)
+            | Statement::CreateFunction(_)
+            | Statement::AlterTable { .. }
+            | Statement::AlterView { .. }
+            | Statement::Drop { .. }
+            | Statement::Truncate { .. } => StatementKind::Ddl,
+            _ => StatementKind::Other,
+        }
+    }
+}
+
+/// Counters of the statements of a session
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SessionStatistics {
+    pub select_statements: u64,
+    pub insert_statements: u64,
+    pub update_statements: u64,
+    pub delete_statements: u64,
+    pub ddl_statements: u64,
+    pub other_statements: u64,
+    /// Rows sent in query results
+    pub rows_returned: u64,
+    /// Bytes of the data row messages sent
+    pub bytes_sent: u64,
+    /// Statements that failed
+    pub errors: u64,
+}
+
+impl SessionStatistics {
+    /// Statements of all kinds
+    pub fn statements(&self) -> u64 {
+        self.select_statements
+            + self.insert_statements
+            + self.update_statements
+            + self.delete_statements
+            + self.ddl_statements
+            + self.other_statements
+    }
+
+    fn count_statement(&mut self, kind: StatementKind) {
+        let counter = match kind {
+            StatementKind::Select => &mut self.select_statements,
+            StatementKind::Insert => &mut self.insert_statements,
+            StatementKind::Update => &mut self.update_statements,
+            StatementKind::Delete => &mut self.delete_statements,
+            StatementKind::Ddl => &mut self.ddl_statements,
+            StatementKind::Other => &mut self.other_statements,
+        };
+        *counter += 1;
+    }
+}
+
+/// Activity of a session, a row of `pg_stat_activity`
+#[derive(Debug, Clone)]
+pub struct SessionActivity {
+    /// Process id reported for the session, assigned by the registry
+    pub pid: i32,
+    pub client_addr: SocketAddr,
+    pub usename: Option<String>,
+    pub datname: Option<String>,
+    pub application_name: Option<String>,
+    pub backend_start: DateTime<Utc>,
+    pub xact_start: Option<DateTime<Utc>>,
+    pub query_start: Option<DateTime<Utc>>,
+    pub state_change: Option<DateTime<Utc>>,
+    /// `None` until the session runs its first statement
+    pub state: Option<SessionState>,
+    /// Wait event type and name
+    pub wait_event: Option<(&'static str, &'static str)>,
+    /// Identifier of the running or last statement, see `sql::query_id`
+    pub query_id: Option<i64>,
+    /// Text of the running or last statement, truncated to
+    /// `track_activity_query_size` bytes
+    pub query: String,
+    /// Counters of the finished statements of the session
+    pub statistics: SessionStatistics,
+}
+
+/// Statistics of the runs of a statement by a user in a database, a row of
+/// `pg_stat_statements`
+#[derive(Debug, Clone, PartialEq)]
+pub struct StatementStatistics {
+    pub usename: Option<String>,
+    pub datname: Option<String>,
+    /// Identifier of the statement, see `sql::query_id`
+    pub query_id: i64,
+    /// Normalized text of the statement, with constants replaced by `$n`
+    pub query: String,
+    /// Runs of the statement that succeeded
+    pub calls: u64,
+    /// Rows sent in query results
+    pub rows: u64,
+    /// Execution times in milliseconds
+    pub total_exec_time: f64,
+    pub min_exec_time: f64,
+    pub max_exec_time: f64,
+}
+
+type StatementKey = (Option<String>, Option<String>, i64);
+
+/// Registry of the activity of all sessions of a server
+///
+/// Sessions are keyed by client address. The handlers record the lifecycle
+/// of every statement, and `pg_catalog.pg_stat_activity` lists the sessions
+/// when the registry is attached to the `SessionConfig` as an extension.
+#[derive(Debug)]
+pub struct ActivityRegistry {
+    next_pid: AtomicI32,
+    track_activity_query_size: usize,
+    sessions: RwLock<HashMap<SocketAddr, SessionActivity>>,
+    /// Statistics of statements by user, database and query id
+    statements: RwLock<HashMap<StatementKey, StatementStatistics>>,
+    /// Time the statistics were last reset
+    stats_reset: RwLock<Option<DateTime<Utc>>>,
+}
+
+impl Default for ActivityRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ActivityRegistry {
+    pub fn new() -> Self {
+        ActivityRegistry {
+            next_pid: AtomicI32::new(1),
+            track_activity_query_size: DEFAULT_TRACK_ACTIVITY_QUERY_SIZE,
+            sessions: RwLock::new(HashMap::new()),
+            statements: RwLock::new(HashMap::new()),
+            stats_reset: RwLock::new(None),
+        }
+    }
+
+    /// Bytes of the statement text kept for `pg_stat_activity.query`
+    pub fn with_track_activity_query_size(mut self, size: usize) -> Self {
+        self.track_activity_query_size = size;
+        self
+    }
+
+    pub fn track_activity_query_size(&self) -> usize {
+        self.track_activity_query_size
+    }
+
+    /// Record a new connection
+    pub fn start_session(&self, client_addr: SocketAddr) {
+        self.start_proxied_session(client_addr, client_addr);
+    }
+
+    /// Record a new connection from `peer` a proxy forwards for the client
+    /// at `client_addr`, read from its PROXY protocol header
+    pub fn start_proxied_session(&self, peer: SocketAddr, client_addr: SocketAddr) {
+        let mut sessions = self.sessions.write().unwrap_or_else(|e| e.into_inner());
+        sessions.insert(peer, self.new_session(client_addr));
+    }
+
+    /// Address of the client of the connection from `peer`, the client a
+    /// proxy forwards for proxied sessions
+    pub fn client_addr(&self, peer: &SocketAddr) -> SocketAddr {
+        self.sessions
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(peer)
+            .map_or(*peer, |session| session.client_addr)
+    }
+
+    /// Forget a closed connection
+    pub fn end_session(&self, client_addr: &SocketAddr) {
+        self.sessions
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(client_addr);
+    }
+
+    /// Activity of all sessions, ordered by pid
+    pub fn sessions(&self) -> Vec<SessionActivity> {
+        let mut sessions = self
+            .sessions
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .values()
+            .cloned()
+            .collect::<Vec<_>>();
+        sessions.sort_by_key(|session| session.pid);
+        sessions
+    }
+
+    /// Activity of the session of `client_addr`
+    pub fn session(&self, client_addr: &SocketAddr) -> Option<SessionActivity> {
+        self.sessions
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(client_addr)
+            .cloned()
+    }
+
+    /// Identifier of the running or last statement of the session of
+    /// `client_addr`
+    pub fn query_id(&self, client_addr: &SocketAddr) -> Option<i64> {
+        self.sessions
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(client_addr)
+            .and_then(|session| session.query_id)
+    }
+
+    /// Record that the session of `client_addr` waits for `event`, `None`
+    /// once it runs again
+    pub(crate) fn set_wait_event(
+        &self,
+        client_addr: &SocketAddr,
+        event: Option<(&'static str, &'static str)>,
+    ) {
+        self.update(client_addr, |session| session.wait_event = event);
+    }
+
+    /// Zero the statistics of all sessions, like `pg_stat_reset()`
+    pub fn reset_statistics(&self) -> DateTime<Utc> {
+        let now = Utc::now();
+        let mut sessions = self.sessions.write().unwrap_or_else(|e| e.into_inner());
+        for session in sessions.values_mut() {
+            session.statistics = SessionStatistics::default();
+        }
+        *self.stats_reset.write().unwrap_or_else(|e| e.into_inner()) = Some(now);
+        now
+    }
+
+    /// Statistics of the statements run since the last reset, ordered by
+    /// user, database and query id
+    pub fn statements(&self) -> Vec<StatementStatistics> {
+        let mut statements = self
+            .statements
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .values()
+            .cloned()
+            .collect::<Vec<_>>();
+        statements.sort_by(|a, b| {
+            (&a.usename, &a.datname, a.query_id).cmp(&(&b.usename, &b.datname, b.query_id))
+        });
+        statements
+    }
+
+    /// Forget the statistics of statements, like `pg_stat_statements_reset()`
+    pub fn reset_statements(&self) {
+        self.statements
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .clear();
+    }
+
+    fn record_statement(&self, key: StatementKey, query: String, exec_time: f64, rows: u64) {
+        let mut statements = self.statements.write().unwrap_or_else(|e| e.into_inner());
+        let statistics = statements
+            .entry(key.clone())
+            .or_insert_with(|| StatementStatistics {
+                usename: key.0,
+                datname: key.1,
+                query_id: key.2,
+                query,
+                calls: 0,
+                rows: 0,
+                total_exec_time: 0.0,
+                min_exec_time: exec_time,
+                max_exec_time: exec_time,
+            });
+        statistics.calls += 1;
+        statistics.rows += rows;
+        statistics.total_exec_time += exec_time;
+        statistics.min_exec_time = statistics.min_exec_time.min(exec_time);
+        statistics.max_exec_time = statistics.max_exec_time.max(exec_time);
+    }
+
+    /// Time the statistics were last reset, `None` if they never were
+    pub fn stats_reset(&self) -> Option<DateTime<Utc>> {
+        *self.stats_reset.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn new_session(&self, client_addr: SocketAddr) -> SessionActivity {
+        SessionActivity {
+            pid: self.next_pid.fetch_add(1, Ordering::Relaxed),
+            client_addr,
+            usename: None,
+            datname: None,
+            application_name: None,
+            backend_start: Utc::now(),
+            xact_start: None,
+            query_start: None,
+            state_change: None,
+            state: None,
+            wait_event: None,
+            query_id: None,
+            query: String::new(),
+            statistics: SessionStatistics::default(),
+        }
+    }
+
+    fn update(&self, client_addr: &SocketAddr, f: impl FnOnce(&mut SessionActivity)) {
+        let mut sessions = self.sessions.write().unwrap_or_else(|e| e.into_inner());
+        if let Some(session) = sessions.get_mut(client_addr) {
+            f(session);
+        }
+    }
+
+    /// Mark the session of the client active running `query`, until the
+    /// returned guard is dropped
+    pub(crate) fn statement_started<C>(
+        self: &Arc<Self>,
+        client: &C,
+        query: &str,
+    ) -> StatementActivity
+    where
+        C: ClientInfo,
+    {
+        let client_addr = client.socket_addr();
+        let now = Utc::now();
+        let transaction_status = client.transaction_status();
+        let metadata = client.metadata();
+
+        let mut sessions = self.sessions.write().unwrap_or_else(|e| e.into_inner());
+        // sessions of handlers not served by `serve` are registered lazily
+        let session = sessions
+            .entry(client_addr)
+            .or_insert_with(|| self.new_session(client_addr));
+        session.usename = metadata.get(METADATA_USER).cloned();
+        session.datname = metadata.get(METADATA_DATABASE).cloned();
+        session.application_name = metadata.get("application_name").cloned();
+        if transaction_status == TransactionStatus::Idle || session.xact_start.is_none() {
+            session.xact_start = Some(now);
+        }
+        session.query_start = Some(now);
+        session.state_change = Some(now);
+        session.state = Some(SessionState::Active);
+        session.wait_event = None;
+        session.query_id = None;
+        session.query = truncate(query, self.track_activity_query_size).to_string();
+
+        StatementActivity {
+            registry: self.clone(),
+            client_addr,
+            transaction_status,
+            succeeded: false,
+            kind: StatementKind::Other,
+            statement: None,
+            started: Instant::now(),
+            rows_returned: 0,
+            bytes_sent: 0,
+        }
+    }
+}
+
+/// Cut `query` to at most `size - 1` bytes on a char boundary, like postgres
+fn truncate(query: &str, size: usize) -> &str {
+    let mut end = query.len().min(size.saturating_sub(1));
+    while !query.is_char_boundary(end) {
+        end -= 1;
+    }
+    &query[..end]
+}
+
+/// Held while a statement runs, the session turns idle when it is dropped
+#[derive(Debug)]
+pub(crate) struct StatementActivity {
+    registry: Arc<ActivityRegistry>,
+    client_addr: SocketAddr,
+    // transaction status after the statement
+    transaction_status: TransactionStatus,
+    // whether the statement produced a response, failed statements abort
+    // the transaction block they run in
+    succeeded: bool,
+    kind: StatementKind,
+    // query id and normalized text of the statement once it is parsed
+    statement: Option<(i64, String)>,
+    started: Instant,
+    // result rows sent so far, added to the session statistics when the
+    // statement ends
+    rows_returned: u64,
+    bytes_sent: u64,
+}
+
+impl StatementActivity {
+    /// Record that the statement waits for an event, `None` once it runs
+    /// again
+    pub(crate) fn wait_event(&self, event: Option<(&'static str, &'static str)>) {
+        self.registry.update(&self.client_addr, |session| {
+            session.wait_event = event;
+        });
+    }
+
+    /// Record the identifier and kind of the statement once it is parsed
+    pub(crate) fn parsed(&mut self, statement: &Statement, query_id: i64) {
+        self.kind = StatementKind::of(statement);
+        self.statement = Some((query_id, normalize(statement)));
+        self.registry.update(&self.client_addr, |session| {
+            session.query_id = Some(query_id);
+        });
+    }
+
+    /// Record the response of the statement, tracking transaction blocks it
+    /// starts or ends
+    pub(crate) fn observe(&mut self, response: &Response) {
+        self.succeeded = true;
+        match response {
+            Response::TransactionStart(_) => {
+                self.transaction_status = TransactionStatus::Transaction
+            }
+            Response::TransactionEnd(_) => self.transaction_status = TransactionStatus::Idle,
+            _ => {}
+        }
+    }
+
+    /// Keep the statement active until all rows of the response are sent
+    pub(crate) fn hold_for<'a>(mut self, resp: QueryResponse<'a>) -> QueryResponse<'a> {
+        self.succeeded = true;
+        let fields = resp.row_schema();
+        let data_rows = resp.data_rows().map(move |row| {
+            // the whole guard moves into the stream, not only its counters
+            let activity = &mut self;
+            if let Ok(row) = &row {
+                activity.rows_returned += 1;
+                // message type, length and field count precede the fields
+                activity.bytes_sent += row.data.len() as u64 + 7;
+            }
+            row
+        });
+        QueryResponse::new(fields, data_rows)
+    }
+}
+
+impl Drop for StatementActivity {
+    fn drop(&mut self) {
+        let now = Utc::now();
+        let transaction_status = match self.transaction_status {
+            TransactionStatus::Transaction if !self.succeeded => TransactionStatus::Error,
+            status => status,
+        };
+        let mut key = None;
+        self.registry.update(&self.client_addr, |session| {
+            key = Some((session.usename.clone(), session.datname.clone()));
+            session.state = Some(match transaction_status {
+                TransactionStatus::Idle => SessionState::Idle,
+                TransactionStatus::Transaction => SessionState::IdleInTransaction,
+                TransactionStatus::Error => SessionState::IdleInTransactionAborted,
+            });
+            if transaction_status == TransactionStatus::Idle {
+                session.xact_start = None;
+            }
+            session.state_change = Some(now);
+            session.wait_event = Some(("Client", "ClientRead"));
+
+            let statistics = &mut session.statistics;
+            statistics.count_statement(self.kind);
+            statistics.rows_returned += self.rows_returned;
+            statistics.bytes_sent += self.bytes_sent;
+            if !self.succeeded {
+                statistics.errors += 1;
+            }
+        });
+
+        // like postgres, only statements that complete are counted
+        if let (true, Some((usename, datname)), Some((query_id, query))) =
+            (self.succeeded, key, self.statement.take())
+        {
+            let exec_time = self.started.elapsed().as_secs_f64() * 1000.0;
+            self.registry.record_statement(
+                (usename, datname, query_id),
+                query,
+                exec_time,
+                self.rows_returned,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_truncate() {
+        assert_eq!(truncate("SELECT 1", 1024), "SELECT 1");
+        assert_eq!(truncate("SELECT 1", 7), "SELECT");
+        // never splits a character
+        assert_eq!(truncate("SELECT 'é'", 10), "SELECT '");
+        assert_eq!(truncate("SELECT 1", 0), "");
+    }
+
+    #[test]
+    fn test_proxied_session() {
+        let registry = ActivityRegistry::new();
+        let proxy: SocketAddr = "10.0.0.2:40000".parse().unwrap();
+        let client: SocketAddr = "192.0.2.1:56324".parse().unwrap();
+        registry.start_proxied_session(proxy, client);
+        assert_eq!(registry.session(&proxy).unwrap().client_addr, client);
+        assert_eq!(registry.client_addr(&proxy), client);
+
+        registry.end_session(&proxy);
+        assert_eq!(registry.client_addr(&proxy), proxy);
+    }
+}
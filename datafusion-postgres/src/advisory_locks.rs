@@ -0,0 +1,958 @@
+//! Advisory locks of `pg_advisory_lock` and its family.
+//!
+//! Migration tools like Flyway, Rails and Alembic take an advisory lock
+//! before migrating, so concurrent deployments migrate one at a time:
+//!
+//! ```sql
+//! SELECT pg_advisory_lock(72707369);
+//! -- migrate
+//! SELECT pg_advisory_unlock(72707369);
+//! ```
+//!
+//! Locks are kept in the lock table of the server, by database and key,
+//! either a bigint or a pair of ints. Like in postgres, a session may take a
+//! lock many times and holds it until it unlocks it as many times, shared
+//! locks only conflict with exclusive ones, and the locks of the `_xact_`
+//! functions are held until the transaction block ends. All locks of a
+//! session are released when it disconnects. `pg_locks` lists the held and
+//! awaited locks.
+//!
+//! The functions learn the calling session from the handlers, which bind it
+//! to their calls before running a statement. The functions waiting for
+//! locks are bound as async functions, so a waiting session doesn't hold a
+//! worker of the runtime and stops waiting when its statement is dropped.
+//! Like in postgres, unlocking a lock the session doesn't hold is answered
+//! with a `WARNING`, which the handlers send along the result.
+
+use std::any::Any;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::net::SocketAddr;
+use std::pin::pin;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use datafusion::arrow::array::{new_null_array, Array, ArrayRef, AsArray, BooleanArray};
+use datafusion::arrow::datatypes::{DataType, Int64Type};
+use datafusion::common::config::ConfigOptions;
+use datafusion::common::tree_node::{Transformed, TreeNode, TreeNodeRecursion};
+use datafusion::common::ScalarValue;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::logical_expr::async_udf::{AsyncScalarUDF, AsyncScalarUDFImpl};
+use datafusion::logical_expr::expr::ScalarFunction;
+use datafusion::logical_expr::{
+    ColumnarValue, Expr, LogicalPlan, ScalarFunctionArgs, ScalarUDF, ScalarUDFImpl, Signature,
+    TypeSignature, Volatility,
+};
+use pgwire::error::{ErrorInfo, PgWireError};
+use tokio::sync::Notify;
+use tokio::time::Instant;
+
+use crate::activity::ActivityRegistry;
+
+/// Wait event of sessions waiting for an advisory lock
+const ADVISORY_LOCK_WAIT: (&str, &str) = ("Lock", "advisory");
+
+/// Key of an advisory lock, a bigint or a pair of ints
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub(crate) enum LockKey {
+    BigInt(i64),
+    Pair(i32, i32),
+}
+
+impl LockKey {
+    /// The `classid`, `objid` and `objsubid` of the key in `pg_locks`
+    pub(crate) fn ids(&self) -> (u32, u32, i16) {
+        match *self {
+            LockKey::BigInt(key) => ((key as u64 >> 32) as u32, key as u32, 1),
+            LockKey::Pair(first, second) => (first as u32, second as u32, 2),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum LockMode {
+    Exclusive,
+    Shared,
+}
+
+impl LockMode {
+    /// The name of the mode in `pg_locks`
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            LockMode::Exclusive => "ExclusiveLock",
+            LockMode::Shared => "ShareLock",
+        }
+    }
+
+    fn conflicts(self, other: LockMode) -> bool {
+        self == LockMode::Exclusive || other == LockMode::Exclusive
+    }
+}
+
+/// Whether a lock is held until it is unlocked or until the transaction
+/// block ends
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LockScope {
+    Session,
+    Transaction,
+}
+
+/// An advisory lock by database and key
+type LockTag = (String, LockKey);
+
+/// The holds of a session on a lock in a mode, counted as locks are
+/// reentrant
+#[derive(Debug)]
+struct Hold {
+    pid: i32,
+    mode: LockMode,
+    session: usize,
+    transaction: usize,
+}
+
+#[derive(Debug)]
+struct Waiter {
+    pid: i32,
+    tag: LockTag,
+    mode: LockMode,
+    since: DateTime<Utc>,
+}
+
+#[derive(Debug, Default)]
+struct LockTable {
+    holds: BTreeMap<LockTag, Vec<Hold>>,
+    waiters: Vec<Waiter>,
+    /// Warnings of the statements of sessions, by pid, until the handlers
+    /// send them
+    warnings: HashMap<i32, Vec<String>>,
+}
+
+impl LockTable {
+    /// The sessions holding `tag` in a mode conflicting with `mode`, other
+    /// than `pid`
+    fn blockers(&self, pid: i32, tag: &LockTag, mode: LockMode) -> Vec<i32> {
+        self.holds
+            .get(tag)
+            .into_iter()
+            .flatten()
+            .filter(|hold| hold.pid != pid && hold.mode.conflicts(mode))
+            .map(|hold| hold.pid)
+            .collect()
+    }
+
+    /// Whether `pid` waiting for `tag` waits for itself through the sessions
+    /// blocking it
+    fn deadlocks(&self, pid: i32, tag: &LockTag, mode: LockMode) -> bool {
+        let mut seen = HashSet::new();
+        let mut blockers = self.blockers(pid, tag, mode);
+        while let Some(blocker) = blockers.pop() {
+            if blocker == pid {
+                return true;
+            }
+            if !seen.insert(blocker) {
+                continue;
+            }
+            for waiter in self.waiters.iter().filter(|waiter| waiter.pid == blocker) {
+                blockers.extend(self.blockers(blocker, &waiter.tag, waiter.mode));
+            }
+        }
+        false
+    }
+
+    fn grant(&mut self, pid: i32, tag: LockTag, mode: LockMode, scope: LockScope) {
+        let holds = self.holds.entry(tag).or_default();
+        let hold = match holds
+            .iter_mut()
+            .position(|hold| hold.pid == pid && hold.mode == mode)
+        {
+            Some(i) => &mut holds[i],
+            None => {
+                holds.push(Hold {
+                    pid,
+                    mode,
+                    session: 0,
+                    transaction: 0,
+                });
+                holds.last_mut().expect("pushed hold")
+            }
+        };
+        match scope {
+            LockScope::Session => hold.session += 1,
+            LockScope::Transaction => hold.transaction += 1,
+        }
+    }
+
+    /// Release the holds of `pid` `release` returns true for, forgetting
+    /// the holds and locks left with no count
+    fn release(&mut self, pid: i32, mut release: impl FnMut(&LockTag, &mut Hold) -> bool) -> bool {
+        let mut released = false;
+        for (tag, holds) in self.holds.iter_mut() {
+            for hold in holds.iter_mut().filter(|hold| hold.pid == pid) {
+                released |= release(tag, hold);
+            }
+            holds.retain(|hold| hold.session + hold.transaction > 0);
+        }
+        self.holds.retain(|_, holds| !holds.is_empty());
+        released
+    }
+}
+
+/// A held or awaited lock, a row of `pg_locks`
+#[derive(Debug, Clone)]
+pub(crate) struct AdvisoryLock {
+    pub(crate) database: String,
+    pub(crate) key: LockKey,
+    pub(crate) pid: i32,
+    pub(crate) mode: LockMode,
+    pub(crate) granted: bool,
+    pub(crate) wait_start: Option<DateTime<Utc>>,
+}
+
+/// The advisory lock table of a server
+///
+/// Attached to the `SessionConfig` as an extension for `pg_locks` to list
+/// the locks.
+#[derive(Debug, Default)]
+pub(crate) struct AdvisoryLocks {
+    table: Mutex<LockTable>,
+    released: Notify,
+}
+
+impl AdvisoryLocks {
+    fn table(&self) -> MutexGuard<'_, LockTable> {
+        self.table.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Take the lock `key` for `session` if no other session holds it in a
+    /// conflicting mode, false if it isn't granted
+    fn try_lock(
+        &self,
+        session: &LockSession,
+        key: LockKey,
+        mode: LockMode,
+        scope: LockScope,
+    ) -> bool {
+        let tag = (session.database.clone(), key);
+        let mut table = self.table();
+        let granted = table.blockers(session.pid, &tag, mode).is_empty();
+        if granted {
+            table.grant(session.pid, tag, mode, scope);
+        }
+        granted
+    }
+
+    /// Take the lock `key` for `session`, waiting for the sessions holding
+    /// it
+    ///
+    /// The wait fails at the statement timeout of the session, and ends when
+    /// the returned future is dropped with the statement.
+    async fn lock(
+        &self,
+        session: &LockSession,
+        key: LockKey,
+        mode: LockMode,
+        scope: LockScope,
+    ) -> Result<()> {
+        let pid = session.pid;
+        let tag = (session.database.clone(), key);
+        let deadline = session.timeout.map(|timeout| Instant::now() + timeout);
+        let mut waiting = None;
+        loop {
+            // registered before looking at the table, so releases in
+            // between aren't missed
+            let mut released = pin!(self.released.notified());
+            released.as_mut().enable();
+            {
+                let mut table = self.table();
+                if table.blockers(pid, &tag, mode).is_empty() {
+                    table.grant(pid, tag, mode, scope);
+                    return Ok(());
+                }
+                if waiting.is_none() {
+                    if table.deadlocks(pid, &tag, mode) {
+                        return Err(lock_error(
+                            "40P01", // deadlock_detected
+                            "deadlock detected".to_string(),
+                        ));
+                    }
+                    table.waiters.push(Waiter {
+                        pid,
+                        tag: tag.clone(),
+                        mode,
+                        since: Utc::now(),
+                    });
+                    session.wait_event(Some(ADVISORY_LOCK_WAIT));
+                    waiting = Some(Waiting {
+                        locks: self,
+                        session,
+                    });
+                }
+            }
+            match deadline {
+                Some(deadline) => {
+                    if tokio::time::timeout_at(deadline, released).await.is_err() {
+                        return Err(lock_error(
+                            "57014", // query_canceled
+                            "canceling statement due to statement timeout".to_string(),
+                        ));
+                    }
+                }
+                None => released.await,
+            }
+        }
+    }
+
+    /// Release one session level hold of `pid` on the lock, false if it
+    /// holds none
+    fn unlock(&self, pid: i32, database: &str, key: LockKey, mode: LockMode) -> bool {
+        let mut unlocked = false;
+        let released = self.table().release(pid, |tag, hold| {
+            let release = !unlocked
+                && tag.0 == database
+                && tag.1 == key
+                && hold.mode == mode
+                && hold.session > 0;
+            if release {
+                hold.session -= 1;
+                unlocked = true;
+            }
+            release
+        });
+        self.released.notify_waiters();
+        released
+    }
+
+    /// Release the session level locks of `pid`, like
+    /// `pg_advisory_unlock_all()`
+    fn unlock_all(&self, pid: i32) {
+        self.table()
+            .release(pid, |_, hold| std::mem::take(&mut hold.session) > 0);
+        self.released.notify_waiters();
+    }
+
+    /// Release the transaction level locks of `pid` when its transaction
+    /// block ends
+    pub(crate) fn end_transaction(&self, pid: i32) {
+        let released = self
+            .table()
+            .release(pid, |_, hold| std::mem::take(&mut hold.transaction) > 0);
+        if released {
+            self.released.notify_waiters();
+        }
+    }
+
+    /// Release all locks of the session of a closed connection
+    pub(crate) fn end_session(&self, pid: i32) {
+        let mut table = self.table();
+        table.release(pid, |_, hold| {
+            hold.session = 0;
+            hold.transaction = 0;
+            true
+        });
+        table.warnings.remove(&pid);
+        drop(table);
+        self.released.notify_waiters();
+    }
+
+    fn warn(&self, pid: i32, message: String) {
+        self.table().warnings.entry(pid).or_default().push(message);
+    }
+
+    /// The warnings of the statement of `pid`, which are forgotten
+    pub(crate) fn take_warnings(&self, pid: i32) -> Vec<String> {
+        self.table().warnings.remove(&pid).unwrap_or_default()
+    }
+
+    /// The held locks, then the awaited ones
+    pub(crate) fn locks(&self) -> Vec<AdvisoryLock> {
+        let table = self.table();
+        let held = table.holds.iter().flat_map(|((database, key), holds)| {
+            holds.iter().map(|hold| AdvisoryLock {
+                database: database.clone(),
+                key: *key,
+                pid: hold.pid,
+                mode: hold.mode,
+                granted: true,
+                wait_start: None,
+            })
+        });
+        let awaited = table.waiters.iter().map(|waiter| AdvisoryLock {
+            database: waiter.tag.0.clone(),
+            key: waiter.tag.1,
+            pid: waiter.pid,
+            mode: waiter.mode,
+            granted: false,
+            wait_start: Some(waiter.since),
+        });
+        held.chain(awaited).collect()
+    }
+}
+
+/// A session waiting for a lock, which leaves the waiters when dropped
+struct Waiting<'a> {
+    locks: &'a AdvisoryLocks,
+    session: &'a LockSession,
+}
+
+impl Drop for Waiting<'_> {
+    fn drop(&mut self) {
+        let pid = self.session.pid;
+        self.locks
+            .table()
+            .waiters
+            .retain(|waiter| waiter.pid != pid);
+        self.session.wait_event(None);
+    }
+}
+
+fn lock_error(code: &str, message: String) -> DataFusionError {
+    DataFusionError::External(Box::new(PgWireError::UserError(Box::new(ErrorInfo::new(
+        "ERROR".to_string(),
+        code.to_string(),
+        message,
+    )))))
+}
+
+/// The session calling the advisory lock functions of a statement
+#[derive(Debug, Clone)]
+pub(crate) struct LockSession {
+    pub(crate) locks: Arc<AdvisoryLocks>,
+    pub(crate) pid: i32,
+    /// Database the locks of the session are taken in
+    pub(crate) database: String,
+    /// Whether the statement runs in a transaction block, outside of one
+    /// transaction level locks are released as soon as they are taken
+    pub(crate) in_transaction: bool,
+    /// Statement timeout of the session, bounding the wait for locks
+    pub(crate) timeout: Option<Duration>,
+    pub(crate) activity: Arc<ActivityRegistry>,
+    pub(crate) client_addr: SocketAddr,
+}
+
+impl LockSession {
+    fn wait_event(&self, event: Option<(&'static str, &'static str)>) {
+        self.activity.set_wait_event(&self.client_addr, event);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LockAction {
+    Lock { scope: LockScope, wait: bool },
+    Unlock,
+    UnlockAll,
+}
+
+/// The functions, by name, action and mode
+const ADVISORY_LOCK_FUNCTIONS: &[(&str, LockAction, LockMode)] = &[
+    (
+        "pg_advisory_lock",
+        LockAction::Lock {
+            scope: LockScope::Session,
+            wait: true,
+        },
+        LockMode::Exclusive,
+    ),
+    (
+        "pg_advisory_lock_shared",
+        LockAction::Lock {
+            scope: LockScope::Session,
+            wait: true,
+        },
+        LockMode::Shared,
+    ),
+    (
+        "pg_try_advisory_lock",
+        LockAction::Lock {
+            scope: LockScope::Session,
+            wait: false,
+        },
+        LockMode::Exclusive,
+    ),
+    (
+        "pg_try_advisory_lock_shared",
+        LockAction::Lock {
+            scope: LockScope::Session,
+            wait: false,
+        },
+        LockMode::Shared,
+    ),
+    (
+        "pg_advisory_xact_lock",
+        LockAction::Lock {
+            scope: LockScope::Transaction,
+            wait: true,
+        },
+        LockMode::Exclusive,
+    ),
+    (
+        "pg_advisory_xact_lock_shared",
+        LockAction::Lock {
+            scope: LockScope::Transaction,
+            wait: true,
+        },
+        LockMode::Shared,
+    ),
+    (
+        "pg_try_advisory_xact_lock",
+        LockAction::Lock {
+            scope: LockScope::Transaction,
+            wait: false,
+        },
+        LockMode::Exclusive,
+    ),
+    (
+        "pg_try_advisory_xact_lock_shared",
+        LockAction::Lock {
+            scope: LockScope::Transaction,
+            wait: false,
+        },
+        LockMode::Shared,
+    ),
+    (
+        "pg_advisory_unlock",
+        LockAction::Unlock,
+        LockMode::Exclusive,
+    ),
+    (
+        "pg_advisory_unlock_shared",
+        LockAction::Unlock,
+        LockMode::Shared,
+    ),
+    (
+        "pg_advisory_unlock_all",
+        LockAction::UnlockAll,
+        LockMode::Exclusive,
+    ),
+];
+
+/// An advisory lock function, bound to the calling session before it runs
+#[derive(Debug)]
+pub(crate) struct AdvisoryLockUDF {
+    name: &'static str,
+    action: LockAction,
+    mode: LockMode,
+    signature: Signature,
+    session: Option<LockSession>,
+}
+
+impl AdvisoryLockUDF {
+    fn new(name: &'static str, action: LockAction, mode: LockMode) -> Self {
+        let signature = match action {
+            LockAction::UnlockAll => Signature::nullary(Volatility::Volatile),
+            _ => Signature::one_of(
+                vec![
+                    TypeSignature::Uniform(1, vec![DataType::Int64]),
+                    TypeSignature::Uniform(2, vec![DataType::Int64]),
+                ],
+                Volatility::Volatile,
+            ),
+        };
+        AdvisoryLockUDF {
+            name,
+            action,
+            mode,
+            signature,
+            session: None,
+        }
+    }
+
+    /// The unbound function of `name`
+    fn unbound(name: &str) -> Option<Self> {
+        ADVISORY_LOCK_FUNCTIONS
+            .iter()
+            .find(|(function, ..)| *function == name)
+            .map(|(name, action, mode)| AdvisoryLockUDF::new(name, *action, *mode))
+    }
+
+    /// The function bound to `session`, async if it waits for locks
+    fn bound(&self, session: LockSession) -> ScalarUDF {
+        let udf = AdvisoryLockUDF {
+            session: Some(session),
+            ..AdvisoryLockUDF::new(self.name, self.action, self.mode)
+        };
+        match udf.action {
+            LockAction::Lock { wait: true, .. } => {
+                AsyncScalarUDF::new(Arc::new(udf)).into_scalar_udf()
+            }
+            _ => ScalarUDF::new_from_impl(udf),
+        }
+    }
+
+    fn session(&self) -> Result<&LockSession> {
+        self.session.as_ref().ok_or_else(|| {
+            DataFusionError::NotImplemented(format!(
+                "{} can only be called by sessions of the server",
+                self.name
+            ))
+        })
+    }
+
+    /// Run the function for the key of a row, unless it waits for locks
+    fn call(&self, session: &LockSession, key: LockKey) -> Result<bool> {
+        let locks = &session.locks;
+        match self.action {
+            LockAction::Lock { scope, wait: false } => {
+                let locked = locks.try_lock(session, key, self.mode, scope);
+                if locked {
+                    self.locked(session, scope);
+                }
+                Ok(locked)
+            }
+            LockAction::Lock { wait: true, .. } => Err(DataFusionError::Internal(format!(
+                "{} waits for locks and can only be called as an async function",
+                self.name
+            ))),
+            LockAction::Unlock => {
+                let unlocked = locks.unlock(session.pid, &session.database, key, self.mode);
+                if !unlocked {
+                    locks.warn(
+                        session.pid,
+                        format!("you don't own a lock of type {}", self.mode.name()),
+                    );
+                }
+                Ok(unlocked)
+            }
+            LockAction::UnlockAll => {
+                locks.unlock_all(session.pid);
+                Ok(true)
+            }
+        }
+    }
+
+    fn locked(&self, session: &LockSession, scope: LockScope) {
+        // the implicit transaction of the statement ends with it
+        if scope == LockScope::Transaction && !session.in_transaction {
+            session.locks.end_transaction(session.pid);
+        }
+    }
+
+    /// The keys of the rows of the arguments, of one row if all arguments
+    /// are scalars, `None` for rows with a null key
+    fn keys(&self, args: &ScalarFunctionArgs) -> Result<Vec<Option<LockKey>>> {
+        let rows = match scalar_args(args) {
+            true => 1,
+            false => args.number_rows,
+        };
+        let values = args
+            .args
+            .iter()
+            .map(|arg| arg.to_array(rows))
+            .collect::<Result<Vec<_>>>()?;
+        (0..rows)
+            .map(|i| match self.action {
+                LockAction::UnlockAll => Ok(Some(LockKey::BigInt(0))),
+                _ => lock_key(&values, i),
+            })
+            .collect()
+    }
+
+    /// The value of the results of the rows, a scalar if all arguments are
+    fn output(
+        &self,
+        args: &ScalarFunctionArgs,
+        results: Vec<Option<bool>>,
+    ) -> Result<ColumnarValue> {
+        let array: ArrayRef = match self.return_type(&[])? {
+            DataType::Boolean => Arc::new(BooleanArray::from(results)),
+            data_type => new_null_array(&data_type, results.len()),
+        };
+        match scalar_args(args) {
+            true => Ok(ColumnarValue::Scalar(ScalarValue::try_from_array(
+                &array, 0,
+            )?)),
+            false => Ok(ColumnarValue::Array(array)),
+        }
+    }
+}
+
+fn scalar_args(args: &ScalarFunctionArgs) -> bool {
+    args.args
+        .iter()
+        .all(|arg| matches!(arg, ColumnarValue::Scalar(_)))
+}
+
+/// The key of row `i` of the arguments, `None` if one is null
+fn lock_key(args: &[ArrayRef], i: usize) -> Result<Option<LockKey>> {
+    let mut values = Vec::with_capacity(args.len());
+    for arg in args {
+        let arg = arg.as_primitive::<Int64Type>();
+        if arg.is_null(i) {
+            return Ok(None);
+        }
+        values.push(arg.value(i));
+    }
+    match values[..] {
+        [key] => Ok(Some(LockKey::BigInt(key))),
+        [first, second] => {
+            let int = |value: i64| {
+                i32::try_from(value).map_err(|_| {
+                    lock_error(
+                        "22003", // numeric_value_out_of_range
+                        "integer out of range".to_string(),
+                    )
+                })
+            };
+            Ok(Some(LockKey::Pair(int(first)?, int(second)?)))
+        }
+        _ => Err(DataFusionError::Internal(
+            "advisory lock key of more than two values".to_string(),
+        )),
+    }
+}
+
+impl ScalarUDFImpl for AdvisoryLockUDF {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn signature(&self) -> &Signature {
+        &self.signature
+    }
+
+    fn return_type(&self, _arg_types: &[DataType]) -> Result<DataType> {
+        Ok(match self.action {
+            LockAction::Lock { wait: true, .. } | LockAction::UnlockAll => DataType::Null,
+            _ => DataType::Boolean,
+        })
+    }
+
+    fn invoke_with_args(&self, args: ScalarFunctionArgs) -> Result<ColumnarValue> {
+        let session = self.session()?;
+        let results = self
+            .keys(&args)?
+            .into_iter()
+            .map(|key| key.map(|key| self.call(session, key)).transpose())
+            .collect::<Result<Vec<_>>>()?;
+        self.output(&args, results)
+    }
+}
+
+#[async_trait]
+impl AsyncScalarUDFImpl for AdvisoryLockUDF {
+    async fn invoke_async_with_args(
+        &self,
+        args: ScalarFunctionArgs,
+        _options: &ConfigOptions,
+    ) -> Result<ArrayRef> {
+        let session = self.session()?;
+        let LockAction::Lock { scope, wait: true } = self.action else {
+            return Err(DataFusionError::Internal(format!(
+                "{} doesn't wait for locks",
+                self.name
+            )));
+        };
+        let mut results = Vec::new();
+        for key in self.keys(&args)? {
+            if let Some(key) = key {
+                session.locks.lock(session, key, self.mode, scope).await?;
+                self.locked(session, scope);
+            }
+            results.push(None);
+        }
+        self.output(&args, results)?.into_array(args.number_rows)
+    }
+}
+
+/// The advisory lock functions, unbound until a session calls them
+pub(crate) fn advisory_lock_udfs() -> Vec<ScalarUDF> {
+    ADVISORY_LOCK_FUNCTIONS
+        .iter()
+        .map(|(name, action, mode)| {
+            ScalarUDF::new_from_impl(AdvisoryLockUDF::new(name, *action, *mode))
+        })
+        .collect()
+}
+
+/// `plan` with its calls of advisory lock functions bound to `session`
+pub(crate) fn bind_advisory_locks(plan: LogicalPlan, session: &LockSession) -> Result<LogicalPlan> {
+    plan.transform_up_with_subqueries(|plan| {
+        plan.map_expressions(|expr| {
+            expr.transform_up(|expr| match expr {
+                Expr::ScalarFunction(ScalarFunction { func, args }) => {
+                    // functions of plans bound before are bound again
+                    let inner = func.inner().as_any();
+                    let unbound = match inner.downcast_ref::<AdvisoryLockUDF>() {
+                        Some(udf) => AdvisoryLockUDF::unbound(udf.name),
+                        None if inner.is::<AsyncScalarUDF>() => {
+                            AdvisoryLockUDF::unbound(func.name())
+                        }
+                        None => None,
+                    };
+                    match unbound {
+                        Some(udf) => Ok(Transformed::yes(Expr::ScalarFunction(ScalarFunction {
+                            func: Arc::new(udf.bound(session.clone())),
+                            args,
+                        }))),
+                        None => Ok(Transformed::no(Expr::ScalarFunction(ScalarFunction {
+                            func,
+                            args,
+                        }))),
+                    }
+                }
+                expr => Ok(Transformed::no(expr)),
+            })
+        })
+    })
+    .map(|transformed| transformed.data)
+}
+
+/// Whether `plan` calls a function unlocking advisory locks, which warns of
+/// locks the session doesn't hold
+pub(crate) fn calls_advisory_unlock(plan: &LogicalPlan) -> bool {
+    let mut unlocks = false;
+    let _ = plan.apply_with_subqueries(|plan| {
+        plan.apply_expressions(|expr| {
+            expr.apply(|expr| {
+                if let Expr::ScalarFunction(function) = expr {
+                    unlocks |= ADVISORY_LOCK_FUNCTIONS.iter().any(|(name, action, _)| {
+                        *action == LockAction::Unlock && *name == function.name()
+                    });
+                }
+                Ok(match unlocks {
+                    true => TreeNodeRecursion::Stop,
+                    false => TreeNodeRecursion::Continue,
+                })
+            })
+        })
+    });
+    unlocks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(locks: &Arc<AdvisoryLocks>, pid: i32) -> LockSession {
+        LockSession {
+            locks: locks.clone(),
+            pid,
+            database: "postgres".to_string(),
+            in_transaction: true,
+            timeout: Some(Duration::from_millis(50)),
+            activity: Arc::new(ActivityRegistry::new()),
+            client_addr: "127.0.0.1:5432".parse().unwrap(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_lock_table() {
+        let locks = Arc::new(AdvisoryLocks::default());
+        let (alice, bob) = (session(&locks, 1), session(&locks, 2));
+        let key = LockKey::BigInt(42);
+        let lock = |session: &LockSession, mode, scope| locks.try_lock(session, key, mode, scope);
+
+        // locks are reentrant, and held until unlocked as often
+        assert!(lock(&alice, LockMode::Exclusive, LockScope::Session));
+        assert!(lock(&alice, LockMode::Exclusive, LockScope::Session));
+        assert!(!lock(&bob, LockMode::Shared, LockScope::Session));
+        assert!(locks.unlock(1, "postgres", key, LockMode::Exclusive));
+        assert!(!lock(&bob, LockMode::Shared, LockScope::Session));
+        assert!(locks.unlock(1, "postgres", key, LockMode::Exclusive));
+        assert!(!locks.unlock(1, "postgres", key, LockMode::Exclusive));
+
+        // shared locks only conflict with exclusive ones
+        assert!(lock(&alice, LockMode::Shared, LockScope::Session));
+        assert!(lock(&bob, LockMode::Shared, LockScope::Transaction));
+        assert!(!lock(&bob, LockMode::Exclusive, LockScope::Session));
+        assert_eq!(locks.locks().len(), 2);
+        locks.end_transaction(2);
+        assert_eq!(locks.locks().len(), 1);
+
+        // waiting fails at the statement timeout
+        let Err(DataFusionError::External(error)) = locks
+            .lock(&bob, key, LockMode::Exclusive, LockScope::Session)
+            .await
+        else {
+            panic!("expected a timeout");
+        };
+        assert!(error.to_string().contains("statement timeout"));
+        assert_eq!(locks.locks().len(), 1);
+        locks.end_session(1);
+        assert!(lock(&bob, LockMode::Exclusive, LockScope::Session));
+
+        // other databases have locks of their own
+        let other = LockSession {
+            database: "other".to_string(),
+            ..session(&locks, 3)
+        };
+        assert!(lock(&other, LockMode::Exclusive, LockScope::Session));
+        assert_eq!(LockKey::BigInt(-1).ids(), (u32::MAX, u32::MAX, 1));
+        assert_eq!(LockKey::Pair(1, 2).ids(), (1, 2, 2));
+    }
+
+    #[tokio::test]
+    async fn test_deadlock() {
+        let locks = Arc::new(AdvisoryLocks::default());
+        let (alice, bob) = (session(&locks, 1), session(&locks, 2));
+        let (first, second) = (LockKey::BigInt(1), LockKey::Pair(0, 2));
+        assert!(locks.try_lock(&alice, first, LockMode::Exclusive, LockScope::Session));
+        assert!(locks.try_lock(&bob, second, LockMode::Exclusive, LockScope::Session));
+        // waits run on the worker of the current thread runtime too
+        let waiting = {
+            let locks = locks.clone();
+            let alice = LockSession {
+                timeout: None,
+                ..alice.clone()
+            };
+            tokio::spawn(async move {
+                locks
+                    .lock(&alice, second, LockMode::Exclusive, LockScope::Session)
+                    .await
+            })
+        };
+        while locks.locks().iter().all(|lock| lock.granted) {
+            tokio::task::yield_now().await;
+        }
+        let bob = LockSession {
+            timeout: None,
+            ..bob
+        };
+        let Err(DataFusionError::External(error)) = locks
+            .lock(&bob, first, LockMode::Exclusive, LockScope::Session)
+            .await
+        else {
+            panic!("expected a deadlock");
+        };
+        assert!(error.to_string().contains("deadlock detected"));
+        // alice gets the lock once bob leaves
+        locks.end_session(2);
+        waiting.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_dropped_wait() {
+        let locks = Arc::new(AdvisoryLocks::default());
+        let alice = session(&locks, 1);
+        let bob = LockSession {
+            timeout: None,
+            ..session(&locks, 2)
+        };
+        let key = LockKey::BigInt(7);
+        assert!(locks.try_lock(&alice, key, LockMode::Exclusive, LockScope::Session));
+        let waiting = {
+            let locks = locks.clone();
+            tokio::spawn(async move {
+                locks
+                    .lock(&bob, key, LockMode::Exclusive, LockScope::Session)
+                    .await
+            })
+        };
+        while locks.locks().len() < 2 {
+            tokio::task::yield_now().await;
+        }
+        // a canceled statement stops waiting
+        waiting.abort();
+        assert!(waiting.await.unwrap_err().is_cancelled());
+        assert_eq!(locks.locks().len(), 1);
+
+        locks.warn(1, "you don't own a lock of type ShareLock".to_string());
+        assert_eq!(locks.take_warnings(1).len(), 1);
+        assert!(locks.take_warnings(1).is_empty());
+    }
+}
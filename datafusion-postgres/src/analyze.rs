@@ -0,0 +1,457 @@
+//! `VACUUM`, `ANALYZE` and `CLUSTER`, the maintenance commands of postgres.
+//!
+//! Scheduled jobs and ORMs send them without knowing the server. Tables have
+//! no dead rows to vacuum nor physical order to cluster, so `VACUUM` and
+//! `CLUSTER` do nothing but say so in a notice. `ANALYZE`, alone or as an
+//! option of `VACUUM`, scans its tables and records their row count and the
+//! null count, minimum and maximum of their columns, shown by
+//! `pg_class.reltuples`, `pg_stats` and `pg_stat_user_tables`:
+//!
+//! ```sql
+//! VACUUM (VERBOSE, ANALYZE) events;
+//! ANALYZE events (user_id, created_at), sessions;
+//! ```
+
+use std::collections::BTreeMap;
+use std::sync::RwLock;
+
+use chrono::{DateTime, Utc};
+use datafusion::common::{ScalarValue, TableReference};
+use datafusion::error::{DataFusionError, Result};
+use datafusion::functions_aggregate::min_max::{MaxAccumulator, MinAccumulator};
+use datafusion::logical_expr::Accumulator;
+use datafusion::prelude::SessionContext;
+use datafusion::sql::sqlparser::ast::ObjectName;
+use datafusion::sql::sqlparser::dialect::PostgreSqlDialect;
+use datafusion::sql::sqlparser::keywords::Keyword;
+use datafusion::sql::sqlparser::parser::{Parser, ParserError};
+use datafusion::sql::sqlparser::tokenizer::Token;
+use futures::StreamExt;
+use pgwire::error::PgWireResult;
+
+use crate::errors::syntax_error;
+use crate::sql::{parse_name, parse_word};
+
+/// A maintenance command
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum MaintenanceCommand {
+    /// `VACUUM`, analyzing its tables with the `ANALYZE` option
+    Vacuum {
+        analyze: bool,
+        tables: Vec<AnalyzeTarget>,
+    },
+    /// `ANALYZE` of its tables, or of all tables without any
+    Analyze { tables: Vec<AnalyzeTarget> },
+    /// `CLUSTER`, whatever its arguments
+    Cluster,
+}
+
+impl MaintenanceCommand {
+    /// The command tag of the command
+    pub(crate) fn tag(&self) -> &'static str {
+        match self {
+            MaintenanceCommand::Vacuum { .. } => "VACUUM",
+            MaintenanceCommand::Analyze { .. } => "ANALYZE",
+            MaintenanceCommand::Cluster => "CLUSTER",
+        }
+    }
+}
+
+/// A table to analyze, and the columns to analyze of it, all if empty
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct AnalyzeTarget {
+    pub(crate) name: ObjectName,
+    pub(crate) columns: Vec<String>,
+}
+
+/// Whether the lowercase query is a maintenance command
+pub(crate) fn is_maintenance_command(query_lower: &str) -> bool {
+    matches!(
+        query_lower
+            .split_whitespace()
+            .next()
+            .map(|word| word.trim_end_matches(';')),
+        Some("vacuum" | "analyze" | "analyse" | "cluster")
+    )
+}
+
+pub(crate) fn parse_maintenance_command(sql: &str) -> PgWireResult<MaintenanceCommand> {
+    parse_command(sql).map_err(|e| syntax_error(e, sql))
+}
+
+fn parse_command(sql: &str) -> Result<MaintenanceCommand, ParserError> {
+    let dialect = PostgreSqlDialect {};
+    let mut parser = Parser::new(&dialect).try_with_sql(sql)?;
+    let command = if parse_word(&mut parser, "vacuum") {
+        let mut analyze = false;
+        if parser.consume_token(&Token::LParen) {
+            for (option, value) in parse_option_list(&mut parser)? {
+                if option == "analyze" || option == "analyse" {
+                    analyze = value;
+                }
+            }
+        } else {
+            // the options of postgres before 9.0, in this order
+            let _ = parser.parse_keyword(Keyword::FULL);
+            let _ = parser.parse_keyword(Keyword::FREEZE);
+            let _ = parser.parse_keyword(Keyword::VERBOSE);
+            analyze = parser.parse_keyword(Keyword::ANALYZE) || parse_word(&mut parser, "analyse");
+        }
+        MaintenanceCommand::Vacuum {
+            analyze,
+            tables: parse_targets(&mut parser)?,
+        }
+    } else if parser.parse_keyword(Keyword::ANALYZE) || parse_word(&mut parser, "analyse") {
+        if parser.consume_token(&Token::LParen) {
+            parse_option_list(&mut parser)?;
+        } else {
+            let _ = parser.parse_keyword(Keyword::VERBOSE);
+        }
+        MaintenanceCommand::Analyze {
+            tables: parse_targets(&mut parser)?,
+        }
+    } else if parse_word(&mut parser, "cluster") {
+        // tables are always clustered, so the arguments don't matter
+        return Ok(MaintenanceCommand::Cluster);
+    } else {
+        return parser.expected("VACUUM, ANALYZE or CLUSTER", parser.peek_token());
+    };
+    let _ = parser.consume_token(&Token::SemiColon);
+    parser.expect_token(&Token::EOF)?;
+    Ok(command)
+}
+
+/// `option [value], ...)`, after the opening parenthesis, with the options
+/// lowercase and their boolean values, true if omitted
+fn parse_option_list(parser: &mut Parser) -> Result<Vec<(String, bool)>, ParserError> {
+    let mut options = Vec::new();
+    loop {
+        let option = parser.parse_identifier()?.value.to_lowercase();
+        let value = match parser.peek_token().token {
+            Token::Comma | Token::RParen => true,
+            _ => {
+                let token = parser.next_token();
+                match token.to_string().to_lowercase().trim_matches('\'') {
+                    "true" | "on" | "1" => true,
+                    // the values of options like `PARALLEL 4` or
+                    // `INDEX_CLEANUP auto` matter to no option read here
+                    "false" | "off" | "0" => false,
+                    _ => true,
+                }
+            }
+        };
+        options.push((option, value));
+        if parser.consume_token(&Token::RParen) {
+            return Ok(options);
+        }
+        parser.expect_token(&Token::Comma)?;
+    }
+}
+
+/// `table [(column, ...)], ...`, none at the end of the statement
+fn parse_targets(parser: &mut Parser) -> Result<Vec<AnalyzeTarget>, ParserError> {
+    let mut targets = Vec::new();
+    if matches!(parser.peek_token().token, Token::EOF | Token::SemiColon) {
+        return Ok(targets);
+    }
+    loop {
+        let name = parser.parse_object_name(false)?;
+        let mut columns = Vec::new();
+        if parser.consume_token(&Token::LParen) {
+            loop {
+                columns.push(parse_name(parser)?);
+                if parser.consume_token(&Token::RParen) {
+                    break;
+                }
+                parser.expect_token(&Token::Comma)?;
+            }
+        }
+        targets.push(AnalyzeTarget { name, columns });
+        if !parser.consume_token(&Token::Comma) {
+            return Ok(targets);
+        }
+    }
+}
+
+/// Statistics of a column, computed by `ANALYZE`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnStatistics {
+    pub name: String,
+    pub null_count: u64,
+    /// The smallest value, `None` if all are null or the values of the type
+    /// can't be compared
+    pub min_value: Option<ScalarValue>,
+    pub max_value: Option<ScalarValue>,
+}
+
+/// Statistics of a table, computed by `ANALYZE`
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableStatistics {
+    pub row_count: u64,
+    pub columns: Vec<ColumnStatistics>,
+    pub last_analyze: DateTime<Utc>,
+    /// Times the table was analyzed
+    pub analyze_count: u64,
+}
+
+/// Catalog, schema and name of a table
+pub type TableKey = (String, String, String);
+
+/// The statistics of the tables analyzed on a server
+///
+/// The handlers attach the registry to the `SessionConfig` of statements as
+/// an extension, for the tables of `pg_catalog` to show the statistics.
+#[derive(Debug, Default)]
+pub struct StatisticsRegistry {
+    tables: RwLock<BTreeMap<TableKey, TableStatistics>>,
+}
+
+impl StatisticsRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Statistics of the table, if it was analyzed
+    pub fn table(&self, catalog: &str, schema: &str, table: &str) -> Option<TableStatistics> {
+        self.tables
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(&(catalog.to_string(), schema.to_string(), table.to_string()))
+            .cloned()
+    }
+
+    /// Statistics of all analyzed tables, ordered by table
+    pub fn tables(&self) -> Vec<(TableKey, TableStatistics)> {
+        self.tables
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .map(|(key, statistics)| (key.clone(), statistics.clone()))
+            .collect()
+    }
+
+    /// Record the statistics of a table, counting the analyses of the table
+    pub fn analyzed(&self, key: TableKey, mut statistics: TableStatistics) {
+        let mut tables = self.tables.write().unwrap_or_else(|e| e.into_inner());
+        if let Some(previous) = tables.get(&key) {
+            statistics.analyze_count += previous.analyze_count;
+        }
+        tables.insert(key, statistics);
+    }
+}
+
+/// Scan a table for the statistics of `columns`, all if empty
+pub async fn analyze_table(
+    session_context: &SessionContext,
+    table: TableReference,
+    columns: &[String],
+) -> Result<TableStatistics> {
+    let df = session_context.table(table.clone()).await?;
+    let df = if columns.is_empty() {
+        df
+    } else {
+        df.select_columns(&columns.iter().map(String::as_str).collect::<Vec<_>>())?
+    };
+    let fields = df.schema().fields().clone();
+
+    // values of types min and max can't compare leave their accumulators
+    let mut accumulators = fields
+        .iter()
+        .map(|field| {
+            let min = MinAccumulator::try_new(field.data_type()).ok();
+            let max = MaxAccumulator::try_new(field.data_type()).ok();
+            (0u64, min, max)
+        })
+        .collect::<Vec<_>>();
+    let mut row_count = 0u64;
+    let mut stream = df.execute_stream().await?;
+    while let Some(batch) = stream.next().await {
+        let batch = batch?;
+        row_count += batch.num_rows() as u64;
+        for (column, (nulls, min, max)) in batch.columns().iter().zip(&mut accumulators) {
+            *nulls += column.null_count() as u64;
+            let values = std::slice::from_ref(column);
+            if min
+                .as_mut()
+                .is_some_and(|min| min.update_batch(values).is_err())
+            {
+                *min = None;
+            }
+            if max
+                .as_mut()
+                .is_some_and(|max| max.update_batch(values).is_err())
+            {
+                *max = None;
+            }
+        }
+    }
+
+    let value = |accumulator: Option<&mut dyn Accumulator>| {
+        accumulator
+            .and_then(|accumulator| accumulator.evaluate().ok())
+            .filter(|value| !value.is_null())
+    };
+    let columns = fields
+        .iter()
+        .zip(accumulators)
+        .map(|(field, (null_count, mut min, mut max))| ColumnStatistics {
+            name: field.name().clone(),
+            null_count,
+            min_value: value(min.as_mut().map(|min| min as &mut dyn Accumulator)),
+            max_value: value(max.as_mut().map(|max| max as &mut dyn Accumulator)),
+        })
+        .collect();
+    Ok(TableStatistics {
+        row_count,
+        columns,
+        last_analyze: Utc::now(),
+        analyze_count: 1,
+    })
+}
+
+/// The tables of the catalogs of `session_context` `ANALYZE` without tables
+/// analyzes, which are all but those of the system schemas
+pub(crate) fn all_tables(session_context: &SessionContext) -> Result<Vec<TableReference>> {
+    let mut tables = Vec::new();
+    for catalog_name in session_context.catalog_names() {
+        let catalog = session_context
+            .catalog(&catalog_name)
+            .ok_or_else(|| DataFusionError::Plan(format!("catalog {catalog_name} not found")))?;
+        for schema_name in catalog.schema_names() {
+            if schema_name == "pg_catalog" || schema_name == "information_schema" {
+                continue;
+            }
+            let Some(schema) = catalog.schema(&schema_name) else {
+                continue;
+            };
+            for table_name in schema.table_names() {
+                tables.push(TableReference::full(
+                    catalog_name.as_str(),
+                    schema_name.as_str(),
+                    table_name,
+                ));
+            }
+        }
+    }
+    Ok(tables)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn targets(command: MaintenanceCommand) -> Vec<String> {
+        let (MaintenanceCommand::Vacuum { tables, .. } | MaintenanceCommand::Analyze { tables }) =
+            command
+        else {
+            panic!("expected tables");
+        };
+        tables
+            .into_iter()
+            .map(|target| format!("{}{:?}", target.name, target.columns))
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_maintenance_command() {
+        assert!(is_maintenance_command("vacuum;"));
+        assert!(is_maintenance_command("analyse events"));
+        assert!(!is_maintenance_command("explain analyze select 1"));
+
+        assert_eq!(
+            parse_command("VACUUM").unwrap(),
+            MaintenanceCommand::Vacuum {
+                analyze: false,
+                tables: vec![]
+            }
+        );
+        let command = parse_command("VACUUM FULL VERBOSE ANALYZE events, public.users").unwrap();
+        assert!(matches!(
+            command,
+            MaintenanceCommand::Vacuum { analyze: true, .. }
+        ));
+        assert_eq!(targets(command), ["events[]", "public.users[]"]);
+        let command = parse_command("vacuum (verbose, analyze on, parallel 4) events").unwrap();
+        assert!(matches!(
+            command,
+            MaintenanceCommand::Vacuum { analyze: true, .. }
+        ));
+        let command = parse_command("VACUUM (ANALYZE false) events;").unwrap();
+        assert!(matches!(
+            command,
+            MaintenanceCommand::Vacuum { analyze: false, .. }
+        ));
+
+        let command = parse_command("ANALYZE VERBOSE events (user_id, \"Kind\"), users").unwrap();
+        assert_eq!(
+            targets(command),
+            ["events[\"user_id\", \"Kind\"]", "users[]"]
+        );
+        assert_eq!(
+            parse_command("ANALYZE;").unwrap(),
+            MaintenanceCommand::Analyze { tables: vec![] }
+        );
+        assert_eq!(
+            parse_command("CLUSTER VERBOSE events USING events_pkey").unwrap(),
+            MaintenanceCommand::Cluster
+        );
+
+        assert!(parse_command("ANALYZE events (").is_err());
+        assert!(parse_command("VACUUM events users").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_analyze_table() {
+        let ctx = SessionContext::new();
+        ctx.sql("CREATE TABLE t (id INT, name TEXT) AS VALUES (3, 'c'), (1, NULL), (2, 'a')")
+            .await
+            .unwrap();
+        let statistics = analyze_table(&ctx, TableReference::bare("t"), &[])
+            .await
+            .unwrap();
+        assert_eq!(statistics.row_count, 3);
+        // text is Utf8 or Utf8View depending on the session, so compare
+        // the values as text
+        let columns = statistics
+            .columns
+            .iter()
+            .map(|column| {
+                let value = |v: &Option<ScalarValue>| v.as_ref().map(ScalarValue::to_string);
+                (
+                    column.name.as_str(),
+                    column.null_count,
+                    value(&column.min_value),
+                    value(&column.max_value),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            columns,
+            [
+                ("id", 0, Some("1".to_string()), Some("3".to_string())),
+                ("name", 1, Some("a".to_string()), Some("c".to_string())),
+            ]
+        );
+
+        let statistics = analyze_table(&ctx, TableReference::bare("t"), &["name".to_string()])
+            .await
+            .unwrap();
+        assert_eq!(statistics.columns.len(), 1);
+
+        let registry = StatisticsRegistry::new();
+        let key = (
+            "datafusion".to_string(),
+            "public".to_string(),
+            "t".to_string(),
+        );
+        registry.analyzed(key.clone(), statistics.clone());
+        registry.analyzed(key, statistics);
+        assert_eq!(
+            registry
+                .table("datafusion", "public", "t")
+                .unwrap()
+                .analyze_count,
+            2
+        );
+    }
+}
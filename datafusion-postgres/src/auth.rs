@@ -1,15 +1,47 @@
 use std::collections::HashMap;
+use std::fmt::Debug;
+use std::io::{Error as IOError, ErrorKind};
+use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::sync::Arc;
 
+use arrow_pg::encoder::IntervalStyle;
 use async_trait::async_trait;
-use pgwire::api::auth::{AuthSource, LoginInfo, Password};
-use pgwire::error::{PgWireError, PgWireResult};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use datafusion::common::TableReference;
+use datafusion::sql::sqlparser::ast::{ObjectType, Statement as SqlStatement};
+use futures::sink::{Sink, SinkExt};
+use pgwire::api::auth::scram::{gen_salted_password, random_nonce};
+use pgwire::api::auth::{
+    finish_authentication, protocol_negotiation, save_startup_parameters_to_metadata,
+    AuthSource as PgWireAuthSource, DefaultServerParameterProvider, LoginInfo, Password,
+    ServerParameterProvider, StartupHandler,
+};
+use pgwire::api::{ClientInfo, PgWireConnectionState, METADATA_USER};
+use pgwire::error::{ErrorInfo, PgWireError, PgWireResult};
+use pgwire::messages::startup::Authentication;
+use pgwire::messages::{PgWireBackendMessage, PgWireFrontendMessage};
+use subtle::ConstantTimeEq;
 use tokio::sync::RwLock;
 
+use crate::limits::QueryLimits;
+use crate::policy::{
+    denying_command_rule, denying_statement_rule, ColumnMaskingRule, RowFilterPolicy,
+    StatementKind, StatementRule, TableRowFilter,
+};
+use crate::role_settings::SettingStatement;
+use crate::roles::{
+    check_role_name, format_credentials, parse_credentials, Credentials, RoleAttributes,
+    RoleStatement,
+};
+
 /// User information stored in the authentication system
 #[derive(Debug, Clone)]
 pub struct User {
     pub username: String,
+    /// The password in cleartext, or its verifier of
+    /// [`ScramCredentials::verifier`] for roles of `CREATE ROLE`
     pub password_hash: String,
     pub roles: Vec<String>,
     pub is_superuser: bool,
@@ -106,11 +138,197 @@ pub struct RoleConfig {
     pub can_replication: bool,
 }
 
+/// Iterations of the SCRAM-SHA-256 key derivation, the postgres default
+pub const SCRAM_ITERATIONS: usize = 4096;
+
+/// Identity system the server authenticates clients against
+///
+/// Implement it to authenticate users of another system without changing
+/// the protocol handling, `AuthManager` is the default implementation.
+#[async_trait]
+pub trait AuthSource: Send + Sync {
+    /// The user named `username` with its roles, `None` if there is none
+    async fn user(&self, username: &str) -> Option<User>;
+
+    /// Whether `password` is the password of `user`
+    async fn verify_password(&self, user: &User, password: &str) -> bool;
+
+    /// Credentials of `user` for SCRAM-SHA-256 authentication
+    ///
+    /// SCRAM never sends the password, so sources which can only verify
+    /// passwords return `None`, the default, and their users can't
+    /// authenticate with it.
+    async fn scram_credentials(&self, _user: &User) -> Option<ScramCredentials> {
+        None
+    }
+}
+
+/// Salt and salted password of a user, as stored by postgres for
+/// SCRAM-SHA-256 authentication
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScramCredentials {
+    pub salt: Vec<u8>,
+    /// The password salted with [`SCRAM_ITERATIONS`] iterations
+    pub salted_password: Vec<u8>,
+}
+
+impl ScramCredentials {
+    pub fn from_password(password: &str, salt: Vec<u8>) -> Self {
+        let salted_password = gen_salted_password(password, &salt, SCRAM_ITERATIONS);
+        ScramCredentials {
+            salt,
+            salted_password,
+        }
+    }
+
+    /// The verifier of `password` with a random salt, how roles keep their
+    /// passwords instead of the cleartext
+    pub fn verifier(password: &str) -> String {
+        Self::from_password(password, random_nonce().into_bytes()).to_verifier()
+    }
+
+    /// The credentials as `SCRAM-SHA-256$<iterations>:<salt>$<salted password>`
+    /// with the salt and salted password in base64
+    pub fn to_verifier(&self) -> String {
+        format!(
+            "{SCRAM_VERIFIER_PREFIX}{SCRAM_ITERATIONS}:{}${}",
+            BASE64.encode(&self.salt),
+            BASE64.encode(&self.salted_password)
+        )
+    }
+
+    /// The credentials of a verifier of [`ScramCredentials::to_verifier`],
+    /// `None` if `verifier` isn't one
+    pub fn from_verifier(verifier: &str) -> Option<Self> {
+        let (iterations, rest) = verifier
+            .strip_prefix(SCRAM_VERIFIER_PREFIX)?
+            .split_once(':')?;
+        let (salt, salted_password) = rest.split_once('$')?;
+        if iterations.parse::<usize>().ok()? != SCRAM_ITERATIONS {
+            return None;
+        }
+        Some(ScramCredentials {
+            salt: BASE64.decode(salt).ok()?,
+            salted_password: BASE64.decode(salted_password).ok()?,
+        })
+    }
+
+    /// Whether `password` salts to the salted password of the credentials
+    pub fn verify(&self, password: &str) -> bool {
+        gen_salted_password(password, &self.salt, SCRAM_ITERATIONS)
+            .ct_eq(&self.salted_password)
+            .into()
+    }
+}
+
+/// Prefix of the password verifiers of [`User::password_hash`]
+const SCRAM_VERIFIER_PREFIX: &str = "SCRAM-SHA-256$";
+
+/// Whether `password` is the password of `user`, kept in cleartext or as a
+/// verifier of [`ScramCredentials::verifier`]
+fn password_matches(user: &User, password: &str) -> bool {
+    if user.password_hash.is_empty() {
+        return true;
+    }
+    if user.password_hash.starts_with(SCRAM_VERIFIER_PREFIX) {
+        return ScramCredentials::from_verifier(&user.password_hash)
+            .is_some_and(|credentials| credentials.verify(password));
+    }
+    // constant time, so the time taken doesn't tell how much matched
+    password
+        .as_bytes()
+        .ct_eq(user.password_hash.as_bytes())
+        .into()
+}
+
+/// Authorization of the statements of sessions
+///
+/// `AuthManager` is the default implementation, checking grants and
+/// statement rules of the user's roles.
+#[async_trait]
+pub trait Authorizer: Send + Sync {
+    /// Check whether `username` may run `statement`, before it is planned
+    async fn authorize_statement(
+        &self,
+        username: &str,
+        statement: &SqlStatement,
+    ) -> PgWireResult<()>;
+
+    /// Check whether `username` holds `permission` on `resource`
+    async fn authorize_table(
+        &self,
+        username: &str,
+        permission: Permission,
+        resource: ResourceType,
+    ) -> PgWireResult<()>;
+
+    /// Check whether `username` may write files of the server with
+    /// `COPY ... TO`, denied unless implemented
+    async fn authorize_write_files(&self, _username: &str) -> PgWireResult<()> {
+        Err(write_files_denied())
+    }
+
+    /// Check whether `username` may read files of the server with
+    /// `CREATE EXTERNAL TABLE`, denied unless implemented
+    async fn authorize_read_files(&self, _username: &str) -> PgWireResult<()> {
+        Err(read_files_denied())
+    }
+
+    /// Check whether `username` may run a statement sqlparser doesn't parse,
+    /// like `CREATE EXTERNAL TABLE` or `ANALYZE`, of `kind` and SQL text
+    /// `sql`
+    async fn authorize_command(
+        &self,
+        _username: &str,
+        _kind: StatementKind,
+        _sql: &str,
+    ) -> PgWireResult<()> {
+        Ok(())
+    }
+}
+
+fn statement_denied(username: &str) -> PgWireError {
+    PgWireError::UserError(Box::new(ErrorInfo::new(
+        "ERROR".to_string(),
+        "42501".to_string(), // insufficient_privilege
+        format!("statement denied by policy for user \"{username}\""),
+    )))
+}
+
+fn write_files_denied() -> PgWireError {
+    PgWireError::UserError(Box::new(ErrorInfo::new(
+        "ERROR".to_string(),
+        "42501".to_string(), // insufficient_privilege
+        "must be superuser or have privileges of the pg_write_server_files role \
+         to COPY to a file"
+            .to_string(),
+    )))
+}
+
+fn read_files_denied() -> PgWireError {
+    PgWireError::UserError(Box::new(ErrorInfo::new(
+        "ERROR".to_string(),
+        "42501".to_string(), // insufficient_privilege
+        "must be superuser or have privileges of the pg_read_server_files role \
+         to read files of the server"
+            .to_string(),
+    )))
+}
+
 /// Authentication manager that handles users and roles
 #[derive(Debug)]
 pub struct AuthManager {
     users: Arc<RwLock<HashMap<String, User>>>,
     roles: Arc<RwLock<HashMap<String, Role>>>,
+    row_filters: Arc<RwLock<Vec<TableRowFilter>>>,
+    column_masks: Arc<RwLock<Vec<ColumnMaskingRule>>>,
+    statement_rules: Arc<RwLock<Vec<StatementRule>>>,
+    query_limits: Arc<RwLock<HashMap<String, QueryLimits>>>,
+    /// Users of single sessions by their session user names, see
+    /// [`AuthManager::add_session_user`]
+    session_users: Arc<std::sync::RwLock<HashMap<String, User>>>,
+    /// File role statements write the roles to, see [`crate::roles`]
+    credentials_file: Option<PathBuf>,
 }
 
 impl Default for AuthManager {
@@ -155,7 +373,184 @@ impl AuthManager {
         AuthManager {
             users: Arc::new(RwLock::new(users)),
             roles: Arc::new(RwLock::new(roles)),
+            row_filters: Arc::new(RwLock::new(Vec::new())),
+            column_masks: Arc::new(RwLock::new(Vec::new())),
+            statement_rules: Arc::new(RwLock::new(Vec::new())),
+            query_limits: Arc::new(RwLock::new(HashMap::new())),
+            session_users: Arc::new(std::sync::RwLock::new(HashMap::new())),
+            credentials_file: None,
+        }
+    }
+
+    /// Read the roles of the credentials file at `path`, and write the
+    /// roles back to it when role statements change them
+    ///
+    /// Roles of the file replace the roles of the same name, like the
+    /// default `postgres` superuser. A missing file is created with the
+    /// first change.
+    pub fn with_credentials_file(mut self, path: impl Into<PathBuf>) -> Result<Self, IOError> {
+        let path = path.into();
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e),
+        };
+        let credentials = parse_credentials(&text).map_err(|e| {
+            IOError::new(ErrorKind::InvalidData, format!("{}: {e}", path.display()))
+        })?;
+        {
+            // nothing else holds the locks of a manager being built
+            let (Ok(mut users), Ok(mut roles)) = (self.users.try_write(), self.roles.try_write())
+            else {
+                return Err(IOError::other("the roles are in use"));
+            };
+            for role in credentials {
+                let attributes = role.attributes.clone();
+                let user = users.entry(role.name.clone()).or_insert_with(|| User {
+                    username: role.name.clone(),
+                    password_hash: String::new(),
+                    roles: vec![role.name.clone()],
+                    is_superuser: false,
+                    can_login: false,
+                    connection_limit: None,
+                });
+                user.password_hash = role.password;
+                set_role_attributes(
+                    user,
+                    roles
+                        .entry(role.name.clone())
+                        .or_insert_with(|| new_role(&role.name)),
+                    &attributes,
+                );
+            }
         }
+        self.credentials_file = Some(path);
+        Ok(self)
+    }
+
+    /// Create a role of `CREATE ROLE`, a user which may login with the
+    /// `LOGIN` attribute, as a member of the roles of `member_of`
+    pub async fn create_role_with_attributes(
+        &self,
+        name: &str,
+        attributes: &RoleAttributes,
+        member_of: &[String],
+    ) -> PgWireResult<()> {
+        check_role_name(name)?;
+        {
+            let mut users = self.users.write().await;
+            let mut roles = self.roles.write().await;
+            if users.contains_key(name) || roles.contains_key(name) {
+                return Err(PgWireError::UserError(Box::new(ErrorInfo::new(
+                    "ERROR".to_string(),
+                    "42710".to_string(), // duplicate_object
+                    format!("role \"{name}\" already exists"),
+                ))));
+            }
+            if let Some(missing) = member_of.iter().find(|role| !roles.contains_key(*role)) {
+                return Err(undefined_role(missing));
+            }
+            let mut user = User {
+                username: name.to_string(),
+                password_hash: attributes
+                    .password
+                    .as_deref()
+                    .map(ScramCredentials::verifier)
+                    .unwrap_or_default(),
+                roles: std::iter::once(name.to_string())
+                    .chain(member_of.iter().cloned())
+                    .collect(),
+                is_superuser: false,
+                can_login: false,
+                connection_limit: None,
+            };
+            let mut role = new_role(name);
+            set_role_attributes(&mut user, &mut role, attributes);
+            users.insert(name.to_string(), user);
+            roles.insert(name.to_string(), role);
+        }
+        self.save_credentials().await
+    }
+
+    /// Change the attributes of a role of `ALTER ROLE`
+    pub async fn alter_role(&self, name: &str, attributes: &RoleAttributes) -> PgWireResult<()> {
+        {
+            let mut users = self.users.write().await;
+            let mut roles = self.roles.write().await;
+            let (Some(user), Some(role)) = (users.get_mut(name), roles.get_mut(name)) else {
+                return Err(undefined_role(name));
+            };
+            if let Some(password) = &attributes.password {
+                user.password_hash = ScramCredentials::verifier(password);
+            }
+            set_role_attributes(user, role, attributes);
+        }
+        self.save_credentials().await
+    }
+
+    /// Remove a role of `DROP ROLE`, and its membership of other roles
+    pub async fn drop_role(&self, name: &str, if_exists: bool) -> PgWireResult<()> {
+        {
+            let mut users = self.users.write().await;
+            let mut roles = self.roles.write().await;
+            let user = users.remove(name);
+            let role = roles.remove(name);
+            if user.is_none() && role.is_none() {
+                return if if_exists {
+                    Ok(())
+                } else {
+                    Err(undefined_role(name))
+                };
+            }
+            for user in users.values_mut() {
+                user.roles.retain(|role| role != name);
+            }
+            for role in roles.values_mut() {
+                role.inherited_roles.retain(|role| role != name);
+            }
+        }
+        self.save_credentials().await
+    }
+
+    /// Write the roles to the credentials file, if there is one
+    async fn save_credentials(&self) -> PgWireResult<()> {
+        let Some(path) = &self.credentials_file else {
+            return Ok(());
+        };
+        let users = self.users.read().await;
+        let roles = self.roles.read().await;
+        let mut credentials = users
+            .values()
+            .map(|user| {
+                let role = roles.get(&user.username);
+                Credentials {
+                    name: user.username.clone(),
+                    // never write cleartext passwords of `add_user`
+                    password: match user.password_hash.as_str() {
+                        "" => String::new(),
+                        hash if hash.starts_with(SCRAM_VERIFIER_PREFIX) => hash.to_string(),
+                        password => ScramCredentials::verifier(password),
+                    },
+                    attributes: RoleAttributes {
+                        superuser: Some(user.is_superuser),
+                        login: Some(user.can_login),
+                        create_db: role.map(|role| role.can_create_db),
+                        create_role: role.map(|role| role.can_create_role),
+                        replication: role.map(|role| role.can_replication),
+                        password: None,
+                        connection_limit: Some(user.connection_limit.unwrap_or(-1)),
+                    },
+                }
+            })
+            .collect::<Vec<_>>();
+        credentials.sort_by(|a, b| a.name.cmp(&b.name));
+
+        // replace the file at once, so a crash never leaves half of it
+        let mut temporary = path.clone().into_os_string();
+        temporary.push(".tmp");
+        write_private_file(&temporary, format_credentials(&credentials).as_bytes())
+            .and_then(|_| std::fs::rename(&temporary, path))
+            .map_err(|e| PgWireError::ApiError(Box::new(e)))
     }
 
     /// Add a new user to the system
@@ -172,6 +567,128 @@ impl AuthManager {
         Ok(())
     }
 
+    /// Register a row-level security policy for a table
+    ///
+    /// All policies registered for the same table apply, combined with AND.
+    pub async fn add_row_filter_policy(
+        &self,
+        table: impl Into<TableReference>,
+        policy: Arc<dyn RowFilterPolicy>,
+    ) {
+        let mut row_filters = self.row_filters.write().await;
+        row_filters.push((table.into(), policy));
+    }
+
+    /// Remove all row-level security policies of a table
+    pub async fn remove_row_filter_policies(&self, table: impl Into<TableReference>) {
+        let table = table.into();
+        let mut row_filters = self.row_filters.write().await;
+        row_filters.retain(|(t, _)| *t != table);
+    }
+
+    /// Get all registered row-level security policies
+    pub async fn row_filter_policies(&self) -> Vec<TableRowFilter> {
+        self.row_filters.read().await.clone()
+    }
+
+    /// Register a column masking rule
+    ///
+    /// When several rules of the user's roles match a column, the rule added
+    /// first wins.
+    pub async fn add_column_masking_rule(&self, rule: ColumnMaskingRule) {
+        let mut column_masks = self.column_masks.write().await;
+        column_masks.push(rule);
+    }
+
+    /// Remove all masking rules of a table column
+    pub async fn remove_column_masking_rules(
+        &self,
+        table: impl Into<TableReference>,
+        column: &str,
+    ) {
+        let table = table.into();
+        let mut column_masks = self.column_masks.write().await;
+        column_masks.retain(|rule| !(rule.table == table && rule.column == column));
+    }
+
+    /// Get all registered column masking rules
+    pub async fn column_masking_rules(&self) -> Vec<ColumnMaskingRule> {
+        self.column_masks.read().await.clone()
+    }
+
+    /// Register a statement allow/deny rule, checked after the rules added
+    /// before it
+    pub async fn add_statement_rule(&self, rule: StatementRule) {
+        let mut statement_rules = self.statement_rules.write().await;
+        statement_rules.push(rule);
+    }
+
+    /// Remove all statement rules
+    pub async fn clear_statement_rules(&self) {
+        self.statement_rules.write().await.clear();
+    }
+
+    /// Get all registered statement rules
+    pub async fn statement_rules(&self) -> Vec<StatementRule> {
+        self.statement_rules.read().await.clone()
+    }
+
+    /// Check the statement against the statement rules of the user's roles
+    ///
+    /// Returns an `insufficient_privilege` error if a rule denies it.
+    /// Superusers bypass statement rules.
+    pub async fn check_statement_rules(
+        &self,
+        username: &str,
+        statement: &SqlStatement,
+    ) -> PgWireResult<()> {
+        let statement_rules = self.statement_rules.read().await;
+        if statement_rules.is_empty() {
+            return Ok(());
+        }
+
+        let roles = match self.get_user(username).await {
+            Some(user) if user.is_superuser => return Ok(()),
+            Some(user) => user.roles,
+            None => Vec::new(),
+        };
+
+        if denying_statement_rule(&statement_rules, &roles, statement).is_some() {
+            return Err(statement_denied(username));
+        }
+
+        Ok(())
+    }
+
+    /// Set the resource limits of queries run by members of `role`
+    ///
+    /// Limits of the `public` role apply to every user.
+    pub async fn set_role_query_limits(&self, role: &str, limits: QueryLimits) {
+        let mut query_limits = self.query_limits.write().await;
+        query_limits.insert(role.to_string(), limits);
+    }
+
+    /// Get the resource limits of queries run by the user
+    ///
+    /// When several roles of the user configure a limit, the most generous
+    /// one applies.
+    pub async fn query_limits_for_user(&self, username: &str) -> QueryLimits {
+        let roles = self
+            .get_user(username)
+            .await
+            .map(|user| user.roles)
+            .unwrap_or_default();
+        let query_limits = self.query_limits.read().await;
+
+        let limits = roles
+            .iter()
+            .filter_map(|role| query_limits.get(role).copied())
+            .reduce(QueryLimits::union);
+        limits
+            .or_else(|| query_limits.get("public").copied())
+            .unwrap_or_default()
+    }
+
     /// Authenticate a user with username and password
     pub async fn authenticate(&self, username: &str, password: &str) -> PgWireResult<bool> {
         let users = self.users.read().await;
@@ -181,9 +698,7 @@ impl AuthManager {
                 return Ok(false);
             }
 
-            // For now, accept empty password or any password for existing users
-            // In production, this should use proper password hashing (bcrypt, etc.)
-            if user.password_hash.is_empty() || password == user.password_hash {
+            if password_matches(user, password) {
                 return Ok(true);
             }
         }
@@ -193,12 +708,46 @@ impl AuthManager {
         Ok(false)
     }
 
-    /// Get user information
+    /// Get user information, of users and of session users
     pub async fn get_user(&self, username: &str) -> Option<User> {
+        let session_user = self
+            .session_users
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(username)
+            .cloned();
+        if session_user.is_some() {
+            return session_user;
+        }
         let users = self.users.read().await;
         users.get(username).cloned()
     }
 
+    /// Register `user` for the session of `client_addr` only, returning the
+    /// name the session is authorized as
+    ///
+    /// Unlike `add_user`, the user of the same name and the sessions of other
+    /// clients keep their roles, like sessions of tokens with other roles.
+    /// Session users aren't listed in `pg_roles` nor written to the
+    /// credentials file, and [`AuthManager::end_session`] removes them.
+    pub fn add_session_user(&self, client_addr: &SocketAddr, user: User) -> String {
+        let name = session_user_name(&user.username, client_addr);
+        self.session_users
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(name.clone(), user);
+        name
+    }
+
+    /// Remove the session users of the closed session of `client_addr`
+    pub fn end_session(&self, client_addr: &SocketAddr) {
+        let suffix = format!(":{client_addr}");
+        self.session_users
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .retain(|name, _| !name.ends_with(&suffix));
+    }
+
     /// Get role information
     pub async fn get_role(&self, role_name: &str) -> Option<Role> {
         let roles = self.roles.read().await;
@@ -326,6 +875,187 @@ impl AuthManager {
         false
     }
 
+    /// Check if a user holds any permission on a resource
+    ///
+    /// This is used to decide whether an object is visible to the user at all,
+    /// regardless of which operations the user may perform on it.
+    pub async fn has_any_permission(&self, username: &str, resource: ResourceType) -> bool {
+        const PERMISSIONS: &[Permission] = &[
+            Permission::Select,
+            Permission::Insert,
+            Permission::Update,
+            Permission::Delete,
+            Permission::Create,
+            Permission::Drop,
+            Permission::Alter,
+            Permission::Index,
+            Permission::References,
+            Permission::Trigger,
+            Permission::Execute,
+            Permission::Usage,
+        ];
+
+        for permission in PERMISSIONS {
+            if self
+                .check_permission(username, permission.clone(), resource.clone())
+                .await
+            {
+                return true;
+            }
+        }
+
+        false
+    }
+
+    /// Whether a user may create and drop databases, as a superuser or as a
+    /// member of a role with `can_create_db`, directly or inherited
+    pub async fn can_create_db(&self, username: &str) -> bool {
+        let Some(user) = self.get_user(username).await else {
+            return false;
+        };
+        if user.is_superuser {
+            return true;
+        }
+        for role_name in &user.roles {
+            if self
+                .role_has_attribute(role_name, |role| role.can_create_db)
+                .await
+            {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Whether a user may create, alter and drop roles, as a superuser or
+    /// as a member of a role with `can_create_role`, directly or inherited
+    pub async fn can_create_role(&self, username: &str) -> bool {
+        let Some(user) = self.get_user(username).await else {
+            return false;
+        };
+        if user.is_superuser {
+            return true;
+        }
+        for role_name in &user.roles {
+            if self
+                .role_has_attribute(role_name, |role| role.can_create_role)
+                .await
+            {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Whether a role or a role it inherits is a superuser or has the
+    /// attribute (helper for recursive checking)
+    fn role_has_attribute<'a>(
+        &'a self,
+        role_name: &'a str,
+        attribute: fn(&Role) -> bool,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = bool> + Send + 'a>> {
+        Box::pin(async move {
+            let Some(role) = self.get_role(role_name).await else {
+                return false;
+            };
+            if role.is_superuser || attribute(&role) {
+                return true;
+            }
+            for inherited_role in &role.inherited_roles {
+                if self.role_has_attribute(inherited_role, attribute).await {
+                    return true;
+                }
+            }
+            false
+        })
+    }
+
+    /// Check whether `username` may run a role statement
+    ///
+    /// Like postgres, managing roles needs `CREATEROLE` and managing
+    /// superusers needs a superuser, while users may change their own
+    /// password.
+    async fn authorize_role_statement(
+        &self,
+        username: &str,
+        statement: &RoleStatement,
+    ) -> PgWireResult<()> {
+        let is_superuser = self
+            .get_user(username)
+            .await
+            .is_some_and(|user| user.is_superuser);
+        if is_superuser {
+            return Ok(());
+        }
+        let (command, names, attributes) = match statement {
+            RoleStatement::Create {
+                names, attributes, ..
+            } => ("create", names.as_slice(), Some(attributes)),
+            RoleStatement::Alter { name, attributes } => {
+                if name == username && attributes.is_password_only() {
+                    return Ok(());
+                }
+                ("alter", std::slice::from_ref(name), Some(attributes))
+            }
+            RoleStatement::Drop { names, .. } => ("drop", names.as_slice(), None),
+        };
+        let mut allowed = self.can_create_role(username).await
+            && attributes.is_none_or(|attributes| attributes.superuser != Some(true));
+        for name in names {
+            if let Some(user) = self.get_user(name).await {
+                allowed &= !user.is_superuser;
+            }
+        }
+        if allowed {
+            Ok(())
+        } else {
+            Err(PgWireError::UserError(Box::new(ErrorInfo::new(
+                "ERROR".to_string(),
+                "42501".to_string(), // insufficient_privilege
+                format!("permission denied to {command} role"),
+            ))))
+        }
+    }
+
+    /// Check whether `username` may change the setting defaults of a role or
+    /// database
+    ///
+    /// Like postgres, users may change their own defaults, those of other
+    /// roles need `CREATEROLE` and those of databases `CREATEDB`, while
+    /// defaults of superusers and of all roles need a superuser.
+    pub(crate) async fn authorize_setting_statement(
+        &self,
+        username: &str,
+        statement: &SettingStatement,
+    ) -> PgWireResult<()> {
+        let is_superuser = self
+            .get_user(username)
+            .await
+            .is_some_and(|user| user.is_superuser);
+        let allowed = is_superuser
+            || match &statement.role {
+                Some(role) if role == username => true,
+                Some(role) => {
+                    self.can_create_role(username).await
+                        && !self
+                            .get_user(role)
+                            .await
+                            .is_some_and(|user| user.is_superuser)
+                }
+                None if statement.command == "ALTER DATABASE" => self.can_create_db(username).await,
+                None => false,
+            };
+        if allowed {
+            Ok(())
+        } else {
+            Err(PgWireError::UserError(Box::new(ErrorInfo::new(
+                "ERROR".to_string(),
+                "42501".to_string(), // insufficient_privilege
+                format!("permission denied to {}", statement.command.to_lowercase()),
+            ))))
+        }
+    }
+
     /// Check if a role has a specific permission (helper for recursive checking)
     fn check_role_permission<'a>(
         &'a self,
@@ -543,6 +1273,343 @@ impl AuthManager {
     }
 }
 
+#[async_trait]
+impl AuthSource for AuthManager {
+    async fn user(&self, username: &str) -> Option<User> {
+        self.get_user(username).await
+    }
+
+    async fn verify_password(&self, user: &User, password: &str) -> bool {
+        password_matches(user, password)
+    }
+
+    async fn scram_credentials(&self, user: &User) -> Option<ScramCredentials> {
+        if user.password_hash.starts_with(SCRAM_VERIFIER_PREFIX) {
+            return ScramCredentials::from_verifier(&user.password_hash);
+        }
+        Some(ScramCredentials::from_password(
+            &user.password_hash,
+            random_nonce().into_bytes(),
+        ))
+    }
+}
+
+#[async_trait]
+impl Authorizer for AuthManager {
+    async fn authorize_statement(
+        &self,
+        username: &str,
+        statement: &SqlStatement,
+    ) -> PgWireResult<()> {
+        let database_command = match statement {
+            SqlStatement::CreateDatabase { .. } => Some("create"),
+            SqlStatement::Drop {
+                object_type: ObjectType::Database,
+                ..
+            } => Some("drop"),
+            _ => None,
+        };
+        if let Some(command) = database_command {
+            if !self.can_create_db(username).await {
+                return Err(PgWireError::UserError(Box::new(ErrorInfo::new(
+                    "ERROR".to_string(),
+                    "42501".to_string(), // insufficient_privilege
+                    format!("permission denied to {command} database"),
+                ))));
+            }
+        }
+        if let Some(role_statement) = RoleStatement::from_statement(statement) {
+            self.authorize_role_statement(username, &role_statement?)
+                .await?;
+        }
+        if let Some(setting_statement) = SettingStatement::from_statement(statement) {
+            self.authorize_setting_statement(username, &setting_statement?)
+                .await?;
+        }
+        self.check_statement_rules(username, statement).await
+    }
+
+    async fn authorize_table(
+        &self,
+        username: &str,
+        permission: Permission,
+        resource: ResourceType,
+    ) -> PgWireResult<()> {
+        if self.check_permission(username, permission, resource).await {
+            Ok(())
+        } else {
+            Err(PgWireError::UserError(Box::new(ErrorInfo::new(
+                "ERROR".to_string(),
+                "42501".to_string(), // insufficient_privilege
+                format!("permission denied for user \"{username}\""),
+            ))))
+        }
+    }
+
+    async fn authorize_write_files(&self, username: &str) -> PgWireResult<()> {
+        if self.user_has_role(username, "pg_write_server_files").await {
+            Ok(())
+        } else {
+            Err(write_files_denied())
+        }
+    }
+
+    async fn authorize_read_files(&self, username: &str) -> PgWireResult<()> {
+        if self.user_has_role(username, "pg_read_server_files").await {
+            Ok(())
+        } else {
+            Err(read_files_denied())
+        }
+    }
+
+    async fn authorize_command(
+        &self,
+        username: &str,
+        kind: StatementKind,
+        sql: &str,
+    ) -> PgWireResult<()> {
+        let statement_rules = self.statement_rules.read().await;
+        if statement_rules.is_empty() {
+            return Ok(());
+        }
+        let roles = match self.get_user(username).await {
+            Some(user) if user.is_superuser => return Ok(()),
+            Some(user) => user.roles,
+            None => Vec::new(),
+        };
+        if denying_command_rule(&statement_rules, &roles, kind, sql).is_some() {
+            return Err(statement_denied(username));
+        }
+        Ok(())
+    }
+}
+
+/// A new role without privileges
+fn new_role(name: &str) -> Role {
+    Role {
+        name: name.to_string(),
+        is_superuser: false,
+        can_login: false,
+        can_create_db: false,
+        can_create_role: false,
+        can_create_user: false,
+        can_replication: false,
+        grants: vec![],
+        inherited_roles: vec![],
+    }
+}
+
+/// Set the attributes of a role of SQL on its user and role
+fn set_role_attributes(user: &mut User, role: &mut Role, attributes: &RoleAttributes) {
+    if let Some(superuser) = attributes.superuser {
+        user.is_superuser = superuser;
+        role.is_superuser = superuser;
+    }
+    if let Some(login) = attributes.login {
+        user.can_login = login;
+        role.can_login = login;
+    }
+    if let Some(create_db) = attributes.create_db {
+        role.can_create_db = create_db;
+    }
+    if let Some(create_role) = attributes.create_role {
+        role.can_create_role = create_role;
+        role.can_create_user = create_role;
+    }
+    if let Some(replication) = attributes.replication {
+        role.can_replication = replication;
+    }
+    if let Some(limit) = attributes.connection_limit {
+        user.connection_limit = (limit >= 0).then_some(limit);
+    }
+}
+
+/// Write `contents` to the file at `path`, readable only by its owner
+fn write_private_file(path: impl AsRef<std::path::Path>, contents: &[u8]) -> std::io::Result<()> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(path)?;
+    std::io::Write::write_all(&mut file, contents)?;
+    file.sync_all()
+}
+
+fn undefined_role(name: &str) -> PgWireError {
+    PgWireError::UserError(Box::new(ErrorInfo::new(
+        "ERROR".to_string(),
+        "42704".to_string(), // undefined_object
+        format!("role \"{name}\" does not exist"),
+    )))
+}
+
+fn password_authentication_failed(username: &str) -> PgWireError {
+    PgWireError::UserError(Box::new(ErrorInfo::new(
+        "FATAL".to_string(),
+        "28P01".to_string(), // invalid_password
+        format!("password authentication failed for user \"{username}\""),
+    )))
+}
+
+/// The user named in the startup message of a client, if it may login
+async fn login_user(source: &dyn AuthSource, username: Option<&str>) -> PgWireResult<User> {
+    let username = username.unwrap_or_default();
+    match source.user(username).await {
+        Some(user) if user.can_login => Ok(user),
+        Some(_) => Err(PgWireError::UserError(Box::new(ErrorInfo::new(
+            "FATAL".to_string(),
+            "28000".to_string(), // invalid_authorization_specification
+            format!("User \"{username}\" is not allowed to login"),
+        )))),
+        None => Err(password_authentication_failed(username)),
+    }
+}
+
+/// Session metadata key of the session user name of
+/// [`AuthManager::add_session_user`] the session is authorized as
+pub(crate) const METADATA_AUTHORIZED_USER: &str = "authorized_user";
+
+/// The name of the session user of `username` for the session of
+/// `client_addr`, which role names never are as they have no colons
+pub(crate) fn session_user_name(username: &str, client_addr: &SocketAddr) -> String {
+    format!("{username}:{client_addr}")
+}
+
+/// Session metadata key of `standard_conforming_strings`
+pub(crate) const METADATA_STANDARD_CONFORMING_STRINGS: &str = "standard_conforming_strings";
+
+/// Session metadata key of `IntervalStyle`, which clients may also set in
+/// their startup parameters
+pub(crate) const METADATA_INTERVAL_STYLE: &str = "IntervalStyle";
+
+/// The boolean value of a setting, like `on`, `false` or `1`
+pub(crate) fn parse_bool_setting(value: &str) -> Option<bool> {
+    match value.to_lowercase().as_str() {
+        "on" | "true" | "yes" | "1" => Some(true),
+        "off" | "false" | "no" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+/// Whether backslashes in `'...'` literals of the session are plain
+/// characters, the default, or escapes
+pub(crate) fn standard_conforming_strings<C: ClientInfo>(client: &C) -> bool {
+    client
+        .metadata()
+        .get(METADATA_STANDARD_CONFORMING_STRINGS)
+        .and_then(|value| parse_bool_setting(value))
+        .unwrap_or(true)
+}
+
+/// The text format of the intervals of the session
+pub(crate) fn interval_style<C: ClientInfo>(client: &C) -> IntervalStyle {
+    client
+        .metadata()
+        .get(METADATA_INTERVAL_STYLE)
+        .and_then(|name| IntervalStyle::from_name(name))
+        .unwrap_or_default()
+}
+
+/// The parameters reported to clients once they are authenticated: the
+/// defaults of pgwire, `standard_conforming_strings`, by which libpq
+/// escapes strings, and `IntervalStyle`, by which clients parse intervals
+#[derive(Debug, Default)]
+pub struct ServerParameters(DefaultServerParameterProvider);
+
+impl ServerParameterProvider for ServerParameters {
+    fn server_parameters<C>(&self, client: &C) -> Option<HashMap<String, String>>
+    where
+        C: ClientInfo,
+    {
+        let mut parameters = self.0.server_parameters(client)?;
+        let standard_conforming_strings = match standard_conforming_strings(client) {
+            true => "on",
+            false => "off",
+        };
+        parameters.insert(
+            METADATA_STANDARD_CONFORMING_STRINGS.to_string(),
+            standard_conforming_strings.to_string(),
+        );
+        parameters.insert(
+            METADATA_INTERVAL_STYLE.to_string(),
+            interval_style(client).name().to_string(),
+        );
+        Some(parameters)
+    }
+}
+
+/// Startup handler authenticating users with a cleartext password checked
+/// by an [`AuthSource`]
+pub struct PasswordStartupHandler {
+    source: Arc<dyn AuthSource>,
+    parameter_provider: ServerParameters,
+}
+
+impl PasswordStartupHandler {
+    pub fn new(source: Arc<dyn AuthSource>) -> Self {
+        PasswordStartupHandler {
+            source,
+            parameter_provider: ServerParameters::default(),
+        }
+    }
+}
+
+#[async_trait]
+impl StartupHandler for PasswordStartupHandler {
+    async fn on_startup<C>(
+        &self,
+        client: &mut C,
+        message: PgWireFrontendMessage,
+    ) -> PgWireResult<()>
+    where
+        C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send,
+        C::Error: Debug,
+        PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    {
+        match message {
+            PgWireFrontendMessage::Startup(ref startup) => {
+                protocol_negotiation(client, startup).await?;
+                save_startup_parameters_to_metadata(client, startup);
+                client.set_state(PgWireConnectionState::AuthenticationInProgress);
+                client
+                    .send(PgWireBackendMessage::Authentication(
+                        Authentication::CleartextPassword,
+                    ))
+                    .await?;
+            }
+            PgWireFrontendMessage::PasswordMessageFamily(pwd) => {
+                let pwd = pwd.into_password()?;
+                let username = client.metadata().get(METADATA_USER).cloned();
+                let user = login_user(self.source.as_ref(), username.as_deref()).await?;
+                if !self.source.verify_password(&user, &pwd.password).await {
+                    return Err(password_authentication_failed(&user.username));
+                }
+                finish_authentication(client, &self.parameter_provider).await?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+/// pgwire `AuthSource` handing the SCRAM credentials of an [`AuthSource`]
+/// over to pgwire's SCRAM-SHA-256 startup handler
+pub struct ScramAuthSource(pub Arc<dyn AuthSource>);
+
+#[async_trait]
+impl PgWireAuthSource for ScramAuthSource {
+    async fn get_password(&self, login: &LoginInfo) -> PgWireResult<Password> {
+        let user = login_user(self.0.as_ref(), login.user()).await?;
+        match self.0.scram_credentials(&user).await {
+            Some(credentials) => Ok(Password::new(
+                Some(credentials.salt),
+                credentials.salted_password,
+            )),
+            None => Err(password_authentication_failed(&user.username)),
+        }
+    }
+}
+
 /// AuthSource implementation for integration with pgwire authentication
 /// Provides proper password-based authentication instead of custom startup handler
 #[derive(Clone)]
@@ -557,7 +1624,7 @@ impl DfAuthSource {
 }
 
 #[async_trait]
-impl AuthSource for DfAuthSource {
+impl PgWireAuthSource for DfAuthSource {
     async fn get_password(&self, login: &LoginInfo) -> PgWireResult<Password> {
         if let Some(username) = login.user() {
             // Check if user exists in our RBAC system
@@ -646,7 +1713,7 @@ impl SimpleAuthSource {
 }
 
 #[async_trait]
-impl AuthSource for SimpleAuthSource {
+impl PgWireAuthSource for SimpleAuthSource {
     async fn get_password(&self, login: &LoginInfo) -> PgWireResult<Password> {
         let username = login.user().unwrap_or("anonymous");
 
@@ -720,4 +1787,38 @@ mod tests {
         assert!(auth_manager.user_has_role("postgres", "postgres").await);
         assert!(auth_manager.user_has_role("postgres", "any_role").await); // superuser
     }
+
+    #[tokio::test]
+    async fn test_auth_manager_as_auth_source() {
+        let auth_manager = AuthManager::new();
+        tokio::time::sleep(tokio::time::Duration::from_millis(10)).await;
+        auth_manager
+            .add_user(User {
+                username: "alice".to_string(),
+                password_hash: "secret".to_string(),
+                roles: vec!["readonly".to_string()],
+                is_superuser: false,
+                can_login: true,
+                connection_limit: None,
+            })
+            .await
+            .unwrap();
+        let source: &dyn AuthSource = &auth_manager;
+
+        assert!(source.user("nobody").await.is_none());
+        let alice = source.user("alice").await.unwrap();
+        assert_eq!(alice.roles, vec!["readonly"]);
+        assert!(source.verify_password(&alice, "secret").await);
+        assert!(!source.verify_password(&alice, "guess").await);
+
+        let credentials = source.scram_credentials(&alice).await.unwrap();
+        assert_eq!(
+            credentials,
+            ScramCredentials::from_password("secret", credentials.salt.clone())
+        );
+        assert_ne!(
+            credentials,
+            ScramCredentials::from_password("guess", credentials.salt.clone())
+        );
+    }
 }
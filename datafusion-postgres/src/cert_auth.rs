@@ -0,0 +1,331 @@
+//! Authentication of clients by their TLS certificate, like the `cert`
+//! method of postgres with `clientcert=verify-full`.
+//!
+//! The TLS layer verifies client certificates against the CA certificates
+//! set with `ServerOptions::with_tls_client_ca_path`. A client then logs in
+//! as the role its certificate is issued to: the common name of its subject,
+//! mapped to roles by the identities of [`CertAuthConfig`]. Unlike postgres,
+//! the subject alternative names of certificates can be matched too, with
+//! [`CertAuthConfig::with_subject_alt_names`].
+
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use futures::sink::Sink;
+use pgwire::api::auth::{
+    finish_authentication, protocol_negotiation, save_startup_parameters_to_metadata,
+    StartupHandler,
+};
+use pgwire::api::{ClientInfo, PgWireConnectionState, METADATA_USER};
+use pgwire::error::{ErrorInfo, PgWireError, PgWireResult};
+use pgwire::messages::{PgWireBackendMessage, PgWireFrontendMessage};
+use rustls_pki_types::CertificateDer;
+use x509_certificate::X509Certificate;
+
+use crate::auth::{AuthSource, ServerParameters};
+
+/// DER of the object identifier of the subject alternative name extension,
+/// 2.5.29.17
+const SUBJECT_ALT_NAME: &[u8] = &[0x55, 0x1d, 0x11];
+
+/// Configuration of certificate authentication
+///
+/// Without identities, the name a certificate is issued to must be the name
+/// of the role, like postgres without a user name map. Otherwise only the
+/// identities apply, like the lines of `pg_ident.conf`.
+#[derive(Debug, Clone, Default)]
+pub struct CertAuthConfig {
+    identities: Vec<(String, String)>,
+    subject_alt_names: bool,
+}
+
+impl CertAuthConfig {
+    pub fn new() -> CertAuthConfig {
+        CertAuthConfig::default()
+    }
+
+    /// Let clients with a certificate issued to `name` log in as `role`
+    pub fn with_identity(mut self, name: impl Into<String>, role: impl Into<String>) -> Self {
+        self.identities.push((name.into(), role.into()));
+        self
+    }
+
+    /// Also match the subject alternative names of certificates, not only
+    /// their common name like postgres
+    pub fn with_subject_alt_names(mut self, subject_alt_names: bool) -> Self {
+        self.subject_alt_names = subject_alt_names;
+        self
+    }
+
+    /// Whether a certificate issued to `names` may log in as `role`
+    pub fn allows(&self, names: &[String], role: &str) -> bool {
+        names.iter().any(|name| {
+            if self.identities.is_empty() {
+                name == role
+            } else {
+                self.identities
+                    .iter()
+                    .any(|(identity, mapped)| identity == name && mapped == role)
+            }
+        })
+    }
+}
+
+/// The names a certificate is issued to: the common name of its subject,
+/// and with `subject_alt_names` the DNS names, email addresses and URIs of
+/// its subject alternative names
+pub fn certificate_names(der: &[u8], subject_alt_names: bool) -> Result<Vec<String>, String> {
+    let certificate =
+        X509Certificate::from_der(der).map_err(|e| format!("invalid certificate: {e}"))?;
+    let mut names = certificate
+        .subject_common_name()
+        .into_iter()
+        .collect::<Vec<_>>();
+    for extension in certificate.iter_extensions() {
+        if !subject_alt_names || extension.id.as_ref() != SUBJECT_ALT_NAME {
+            continue;
+        }
+        let value = extension.value.to_bytes();
+        let Some((0x30, mut general_names, _)) = der_element(&value) else {
+            return Err("invalid subject alternative names".to_string());
+        };
+        while let Some((tag, content, rest)) = der_element(general_names) {
+            // rfc822Name [1], dNSName [2] and uniformResourceIdentifier [6]
+            if matches!(tag, 0x81 | 0x82 | 0x86) {
+                if let Ok(name) = std::str::from_utf8(content) {
+                    names.push(name.to_string());
+                }
+            }
+            general_names = rest;
+        }
+    }
+    Ok(names)
+}
+
+/// The tag and content of the DER element at the start of `der`, and the
+/// bytes after it
+fn der_element(der: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, rest) = der.split_first()?;
+    let (&first, rest) = rest.split_first()?;
+    let (len, rest) = if first < 0x80 {
+        (first as usize, rest)
+    } else {
+        let count = (first & 0x7f) as usize;
+        if count == 0 || count > 4 || rest.len() < count {
+            return None;
+        }
+        let len = rest[..count]
+            .iter()
+            .fold(0, |len, byte| len << 8 | *byte as usize);
+        (len, &rest[count..])
+    };
+    (rest.len() >= len).then(|| (tag, &rest[..len], &rest[len..]))
+}
+
+fn certificate_authentication_failed(username: &str, detail: Option<String>) -> PgWireError {
+    let mut info = ErrorInfo::new(
+        "FATAL".to_string(),
+        "28000".to_string(), // invalid_authorization_specification
+        format!("certificate authentication failed for user \"{username}\""),
+    );
+    info.detail = detail;
+    PgWireError::UserError(Box::new(info))
+}
+
+/// Startup handler authenticating users with the TLS certificate of their
+/// connection, without asking for a password
+pub struct CertStartupHandler {
+    source: Arc<dyn AuthSource>,
+    config: CertAuthConfig,
+    parameter_provider: ServerParameters,
+}
+
+impl CertStartupHandler {
+    pub fn new(source: Arc<dyn AuthSource>, config: CertAuthConfig) -> Self {
+        CertStartupHandler {
+            source,
+            config,
+            parameter_provider: ServerParameters::default(),
+        }
+    }
+
+    /// Check that `certificate`, verified by the TLS layer, is issued to
+    /// `username` and that the user may login
+    async fn authenticate(
+        &self,
+        username: &str,
+        certificate: Option<&CertificateDer<'_>>,
+    ) -> PgWireResult<()> {
+        let Some(certificate) = certificate else {
+            return Err(PgWireError::UserError(Box::new(ErrorInfo::new(
+                "FATAL".to_string(),
+                "28000".to_string(), // invalid_authorization_specification
+                "connection requires a valid client certificate".to_string(),
+            ))));
+        };
+        let names = certificate_names(certificate, self.config.subject_alt_names)
+            .map_err(|e| certificate_authentication_failed(username, Some(e)))?;
+        if !self.config.allows(&names, username) {
+            return Err(certificate_authentication_failed(
+                username,
+                Some(format!(
+                    "The certificate is issued to {}.",
+                    names.join(", ")
+                )),
+            ));
+        }
+        match self.source.user(username).await {
+            Some(user) if user.can_login => Ok(()),
+            _ => Err(certificate_authentication_failed(username, None)),
+        }
+    }
+}
+
+#[async_trait]
+impl StartupHandler for CertStartupHandler {
+    async fn on_startup<C>(
+        &self,
+        client: &mut C,
+        message: PgWireFrontendMessage,
+    ) -> PgWireResult<()>
+    where
+        C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send,
+        C::Error: Debug,
+        PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    {
+        if let PgWireFrontendMessage::Startup(ref startup) = message {
+            protocol_negotiation(client, startup).await?;
+            save_startup_parameters_to_metadata(client, startup);
+            client.set_state(PgWireConnectionState::AuthenticationInProgress);
+            let username = client
+                .metadata()
+                .get(METADATA_USER)
+                .cloned()
+                .unwrap_or_default();
+            let certificate = client
+                .client_certificates()
+                .and_then(|certificates| certificates.first());
+            self.authenticate(&username, certificate).await?;
+            finish_authentication(client, &self.parameter_provider).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bcder::Oid;
+    use x509_certificate::{KeyAlgorithm, X509CertificateBuilder};
+
+    use super::*;
+    use crate::auth::{AuthManager, User};
+
+    /// A self-signed certificate issued to `common_name` and the subject
+    /// alternative names of the DER `alt_names`
+    fn certificate(common_name: &str, alt_names: Option<&[u8]>) -> CertificateDer<'static> {
+        let mut builder = X509CertificateBuilder::default();
+        builder
+            .subject()
+            .append_common_name_utf8_string(common_name)
+            .unwrap();
+        if let Some(alt_names) = alt_names {
+            builder.add_extension_der_data(
+                Oid(bytes::Bytes::from_static(SUBJECT_ALT_NAME)),
+                false,
+                alt_names,
+            );
+        }
+        let (certificate, _) = builder
+            .create_with_random_keypair(KeyAlgorithm::Ed25519)
+            .unwrap();
+        CertificateDer::from(certificate.encode_der().unwrap())
+    }
+
+    #[test]
+    fn test_certificate_names() {
+        // SEQUENCE { dNSName "db.example.com", rfc822Name "bob@example.com" }
+        let mut alt_names = vec![0x30, 33, 0x82, 14];
+        alt_names.extend_from_slice(b"db.example.com");
+        alt_names.extend_from_slice(&[0x81, 15]);
+        alt_names.extend_from_slice(b"bob@example.com");
+        let der = certificate("alice", Some(&alt_names));
+        assert_eq!(certificate_names(&der, false).unwrap(), ["alice"]);
+        assert_eq!(
+            certificate_names(&der, true).unwrap(),
+            ["alice", "db.example.com", "bob@example.com"]
+        );
+
+        assert!(certificate_names(b"not a certificate", false).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_authenticate() {
+        let auth_manager = Arc::new(AuthManager::new());
+        auth_manager
+            .add_user(User {
+                username: "reporting".to_string(),
+                password_hash: String::new(),
+                roles: vec![],
+                is_superuser: false,
+                can_login: true,
+                connection_limit: None,
+            })
+            .await
+            .unwrap();
+        let handler = CertStartupHandler::new(auth_manager.clone(), CertAuthConfig::new());
+        let postgres = certificate("postgres", None);
+        handler
+            .authenticate("postgres", Some(&postgres))
+            .await
+            .unwrap();
+
+        let error_code = |result: PgWireResult<()>| match result {
+            Err(PgWireError::UserError(info)) => info.code,
+            _ => panic!("expected authentication to fail"),
+        };
+        assert_eq!(
+            error_code(handler.authenticate("reporting", Some(&postgres)).await),
+            "28000"
+        );
+        assert_eq!(
+            error_code(handler.authenticate("postgres", None).await),
+            "28000"
+        );
+        let unknown = certificate("mallory", None);
+        assert_eq!(
+            error_code(handler.authenticate("mallory", Some(&unknown)).await),
+            "28000"
+        );
+
+        // with identities, only they apply
+        let config = CertAuthConfig::new().with_identity("reporting.example.com", "reporting");
+        let handler = CertStartupHandler::new(auth_manager.clone(), config);
+        let reporting = certificate("reporting.example.com", None);
+        handler
+            .authenticate("reporting", Some(&reporting))
+            .await
+            .unwrap();
+        assert!(handler
+            .authenticate("postgres", Some(&postgres))
+            .await
+            .is_err());
+
+        // subject alternative names only match when enabled
+        // SEQUENCE { dNSName "reporting" }
+        let mut alt_names = vec![0x30, 11, 0x82, 9];
+        alt_names.extend_from_slice(b"reporting");
+        let alternative = certificate("someone", Some(&alt_names));
+        let handler = CertStartupHandler::new(auth_manager.clone(), CertAuthConfig::new());
+        assert!(handler
+            .authenticate("reporting", Some(&alternative))
+            .await
+            .is_err());
+        let config = CertAuthConfig::new().with_subject_alt_names(true);
+        let handler = CertStartupHandler::new(auth_manager, config);
+        handler
+            .authenticate("reporting", Some(&alternative))
+            .await
+            .unwrap();
+    }
+}
@@ -0,0 +1,116 @@
+//! Table OIDs and attribute numbers of the columns of query results
+//!
+//! `RowDescription` tells clients which table column each result column comes
+//! from. pgAdmin makes the grids of such results editable and ORMs map them
+//! back to their models. The origin of a column is found by following it
+//! down the plan to a table scan, through projections of plain columns,
+//! filters, sorts, limits, aliases and joins. Columns computed by
+//! expressions or aggregates have none, as in postgres.
+
+use std::sync::Arc;
+
+use datafusion::arrow::datatypes::DataType;
+use datafusion::logical_expr::{Distinct, Expr, JoinType, LogicalPlan, TableScan};
+use datafusion::prelude::SessionContext;
+use pgwire::api::results::FieldInfo;
+
+use crate::pg_catalog::{OidRegistry, PgCatalogSchemaProvider};
+
+/// `fields`, describing the output columns of `plan`, with the table OIDs
+/// and attribute numbers of the columns they come from
+pub(crate) fn with_column_origins(
+    fields: Vec<FieldInfo>,
+    plan: &LogicalPlan,
+    session_context: &SessionContext,
+) -> Vec<FieldInfo> {
+    let state = session_context.state();
+    let defaults = &state.config().options().catalog;
+    fields
+        .into_iter()
+        .enumerate()
+        .map(|(index, field)| {
+            let Some((scan, source_index)) = scanned_column(plan, index) else {
+                return field;
+            };
+            let table = scan
+                .table_name
+                .clone()
+                .resolve(&defaults.default_catalog, &defaults.default_schema);
+            let Some(registry) = oid_registry(session_context, &table.catalog) else {
+                return field;
+            };
+            let table_oid = registry.table_oid(&table.catalog, &table.schema, &table.table);
+            // pg_attribute numbers the columns of a table from 1
+            let attnum = i16::try_from(source_index + 1).ok();
+            FieldInfo::new(
+                field.name().to_string(),
+                Some(table_oid as i32),
+                attnum,
+                field.datatype().clone(),
+                field.format(),
+            )
+        })
+        .collect()
+}
+
+/// The table scan the output column `index` of `plan` is read by, with the
+/// index of the column in the table
+fn scanned_column(plan: &LogicalPlan, index: usize) -> Option<(&TableScan, usize)> {
+    match plan {
+        LogicalPlan::TableScan(scan) => {
+            let source_index = match &scan.projection {
+                Some(projection) => *projection.get(index)?,
+                None => index,
+            };
+            Some((scan, source_index))
+        }
+        LogicalPlan::Projection(projection) => {
+            let mut expr = projection.expr.get(index)?;
+            // timestamptz columns are cast to the time zone of the session
+            loop {
+                expr = match expr {
+                    Expr::Alias(alias) => &alias.expr,
+                    Expr::Cast(cast)
+                        if matches!(cast.data_type, DataType::Timestamp(_, Some(_))) =>
+                    {
+                        &cast.expr
+                    }
+                    _ => break,
+                };
+            }
+            let Expr::Column(column) = expr else {
+                return None;
+            };
+            let input_index = projection.input.schema().index_of_column(column).ok()?;
+            scanned_column(&projection.input, input_index)
+        }
+        LogicalPlan::Join(join)
+            if matches!(
+                join.join_type,
+                JoinType::Inner | JoinType::Left | JoinType::Right | JoinType::Full
+            ) =>
+        {
+            let left_len = join.left.schema().fields().len();
+            if index < left_len {
+                scanned_column(&join.left, index)
+            } else {
+                scanned_column(&join.right, index - left_len)
+            }
+        }
+        LogicalPlan::Filter(filter) => scanned_column(&filter.input, index),
+        LogicalPlan::Sort(sort) => scanned_column(&sort.input, index),
+        LogicalPlan::Limit(limit) => scanned_column(&limit.input, index),
+        LogicalPlan::SubqueryAlias(alias) => scanned_column(&alias.input, index),
+        LogicalPlan::Distinct(Distinct::All(input)) => scanned_column(input, index),
+        _ => None,
+    }
+}
+
+/// The OID registry of the `pg_catalog` of `catalog`
+fn oid_registry(session_context: &SessionContext, catalog: &str) -> Option<Arc<OidRegistry>> {
+    let pg_catalog = session_context.catalog(catalog)?.schema("pg_catalog")?;
+    let pg_catalog = pg_catalog
+        .as_any()
+        .downcast_ref::<PgCatalogSchemaProvider>()?;
+    Some(pg_catalog.oid_registry().clone())
+}
@@ -0,0 +1,1448 @@
+//! `COPY ... TO STDOUT` and `COPY ... FROM STDIN`, which `\copy` of psql and
+//! the bulk loaders of drivers run.
+//!
+//! Rows are exchanged in the text and CSV formats of postgres, with all of
+//! their options in both the current and the pre-9.0 syntax:
+//!
+//! ```sql
+//! COPY events TO STDOUT (FORMAT csv, HEADER, FORCE_QUOTE *);
+//! COPY events (id, kind) FROM STDIN (FORMAT csv, NULL 'NA', FORCE_NOT_NULL (kind));
+//! COPY events FROM STDIN WITH DELIMITER '|' NULL AS '' CSV HEADER;
+//! ```
+//!
+//! Options are checked like postgres does, e.g. `QUOTE` only applies to
+//! CSV and `FORCE_QUOTE` only to `COPY ... TO`. Data is exchanged in UTF8,
+//! LATIN1 or SQL_ASCII by `ENCODING`. The binary format isn't supported.
+//!
+//! `COPY ... TO` files are written by DataFusion, see `copy_to`.
+
+use std::collections::{HashMap, HashSet};
+use std::net::SocketAddr;
+use std::sync::Mutex;
+
+use datafusion::arrow::array::{new_null_array, Array, ArrayRef, RecordBatch, StringArray};
+use datafusion::arrow::compute::{cast_with_options, CastOptions};
+use datafusion::arrow::datatypes::{Field, SchemaRef};
+use datafusion::arrow::record_batch::RecordBatchOptions;
+use datafusion::common::TableReference;
+use datafusion::sql::planner::IdentNormalizer;
+use datafusion::sql::sqlparser::ast::{CopySource, CopyTarget, Query, Statement};
+use datafusion::sql::sqlparser::dialect::PostgreSqlDialect;
+use datafusion::sql::sqlparser::keywords::Keyword;
+use datafusion::sql::sqlparser::parser::{IsOptional, Parser, ParserError};
+use datafusion::sql::sqlparser::tokenizer::Token;
+use pgwire::error::{ErrorInfo, PgWireError, PgWireResult};
+use pgwire::messages::data::DataRow;
+
+use arrow_pg::datatypes::into_pg_type;
+
+use crate::errors::syntax_error;
+
+/// Characters a delimiter of the text format can't be, as they start
+/// escapes or the end of data marker
+const TEXT_DELIMITER_RESERVED: &str = "\\.abcdefghijklmnopqrstuvwxyz0123456789";
+
+/// Normalized names of encodings of postgres that aren't supported
+const UNSUPPORTED_ENCODINGS: &[&str] = &[
+    "big5",
+    "euccn",
+    "eucjis2004",
+    "eucjp",
+    "euckr",
+    "euctw",
+    "gb18030",
+    "gbk",
+    "iso885905",
+    "iso885906",
+    "iso885907",
+    "iso885908",
+    "johab",
+    "koi8r",
+    "koi8u",
+    "mulecode",
+    "shiftjis2004",
+    "sjis",
+    "uhc",
+    "win866",
+    "win874",
+    "win1250",
+    "win1251",
+    "win1252",
+    "win1253",
+    "win1254",
+    "win1255",
+    "win1256",
+    "win1257",
+    "win1258",
+];
+
+fn copy_error(code: &str, message: impl Into<String>) -> PgWireError {
+    PgWireError::UserError(Box::new(ErrorInfo::new(
+        "ERROR".to_string(),
+        code.to_string(),
+        message.into(),
+    )))
+}
+
+/// An error in the data of a `COPY ... FROM`, located like postgres does
+fn data_error(code: &str, message: impl Into<String>, table: &str, line: usize) -> PgWireError {
+    let mut info = ErrorInfo::new("ERROR".to_string(), code.to_string(), message.into());
+    info.where_context = Some(format!("COPY {table}, line {line}"));
+    PgWireError::UserError(Box::new(info))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum CopyFormat {
+    Text,
+    Csv,
+    Binary,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum CopyHeader {
+    Off,
+    On,
+    /// Check the header of `COPY ... FROM` names the columns
+    Match,
+}
+
+/// The columns of `FORCE_QUOTE`, `FORCE_NOT_NULL` and `FORCE_NULL`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum ForceColumns {
+    All,
+    Columns(Vec<String>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum CopyEncoding {
+    Utf8,
+    Latin1,
+    SqlAscii,
+}
+
+/// The value of an option of `COPY`
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum OptionValue {
+    None,
+    Text(String),
+    All,
+    Columns(Vec<String>),
+}
+
+/// The options of a `COPY ... TO STDOUT` or `COPY ... FROM STDIN`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct CopyOptions {
+    pub(crate) format: CopyFormat,
+    pub(crate) delimiter: char,
+    pub(crate) null: String,
+    pub(crate) header: CopyHeader,
+    pub(crate) quote: char,
+    pub(crate) escape: char,
+    pub(crate) force_quote: Option<ForceColumns>,
+    pub(crate) force_not_null: Option<ForceColumns>,
+    pub(crate) force_null: Option<ForceColumns>,
+    pub(crate) encoding: CopyEncoding,
+}
+
+/// A `COPY ... TO STDOUT` or `COPY ... FROM STDIN`
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct CopyStatement {
+    pub(crate) source: CopySource,
+    pub(crate) to: bool,
+    pub(crate) options: CopyOptions,
+}
+
+impl CopyStatement {
+    /// The `COPY` of `sql` to STDOUT or from STDIN, `None` for other
+    /// statements
+    ///
+    /// sqlparser neither parses all options nor `COPY ... FROM STDIN`
+    /// without the data following it, so these are parsed here.
+    pub(crate) fn parse(sql: &str) -> Option<PgWireResult<CopyStatement>> {
+        let sql_lower = sql.to_lowercase();
+        if !sql_lower.trim_start().starts_with("copy")
+            || !(sql_lower.contains("stdin") || sql_lower.contains("stdout"))
+        {
+            return None;
+        }
+        match parse_copy(sql) {
+            Ok(None) => None,
+            Ok(Some((source, to, options))) => {
+                Some(CopyOptions::new(options, to).map(|options| CopyStatement {
+                    source,
+                    to,
+                    options,
+                }))
+            }
+            Err(e) => Some(Err(syntax_error(e, sql))),
+        }
+    }
+
+    /// The statement for statement rules, without its options
+    pub(crate) fn statement(&self) -> Statement {
+        Statement::Copy {
+            source: self.source.clone(),
+            to: self.to,
+            target: if self.to {
+                CopyTarget::Stdout
+            } else {
+                CopyTarget::Stdin
+            },
+            options: vec![],
+            legacy_options: vec![],
+            values: vec![],
+        }
+    }
+
+    /// The query of the rows of a `COPY ... TO`
+    pub(crate) fn query(&self) -> PgWireResult<Box<Query>> {
+        match &self.source {
+            CopySource::Query(query) => Ok(query.clone()),
+            CopySource::Table {
+                table_name,
+                columns,
+            } => {
+                let columns = match columns.is_empty() {
+                    true => "*".to_string(),
+                    false => columns
+                        .iter()
+                        .map(ToString::to_string)
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                };
+                let sql = format!("SELECT {columns} FROM {table_name}");
+                Parser::new(&PostgreSqlDialect {})
+                    .try_with_sql(&sql)
+                    .and_then(|mut parser| parser.parse_query())
+                    .map_err(|e| syntax_error(e, &sql))
+            }
+        }
+    }
+}
+
+type CopyParts = (CopySource, bool, Vec<(String, OptionValue)>);
+
+fn parse_copy(sql: &str) -> Result<Option<CopyParts>, ParserError> {
+    let dialect = PostgreSqlDialect {};
+    let mut parser = Parser::new(&dialect).try_with_sql(sql)?;
+    parser.expect_keyword_is(Keyword::COPY)?;
+    let source = if parser.consume_token(&Token::LParen) {
+        let query = parser.parse_query()?;
+        parser.expect_token(&Token::RParen)?;
+        CopySource::Query(query)
+    } else {
+        let table_name = parser.parse_object_name(false)?;
+        let columns = parser.parse_parenthesized_column_list(IsOptional::Optional, false)?;
+        CopySource::Table {
+            table_name,
+            columns,
+        }
+    };
+    let to = match parser.parse_one_of_keywords(&[Keyword::FROM, Keyword::TO]) {
+        Some(Keyword::TO) => true,
+        Some(_) => false,
+        None => return parser.expected("FROM or TO", parser.peek_token()),
+    };
+    // files and programs are copied by `copy_to` or not at all
+    if !parser.parse_keyword(if to { Keyword::STDOUT } else { Keyword::STDIN }) {
+        return Ok(None);
+    }
+    if !to && matches!(source, CopySource::Query(_)) {
+        return Err(ParserError::ParserError(
+            "COPY ... FROM does not support query as a source".to_string(),
+        ));
+    }
+    let _ = parser.parse_keyword(Keyword::WITH);
+    let options = if parser.consume_token(&Token::LParen) {
+        let options = parser.parse_comma_separated(parse_option)?;
+        parser.expect_token(&Token::RParen)?;
+        options
+    } else {
+        parse_legacy_options(&mut parser)?
+    };
+    let _ = parser.consume_token(&Token::SemiColon);
+    parser.expect_token(&Token::EOF)?;
+    Ok(Some((source, to, options)))
+}
+
+/// An option of `COPY ... (name [value], ...)`
+fn parse_option(parser: &mut Parser) -> Result<(String, OptionValue), ParserError> {
+    let name = match parser.next_token().token {
+        Token::Word(word) => word.value.to_lowercase(),
+        _ => return parser.expected("option name", parser.peek_token()),
+    };
+    let value = match parser.peek_token().token {
+        Token::Comma | Token::RParen => OptionValue::None,
+        Token::Mul => {
+            parser.next_token();
+            OptionValue::All
+        }
+        Token::LParen => OptionValue::Columns(parse_columns(parser)?),
+        Token::Number(number, _) => {
+            parser.next_token();
+            OptionValue::Text(number)
+        }
+        Token::Word(word) => {
+            parser.next_token();
+            OptionValue::Text(word.value)
+        }
+        _ => OptionValue::Text(parser.parse_literal_string()?),
+    };
+    Ok((name, value))
+}
+
+/// The options of the syntax before postgres 9.0, like `CSV HEADER` and
+/// `DELIMITER AS ','`
+fn parse_legacy_options(parser: &mut Parser) -> Result<Vec<(String, OptionValue)>, ParserError> {
+    let text = |text: &str| OptionValue::Text(text.to_string());
+    let mut options = Vec::new();
+    loop {
+        let option = if parser.parse_keyword(Keyword::BINARY) {
+            ("format", text("binary"))
+        } else if parser.parse_keyword(Keyword::CSV) {
+            ("format", text("csv"))
+        } else if parser.parse_keyword(Keyword::HEADER) {
+            ("header", OptionValue::None)
+        } else if parser.parse_keyword(Keyword::FREEZE) {
+            ("freeze", OptionValue::None)
+        } else if let Some(keyword) = parser.parse_one_of_keywords(&[
+            Keyword::DELIMITER,
+            Keyword::NULL,
+            Keyword::QUOTE,
+            Keyword::ESCAPE,
+            Keyword::ENCODING,
+        ]) {
+            let name = match keyword {
+                Keyword::DELIMITER => "delimiter",
+                Keyword::NULL => "null",
+                Keyword::QUOTE => "quote",
+                Keyword::ESCAPE => "escape",
+                _ => "encoding",
+            };
+            let _ = parser.parse_keyword(Keyword::AS);
+            (name, OptionValue::Text(parser.parse_literal_string()?))
+        } else if parser.parse_keyword(Keyword::FORCE) {
+            let name = if parser.parse_keyword(Keyword::QUOTE) {
+                "force_quote"
+            } else if parser.parse_keywords(&[Keyword::NOT, Keyword::NULL]) {
+                "force_not_null"
+            } else if parser.parse_keyword(Keyword::NULL) {
+                "force_null"
+            } else {
+                return parser.expected("QUOTE, NOT NULL or NULL", parser.peek_token());
+            };
+            let value = if parser.consume_token(&Token::Mul) {
+                OptionValue::All
+            } else {
+                let normalizer = IdentNormalizer::default();
+                OptionValue::Columns(
+                    parser
+                        .parse_comma_separated(|parser| parser.parse_identifier())?
+                        .into_iter()
+                        .map(|column| normalizer.normalize(column))
+                        .collect(),
+                )
+            };
+            (name, value)
+        } else {
+            return Ok(options);
+        };
+        options.push((option.0.to_string(), option.1));
+    }
+}
+
+fn parse_columns(parser: &mut Parser) -> Result<Vec<String>, ParserError> {
+    let normalizer = IdentNormalizer::default();
+    Ok(parser
+        .parse_parenthesized_column_list(IsOptional::Mandatory, false)?
+        .into_iter()
+        .map(|column| normalizer.normalize(column))
+        .collect())
+}
+
+impl CopyOptions {
+    /// Check the options of a `COPY ... TO` if `to`, else of a `COPY ...
+    /// FROM`, like postgres does
+    fn new(options: Vec<(String, OptionValue)>, to: bool) -> PgWireResult<CopyOptions> {
+        let mut seen = HashSet::new();
+        let mut format = None;
+        let mut delimiter = None;
+        let mut null = None;
+        let mut header = None;
+        let mut quote = None;
+        let mut escape = None;
+        let mut force_quote = None;
+        let mut force_not_null = None;
+        let mut force_null = None;
+        let mut encoding = None;
+        for (name, value) in options {
+            if !seen.insert(name.clone()) {
+                return Err(copy_error(
+                    "42601", // syntax_error
+                    "conflicting or redundant options",
+                ));
+            }
+            match name.as_str() {
+                "format" => {
+                    format = Some(match string_value(&name, value)?.to_lowercase().as_str() {
+                        "text" => CopyFormat::Text,
+                        "csv" => CopyFormat::Csv,
+                        "binary" => CopyFormat::Binary,
+                        other => {
+                            return Err(copy_error(
+                                "22023", // invalid_parameter_value
+                                format!("COPY format \"{other}\" not recognized"),
+                            ));
+                        }
+                    })
+                }
+                // tables are never frozen
+                "freeze" => {
+                    boolean_value(&name, &value).ok_or_else(|| {
+                        copy_error(
+                            "22023", // invalid_parameter_value
+                            "freeze requires a Boolean value",
+                        )
+                    })?;
+                }
+                "delimiter" => delimiter = Some(string_value(&name, value)?),
+                "null" => null = Some(string_value(&name, value)?),
+                "header" => {
+                    header = Some(match boolean_value(&name, &value) {
+                        Some(true) => CopyHeader::On,
+                        Some(false) => CopyHeader::Off,
+                        None if value == OptionValue::Text("match".to_string()) => {
+                            if to {
+                                return Err(copy_error(
+                                    "0A000", // feature_not_supported
+                                    "cannot use \"match\" with HEADER in COPY TO",
+                                ));
+                            }
+                            CopyHeader::Match
+                        }
+                        None => {
+                            return Err(copy_error(
+                                "22023", // invalid_parameter_value
+                                "header requires a Boolean value or \"match\"",
+                            ));
+                        }
+                    })
+                }
+                "quote" => quote = Some(string_value(&name, value)?),
+                "escape" => escape = Some(string_value(&name, value)?),
+                "force_quote" => force_quote = Some(columns_value(&name, value, true)?),
+                "force_not_null" => force_not_null = Some(columns_value(&name, value, false)?),
+                "force_null" => force_null = Some(columns_value(&name, value, false)?),
+                "encoding" => encoding = Some(copy_encoding(&string_value(&name, value)?)?),
+                _ => {
+                    return Err(copy_error(
+                        "42601", // syntax_error
+                        format!("option \"{name}\" not recognized"),
+                    ));
+                }
+            }
+        }
+
+        let format = format.unwrap_or(CopyFormat::Text);
+        let csv = format == CopyFormat::Csv;
+        if format == CopyFormat::Binary {
+            for (option, set) in [
+                ("DELIMITER", delimiter.is_some()),
+                ("NULL", null.is_some()),
+                ("HEADER", header.is_some()),
+            ] {
+                if set {
+                    return Err(copy_error(
+                        "42601", // syntax_error
+                        format!("cannot specify {option} in BINARY mode"),
+                    ));
+                }
+            }
+        }
+        let delimiter = delimiter.unwrap_or_else(|| if csv { "," } else { "\t" }.to_string());
+        let delimiter = single_byte(&delimiter).ok_or_else(|| {
+            copy_error(
+                "0A000", // feature_not_supported
+                "COPY delimiter must be a single one-byte character",
+            )
+        })?;
+        if delimiter == '\r' || delimiter == '\n' {
+            return Err(copy_error(
+                "22023", // invalid_parameter_value
+                "COPY delimiter cannot be newline or carriage return",
+            ));
+        }
+        let null = null.unwrap_or_else(|| if csv { "" } else { "\\N" }.to_string());
+        if null.contains(['\r', '\n']) {
+            return Err(copy_error(
+                "22023", // invalid_parameter_value
+                "COPY null representation cannot use newline or carriage return",
+            ));
+        }
+        if !csv && TEXT_DELIMITER_RESERVED.contains(delimiter) {
+            return Err(copy_error(
+                "22023", // invalid_parameter_value
+                format!("COPY delimiter cannot be \"{delimiter}\""),
+            ));
+        }
+        if !csv && quote.is_some() {
+            return Err(copy_error(
+                "0A000", // feature_not_supported
+                "COPY quote available only in CSV mode",
+            ));
+        }
+        let quote = single_byte(quote.as_deref().unwrap_or("\"")).ok_or_else(|| {
+            copy_error(
+                "0A000", // feature_not_supported
+                "COPY quote must be a single one-byte character",
+            )
+        })?;
+        if csv && delimiter == quote {
+            return Err(copy_error(
+                "22023", // invalid_parameter_value
+                "COPY delimiter and quote must be different",
+            ));
+        }
+        if !csv && escape.is_some() {
+            return Err(copy_error(
+                "0A000", // feature_not_supported
+                "COPY escape available only in CSV mode",
+            ));
+        }
+        let escape = match escape {
+            Some(escape) => single_byte(&escape).ok_or_else(|| {
+                copy_error(
+                    "0A000", // feature_not_supported
+                    "COPY escape must be a single one-byte character",
+                )
+            })?,
+            None => quote,
+        };
+        for (option, set, available_to) in [
+            ("quote", force_quote.is_some(), true),
+            ("not null", force_not_null.is_some(), false),
+            ("null", force_null.is_some(), false),
+        ] {
+            if set && !csv {
+                return Err(copy_error(
+                    "0A000", // feature_not_supported
+                    format!("COPY force {option} available only in CSV mode"),
+                ));
+            }
+            if set && to != available_to {
+                let direction = if available_to { "TO" } else { "FROM" };
+                return Err(copy_error(
+                    "0A000", // feature_not_supported
+                    format!("COPY force {option} only available using COPY {direction}"),
+                ));
+            }
+        }
+        if null.contains(delimiter) {
+            return Err(copy_error(
+                "22023", // invalid_parameter_value
+                "COPY delimiter must not appear in the NULL specification",
+            ));
+        }
+        if csv && null.contains(quote) {
+            return Err(copy_error(
+                "22023", // invalid_parameter_value
+                "CSV quote character must not appear in the NULL specification",
+            ));
+        }
+        if format == CopyFormat::Binary {
+            return Err(copy_error(
+                "0A000", // feature_not_supported
+                "COPY in binary format is not supported, use text or csv",
+            ));
+        }
+
+        Ok(CopyOptions {
+            format,
+            delimiter,
+            null,
+            header: header.unwrap_or(CopyHeader::Off),
+            quote,
+            escape,
+            force_quote,
+            force_not_null,
+            force_null,
+            encoding: encoding.unwrap_or(CopyEncoding::Utf8),
+        })
+    }
+
+    /// Check the columns of the `FORCE_*` options are copied
+    pub(crate) fn check_columns(&self, columns: &[String]) -> PgWireResult<()> {
+        for (option, force) in [
+            ("FORCE_QUOTE", &self.force_quote),
+            ("FORCE_NOT_NULL", &self.force_not_null),
+            ("FORCE_NULL", &self.force_null),
+        ] {
+            if let Some(ForceColumns::Columns(forced)) = force {
+                if let Some(column) = forced.iter().find(|column| !columns.contains(column)) {
+                    return Err(copy_error(
+                        "42P10", // invalid_column_reference
+                        format!("{option} column \"{column}\" not referenced by COPY"),
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The header line of `COPY ... TO`, if any
+    pub(crate) fn header_line(&self, columns: &[String]) -> Option<String> {
+        if self.header == CopyHeader::Off {
+            return None;
+        }
+        let names = columns
+            .iter()
+            .map(|column| Some(column.as_str()))
+            .collect::<Vec<_>>();
+        Some(self.encode_row(&names, &vec![false; columns.len()]))
+    }
+
+    /// The line of `COPY ... TO` of the text of `values`, `None` for nulls,
+    /// quoting the columns of `force_quote` in CSV
+    pub(crate) fn encode_row(&self, values: &[Option<&str>], force_quote: &[bool]) -> String {
+        let mut line = String::new();
+        for (i, value) in values.iter().enumerate() {
+            if i > 0 {
+                line.push(self.delimiter);
+            }
+            match (value, self.format) {
+                (None, _) => line.push_str(&self.null),
+                (Some(value), CopyFormat::Csv) => {
+                    self.encode_csv_value(value, force_quote[i], values.len() == 1, &mut line)
+                }
+                (Some(value), _) => self.encode_text_value(value, &mut line),
+            }
+        }
+        line.push('\n');
+        line
+    }
+
+    fn encode_text_value(&self, value: &str, line: &mut String) {
+        for c in value.chars() {
+            match c {
+                '\\' => line.push_str("\\\\"),
+                '\u{8}' => line.push_str("\\b"),
+                '\u{c}' => line.push_str("\\f"),
+                '\n' => line.push_str("\\n"),
+                '\r' => line.push_str("\\r"),
+                '\t' => line.push_str("\\t"),
+                '\u{b}' => line.push_str("\\v"),
+                c if c == self.delimiter => {
+                    line.push('\\');
+                    line.push(c);
+                }
+                c => line.push(c),
+            }
+        }
+    }
+
+    fn encode_csv_value(&self, value: &str, force: bool, single_column: bool, line: &mut String) {
+        let quoted = force
+            // the value of the null string is told from nulls by quotes
+            || value == self.null
+            // and a single column `\.` from the end of data marker
+            || (single_column && value == "\\.")
+            || value.contains(|c| {
+                c == self.delimiter || c == self.quote || c == '\n' || c == '\r'
+            });
+        if !quoted {
+            line.push_str(value);
+            return;
+        }
+        line.push(self.quote);
+        for c in value.chars() {
+            if c == self.quote || c == self.escape {
+                line.push(self.escape);
+            }
+            line.push(c);
+        }
+        line.push(self.quote);
+    }
+
+    /// The bytes of `line` in the encoding of the options
+    pub(crate) fn encode(&self, line: String) -> PgWireResult<Vec<u8>> {
+        if self.encoding != CopyEncoding::Latin1 {
+            return Ok(line.into_bytes());
+        }
+        line.chars()
+            .map(|c| {
+                u8::try_from(u32::from(c)).map_err(|_| {
+                    copy_error(
+                        "22P05", // untranslatable_character
+                        format!(
+                            "character \"{c}\" of encoding \"UTF8\" has no equivalent in \
+                             encoding \"LATIN1\""
+                        ),
+                    )
+                })
+            })
+            .collect()
+    }
+
+    /// The rows of the data of a `COPY ... FROM` into `columns` of `table`,
+    /// `None` for nulls
+    pub(crate) fn decode_rows(
+        &self,
+        data: &[u8],
+        columns: &[String],
+        table: &str,
+    ) -> PgWireResult<Vec<Vec<Option<String>>>> {
+        let text = match self.encoding {
+            CopyEncoding::Latin1 => data.iter().map(|byte| char::from(*byte)).collect(),
+            _ => String::from_utf8(data.to_vec()).map_err(|e| {
+                let byte = data[e.utf8_error().valid_up_to()];
+                let line = data[..e.utf8_error().valid_up_to()]
+                    .iter()
+                    .filter(|byte| **byte == b'\n')
+                    .count();
+                data_error(
+                    "22021", // character_not_in_repertoire
+                    format!("invalid byte sequence for encoding \"UTF8\": 0x{byte:02x}"),
+                    table,
+                    line + 1,
+                )
+            })?,
+        };
+        let records = match self.format {
+            CopyFormat::Csv => self.csv_records(&text, columns, table)?,
+            _ => self.text_records(&text, table)?,
+        };
+
+        let mut rows = Vec::with_capacity(records.len());
+        for (i, (line, record)) in records.into_iter().enumerate() {
+            if i == 0 && self.header != CopyHeader::Off {
+                if self.header == CopyHeader::Match {
+                    check_header(&record, columns, table, line)?;
+                }
+                continue;
+            }
+            if record.len() > columns.len() {
+                return Err(data_error(
+                    "22P04", // bad_copy_file_format
+                    "extra data after last expected column",
+                    table,
+                    line,
+                ));
+            }
+            if let Some(column) = columns.get(record.len()) {
+                return Err(data_error(
+                    "22P04", // bad_copy_file_format
+                    format!("missing data for column \"{column}\""),
+                    table,
+                    line,
+                ));
+            }
+            rows.push(record);
+        }
+        Ok(rows)
+    }
+
+    /// The lines of the text format, with the number of each
+    fn text_records(
+        &self,
+        text: &str,
+        table: &str,
+    ) -> PgWireResult<Vec<(usize, Vec<Option<String>>)>> {
+        let mut records = Vec::new();
+        for (i, line) in text.split_terminator('\n').enumerate() {
+            let line = line.strip_suffix('\r').unwrap_or(line);
+            if line == "\\." {
+                break;
+            }
+            let fields = line
+                .split(self.delimiter)
+                .scan(false, |continued, field| {
+                    // a delimiter escaped by a backslash is part of the field
+                    let escaped = *continued;
+                    *continued = ends_with_escape(field);
+                    Some((escaped, field))
+                })
+                .fold(Vec::<String>::new(), |mut fields, (escaped, field)| {
+                    match fields.last_mut() {
+                        Some(last) if escaped => {
+                            last.push(self.delimiter);
+                            last.push_str(field);
+                        }
+                        _ => fields.push(field.to_string()),
+                    }
+                    fields
+                });
+            let record = fields
+                .into_iter()
+                .map(|field| match field == self.null {
+                    true => Ok(None),
+                    false => unescape_text(&field)
+                        .map(Some)
+                        .map_err(|message| data_error("22P04", message, table, i + 1)),
+                })
+                .collect::<PgWireResult<Vec<_>>>()?;
+            records.push((i + 1, record));
+        }
+        Ok(records)
+    }
+
+    /// The records of the CSV format, whose quoted values may span lines,
+    /// with the number of the line each starts on
+    fn csv_records(
+        &self,
+        text: &str,
+        columns: &[String],
+        table: &str,
+    ) -> PgWireResult<Vec<(usize, Vec<Option<String>>)>> {
+        let force_not_null = force_mask(&self.force_not_null, columns);
+        let force_null = force_mask(&self.force_null, columns);
+        let mut records = Vec::new();
+        let mut chars = text.chars().peekable();
+        let mut line = 1;
+        while chars.peek().is_some() {
+            let start = line;
+            let mut record = Vec::new();
+            let mut field = String::new();
+            let mut quoted = false;
+            let mut in_quotes = false;
+            loop {
+                let Some(c) = chars.next() else {
+                    if in_quotes {
+                        return Err(data_error(
+                            "22P04", // bad_copy_file_format
+                            "unterminated CSV quoted field",
+                            table,
+                            start,
+                        ));
+                    }
+                    break;
+                };
+                if in_quotes {
+                    if c == self.escape && chars.peek().is_some_and(|next| *next == self.quote) {
+                        field.push(self.quote);
+                        chars.next();
+                    } else if c == self.escape
+                        && self.escape != self.quote
+                        && chars.peek() == Some(&self.escape)
+                    {
+                        field.push(self.escape);
+                        chars.next();
+                    } else if c == self.quote {
+                        in_quotes = false;
+                    } else {
+                        line += usize::from(c == '\n');
+                        field.push(c);
+                    }
+                } else if c == self.quote {
+                    in_quotes = true;
+                    quoted = true;
+                } else if c == self.delimiter {
+                    record.push((std::mem::take(&mut field), quoted));
+                    quoted = false;
+                } else if c == '\n' || c == '\r' {
+                    if c == '\r' && chars.peek() == Some(&'\n') {
+                        chars.next();
+                    }
+                    line += 1;
+                    break;
+                } else {
+                    field.push(c);
+                }
+            }
+            record.push((field, quoted));
+            if record.len() == 1 && record[0] == ("\\.".to_string(), false) {
+                break;
+            }
+            let record = record
+                .into_iter()
+                .enumerate()
+                .map(|(i, (value, quoted))| {
+                    let is_null = value == self.null
+                        && match quoted {
+                            true => force_null.get(i) == Some(&true),
+                            false => force_not_null.get(i) != Some(&true),
+                        };
+                    (!is_null).then_some(value)
+                })
+                .collect();
+            records.push((start, record));
+        }
+        Ok(records)
+    }
+}
+
+/// Whether `field` ends with a backslash escaping what follows
+fn ends_with_escape(field: &str) -> bool {
+    field.chars().rev().take_while(|c| *c == '\\').count() % 2 == 1
+}
+
+/// The value of a field of the text format, with its escapes replaced
+fn unescape_text(field: &str) -> Result<String, String> {
+    if !field.contains('\\') {
+        return Ok(field.to_string());
+    }
+    let mut bytes = Vec::with_capacity(field.len());
+    let mut chars = field.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buffer = [0; 4];
+            bytes.extend_from_slice(c.encode_utf8(&mut buffer).as_bytes());
+            continue;
+        }
+        let Some(c) = chars.next() else {
+            // a trailing backslash stands for itself
+            bytes.push(b'\\');
+            break;
+        };
+        match c {
+            'b' => bytes.push(0x08),
+            'f' => bytes.push(0x0c),
+            'n' => bytes.push(b'\n'),
+            'r' => bytes.push(b'\r'),
+            't' => bytes.push(b'\t'),
+            'v' => bytes.push(0x0b),
+            '0'..='7' => {
+                let mut value = c.to_digit(8).unwrap_or_default();
+                for _ in 0..2 {
+                    match chars.peek().and_then(|c| c.to_digit(8)) {
+                        Some(digit) => {
+                            value = value * 8 + digit;
+                            chars.next();
+                        }
+                        None => break,
+                    }
+                }
+                bytes.push(value as u8);
+            }
+            'x' if chars.peek().is_some_and(char::is_ascii_hexdigit) => {
+                let mut value = 0;
+                for _ in 0..2 {
+                    match chars.peek().and_then(|c| c.to_digit(16)) {
+                        Some(digit) => {
+                            value = value * 16 + digit;
+                            chars.next();
+                        }
+                        None => break,
+                    }
+                }
+                bytes.push(value as u8);
+            }
+            c => {
+                let mut buffer = [0; 4];
+                bytes.extend_from_slice(c.encode_utf8(&mut buffer).as_bytes());
+            }
+        }
+    }
+    String::from_utf8(bytes).map_err(|e| {
+        let byte = e.as_bytes()[e.utf8_error().valid_up_to()];
+        format!("invalid byte sequence for encoding \"UTF8\": 0x{byte:02x}")
+    })
+}
+
+/// Check the header of `HEADER MATCH` names the columns
+fn check_header(
+    header: &[Option<String>],
+    columns: &[String],
+    table: &str,
+    line: usize,
+) -> PgWireResult<()> {
+    if header.len() != columns.len() {
+        return Err(data_error(
+            "22P04", // bad_copy_file_format
+            format!(
+                "wrong number of fields in header line: got {}, expected {}",
+                header.len(),
+                columns.len()
+            ),
+            table,
+            line,
+        ));
+    }
+    for (i, (name, column)) in header.iter().zip(columns).enumerate() {
+        if name.as_deref() != Some(column.as_str()) {
+            return Err(data_error(
+                "22P04", // bad_copy_file_format
+                format!(
+                    "column name mismatch in header line field {}: got \"{}\", expected \
+                     \"{column}\"",
+                    i + 1,
+                    name.as_deref().unwrap_or("null")
+                ),
+                table,
+                line,
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Which of `columns` the `FORCE_*` option applies to
+pub(crate) fn force_mask(force: &Option<ForceColumns>, columns: &[String]) -> Vec<bool> {
+    columns
+        .iter()
+        .map(|column| match force {
+            Some(ForceColumns::All) => true,
+            Some(ForceColumns::Columns(forced)) => forced.contains(column),
+            None => false,
+        })
+        .collect()
+}
+
+fn single_byte(text: &str) -> Option<char> {
+    let mut chars = text.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if c.is_ascii() => Some(c),
+        _ => None,
+    }
+}
+
+fn string_value(name: &str, value: OptionValue) -> PgWireResult<String> {
+    match value {
+        OptionValue::Text(text) => Ok(text),
+        OptionValue::None => Err(copy_error(
+            "42601", // syntax_error
+            format!("{name} requires a parameter"),
+        )),
+        _ => Err(copy_error(
+            "22023", // invalid_parameter_value
+            format!("{name} requires a string value"),
+        )),
+    }
+}
+
+/// The boolean of an option, true without value like postgres
+fn boolean_value(name: &str, value: &OptionValue) -> Option<bool> {
+    let text = match value {
+        OptionValue::None => return Some(true),
+        OptionValue::Text(text) => text.to_lowercase(),
+        _ => return None,
+    };
+    match text.as_str() {
+        "true" | "on" | "yes" | "1" | "t" | "y" => Some(true),
+        "false" | "off" | "no" | "0" | "f" | "n" => Some(false),
+        _ => {
+            log::debug!("COPY option {name} is not a boolean: {text}");
+            None
+        }
+    }
+}
+
+fn columns_value(name: &str, value: OptionValue, all: bool) -> PgWireResult<ForceColumns> {
+    match value {
+        OptionValue::Columns(columns) => Ok(ForceColumns::Columns(columns)),
+        OptionValue::All if all => Ok(ForceColumns::All),
+        _ => Err(copy_error(
+            "22023", // invalid_parameter_value
+            format!("argument to option \"{name}\" must be a list of column names"),
+        )),
+    }
+}
+
+/// The encoding named `name`, in any of the spellings of postgres
+fn copy_encoding(name: &str) -> PgWireResult<CopyEncoding> {
+    let normalized = name
+        .chars()
+        .filter(char::is_ascii_alphanumeric)
+        .collect::<String>()
+        .to_lowercase();
+    match normalized.as_str() {
+        "utf8" | "unicode" => Ok(CopyEncoding::Utf8),
+        "latin1" | "iso88591" => Ok(CopyEncoding::Latin1),
+        "sqlascii" => Ok(CopyEncoding::SqlAscii),
+        other
+            if UNSUPPORTED_ENCODINGS.contains(&other)
+                || other
+                    .strip_prefix("latin")
+                    .and_then(|n| n.parse::<u8>().ok())
+                    .is_some_and(|n| (2..=10).contains(&n)) =>
+        {
+            Err(copy_error(
+                "0A000", // feature_not_supported
+                format!("COPY encoding \"{name}\" is not supported, use UTF8, LATIN1 or SQL_ASCII"),
+            ))
+        }
+        _ => Err(copy_error(
+            "22023", // invalid_parameter_value
+            "argument to option \"encoding\" must be a valid encoding name",
+        )),
+    }
+}
+
+/// The batch of `rows` of `columns` in the schema of the table, with nulls
+/// in the other columns
+pub(crate) fn record_batch(
+    schema: &SchemaRef,
+    columns: &[String],
+    rows: &[Vec<Option<String>>],
+    table: &str,
+) -> PgWireResult<RecordBatch> {
+    let mut arrays = Vec::with_capacity(schema.fields().len());
+    for field in schema.fields() {
+        let array = match columns.iter().position(|column| column == field.name()) {
+            Some(i) => {
+                let text = rows
+                    .iter()
+                    .map(|row| row[i].as_deref())
+                    .collect::<StringArray>();
+                cast_text(&text, field)?
+            }
+            None => new_null_array(field.data_type(), rows.len()),
+        };
+        if !field.is_nullable() && array.null_count() > 0 {
+            return Err(copy_error(
+                "23502", // not_null_violation
+                format!(
+                    "null value in column \"{}\" of relation \"{table}\" violates not-null \
+                     constraint",
+                    field.name()
+                ),
+            ));
+        }
+        arrays.push(array);
+    }
+    let options = RecordBatchOptions::new().with_row_count(Some(rows.len()));
+    RecordBatch::try_new_with_options(schema.clone(), arrays, &options)
+        .map_err(|e| PgWireError::ApiError(Box::new(e)))
+}
+
+/// The values of `text` in the type of `field`, parsed like casts do
+fn cast_text(text: &StringArray, field: &Field) -> PgWireResult<ArrayRef> {
+    let options = CastOptions {
+        safe: false,
+        ..Default::default()
+    };
+    if let Ok(array) = cast_with_options(text, field.data_type(), &options) {
+        return Ok(array);
+    }
+    let type_name = into_pg_type(field.data_type())
+        .map(|pg_type| pg_type.name().to_string())
+        .unwrap_or_else(|_| field.data_type().to_string());
+    let invalid = text.iter().flatten().find(|value| {
+        cast_with_options(
+            &StringArray::from(vec![*value]),
+            field.data_type(),
+            &options,
+        )
+        .is_err()
+    });
+    Err(copy_error(
+        "22P02", // invalid_text_representation
+        match invalid {
+            Some(value) => format!("invalid input syntax for type {type_name}: \"{value}\""),
+            None => format!("invalid input for type {type_name}"),
+        },
+    ))
+}
+
+/// A `COPY ... FROM STDIN` waiting for the data of its client
+#[derive(Debug)]
+pub(crate) struct CopyIn {
+    pub(crate) table: TableReference,
+    pub(crate) schema: SchemaRef,
+    pub(crate) columns: Vec<String>,
+    pub(crate) options: CopyOptions,
+    pub(crate) data: Vec<u8>,
+}
+
+/// The `COPY ... FROM STDIN` of the sessions, by client address
+#[derive(Debug, Default)]
+pub(crate) struct CopyIns {
+    copies: Mutex<HashMap<SocketAddr, CopyIn>>,
+}
+
+impl CopyIns {
+    pub(crate) fn start(&self, client_addr: SocketAddr, copy: CopyIn) {
+        self.copies
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(client_addr, copy);
+    }
+
+    /// Append data of the client, false if its session copies nothing
+    pub(crate) fn append(&self, client_addr: &SocketAddr, data: &[u8]) -> bool {
+        match self
+            .copies
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get_mut(client_addr)
+        {
+            Some(copy) => {
+                copy.data.extend_from_slice(data);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The copy of the session, which is over
+    pub(crate) fn finish(&self, client_addr: &SocketAddr) -> Option<CopyIn> {
+        self.copies
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(client_addr)
+    }
+}
+
+/// The columns of a table, by name
+pub(crate) fn column_names(schema: &SchemaRef) -> Vec<String> {
+    schema
+        .fields()
+        .iter()
+        .map(|field| field.name().to_string())
+        .collect()
+}
+
+/// The text values of a data row of the text format, `None` for nulls
+pub(crate) fn data_row_values(row: &DataRow) -> Vec<Option<String>> {
+    let mut values = Vec::with_capacity(row.field_count as usize);
+    let mut data = &row.data[..];
+    while data.len() >= 4 {
+        let len = i32::from_be_bytes([data[0], data[1], data[2], data[3]]);
+        data = &data[4..];
+        if len < 0 {
+            values.push(None);
+            continue;
+        }
+        let (value, rest) = data.split_at(len as usize);
+        values.push(Some(String::from_utf8_lossy(value).into_owned()));
+        data = rest;
+    }
+    values
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use datafusion::arrow::array::{Int32Array, StringArray};
+    use datafusion::arrow::datatypes::{DataType, Field, Schema};
+
+    use super::*;
+
+    fn parse(sql: &str) -> PgWireResult<CopyStatement> {
+        CopyStatement::parse(sql).expect("a COPY to STDOUT or from STDIN")
+    }
+
+    fn error_code(sql: &str) -> String {
+        match parse(sql) {
+            Err(PgWireError::UserError(info)) => info.code,
+            other => panic!("expected {sql} to fail, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_parse_options() {
+        assert!(CopyStatement::parse("SELECT 'stdin'").is_none());
+        assert!(CopyStatement::parse("COPY t TO 'stdout.csv'").is_none());
+
+        let copy = parse("COPY t FROM STDIN").unwrap();
+        assert!(!copy.to);
+        assert_eq!(copy.options.format, CopyFormat::Text);
+        assert_eq!(copy.options.delimiter, '\t');
+        assert_eq!(copy.options.null, "\\N");
+
+        let copy = parse(
+            "COPY t (a, \"B\") TO STDOUT (FORMAT csv, DELIMITER ';', NULL 'NA', HEADER true, \
+             QUOTE '''', ESCAPE '\\', FORCE_QUOTE (a, \"B\"), ENCODING 'latin1')",
+        )
+        .unwrap();
+        assert!(copy.to);
+        let options = copy.options;
+        assert_eq!(options.format, CopyFormat::Csv);
+        assert_eq!(options.delimiter, ';');
+        assert_eq!(options.null, "NA");
+        assert_eq!(options.header, CopyHeader::On);
+        assert_eq!((options.quote, options.escape), ('\'', '\\'));
+        assert_eq!(
+            options.force_quote,
+            Some(ForceColumns::Columns(vec![
+                "a".to_string(),
+                "B".to_string()
+            ]))
+        );
+        assert_eq!(options.encoding, CopyEncoding::Latin1);
+        assert!(options.check_columns(&["a".to_string()]).is_err());
+
+        let options = parse(
+            "COPY t FROM STDIN WITH DELIMITER AS '|' NULL AS '' CSV HEADER QUOTE AS '\"' \
+             FORCE NOT NULL a, b;",
+        )
+        .unwrap()
+        .options;
+        assert_eq!(options.format, CopyFormat::Csv);
+        assert_eq!((options.delimiter, options.null.as_str()), ('|', ""));
+        assert_eq!(
+            options.force_not_null,
+            Some(ForceColumns::Columns(vec![
+                "a".to_string(),
+                "b".to_string()
+            ]))
+        );
+        let options = parse("COPY t TO STDOUT CSV FORCE QUOTE *").unwrap().options;
+        assert_eq!(options.force_quote, Some(ForceColumns::All));
+        let options = parse("COPY t FROM STDIN (FORMAT csv, HEADER match)")
+            .unwrap()
+            .options;
+        assert_eq!(options.header, CopyHeader::Match);
+    }
+
+    #[test]
+    fn test_invalid_options() {
+        for (sql, code) in [
+            ("COPY t FROM STDIN (FORMAT csv, FORMAT text)", "42601"),
+            ("COPY t FROM STDIN (COLOR 'red')", "42601"),
+            ("COPY t FROM STDIN (FORMAT xml)", "22023"),
+            ("COPY t FROM STDIN (FORMAT binary, NULL 'x')", "42601"),
+            ("COPY t FROM STDIN (FORMAT binary)", "0A000"),
+            ("COPY t FROM STDIN (DELIMITER '||')", "0A000"),
+            ("COPY t FROM STDIN (DELIMITER 'a')", "22023"),
+            ("COPY t FROM STDIN (DELIMITER ',', NULL 'a,b')", "22023"),
+            ("COPY t FROM STDIN (QUOTE '''')", "0A000"),
+            ("COPY t FROM STDIN (FORMAT csv, DELIMITER '\"')", "22023"),
+            ("COPY t FROM STDIN (FORMAT csv, NULL '\"')", "22023"),
+            ("COPY t FROM STDIN (FORMAT csv, FORCE_QUOTE (a))", "0A000"),
+            ("COPY t TO STDOUT (FORMAT csv, FORCE_NOT_NULL (a))", "0A000"),
+            ("COPY t TO STDOUT (FORCE_QUOTE *)", "0A000"),
+            ("COPY t TO STDOUT (FORMAT csv, HEADER match)", "0A000"),
+            ("COPY t TO STDOUT (HEADER maybe)", "22023"),
+            ("COPY t TO STDOUT (ENCODING 'SJIS')", "0A000"),
+            ("COPY t TO STDOUT (ENCODING 'klingon')", "22023"),
+            ("COPY t TO STDOUT (FORMAT", "42601"),
+            ("COPY (SELECT 1) FROM STDIN", "42601"),
+        ] {
+            assert_eq!(error_code(sql), code, "{sql}");
+        }
+    }
+
+    #[test]
+    fn test_encode_rows() {
+        let text = parse("COPY t TO STDOUT (DELIMITER '|')").unwrap().options;
+        assert_eq!(
+            text.encode_row(&[Some("a|b"), Some("tab\there\\"), None], &[false; 3]),
+            "a\\|b|tab\\there\\\\|\\N\n"
+        );
+
+        let csv = parse("COPY t TO STDOUT (FORMAT csv, HEADER, ESCAPE '\\', FORCE_QUOTE (b))")
+            .unwrap()
+            .options;
+        assert_eq!(
+            csv.header_line(&["a".to_string(), "b".to_string()]),
+            Some("a,b\n".to_string())
+        );
+        assert_eq!(
+            csv.encode_row(&[Some("say \"hi\""), Some("x")], &[false, true]),
+            "\"say \\\"hi\\\"\",\"x\"\n"
+        );
+        assert_eq!(
+            csv.encode_row(&[Some(""), None], &[false, false]),
+            "\"\",\n"
+        );
+        let csv = parse("COPY t TO STDOUT CSV").unwrap().options;
+        assert_eq!(csv.encode_row(&[Some("\\.")], &[false]), "\"\\.\"\n");
+
+        let latin1 = parse("COPY t TO STDOUT (ENCODING 'LATIN1')")
+            .unwrap()
+            .options;
+        assert_eq!(latin1.encode("café".to_string()).unwrap(), b"caf\xe9");
+        assert!(latin1.encode("€".to_string()).is_err());
+    }
+
+    #[test]
+    fn test_decode_rows() {
+        let columns = ["a".to_string(), "b".to_string()];
+        let text = parse("COPY t FROM STDIN").unwrap().options;
+        assert_eq!(
+            text.decode_rows(
+                b"1\t\\N\nx\\ty\tline\\nbreak\\101\\x42\n\\.\nignored\n",
+                &columns,
+                "t"
+            )
+            .unwrap(),
+            [
+                vec![Some("1".to_string()), None],
+                vec![Some("x\ty".to_string()), Some("line\nbreakAB".to_string())],
+            ]
+        );
+        let Err(PgWireError::UserError(info)) = text.decode_rows(b"1\t2\t3\n", &columns, "t")
+        else {
+            panic!("expected extra data");
+        };
+        assert_eq!(info.code, "22P04");
+        assert_eq!(info.where_context.as_deref(), Some("COPY t, line 1"));
+
+        let csv = parse(
+            "COPY t FROM STDIN (FORMAT csv, HEADER match, NULL 'NA', FORCE_NULL (a), \
+             FORCE_NOT_NULL (b))",
+        )
+        .unwrap()
+        .options;
+        assert_eq!(
+            csv.decode_rows(
+                b"a,b\r\n\"NA\",NA\n\"multi\nline\",\"\"\"q\"\"\"\n",
+                &columns,
+                "t"
+            )
+            .unwrap(),
+            [
+                vec![None, Some("NA".to_string())],
+                vec![Some("multi\nline".to_string()), Some("\"q\"".to_string())],
+            ]
+        );
+        for data in [&b"a,c\n"[..], b"a,b\n\"open,1\n"] {
+            let Err(PgWireError::UserError(info)) = csv.decode_rows(data, &columns, "t") else {
+                panic!("expected a bad header or quote");
+            };
+            assert_eq!(info.code, "22P04");
+        }
+
+        let Err(PgWireError::UserError(info)) = text.decode_rows(b"\xff\t1\n", &columns, "t")
+        else {
+            panic!("expected invalid UTF8");
+        };
+        assert_eq!(info.code, "22021");
+    }
+
+    #[test]
+    fn test_record_batch() {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new("name", DataType::Utf8, true),
+        ]));
+        let batch = record_batch(
+            &schema,
+            &["id".to_string()],
+            &[vec![Some("1".to_string())], vec![Some("2".to_string())]],
+            "t",
+        )
+        .unwrap();
+        assert_eq!(
+            batch
+                .column(0)
+                .as_any()
+                .downcast_ref::<Int32Array>()
+                .unwrap()
+                .values(),
+            &[1, 2]
+        );
+        assert_eq!(
+            batch
+                .column(1)
+                .as_any()
+                .downcast_ref::<StringArray>()
+                .unwrap()
+                .null_count(),
+            2
+        );
+
+        for (value, code) in [(Some("one"), "22P02"), (None, "23502")] {
+            let Err(PgWireError::UserError(info)) = record_batch(
+                &schema,
+                &["id".to_string()],
+                &[vec![value.map(str::to_string)]],
+                "t",
+            ) else {
+                panic!("expected {value:?} to be rejected");
+            };
+            assert_eq!(info.code, code);
+        }
+    }
+}
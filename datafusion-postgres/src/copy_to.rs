@@ -0,0 +1,496 @@
+//! `COPY ... TO` files and object stores.
+//!
+//! Postgres' `COPY` writes the result of a table or query to a file of the
+//! server. Here the file is any URL of a registered object store, and the
+//! result is written by DataFusion in the formats it has writers for:
+//!
+//! ```sql
+//! COPY (SELECT * FROM events WHERE day = '2024-06-01')
+//!     TO 's3://lake/exports/events.parquet' (FORMAT parquet);
+//! COPY events TO '/tmp/events.csv' (FORMAT csv, HEADER, DELIMITER ';');
+//! ```
+//!
+//! Without `FORMAT`, the format is told by the extension of the file. The
+//! `text` and `binary` formats of postgres aren't supported.
+//!
+//! Like postgres, writing files takes superuser or the privileges of the
+//! `pg_write_server_files` role. URLs must be of registered object stores,
+//! and local files are only written under the export root of the server.
+//!
+//! `PARTITIONED BY` writes a hive-style directory per value of the columns,
+//! which is how partitions are appended to the external tables partitioned
+//! by them:
+//!
+//! ```sql
+//! COPY (SELECT * FROM staging) TO 's3://lake/events/'
+//!     PARTITIONED BY (day) (FORMAT parquet);
+//! ```
+
+use std::ops::Range;
+use std::path::{Component, Path, PathBuf};
+
+use datafusion::execution::runtime_env::RuntimeEnv;
+use datafusion::sql::parser::{CopyToSource, CopyToStatement};
+use datafusion::sql::planner::IdentNormalizer;
+use datafusion::sql::sqlparser::ast::{
+    CopyLegacyCsvOption, CopyLegacyOption, CopyOption, CopySource, CopyTarget, Statement, Value,
+};
+use datafusion::sql::sqlparser::dialect::PostgreSqlDialect;
+use datafusion::sql::sqlparser::keywords::Keyword;
+use datafusion::sql::sqlparser::parser::{IsOptional, Parser, ParserError};
+use datafusion::sql::sqlparser::tokenizer::{Token, TokenWithSpan, Tokenizer};
+use pgwire::error::{ErrorInfo, PgWireError, PgWireResult};
+use url::Url;
+
+use crate::errors::syntax_error;
+
+fn copy_error(code: &str, message: String) -> PgWireError {
+    PgWireError::UserError(Box::new(ErrorInfo::new(
+        "ERROR".to_string(),
+        code.to_string(),
+        message,
+    )))
+}
+
+fn unsupported_option(option: impl std::fmt::Display) -> PgWireError {
+    copy_error(
+        "0A000", // feature_not_supported
+        format!("COPY option {option} is not supported"),
+    )
+}
+
+/// The DataFusion `COPY ... TO` of a `COPY ... TO '<url>'`, `None` for other
+/// statements
+pub(crate) fn copy_to_statement(statement: &Statement) -> Option<PgWireResult<CopyToStatement>> {
+    let Statement::Copy {
+        source,
+        to: true,
+        target: CopyTarget::File { filename },
+        options,
+        legacy_options,
+        ..
+    } = statement
+    else {
+        return None;
+    };
+    Some(convert(source, filename, options, legacy_options))
+}
+
+/// Check that `target` is a URL of a registered object store, or a file
+/// under `export_root`, so `COPY ... TO` can't overwrite other files of the
+/// server
+pub(crate) fn check_copy_target(
+    target: &str,
+    export_root: Option<&Path>,
+    runtime_env: &RuntimeEnv,
+) -> PgWireResult<()> {
+    check_server_location(target, export_root, runtime_env, "COPY to", "export root")
+}
+
+/// Check that `location` is a URL of a registered object store, or an
+/// absolute path under `root`, once symlinks are resolved
+///
+/// `operation` and `root_name` name what the location is for in the errors,
+/// like `COPY to` and `export root`.
+pub(crate) fn check_server_location(
+    location: &str,
+    root: Option<&Path>,
+    runtime_env: &RuntimeEnv,
+    operation: &str,
+    root_name: &str,
+) -> PgWireResult<()> {
+    let path = match Url::parse(location) {
+        // one letter schemes are the drives of windows paths
+        Ok(url) if url.scheme() != "file" && url.scheme().len() > 1 => {
+            return runtime_env
+                .object_store_registry
+                .get_store(&url)
+                .map(|_| ())
+                .map_err(|_| {
+                    copy_error(
+                        "42501", // insufficient_privilege
+                        format!("no object store is registered for {operation} \"{location}\""),
+                    )
+                });
+        }
+        Ok(url) if url.scheme() == "file" => url.to_file_path().map_err(|_| {
+            copy_error(
+                "42602", // invalid_name
+                format!("invalid file URL \"{location}\""),
+            )
+        })?,
+        _ => PathBuf::from(location),
+    };
+    let Some(root) = root else {
+        return Err(copy_error(
+            "42501", // insufficient_privilege
+            format!("{operation} file \"{location}\" is not allowed without an {root_name}"),
+        ));
+    };
+    if !path.is_absolute() {
+        return Err(copy_error(
+            "42602", // invalid_name
+            format!("relative path not allowed for {operation} file"),
+        ));
+    }
+    if path
+        .components()
+        .any(|component| component == Component::ParentDir)
+        || !resolve_symlinks(&path, SYMLINKS_MAX)
+            .zip(resolve_symlinks(root, SYMLINKS_MAX))
+            .is_some_and(|(path, root)| path.starts_with(root))
+    {
+        return Err(copy_error(
+            "42501", // insufficient_privilege
+            format!(
+                "{operation} file \"{location}\" outside of the {root_name} \"{}\"",
+                root.display()
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Links followed resolving a path, like `MAXSYMLINKS` of linux
+const SYMLINKS_MAX: usize = 40;
+
+/// `path` with the symlinks of its longest existing ancestor resolved, so a
+/// link under a root can't point outside of it
+///
+/// Links which don't resolve are followed too, as writing to them creates
+/// their target. `None` for paths of more than `links` links.
+fn resolve_symlinks(path: &Path, links: usize) -> Option<PathBuf> {
+    for ancestor in path.ancestors() {
+        let rest = path.strip_prefix(ancestor).ok()?;
+        // joining an empty path would add a trailing separator
+        let join = |path: PathBuf| {
+            if rest.as_os_str().is_empty() {
+                path
+            } else {
+                path.join(rest)
+            }
+        };
+        if let Ok(resolved) = ancestor.canonicalize() {
+            return Some(join(resolved));
+        }
+        if let Ok(target) = std::fs::read_link(ancestor) {
+            let target = ancestor.parent().unwrap_or(ancestor).join(target);
+            return resolve_symlinks(&join(target), links.checked_sub(1)?);
+        }
+    }
+    Some(path.to_path_buf())
+}
+
+/// The `COPY` statement of `sql` without its `PARTITIONED BY (columns)`
+/// clause, which sqlparser doesn't parse, and the partition columns
+///
+/// `None` for statements without the clause.
+pub(crate) fn partitioned_copy(sql: &str) -> Option<PgWireResult<(Statement, Vec<String>)>> {
+    let sql_lower = sql.to_lowercase();
+    if !sql_lower.trim_start().starts_with("copy") || !sql_lower.contains("partition") {
+        return None;
+    }
+    let dialect = PostgreSqlDialect {};
+    let mut tokens = Tokenizer::new(&dialect, sql)
+        .tokenize_with_location()
+        .ok()?;
+    tokens.retain(|token| !matches!(token.token, Token::Whitespace(_)));
+    let clause = partition_clause(&tokens)?;
+    Some(parse_partitioned_copy(tokens, clause).map_err(|e| syntax_error(e, sql)))
+}
+
+/// The tokens of `PARTITION[ED] BY (...)` out of parentheses, which are the
+/// ones of the query, column list and options
+fn partition_clause(tokens: &[TokenWithSpan]) -> Option<Range<usize>> {
+    let mut depth = 0usize;
+    for (i, token) in tokens.iter().enumerate() {
+        match &token.token {
+            Token::LParen => depth += 1,
+            Token::RParen => depth = depth.saturating_sub(1),
+            Token::Word(word)
+                if depth == 0
+                    && matches!(word.keyword, Keyword::PARTITION | Keyword::PARTITIONED) =>
+            {
+                let is_by = matches!(
+                    tokens.get(i + 1).map(|token| &token.token),
+                    Some(Token::Word(word)) if word.keyword == Keyword::BY
+                );
+                if !is_by || tokens.get(i + 2).map(|token| &token.token) != Some(&Token::LParen) {
+                    return None;
+                }
+                let mut columns_depth = 0usize;
+                for (j, token) in tokens.iter().enumerate().skip(i + 2) {
+                    match token.token {
+                        Token::LParen => columns_depth += 1,
+                        Token::RParen if columns_depth == 1 => return Some(i..j + 1),
+                        Token::RParen => columns_depth -= 1,
+                        _ => {}
+                    }
+                }
+                return None;
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+fn parse_partitioned_copy(
+    mut tokens: Vec<TokenWithSpan>,
+    clause: Range<usize>,
+) -> Result<(Statement, Vec<String>), ParserError> {
+    let dialect = PostgreSqlDialect {};
+    let columns = tokens.drain(clause).skip(2).collect();
+    let columns = Parser::new(&dialect)
+        .with_tokens_with_locations(columns)
+        .parse_parenthesized_column_list(IsOptional::Mandatory, false)?;
+    let mut parser = Parser::new(&dialect).with_tokens_with_locations(tokens);
+    let statement = parser.parse_statement()?;
+    let _ = parser.consume_token(&Token::SemiColon);
+    parser.expect_token(&Token::EOF)?;
+    let normalizer = IdentNormalizer::default();
+    let columns = columns
+        .into_iter()
+        .map(|column| normalizer.normalize(column))
+        .collect();
+    Ok((statement, columns))
+}
+
+fn convert(
+    source: &CopySource,
+    filename: &str,
+    options: &[CopyOption],
+    legacy_options: &[CopyLegacyOption],
+) -> PgWireResult<CopyToStatement> {
+    let mut format = None;
+    let mut header = false;
+    let mut format_options = Vec::new();
+    let mut set = |key: &str, value: String| {
+        format_options.push((format!("format.{key}"), Value::SingleQuotedString(value)));
+    };
+    for option in options {
+        match option {
+            CopyOption::Format(name) => format = Some(name.value.to_lowercase()),
+            CopyOption::Header(value) => header = *value,
+            CopyOption::Delimiter(c) => set("delimiter", c.to_string()),
+            CopyOption::Null(null) => set("null_value", null.clone()),
+            CopyOption::Quote(c) => set("quote", c.to_string()),
+            CopyOption::Escape(c) => set("escape", c.to_string()),
+            option => return Err(unsupported_option(option)),
+        }
+    }
+    for option in legacy_options {
+        match option {
+            CopyLegacyOption::Binary => format = Some("binary".to_string()),
+            CopyLegacyOption::Delimiter(c) => set("delimiter", c.to_string()),
+            CopyLegacyOption::Null(null) => set("null_value", null.clone()),
+            CopyLegacyOption::Csv(csv_options) => {
+                format = Some("csv".to_string());
+                for option in csv_options {
+                    match option {
+                        CopyLegacyCsvOption::Header => header = true,
+                        CopyLegacyCsvOption::Quote(c) => set("quote", c.to_string()),
+                        CopyLegacyCsvOption::Escape(c) => set("escape", c.to_string()),
+                        option => return Err(unsupported_option(option)),
+                    }
+                }
+            }
+        }
+    }
+
+    match format.as_deref() {
+        // without header unless asked for, like postgres
+        Some("csv") => set("has_header", header.to_string()),
+        Some("parquet" | "json") | None => {}
+        Some(format) => {
+            return Err(copy_error(
+                "0A000", // feature_not_supported
+                format!(
+                    "COPY TO a file in format {format} is not supported, use csv, parquet or json"
+                ),
+            ));
+        }
+    }
+
+    let source = match source {
+        CopySource::Table {
+            table_name,
+            columns,
+        } if columns.is_empty() => CopyToSource::Relation(table_name.clone()),
+        CopySource::Table {
+            table_name,
+            columns,
+        } => {
+            let columns = columns
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(", ");
+            let sql = format!("SELECT {columns} FROM {table_name}");
+            let query = Parser::new(&PostgreSqlDialect {})
+                .try_with_sql(&sql)
+                .and_then(|mut parser| parser.parse_query())
+                .map_err(|e| PgWireError::ApiError(Box::new(e)))?;
+            CopyToSource::Query(query)
+        }
+        CopySource::Query(query) => CopyToSource::Query(query.clone()),
+    };
+    Ok(CopyToStatement {
+        source,
+        target: filename.to_string(),
+        partitioned_by: Vec::new(),
+        stored_as: format.map(|format| format.to_uppercase()),
+        options: format_options,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::sql::parse;
+
+    fn copy_to(sql: &str) -> PgWireResult<CopyToStatement> {
+        copy_to_statement(&parse(sql).unwrap()[0]).unwrap()
+    }
+
+    fn code(sql: &str) -> String {
+        let Err(PgWireError::UserError(info)) = copy_to(sql) else {
+            panic!("expected an error for {sql}");
+        };
+        info.code
+    }
+
+    #[test]
+    fn test_copy_to_statement() {
+        assert!(copy_to_statement(&parse("COPY t TO STDOUT").unwrap()[0]).is_none());
+        assert!(copy_to_statement(&parse("SELECT 1").unwrap()[0]).is_none());
+
+        let statement =
+            copy_to("COPY events TO 's3://lake/events.parquet' (FORMAT parquet)").unwrap();
+        assert_eq!(
+            statement.to_string(),
+            "COPY events TO s3://lake/events.parquet STORED AS PARQUET"
+        );
+        let statement =
+            copy_to("COPY events (id, kind) TO '/tmp/events.csv' (FORMAT csv, DELIMITER ';')")
+                .unwrap();
+        assert_eq!(
+            statement.to_string(),
+            "COPY (SELECT id, kind FROM events) TO /tmp/events.csv STORED AS CSV \
+             OPTIONS ('format.delimiter' ';', 'format.has_header' 'false')"
+        );
+        let statement = copy_to("COPY (SELECT 1) TO '/tmp/one.csv' WITH CSV HEADER").unwrap();
+        assert_eq!(
+            statement.options,
+            [(
+                "format.has_header".to_string(),
+                Value::SingleQuotedString("true".to_string())
+            )]
+        );
+
+        assert_eq!(
+            code("COPY events TO '/tmp/events' (FORMAT binary)"),
+            "0A000"
+        );
+        assert_eq!(code("COPY events TO '/tmp/events' (FORMAT text)"), "0A000");
+        assert_eq!(code("COPY events TO '/tmp/events.csv' (FREEZE)"), "0A000");
+    }
+
+    #[test]
+    fn test_partitioned_copy() {
+        assert!(partitioned_copy("COPY events TO '/tmp/events.csv'").is_none());
+        assert!(partitioned_copy(
+            "COPY (SELECT rank() OVER (PARTITION BY day) FROM events) TO '/tmp/ranks.csv'"
+        )
+        .is_none());
+
+        let (statement, columns) = partitioned_copy(
+            "COPY (SELECT * FROM events) TO 'memory:///events/' \
+             PARTITIONED BY (Day, \"Kind\") (FORMAT json);",
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(columns, ["day", "Kind"]);
+        assert_eq!(
+            statement.to_string(),
+            "COPY (SELECT * FROM events) TO 'memory:///events/' (FORMAT json)"
+        );
+        let (statement, columns) =
+            partitioned_copy("copy events partition by (day) to '/tmp/events/'")
+                .unwrap()
+                .unwrap();
+        assert_eq!(columns, ["day"]);
+        assert_eq!(statement.to_string(), "COPY events TO '/tmp/events/'");
+
+        let Err(PgWireError::UserError(info)) =
+            partitioned_copy("COPY events TO '/tmp/events/' PARTITIONED BY (day) WHERE").unwrap()
+        else {
+            panic!("expected a syntax error");
+        };
+        assert_eq!(info.code, "42601");
+    }
+
+    #[test]
+    fn test_check_copy_target() {
+        let runtime_env = RuntimeEnv::default();
+        runtime_env.register_object_store(
+            &Url::parse("memory://").unwrap(),
+            Arc::new(object_store::memory::InMemory::new()),
+        );
+        let code = |target: &str, export_root: Option<&str>| match check_copy_target(
+            target,
+            export_root.map(Path::new),
+            &runtime_env,
+        ) {
+            Ok(()) => None,
+            Err(PgWireError::UserError(info)) => Some(info.code),
+            Err(e) => panic!("unexpected error {e}"),
+        };
+
+        assert_eq!(code("memory:///exports/events.csv", None), None);
+        assert_eq!(code("s3://lake/events.csv", None).unwrap(), "42501");
+        assert_eq!(code("/tmp/events.csv", None).unwrap(), "42501");
+        assert_eq!(code("/exports/events.csv", Some("/exports")), None);
+        assert_eq!(code("file:///exports/events.csv", Some("/exports")), None);
+        assert_eq!(
+            code("/etc/credentials.conf", Some("/exports")).unwrap(),
+            "42501"
+        );
+        assert_eq!(
+            code("/exports/../etc/credentials.conf", Some("/exports")).unwrap(),
+            "42501"
+        );
+        assert_eq!(
+            code("exports/events.csv", Some("/exports")).unwrap(),
+            "42602"
+        );
+
+        // links under the root are followed
+        #[cfg(unix)]
+        {
+            let export_root = std::env::temp_dir().join(format!(
+                "datafusion-postgres-copy-links-{}",
+                std::process::id()
+            ));
+            std::fs::create_dir_all(export_root.join("reports")).unwrap();
+            let link = |target: &str, name: &str| {
+                let _ = std::fs::remove_file(export_root.join(name));
+                std::os::unix::fs::symlink(target, export_root.join(name)).unwrap();
+            };
+            link("/etc", "etc");
+            link("/etc/credentials.conf", "credentials.conf");
+            link("loop", "loop");
+            link(export_root.join("reports").to_str().unwrap(), "latest");
+            let file = |name: &str| export_root.join(name).to_str().unwrap().to_string();
+            let root = export_root.to_str();
+            assert_eq!(code(&file("reports/events.csv"), root), None);
+            assert_eq!(code(&file("latest/events.csv"), root), None);
+            assert_eq!(code(&file("etc/credentials.conf"), root).unwrap(), "42501");
+            assert_eq!(code(&file("credentials.conf"), root).unwrap(), "42501");
+            assert_eq!(code(&file("loop"), root).unwrap(), "42501");
+            std::fs::remove_dir_all(&export_root).unwrap();
+        }
+    }
+}
@@ -0,0 +1,186 @@
+//! `CREATE DATABASE` and `DROP DATABASE`.
+//!
+//! Databases are the catalogs of the `SessionContext`. `CREATE DATABASE`
+//! registers an in-memory catalog with an empty default schema, listed in
+//! `pg_database` right away, and clients connect to it by naming it as the
+//! database of their startup message. `DROP DATABASE` deregisters catalogs
+//! without tables, as long as no other session is connected to them.
+
+use std::sync::Arc;
+
+use datafusion::catalog::{
+    CatalogProvider, MemoryCatalogProvider, MemoryCatalogProviderList, MemorySchemaProvider,
+};
+use datafusion::prelude::SessionContext;
+use pgwire::error::{ErrorInfo, PgWireError, PgWireResult};
+
+use crate::pg_catalog::setup_created_catalog;
+
+/// Schemas every database has, which don't count as its content
+const SYSTEM_SCHEMAS: [&str; 2] = ["pg_catalog", "information_schema"];
+
+fn database_error(code: &str, message: String) -> PgWireError {
+    PgWireError::UserError(Box::new(ErrorInfo::new(
+        "ERROR".to_string(),
+        code.to_string(),
+        message,
+    )))
+}
+
+/// Register the catalog of the database `name`
+pub(crate) fn create_database(
+    session_context: &SessionContext,
+    name: &str,
+    if_not_exists: bool,
+) -> PgWireResult<()> {
+    if session_context.catalog(name).is_some() {
+        if if_not_exists {
+            return Ok(());
+        }
+        return Err(database_error(
+            "42P04", // duplicate_database
+            format!("database \"{name}\" already exists"),
+        ));
+    }
+
+    let default_schema = session_context
+        .state()
+        .config_options()
+        .catalog
+        .default_schema
+        .clone();
+    let catalog = MemoryCatalogProvider::new();
+    catalog
+        .register_schema(&default_schema, Arc::new(MemorySchemaProvider::new()))
+        .map_err(|e| PgWireError::ApiError(Box::new(e)))?;
+    session_context.register_catalog(name, Arc::new(catalog));
+    // catalog lists of tenants only register the catalogs of the tenant
+    if session_context.catalog(name).is_none() {
+        return Err(database_error(
+            "42501", // insufficient_privilege
+            "permission denied to create database".to_string(),
+        ));
+    }
+    setup_created_catalog(session_context, name).map_err(|e| PgWireError::ApiError(Box::new(e)))
+}
+
+/// Deregister the catalog of the database `name`, if it has no tables
+///
+/// Catalogs can only be deregistered from the default catalog list of
+/// DataFusion.
+pub(crate) fn drop_database(
+    session_context: &SessionContext,
+    name: &str,
+    if_exists: bool,
+) -> PgWireResult<()> {
+    let Some(catalog) = session_context.catalog(name) else {
+        if if_exists {
+            return Ok(());
+        }
+        return Err(database_error(
+            "3D000", // invalid_catalog_name
+            format!("database \"{name}\" does not exist"),
+        ));
+    };
+    let state = session_context.state();
+    if state.config_options().catalog.default_catalog == name {
+        return Err(database_error(
+            "55006", // object_in_use
+            "cannot drop the currently open database".to_string(),
+        ));
+    }
+    let has_tables = catalog
+        .schema_names()
+        .into_iter()
+        .filter(|schema| !SYSTEM_SCHEMAS.contains(&schema.as_str()))
+        .filter_map(|schema| catalog.schema(&schema))
+        .any(|schema| !schema.table_names().is_empty());
+    if has_tables {
+        return Err(database_error(
+            "2BP01", // dependent_objects_still_exist
+            format!("database \"{name}\" is not empty"),
+        ));
+    }
+
+    let Some(catalogs) = state
+        .catalog_list()
+        .as_any()
+        .downcast_ref::<MemoryCatalogProviderList>()
+    else {
+        return Err(database_error(
+            "0A000", // feature_not_supported
+            "dropping databases of a custom catalog list is not supported".to_string(),
+        ));
+    };
+    catalogs.catalogs.remove(name);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pg_catalog::setup_pg_catalog;
+
+    fn code(error: PgWireError) -> String {
+        let PgWireError::UserError(info) = error else {
+            panic!("expected a user error");
+        };
+        info.code
+    }
+
+    #[tokio::test]
+    async fn test_create_and_drop_database() {
+        let ctx = SessionContext::new();
+        setup_pg_catalog(&ctx, "datafusion").unwrap();
+
+        create_database(&ctx, "analytics", false).unwrap();
+        assert_eq!(
+            code(create_database(&ctx, "analytics", false).unwrap_err()),
+            "42P04"
+        );
+        create_database(&ctx, "analytics", true).unwrap();
+        let catalog = ctx.catalog("analytics").unwrap();
+        let mut schemas = catalog.schema_names();
+        schemas.sort();
+        assert_eq!(schemas, ["information_schema", "pg_catalog", "public"]);
+
+        let batches = ctx
+            .sql("SELECT datname FROM pg_catalog.pg_database WHERE datname = 'analytics'")
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+        assert_eq!(batches[0].num_rows(), 1);
+        let batches = ctx
+            .sql("SELECT count(*) FROM analytics.information_schema.tables")
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+        assert_eq!(batches[0].num_rows(), 1);
+
+        // databases with tables and the current database can't be dropped
+        ctx.sql("CREATE TABLE analytics.public.t AS SELECT 1 AS a")
+            .await
+            .unwrap();
+        assert_eq!(
+            code(drop_database(&ctx, "analytics", false).unwrap_err()),
+            "2BP01"
+        );
+        ctx.sql("DROP TABLE analytics.public.t").await.unwrap();
+        assert_eq!(
+            code(drop_database(&ctx, "datafusion", false).unwrap_err()),
+            "55006"
+        );
+
+        drop_database(&ctx, "analytics", false).unwrap();
+        assert!(ctx.catalog("analytics").is_none());
+        assert_eq!(
+            code(drop_database(&ctx, "analytics", false).unwrap_err()),
+            "3D000"
+        );
+        drop_database(&ctx, "analytics", true).unwrap();
+    }
+}
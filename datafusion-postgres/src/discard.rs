@@ -0,0 +1,91 @@
+//! `DISCARD`, resetting the state of a session.
+//!
+//! Connection poolers in session mode, like PgBouncer with its default
+//! `server_reset_query`, send `DISCARD ALL` before handing a connection to the
+//! next client. It resets the settings of the session, stops listening on all
+//! channels, drops the cached plans of the session and closes its prepared
+//! statements and portals. Sessions have no temporary tables or sequences, so
+//! `DISCARD TEMP` and `DISCARD SEQUENCES` have nothing to drop.
+
+use std::collections::{BTreeSet, HashMap};
+use std::net::SocketAddr;
+use std::sync::Mutex;
+
+/// The names of the prepared statements and portals of sessions
+///
+/// The portal store of pgwire can't list them, so they are recorded as
+/// clients parse, bind and close them, for `DISCARD ALL` to close them all.
+#[derive(Debug, Default)]
+pub(crate) struct PreparedStatements {
+    sessions: Mutex<HashMap<SocketAddr, Prepared>>,
+}
+
+#[derive(Debug, Default)]
+struct Prepared {
+    statements: BTreeSet<String>,
+    portals: BTreeSet<String>,
+    /// Whether a `DISCARD ALL` is waiting for them to be closed
+    discarded: bool,
+}
+
+/// Prepared statements and portals to close from the portal store
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct Discarded {
+    pub(crate) statements: Vec<String>,
+    pub(crate) portals: Vec<String>,
+}
+
+impl PreparedStatements {
+    fn with_session<T>(&self, session: SocketAddr, f: impl FnOnce(&mut Prepared) -> T) -> T {
+        let mut sessions = self.sessions.lock().unwrap_or_else(|e| e.into_inner());
+        f(sessions.entry(session).or_default())
+    }
+
+    pub(crate) fn parsed(&self, session: SocketAddr, name: &str) {
+        self.with_session(session, |prepared| {
+            prepared.statements.insert(name.to_string())
+        });
+    }
+
+    pub(crate) fn bound(&self, session: SocketAddr, name: &str) {
+        self.with_session(session, |prepared| {
+            prepared.portals.insert(name.to_string())
+        });
+    }
+
+    pub(crate) fn closed_statement(&self, session: SocketAddr, name: &str) {
+        self.with_session(session, |prepared| prepared.statements.remove(name));
+    }
+
+    pub(crate) fn closed_portal(&self, session: SocketAddr, name: &str) {
+        self.with_session(session, |prepared| prepared.portals.remove(name));
+    }
+
+    /// Mark all prepared statements and portals of `session` to be closed
+    pub(crate) fn discard_all(&self, session: SocketAddr) {
+        self.with_session(session, |prepared| prepared.discarded = true);
+    }
+
+    /// The prepared statements and portals of `session` to close, if a
+    /// `DISCARD ALL` ran in it since the last call
+    pub(crate) fn take_discarded(&self, session: SocketAddr) -> Option<Discarded> {
+        self.with_session(session, |prepared| {
+            if !std::mem::take(&mut prepared.discarded) {
+                return None;
+            }
+            Some(Discarded {
+                statements: std::mem::take(&mut prepared.statements)
+                    .into_iter()
+                    .collect(),
+                portals: std::mem::take(&mut prepared.portals).into_iter().collect(),
+            })
+        })
+    }
+
+    pub(crate) fn end_session(&self, session: &SocketAddr) {
+        self.sessions
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(session);
+    }
+}
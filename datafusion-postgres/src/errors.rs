@@ -0,0 +1,284 @@
+//! SQLSTATE codes of the errors sent to clients
+//!
+//! Errors of DataFusion and of the SQL parser reach pgwire as `ApiError`,
+//! which it sends with the code XX000 (internal_error). ORMs and drivers
+//! branch on the code, e.g. to create a missing table, so
+//! [`into_sqlstate_error`] assigns the code postgres sends for the same
+//! failure. The notes and helps of DataFusion diagnostics become the detail
+//! and the hint of the error, and syntax errors carry the position of the
+//! offending token.
+
+use datafusion::arrow::error::ArrowError;
+use datafusion::common::SchemaError;
+use datafusion::error::DataFusionError;
+use datafusion::sql::sqlparser::parser::ParserError;
+use pgwire::error::{ErrorInfo, PgWireError};
+
+/// The error with the SQLSTATE of its cause
+///
+/// Errors of the handlers, which already have a code, are returned unchanged.
+/// [`crate::DfErrorHandler`] translates the errors sent to clients, so only
+/// servers with their own `ErrorHandler` call it.
+pub fn into_sqlstate_error(error: PgWireError) -> PgWireError {
+    let PgWireError::ApiError(source) = &error else {
+        return error;
+    };
+    let info = if let Some(error) = source.downcast_ref::<DataFusionError>() {
+        datafusion_error_info(error)
+    } else if let Some(error) = source.downcast_ref::<ParserError>() {
+        error_info("42601", parser_message(error)) // syntax_error
+    } else {
+        return error;
+    };
+    PgWireError::UserError(Box::new(info))
+}
+
+fn error_info(code: &str, message: String) -> ErrorInfo {
+    ErrorInfo::new("ERROR".to_string(), code.to_string(), message)
+}
+
+fn datafusion_error_info(error: &DataFusionError) -> ErrorInfo {
+    // the first of the errors DataFusion collected while planning
+    let error = error.iter().next().unwrap_or(error);
+    let root = error.find_root();
+    // errors of functions with the code postgres sends, like deadlocks
+    if let DataFusionError::External(source) = root {
+        if let Some(PgWireError::UserError(info)) = source.downcast_ref::<PgWireError>() {
+            return error_info(&info.code, info.message.clone());
+        }
+    }
+    let (code, message) = match root {
+        // syntax_error
+        DataFusionError::SQL(error, _) => ("42601", parser_message(error)),
+        DataFusionError::SchemaError(error, _) => {
+            let code = match error.as_ref() {
+                // undefined_column
+                SchemaError::FieldNotFound { .. } => "42703",
+                // ambiguous_column
+                SchemaError::AmbiguousReference { .. } => "42702",
+                // duplicate_column
+                SchemaError::DuplicateQualifiedField { .. }
+                | SchemaError::DuplicateUnqualifiedField { .. } => "42701",
+            };
+            (code, error.to_string())
+        }
+        DataFusionError::ArrowError(error, _) => {
+            let code = match error.as_ref() {
+                // division_by_zero
+                ArrowError::DivideByZero => "22012",
+                // numeric_value_out_of_range
+                ArrowError::ArithmeticOverflow(_) => "22003",
+                // invalid_text_representation
+                ArrowError::CastError(_) | ArrowError::ParseError(_) => "22P02",
+                // out_of_memory
+                ArrowError::MemoryError(_) => "53200",
+                _ => "XX000",
+            };
+            (code, root.message().to_string())
+        }
+        DataFusionError::Plan(message) => (plan_sqlstate(message), message.clone()),
+        // feature_not_supported
+        DataFusionError::NotImplemented(message) => ("0A000", message.clone()),
+        // invalid_parameter_value
+        DataFusionError::Configuration(message) => ("22023", message.clone()),
+        // out_of_memory
+        DataFusionError::ResourcesExhausted(message) => {
+            ("53200", format!("out of memory: {message}"))
+        }
+        // query_canceled
+        DataFusionError::ExecutionJoin(error) if error.is_cancelled() => (
+            "57014",
+            "canceling statement due to user request".to_string(),
+        ),
+        // internal_error
+        _ => ("XX000", root.message().to_string()),
+    };
+
+    let mut info = error_info(code, message);
+    if let Some(diagnostic) = error.diagnostic() {
+        let join = |messages: Vec<&str>| (!messages.is_empty()).then(|| messages.join("\n"));
+        let notes = diagnostic.notes.iter().map(|note| note.message.as_str());
+        let helps = diagnostic.helps.iter().map(|help| help.message.as_str());
+        info.detail = join(notes.collect());
+        info.hint = join(helps.collect());
+    }
+    info
+}
+
+/// The SQLSTATE of a planning error, told apart by its message as DataFusion
+/// has no variants for them
+fn plan_sqlstate(message: &str) -> &'static str {
+    if message.starts_with("table '") && message.ends_with("' not found") {
+        "42P01" // undefined_table
+    } else if message.starts_with("Invalid function")
+        || message.starts_with("operator does not exist")
+        || (message.starts_with("table function '") && message.ends_with("' not found"))
+    {
+        "42883" // undefined_function
+    } else {
+        "42000" // syntax_error_or_access_rule_violation
+    }
+}
+
+/// The syntax error of parsing `query`, with the position of the offending
+/// token for clients to point at it
+///
+/// The position is the 1-based byte offset into `query` of the location the
+/// parser reports, or the end of `query` when it ran out of input.
+pub(crate) fn syntax_error(error: ParserError, query: &str) -> PgWireError {
+    let message = parser_message(&error);
+    let (message, position) = match message.rsplit_once(" at Line: ") {
+        Some((text, location)) => match location_offset(query, location) {
+            Some(offset) => (text.to_string(), Some(offset + 1)),
+            None => (message, None),
+        },
+        None if message.ends_with("found: EOF") => (message, Some(query.len() + 1)),
+        None => (message, None),
+    };
+    // feature_not_supported, for the constructs refused by crate::sql::parse
+    let code = if message.ends_with("is not supported") {
+        "0A000"
+    } else {
+        "42601" // syntax_error
+    };
+    let mut info = error_info(code, message);
+    info.position = position.map(|position| position.to_string());
+    PgWireError::UserError(Box::new(info))
+}
+
+/// The byte offset into `query` of a `1, Column: 8` location of sqlparser,
+/// whose lines and columns count characters from 1
+fn location_offset(query: &str, location: &str) -> Option<usize> {
+    let (line, column) = location.split_once(", Column: ")?;
+    let (line, column) = (line.parse::<usize>().ok()?, column.parse::<usize>().ok()?);
+    let line_start = query
+        .split_inclusive('\n')
+        .take(line.checked_sub(1)?)
+        .map(str::len)
+        .sum::<usize>();
+    let column_offset = query[line_start..]
+        .char_indices()
+        .nth(column.checked_sub(1)?)
+        .map_or(query.len() - line_start, |(offset, _)| offset);
+    Some(line_start + column_offset)
+}
+
+/// The message of a parser error, without the `sql parser error: ` prefix
+fn parser_message(error: &ParserError) -> String {
+    match error {
+        ParserError::ParserError(message) | ParserError::TokenizerError(message) => message.clone(),
+        ParserError::RecursionLimitExceeded => "statement is too deeply nested".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datafusion::prelude::SessionContext;
+
+    async fn sql_error(sql: &str) -> ErrorInfo {
+        let ctx = SessionContext::new();
+        ctx.sql("CREATE TABLE users (id INT, name TEXT) AS VALUES (1, 'a')")
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+        let error = match ctx.sql(sql).await {
+            Ok(df) => df.collect().await.unwrap_err(),
+            Err(error) => error,
+        };
+        match into_sqlstate_error(PgWireError::ApiError(Box::new(error))) {
+            PgWireError::UserError(info) => *info,
+            error => panic!("untranslated error {error}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sqlstates() {
+        assert_eq!(sql_error("SELECT * FROM missing").await.code, "42P01");
+        assert_eq!(sql_error("SELECT email FROM users").await.code, "42703");
+        assert_eq!(sql_error("SELECT (1").await.code, "42601");
+        assert_eq!(sql_error("SELECT id / 0 FROM users").await.code, "22012");
+        assert_eq!(sql_error("SELECT missing_fn(1)").await.code, "42883");
+        assert_eq!(sql_error("SELECT 'abc'::int").await.code, "22P02");
+
+        let info =
+            sql_error("SELECT * FROM users u JOIN users v ON u.id = v.id WHERE id = 1").await;
+        assert_eq!(info.code, "42702");
+
+        let info = into_sqlstate_error(PgWireError::ApiError(Box::new(
+            DataFusionError::ResourcesExhausted("pool is full".to_string()),
+        )));
+        let PgWireError::UserError(info) = info else {
+            panic!("untranslated error");
+        };
+        assert_eq!(info.code, "53200");
+        assert_eq!(info.message, "out of memory: pool is full");
+
+        let info = into_sqlstate_error(PgWireError::ApiError(Box::new(ParserError::ParserError(
+            "Expected: an expression, found: EOF".to_string(),
+        ))));
+        let PgWireError::UserError(info) = info else {
+            panic!("untranslated error");
+        };
+        assert_eq!(info.code, "42601");
+        assert_eq!(info.message, "Expected: an expression, found: EOF");
+
+        let info = into_sqlstate_error(PgWireError::ApiError(Box::new(DataFusionError::External(
+            Box::new(PgWireError::UserError(Box::new(ErrorInfo::new(
+                "ERROR".to_string(),
+                "40P01".to_string(),
+                "deadlock detected".to_string(),
+            )))),
+        ))));
+        let PgWireError::UserError(info) = info else {
+            panic!("untranslated error");
+        };
+        assert_eq!(info.code, "40P01");
+        assert_eq!(info.message, "deadlock detected");
+    }
+
+    #[tokio::test]
+    async fn test_diagnostic_detail() {
+        let info = sql_error("SELECT nme FROM users").await;
+        assert_eq!(info.code, "42703");
+        assert_eq!(info.detail.as_deref(), Some("possible column users.name"));
+    }
+
+    fn parse_error(query: &str) -> (String, Option<String>) {
+        let error = crate::sql::parse(query).unwrap_err();
+        let PgWireError::UserError(info) = syntax_error(error, query) else {
+            panic!("untranslated error");
+        };
+        assert_eq!(info.code, "42601");
+        (info.message, info.position)
+    }
+
+    #[test]
+    fn test_syntax_error_position() {
+        let (message, position) = parse_error("SELECT * FORM users");
+        assert_eq!(message, "Expected: end of statement, found: FORM");
+        assert_eq!(position.as_deref(), Some("10"));
+
+        // lines and multi-byte characters before the token
+        let (_, position) = parse_error("SELECT 'é',\n  1 2");
+        assert_eq!(position.as_deref(), Some("18"));
+
+        // the end of the query when the parser ran out of input
+        let (_, position) = parse_error("SELECT * FROM");
+        assert_eq!(position.as_deref(), Some("14"));
+    }
+
+    #[test]
+    fn test_handler_errors_unchanged() {
+        let error = into_sqlstate_error(PgWireError::UserError(Box::new(error_info(
+            "42501",
+            "permission denied".to_string(),
+        ))));
+        let PgWireError::UserError(info) = error else {
+            panic!("changed error");
+        };
+        assert_eq!(info.code, "42501");
+    }
+}
@@ -0,0 +1,125 @@
+//! Callbacks on the lifecycle of connections.
+//!
+//! A [`ConnectionListener`] registered with
+//! `DfPostgresServerBuilder::connection_listener` or
+//! `DfSessionService::with_connection_listener` is told when connections open
+//! and close and when clients fail to authenticate, e.g. to keep the
+//! connection accounting of an embedding application.
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use pgwire::error::PgWireError;
+
+use crate::activity::SessionActivity;
+
+/// Connection an event is about
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectionEvent {
+    /// Address of the client, the client a proxy forwards for connections
+    /// with a PROXY protocol header
+    pub peer: SocketAddr,
+    /// Process id of the session in `pg_stat_activity`, `None` for sessions
+    /// not started with `DfSessionService::start_session`
+    pub session_id: Option<i32>,
+    /// User of the connection, `None` until the client sent its startup
+    /// message
+    pub user: Option<String>,
+}
+
+impl ConnectionEvent {
+    pub(crate) fn new(peer: SocketAddr, session: Option<SessionActivity>) -> Self {
+        ConnectionEvent {
+            peer: session.as_ref().map_or(peer, |session| session.client_addr),
+            session_id: session.as_ref().map(|session| session.pid),
+            user: session.and_then(|session| session.usename),
+        }
+    }
+}
+
+/// Callbacks on the lifecycle of connections
+///
+/// All callbacks default to doing nothing. They are called on the task of
+/// the connection, so they should return quickly.
+pub trait ConnectionListener: Send + Sync {
+    /// Called once a connection is accepted, before its startup
+    fn on_connect(&self, _event: &ConnectionEvent) {}
+
+    /// Called once a connection is closed, whether or not it authenticated
+    fn on_disconnect(&self, _event: &ConnectionEvent) {}
+
+    /// Called when a client fails to authenticate with `error`, before the
+    /// connection is closed
+    fn on_auth_failure(&self, _event: &ConnectionEvent, _error: &PgWireError) {}
+}
+
+/// The connection listeners of a session service, called in registration
+/// order
+#[derive(Clone, Default)]
+pub(crate) struct ConnectionListeners {
+    listeners: Vec<Arc<dyn ConnectionListener>>,
+}
+
+impl ConnectionListeners {
+    pub(crate) fn push(&mut self, listener: Arc<dyn ConnectionListener>) {
+        self.listeners.push(listener);
+    }
+
+    pub(crate) fn on_connect(&self, event: &ConnectionEvent) {
+        for listener in &self.listeners {
+            listener.on_connect(event);
+        }
+    }
+
+    pub(crate) fn on_disconnect(&self, event: &ConnectionEvent) {
+        for listener in &self.listeners {
+            listener.on_disconnect(event);
+        }
+    }
+
+    /// Call the listeners if `error` of the startup of a connection is an
+    /// authentication failure
+    pub(crate) fn on_startup_error(&self, event: &ConnectionEvent, error: &PgWireError) {
+        if !is_auth_failure(error) {
+            return;
+        }
+        for listener in &self.listeners {
+            listener.on_auth_failure(event, error);
+        }
+    }
+}
+
+/// Whether `error` is of SQLSTATE class 28, invalid authorization
+/// specification
+fn is_auth_failure(error: &PgWireError) -> bool {
+    match error {
+        PgWireError::InvalidPassword(_) | PgWireError::UserNameRequired => true,
+        PgWireError::UserError(info) => info.code.starts_with("28"),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pgwire::error::ErrorInfo;
+
+    use super::*;
+
+    #[test]
+    fn test_is_auth_failure() {
+        let error = |code: &str| {
+            PgWireError::UserError(Box::new(ErrorInfo::new(
+                "FATAL".to_string(),
+                code.to_string(),
+                "failed".to_string(),
+            )))
+        };
+        assert!(is_auth_failure(&error("28P01")));
+        assert!(is_auth_failure(&error("28000")));
+        assert!(!is_auth_failure(&error("53300")));
+        assert!(is_auth_failure(&PgWireError::InvalidPassword(
+            "alice".to_string()
+        )));
+        assert!(!is_auth_failure(&PgWireError::NotReadyForQuery));
+    }
+}
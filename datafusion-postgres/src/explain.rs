@@ -0,0 +1,684 @@
+//! `EXPLAIN` output in the tree format of postgres.
+//!
+//! DataFusion renders `EXPLAIN` as a two column table of plan types and
+//! plans, which client tools expecting postgres' single `QUERY PLAN` column
+//! can't parse. Explained statements are planned here instead, and their
+//! physical plan is rendered like postgres does:
+//!
+//! ```text
+//! ProjectionExec: expr=[id@0 as id]  (actual time=0.000..0.021 rows=3 loops=1)
+//!   ->  FilterExec: id@0 > 1  (actual time=0.000..0.102 rows=3 loops=1)
+//!         ->  DataSourceExec: partitions=1, partition_sizes=[1]  (rows=5)
+//! Planning Time: 0.412 ms
+//! Execution Time: 0.298 ms
+//! ```
+//!
+//! `FORMAT JSON` and `FORMAT YAML` render the same tree as a single document
+//! with the property names of postgres plans, so plan visualizers can read
+//! it.
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use datafusion::arrow::array::{RecordBatch, StringArray};
+use datafusion::arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use datafusion::common::stats::Precision;
+use datafusion::dataframe::DataFrame;
+use datafusion::error::Result;
+use datafusion::physical_plan::metrics::MetricsSet;
+use datafusion::physical_plan::{displayable, execute_stream, ExecutionPlan};
+use datafusion::sql::sqlparser::ast::{
+    AnalyzeFormat, Expr, Statement as SqlStatement, UtilityOption, Value,
+};
+use futures::TryStreamExt;
+use pgwire::error::{ErrorInfo, PgWireError, PgWireResult};
+
+/// Output format of `EXPLAIN`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) enum ExplainFormat {
+    #[default]
+    Text,
+    Json,
+    Yaml,
+}
+
+/// Options of an `EXPLAIN` statement
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct ExplainOptions {
+    pub(crate) analyze: bool,
+    pub(crate) verbose: bool,
+    pub(crate) format: ExplainFormat,
+}
+
+impl ExplainOptions {
+    /// Options and explained statement of an `EXPLAIN` statement, `None` for
+    /// other statements
+    pub(crate) fn from_statement(
+        statement: &SqlStatement,
+    ) -> Option<PgWireResult<(ExplainOptions, SqlStatement)>> {
+        let SqlStatement::Explain {
+            analyze,
+            verbose,
+            statement,
+            format,
+            options,
+            ..
+        } = statement
+        else {
+            return None;
+        };
+
+        let mut explain_options = ExplainOptions {
+            analyze: *analyze,
+            verbose: *verbose,
+            ..Default::default()
+        };
+        if let Some(format) = format {
+            if let Err(e) = explain_options.set_format(&format.to_string()) {
+                return Some(Err(e));
+            }
+        }
+        for option in options.iter().flatten() {
+            if let Err(e) = explain_options.set_option(option) {
+                return Some(Err(e));
+            }
+        }
+        Some(Ok((explain_options, statement.as_ref().clone())))
+    }
+
+    /// Options of `sql` if it is an `EXPLAIN` statement
+    pub(crate) fn from_sql(sql: &str) -> Option<PgWireResult<(ExplainOptions, SqlStatement)>> {
+        if !sql
+            .trim_start()
+            .get(..7)
+            .is_some_and(|keyword| keyword.eq_ignore_ascii_case("explain"))
+        {
+            return None;
+        }
+        let statement = crate::sql::parse(sql).ok()?.into_iter().next()?;
+        Self::from_statement(&statement)
+    }
+
+    fn set_option(&mut self, option: &UtilityOption) -> PgWireResult<()> {
+        let name = option.name.value.to_lowercase();
+        match name.as_str() {
+            "analyze" => self.analyze = bool_option(&name, option.arg.as_ref())?,
+            "verbose" => self.verbose = bool_option(&name, option.arg.as_ref())?,
+            "format" => {
+                let format = match &option.arg {
+                    Some(Expr::Identifier(ident)) => ident.value.clone(),
+                    Some(Expr::Value(value)) => match &value.value {
+                        Value::SingleQuotedString(s) => s.clone(),
+                        value => value.to_string(),
+                    },
+                    Some(arg) => arg.to_string(),
+                    None => return Err(invalid_option(&name, None)),
+                };
+                self.set_format(&format)?;
+            }
+            // postgres options without an equivalent, accepted so scripts
+            // written for postgres still run
+            "costs" | "settings" | "generic_plan" | "buffers" | "wal" | "timing" | "summary"
+            | "memory" | "serialize" => {
+                bool_option(&name, option.arg.as_ref())?;
+            }
+            _ => {
+                return Err(PgWireError::UserError(Box::new(ErrorInfo::new(
+                    "ERROR".to_string(),
+                    "42601".to_string(), // syntax_error
+                    format!("unrecognized EXPLAIN option \"{name}\""),
+                ))));
+            }
+        }
+        Ok(())
+    }
+
+    fn set_format(&mut self, format: &str) -> PgWireResult<()> {
+        self.format = if format.eq_ignore_ascii_case(&AnalyzeFormat::TEXT.to_string()) {
+            ExplainFormat::Text
+        } else if format.eq_ignore_ascii_case(&AnalyzeFormat::JSON.to_string()) {
+            ExplainFormat::Json
+        } else if format.eq_ignore_ascii_case("yaml") {
+            ExplainFormat::Yaml
+        } else {
+            return Err(PgWireError::UserError(Box::new(ErrorInfo::new(
+                "ERROR".to_string(),
+                "0A000".to_string(), // feature_not_supported
+                format!(
+                    "EXPLAIN format \"{}\" is not supported",
+                    format.to_lowercase()
+                ),
+            ))));
+        };
+        Ok(())
+    }
+}
+
+fn bool_option(name: &str, arg: Option<&Expr>) -> PgWireResult<bool> {
+    let Some(arg) = arg else {
+        return Ok(true);
+    };
+    let value = match arg {
+        Expr::Identifier(ident) => ident.value.to_lowercase(),
+        Expr::Value(value) => match &value.value {
+            Value::Boolean(b) => return Ok(*b),
+            Value::SingleQuotedString(s) => s.to_lowercase(),
+            value => value.to_string(),
+        },
+        arg => arg.to_string(),
+    };
+    match value.as_str() {
+        "true" | "on" | "1" => Ok(true),
+        "false" | "off" | "0" => Ok(false),
+        _ => Err(invalid_option(name, Some(&value))),
+    }
+}
+
+fn invalid_option(name: &str, value: Option<&str>) -> PgWireError {
+    let message = match value {
+        Some(value) => format!("{name} requires a Boolean value, got \"{value}\""),
+        None => format!("EXPLAIN option \"{name}\" requires a value"),
+    };
+    PgWireError::UserError(Box::new(ErrorInfo::new(
+        "ERROR".to_string(),
+        "22023".to_string(), // invalid_parameter_value
+        message,
+    )))
+}
+
+/// Schema of the result of `EXPLAIN`
+pub(crate) fn explain_schema() -> SchemaRef {
+    Arc::new(Schema::new(vec![Field::new(
+        "QUERY PLAN",
+        DataType::Utf8,
+        false,
+    )]))
+}
+
+/// Plan, and with `ANALYZE` run, the query of `df` and render its plan as a
+/// single column batch of lines, or a single document in the JSON and YAML
+/// formats
+///
+/// Like postgres, `VERBOSE` shows the `query_id` of the explained statement.
+pub(crate) async fn explain(
+    df: DataFrame,
+    options: &ExplainOptions,
+    query_id: i64,
+) -> Result<RecordBatch> {
+    let planning_start = Instant::now();
+    let (state, plan) = df.into_parts();
+    let physical_plan = state.create_physical_plan(&plan).await?;
+    let planning_time = planning_start.elapsed();
+
+    let execution_time = if options.analyze {
+        let execution_start = Instant::now();
+        // rows are discarded, only the metrics of the run are shown
+        execute_stream(physical_plan.clone(), state.task_ctx())?
+            .try_for_each(|_| futures::future::ready(Ok(())))
+            .await?;
+        Some(execution_start.elapsed())
+    } else {
+        None
+    };
+
+    let lines = match options.format {
+        ExplainFormat::Text => {
+            let mut lines = Vec::new();
+            render_node(physical_plan.as_ref(), 0, options, &mut lines);
+            if options.verbose {
+                lines.push(format!("Query Identifier: {query_id}"));
+            }
+            if let Some(execution_time) = execution_time {
+                lines.push(format!("Planning Time: {} ms", millis(planning_time)));
+                lines.push(format!("Execution Time: {} ms", millis(execution_time)));
+            }
+            lines
+        }
+        ExplainFormat::Json | ExplainFormat::Yaml => {
+            let mut document = vec![(
+                "Plan".to_string(),
+                Property::Object(node_properties(physical_plan.as_ref(), options)),
+            )];
+            if options.verbose {
+                document.push((
+                    "Query Identifier".to_string(),
+                    Property::Number(query_id.to_string()),
+                ));
+            }
+            if let Some(execution_time) = execution_time {
+                document.push((
+                    "Planning Time".to_string(),
+                    Property::Number(millis(planning_time)),
+                ));
+                document.push((
+                    "Execution Time".to_string(),
+                    Property::Number(millis(execution_time)),
+                ));
+            }
+            // like postgres, the document is a list of the explained
+            // statements
+            let document = Property::Array(vec![Property::Object(document)]);
+            let mut out = String::new();
+            if options.format == ExplainFormat::Json {
+                write_json(&document, 0, &mut out);
+            } else {
+                write_yaml(&document, &mut out);
+            }
+            vec![out]
+        }
+    };
+
+    Ok(RecordBatch::try_new(
+        explain_schema(),
+        vec![Arc::new(StringArray::from(lines))],
+    )?)
+}
+
+fn millis(duration: Duration) -> String {
+    format!("{:.3}", duration.as_secs_f64() * 1000.0)
+}
+
+/// Estimated number of rows produced by `node`
+fn estimated_rows(node: &dyn ExecutionPlan) -> Option<usize> {
+    match node.partition_statistics(None).ok()?.num_rows {
+        Precision::Exact(rows) | Precision::Inexact(rows) => Some(rows),
+        Precision::Absent => None,
+    }
+}
+
+/// Metrics of the run of `node`, with `ANALYZE`
+fn analyzed_metrics(node: &dyn ExecutionPlan, options: &ExplainOptions) -> Option<MetricsSet> {
+    options
+        .analyze
+        .then(|| node.metrics())
+        .flatten()
+        .map(|metrics| metrics.aggregate_by_name())
+}
+
+/// Time spent computing the output of `node`, in milliseconds
+fn total_time(metrics: &MetricsSet) -> String {
+    metrics
+        .elapsed_compute()
+        .map(|nanos| millis(Duration::from_nanos(nanos as u64)))
+        .unwrap_or_else(|| "0.000".to_string())
+}
+
+fn output_columns(node: &dyn ExecutionPlan) -> Vec<String> {
+    node.schema()
+        .fields()
+        .iter()
+        .map(|field| field.name().clone())
+        .collect()
+}
+
+/// Append the lines of `node` and its children at `depth` of the tree
+fn render_node(
+    node: &dyn ExecutionPlan,
+    depth: usize,
+    options: &ExplainOptions,
+    lines: &mut Vec<String>,
+) {
+    // children are prefixed by an arrow, their details are aligned with the
+    // text after it
+    let prefix = match depth {
+        0 => String::new(),
+        _ => format!("{}->  ", " ".repeat(6 * (depth - 1) + 2)),
+    };
+    let detail_indent = " ".repeat(6 * depth + 2);
+
+    let label = displayable(node).one_line().to_string();
+    let mut line = format!("{prefix}{}", label.trim_end());
+
+    let mut annotations = Vec::new();
+    if let Some(rows) = estimated_rows(node) {
+        annotations.push(format!("rows={rows}"));
+    }
+    let metrics = analyzed_metrics(node, options);
+    if let Some(metrics) = &metrics {
+        // DataFusion doesn't record when an operator produced its first row,
+        // so the startup time is always shown as 0
+        let total = total_time(metrics);
+        let rows = metrics.output_rows().unwrap_or(0);
+        annotations = vec![format!("actual time=0.000..{total} rows={rows} loops=1")];
+    }
+    if !annotations.is_empty() {
+        line.push_str(&format!("  ({})", annotations.join(" ")));
+    }
+    lines.push(line);
+
+    if options.verbose {
+        lines.push(format!(
+            "{detail_indent}Output: {}",
+            output_columns(node).join(", ")
+        ));
+        if let Some(metrics) = metrics {
+            let metrics = metrics.sorted_for_display().timestamps_removed();
+            lines.push(format!("{detail_indent}Metrics: {metrics}"));
+        }
+    }
+
+    for child in node.children() {
+        render_node(child.as_ref(), depth + 1, options, lines);
+    }
+}
+
+/// Value in the JSON and YAML formats, objects keep the order of their
+/// properties as postgres does
+enum Property {
+    Text(String),
+    Number(String),
+    Array(Vec<Property>),
+    Object(Vec<(String, Property)>),
+}
+
+/// Properties of `node` and its children, named like the properties of
+/// postgres plans where DataFusion has an equivalent
+fn node_properties(node: &dyn ExecutionPlan, options: &ExplainOptions) -> Vec<(String, Property)> {
+    let label = displayable(node).one_line().to_string();
+    let mut properties = vec![
+        (
+            "Node Type".to_string(),
+            Property::Text(node.name().to_string()),
+        ),
+        (
+            "Details".to_string(),
+            Property::Text(label.trim_end().to_string()),
+        ),
+    ];
+    if let Some(rows) = estimated_rows(node) {
+        properties.push(("Plan Rows".to_string(), Property::Number(rows.to_string())));
+    }
+    let metrics = analyzed_metrics(node, options);
+    if let Some(metrics) = &metrics {
+        let rows = metrics.output_rows().unwrap_or(0);
+        properties.extend([
+            (
+                "Actual Startup Time".to_string(),
+                Property::Number("0.000".to_string()),
+            ),
+            (
+                "Actual Total Time".to_string(),
+                Property::Number(total_time(metrics)),
+            ),
+            (
+                "Actual Rows".to_string(),
+                Property::Number(rows.to_string()),
+            ),
+            (
+                "Actual Loops".to_string(),
+                Property::Number("1".to_string()),
+            ),
+        ]);
+    }
+    if options.verbose {
+        let columns = output_columns(node).into_iter().map(Property::Text);
+        properties.push(("Output".to_string(), Property::Array(columns.collect())));
+        if let Some(metrics) = metrics {
+            let metrics = metrics
+                .sorted_for_display()
+                .timestamps_removed()
+                .iter()
+                .map(|metric| {
+                    let value = metric.value();
+                    (value.name().to_string(), Property::Text(value.to_string()))
+                })
+                .collect();
+            properties.push(("Metrics".to_string(), Property::Object(metrics)));
+        }
+    }
+
+    let children = node.children();
+    if !children.is_empty() {
+        let plans = children
+            .into_iter()
+            .map(|child| Property::Object(node_properties(child.as_ref(), options)))
+            .collect();
+        properties.push(("Plans".to_string(), Property::Array(plans)));
+    }
+    properties
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Write `value` as JSON indented by 2 spaces per level like postgres
+fn write_json(value: &Property, indent: usize, out: &mut String) {
+    let pad = " ".repeat(indent);
+    match value {
+        Property::Text(s) => out.push_str(&json_string(s)),
+        Property::Number(n) => out.push_str(n),
+        Property::Array(items) if items.is_empty() => out.push_str("[]"),
+        Property::Array(items) => {
+            out.push_str("[\n");
+            for (i, item) in items.iter().enumerate() {
+                out.push_str(&pad);
+                out.push_str("  ");
+                write_json(item, indent + 2, out);
+                out.push_str(if i + 1 < items.len() { ",\n" } else { "\n" });
+            }
+            out.push_str(&pad);
+            out.push(']');
+        }
+        Property::Object(properties) if properties.is_empty() => out.push_str("{}"),
+        Property::Object(properties) => {
+            out.push_str("{\n");
+            for (i, (name, value)) in properties.iter().enumerate() {
+                out.push_str(&format!("{pad}  {}: ", json_string(name)));
+                write_json(value, indent + 2, out);
+                out.push_str(if i + 1 < properties.len() {
+                    ",\n"
+                } else {
+                    "\n"
+                });
+            }
+            out.push_str(&pad);
+            out.push('}');
+        }
+    }
+}
+
+/// Write `value` as YAML, strings are quoted like postgres does
+fn write_yaml(value: &Property, out: &mut String) {
+    out.push_str(&yaml_lines(value).join("\n"));
+}
+
+/// Lines of `value` in YAML, relative to the indentation of `value`
+fn yaml_lines(value: &Property) -> Vec<String> {
+    let mut lines = Vec::new();
+    match value {
+        Property::Text(s) => lines.push(json_string(s)),
+        Property::Number(n) => lines.push(n.clone()),
+        Property::Array(items) if items.is_empty() => lines.push("[]".to_string()),
+        Property::Array(items) => {
+            // the first line of an item follows its dash, the others are
+            // aligned with it
+            for item in items {
+                for (i, line) in yaml_lines(item).into_iter().enumerate() {
+                    let marker = if i == 0 { "- " } else { "  " };
+                    lines.push(format!("{marker}{line}"));
+                }
+            }
+        }
+        Property::Object(properties) if properties.is_empty() => lines.push("{}".to_string()),
+        Property::Object(properties) => {
+            for (name, value) in properties {
+                match value {
+                    Property::Array(items) if !items.is_empty() => {}
+                    Property::Object(properties) if !properties.is_empty() => {}
+                    scalar => {
+                        lines.push(format!("{name}: {}", yaml_lines(scalar).join("")));
+                        continue;
+                    }
+                }
+                lines.push(format!("{name}: "));
+                lines.extend(
+                    yaml_lines(value)
+                        .into_iter()
+                        .map(|line| format!("  {line}")),
+                );
+            }
+        }
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datafusion::arrow::array::AsArray;
+    use datafusion::prelude::SessionContext;
+
+    fn options(sql: &str) -> PgWireResult<ExplainOptions> {
+        ExplainOptions::from_sql(sql)
+            .expect("EXPLAIN")
+            .map(|(options, _)| options)
+    }
+
+    #[test]
+    fn test_explain_options() {
+        assert!(ExplainOptions::from_sql("SELECT 1").is_none());
+        assert_eq!(
+            options("EXPLAIN SELECT 1").unwrap(),
+            ExplainOptions::default()
+        );
+        assert_eq!(
+            options("explain analyze verbose SELECT 1").unwrap(),
+            ExplainOptions {
+                analyze: true,
+                verbose: true,
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            options("EXPLAIN (ANALYZE, VERBOSE off, COSTS false, FORMAT TEXT) SELECT 1").unwrap(),
+            ExplainOptions {
+                analyze: true,
+                verbose: false,
+                format: ExplainFormat::Text
+            }
+        );
+        assert_eq!(
+            options("EXPLAIN (FORMAT JSON) SELECT 1").unwrap().format,
+            ExplainFormat::Json
+        );
+        assert_eq!(
+            options("EXPLAIN (FORMAT 'yaml') SELECT 1").unwrap().format,
+            ExplainFormat::Yaml
+        );
+        assert!(options("EXPLAIN (ANALYZE maybe) SELECT 1").is_err());
+        assert!(options("EXPLAIN (FORMAT XML) SELECT 1").is_err());
+        assert!(options("EXPLAIN (UNKNOWN) SELECT 1").is_err());
+    }
+
+    async fn explain_lines(ctx: &SessionContext, sql: &str) -> Vec<String> {
+        let (options, statement) = ExplainOptions::from_sql(sql).unwrap().unwrap();
+        let plan = ctx
+            .state()
+            .statement_to_plan(datafusion::sql::parser::Statement::Statement(Box::new(
+                statement,
+            )))
+            .await
+            .unwrap();
+        let df = ctx.execute_logical_plan(plan).await.unwrap();
+        let batch = explain(df, &options, 42).await.unwrap();
+        batch
+            .column(0)
+            .as_string::<i32>()
+            .iter()
+            .map(|line| line.unwrap().to_string())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_explain_tree() {
+        let ctx = SessionContext::new();
+        ctx.sql("CREATE TABLE t AS VALUES (1), (2), (3)")
+            .await
+            .unwrap();
+
+        let lines =
+            explain_lines(&ctx, "EXPLAIN SELECT column1 + 1 FROM t WHERE column1 > 1").await;
+        assert!(lines[0].starts_with("ProjectionExec"), "{lines:?}");
+        assert!(lines[1..].iter().all(|line| line.starts_with(' ')));
+        // each level is indented by 6 more characters
+        let child = lines.iter().position(|l| l.starts_with("  ->  ")).unwrap();
+        assert!(lines[child + 1..]
+            .iter()
+            .any(|l| l.starts_with("        ->  ")));
+
+        let lines = explain_lines(
+            &ctx,
+            "EXPLAIN (ANALYZE, VERBOSE) SELECT column1 FROM t WHERE column1 > 1",
+        )
+        .await;
+        assert!(lines[0].contains("(actual time=0.000.."), "{lines:?}");
+        assert!(lines[0].ends_with("rows=2 loops=1)"), "{lines:?}");
+        assert_eq!(lines[1], "  Output: column1");
+        assert!(lines[2].starts_with("  Metrics: "));
+        assert_eq!(lines[lines.len() - 3], "Query Identifier: 42");
+        assert!(lines[lines.len() - 2].starts_with("Planning Time: "));
+        assert!(lines[lines.len() - 1].starts_with("Execution Time: "));
+    }
+
+    #[tokio::test]
+    async fn test_explain_documents() {
+        let ctx = SessionContext::new();
+        ctx.sql("CREATE TABLE t AS VALUES (1), (2), (3)")
+            .await
+            .unwrap();
+
+        let lines = explain_lines(
+            &ctx,
+            "EXPLAIN (FORMAT JSON, ANALYZE) SELECT column1 FROM t WHERE column1 > 1",
+        )
+        .await;
+        assert_eq!(lines.len(), 1);
+        let document = &lines[0];
+        assert!(document.starts_with("[\n  {\n    \"Plan\": {\n      \"Node Type\": "));
+        assert!(
+            document.contains("\"Node Type\": \"FilterExec\""),
+            "{document}"
+        );
+        assert!(document.contains("\"Actual Rows\": 2,"), "{document}");
+        assert!(document.contains("\"Plans\": ["), "{document}");
+        assert!(document.contains("\"Execution Time\": "), "{document}");
+        assert!(document.ends_with("\n  }\n]"), "{document}");
+
+        let lines = explain_lines(
+            &ctx,
+            "EXPLAIN (FORMAT YAML, VERBOSE) SELECT column1 FROM t WHERE column1 > 1",
+        )
+        .await;
+        assert_eq!(lines.len(), 1);
+        let document = &lines[0];
+        assert!(
+            document.starts_with("- Plan: \n    Node Type: "),
+            "{document}"
+        );
+        assert!(
+            document.contains("\n    Output: \n      - \"column1\""),
+            "{document}"
+        );
+        assert!(
+            document.contains("\n    Plans: \n      - Node Type: \"FilterExec\""),
+            "{document}"
+        );
+        assert!(document.contains("\n  Query Identifier: 42"), "{document}");
+        assert!(!document.contains("Execution Time"));
+    }
+}
@@ -0,0 +1,131 @@
+//! `CREATE EXTERNAL TABLE`, registering tables of files and of lakehouse
+//! formats.
+//!
+//! sqlparser doesn't parse the statement, DataFusion's own parser does.
+//! `STORED AS` names a table factory of the session state: the file formats
+//! DataFusion reads itself, like `PARQUET` or `CSV`, and the formats of the
+//! factories an application registers, like the Delta Lake and Iceberg
+//! factories of `deltalake` and `iceberg-datafusion`:
+//!
+//! ```rust,ignore
+//! let state = SessionStateBuilder::new()
+//!     .with_default_features()
+//!     .with_table_factory("DELTA".to_string(), Arc::new(DeltaTableFactory {}))
+//!     .build();
+//! ```
+//!
+//! `OPTIONS` are handed to the factory, like the snapshot to time travel to
+//! in `STORED AS DELTA LOCATION 's3://lake/events' OPTIONS ('version' '12')`,
+//! with keys without namespace prefixed by `format.`, e.g. `format.version`.
+//!
+//! Like `COPY ... TO`, reading files of the server takes superuser or the
+//! privileges of the `pg_read_server_files` role. `LOCATION` must be a URL of
+//! a registered object store, or a local path under the import root of the
+//! server.
+//!
+//! Sessions without factories of their own get the `DELTA` factory of the
+//! `deltalake` feature and the `ICEBERG` factory of the `iceberg` feature,
+//! see the `lakehouse` module. Formats without a factory are refused with
+//! `feature_not_supported`.
+
+use std::path::Path;
+use std::sync::Arc;
+
+use datafusion::catalog::TableProviderFactory;
+use datafusion::execution::runtime_env::RuntimeEnv;
+use datafusion::prelude::SessionContext;
+use datafusion::sql::parser::{CreateExternalTable, DFParser, Statement};
+use datafusion::sql::sqlparser::dialect::PostgreSqlDialect;
+use pgwire::error::{ErrorInfo, PgWireError, PgWireResult};
+
+use crate::copy_to::check_server_location;
+
+fn external_table_error(code: &str, message: String) -> PgWireError {
+    PgWireError::UserError(Box::new(ErrorInfo::new(
+        "ERROR".to_string(),
+        code.to_string(),
+        message,
+    )))
+}
+
+/// Whether the lowercase query is a `CREATE [UNBOUNDED] EXTERNAL TABLE`
+pub(crate) fn is_create_external_table(query_lower: &str) -> bool {
+    let mut words = query_lower.split_whitespace();
+    words.next() == Some("create")
+        && words
+            .skip_while(|word| *word == "unbounded")
+            .take(2)
+            .eq(["external", "table"])
+}
+
+pub(crate) fn parse_create_external_table(sql: &str) -> PgWireResult<CreateExternalTable> {
+    let mut statements = DFParser::parse_sql_with_dialect(sql, &PostgreSqlDialect {})
+        .map_err(|e| PgWireError::ApiError(Box::new(e)))?;
+    match (statements.pop_front(), statements.is_empty()) {
+        (Some(Statement::CreateExternalTable(statement)), true) => Ok(statement),
+        _ => Err(external_table_error(
+            "42601", // syntax_error
+            "expected a single CREATE EXTERNAL TABLE statement".to_string(),
+        )),
+    }
+}
+
+/// Check that `location` is a URL of a registered object store, or a file
+/// under `import_root`, so `CREATE EXTERNAL TABLE` can't read other files of
+/// the server
+pub(crate) fn check_table_location(
+    location: &str,
+    import_root: Option<&Path>,
+    runtime_env: &RuntimeEnv,
+) -> PgWireResult<()> {
+    check_server_location(
+        location,
+        import_root,
+        runtime_env,
+        "external table of",
+        "import root",
+    )
+}
+
+/// The table factory of `format` bundled by the features of the crate
+fn bundled_table_factory(format: &str) -> Option<Arc<dyn TableProviderFactory>> {
+    match format {
+        #[cfg(feature = "deltalake")]
+        "DELTA" => Some(Arc::new(crate::lakehouse::DeltaTableFactory)),
+        #[cfg(feature = "iceberg")]
+        "ICEBERG" => Some(Arc::new(crate::lakehouse::IcebergTableFactory)),
+        _ => None,
+    }
+}
+
+/// Register the table of `CREATE EXTERNAL TABLE` with the table factory of
+/// its format
+pub(crate) async fn create_external_table(
+    session_context: &SessionContext,
+    statement: CreateExternalTable,
+) -> PgWireResult<()> {
+    let format = statement.file_type.to_uppercase();
+    if session_context.table_factory(&format).is_none() {
+        let Some(factory) = bundled_table_factory(&format) else {
+            return Err(external_table_error(
+                "0A000", // feature_not_supported
+                format!("no table factory is registered for STORED AS {format}"),
+            ));
+        };
+        session_context
+            .state_ref()
+            .write()
+            .table_factories_mut()
+            .insert(format, factory);
+    }
+    let plan = session_context
+        .state()
+        .statement_to_plan(Statement::CreateExternalTable(statement))
+        .await
+        .map_err(|e| PgWireError::ApiError(Box::new(e)))?;
+    session_context
+        .execute_logical_plan(plan)
+        .await
+        .map_err(|e| PgWireError::ApiError(Box::new(e)))?;
+    Ok(())
+}
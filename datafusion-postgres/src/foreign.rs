@@ -0,0 +1,510 @@
+//! Tables of upstream Postgres servers.
+//!
+//! With the `postgres-fdw` feature, tables of a real Postgres server are
+//! queried live and joined with local data, like with `postgres_fdw`:
+//!
+//! ```sql
+//! CREATE SERVER shop FOREIGN DATA WRAPPER postgres_fdw
+//!     OPTIONS (host 'db.internal', dbname 'shop', user 'reader', password 'secret');
+//! CREATE FOREIGN TABLE orders (id BIGINT, total DOUBLE PRECISION)
+//!     SERVER shop OPTIONS (schema_name 'public', table_name 'orders');
+//! ```
+//!
+//! There are no user mappings, servers take the credentials as options.
+//! Scans of a [`RemoteTable`] select its projected columns, up to the limit
+//! of the scan, and filter the rows locally. Values are read in text format
+//! and cast to the declared types of the columns.
+
+use std::any::Any;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use datafusion::arrow::array::{ArrayRef, RecordBatchOptions, StringArray};
+use datafusion::arrow::compute::{cast_with_options, CastOptions};
+use datafusion::arrow::datatypes::SchemaRef;
+use datafusion::arrow::record_batch::RecordBatch;
+use datafusion::catalog::{Session, TableProvider};
+use datafusion::datasource::TableType;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::execution::{SendableRecordBatchStream, TaskContext};
+use datafusion::logical_expr::Expr;
+use datafusion::physical_plan::stream::RecordBatchStreamAdapter;
+use datafusion::physical_plan::streaming::{PartitionStream, StreamingTableExec};
+use datafusion::physical_plan::ExecutionPlan;
+use datafusion::sql::sqlparser::ast::{ColumnDef, ObjectName};
+use datafusion::sql::sqlparser::dialect::PostgreSqlDialect;
+use datafusion::sql::sqlparser::keywords::Keyword;
+use datafusion::sql::sqlparser::parser::{Parser, ParserError};
+use datafusion::sql::sqlparser::tokenizer::Token;
+use futures::stream::{self, TryChunksError};
+use futures::{StreamExt, TryStreamExt};
+use pgwire::error::{ErrorInfo, PgWireError, PgWireResult};
+use tokio_postgres::{Config, NoTls, SimpleQueryMessage, SimpleQueryRow};
+
+use crate::errors::syntax_error;
+use crate::sql::{parse_name, parse_options, parse_word};
+
+/// The only foreign-data wrapper
+const POSTGRES_FDW: &str = "postgres_fdw";
+
+fn foreign_error(code: &str, message: String) -> PgWireError {
+    PgWireError::UserError(Box::new(ErrorInfo::new(
+        "ERROR".to_string(),
+        code.to_string(),
+        message,
+    )))
+}
+
+fn invalid_option(name: &str) -> PgWireError {
+    foreign_error(
+        "HV00D", // fdw_invalid_option_name
+        format!("invalid option \"{name}\""),
+    )
+}
+
+/// `CREATE SERVER` and `CREATE FOREIGN TABLE`, which sqlparser doesn't parse
+#[derive(Debug)]
+pub(crate) enum ForeignStatement {
+    CreateServer {
+        name: String,
+        if_not_exists: bool,
+        wrapper: String,
+        options: Vec<(String, String)>,
+    },
+    CreateForeignTable {
+        name: ObjectName,
+        if_not_exists: bool,
+        columns: Vec<ColumnDef>,
+        server: String,
+        options: Vec<(String, String)>,
+    },
+}
+
+impl ForeignStatement {
+    /// Whether the lowercase query is a statement to parse with
+    /// [`ForeignStatement::parse`]
+    pub(crate) fn matches(query_lower: &str) -> bool {
+        let mut words = query_lower.split_whitespace();
+        words.next() == Some("create")
+            && match words.next() {
+                Some("server") => true,
+                Some("foreign") => words.next() == Some("table"),
+                _ => false,
+            }
+    }
+
+    pub(crate) fn parse(sql: &str) -> PgWireResult<Self> {
+        let statement = Self::parse_statement(sql).map_err(|e| syntax_error(e, sql))?;
+        match &statement {
+            ForeignStatement::CreateServer { wrapper, .. } if wrapper != POSTGRES_FDW => {
+                Err(foreign_error(
+                    "42704", // undefined_object
+                    format!("foreign-data wrapper \"{wrapper}\" does not exist"),
+                ))
+            }
+            _ => Ok(statement),
+        }
+    }
+
+    fn parse_statement(sql: &str) -> Result<Self, ParserError> {
+        let dialect = PostgreSqlDialect {};
+        let mut parser = Parser::new(&dialect).try_with_sql(sql)?;
+        parser.expect_keyword(Keyword::CREATE)?;
+        let statement = if parse_word(&mut parser, "server") {
+            let if_not_exists =
+                parser.parse_keywords(&[Keyword::IF, Keyword::NOT, Keyword::EXISTS]);
+            let name = parse_name(&mut parser)?;
+            parser.expect_keywords(&[Keyword::FOREIGN, Keyword::DATA])?;
+            if !parse_word(&mut parser, "wrapper") {
+                return parser.expected("WRAPPER", parser.peek_token());
+            }
+            ForeignStatement::CreateServer {
+                name,
+                if_not_exists,
+                wrapper: parse_name(&mut parser)?,
+                options: parse_options(&mut parser)?,
+            }
+        } else {
+            parser.expect_keywords(&[Keyword::FOREIGN, Keyword::TABLE])?;
+            let if_not_exists =
+                parser.parse_keywords(&[Keyword::IF, Keyword::NOT, Keyword::EXISTS]);
+            let name = parser.parse_object_name(false)?;
+            let (columns, _constraints) = parser.parse_columns()?;
+            if !parse_word(&mut parser, "server") {
+                return parser.expected("SERVER", parser.peek_token());
+            }
+            ForeignStatement::CreateForeignTable {
+                name,
+                if_not_exists,
+                columns,
+                server: parse_name(&mut parser)?,
+                options: parse_options(&mut parser)?,
+            }
+        };
+        let _ = parser.consume_token(&Token::SemiColon);
+        parser.expect_token(&Token::EOF)?;
+        Ok(statement)
+    }
+}
+
+/// The connections of the servers of `CREATE SERVER`
+#[derive(Debug, Default)]
+pub(crate) struct ForeignServers {
+    servers: RwLock<HashMap<String, Config>>,
+}
+
+impl ForeignServers {
+    /// Register the server `name` with the connection options of
+    /// `CREATE SERVER`
+    pub(crate) fn create(
+        &self,
+        name: &str,
+        if_not_exists: bool,
+        options: &[(String, String)],
+    ) -> PgWireResult<()> {
+        let config = server_config(options)?;
+        let mut servers = self.servers.write().unwrap();
+        if servers.contains_key(name) {
+            if if_not_exists {
+                return Ok(());
+            }
+            return Err(foreign_error(
+                "42710", // duplicate_object
+                format!("server \"{name}\" already exists"),
+            ));
+        }
+        servers.insert(name.to_string(), config);
+        Ok(())
+    }
+
+    /// The table of `CREATE FOREIGN TABLE` on the server `server`, named
+    /// `name` unless the options name it
+    pub(crate) fn table(
+        &self,
+        server: &str,
+        name: &str,
+        options: &[(String, String)],
+        schema: SchemaRef,
+    ) -> PgWireResult<RemoteTable> {
+        let Some(config) = self.servers.read().unwrap().get(server).cloned() else {
+            return Err(foreign_error(
+                "42704", // undefined_object
+                format!("server \"{server}\" does not exist"),
+            ));
+        };
+        let mut schema_name = "public";
+        let mut table_name = name;
+        for (option, value) in options {
+            match option.as_str() {
+                "schema_name" => schema_name = value,
+                "table_name" => table_name = value,
+                _ => return Err(invalid_option(option)),
+            }
+        }
+        Ok(RemoteTable::new(config, schema_name, table_name, schema))
+    }
+}
+
+/// Connection of the options of `CREATE SERVER`
+fn server_config(options: &[(String, String)]) -> PgWireResult<Config> {
+    let mut config = Config::new();
+    for (name, value) in options {
+        let invalid_value = || {
+            foreign_error(
+                "22023", // invalid_parameter_value
+                format!("invalid value for option \"{name}\": \"{value}\""),
+            )
+        };
+        match name.as_str() {
+            "host" => config.host(value),
+            "port" => config.port(value.parse().map_err(|_| invalid_value())?),
+            "dbname" => config.dbname(value),
+            "user" => config.user(value),
+            "password" => config.password(value),
+            "application_name" => config.application_name(value),
+            "connect_timeout" => config.connect_timeout(Duration::from_secs(
+                value.parse().map_err(|_| invalid_value())?,
+            )),
+            _ => return Err(invalid_option(name)),
+        };
+    }
+    if config.get_hosts().is_empty() {
+        config.host("localhost");
+    }
+    Ok(config)
+}
+
+fn quote_identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+/// A table of an upstream Postgres server, read on every scan
+#[derive(Debug, Clone)]
+pub struct RemoteTable {
+    config: Config,
+    /// Quoted name of the table on the server
+    relation: String,
+    schema: SchemaRef,
+}
+
+impl RemoteTable {
+    /// The table `schema_name.table_name` of the server of `config`, read as
+    /// the columns of `schema`
+    pub fn new(config: Config, schema_name: &str, table_name: &str, schema: SchemaRef) -> Self {
+        RemoteTable {
+            config,
+            relation: format!(
+                "{}.{}",
+                quote_identifier(schema_name),
+                quote_identifier(table_name)
+            ),
+            schema,
+        }
+    }
+}
+
+#[async_trait]
+impl TableProvider for RemoteTable {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn table_type(&self) -> TableType {
+        TableType::Base
+    }
+
+    async fn scan(
+        &self,
+        _state: &dyn Session,
+        projection: Option<&Vec<usize>>,
+        _filters: &[Expr],
+        limit: Option<usize>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        let schema = match projection {
+            Some(projection) => Arc::new(self.schema.project(projection)?),
+            None => self.schema.clone(),
+        };
+        // no columns, like for count(*), is `SELECT FROM t`
+        let columns = schema
+            .fields()
+            .iter()
+            .map(|field| quote_identifier(field.name()))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let mut query = format!("SELECT {columns} FROM {}", self.relation);
+        if let Some(limit) = limit {
+            query.push_str(&format!(" LIMIT {limit}"));
+        }
+        let partition = RemoteQuery {
+            config: self.config.clone(),
+            query,
+            schema: schema.clone(),
+        };
+        Ok(Arc::new(StreamingTableExec::try_new(
+            schema,
+            vec![Arc::new(partition)],
+            None,
+            vec![],
+            false,
+            None,
+        )?))
+    }
+}
+
+/// The query of a scan of a [`RemoteTable`], run on a connection of its own
+#[derive(Debug)]
+struct RemoteQuery {
+    config: Config,
+    query: String,
+    schema: SchemaRef,
+}
+
+impl PartitionStream for RemoteQuery {
+    fn schema(&self) -> &SchemaRef {
+        &self.schema
+    }
+
+    fn execute(&self, ctx: Arc<TaskContext>) -> SendableRecordBatchStream {
+        let batch_size = ctx.session_config().batch_size();
+        let config = self.config.clone();
+        let query = self.query.clone();
+        let schema = self.schema.clone();
+        let batches = stream::once(async move {
+            let (client, connection) = config.connect(NoTls).await.map_err(external)?;
+            tokio::spawn(async move {
+                if let Err(e) = connection.await {
+                    log::warn!("Connection to upstream server failed: {e}");
+                }
+            });
+            let messages = client.simple_query_raw(&query).await.map_err(external)?;
+            let batches = messages
+                // the connection closes once the client is dropped
+                .map(move |message| {
+                    let _client = &client;
+                    message.map_err(external)
+                })
+                .try_filter_map(|message| async move {
+                    Ok(match message {
+                        SimpleQueryMessage::Row(row) => Some(row),
+                        _ => None,
+                    })
+                })
+                .try_chunks(batch_size)
+                .map(move |rows| {
+                    rows.map_err(|TryChunksError(_, e)| e)
+                        .and_then(|rows| record_batch(&schema, &rows))
+                });
+            Ok::<_, DataFusionError>(batches)
+        })
+        .try_flatten();
+        Box::pin(RecordBatchStreamAdapter::new(self.schema.clone(), batches))
+    }
+}
+
+fn external(error: tokio_postgres::Error) -> DataFusionError {
+    DataFusionError::External(Box::new(error))
+}
+
+/// Batch of rows in text format, cast to the types of `schema`
+fn record_batch(schema: &SchemaRef, rows: &[SimpleQueryRow]) -> Result<RecordBatch> {
+    let options = CastOptions {
+        safe: false,
+        ..Default::default()
+    };
+    let columns = (0..schema.fields().len())
+        .map(|i| {
+            let text: ArrayRef =
+                Arc::new(rows.iter().map(|row| row.get(i)).collect::<StringArray>());
+            cast_with_options(&text, schema.field(i).data_type(), &options)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(RecordBatch::try_new_with_options(
+        schema.clone(),
+        columns,
+        &RecordBatchOptions::new().with_row_count(Some(rows.len())),
+    )?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_foreign_statements() {
+        assert!(ForeignStatement::matches(
+            "create  server s foreign data wrapper"
+        ));
+        assert!(ForeignStatement::matches(
+            "create foreign table t () server s"
+        ));
+        assert!(!ForeignStatement::matches("create table t (a int)"));
+
+        let statement = ForeignStatement::parse(
+            "CREATE SERVER IF NOT EXISTS Shop FOREIGN DATA WRAPPER postgres_fdw \
+             OPTIONS (host 'db', port '5433');",
+        )
+        .unwrap();
+        let ForeignStatement::CreateServer {
+            name,
+            if_not_exists,
+            options,
+            ..
+        } = statement
+        else {
+            panic!("expected CREATE SERVER");
+        };
+        assert_eq!(name, "shop");
+        assert!(if_not_exists);
+        assert_eq!(
+            options,
+            [
+                ("host".to_string(), "db".to_string()),
+                ("port".to_string(), "5433".to_string())
+            ]
+        );
+
+        let statement = ForeignStatement::parse(
+            "CREATE FOREIGN TABLE orders (id BIGINT, total DOUBLE PRECISION) SERVER shop",
+        )
+        .unwrap();
+        let ForeignStatement::CreateForeignTable {
+            name,
+            columns,
+            server,
+            options,
+            ..
+        } = statement
+        else {
+            panic!("expected CREATE FOREIGN TABLE");
+        };
+        assert_eq!(name.to_string(), "orders");
+        assert_eq!(columns.len(), 2);
+        assert_eq!(server, "shop");
+        assert!(options.is_empty());
+
+        let Err(PgWireError::UserError(info)) =
+            ForeignStatement::parse("CREATE SERVER s FOREIGN DATA WRAPPER file_fdw")
+        else {
+            panic!("expected an unknown wrapper");
+        };
+        assert_eq!(info.code, "42704");
+        for sql in [
+            "CREATE FOREIGN TABLE t (a INT)",
+            "CREATE FOREIGN TABLE t (a INT) SERVER s OPTIONS (table_name)",
+        ] {
+            let Err(PgWireError::UserError(info)) = ForeignStatement::parse(sql) else {
+                panic!("expected a syntax error for {sql}");
+            };
+            assert_eq!(info.code, "42601");
+        }
+    }
+
+    #[test]
+    fn test_foreign_servers() {
+        let servers = ForeignServers::default();
+        let code = |error: PgWireError| {
+            let PgWireError::UserError(info) = error else {
+                panic!("expected a user error");
+            };
+            info.code
+        };
+        let options = [("dbname".to_string(), "shop".to_string())];
+        servers.create("shop", false, &options).unwrap();
+        servers.create("shop", true, &options).unwrap();
+        assert_eq!(
+            code(servers.create("shop", false, &options).unwrap_err()),
+            "42710"
+        );
+        let options = [("sslmode".to_string(), "require".to_string())];
+        assert_eq!(
+            code(servers.create("other", false, &options).unwrap_err()),
+            "HV00D"
+        );
+        let options = [("port".to_string(), "postgres".to_string())];
+        assert_eq!(
+            code(servers.create("other", false, &options).unwrap_err()),
+            "22023"
+        );
+
+        let schema = Arc::new(datafusion::arrow::datatypes::Schema::empty());
+        let table = servers
+            .table(
+                "shop",
+                "orders",
+                &[("schema_name".to_string(), "sales".to_string())],
+                schema.clone(),
+            )
+            .unwrap();
+        assert_eq!(table.relation, "\"sales\".\"orders\"");
+        assert_eq!(
+            code(servers.table("other", "orders", &[], schema).unwrap_err()),
+            "42704"
+        );
+    }
+}
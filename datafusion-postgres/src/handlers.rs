@@ -1,55 +1,489 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Debug;
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use crate::auth::{AuthManager, Permission, ResourceType};
+use crate::activity::ActivityRegistry;
+use crate::advisory_locks::{
+    bind_advisory_locks, calls_advisory_unlock, AdvisoryLocks, LockSession,
+};
+use crate::analyze::{
+    all_tables, analyze_table, is_maintenance_command, parse_maintenance_command,
+    MaintenanceCommand, StatisticsRegistry,
+};
+use crate::auth::{
+    interval_style, parse_bool_setting, session_user_name, standard_conforming_strings,
+    AuthManager, AuthSource, Authorizer, PasswordStartupHandler, Permission, ResourceType,
+    ScramAuthSource, ServerParameters, METADATA_AUTHORIZED_USER, METADATA_INTERVAL_STYLE,
+    METADATA_STANDARD_CONFORMING_STRINGS,
+};
+use crate::cert_auth::{CertAuthConfig, CertStartupHandler};
+use crate::column_origins::with_column_origins;
+use crate::copy::{
+    column_names, data_row_values, force_mask, record_batch, CopyIn, CopyIns, CopyStatement,
+};
+use crate::copy_to::{check_copy_target, copy_to_statement, partitioned_copy};
+use crate::database::{create_database, drop_database};
+use crate::discard::PreparedStatements;
+use crate::errors::{into_sqlstate_error, syntax_error};
+use crate::events::{ConnectionEvent, ConnectionListener, ConnectionListeners};
+use crate::explain::{self, ExplainOptions};
+use crate::external::{
+    check_table_location, create_external_table, is_create_external_table,
+    parse_create_external_table,
+};
+#[cfg(feature = "postgres-fdw")]
+use crate::foreign::{ForeignServers, ForeignStatement};
+use crate::hba::{check_connection, HbaRule};
+use crate::hooks::{HookContext, QueryHook, QueryHooks};
+#[cfg(feature = "jwt")]
+use crate::jwt::{JwtAuthenticator, JwtStartupHandler};
+use crate::limits::{map_resource_error, QueryLimits};
+use crate::negotiation::negotiate_protocol;
+use crate::notify::{schema_change, Notifications, SCHEMA_CHANGED_CHANNEL};
+use crate::on_conflict::{statement_to_plan, unsupported_on_conflict};
+use crate::pg_catalog::clock_udf::bind_statement_timestamp;
+use crate::pg_catalog::pg_settings::SessionSettings;
+use crate::pg_catalog::{CatalogVisibility, PgCatalogSchemaProvider};
+use crate::pipeline::PipelineClient;
+use crate::plan_cache::{invalidates_plans, PlanCache};
+use crate::policy::{apply_table_policies, PolicyContext, StatementKind};
+use crate::progress;
+use crate::rate_limit::{RateLimit, RateLimiter};
+use crate::refresh::{
+    is_alter_table_refresh_schema, parse_alter_table_refresh_schema, refresh_function_argument,
+    refresh_table_schema, REFRESH_TABLE_SCHEMA,
+};
+use crate::replication::{
+    replication_not_supported, ReplicationHandler, ReplicationMode, REPLICATION_PARAMETER,
+};
+use crate::result_cache::{invalidates_results, ResultCache, ResultCacheKey};
+use crate::returning::{insert_returning, returning_plan, split_returning};
+use crate::role_settings::{statement_timeout, DbRoleSettings, SettingChange, SettingStatement};
+use crate::roles::RoleStatement;
+use crate::scheduler::StatementScheduler;
+use crate::session::{SessionContextFactory, SessionContexts};
 use crate::sql::{
-    parse, rewrite, AliasDuplicatedProjectionRewrite, BlacklistSqlRewriter, FixArrayLiteral,
-    PrependUnqualifiedPgTableName, RemoveTableFunctionQualifier, RemoveUnsupportedTypes,
-    ResolveUnqualifiedIdentifer, RewriteArrayAnyAllOperation, SqlStatementRewriteRule,
+    escape_backslash_literals, parse, query_id, rewrite_with_notices,
+    AliasDuplicatedProjectionRewrite, BlacklistSqlRewriter, DateOrder, DateStyle, FixArrayLiteral,
+    InlineLateralProjection, PrependUnqualifiedPgTableName, RemoveFunctionQualifier,
+    RemoveTableFunctionQualifier, RemoveUnsupportedClauses, RemoveUnsupportedTypes,
+    ResolveRegclassLiteral, ResolveUnqualifiedIdentifer, RewriteArrayAnyAllOperation,
+    RewriteExtract, RewriteJsonSetFunctions, RewriteWindowFilter, SqlStatementRewriteRule,
+    TemporalLiteralRewrite,
 };
+use crate::storage::{StorageCredentials, StorageStatement};
+use crate::telemetry::{Phase, SpanExporter, Telemetry};
+use crate::tenant::TenantResolver;
+use crate::time_zone::{in_time_zone, time_zone_setting};
+use crate::wire_debug::{WireDebug, WireDebugClient, METADATA_WIRE_DEBUG};
 use async_trait::async_trait;
 use datafusion::arrow::datatypes::DataType;
+use datafusion::common::tree_node::{Transformed, TransformedResult};
+use datafusion::common::{ParamValues, TableReference};
+use datafusion::dataframe::DataFrameWriteOptions;
+use datafusion::execution::context::SessionState;
+use datafusion::execution::FunctionRegistry;
 use datafusion::logical_expr::LogicalPlan;
+use datafusion::physical_plan::ExecutionPlan;
 use datafusion::prelude::*;
-use datafusion::sql::parser::Statement;
+use datafusion::sql::parser::{CopyToSource, CopyToStatement, Statement};
+use datafusion::sql::planner::object_name_to_table_reference;
+use datafusion::sql::planner::IdentNormalizer;
+#[cfg(feature = "postgres-fdw")]
+use datafusion::sql::sqlparser::ast::helpers::stmt_create_table::CreateTableBuilder;
+#[cfg(feature = "postgres-fdw")]
+use datafusion::sql::sqlparser::ast::HiveFormat;
+use datafusion::sql::sqlparser::ast::{
+    CopySource, DiscardObject, Expr, ObjectName, ObjectType, OneOrManyWithParens, SelectItem,
+    Statement as SqlStatement, Value,
+};
+use futures::{stream, Sink, SinkExt, StreamExt};
 use log::{info, warn};
-use pgwire::api::auth::noop::NoopStartupHandler;
-use pgwire::api::auth::StartupHandler;
+use pgwire::api::auth::scram::SASLScramAuthStartupHandler;
+use pgwire::api::auth::{
+    finish_authentication, protocol_negotiation, save_startup_parameters_to_metadata,
+    StartupHandler,
+};
+use pgwire::api::copy::CopyHandler;
 use pgwire::api::portal::{Format, Portal};
 use pgwire::api::query::{ExtendedQueryHandler, SimpleQueryHandler};
 use pgwire::api::results::{
-    DescribePortalResponse, DescribeStatementResponse, FieldFormat, FieldInfo, QueryResponse,
-    Response, Tag,
+    CopyResponse, DescribePortalResponse, DescribeStatementResponse, FieldFormat, FieldInfo,
+    QueryResponse, Response, Tag,
 };
 use pgwire::api::stmt::QueryParser;
 use pgwire::api::stmt::StoredStatement;
-use pgwire::api::{ClientInfo, ErrorHandler, PgWireServerHandlers, Type};
-use pgwire::error::{PgWireError, PgWireResult};
-use pgwire::messages::response::TransactionStatus;
-use tokio::sync::Mutex;
+use pgwire::api::store::PortalStore;
+use pgwire::api::{
+    ClientInfo, ClientPortalStore, ErrorHandler, PgWireConnectionState, PgWireServerHandlers, Type,
+    DEFAULT_NAME, METADATA_DATABASE, METADATA_USER,
+};
+use pgwire::error::{ErrorInfo, PgWireError, PgWireResult};
+use pgwire::messages::copy::{CopyData, CopyDone, CopyFail, CopyOutResponse};
+use pgwire::messages::extendedquery::{
+    Bind, BindComplete, Close, CloseComplete, Describe, Execute, Flush, Parse, ParseComplete,
+    Sync as PgSync, TARGET_TYPE_BYTE_PORTAL, TARGET_TYPE_BYTE_STATEMENT,
+};
+use pgwire::messages::response::{NoticeResponse, ReadyForQuery, TransactionStatus};
+use pgwire::messages::simplequery::Query;
+use pgwire::messages::startup::ParameterStatus;
+use pgwire::messages::{PgWireBackendMessage, PgWireFrontendMessage};
 
-use arrow_pg::datatypes::df;
+use arrow_pg::datatypes::df::{self, EncodeOptions};
 use arrow_pg::datatypes::{arrow_schema_to_pg_fields, into_pg_type};
+use arrow_pg::encoder::{IntervalStyle, TextFormat};
 
 // Metadata keys for session-level settings
 const METADATA_STATEMENT_TIMEOUT: &str = "statement_timeout_ms";
+const METADATA_RESULT_BATCH_SIZE: &str = "result_batch_size";
+const METADATA_RESULT_BUFFER_SIZE: &str = "result_buffer_size";
+const METADATA_MAX_FIELD_SIZE: &str = "max_field_size";
+const METADATA_TRANSACTION_ISOLATION: &str = "transaction_isolation";
+const METADATA_DEFAULT_TRANSACTION_ISOLATION: &str = "default_transaction_isolation";
+const METADATA_DATESTYLE: &str = "DateStyle";
+const METADATA_TIME_ZONE: &str = "TimeZone";
+const METADATA_EXTRA_FLOAT_DIGITS: &str = "extra_float_digits";
+/// Metadata key of the role and database defaults the session started with,
+/// a JSON object of their values and sources by name
+const METADATA_SETTING_DEFAULTS: &str = "setting_defaults";
+
+/// Metadata keys of the settings `DISCARD ALL` resets to their defaults
+const METADATA_SESSION_SETTINGS: &[&str] = &[
+    METADATA_STATEMENT_TIMEOUT,
+    METADATA_RESULT_BATCH_SIZE,
+    METADATA_RESULT_BUFFER_SIZE,
+    METADATA_MAX_FIELD_SIZE,
+    METADATA_WIRE_DEBUG,
+    METADATA_TRANSACTION_ISOLATION,
+    METADATA_DEFAULT_TRANSACTION_ISOLATION,
+    METADATA_TIME_ZONE,
+    METADATA_STANDARD_CONFORMING_STRINGS,
+    METADATA_EXTRA_FLOAT_DIGITS,
+    METADATA_INTERVAL_STYLE,
+];
+
+/// Wait event of statements queued by the statement scheduler
+const STATEMENT_ADMISSION_WAIT: (&str, &str) = ("Lock", "StatementAdmission");
 
 /// Simple startup handler that does no authentication
 /// For production, use DfAuthSource with proper pgwire authentication handlers
 pub struct SimpleStartupHandler;
 
-#[async_trait::async_trait]
-impl NoopStartupHandler for SimpleStartupHandler {}
+#[async_trait]
+impl StartupHandler for SimpleStartupHandler {
+    async fn on_startup<C>(
+        &self,
+        client: &mut C,
+        message: PgWireFrontendMessage,
+    ) -> PgWireResult<()>
+    where
+        C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send,
+        C::Error: Debug,
+        PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    {
+        if let PgWireFrontendMessage::Startup(startup) = &message {
+            protocol_negotiation(client, startup).await?;
+            save_startup_parameters_to_metadata(client, startup);
+            finish_authentication(client, &ServerParameters::default()).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Startup handler of the server, selected by the server options
+///
+/// Negotiates the protocol version with clients asking for a newer one
+/// before handing the startup message over to the selected handler.
+pub enum DfStartupHandler {
+    /// Accept every connection without authentication
+    Simple(SimpleStartupHandler),
+    /// Authenticate with a JSON Web Token sent as password
+    #[cfg(feature = "jwt")]
+    Jwt(JwtStartupHandler),
+    /// Authenticate with a cleartext password checked by an `AuthSource`
+    Password(PasswordStartupHandler),
+    /// Authenticate with SCRAM-SHA-256 against the credentials of an
+    /// `AuthSource`
+    Scram(SASLScramAuthStartupHandler<ScramAuthSource, ServerParameters>),
+    /// Authenticate with the TLS client certificate of the connection
+    Cert(CertStartupHandler),
+}
+
+#[async_trait]
+impl StartupHandler for DfStartupHandler {
+    async fn on_startup<C>(
+        &self,
+        client: &mut C,
+        message: PgWireFrontendMessage,
+    ) -> PgWireResult<()>
+    where
+        C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send,
+        C::Error: Debug,
+        PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    {
+        let mut message = message;
+        if let PgWireFrontendMessage::Startup(startup) = &mut message {
+            negotiate_protocol(client, startup).await?;
+        }
+        match self {
+            DfStartupHandler::Simple(handler) => handler.on_startup(client, message).await,
+            #[cfg(feature = "jwt")]
+            DfStartupHandler::Jwt(handler) => handler.on_startup(client, message).await,
+            DfStartupHandler::Password(handler) => handler.on_startup(client, message).await,
+            DfStartupHandler::Scram(handler) => handler.on_startup(client, message).await,
+            DfStartupHandler::Cert(handler) => handler.on_startup(client, message).await,
+        }
+    }
+}
+
+/// Startup handler recording the startup and authentication phases
+///
+/// Wraps the startup handler of a session service to trace and debug log
+/// startup with its settings.
+pub struct TracedStartupHandler {
+    inner: Arc<DfStartupHandler>,
+    telemetry: Telemetry,
+    wire_debug: WireDebug,
+    activity: Arc<ActivityRegistry>,
+    connection_listeners: ConnectionListeners,
+    /// Whether a replication handler serves replication connections
+    serves_replication: bool,
+    hba_rules: Arc<Vec<HbaRule>>,
+    db_role_settings: Arc<DbRoleSettings>,
+}
+
+impl TracedStartupHandler {
+    pub fn new(inner: Arc<DfStartupHandler>, session_service: &DfSessionService) -> Self {
+        TracedStartupHandler {
+            inner,
+            telemetry: session_service.telemetry.clone(),
+            wire_debug: session_service.wire_debug,
+            activity: session_service.activity.clone(),
+            connection_listeners: session_service.connection_listeners.clone(),
+            serves_replication: session_service.replication_handler.is_some(),
+            hba_rules: session_service.hba_rules.clone(),
+            db_role_settings: session_service.db_role_settings.clone(),
+        }
+    }
+
+    /// Start the session of a client that completed its startup with the
+    /// setting defaults of its role and database, except those it sent
+    async fn apply_setting_defaults<C>(&self, client: &mut C) -> PgWireResult<()>
+    where
+        C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send,
+        PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    {
+        let metadata = client.metadata();
+        let user = metadata.get(METADATA_USER).cloned().unwrap_or_default();
+        let database = metadata.get(METADATA_DATABASE).unwrap_or(&user);
+        let mut sources = self
+            .db_role_settings
+            .session_default_sources(database, &user);
+        sources.retain(|name, _| !metadata.contains_key(name));
+        let mut defaults = sources
+            .iter()
+            .map(|(name, (value, _))| (name.clone(), value.clone()))
+            .collect::<BTreeMap<_, _>>();
+        if let Some(timeout) = metadata.get("statement_timeout") {
+            defaults.insert("statement_timeout".to_string(), timeout.clone());
+        }
+        // pg_settings shows them as the values RESET goes back to
+        if !sources.is_empty() {
+            let sources = serde_json::to_string(&sources).unwrap_or_default();
+            client
+                .metadata_mut()
+                .insert(METADATA_SETTING_DEFAULTS.to_string(), sources);
+        }
+
+        for (name, value) in defaults {
+            match name.as_str() {
+                "statement_timeout" => {
+                    if let Ok(timeout) = statement_timeout(&value) {
+                        DfSessionService::set_statement_timeout(client, timeout);
+                    }
+                }
+                METADATA_TIME_ZONE => {
+                    client.metadata_mut().insert(name.clone(), value.clone());
+                    client
+                        .send(PgWireBackendMessage::ParameterStatus(ParameterStatus::new(
+                            name, value,
+                        )))
+                        .await?;
+                }
+                _ => {
+                    client.metadata_mut().insert(name, value);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Refuse clients no connection rule lets connect, before they
+    /// authenticate
+    fn check_hba_rules<C: ClientInfo>(
+        &self,
+        client: &C,
+        message: &PgWireFrontendMessage,
+    ) -> PgWireResult<()> {
+        let PgWireFrontendMessage::Startup(startup) = message else {
+            return Ok(());
+        };
+        let user = startup.parameters.get("user").map_or("", String::as_str);
+        let database = startup
+            .parameters
+            .get("database")
+            .map_or(user, String::as_str);
+        let client_addr = self.activity.client_addr(&client.socket_addr());
+        check_connection(
+            &self.hba_rules,
+            client_addr.ip(),
+            client.is_secure(),
+            user,
+            database,
+        )
+    }
+
+    /// Refuse the replication connections no replication handler serves,
+    /// before they authenticate
+    fn check_replication(&self, message: &PgWireFrontendMessage) -> PgWireResult<()> {
+        let PgWireFrontendMessage::Startup(startup) = message else {
+            return Ok(());
+        };
+        let Some(value) = startup.parameters.get(REPLICATION_PARAMETER) else {
+            return Ok(());
+        };
+        match ReplicationMode::from_parameter(value)? {
+            Some(_) if !self.serves_replication => Err(replication_not_supported()),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[async_trait]
+impl StartupHandler for TracedStartupHandler {
+    async fn on_startup<C>(
+        &self,
+        client: &mut C,
+        message: PgWireFrontendMessage,
+    ) -> PgWireResult<()>
+    where
+        C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send,
+        C::Error: Debug,
+        PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    {
+        let mut client = WireDebugClient::new(client, self.wire_debug);
+        client.log_startup(&message);
+        let phase = match message {
+            PgWireFrontendMessage::PasswordMessageFamily(_) => Phase::Auth,
+            _ => Phase::Startup,
+        };
+        let mut span = self.telemetry.phase(&client, phase);
+        let checked = self
+            .check_hba_rules(&client, &message)
+            .and_then(|()| self.check_replication(&message));
+        let starting = !matches!(client.state(), PgWireConnectionState::ReadyForQuery);
+        let mut result = match checked {
+            Ok(()) => self.inner.on_startup(&mut client, message).await,
+            Err(error) => Err(error),
+        };
+        let started = matches!(client.state(), PgWireConnectionState::ReadyForQuery);
+        if result.is_ok() && starting && started {
+            result = self.apply_setting_defaults(&mut client).await;
+        }
+        span.record_session(&client);
+        if let Err(error) = &result {
+            let peer = client.socket_addr();
+            let mut event = ConnectionEvent::new(peer, self.activity.session(&peer));
+            event.user = client.metadata().get(METADATA_USER).cloned();
+            self.connection_listeners.on_startup_error(&event, error);
+        }
+        result
+    }
+}
 
+/// The handlers of the server, serving connections with `process_socket` of
+/// pgwire
+///
+/// Applications running their own pgwire server can instead mount the
+/// handlers next to their own: [`DfSessionService`] handles simple and
+/// extended queries, [`TracedStartupHandler`], [`DfCopyHandler`] and
+/// [`DfErrorHandler`] the other messages. Such servers call
+/// `DfSessionService::start_session` and `DfSessionService::end_session`
+/// around each connection.
 pub struct HandlerFactory {
     pub session_service: Arc<DfSessionService>,
+    pub startup_handler: Arc<DfStartupHandler>,
 }
 
 impl HandlerFactory {
-    pub fn new(session_context: Arc<SessionContext>, auth_manager: Arc<AuthManager>) -> Self {
-        let session_service =
-            Arc::new(DfSessionService::new(session_context, auth_manager.clone()));
-        HandlerFactory { session_service }
+    pub fn new(session_service: DfSessionService) -> Self {
+        HandlerFactory {
+            session_service: Arc::new(session_service),
+            startup_handler: Arc::new(DfStartupHandler::Simple(SimpleStartupHandler)),
+        }
+    }
+
+    /// Authenticate clients with JSON Web Tokens
+    #[cfg(feature = "jwt")]
+    pub fn with_jwt_authenticator(mut self, authenticator: Arc<JwtAuthenticator>) -> Self {
+        self.startup_handler = Arc::new(DfStartupHandler::Jwt(JwtStartupHandler::new(
+            authenticator,
+            self.session_service.auth_manager.clone(),
+        )));
+        self
+    }
+
+    /// Authenticate clients with passwords checked against `auth_source`
+    pub fn with_auth_source(mut self, auth_source: Arc<dyn AuthSource>) -> Self {
+        self.startup_handler = Arc::new(DfStartupHandler::Password(PasswordStartupHandler::new(
+            auth_source,
+        )));
+        self
+    }
+
+    /// Authenticate clients with SCRAM-SHA-256 against the credentials of
+    /// `auth_source`
+    pub fn with_scram_auth_source(mut self, auth_source: Arc<dyn AuthSource>) -> Self {
+        self.startup_handler = Arc::new(DfStartupHandler::Scram(SASLScramAuthStartupHandler::new(
+            Arc::new(ScramAuthSource(auth_source)),
+            Arc::new(ServerParameters::default()),
+        )));
+        self
+    }
+
+    /// Authenticate clients with SCRAM-SHA-256 against the credentials of
+    /// `auth_source`, also offering SCRAM-SHA-256-PLUS to clients on TLS
+    /// connections, which binds authentication to the TLS channel of the
+    /// server certificates `certs_pem`
+    pub fn with_scram_plus_auth_source(
+        mut self,
+        auth_source: Arc<dyn AuthSource>,
+        certs_pem: &[u8],
+    ) -> PgWireResult<Self> {
+        let mut handler = SASLScramAuthStartupHandler::new(
+            Arc::new(ScramAuthSource(auth_source)),
+            Arc::new(ServerParameters::default()),
+        );
+        handler.configure_certificate(certs_pem)?;
+        self.startup_handler = Arc::new(DfStartupHandler::Scram(handler));
+        Ok(self)
+    }
+
+    /// Authenticate clients with their TLS client certificates, the users
+    /// of `auth_source` the certificates are issued to by `config`
+    pub fn with_cert_auth(
+        mut self,
+        auth_source: Arc<dyn AuthSource>,
+        config: CertAuthConfig,
+    ) -> Self {
+        self.startup_handler = Arc::new(DfStartupHandler::Cert(CertStartupHandler::new(
+            auth_source,
+            config,
+        )));
+        self
     }
 }
 
@@ -63,32 +497,163 @@ impl PgWireServerHandlers for HandlerFactory {
     }
 
     fn startup_handler(&self) -> Arc<impl StartupHandler> {
-        Arc::new(SimpleStartupHandler)
+        Arc::new(TracedStartupHandler::new(
+            self.startup_handler.clone(),
+            &self.session_service,
+        ))
+    }
+
+    fn copy_handler(&self) -> Arc<impl CopyHandler> {
+        Arc::new(DfCopyHandler::new(self.session_service.clone()))
     }
 
     fn error_handler(&self) -> Arc<impl ErrorHandler> {
-        Arc::new(LoggingErrorHandler)
+        Arc::new(DfErrorHandler::new(&self.session_service))
+    }
+}
+
+/// Copy handler of the server, inserting the data of `COPY ... FROM STDIN`
+/// into the tables of the [`DfSessionService`] that started the copy
+pub struct DfCopyHandler {
+    session_service: Arc<DfSessionService>,
+}
+
+impl DfCopyHandler {
+    pub fn new(session_service: Arc<DfSessionService>) -> Self {
+        DfCopyHandler { session_service }
+    }
+
+    fn unexpected(message: &str) -> PgWireError {
+        PgWireError::UserError(Box::new(ErrorInfo::new(
+            "ERROR".to_string(),
+            "08P01".to_string(), // protocol_violation
+            format!("unexpected {message} message, no COPY FROM STDIN is in progress"),
+        )))
+    }
+}
+
+#[async_trait]
+impl CopyHandler for DfCopyHandler {
+    async fn on_copy_data<C>(&self, client: &mut C, copy_data: CopyData) -> PgWireResult<()>
+    where
+        C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+        C::Error: Debug,
+        PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    {
+        match self
+            .session_service
+            .copies
+            .append(&client.socket_addr(), &copy_data.data)
+        {
+            true => Ok(()),
+            false => Err(Self::unexpected("CopyData")),
+        }
+    }
+
+    async fn on_copy_done<C>(&self, client: &mut C, _done: CopyDone) -> PgWireResult<()>
+    where
+        C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+        C::Error: Debug,
+        PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    {
+        let Some(copy) = self.session_service.copies.finish(&client.socket_addr()) else {
+            return Err(Self::unexpected("CopyDone"));
+        };
+        let rows = self.session_service.finish_copy_in(client, copy).await?;
+        client
+            .send(PgWireBackendMessage::CommandComplete(
+                Tag::new("COPY").with_rows(rows).into(),
+            ))
+            .await?;
+        Ok(())
+    }
+
+    async fn on_copy_fail<C>(&self, client: &mut C, fail: CopyFail) -> PgWireError
+    where
+        C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+        C::Error: Debug,
+        PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    {
+        if self
+            .session_service
+            .copies
+            .finish(&client.socket_addr())
+            .is_none()
+        {
+            return Self::unexpected("CopyFail");
+        }
+        PgWireError::UserError(Box::new(ErrorInfo::new(
+            "ERROR".to_string(),
+            "57014".to_string(), // query_canceled
+            format!("COPY from stdin failed: {}", fail.message),
+        )))
     }
 }
 
-struct LoggingErrorHandler;
+/// Error handler assigning SQLSTATE codes to the errors sent to clients and
+/// logging them with the identifier of the failed statement
+pub struct DfErrorHandler {
+    activity: Arc<ActivityRegistry>,
+}
+
+impl DfErrorHandler {
+    pub fn new(session_service: &DfSessionService) -> Self {
+        DfErrorHandler {
+            activity: session_service.activity_registry().clone(),
+        }
+    }
+}
 
-impl ErrorHandler for LoggingErrorHandler {
-    fn on_error<C>(&self, _client: &C, error: &mut PgWireError)
+impl ErrorHandler for DfErrorHandler {
+    fn on_error<C>(&self, client: &C, error: &mut PgWireError)
     where
         C: ClientInfo,
     {
-        info!("Sending error: {error}")
+        let untranslated = std::mem::replace(error, PgWireError::InvalidStartupMessage);
+        *error = into_sqlstate_error(untranslated);
+        // the identifier of the failed statement correlates the error with
+        // pg_stat_activity and EXPLAIN
+        match self.activity.query_id(&client.socket_addr()) {
+            Some(query_id) => info!("Sending error for query {query_id}: {error}"),
+            None => info!("Sending error: {error}"),
+        }
     }
 }
 
 /// The pgwire handler backed by a datafusion `SessionContext`
 pub struct DfSessionService {
-    session_context: Arc<SessionContext>,
+    session_contexts: Arc<SessionContexts>,
     parser: Arc<Parser>,
-    timezone: Arc<Mutex<String>>,
     auth_manager: Arc<AuthManager>,
+    authorizer: Arc<dyn Authorizer>,
     sql_rewrite_rules: Vec<Arc<dyn SqlStatementRewriteRule>>,
+    statement_rate_limiter: Option<Arc<RateLimiter<(String, IpAddr)>>>,
+    /// Rules of the client addresses that may connect with or without TLS
+    hba_rules: Arc<Vec<HbaRule>>,
+    encode_options: EncodeOptions,
+    plan_cache: Option<Arc<PlanCache>>,
+    result_cache: Option<Arc<ResultCache>>,
+    scheduler: Arc<StatementScheduler>,
+    activity: Arc<ActivityRegistry>,
+    statistics: Arc<StatisticsRegistry>,
+    db_role_settings: Arc<DbRoleSettings>,
+    advisory_locks: Arc<AdvisoryLocks>,
+    telemetry: Telemetry,
+    progress_notice_interval: Option<Duration>,
+    wire_debug: WireDebug,
+    query_hooks: QueryHooks,
+    connection_listeners: ConnectionListeners,
+    notifications: Arc<Notifications>,
+    prepared_statements: Arc<PreparedStatements>,
+    copies: Arc<CopyIns>,
+    replication_handler: Option<Arc<dyn ReplicationHandler>>,
+    storage_credentials: Arc<StorageCredentials>,
+    /// Directory of the local files `COPY ... TO` may write
+    copy_export_root: Option<PathBuf>,
+    /// Directory of the local files `CREATE EXTERNAL TABLE` may read
+    import_root: Option<PathBuf>,
+    #[cfg(feature = "postgres-fdw")]
+    foreign_servers: Arc<ForeignServers>,
 }
 
 impl DfSessionService {
@@ -102,807 +667,8664 @@ impl DfSessionService {
             Arc::new(BlacklistSqlRewriter::new()),
             Arc::new(AliasDuplicatedProjectionRewrite),
             Arc::new(ResolveUnqualifiedIdentifer),
+            Arc::new(ResolveRegclassLiteral),
             Arc::new(RemoveUnsupportedTypes::new()),
             Arc::new(RewriteArrayAnyAllOperation),
+            Arc::new(RewriteExtract),
+            Arc::new(RewriteJsonSetFunctions),
+            Arc::new(RewriteWindowFilter),
             Arc::new(PrependUnqualifiedPgTableName),
             Arc::new(FixArrayLiteral),
             Arc::new(RemoveTableFunctionQualifier),
+            Arc::new(RemoveFunctionQualifier),
+            Arc::new(InlineLateralProjection),
+            Arc::new(RemoveUnsupportedClauses),
         ];
+        let activity = Arc::new(ActivityRegistry::new());
+        let session_contexts = Arc::new(SessionContexts::new(session_context, activity.clone()));
         let parser = Arc::new(Parser {
-            session_context: session_context.clone(),
+            session_contexts: session_contexts.clone(),
             sql_rewrite_rules: sql_rewrite_rules.clone(),
+            authorizer: auth_manager.clone(),
+            plan_cache: None,
+            telemetry: Telemetry::default(),
+            query_hooks: QueryHooks::default(),
         });
         DfSessionService {
-            session_context,
+            session_contexts,
             parser,
-            timezone: Arc::new(Mutex::new("UTC".to_string())),
+            authorizer: auth_manager.clone(),
             auth_manager,
             sql_rewrite_rules,
+            statement_rate_limiter: None,
+            hba_rules: Arc::new(vec![]),
+            encode_options: EncodeOptions::default(),
+            plan_cache: None,
+            result_cache: None,
+            scheduler: Arc::new(StatementScheduler::new()),
+            activity,
+            statistics: Arc::new(StatisticsRegistry::new()),
+            db_role_settings: Arc::new(DbRoleSettings::new()),
+            advisory_locks: Arc::new(AdvisoryLocks::default()),
+            telemetry: Telemetry::default(),
+            progress_notice_interval: None,
+            wire_debug: WireDebug::Off,
+            query_hooks: QueryHooks::default(),
+            connection_listeners: ConnectionListeners::default(),
+            notifications: Arc::new(Notifications::default()),
+            prepared_statements: Arc::new(PreparedStatements::default()),
+            copies: Arc::new(CopyIns::default()),
+            replication_handler: None,
+            storage_credentials: Arc::new(StorageCredentials::default()),
+            copy_export_root: None,
+            import_root: None,
+            #[cfg(feature = "postgres-fdw")]
+            foreign_servers: Arc::new(ForeignServers::default()),
         }
     }
 
-    /// Get statement timeout from client metadata
-    fn get_statement_timeout<C>(client: &C) -> Option<std::time::Duration>
-    where
-        C: ClientInfo,
-    {
-        client
-            .metadata()
-            .get(METADATA_STATEMENT_TIMEOUT)
-            .and_then(|s| s.parse::<u64>().ok())
-            .map(std::time::Duration::from_millis)
+    /// Record the activity of sessions in the registry, shared with the
+    /// server to track connections
+    pub fn with_activity_registry(mut self, activity: Arc<ActivityRegistry>) -> Self {
+        self.session_contexts = Arc::new(
+            self.session_contexts
+                .with_activity_registry(activity.clone()),
+        );
+        self.activity = activity;
+        self.rebuild_parser();
+        self
     }
 
-    /// Set statement timeout in client metadata
-    fn set_statement_timeout<C>(client: &mut C, timeout: Option<std::time::Duration>)
-    where
-        C: ClientInfo,
-    {
-        let metadata = client.metadata_mut();
-        if let Some(duration) = timeout {
-            metadata.insert(
-                METADATA_STATEMENT_TIMEOUT.to_string(),
-                duration.as_millis().to_string(),
-            );
-        } else {
-            metadata.remove(METADATA_STATEMENT_TIMEOUT);
-        }
+    /// Serve each connection with a `SessionContext` of its own, created by
+    /// `factory` when the connection runs its first statement
+    ///
+    /// Contexts are dropped when their connection closes if the connections
+    /// are served by `DfPostgresServer`.
+    pub fn with_session_context_factory(mut self, factory: Arc<dyn SessionContextFactory>) -> Self {
+        self.session_contexts = Arc::new(self.session_contexts.with_factory(factory));
+        self.rebuild_parser();
+        self
     }
 
-    /// Check if the current user has permission to execute a query
-    async fn check_query_permission<C>(&self, client: &C, query: &str) -> PgWireResult<()>
-    where
-        C: ClientInfo,
-    {
-        // Get the username from client metadata
-        let username = client
-            .metadata()
-            .get("user")
-            .map(|s| s.as_str())
-            .unwrap_or("anonymous");
+    /// Restrict each connection to the catalogs, name resolution and limits
+    /// of the tenant `resolver` resolves for it
+    ///
+    /// Like contexts of a `SessionContextFactory`, which the tenant
+    /// restricts when both are set, tenants are forgotten when their
+    /// connection closes.
+    pub fn with_tenant_resolver(mut self, resolver: Arc<dyn TenantResolver>) -> Self {
+        self.session_contexts = Arc::new(self.session_contexts.with_tenant_resolver(resolver));
+        self.rebuild_parser();
+        self
+    }
 
-        // Parse query to determine required permissions
-        let query_lower = query.to_lowercase();
-        let query_trimmed = query_lower.trim();
+    /// The `SessionContext` serving the statements of `client`
+    pub fn session_context<C: ClientInfo>(&self, client: &C) -> Arc<SessionContext> {
+        self.session_contexts.for_client(client)
+    }
 
-        let (required_permission, resource) = if query_trimmed.starts_with("select") {
-            (Permission::Select, self.extract_table_from_query(query))
-        } else if query_trimmed.starts_with("insert") {
-            (Permission::Insert, self.extract_table_from_query(query))
-        } else if query_trimmed.starts_with("update") {
-            (Permission::Update, self.extract_table_from_query(query))
-        } else if query_trimmed.starts_with("delete") {
-            (Permission::Delete, self.extract_table_from_query(query))
-        } else if query_trimmed.starts_with("create table")
-            || query_trimmed.starts_with("create view")
-        {
-            (Permission::Create, ResourceType::All)
-        } else if query_trimmed.starts_with("drop") {
-            (Permission::Drop, self.extract_table_from_query(query))
-        } else if query_trimmed.starts_with("alter") {
-            (Permission::Alter, self.extract_table_from_query(query))
-        } else {
-            // For other queries (SHOW, EXPLAIN, etc.), allow all users
-            return Ok(());
-        };
+    /// Track the session of a new connection in `pg_stat_activity`
+    pub fn start_session(&self, client_addr: SocketAddr) {
+        self.start_proxied_session(client_addr, client_addr);
+    }
 
-        // Check permission
-        let has_permission = self
-            .auth_manager
-            .check_permission(username, required_permission, resource)
-            .await;
+    /// Track the session of a new connection from `peer` a proxy forwards
+    /// for the client at `client_addr`, which `pg_stat_activity`, connection
+    /// listeners and rate limits see in place of the proxy
+    pub fn start_proxied_session(&self, peer: SocketAddr, client_addr: SocketAddr) {
+        self.activity.start_proxied_session(peer, client_addr);
+        self.connection_listeners
+            .on_connect(&self.connection_event(&peer));
+    }
 
-        if !has_permission {
-            return Err(PgWireError::UserError(Box::new(
-                pgwire::error::ErrorInfo::new(
-                    "ERROR".to_string(),
-                    "42501".to_string(), // insufficient_privilege
-                    format!("permission denied for user \"{username}\""),
-                ),
-            )));
+    /// Forget the session of a closed connection
+    pub fn end_session(&self, client_addr: &SocketAddr) {
+        self.connection_listeners
+            .on_disconnect(&self.connection_event(client_addr));
+        if let Some(session) = self.activity.session(client_addr) {
+            self.advisory_locks.end_session(session.pid);
         }
+        self.activity.end_session(client_addr);
+        self.session_contexts.remove(client_addr);
+        self.auth_manager.end_session(client_addr);
+        self.notifications.end_session(client_addr);
+        self.prepared_statements.end_session(client_addr);
+        self.copies.finish(client_addr);
+    }
 
-        Ok(())
+    fn connection_event(&self, client_addr: &SocketAddr) -> ConnectionEvent {
+        ConnectionEvent::new(*client_addr, self.activity.session(client_addr))
     }
 
-    /// Extract table name from query (simplified parsing)
-    fn extract_table_from_query(&self, query: &str) -> ResourceType {
-        let words: Vec<&str> = query.split_whitespace().collect();
+    /// The `SessionContext` of the server, serving connections without a
+    /// context of their own
+    pub fn default_session_context(&self) -> &Arc<SessionContext> {
+        self.session_contexts.default_context()
+    }
 
-        // Simple heuristic to find table names
-        for (i, word) in words.iter().enumerate() {
-            let word_lower = word.to_lowercase();
-            if (word_lower == "from" || word_lower == "into" || word_lower == "table")
-                && i + 1 < words.len()
-            {
-                let table_name = words[i + 1].trim_matches(|c| c == '(' || c == ')' || c == ';');
-                return ResourceType::Table(table_name.to_string());
-            }
-        }
+    /// Notify the sessions listening on `channel`
+    pub fn notify(&self, channel: &str, payload: &str) {
+        self.notifications.notify(0, channel, payload);
+    }
 
-        // If we can't determine the table, default to All
-        ResourceType::All
+    pub fn activity_registry(&self) -> &Arc<ActivityRegistry> {
+        &self.activity
     }
 
-    fn mock_show_response<'a>(name: &str, value: &str) -> PgWireResult<QueryResponse<'a>> {
-        let fields = vec![FieldInfo::new(
-            name.to_string(),
-            None,
-            None,
-            Type::VARCHAR,
-            FieldFormat::Text,
-        )];
+    /// The statistics `ANALYZE` computed of tables
+    pub fn statistics_registry(&self) -> &Arc<StatisticsRegistry> {
+        &self.statistics
+    }
 
-        let row = {
-            let mut encoder = pgwire::api::results::DataRowEncoder::new(Arc::new(fields.clone()));
-            encoder.encode_field(&Some(value))?;
-            encoder.finish()
-        };
+    /// Keep the setting defaults of `ALTER ROLE ... SET` and `ALTER DATABASE
+    /// ... SET` in `settings`, e.g. to preset them
+    pub fn with_db_role_settings(mut self, settings: Arc<DbRoleSettings>) -> Self {
+        self.db_role_settings = settings;
+        self
+    }
 
-        let row_stream = futures::stream::once(async move { row });
-        Ok(QueryResponse::new(Arc::new(fields), Box::pin(row_stream)))
+    /// The setting defaults of roles and databases, applied to new sessions
+    pub fn db_role_settings(&self) -> &Arc<DbRoleSettings> {
+        &self.db_role_settings
     }
 
-    async fn try_respond_set_statements<'a, C>(
+    /// Admit statements through the scheduler, e.g. to share a limited
+    /// number of execution slots by weight
+    pub fn with_scheduler(mut self, scheduler: Arc<StatementScheduler>) -> Self {
+        self.scheduler = scheduler;
+        self
+    }
+
+    /// Authorize statements with `authorizer` instead of the grants and
+    /// statement rules of the `AuthManager`
+    pub fn with_authorizer(mut self, authorizer: Arc<dyn Authorizer>) -> Self {
+        self.authorizer = authorizer;
+        self.rebuild_parser();
+        self
+    }
+
+    /// Cache the logical plans of queries
+    pub fn with_plan_cache(mut self, plan_cache: Arc<PlanCache>) -> Self {
+        self.plan_cache = Some(plan_cache);
+        self.rebuild_parser();
+        self
+    }
+
+    /// Serve the results of repeated read-only queries from `result_cache`
+    pub fn with_result_cache(mut self, result_cache: Arc<ResultCache>) -> Self {
+        self.result_cache = Some(result_cache);
+        self
+    }
+
+    /// Hand the spans of request phases to `exporter`, in addition to
+    /// emitting them through `tracing`
+    pub fn with_span_exporter(mut self, exporter: Arc<dyn SpanExporter>) -> Self {
+        self.telemetry = Telemetry::new(Some(exporter));
+        self.rebuild_parser();
+        self
+    }
+
+    /// Send a progress notice every `interval` while a query hasn't produced
+    /// its first row
+    pub fn with_progress_notices(mut self, interval: Duration) -> Self {
+        self.progress_notice_interval = Some(interval);
+        self
+    }
+
+    /// Log the protocol messages of all sessions, sessions may still switch
+    /// it with `SET wire_debug`
+    pub fn with_wire_debug(mut self, wire_debug: WireDebug) -> Self {
+        self.wire_debug = wire_debug;
+        self
+    }
+
+    /// Report the progress of the query of `resp` until its first row, if
+    /// progress notices are enabled
+    async fn notify_progress<'a, C>(
         &self,
         client: &mut C,
-        query_lower: &str,
-    ) -> PgWireResult<Option<Response<'a>>>
+        resp: QueryResponse<'a>,
+        plan: Arc<dyn ExecutionPlan>,
+    ) -> PgWireResult<QueryResponse<'a>>
     where
-        C: ClientInfo,
+        C: Sink<PgWireBackendMessage> + Unpin + Send,
+        PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
     {
-        if query_lower.starts_with("set") {
-            if query_lower.starts_with("set time zone") {
-                let parts: Vec<&str> = query_lower.split_whitespace().collect();
-                if parts.len() >= 4 {
-                    let tz = parts[3].trim_matches('"');
-                    let mut timezone = self.timezone.lock().await;
-                    *timezone = tz.to_string();
-                    Ok(Some(Response::Execution(Tag::new("SET"))))
-                } else {
-                    Err(PgWireError::UserError(Box::new(
-                        pgwire::error::ErrorInfo::new(
-                            "ERROR".to_string(),
-                            "42601".to_string(),
-                            "Invalid SET TIME ZONE syntax".to_string(),
-                        ),
-                    )))
-                }
-            } else if query_lower.starts_with("set statement_timeout") {
-                let parts: Vec<&str> = query_lower.split_whitespace().collect();
-                if parts.len() >= 3 {
-                    let timeout_str = parts[2].trim_matches('"').trim_matches('\'');
+        match self.progress_notice_interval {
+            Some(interval) => progress::notify_until_first_row(client, resp, plan, interval).await,
+            None => Ok(resp),
+        }
+    }
 
-                    let timeout = if timeout_str == "0" || timeout_str.is_empty() {
-                        None
-                    } else {
-                        // Parse timeout value (supports ms, s, min formats)
-                        let timeout_ms = if timeout_str.ends_with("ms") {
-                            timeout_str.trim_end_matches("ms").parse::<u64>()
-                        } else if timeout_str.ends_with("s") {
-                            timeout_str
-                                .trim_end_matches("s")
-                                .parse::<u64>()
-                                .map(|s| s * 1000)
-                        } else if timeout_str.ends_with("min") {
-                            timeout_str
-                                .trim_end_matches("min")
-                                .parse::<u64>()
-                                .map(|m| m * 60 * 1000)
-                        } else {
-                            // Default to milliseconds
-                            timeout_str.parse::<u64>()
-                        };
+    /// Tell `listener` when connections open and close and when clients fail
+    /// to authenticate, after the listeners registered before
+    pub fn with_connection_listener(mut self, listener: Arc<dyn ConnectionListener>) -> Self {
+        self.connection_listeners.push(listener);
+        self
+    }
 
-                        match timeout_ms {
-                            Ok(ms) if ms > 0 => Some(std::time::Duration::from_millis(ms)),
-                            _ => None,
-                        }
-                    };
+    /// Serve the connections of clients asking for replication with
+    /// `handler`, instead of refusing them at startup
+    pub fn with_replication_handler(mut self, handler: Arc<dyn ReplicationHandler>) -> Self {
+        self.replication_handler = Some(handler);
+        self
+    }
 
-                    Self::set_statement_timeout(client, timeout);
-                    Ok(Some(Response::Execution(Tag::new("SET"))))
-                } else {
-                    Err(PgWireError::UserError(Box::new(
-                        pgwire::error::ErrorInfo::new(
-                            "ERROR".to_string(),
-                            "42601".to_string(),
-                            "Invalid SET statement_timeout syntax".to_string(),
-                        ),
-                    )))
-                }
-            } else {
-                // pass SET query to datafusion
-                if let Err(e) = self.session_context.sql(query_lower).await {
-                    warn!("SET statement {query_lower} is not supported by datafusion, error {e}, statement ignored");
-                }
+    /// Call `hook` around every statement, after the hooks registered before
+    pub fn with_query_hook(mut self, hook: Arc<dyn QueryHook>) -> Self {
+        self.query_hooks.push(hook);
+        self.rebuild_parser();
+        self
+    }
 
-                // Always return SET success
-                Ok(Some(Response::Execution(Tag::new("SET"))))
-            }
-        } else {
-            Ok(None)
-        }
+    /// Share the authorizer, plan cache, telemetry and hooks with the parser
+    /// of the extended query protocol
+    fn rebuild_parser(&mut self) {
+        self.parser = Arc::new(Parser {
+            session_contexts: self.session_contexts.clone(),
+            sql_rewrite_rules: self.sql_rewrite_rules.clone(),
+            authorizer: self.authorizer.clone(),
+            plan_cache: self.plan_cache.clone(),
+            telemetry: self.telemetry.clone(),
+            query_hooks: self.query_hooks.clone(),
+        });
     }
 
-    async fn try_respond_transaction_statements<'a, C>(
+    /// Plan and execute the statement, taking plans of queries from the plan
+    /// cache
+    async fn execute_statement<C>(
         &self,
         client: &C,
-        query_lower: &str,
-    ) -> PgWireResult<Option<Response<'a>>>
+        statement: SqlStatement,
+    ) -> PgWireResult<DataFrame>
     where
         C: ClientInfo,
     {
-        // Transaction handling based on pgwire example:
-        // https://github.com/sunng87/pgwire/blob/master/examples/transaction.rs#L57
-        match query_lower.trim() {
-            "begin" | "begin transaction" | "begin work" | "start transaction" => {
-                match client.transaction_status() {
-                    TransactionStatus::Idle => {
-                        Ok(Some(Response::TransactionStart(Tag::new("BEGIN"))))
-                    }
-                    TransactionStatus::Transaction => {
-                        // PostgreSQL behavior: ignore nested BEGIN, just return SUCCESS
-                        // This matches PostgreSQL's handling of nested transaction blocks
-                        log::warn!("BEGIN command ignored: already in transaction block");
-                        Ok(Some(Response::Execution(Tag::new("BEGIN"))))
-                    }
-                    TransactionStatus::Error => {
-                        // Can't start new transaction from failed state
-                        Err(PgWireError::UserError(Box::new(
-                            pgwire::error::ErrorInfo::new(
-                                "ERROR".to_string(),
-                                "25P01".to_string(),
-                                "current transaction is aborted, commands ignored until end of transaction block".to_string(),
-                            ),
-                        )))
-                    }
-                }
-            }
-            "commit" | "commit transaction" | "commit work" | "end" | "end transaction" => {
-                match client.transaction_status() {
-                    TransactionStatus::Idle | TransactionStatus::Transaction => {
-                        Ok(Some(Response::TransactionEnd(Tag::new("COMMIT"))))
-                    }
-                    TransactionStatus::Error => {
-                        Ok(Some(Response::TransactionEnd(Tag::new("ROLLBACK"))))
-                    }
-                }
-            }
-            "rollback" | "rollback transaction" | "rollback work" | "abort" => {
-                Ok(Some(Response::TransactionEnd(Tag::new("ROLLBACK"))))
+        let session_context = self.session_context(client);
+        let state = session_state(&session_context, client);
+        let invalidates_plans = invalidates_plans(&statement);
+        if let Some((insert, returning)) = split_returning(&statement) {
+            return self
+                .execute_insert_returning(client, insert, &returning, None)
+                .await;
+        }
+        if let Some(copy_to) = copy_to_statement(&statement) {
+            return self.execute_copy_to(client, copy_to?).await;
+        }
+        let plan = match &self.plan_cache {
+            Some(plan_cache) if matches!(statement, SqlStatement::Query(_)) => {
+                plan_cache.get_or_plan(&state, client, statement).await
             }
-            _ => Ok(None),
+            _ => statement_to_plan(&state, statement).await,
+        }
+        .map_err(|e| PgWireError::ApiError(Box::new(e)))?;
+        let plan = self
+            .query_hooks
+            .before_execute(&HookContext::new(client), plan)
+            .await?;
+        let plan = self.apply_row_policies(client, &state, plan).await?;
+
+        let change = invalidates_plans.then(|| schema_change(&plan));
+        let df = session_context
+            .execute_logical_plan(plan)
+            .await
+            .map_err(|e| PgWireError::ApiError(Box::new(e)));
+        if let Some(change) = change {
+            self.catalog_changed(&session_context, &change);
         }
+        df
     }
 
-    async fn try_respond_show_statements<'a, C>(
+    /// Plan and execute a `COPY ... TO`, once the session user may write its
+    /// target and read its source
+    async fn execute_copy_to<C>(
         &self,
         client: &C,
-        query_lower: &str,
-    ) -> PgWireResult<Option<Response<'a>>>
+        copy_to: CopyToStatement,
+    ) -> PgWireResult<DataFrame>
     where
         C: ClientInfo,
     {
-        if query_lower.starts_with("show ") {
-            match query_lower.strip_suffix(";").unwrap_or(query_lower) {
-                "show time zone" => {
-                    let timezone = self.timezone.lock().await.clone();
-                    let resp = Self::mock_show_response("TimeZone", &timezone)?;
-                    Ok(Some(Response::Query(resp)))
-                }
-                "show server_version" => {
-                    let resp = Self::mock_show_response("server_version", "15.0 (DataFusion)")?;
-                    Ok(Some(Response::Query(resp)))
-                }
-                "show transaction_isolation" => {
-                    let resp =
-                        Self::mock_show_response("transaction_isolation", "read uncommitted")?;
-                    Ok(Some(Response::Query(resp)))
-                }
-                "show catalogs" => {
-                    let catalogs = self.session_context.catalog_names();
-                    let value = catalogs.join(", ");
-                    let resp = Self::mock_show_response("Catalogs", &value)?;
-                    Ok(Some(Response::Query(resp)))
-                }
-                "show search_path" => {
-                    let default_schema = "public";
-                    let resp = Self::mock_show_response("search_path", default_schema)?;
-                    Ok(Some(Response::Query(resp)))
-                }
-                "show statement_timeout" => {
-                    let timeout = Self::get_statement_timeout(client);
-                    let timeout_str = match timeout {
-                        Some(duration) => format!("{}ms", duration.as_millis()),
-                        None => "0".to_string(),
-                    };
-                    let resp = Self::mock_show_response("statement_timeout", &timeout_str)?;
-                    Ok(Some(Response::Query(resp)))
-                }
-                _ => Err(PgWireError::UserError(Box::new(
-                    pgwire::error::ErrorInfo::new(
-                        "ERROR".to_string(),
-                        "42704".to_string(),
-                        format!("Unrecognized SHOW command: {query_lower}"),
-                    ),
-                ))),
-            }
-        } else {
-            Ok(None)
-        }
+        self.authorize_copy_to(client, &copy_to).await?;
+        let session_context = self.session_context(client);
+        let state = session_state(&session_context, client);
+        let plan = state
+            .statement_to_plan(Statement::CopyTo(copy_to))
+            .await
+            .map_err(|e| PgWireError::ApiError(Box::new(e)))?;
+        let plan = self
+            .query_hooks
+            .before_execute(&HookContext::new(client), plan)
+            .await?;
+        let plan = self.apply_row_policies(client, &state, plan).await?;
+        session_context
+            .execute_logical_plan(plan)
+            .await
+            .map_err(|e| PgWireError::ApiError(Box::new(e)))
     }
-}
 
-#[async_trait]
-impl SimpleQueryHandler for DfSessionService {
-    async fn do_query<'a, C>(&self, client: &mut C, query: &str) -> PgWireResult<Vec<Response<'a>>>
+    /// Insert the rows of `INSERT ... RETURNING`, answering the returned rows
+    async fn execute_insert_returning<C>(
+        &self,
+        client: &C,
+        insert: SqlStatement,
+        returning: &[SelectItem],
+        param_values: Option<&ParamValues>,
+    ) -> PgWireResult<DataFrame>
     where
-        C: ClientInfo + Unpin + Send + Sync,
+        C: ClientInfo,
     {
-        log::debug!("Received query: {query}"); // Log the query for debugging
+        let session_context = self.session_context(client);
+        let state = session_state(&session_context, client);
+        let mut plan = statement_to_plan(&state, insert)
+            .await
+            .map_err(|e| PgWireError::ApiError(Box::new(e)))?;
+        if let Some(param_values) = param_values {
+            plan = plan
+                .replace_params_with_values(param_values)
+                .map_err(|e| PgWireError::ApiError(Box::new(e)))?;
+        }
+        let plan = self
+            .query_hooks
+            .before_execute(&HookContext::new(client), plan)
+            .await?;
+        let plan = self.apply_row_policies(client, &state, plan).await?;
+        insert_returning(&session_context, plan, returning)
+            .await
+            .map_err(|e| PgWireError::ApiError(Box::new(e)))
+    }
 
-        // Check for transaction commands early to avoid SQL parsing issues with ABORT
-        let query_lower = query.to_lowercase().trim().to_string();
-        if let Some(resp) = self
-            .try_respond_transaction_statements(client, &query_lower)
-            .await?
-        {
-            return Ok(vec![resp]);
+    /// Drop cached plans and table schemas after DDL, and notify the
+    /// sessions listening for schema changes with `change`
+    pub(crate) fn catalog_changed(&self, session_context: &SessionContext, change: &str) {
+        self.notifications.notify(0, SCHEMA_CHANGED_CHANNEL, change);
+        if let Some(plan_cache) = &self.plan_cache {
+            plan_cache.clear();
+        }
+        if let Some(result_cache) = &self.result_cache {
+            result_cache.clear();
+        }
+        for catalog_name in session_context.catalog_names() {
+            let pg_catalog = session_context
+                .catalog(&catalog_name)
+                .and_then(|catalog| catalog.schema("pg_catalog"));
+            if let Some(pg_catalog) = pg_catalog
+                .as_ref()
+                .and_then(|schema| schema.as_any().downcast_ref::<PgCatalogSchemaProvider>())
+            {
+                pg_catalog.oid_registry().clear_relations();
+            }
         }
+    }
 
-        let mut statements = parse(query).map_err(|e| PgWireError::ApiError(Box::new(e)))?;
+    /// `df` reading the result of `query` from the result cache, if there's
+    /// one
+    async fn cached_result<C>(
+        &self,
+        client: &C,
+        query: String,
+        df: DataFrame,
+    ) -> PgWireResult<DataFrame>
+    where
+        C: ClientInfo,
+    {
+        let Some(result_cache) = &self.result_cache else {
+            return Ok(df);
+        };
+        let session_context = self.session_context(client);
+        let key = ResultCacheKey::new(
+            &session_state(&session_context, client),
+            authorized_user(client),
+            query,
+        );
+        result_cache
+            .dataframe(&session_context, key, df)
+            .await
+            .map_err(|e| PgWireError::ApiError(Box::new(e)))
+    }
 
-        // TODO: deal with multiple statements
-        let mut statement = statements.remove(0);
+    /// Respond to `EXPLAIN` with the postgres style plan of the explained
+    /// statement
+    async fn explain_plan<'a, C>(
+        &self,
+        client: &C,
+        plan: LogicalPlan,
+        options: &ExplainOptions,
+        query_id: i64,
+        format: &Format,
+        limits: &QueryLimits,
+    ) -> PgWireResult<QueryResponse<'a>>
+    where
+        C: ClientInfo,
+    {
+        let session_context = self.session_context(client);
+        let plan = self
+            .apply_row_policies(client, &session_context.state(), plan)
+            .await?;
+        let df = session_context
+            .execute_logical_plan(plan)
+            .await
+            .map_err(|e| PgWireError::ApiError(Box::new(e)))?;
+        let df = limits.apply_memory_limit(self.apply_session_policies(client, df).await?);
 
-        // Attempt to rewrite
-        statement = rewrite(statement, &self.sql_rewrite_rules);
+        let explained = explain::explain(df, options, query_id);
+        let batch = match Self::get_statement_timeout(client) {
+            Some(timeout) => tokio::time::timeout(timeout, explained)
+                .await
+                .map_err(|_| {
+                    PgWireError::UserError(Box::new(pgwire::error::ErrorInfo::new(
+                        "ERROR".to_string(),
+                        "57014".to_string(), // query_canceled error code
+                        "canceling statement due to statement timeout".to_string(),
+                    )))
+                })?,
+            None => explained.await,
+        }
+        .map_err(|e| map_resource_error(PgWireError::ApiError(Box::new(e))))?;
 
-        // TODO: improve statement check by using statement directly
-        let query = statement.to_string();
-        let query_lower = query.to_lowercase().trim().to_string();
+        let df = session_context
+            .read_batch(batch)
+            .map_err(|e| PgWireError::ApiError(Box::new(e)))?;
+        df::encode_dataframe_with_options(df, format, &self.encode_options(client))
+            .await
+            .map_err(map_resource_error)
+    }
 
-        // Check permissions for the query (skip for SET, transaction, and SHOW statements)
-        if !query_lower.starts_with("set")
-            && !query_lower.starts_with("begin")
-            && !query_lower.starts_with("commit")
-            && !query_lower.starts_with("rollback")
-            && !query_lower.starts_with("start")
-            && !query_lower.starts_with("end")
-            && !query_lower.starts_with("abort")
-            && !query_lower.starts_with("show")
-        {
-            self.check_query_permission(client, &query).await?;
-        }
+    /// Default batch, buffer and field sizes of query results, sessions may
+    /// override them with `SET result_batch_size`, `SET result_buffer_size`
+    /// and `SET max_field_size`
+    pub fn with_encode_options(mut self, encode_options: EncodeOptions) -> Self {
+        self.encode_options = encode_options;
+        self
+    }
 
-        if let Some(resp) = self
-            .try_respond_set_statements(client, &query_lower)
-            .await?
-        {
-            return Ok(vec![resp]);
+    /// Result encoding options of the session
+    fn encode_options<C>(&self, client: &C) -> EncodeOptions
+    where
+        C: ClientInfo,
+    {
+        let setting = |key: &str| {
+            client
+                .metadata()
+                .get(key)
+                .and_then(|s| s.parse::<usize>().ok())
+        };
+        EncodeOptions {
+            batch_size: setting(METADATA_RESULT_BATCH_SIZE).or(self.encode_options.batch_size),
+            buffer_size: setting(METADATA_RESULT_BUFFER_SIZE)
+                .unwrap_or(self.encode_options.buffer_size),
+            max_field_size: setting(METADATA_MAX_FIELD_SIZE)
+                .unwrap_or(self.encode_options.max_field_size),
+            text_format: TextFormat {
+                extra_float_digits: client
+                    .metadata()
+                    .get(METADATA_EXTRA_FLOAT_DIGITS)
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or(self.encode_options.text_format.extra_float_digits),
+                interval_style: interval_style(client),
+            },
         }
+    }
 
-        if let Some(resp) = self
-            .try_respond_show_statements(client, &query_lower)
-            .await?
-        {
-            return Ok(vec![resp]);
+    /// Store a positive size setting in client metadata, `default` resets it
+    fn set_size_setting<C>(client: &mut C, key: &str, value: &str) -> PgWireResult<()>
+    where
+        C: ClientInfo,
+    {
+        let value = value.trim_matches('\'');
+        if value == "default" {
+            client.metadata_mut().remove(key);
+            return Ok(());
         }
-
-        // Check if we're in a failed transaction and block non-transaction
-        // commands
-        if client.transaction_status() == TransactionStatus::Error {
-            return Err(PgWireError::UserError(Box::new(
+        match value.parse::<usize>() {
+            Ok(size) if size > 0 => {
+                client
+                    .metadata_mut()
+                    .insert(key.to_string(), size.to_string());
+                Ok(())
+            }
+            _ => Err(PgWireError::UserError(Box::new(
                 pgwire::error::ErrorInfo::new(
                     "ERROR".to_string(),
-                    "25P01".to_string(),
-                    "current transaction is aborted, commands ignored until end of transaction block".to_string(),
+                    "22023".to_string(), // invalid_parameter_value
+                    format!("invalid value for parameter \"{key}\": \"{value}\""),
                 ),
-            )));
+            ))),
         }
+    }
 
-        let df_result = {
-            let timeout = Self::get_statement_timeout(client);
-            if let Some(timeout_duration) = timeout {
-                tokio::time::timeout(timeout_duration, self.session_context.sql(&query))
-                    .await
-                    .map_err(|_| {
-                        PgWireError::UserError(Box::new(pgwire::error::ErrorInfo::new(
-                            "ERROR".to_string(),
-                            "57014".to_string(), // query_canceled error code
-                            "canceling statement due to statement timeout".to_string(),
-                        )))
-                    })?
-            } else {
-                self.session_context.sql(&query).await
-            }
-        };
-
-        // Handle query execution errors and transaction state
-        let df = match df_result {
-            Ok(df) => df,
-            Err(e) => {
-                return Err(PgWireError::ApiError(Box::new(e)));
-            }
-        };
+    /// Let clients connect only if one of `rules` matches their address and
+    /// whether they connect with TLS
+    pub fn with_hba_rules(mut self, rules: Vec<HbaRule>) -> Self {
+        self.hba_rules = Arc::new(rules);
+        self
+    }
 
-        if query_lower.starts_with("insert into") {
-            // For INSERT queries, we need to execute the query to get the row count
-            // and return an Execution response with the proper tag
-            let result = df
-                .clone()
-                .collect()
-                .await
-                .map_err(|e| PgWireError::ApiError(Box::new(e)))?;
+    /// Let `COPY ... TO` write local files under `root`, without it only
+    /// URLs of registered object stores are written
+    pub fn with_copy_export_root(mut self, root: impl Into<PathBuf>) -> Self {
+        self.copy_export_root = Some(root.into());
+        self
+    }
 
-            // Extract count field from the first batch
-            let rows_affected = result
-                .first()
-                .and_then(|batch| batch.column_by_name("count"))
-                .and_then(|col| {
-                    col.as_any()
-                        .downcast_ref::<datafusion::arrow::array::UInt64Array>()
-                })
-                .map_or(0, |array| array.value(0) as usize);
+    /// Let `CREATE EXTERNAL TABLE` read local files under `root`, without it
+    /// only URLs of registered object stores are read
+    pub fn with_import_root(mut self, root: impl Into<PathBuf>) -> Self {
+        self.import_root = Some(root.into());
+        self
+    }
 
-            // Create INSERT tag with the affected row count
-            let tag = Tag::new("INSERT").with_oid(0).with_rows(rows_affected);
-            Ok(vec![Response::Execution(tag)])
-        } else {
-            // For non-INSERT queries, return a regular Query response
-            let resp = df::encode_dataframe(df, &Format::UnifiedText).await?;
-            Ok(vec![Response::Query(resp)])
-        }
+    /// Limit the statements each user may run per client address
+    pub fn with_statement_rate_limit(mut self, limit: RateLimit) -> Self {
+        self.statement_rate_limiter = Some(Arc::new(RateLimiter::new(limit)));
+        self
     }
-}
 
-#[async_trait]
-impl ExtendedQueryHandler for DfSessionService {
-    type Statement = (String, LogicalPlan);
-    type QueryParser = Parser;
+    /// Reject the statement if the user exceeds the statement rate limit
+    fn check_statement_rate_limit<C>(&self, client: &C) -> PgWireResult<()>
+    where
+        C: ClientInfo,
+    {
+        let Some(rate_limiter) = &self.statement_rate_limiter else {
+            return Ok(());
+        };
 
-    fn query_parser(&self) -> Arc<Self::QueryParser> {
-        self.parser.clone()
+        let username = username(client);
+        let client_addr = self.activity.client_addr(&client.socket_addr());
+        if rate_limiter.try_acquire((username.to_string(), client_addr.ip())) {
+            return Ok(());
+        }
+
+        Err(PgWireError::UserError(Box::new(
+            pgwire::error::ErrorInfo::new(
+                "ERROR".to_string(),
+                "53400".to_string(), // configuration_limit_exceeded
+                format!(
+                    "statement rate limit of {} per second exceeded for user \"{username}\"",
+                    rate_limiter.limit().per_second
+                ),
+            ),
+        )))
     }
 
-    async fn do_describe_statement<C>(
-        &self,
-        _client: &mut C,
-        target: &StoredStatement<Self::Statement>,
-    ) -> PgWireResult<DescribeStatementResponse>
+    /// Get statement timeout from client metadata
+    fn get_statement_timeout<C>(client: &C) -> Option<std::time::Duration>
     where
-        C: ClientInfo + Unpin + Send + Sync,
+        C: ClientInfo,
     {
-        let (_, plan) = &target.statement;
-        let schema = plan.schema();
-        let fields = arrow_schema_to_pg_fields(schema.as_arrow(), &Format::UnifiedBinary)?;
-        let params = plan
-            .get_parameter_types()
-            .map_err(|e| PgWireError::ApiError(Box::new(e)))?;
+        client
+            .metadata()
+            .get(METADATA_STATEMENT_TIMEOUT)
+            .and_then(|s| s.parse::<u64>().ok())
+            .map(std::time::Duration::from_millis)
+    }
 
-        let mut param_types = Vec::with_capacity(params.len());
-        for param_type in ordered_param_types(&params).iter() {
-            // Fixed: Use &params
-            if let Some(datatype) = param_type {
-                let pgtype = into_pg_type(datatype)?;
-                param_types.push(pgtype);
-            } else {
-                param_types.push(Type::UNKNOWN);
+    /// Set statement timeout in client metadata
+    fn set_statement_timeout<C>(client: &mut C, timeout: Option<std::time::Duration>)
+    where
+        C: ClientInfo,
+    {
+        let metadata = client.metadata_mut();
+        if let Some(duration) = timeout {
+            metadata.insert(
+                METADATA_STATEMENT_TIMEOUT.to_string(),
+                duration.as_millis().to_string(),
+            );
+        } else {
+            metadata.remove(METADATA_STATEMENT_TIMEOUT);
+        }
+    }
+
+    /// Limits of the statements of `client`, those of the roles of its user
+    /// capped by those of its tenant
+    async fn query_limits<C: ClientInfo>(&self, client: &C) -> QueryLimits {
+        let limits = self
+            .auth_manager
+            .query_limits_for_user(authorized_user(client))
+            .await;
+        match self.session_contexts.tenant(client) {
+            Some(tenant) => limits.intersection(tenant.limits),
+            None => limits,
+        }
+    }
+
+    /// Check if the current user has permission to execute a query
+    async fn check_query_permission<C>(&self, client: &C, query: &str) -> PgWireResult<()>
+    where
+        C: ClientInfo,
+    {
+        let username = authorized_user(client);
+
+        // Parse query to determine required permissions
+        let query_lower = query.to_lowercase();
+        let query_trimmed = query_lower.trim();
+
+        let (required_permission, resource) = if query_trimmed.starts_with("select") {
+            (Permission::Select, self.extract_table_from_query(query))
+        } else if query_trimmed.starts_with("insert") {
+            (Permission::Insert, self.extract_table_from_query(query))
+        } else if query_trimmed.starts_with("update") {
+            (Permission::Update, self.extract_table_from_query(query))
+        } else if query_trimmed.starts_with("delete") {
+            (Permission::Delete, self.extract_table_from_query(query))
+        } else if query_trimmed.starts_with("create table")
+            || query_trimmed.starts_with("create view")
+        {
+            (Permission::Create, ResourceType::All)
+        } else if query_trimmed.starts_with("drop") {
+            (Permission::Drop, self.extract_table_from_query(query))
+        } else if query_trimmed.starts_with("alter") {
+            (Permission::Alter, self.extract_table_from_query(query))
+        } else {
+            // For other queries (SHOW, EXPLAIN, etc.), allow all users
+            return Ok(());
+        };
+
+        self.authorizer
+            .authorize_table(username, required_permission, resource)
+            .await
+    }
+
+    /// Check whether the session user may write the target of a `COPY ... TO`
+    /// and read its source
+    async fn authorize_copy_to<C>(&self, client: &C, copy_to: &CopyToStatement) -> PgWireResult<()>
+    where
+        C: ClientInfo,
+    {
+        self.authorizer
+            .authorize_write_files(authorized_user(client))
+            .await?;
+        check_copy_target(
+            &copy_to.target,
+            self.copy_export_root.as_deref(),
+            &self.session_context(client).runtime_env(),
+        )?;
+        match &copy_to.source {
+            CopyToSource::Relation(name) => {
+                let reference = object_name_to_table_reference(name.clone(), true)
+                    .map_err(|e| PgWireError::ApiError(Box::new(e)))?;
+                self.authorizer
+                    .authorize_table(
+                        authorized_user(client),
+                        Permission::Select,
+                        ResourceType::Table(reference.table().to_string()),
+                    )
+                    .await
+            }
+            CopyToSource::Query(query) => {
+                self.check_query_permission(client, &query.to_string())
+                    .await
             }
         }
+    }
 
-        Ok(DescribeStatementResponse::new(param_types, fields))
+    /// Apply the user's security policies to the dataframe before execution
+    ///
+    /// This attaches the catalog visibility, so pg_catalog tables only list
+    /// objects the user can access. Row-level security filters and column
+    /// masks are injected into the plan before it runs, see
+    /// `apply_row_policies`.
+    async fn apply_session_policies<C>(&self, client: &C, df: DataFrame) -> PgWireResult<DataFrame>
+    where
+        C: ClientInfo,
+    {
+        let (mut state, plan) = df.into_parts();
+        let plan = self.bind_start_times(client, &mut state, plan)?;
+        self.apply_bound_session_policies(client, state, plan).await
     }
 
-    async fn do_describe_portal<C>(
+    /// `apply_session_policies` for a plan whose start times are already
+    /// bound in `state`
+    async fn apply_bound_session_policies<C>(
         &self,
-        _client: &mut C,
-        target: &Portal<Self::Statement>,
-    ) -> PgWireResult<DescribePortalResponse>
+        client: &C,
+        mut state: SessionState,
+        plan: LogicalPlan,
+    ) -> PgWireResult<DataFrame>
     where
-        C: ClientInfo + Unpin + Send + Sync,
+        C: ClientInfo,
     {
-        let (_, plan) = &target.statement.statement;
-        let format = &target.result_column_format;
-        let schema = plan.schema();
-        let fields = arrow_schema_to_pg_fields(schema.as_arrow(), format)?;
+        let mut plan = self.bind_advisory_locks(client, &state, plan)?;
+        if let Some(time_zone) = client.metadata().get(METADATA_TIME_ZONE) {
+            state.config_mut().options_mut().execution.time_zone = time_zone.clone();
+            plan = in_time_zone(plan, time_zone).map_err(|e| PgWireError::ApiError(Box::new(e)))?;
+        }
 
-        Ok(DescribePortalResponse::new(fields))
+        state.config_mut().set_extension(Arc::new(
+            CatalogVisibility::new(
+                authorized_user(client).to_string(),
+                self.auth_manager.clone(),
+            )
+            .with_catalogs(self.session_contexts.catalogs(client)),
+        ));
+        state.config_mut().set_extension(self.activity.clone());
+        state.config_mut().set_extension(self.statistics.clone());
+        state
+            .config_mut()
+            .set_extension(self.db_role_settings.clone());
+        state
+            .config_mut()
+            .set_extension(self.advisory_locks.clone());
+        state
+            .config_mut()
+            .set_extension(Arc::new(self.session_settings(client).await));
+        Ok(DataFrame::new(state, plan))
     }
 
-    async fn do_query<'a, C>(
+    /// Inject the row-level security filters and column masks of the session
+    /// user into the table scans of `plan`
+    ///
+    /// This runs before the plan is executed, so that DDL and DML reading
+    /// policed tables, like `CREATE TABLE ... AS SELECT`, and scans projection
+    /// pushdown would narrow only see what the user may.
+    async fn apply_row_policies<C>(
         &self,
-        client: &mut C,
-        portal: &Portal<Self::Statement>,
-        _max_rows: usize,
-    ) -> PgWireResult<Response<'a>>
+        client: &C,
+        state: &SessionState,
+        plan: LogicalPlan,
+    ) -> PgWireResult<LogicalPlan>
     where
-        C: ClientInfo + Unpin + Send + Sync,
+        C: ClientInfo,
     {
-        let query = portal
-            .statement
-            .statement
-            .0
-            .to_lowercase()
-            .trim()
-            .to_string();
-        log::debug!("Received execute extended query: {query}"); // Log for debugging
+        // the roles of the session, which may be its own like those of tokens
+        let user = self.auth_manager.get_user(authorized_user(client)).await;
+        if user.as_ref().is_some_and(|u| u.is_superuser) {
+            return Ok(plan);
+        }
+        let roles = user.map(|u| u.roles).unwrap_or_default();
+        let row_filters = self.auth_manager.row_filter_policies().await;
+        let column_masks = self.auth_manager.column_masking_rules().await;
+        let catalog_options = &state.config_options().catalog;
+        let policy_ctx = PolicyContext {
+            username: username(client),
+            roles: &roles,
+            default_catalog: &catalog_options.default_catalog,
+            default_schema: &catalog_options.default_schema,
+            functions: state,
+        };
+        apply_table_policies(plan, &row_filters, &column_masks, &policy_ctx)
+            .map_err(|e| PgWireError::ApiError(Box::new(e)))
+    }
 
-        // Check permissions for the query (skip for SET and SHOW statements)
-        if !query.starts_with("set") && !query.starts_with("show") {
-            self.check_query_permission(client, &portal.statement.statement.0)
-                .await?;
+    /// Bind `now()` and `statement_timestamp()` of `plan` to the start of the
+    /// transaction and statement of `client`, like postgres
+    fn bind_start_times<C>(
+        &self,
+        client: &C,
+        state: &mut SessionState,
+        plan: LogicalPlan,
+    ) -> PgWireResult<LogicalPlan>
+    where
+        C: ClientInfo,
+    {
+        let Some(session) = self.activity.session(&client.socket_addr()) else {
+            return Ok(plan);
+        };
+        if let Some(xact_start) = session.xact_start {
+            state.execution_props_mut().query_execution_start_time = xact_start;
+        }
+        match session.query_start {
+            Some(query_start) => bind_statement_timestamp(plan, query_start)
+                .map_err(|e| PgWireError::ApiError(Box::new(e))),
+            None => Ok(plan),
         }
+    }
 
-        if let Some(resp) = self.try_respond_set_statements(client, &query).await? {
+    /// Bind the calls of advisory lock functions in `plan` to the session of
+    /// `client`, which takes its locks in the current database
+    fn bind_advisory_locks<C>(
+        &self,
+        client: &C,
+        state: &SessionState,
+        plan: LogicalPlan,
+    ) -> PgWireResult<LogicalPlan>
+    where
+        C: ClientInfo,
+    {
+        let Some(session) = self.activity.session(&client.socket_addr()) else {
+            return Ok(plan);
+        };
+        // warnings left by a failed statement
+        self.advisory_locks.take_warnings(session.pid);
+        let session = LockSession {
+            locks: self.advisory_locks.clone(),
+            pid: session.pid,
+            database: state.config_options().catalog.default_catalog.clone(),
+            in_transaction: client.transaction_status() != TransactionStatus::Idle,
+            timeout: Self::get_statement_timeout(client),
+            activity: self.activity.clone(),
+            client_addr: client.socket_addr(),
+        };
+        bind_advisory_locks(plan, &session).map_err(|e| PgWireError::ApiError(Box::new(e)))
+    }
+
+    /// Send the warnings of the advisory lock functions of the query of
+    /// `resp`, whose rows are read first as the warnings come before them
+    async fn send_lock_warnings<'a, C>(
+        &self,
+        client: &mut C,
+        resp: QueryResponse<'a>,
+        plan: &LogicalPlan,
+    ) -> PgWireResult<QueryResponse<'a>>
+    where
+        C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send,
+        PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    {
+        let Some(session) = self
+            .activity
+            .session(&client.socket_addr())
+            .filter(|_| calls_advisory_unlock(plan))
+        else {
             return Ok(resp);
+        };
+        let fields = resp.row_schema();
+        let mut data_rows = resp.data_rows();
+        let mut rows = Vec::new();
+        while let Some(row) = data_rows.next().await {
+            rows.push(row);
+        }
+        for warning in self.advisory_locks.take_warnings(session.pid) {
+            client
+                .send(PgWireBackendMessage::NoticeResponse(NoticeResponse::from(
+                    ErrorInfo::new("WARNING".to_string(), "01000".to_string(), warning),
+                )))
+                .await?;
         }
+        // the drained rows keep the guards of the response
+        Ok(QueryResponse::new(
+            fields,
+            stream::iter(rows).chain(data_rows),
+        ))
+    }
 
-        if let Some(resp) = self
-            .try_respond_transaction_statements(client, &query)
-            .await?
-        {
-            return Ok(resp);
+    /// Release the transaction level advisory locks of the session of
+    /// `client` as its transaction block ends
+    fn end_transaction_locks<C>(&self, client: &C)
+    where
+        C: ClientInfo,
+    {
+        if let Some(session) = self.activity.session(&client.socket_addr()) {
+            self.advisory_locks.end_transaction(session.pid);
         }
+    }
 
-        if let Some(resp) = self.try_respond_show_statements(client, &query).await? {
-            return Ok(resp);
+    /// Extract table name from query (simplified parsing)
+    fn extract_table_from_query(&self, query: &str) -> ResourceType {
+        let words: Vec<&str> = query.split_whitespace().collect();
+
+        // Simple heuristic to find table names
+        for (i, word) in words.iter().enumerate() {
+            let word_lower = word.to_lowercase();
+            if (word_lower == "from" || word_lower == "into" || word_lower == "table")
+                && i + 1 < words.len()
+            {
+                let table_name = words[i + 1].trim_matches(|c| c == '(' || c == ')' || c == ';');
+                return ResourceType::Table(table_name.to_string());
+            }
         }
 
-        // Check if we're in a failed transaction and block non-transaction
-        // commands
-        if client.transaction_status() == TransactionStatus::Error {
-            return Err(PgWireError::UserError(Box::new(
-                pgwire::error::ErrorInfo::new(
-                    "ERROR".to_string(),
-                    "25P01".to_string(),
-                    "current transaction is aborted, commands ignored until end of transaction block".to_string(),
+        // If we can't determine the table, default to All
+        ResourceType::All
+    }
+
+    fn mock_show_response<'a>(name: &str, value: &str) -> PgWireResult<QueryResponse<'a>> {
+        let fields = vec![FieldInfo::new(
+            name.to_string(),
+            None,
+            None,
+            Type::VARCHAR,
+            FieldFormat::Text,
+        )];
+
+        let row = {
+            let mut encoder = pgwire::api::results::DataRowEncoder::new(Arc::new(fields.clone()));
+            encoder.encode_field(&Some(value))?;
+            encoder.finish()
+        };
+
+        let row_stream = futures::stream::once(async move { row });
+        Ok(QueryResponse::new(Arc::new(fields), Box::pin(row_stream)))
+    }
+
+    fn show_all_response<'a>(settings: &SessionSettings) -> PgWireResult<QueryResponse<'a>> {
+        let fields = Arc::new(
+            ["name", "setting", "description"]
+                .into_iter()
+                .map(|name| {
+                    FieldInfo::new(name.to_string(), None, None, Type::TEXT, FieldFormat::Text)
+                })
+                .collect::<Vec<_>>(),
+        );
+
+        let rows = settings
+            .iter()
+            .map(|setting| {
+                let mut encoder = pgwire::api::results::DataRowEncoder::new(fields.clone());
+                encoder.encode_field(&setting.name())?;
+                encoder.encode_field(&setting.setting)?;
+                encoder.encode_field(&setting.parameter.short_desc)?;
+                encoder.finish()
+            })
+            .collect::<Vec<_>>();
+
+        Ok(QueryResponse::new(
+            fields,
+            Box::pin(futures::stream::iter(rows)),
+        ))
+    }
+
+    /// The run-time parameters of the session, as `SHOW` and `pg_settings`
+    /// show them
+    async fn session_settings<C>(&self, client: &C) -> SessionSettings
+    where
+        C: ClientInfo,
+    {
+        let config = self.session_context(client).copied_config();
+        let encode_options = self.encode_options(client);
+        let mut settings = SessionSettings::default()
+            .with_setting(METADATA_TIME_ZONE, time_zone(client))
+            .with_setting("search_path", &config.options().catalog.default_schema)
+            .with_setting(
+                "statement_timeout",
+                statement_timeout_setting(Self::get_statement_timeout(client)),
+            )
+            .with_setting(
+                METADATA_RESULT_BATCH_SIZE,
+                encode_options
+                    .batch_size
+                    .unwrap_or(config.batch_size())
+                    .to_string(),
+            )
+            .with_setting(
+                METADATA_RESULT_BUFFER_SIZE,
+                encode_options.buffer_size.to_string(),
+            )
+            .with_setting(
+                METADATA_MAX_FIELD_SIZE,
+                encode_options.max_field_size.to_string(),
+            )
+            .with_setting(
+                METADATA_EXTRA_FLOAT_DIGITS,
+                encode_options.text_format.extra_float_digits.to_string(),
+            )
+            .with_setting(
+                METADATA_INTERVAL_STYLE,
+                encode_options.text_format.interval_style.name(),
+            )
+            .with_setting(
+                METADATA_WIRE_DEBUG,
+                self.wire_debug.for_session(client.metadata()).as_str(),
+            );
+        let default_isolation = client
+            .metadata()
+            .get(METADATA_DEFAULT_TRANSACTION_ISOLATION)
+            .cloned()
+            .unwrap_or_else(|| "read committed".to_string());
+        let isolation = match client.transaction_status() {
+            TransactionStatus::Idle => None,
+            _ => client
+                .metadata()
+                .get(METADATA_TRANSACTION_ISOLATION)
+                .cloned(),
+        };
+        settings = settings
+            .with_setting(
+                "transaction_isolation",
+                isolation.unwrap_or_else(|| default_isolation.clone()),
+            )
+            .with_setting(METADATA_DEFAULT_TRANSACTION_ISOLATION, default_isolation);
+        if let Some(application_name) = client.metadata().get("application_name") {
+            settings = settings.with_setting("application_name", application_name);
+        }
+        let defaults = client
+            .metadata()
+            .get(METADATA_SETTING_DEFAULTS)
+            .and_then(|defaults| {
+                serde_json::from_str::<BTreeMap<String, (String, String)>>(defaults).ok()
+            })
+            .unwrap_or_default();
+        for (name, (value, source)) in defaults {
+            let value = match name.as_str() {
+                "statement_timeout" => match statement_timeout(&value) {
+                    Ok(timeout) => statement_timeout_setting(timeout),
+                    Err(_) => continue,
+                },
+                _ => value,
+            };
+            settings = settings.with_reset_val(&name, value, &source);
+        }
+        settings
+            .with_setting(METADATA_DATESTYLE, datestyle(client).to_string())
+            .with_setting(
+                METADATA_STANDARD_CONFORMING_STRINGS,
+                on_off(standard_conforming_strings(client)),
+            )
+    }
+
+    /// Set the `DateStyle` of the session, keeping the part the value doesn't
+    /// name like postgres, so `SET DateStyle = ISO` keeps the order of dates
+    fn try_respond_set_datestyle<'a, C>(
+        client: &mut C,
+        statement: &SqlStatement,
+    ) -> PgWireResult<Option<Response<'a>>>
+    where
+        C: ClientInfo,
+    {
+        let SqlStatement::SetVariable {
+            variables: OneOrManyWithParens::One(name),
+            value,
+            ..
+        } = statement
+        else {
+            return Ok(None);
+        };
+        if !name.to_string().eq_ignore_ascii_case(METADATA_DATESTYLE) {
+            return Ok(None);
+        }
+        let value = set_value_text(value);
+        if value.eq_ignore_ascii_case("default") {
+            client.metadata_mut().remove(METADATA_DATESTYLE);
+            return Ok(Some(Response::Execution(Tag::new("SET"))));
+        }
+        let datestyle = DateStyle::parse(&value, datestyle(client)).map_err(|detail| {
+            let mut info = ErrorInfo::new(
+                "ERROR".to_string(),
+                "22023".to_string(), // invalid_parameter_value
+                format!("invalid value for parameter \"{METADATA_DATESTYLE}\": \"{value}\""),
+            );
+            info.detail = Some(detail);
+            PgWireError::UserError(Box::new(info))
+        })?;
+        client
+            .metadata_mut()
+            .insert(METADATA_DATESTYLE.to_string(), datestyle.to_string());
+        Ok(Some(Response::Execution(Tag::new("SET"))))
+    }
+
+    /// Set whether backslashes in `'...'` literals are escapes, which libpq
+    /// escapes strings by
+    fn try_respond_set_standard_conforming_strings<'a, C>(
+        client: &mut C,
+        statement: &SqlStatement,
+    ) -> PgWireResult<Option<Response<'a>>>
+    where
+        C: ClientInfo,
+    {
+        let SqlStatement::SetVariable {
+            variables: OneOrManyWithParens::One(name),
+            value,
+            ..
+        } = statement
+        else {
+            return Ok(None);
+        };
+        if !name
+            .to_string()
+            .eq_ignore_ascii_case(METADATA_STANDARD_CONFORMING_STRINGS)
+        {
+            return Ok(None);
+        }
+        let value = set_value_text(value);
+        if value.eq_ignore_ascii_case("default") {
+            client
+                .metadata_mut()
+                .remove(METADATA_STANDARD_CONFORMING_STRINGS);
+            return Ok(Some(Response::Execution(Tag::new("SET"))));
+        }
+        let Some(on) = parse_bool_setting(&value) else {
+            return Err(PgWireError::UserError(Box::new(ErrorInfo::new(
+                "ERROR".to_string(),
+                "22023".to_string(), // invalid_parameter_value
+                format!(
+                    "parameter \"{METADATA_STANDARD_CONFORMING_STRINGS}\" requires a Boolean value"
                 ),
-            )));
+            ))));
+        };
+        client.metadata_mut().insert(
+            METADATA_STANDARD_CONFORMING_STRINGS.to_string(),
+            on_off(on).to_string(),
+        );
+        Ok(Some(Response::Execution(Tag::new("SET"))))
+    }
+
+    /// Set the digits of floats in the text format, which JDBC sets to 3 on
+    /// connecting
+    fn try_respond_set_extra_float_digits<'a, C>(
+        client: &mut C,
+        statement: &SqlStatement,
+    ) -> PgWireResult<Option<Response<'a>>>
+    where
+        C: ClientInfo,
+    {
+        let SqlStatement::SetVariable {
+            variables: OneOrManyWithParens::One(name),
+            value,
+            ..
+        } = statement
+        else {
+            return Ok(None);
+        };
+        if !name
+            .to_string()
+            .eq_ignore_ascii_case(METADATA_EXTRA_FLOAT_DIGITS)
+        {
+            return Ok(None);
+        }
+        let value = set_value_text(value);
+        if value.eq_ignore_ascii_case("default") {
+            client.metadata_mut().remove(METADATA_EXTRA_FLOAT_DIGITS);
+            return Ok(Some(Response::Execution(Tag::new("SET"))));
+        }
+        let digits = value.trim().parse::<i64>().map_err(|_| {
+            PgWireError::UserError(Box::new(ErrorInfo::new(
+                "ERROR".to_string(),
+                "22023".to_string(), // invalid_parameter_value
+                format!(
+                    "invalid value for parameter \"{METADATA_EXTRA_FLOAT_DIGITS}\": \"{value}\""
+                ),
+            )))
+        })?;
+        if !(-15..=3).contains(&digits) {
+            return Err(PgWireError::UserError(Box::new(ErrorInfo::new(
+                "ERROR".to_string(),
+                "22023".to_string(), // invalid_parameter_value
+                format!(
+                    "{digits} is outside the valid range for parameter \"{METADATA_EXTRA_FLOAT_DIGITS}\" (-15 .. 3)"
+                ),
+            ))));
         }
+        client
+            .metadata_mut()
+            .insert(METADATA_EXTRA_FLOAT_DIGITS.to_string(), digits.to_string());
+        Ok(Some(Response::Execution(Tag::new("SET"))))
+    }
+
+    /// Set the text format of intervals, which .NET and Java clients parse
+    /// in the style they set on connecting
+    fn try_respond_set_interval_style<'a, C>(
+        client: &mut C,
+        statement: &SqlStatement,
+    ) -> PgWireResult<Option<Response<'a>>>
+    where
+        C: ClientInfo,
+    {
+        let SqlStatement::SetVariable {
+            variables: OneOrManyWithParens::One(name),
+            value,
+            ..
+        } = statement
+        else {
+            return Ok(None);
+        };
+        if !name
+            .to_string()
+            .eq_ignore_ascii_case(METADATA_INTERVAL_STYLE)
+        {
+            return Ok(None);
+        }
+        let value = set_value_text(value);
+        if value.eq_ignore_ascii_case("default") {
+            client.metadata_mut().remove(METADATA_INTERVAL_STYLE);
+            return Ok(Some(Response::Execution(Tag::new("SET"))));
+        }
+        let style = IntervalStyle::from_name(&value).ok_or_else(|| {
+            let mut info = ErrorInfo::new(
+                "ERROR".to_string(),
+                "22023".to_string(), // invalid_parameter_value
+                format!("invalid value for parameter \"{METADATA_INTERVAL_STYLE}\": \"{value}\""),
+            );
+            info.hint = Some(
+                "Available values: postgres, postgres_verbose, sql_standard, iso_8601.".to_string(),
+            );
+            PgWireError::UserError(Box::new(info))
+        })?;
+        client.metadata_mut().insert(
+            METADATA_INTERVAL_STYLE.to_string(),
+            style.name().to_string(),
+        );
+        Ok(Some(Response::Execution(Tag::new("SET"))))
+    }
+
+    /// Set the time zone of the session with `SET TIME ZONE` or
+    /// `SET timezone`
+    fn try_respond_set_time_zone<'a, C>(
+        client: &mut C,
+        statement: &SqlStatement,
+    ) -> PgWireResult<Option<Response<'a>>>
+    where
+        C: ClientInfo,
+    {
+        let Some(time_zone) = time_zone_setting(statement) else {
+            return Ok(None);
+        };
+        match time_zone? {
+            Some(time_zone) => client
+                .metadata_mut()
+                .insert(METADATA_TIME_ZONE.to_string(), time_zone),
+            None => client.metadata_mut().remove(METADATA_TIME_ZONE),
+        };
+        Ok(Some(Response::Execution(Tag::new("SET"))))
+    }
+
+    async fn try_respond_set_statements<'a, C>(
+        &self,
+        client: &mut C,
+        query_lower: &str,
+    ) -> PgWireResult<Option<Response<'a>>>
+    where
+        C: ClientInfo,
+    {
+        if query_lower.starts_with("set") {
+            if let Some(key) = [
+                METADATA_RESULT_BATCH_SIZE,
+                METADATA_RESULT_BUFFER_SIZE,
+                METADATA_MAX_FIELD_SIZE,
+            ]
+            .into_iter()
+            .find(|key| query_lower.split([' ', '=']).nth(1) == Some(*key))
+            {
+                // SET result_batch_size = 1000 / SET result_batch_size TO 1000
+                let query = query_lower.strip_suffix(';').unwrap_or(query_lower);
+                let value = query
+                    .split([' ', '='])
+                    .filter(|part| !part.is_empty() && *part != "to")
+                    .nth(2)
+                    .unwrap_or_default();
+                Self::set_size_setting(client, key, value)?;
+                Ok(Some(Response::Execution(Tag::new("SET"))))
+            } else if query_lower.split([' ', '=']).nth(1) == Some(METADATA_WIRE_DEBUG) {
+                // SET wire_debug = on / SET wire_debug TO hex
+                let query = query_lower.strip_suffix(';').unwrap_or(query_lower);
+                let value = query
+                    .split([' ', '='])
+                    .filter(|part| !part.is_empty() && *part != "to")
+                    .nth(2)
+                    .unwrap_or_default()
+                    .trim_matches('\'');
+                if value == "default" {
+                    client.metadata_mut().remove(METADATA_WIRE_DEBUG);
+                } else {
+                    let mode = value.parse::<WireDebug>().map_err(|_| {
+                        PgWireError::UserError(Box::new(pgwire::error::ErrorInfo::new(
+                            "ERROR".to_string(),
+                            "22023".to_string(), // invalid_parameter_value
+                            format!(
+                                "invalid value for parameter \"{METADATA_WIRE_DEBUG}\": \"{value}\""
+                            ),
+                        )))
+                    })?;
+                    client
+                        .metadata_mut()
+                        .insert(METADATA_WIRE_DEBUG.to_string(), mode.as_str().to_string());
+                }
+                Ok(Some(Response::Execution(Tag::new("SET"))))
+            } else if let Some(modes) = query_lower.strip_prefix("set transaction ") {
+                // SET TRANSACTION ISOLATION LEVEL SERIALIZABLE only records the
+                // level for SHOW, statements still run as read committed
+                let level = isolation_level(modes)?;
+                if client.transaction_status() == TransactionStatus::Idle {
+                    warn!("SET TRANSACTION can only be used in transaction blocks");
+                } else if let Some(level) = level {
+                    client.metadata_mut().insert(
+                        METADATA_TRANSACTION_ISOLATION.to_string(),
+                        level.to_string(),
+                    );
+                }
+                Ok(Some(Response::Execution(Tag::new("SET"))))
+            } else if let Some(modes) =
+                query_lower.strip_prefix("set session characteristics as transaction ")
+            {
+                if let Some(level) = isolation_level(modes)? {
+                    client.metadata_mut().insert(
+                        METADATA_DEFAULT_TRANSACTION_ISOLATION.to_string(),
+                        level.to_string(),
+                    );
+                }
+                Ok(Some(Response::Execution(Tag::new("SET"))))
+            } else if let Some(value) = query_lower
+                .strip_prefix("set ")
+                .and_then(|query| query.strip_prefix(METADATA_DEFAULT_TRANSACTION_ISOLATION))
+                .filter(|value| value.starts_with([' ', '=']))
+            {
+                // SET default_transaction_isolation = 'serializable' / TO DEFAULT
+                let value = value.strip_suffix(';').unwrap_or(value).trim_start();
+                let value = value
+                    .strip_prefix('=')
+                    .or_else(|| value.strip_prefix("to "))
+                    .unwrap_or(value)
+                    .trim()
+                    .trim_matches('\'');
+                if value == "default" {
+                    client
+                        .metadata_mut()
+                        .remove(METADATA_DEFAULT_TRANSACTION_ISOLATION);
+                } else {
+                    let level = ISOLATION_LEVELS
+                        .into_iter()
+                        .find(|level| *level == value)
+                        .ok_or_else(|| {
+                            PgWireError::UserError(Box::new(pgwire::error::ErrorInfo::new(
+                                "ERROR".to_string(),
+                                "22023".to_string(), // invalid_parameter_value
+                                format!(
+                                    "invalid value for parameter \"{METADATA_DEFAULT_TRANSACTION_ISOLATION}\": \"{value}\""
+                                ),
+                            )))
+                        })?;
+                    client.metadata_mut().insert(
+                        METADATA_DEFAULT_TRANSACTION_ISOLATION.to_string(),
+                        level.to_string(),
+                    );
+                }
+                Ok(Some(Response::Execution(Tag::new("SET"))))
+            } else if query_lower.starts_with("set statement_timeout") {
+                let parts: Vec<&str> = query_lower
+                    .split_whitespace()
+                    .filter(|part| !matches!(*part, "=" | "to"))
+                    .collect();
+                if parts.len() >= 3 {
+                    // Supports ms, s and min, an invalid value disables the timeout
+                    let timeout = statement_timeout(parts[2]).ok().flatten();
+
+                    Self::set_statement_timeout(client, timeout);
+                    Ok(Some(Response::Execution(Tag::new("SET"))))
+                } else {
+                    Err(PgWireError::UserError(Box::new(
+                        pgwire::error::ErrorInfo::new(
+                            "ERROR".to_string(),
+                            "42601".to_string(),
+                            "Invalid SET statement_timeout syntax".to_string(),
+                        ),
+                    )))
+                }
+            } else {
+                // pass SET query to datafusion
+                if let Err(e) = self.session_context(client).sql(query_lower).await {
+                    warn!("SET statement {query_lower} is not supported by datafusion, error {e}, statement ignored");
+                }
+
+                // Always return SET success
+                Ok(Some(Response::Execution(Tag::new("SET"))))
+            }
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Respond to `LISTEN`, `UNLISTEN` and `NOTIFY`
+    fn try_respond_notification_statements<'a, C>(
+        &self,
+        client: &C,
+        statement: &SqlStatement,
+    ) -> Option<Response<'a>>
+    where
+        C: ClientInfo,
+    {
+        let session = client.socket_addr();
+        let tag = match statement {
+            SqlStatement::LISTEN { channel } => {
+                self.notifications.listen(session, &channel.value);
+                "LISTEN"
+            }
+            SqlStatement::UNLISTEN { channel } => {
+                self.notifications.unlisten(session, &channel.value);
+                "UNLISTEN"
+            }
+            SqlStatement::NOTIFY { channel, payload } => {
+                let (pid, _) = client.pid_and_secret_key();
+                self.notifications.notify(
+                    pid,
+                    &channel.value,
+                    payload.as_deref().unwrap_or_default(),
+                );
+                "NOTIFY"
+            }
+            _ => return None,
+        };
+        Some(Response::Execution(Tag::new(tag)))
+    }
+
+    /// Respond to `DISCARD`
+    ///
+    /// The prepared statements and portals closed by `DISCARD ALL` are
+    /// removed from the portal store by [`Self::close_discarded`] on the next
+    /// message of the extended query protocol, the only ones using them.
+    fn try_respond_discard_statements<'a, C>(
+        &self,
+        client: &mut C,
+        statement: &SqlStatement,
+    ) -> PgWireResult<Option<Response<'a>>>
+    where
+        C: ClientInfo,
+    {
+        let SqlStatement::Discard { object_type } = statement else {
+            return Ok(None);
+        };
+        let session = client.socket_addr();
+        match object_type {
+            DiscardObject::ALL => {
+                if client.transaction_status() != TransactionStatus::Idle {
+                    return Err(PgWireError::UserError(Box::new(
+                        pgwire::error::ErrorInfo::new(
+                            "ERROR".to_string(),
+                            "25001".to_string(), // active_sql_transaction
+                            "DISCARD ALL cannot run inside a transaction block".to_string(),
+                        ),
+                    )));
+                }
+                for key in METADATA_SESSION_SETTINGS {
+                    client.metadata_mut().remove(*key);
+                }
+                self.notifications.unlisten(session, "*");
+                if let Some(plan_cache) = &self.plan_cache {
+                    plan_cache.clear_session(&session);
+                }
+                self.prepared_statements.discard_all(session);
+            }
+            DiscardObject::PLANS => {
+                if let Some(plan_cache) = &self.plan_cache {
+                    plan_cache.clear_session(&session);
+                }
+            }
+            // sessions have no temporary tables or sequences
+            DiscardObject::TEMP | DiscardObject::SEQUENCES => {}
+        }
+        Ok(Some(Response::Execution(Tag::new(&format!(
+            "DISCARD {object_type}"
+        )))))
+    }
+
+    /// Create and drop the catalogs of `CREATE DATABASE` and `DROP DATABASE`
+    fn try_respond_database_statements<'a, C>(
+        &self,
+        client: &C,
+        statement: &SqlStatement,
+    ) -> PgWireResult<Option<Response<'a>>>
+    where
+        C: ClientInfo,
+    {
+        let (command, name) = match statement {
+            SqlStatement::CreateDatabase { db_name, .. } => ("CREATE DATABASE", db_name),
+            SqlStatement::Drop {
+                object_type: ObjectType::Database,
+                names,
+                ..
+            } if names.len() == 1 => ("DROP DATABASE", &names[0]),
+            _ => return Ok(None),
+        };
+        if client.transaction_status() != TransactionStatus::Idle {
+            return Err(PgWireError::UserError(Box::new(
+                pgwire::error::ErrorInfo::new(
+                    "ERROR".to_string(),
+                    "25001".to_string(), // active_sql_transaction
+                    format!("{command} cannot run inside a transaction block"),
+                ),
+            )));
+        }
+        let name = database_name(name);
+        let session_context = self.session_context(client);
+        match statement {
+            SqlStatement::CreateDatabase { if_not_exists, .. } => {
+                create_database(&session_context, &name, *if_not_exists)?
+            }
+            SqlStatement::Drop { if_exists, .. } => {
+                let session = client.socket_addr();
+                let accessed = self.activity.sessions().into_iter().any(|activity| {
+                    activity.client_addr != session
+                        && activity.datname.as_deref() == Some(name.as_str())
+                });
+                if accessed && session_context.catalog(&name).is_some() {
+                    return Err(PgWireError::UserError(Box::new(
+                        pgwire::error::ErrorInfo::new(
+                            "ERROR".to_string(),
+                            "55006".to_string(), // object_in_use
+                            format!("database \"{name}\" is being accessed by other users"),
+                        ),
+                    )));
+                }
+                drop_database(&session_context, &name, *if_exists)?;
+                self.db_role_settings.forget_database(&name);
+            }
+            _ => {}
+        }
+        self.catalog_changed(&session_context, &format!("{command} {name}"));
+        Ok(Some(Response::Execution(Tag::new(command))))
+    }
+
+    /// Create, alter and drop the roles of `CREATE ROLE`, `ALTER ROLE` and
+    /// `DROP ROLE` in the `AuthManager`
+    async fn try_respond_role_statements<'a, C>(
+        &self,
+        client: &mut C,
+        statement: &SqlStatement,
+    ) -> PgWireResult<Option<Response<'a>>>
+    where
+        C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send,
+        PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    {
+        let Some(role_statement) = RoleStatement::from_statement(statement) else {
+            return Ok(None);
+        };
+        let role_statement = role_statement?;
+        match &role_statement {
+            RoleStatement::Create {
+                names,
+                if_not_exists,
+                attributes,
+                member_of,
+            } => {
+                for name in names {
+                    let exists = self.auth_manager.get_user(name).await.is_some()
+                        || self.auth_manager.get_role(name).await.is_some();
+                    if *if_not_exists && exists {
+                        client
+                            .send(PgWireBackendMessage::NoticeResponse(NoticeResponse::from(
+                                ErrorInfo::new(
+                                    "NOTICE".to_string(),
+                                    "42710".to_string(), // duplicate_object
+                                    format!("role \"{name}\" already exists, skipping"),
+                                ),
+                            )))
+                            .await?;
+                        continue;
+                    }
+                    self.auth_manager
+                        .create_role_with_attributes(name, attributes, member_of)
+                        .await?;
+                }
+            }
+            RoleStatement::Alter { name, attributes } => {
+                self.auth_manager.alter_role(name, attributes).await?
+            }
+            RoleStatement::Drop { names, if_exists } => {
+                for name in names {
+                    if name == username(client) {
+                        return Err(PgWireError::UserError(Box::new(
+                            pgwire::error::ErrorInfo::new(
+                                "ERROR".to_string(),
+                                "55006".to_string(), // object_in_use
+                                "current user cannot be dropped".to_string(),
+                            ),
+                        )));
+                    }
+                    self.auth_manager.drop_role(name, *if_exists).await?;
+                    self.db_role_settings.forget_role(name);
+                }
+            }
+        }
+        Ok(Some(Response::Execution(Tag::new(role_statement.tag()))))
+    }
+
+    /// Change the setting defaults of `ALTER ROLE ... SET` and `ALTER ROLE
+    /// ... RESET`
+    async fn try_respond_setting_statements<'a, C>(
+        &self,
+        client: &C,
+        statement: &SqlStatement,
+    ) -> PgWireResult<Option<Response<'a>>>
+    where
+        C: ClientInfo,
+    {
+        let Some(setting_statement) = SettingStatement::from_statement(statement) else {
+            return Ok(None);
+        };
+        self.change_setting_defaults(client, setting_statement?)
+            .await
+            .map(Some)
+    }
+
+    /// Change the setting defaults of `ALTER DATABASE ... SET` and `ALTER
+    /// DATABASE ... RESET`, which sqlparser doesn't parse
+    async fn try_respond_alter_database_statements<'a, C>(
+        &self,
+        client: &C,
+        query: &str,
+    ) -> PgWireResult<Option<Response<'a>>>
+    where
+        C: ClientInfo,
+    {
+        if !SettingStatement::matches(&query.to_lowercase()) {
+            return Ok(None);
+        }
+        let statement = SettingStatement::parse(query)?;
+        check_command_rules(
+            self.authorizer.as_ref(),
+            client,
+            StatementKind::Alter,
+            query,
+        )
+        .await?;
+        self.auth_manager
+            .authorize_setting_statement(authorized_user(client), &statement)
+            .await?;
+        self.change_setting_defaults(client, statement)
+            .await
+            .map(Some)
+    }
+
+    async fn change_setting_defaults<'a, C>(
+        &self,
+        client: &C,
+        statement: SettingStatement,
+    ) -> PgWireResult<Response<'a>>
+    where
+        C: ClientInfo,
+    {
+        if let Some(role) = &statement.role {
+            if self.auth_manager.get_user(role).await.is_none()
+                && self.auth_manager.get_role(role).await.is_none()
+            {
+                return Err(PgWireError::UserError(Box::new(
+                    pgwire::error::ErrorInfo::new(
+                        "ERROR".to_string(),
+                        "42704".to_string(), // undefined_object
+                        format!("role \"{role}\" does not exist"),
+                    ),
+                )));
+            }
+        }
+        if let Some(database) = &statement.database {
+            if self.session_context(client).catalog(database).is_none() {
+                return Err(PgWireError::UserError(Box::new(
+                    pgwire::error::ErrorInfo::new(
+                        "ERROR".to_string(),
+                        "3D000".to_string(), // invalid_catalog_name
+                        format!("database \"{database}\" does not exist"),
+                    ),
+                )));
+            }
+        }
+        let (database, role) = (statement.database.as_deref(), statement.role.as_deref());
+        match &statement.change {
+            SettingChange::Set { name, value } => {
+                self.db_role_settings.set(database, role, name, value)
+            }
+            SettingChange::Reset { name } => self.db_role_settings.reset(database, role, *name),
+        }
+        Ok(Response::Execution(Tag::new(statement.command)))
+    }
+
+    /// Register and forget the object store credentials of
+    /// `CREATE STORAGE CREDENTIAL` and `DROP STORAGE CREDENTIAL`
+    async fn try_respond_storage_statements<'a, C>(
+        &self,
+        client: &C,
+        query: &str,
+    ) -> PgWireResult<Option<Response<'a>>>
+    where
+        C: ClientInfo,
+    {
+        if !StorageStatement::matches(&query.to_lowercase()) {
+            return Ok(None);
+        }
+        let statement = StorageStatement::parse(query)?;
+        check_command_rules(
+            self.authorizer.as_ref(),
+            client,
+            StatementKind::Other,
+            query,
+        )
+        .await?;
+        self.authorizer
+            .authorize_table(
+                authorized_user(client),
+                Permission::Usage,
+                ResourceType::All,
+            )
+            .await?;
+        let session_context = self.session_context(client);
+        let tag = match statement {
+            StorageStatement::Create {
+                name,
+                if_not_exists,
+                location,
+                options,
+            } => {
+                self.storage_credentials.create(
+                    &session_context,
+                    &name,
+                    if_not_exists,
+                    &location,
+                    &options,
+                )?;
+                "CREATE STORAGE CREDENTIAL"
+            }
+            StorageStatement::Drop { name, if_exists } => {
+                self.storage_credentials
+                    .remove(&session_context, &name, if_exists)?;
+                "DROP STORAGE CREDENTIAL"
+            }
+        };
+        Ok(Some(Response::Execution(Tag::new(tag))))
+    }
+
+    /// Register the tables of `CREATE EXTERNAL TABLE`
+    async fn try_respond_external_table_statements<'a, C>(
+        &self,
+        client: &C,
+        query: &str,
+    ) -> PgWireResult<Option<Response<'a>>>
+    where
+        C: ClientInfo,
+    {
+        if !is_create_external_table(&query.to_lowercase()) {
+            return Ok(None);
+        }
+        let statement = parse_create_external_table(query)?;
+        check_command_rules(
+            self.authorizer.as_ref(),
+            client,
+            StatementKind::CreateTable,
+            query,
+        )
+        .await?;
+        let reference = object_name_to_table_reference(statement.name.clone(), true)
+            .map_err(|e| PgWireError::ApiError(Box::new(e)))?;
+        self.authorizer
+            .authorize_table(
+                authorized_user(client),
+                Permission::Create,
+                ResourceType::Table(reference.table().to_string()),
+            )
+            .await?;
+        self.authorizer
+            .authorize_read_files(authorized_user(client))
+            .await?;
+        let session_context = self.session_context(client);
+        check_table_location(
+            &statement.location,
+            self.import_root.as_deref(),
+            &session_context.runtime_env(),
+        )?;
+        create_external_table(&session_context, statement).await?;
+        self.catalog_changed(&session_context, &format!("CREATE TABLE {reference}"));
+        Ok(Some(Response::Execution(Tag::new("CREATE EXTERNAL TABLE"))))
+    }
+
+    /// Infer the schema of the table of `ALTER TABLE ... REFRESH SCHEMA`
+    /// again
+    async fn try_respond_refresh_schema_statements<'a, C>(
+        &self,
+        client: &C,
+        query: &str,
+    ) -> PgWireResult<Option<Response<'a>>>
+    where
+        C: ClientInfo,
+    {
+        if !is_alter_table_refresh_schema(&query.to_lowercase()) {
+            return Ok(None);
+        }
+        let name = parse_alter_table_refresh_schema(query)?;
+        check_command_rules(
+            self.authorizer.as_ref(),
+            client,
+            StatementKind::Alter,
+            query,
+        )
+        .await?;
+        let reference = object_name_to_table_reference(name, true)
+            .map_err(|e| PgWireError::ApiError(Box::new(e)))?;
+        self.refresh_table(client, reference).await?;
+        Ok(Some(Response::Execution(Tag::new("ALTER TABLE"))))
+    }
+
+    /// Answer `VACUUM` and `CLUSTER` with a notice, and compute the
+    /// statistics of the tables of `ANALYZE`
+    async fn try_respond_maintenance_statements<'a, C>(
+        &self,
+        client: &mut C,
+        query: &str,
+    ) -> PgWireResult<Option<Response<'a>>>
+    where
+        C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send,
+        PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    {
+        if !is_maintenance_command(&query.to_lowercase()) {
+            return Ok(None);
+        }
+        let command = parse_maintenance_command(query)?;
+        check_command_rules(
+            self.authorizer.as_ref(),
+            client,
+            StatementKind::Other,
+            query,
+        )
+        .await?;
+        let notice = match &command {
+            MaintenanceCommand::Vacuum { .. } => {
+                if client.transaction_status() != TransactionStatus::Idle {
+                    return Err(PgWireError::UserError(Box::new(
+                        pgwire::error::ErrorInfo::new(
+                            "ERROR".to_string(),
+                            "25001".to_string(), // active_sql_transaction
+                            "VACUUM cannot run inside a transaction block".to_string(),
+                        ),
+                    )));
+                }
+                Some("VACUUM does nothing, tables have no dead rows to reclaim")
+            }
+            MaintenanceCommand::Cluster => {
+                Some("CLUSTER does nothing, tables have no physical order to keep")
+            }
+            MaintenanceCommand::Analyze { .. } => None,
+        };
+        if let Some(notice) = notice {
+            client
+                .send(PgWireBackendMessage::NoticeResponse(NoticeResponse::from(
+                    ErrorInfo::new(
+                        "NOTICE".to_string(),
+                        "00000".to_string(),
+                        notice.to_string(),
+                    ),
+                )))
+                .await?;
+        }
+
+        let tables = match &command {
+            MaintenanceCommand::Vacuum {
+                analyze: true,
+                tables,
+            }
+            | MaintenanceCommand::Analyze { tables } => tables,
+            _ => return Ok(Some(Response::Execution(Tag::new(command.tag())))),
+        };
+        let session_context = self.session_context(client);
+        let targets = if tables.is_empty() {
+            // all tables the user may read, like postgres skips those the
+            // user may not analyze
+            let mut targets = Vec::new();
+            for reference in
+                all_tables(&session_context).map_err(|e| PgWireError::ApiError(Box::new(e)))?
+            {
+                let resource = ResourceType::Table(reference.table().to_string());
+                if self
+                    .authorizer
+                    .authorize_table(authorized_user(client), Permission::Select, resource)
+                    .await
+                    .is_ok()
+                {
+                    targets.push((reference, Vec::new()));
+                }
+            }
+            targets
+        } else {
+            let mut targets = Vec::new();
+            for table in tables {
+                let reference = object_name_to_table_reference(table.name.clone(), true)
+                    .map_err(|e| PgWireError::ApiError(Box::new(e)))?;
+                self.authorizer
+                    .authorize_table(
+                        authorized_user(client),
+                        Permission::Select,
+                        ResourceType::Table(reference.table().to_string()),
+                    )
+                    .await?;
+                targets.push((reference, table.columns.clone()));
+            }
+            targets
+        };
+
+        let state = session_context.state();
+        let catalog_options = &state.config_options().catalog;
+        for (reference, columns) in targets {
+            let statistics = analyze_table(&session_context, reference.clone(), &columns)
+                .await
+                .map_err(|e| PgWireError::ApiError(Box::new(e)))?;
+            let table = reference.resolve(
+                &catalog_options.default_catalog,
+                &catalog_options.default_schema,
+            );
+            self.statistics.analyzed(
+                (
+                    table.catalog.to_string(),
+                    table.schema.to_string(),
+                    table.table.to_string(),
+                ),
+                statistics,
+            );
+        }
+        Ok(Some(Response::Execution(Tag::new(command.tag()))))
+    }
+
+    /// Write the partitions of `COPY ... TO ... PARTITIONED BY (columns)`
+    async fn try_respond_partitioned_copy_statements<'a, C>(
+        &self,
+        client: &C,
+        query: &str,
+    ) -> PgWireResult<Option<Response<'a>>>
+    where
+        C: ClientInfo,
+    {
+        let Some(partitioned) = partitioned_copy(query) else {
+            return Ok(None);
+        };
+        let (statement, partitioned_by) = partitioned?;
+        check_statement_rules(self.authorizer.as_ref(), client, &statement).await?;
+        let Some(copy_to) = copy_to_statement(&statement) else {
+            return Err(PgWireError::UserError(Box::new(ErrorInfo::new(
+                "ERROR".to_string(),
+                "0A000".to_string(), // feature_not_supported
+                "PARTITIONED BY is only supported by COPY ... TO a file".to_string(),
+            ))));
+        };
+        let copy_to = CopyToStatement {
+            partitioned_by,
+            ..copy_to?
+        };
+
+        // the row filters, column masks and limits of the session apply like
+        // to any other COPY ... TO
+        let limits = self.query_limits(client).await;
+        let df = self.execute_copy_to(client, copy_to).await?;
+        let df = limits.apply_memory_limit(self.apply_session_policies(client, df).await?);
+        let invalidation = self
+            .result_cache
+            .as_ref()
+            .map(|result_cache| result_cache.invalidate());
+        let result = match Self::get_statement_timeout(client) {
+            Some(timeout) => tokio::time::timeout(timeout, df.collect())
+                .await
+                .map_err(|_| {
+                    PgWireError::UserError(Box::new(pgwire::error::ErrorInfo::new(
+                        "ERROR".to_string(),
+                        "57014".to_string(), // query_canceled error code
+                        "canceling statement due to statement timeout".to_string(),
+                    )))
+                })?,
+            None => df.collect().await,
+        }
+        .map_err(|e| map_resource_error(PgWireError::ApiError(Box::new(e))))?;
+        drop(invalidation);
+        Ok(Some(Response::Execution(
+            Tag::new("COPY").with_rows(rows_affected(&result)),
+        )))
+    }
+
+    /// Answer `COPY ... TO STDOUT` with the rows of the copy, and start
+    /// `COPY ... FROM STDIN`, whose data the `DfCopyHandler` inserts
+    async fn try_respond_copy_statements<'a, C>(
+        &self,
+        client: &mut C,
+        query: &str,
+    ) -> PgWireResult<Option<Response<'a>>>
+    where
+        C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+        C::Error: Debug,
+        PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    {
+        let Some(statement) = CopyStatement::parse(query) else {
+            return Ok(None);
+        };
+        let statement = statement?;
+        check_statement_rules(self.authorizer.as_ref(), client, &statement.statement()).await?;
+        let resp = match statement.to {
+            true => self.copy_out(client, &statement).await?,
+            false => self.copy_in(client, &statement).await?,
+        };
+        Ok(Some(resp))
+    }
+
+    /// Send the rows of `COPY ... TO STDOUT` as copy data
+    async fn copy_out<'a, C>(
+        &self,
+        client: &mut C,
+        statement: &CopyStatement,
+    ) -> PgWireResult<Response<'a>>
+    where
+        C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+        C::Error: Debug,
+        PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    {
+        let query = SqlStatement::Query(statement.query()?);
+        self.check_query_permission(client, &query.to_string())
+            .await?;
+        let (query, _) = rewrite_with_notices(query, &self.sql_rewrite_rules);
+
+        let limits = self.query_limits(client).await;
+        let _permit = self.scheduler.acquire(username(client), &limits).await?;
+        let df = self.execute_statement(client, query).await?;
+        let df = limits.apply_memory_limit(self.apply_session_policies(client, df).await?);
+        let resp = df::encode_dataframe_with_options(
+            df,
+            &Format::UnifiedText,
+            &self.encode_options(client),
+        )
+        .await
+        .map_err(map_resource_error)?;
+
+        let options = &statement.options;
+        let columns = resp
+            .row_schema()
+            .iter()
+            .map(|field| field.name().to_string())
+            .collect::<Vec<_>>();
+        options.check_columns(&columns)?;
+        let force_quote = force_mask(&options.force_quote, &columns);
+        client
+            .send(PgWireBackendMessage::CopyOutResponse(CopyOutResponse::new(
+                0,
+                columns.len() as i16,
+                vec![0; columns.len()],
+            )))
+            .await?;
+        if let Some(header) = options.header_line(&columns) {
+            client
+                .send(PgWireBackendMessage::CopyData(CopyData::new(
+                    options.encode(header)?.into(),
+                )))
+                .await?;
+        }
+        let mut rows = resp.data_rows();
+        let mut count = 0;
+        while let Some(row) = rows.next().await {
+            let values = data_row_values(&row?);
+            let values = values.iter().map(Option::as_deref).collect::<Vec<_>>();
+            let line = options.encode_row(&values, &force_quote);
+            client
+                .send(PgWireBackendMessage::CopyData(CopyData::new(
+                    options.encode(line)?.into(),
+                )))
+                .await?;
+            count += 1;
+        }
+        client
+            .send(PgWireBackendMessage::CopyDone(CopyDone::new()))
+            .await?;
+        Ok(Response::Execution(Tag::new("COPY").with_rows(count)))
+    }
+
+    /// Start `COPY ... FROM STDIN`, keeping what the data is inserted into
+    /// until the client sent it
+    async fn copy_in<'a, C>(
+        &self,
+        client: &C,
+        statement: &CopyStatement,
+    ) -> PgWireResult<Response<'a>>
+    where
+        C: ClientInfo,
+    {
+        let CopySource::Table {
+            table_name,
+            columns,
+        } = &statement.source
+        else {
+            return Err(PgWireError::UserError(Box::new(ErrorInfo::new(
+                "ERROR".to_string(),
+                "0A000".to_string(), // feature_not_supported
+                "COPY FROM does not support a query as source".to_string(),
+            ))));
+        };
+        let table = object_name_to_table_reference(table_name.clone(), true)
+            .map_err(|e| PgWireError::ApiError(Box::new(e)))?;
+        self.authorizer
+            .authorize_table(
+                authorized_user(client),
+                Permission::Insert,
+                ResourceType::Table(table.table().to_string()),
+            )
+            .await?;
+        let schema = self
+            .session_context(client)
+            .table_provider(table.clone())
+            .await
+            .map_err(|e| PgWireError::ApiError(Box::new(e)))?
+            .schema();
+
+        let columns = match columns.is_empty() {
+            true => column_names(&schema),
+            false => {
+                let normalizer = IdentNormalizer::default();
+                columns
+                    .iter()
+                    .map(|column| normalizer.normalize(column.clone()))
+                    .collect()
+            }
+        };
+        if let Some(column) = columns
+            .iter()
+            .find(|column| schema.field_with_name(column).is_err())
+        {
+            return Err(PgWireError::UserError(Box::new(ErrorInfo::new(
+                "ERROR".to_string(),
+                "42703".to_string(), // undefined_column
+                format!(
+                    "column \"{column}\" of relation \"{}\" does not exist",
+                    table.table()
+                ),
+            ))));
+        }
+        statement.options.check_columns(&columns)?;
+
+        let resp = CopyResponse::new(0, columns.len(), vec![0; columns.len()]);
+        self.copies.start(
+            client.socket_addr(),
+            CopyIn {
+                table,
+                schema,
+                columns,
+                options: statement.options.clone(),
+                data: Vec::new(),
+            },
+        );
+        Ok(Response::CopyIn(resp))
+    }
+
+    /// Insert the rows of the data of a `COPY ... FROM STDIN`
+    async fn finish_copy_in<C>(&self, client: &C, copy: CopyIn) -> PgWireResult<usize>
+    where
+        C: ClientInfo,
+    {
+        let table_name = copy.table.table().to_string();
+        let rows = copy
+            .options
+            .decode_rows(&copy.data, &copy.columns, &table_name)?;
+        let batch = record_batch(&copy.schema, &copy.columns, &rows, &table_name)?;
+
+        let invalidation = self
+            .result_cache
+            .as_ref()
+            .map(|result_cache| result_cache.invalidate());
+        self.session_context(client)
+            .read_batch(batch)
+            .map_err(|e| PgWireError::ApiError(Box::new(e)))?
+            .write_table(&copy.table.to_quoted_string(), DataFrameWriteOptions::new())
+            .await
+            .map_err(|e| map_resource_error(PgWireError::ApiError(Box::new(e))))?;
+        drop(invalidation);
+        Ok(rows.len())
+    }
+
+    /// Answer `SELECT refresh_table_schema('name')`, which DataFusion can't
+    /// run as the refresh is async
+    async fn try_respond_refresh_schema_function<'a, C>(
+        &self,
+        client: &C,
+        statement: &SqlStatement,
+    ) -> PgWireResult<Option<Response<'a>>>
+    where
+        C: ClientInfo,
+    {
+        let Some(name) = refresh_function_argument(statement) else {
+            return Ok(None);
+        };
+        // sessions without the function fail to plan the call
+        if self
+            .session_context(client)
+            .udf(REFRESH_TABLE_SCHEMA)
+            .is_err()
+        {
+            return Ok(None);
+        }
+        self.refresh_table(client, TableReference::from(name))
+            .await?;
+
+        let fields = vec![FieldInfo::new(
+            REFRESH_TABLE_SCHEMA.to_string(),
+            None,
+            None,
+            Type::BOOL,
+            FieldFormat::Text,
+        )];
+        let row = {
+            let mut encoder = pgwire::api::results::DataRowEncoder::new(Arc::new(fields.clone()));
+            encoder.encode_field(&Some(true))?;
+            encoder.finish()
+        };
+        let row_stream = futures::stream::once(async move { row });
+        Ok(Some(Response::Query(QueryResponse::new(
+            Arc::new(fields),
+            Box::pin(row_stream),
+        ))))
+    }
+
+    async fn refresh_table<C>(&self, client: &C, reference: TableReference) -> PgWireResult<()>
+    where
+        C: ClientInfo,
+    {
+        self.authorizer
+            .authorize_table(
+                authorized_user(client),
+                Permission::Alter,
+                ResourceType::Table(reference.table().to_string()),
+            )
+            .await?;
+        let session_context = self.session_context(client);
+        refresh_table_schema(&session_context, reference.clone()).await?;
+        self.catalog_changed(&session_context, &format!("ALTER TABLE {reference}"));
+        Ok(())
+    }
+
+    /// Register the servers and tables of `CREATE SERVER` and
+    /// `CREATE FOREIGN TABLE`
+    #[cfg(feature = "postgres-fdw")]
+    async fn try_respond_foreign_statements<'a, C>(
+        &self,
+        client: &C,
+        query: &str,
+    ) -> PgWireResult<Option<Response<'a>>>
+    where
+        C: ClientInfo,
+    {
+        if !ForeignStatement::matches(&query.to_lowercase()) {
+            return Ok(None);
+        }
+        let statement = ForeignStatement::parse(query)?;
+        let kind = match &statement {
+            ForeignStatement::CreateServer { .. } => StatementKind::Other,
+            ForeignStatement::CreateForeignTable { .. } => StatementKind::CreateTable,
+        };
+        check_command_rules(self.authorizer.as_ref(), client, kind, query).await?;
+        let (name, if_not_exists, columns, server, options) = match statement {
+            ForeignStatement::CreateServer {
+                name,
+                if_not_exists,
+                options,
+                ..
+            } => {
+                self.authorizer
+                    .authorize_table(
+                        authorized_user(client),
+                        Permission::Usage,
+                        ResourceType::All,
+                    )
+                    .await?;
+                self.foreign_servers
+                    .create(&name, if_not_exists, &options)?;
+                return Ok(Some(Response::Execution(Tag::new("CREATE SERVER"))));
+            }
+            ForeignStatement::CreateForeignTable {
+                name,
+                if_not_exists,
+                columns,
+                server,
+                options,
+            } => (name, if_not_exists, columns, server, options),
+        };
+
+        let reference = object_name_to_table_reference(name.clone(), true)
+            .map_err(|e| PgWireError::ApiError(Box::new(e)))?;
+        self.authorizer
+            .authorize_table(
+                authorized_user(client),
+                Permission::Create,
+                ResourceType::Table(reference.table().to_string()),
+            )
+            .await?;
+        let session_context = self.session_context(client);
+        let tag = Response::Execution(Tag::new("CREATE FOREIGN TABLE"));
+        if session_context
+            .table_exist(reference.clone())
+            .map_err(|e| PgWireError::ApiError(Box::new(e)))?
+        {
+            if if_not_exists {
+                return Ok(Some(tag));
+            }
+            return Err(PgWireError::UserError(Box::new(ErrorInfo::new(
+                "ERROR".to_string(),
+                "42P07".to_string(), // duplicate_table
+                format!("relation \"{}\" already exists", reference.table()),
+            ))));
+        }
+        // the column types are the ones of the same CREATE TABLE
+        let create_table = CreateTableBuilder::new(name)
+            .columns(columns)
+            .hive_formats(Some(HiveFormat::default()))
+            .build();
+        let plan = session_context
+            .state()
+            .statement_to_plan(Statement::Statement(Box::new(create_table)))
+            .await
+            .map_err(|e| PgWireError::ApiError(Box::new(e)))?;
+        let schema = Arc::new(plan.schema().as_arrow().clone());
+        let table = self
+            .foreign_servers
+            .table(&server, reference.table(), &options, schema)?;
+        session_context
+            .register_table(reference.clone(), Arc::new(table))
+            .map_err(|e| PgWireError::ApiError(Box::new(e)))?;
+        self.catalog_changed(
+            &session_context,
+            &format!("CREATE FOREIGN TABLE {reference}"),
+        );
+        Ok(Some(tag))
+    }
+
+    /// Close the prepared statements and portals of the session of `client`
+    /// discarded by `DISCARD ALL`
+    ///
+    /// Simple queries can't reach the portal store, so the message handlers of
+    /// the extended query protocol call it before using the store.
+    fn close_discarded<C>(&self, client: &C)
+    where
+        C: ClientInfo + ClientPortalStore,
+        C::PortalStore: PortalStore,
+    {
+        let Some(discarded) = self
+            .prepared_statements
+            .take_discarded(client.socket_addr())
+        else {
+            return;
+        };
+        for name in &discarded.statements {
+            client.portal_store().rm_statement(name);
+        }
+        for name in &discarded.portals {
+            client.portal_store().rm_portal(name);
+        }
+    }
+
+    /// Deliver the notifications pending for the session of `client`
+    async fn send_notifications<C>(&self, client: &mut C) -> PgWireResult<()>
+    where
+        C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send,
+        PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    {
+        for notification in self.notifications.take(&client.socket_addr()) {
+            client
+                .send(PgWireBackendMessage::NotificationResponse(notification))
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn try_respond_transaction_statements<'a, C>(
+        &self,
+        client: &mut C,
+        query_lower: &str,
+    ) -> PgWireResult<Option<Response<'a>>>
+    where
+        C: ClientInfo,
+    {
+        // BEGIN ISOLATION LEVEL SERIALIZABLE, READ ONLY;
+        let (command, modes) = split_transaction_statement(query_lower);
+
+        // Transaction handling based on pgwire example:
+        // https://github.com/sunng87/pgwire/blob/master/examples/transaction.rs#L57
+        match command {
+            "begin" | "begin transaction" | "begin work" | "start transaction" => {
+                match client.transaction_status() {
+                    TransactionStatus::Idle => {
+                        // the level of the transaction, the default if none
+                        match modes.map(isolation_level).transpose()?.flatten() {
+                            Some(level) => client.metadata_mut().insert(
+                                METADATA_TRANSACTION_ISOLATION.to_string(),
+                                level.to_string(),
+                            ),
+                            None => client.metadata_mut().remove(METADATA_TRANSACTION_ISOLATION),
+                        };
+                        Ok(Some(Response::TransactionStart(Tag::new("BEGIN"))))
+                    }
+                    TransactionStatus::Transaction => {
+                        // PostgreSQL behavior: ignore nested BEGIN, just return SUCCESS
+                        // This matches PostgreSQL's handling of nested transaction blocks
+                        log::warn!("BEGIN command ignored: already in transaction block");
+                        Ok(Some(Response::Execution(Tag::new("BEGIN"))))
+                    }
+                    TransactionStatus::Error => {
+                        // Can't start new transaction from failed state
+                        Err(PgWireError::UserError(Box::new(
+                            pgwire::error::ErrorInfo::new(
+                                "ERROR".to_string(),
+                                "25P01".to_string(),
+                                "current transaction is aborted, commands ignored until end of transaction block".to_string(),
+                            ),
+                        )))
+                    }
+                }
+            }
+            "commit" | "commit transaction" | "commit work" | "end" | "end transaction" => {
+                self.end_transaction_locks(client);
+                match client.transaction_status() {
+                    TransactionStatus::Idle | TransactionStatus::Transaction => {
+                        Ok(Some(Response::TransactionEnd(Tag::new("COMMIT"))))
+                    }
+                    TransactionStatus::Error => {
+                        Ok(Some(Response::TransactionEnd(Tag::new("ROLLBACK"))))
+                    }
+                }
+            }
+            "rollback" | "rollback transaction" | "rollback work" | "abort" => {
+                self.end_transaction_locks(client);
+                Ok(Some(Response::TransactionEnd(Tag::new("ROLLBACK"))))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Answer the commands of replication connections with the replication
+    /// handler
+    async fn try_respond_replication_command<'a, C>(
+        &self,
+        client: &C,
+        query: &str,
+    ) -> PgWireResult<Option<Response<'a>>>
+    where
+        C: ClientInfo,
+    {
+        let Some(handler) = &self.replication_handler else {
+            return Ok(None);
+        };
+        let Some(value) = client.metadata().get(REPLICATION_PARAMETER) else {
+            return Ok(None);
+        };
+        let Some(mode) = ReplicationMode::from_parameter(value)? else {
+            return Ok(None);
+        };
+        let command = query.trim().trim_end_matches(';').trim_end();
+        handler
+            .on_command(&HookContext::new(client), mode, command)
+            .await
+    }
+
+    async fn try_respond_show_statements<'a, C>(
+        &self,
+        client: &C,
+        query_lower: &str,
+    ) -> PgWireResult<Option<Response<'a>>>
+    where
+        C: ClientInfo,
+    {
+        if query_lower.starts_with("show ") {
+            let name = query_lower.strip_suffix(";").unwrap_or(query_lower)["show ".len()..].trim();
+            match name {
+                "all" => {
+                    let settings = self.session_settings(client).await;
+                    Ok(Some(Response::Query(Self::show_all_response(&settings)?)))
+                }
+                "catalogs" => {
+                    let catalogs = self.session_context(client).catalog_names();
+                    let value = catalogs.join(", ");
+                    let resp = Self::mock_show_response("Catalogs", &value)?;
+                    Ok(Some(Response::Query(resp)))
+                }
+                _ => {
+                    let name = match name.trim_matches('"') {
+                        "time zone" => "timezone",
+                        "transaction isolation level" => "transaction_isolation",
+                        name => name,
+                    };
+                    let settings = self.session_settings(client).await;
+                    let setting = settings.get(name).ok_or_else(|| {
+                        PgWireError::UserError(Box::new(pgwire::error::ErrorInfo::new(
+                            "ERROR".to_string(),
+                            "42704".to_string(), // undefined_object
+                            format!("unrecognized configuration parameter \"{name}\""),
+                        )))
+                    })?;
+                    let resp = Self::mock_show_response(setting.name(), &setting.setting)?;
+                    Ok(Some(Response::Query(resp)))
+                }
+            }
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+impl DfSessionService {
+    /// Run a statement of the simple query protocol
+    async fn run_simple_query<'a, C>(
+        &self,
+        client: &mut C,
+        query: &str,
+    ) -> PgWireResult<Response<'a>>
+    where
+        C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+        C::Error: Debug,
+        PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    {
+        log::debug!("Received query: {query}"); // Log the query for debugging
+        let mut activity = self.activity.statement_started(client, query);
+
+        self.check_statement_rate_limit(client)?;
+
+        // Check for transaction commands early to avoid SQL parsing issues with ABORT
+        let query_lower = query.to_lowercase().trim().to_string();
+        if let Some(resp) = self
+            .try_respond_transaction_statements(client, &query_lower)
+            .await?
+        {
+            activity.observe(&resp);
+            return Ok(resp);
+        }
+
+        // statements sqlparser doesn't parse
+        if let Some(resp) = self.try_respond_storage_statements(client, query).await? {
+            activity.observe(&resp);
+            return Ok(resp);
+        }
+        if let Some(resp) = self
+            .try_respond_alter_database_statements(client, query)
+            .await?
+        {
+            activity.observe(&resp);
+            return Ok(resp);
+        }
+        if let Some(resp) = self
+            .try_respond_external_table_statements(client, query)
+            .await?
+        {
+            activity.observe(&resp);
+            return Ok(resp);
+        }
+        if let Some(resp) = self
+            .try_respond_refresh_schema_statements(client, query)
+            .await?
+        {
+            activity.observe(&resp);
+            return Ok(resp);
+        }
+        if let Some(resp) = self
+            .try_respond_partitioned_copy_statements(client, query)
+            .await?
+        {
+            activity.observe(&resp);
+            return Ok(resp);
+        }
+        if let Some(resp) = self.try_respond_copy_statements(client, query).await? {
+            activity.observe(&resp);
+            return Ok(resp);
+        }
+        if let Some(resp) = self
+            .try_respond_maintenance_statements(client, query)
+            .await?
+        {
+            activity.observe(&resp);
+            return Ok(resp);
+        }
+        #[cfg(feature = "postgres-fdw")]
+        if let Some(resp) = self.try_respond_foreign_statements(client, query).await? {
+            activity.observe(&resp);
+            return Ok(resp);
+        }
+
+        let parse_span = self.telemetry.phase(client, Phase::Parse);
+        let statements = parse(query).map_err(|e| syntax_error(e, query))?;
+
+        // queries of only comments are empty, like the keepalives of drivers
+        let Some(statement) = statements.into_iter().next() else {
+            let resp = Response::EmptyQuery;
+            activity.observe(&resp);
+            return Ok(resp);
+        };
+
+        // Attempt to rewrite
+        let (statement, notices) = rewrite_with_notices(statement, &self.sql_rewrite_rules);
+        let statement = TemporalLiteralRewrite::new(date_order(client)).rewrite(statement);
+        let statement_query_id = query_id(&statement);
+        activity.parsed(&statement, statement_query_id);
+        log::debug!("Query {statement_query_id}: {statement}");
+
+        check_statement_rules(self.authorizer.as_ref(), client, &statement).await?;
+        drop(parse_span);
+        send_ignored_clause_notices(client, &notices).await?;
+        if let Some(unsupported) = unsupported_on_conflict(&statement) {
+            client
+                .send(PgWireBackendMessage::NoticeResponse(unsupported.notice()))
+                .await?;
+            return Err(unsupported.into_error());
+        }
+
+        if let Some(resp) = self.try_respond_notification_statements(client, &statement) {
+            activity.observe(&resp);
+            return Ok(resp);
+        }
+
+        if let Some(resp) = self.try_respond_discard_statements(client, &statement)? {
+            activity.observe(&resp);
+            return Ok(resp);
+        }
+
+        if let Some(resp) = self.try_respond_database_statements(client, &statement)? {
+            activity.observe(&resp);
+            return Ok(resp);
+        }
+
+        if let Some(resp) = self.try_respond_role_statements(client, &statement).await? {
+            activity.observe(&resp);
+            return Ok(resp);
+        }
+
+        if let Some(resp) = self
+            .try_respond_setting_statements(client, &statement)
+            .await?
+        {
+            activity.observe(&resp);
+            return Ok(resp);
+        }
+
+        if let Some(resp) = self
+            .try_respond_refresh_schema_function(client, &statement)
+            .await?
+        {
+            activity.observe(&resp);
+            return Ok(resp);
+        }
+
+        // TODO: improve statement check by using statement directly
+        let query = statement.to_string();
+        let query_lower = query.to_lowercase().trim().to_string();
+
+        // Check permissions for the query (skip for SET, transaction, and SHOW statements)
+        if !query_lower.starts_with("set")
+            && !query_lower.starts_with("begin")
+            && !query_lower.starts_with("commit")
+            && !query_lower.starts_with("rollback")
+            && !query_lower.starts_with("start")
+            && !query_lower.starts_with("end")
+            && !query_lower.starts_with("abort")
+            && !query_lower.starts_with("show")
+        {
+            self.check_query_permission(client, &query).await?;
+        }
+
+        if let Some(resp) = Self::try_respond_set_time_zone(client, &statement)?
+            .or(Self::try_respond_set_datestyle(client, &statement)?)
+            .or(Self::try_respond_set_standard_conforming_strings(
+                client, &statement,
+            )?)
+            .or(Self::try_respond_set_extra_float_digits(
+                client, &statement,
+            )?)
+            .or(Self::try_respond_set_interval_style(client, &statement)?)
+        {
+            send_parameter_status(client, &statement).await?;
+            activity.observe(&resp);
+            return Ok(resp);
+        }
+
+        if let Some(resp) = self
+            .try_respond_set_statements(client, &query_lower)
+            .await?
+        {
+            send_parameter_status(client, &statement).await?;
+            activity.observe(&resp);
+            return Ok(resp);
+        }
+
+        if let Some(resp) = self
+            .try_respond_show_statements(client, &query_lower)
+            .await?
+        {
+            activity.observe(&resp);
+            return Ok(resp);
+        }
+
+        // Check if we're in a failed transaction and block non-transaction
+        // commands
+        if client.transaction_status() == TransactionStatus::Error {
+            return Err(PgWireError::UserError(Box::new(
+                pgwire::error::ErrorInfo::new(
+                    "ERROR".to_string(),
+                    "25P01".to_string(),
+                    "current transaction is aborted, commands ignored until end of transaction block".to_string(),
+                ),
+            )));
+        }
+
+        let limits = self.query_limits(client).await;
+        activity.wait_event(Some(STATEMENT_ADMISSION_WAIT));
+        let permit = self.scheduler.acquire(username(client), &limits).await;
+        activity.wait_event(None);
+        let permit = permit?;
+
+        if let Some(explain) = ExplainOptions::from_statement(&statement) {
+            let (options, statement) = explain?;
+            self.check_query_permission(client, &statement.to_string())
+                .await?;
+            let explained_query_id = query_id(&statement);
+            let plan = session_state(&self.session_context(client), client)
+                .statement_to_plan(Statement::Statement(Box::new(statement)))
+                .await
+                .map_err(|e| PgWireError::ApiError(Box::new(e)))?;
+            let resp = self
+                .explain_plan(
+                    client,
+                    plan,
+                    &options,
+                    explained_query_id,
+                    &Format::UnifiedText,
+                    &limits,
+                )
+                .await?;
+            return Ok(Response::Query(activity.hold_for(permit.hold_for(resp))));
+        }
+
+        // INSERT ... RETURNING answers the inserted rows
+        let returning = split_returning(&statement).is_some();
+        let invalidation = self
+            .result_cache
+            .as_ref()
+            .filter(|_| invalidates_results(&statement))
+            .map(|result_cache| result_cache.invalidate());
+        let plan_span = self.telemetry.phase(client, Phase::Plan);
+        let df_result = {
+            let timeout = Self::get_statement_timeout(client);
+            if let Some(timeout_duration) = timeout {
+                tokio::time::timeout(timeout_duration, self.execute_statement(client, statement))
+                    .await
+                    .map_err(|_| {
+                        PgWireError::UserError(Box::new(pgwire::error::ErrorInfo::new(
+                            "ERROR".to_string(),
+                            "57014".to_string(), // query_canceled error code
+                            "canceling statement due to statement timeout".to_string(),
+                        )))
+                    })?
+            } else {
+                self.execute_statement(client, statement).await
+            }
+        };
+        drop(plan_span);
+
+        let df = limits.apply_memory_limit(self.apply_session_policies(client, df_result?).await?);
+
+        // INSERT and COPY ... TO respond with the count of the rows they wrote
+        let command_tag = if query_lower.starts_with("insert into") && !returning {
+            Some(Tag::new("INSERT").with_oid(0))
+        } else if matches!(df.logical_plan(), LogicalPlan::Copy(_)) {
+            Some(Tag::new("COPY"))
+        } else {
+            None
+        };
+        if let Some(tag) = command_tag {
+            // For these statements, we need to execute the query to get the row count
+            // and return an Execution response with the proper tag
+            let result = self
+                .telemetry
+                .phase(client, Phase::Execute)
+                .run(df.clone().collect())
+                .await
+                .map_err(|e| map_resource_error(PgWireError::ApiError(Box::new(e))))?;
+
+            drop(invalidation);
+
+            let resp = Response::Execution(tag.with_rows(rows_affected(&result)));
+            activity.observe(&resp);
+            Ok(resp)
+        } else {
+            // For other statements, return a regular Query response
+            let logical_plan = df.logical_plan().clone();
+            let (resp, plan) = self
+                .telemetry
+                .phase(client, Phase::Execute)
+                .run(async {
+                    let df = if returning {
+                        df
+                    } else {
+                        self.cached_result(client, query, df).await?
+                    };
+                    df::encode_dataframe_with_plan(
+                        df,
+                        &Format::UnifiedText,
+                        &self.encode_options(client),
+                    )
+                    .await
+                })
+                .await
+                .map_err(map_resource_error)?;
+            let fields = with_column_origins(
+                resp.row_schema().as_ref().clone(),
+                &logical_plan,
+                &self.session_context(client),
+            );
+            let resp = QueryResponse::new(Arc::new(fields), resp.data_rows());
+            let resp = match invalidation {
+                Some(invalidation) => invalidation.hold_for(resp),
+                None => resp,
+            };
+            let resp = self.telemetry.phase(client, Phase::Encode).hold_for(resp);
+            let resp = activity.hold_for(permit.hold_for(limits.guard_response(resp)));
+            let resp = self.notify_progress(client, resp, plan).await?;
+            let mut resp = self.send_lock_warnings(client, resp, &logical_plan).await?;
+            if returning {
+                resp.set_command_tag("INSERT 0");
+            }
+            Ok(Response::Query(resp))
+        }
+    }
+
+    /// Run the statement of a portal of the extended query protocol
+    async fn run_portal<'a, C>(
+        &self,
+        client: &mut C,
+        portal: &Portal<(String, LogicalPlan, Vec<String>)>,
+    ) -> PgWireResult<Response<'a>>
+    where
+        C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+        C::Error: Debug,
+        PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    {
+        let query = portal
+            .statement
+            .statement
+            .0
+            .to_lowercase()
+            .trim()
+            .to_string();
+        log::debug!("Received execute extended query: {query}"); // Log for debugging
+        let mut activity = self
+            .activity
+            .statement_started(client, &portal.statement.statement.0);
+        if query.is_empty() {
+            let resp = Response::EmptyQuery;
+            activity.observe(&resp);
+            return Ok(resp);
+        }
+        // the stored text is the rewritten statement, its identifier is the
+        // one of the same simple query
+        let statement = parse(&portal.statement.statement.0)
+            .ok()
+            .and_then(|statements| statements.into_iter().next());
+        if let Some(statement) = &statement {
+            let statement_query_id = query_id(statement);
+            activity.parsed(statement, statement_query_id);
+            log::debug!("Query {statement_query_id}: {query}");
+        }
+
+        self.check_statement_rate_limit(client)?;
+
+        if let Some(resp) = statement
+            .as_ref()
+            .and_then(|statement| self.try_respond_notification_statements(client, statement))
+        {
+            activity.observe(&resp);
+            return Ok(resp);
+        }
+
+        if let Some(statement) = &statement {
+            if let Some(resp) = self.try_respond_discard_statements(client, statement)? {
+                activity.observe(&resp);
+                return Ok(resp);
+            }
+            if let Some(resp) = self.try_respond_database_statements(client, statement)? {
+                activity.observe(&resp);
+                return Ok(resp);
+            }
+            if let Some(resp) = self.try_respond_role_statements(client, statement).await? {
+                activity.observe(&resp);
+                return Ok(resp);
+            }
+            if let Some(resp) = self
+                .try_respond_setting_statements(client, statement)
+                .await?
+            {
+                activity.observe(&resp);
+                return Ok(resp);
+            }
+            if let Some(resp) = self
+                .try_respond_refresh_schema_function(client, statement)
+                .await?
+            {
+                activity.observe(&resp);
+                return Ok(resp);
+            }
+        }
+
+        // Check permissions for the query (skip for SET and SHOW statements)
+        if !query.starts_with("set") && !query.starts_with("show") {
+            self.check_query_permission(client, &portal.statement.statement.0)
+                .await?;
+        }
+        if let Some(copy_to) = statement.as_ref().and_then(copy_to_statement) {
+            self.authorize_copy_to(client, &copy_to?).await?;
+        }
+        send_ignored_clause_notices(client, &portal.statement.statement.2).await?;
+
+        if let Some(statement) = &statement {
+            if let Some(resp) = Self::try_respond_set_time_zone(client, statement)?
+                .or(Self::try_respond_set_datestyle(client, statement)?)
+                .or(Self::try_respond_set_standard_conforming_strings(
+                    client, statement,
+                )?)
+                .or(Self::try_respond_set_extra_float_digits(client, statement)?)
+                .or(Self::try_respond_set_interval_style(client, statement)?)
+            {
+                send_parameter_status(client, statement).await?;
+                activity.observe(&resp);
+                return Ok(resp);
+            }
+        }
+
+        if let Some(resp) = self.try_respond_set_statements(client, &query).await? {
+            if let Some(statement) = &statement {
+                send_parameter_status(client, statement).await?;
+            }
+            activity.observe(&resp);
+            return Ok(resp);
+        }
+
+        if let Some(resp) = self
+            .try_respond_transaction_statements(client, &query)
+            .await?
+        {
+            activity.observe(&resp);
+            return Ok(resp);
+        }
+
+        if let Some(resp) = self
+            .try_respond_storage_statements(client, &portal.statement.statement.0)
+            .await?
+        {
+            activity.observe(&resp);
+            return Ok(resp);
+        }
+        if let Some(resp) = self
+            .try_respond_alter_database_statements(client, &portal.statement.statement.0)
+            .await?
+        {
+            activity.observe(&resp);
+            return Ok(resp);
+        }
+        if let Some(resp) = self
+            .try_respond_external_table_statements(client, &portal.statement.statement.0)
+            .await?
+        {
+            activity.observe(&resp);
+            return Ok(resp);
+        }
+        if let Some(resp) = self
+            .try_respond_refresh_schema_statements(client, &portal.statement.statement.0)
+            .await?
+        {
+            activity.observe(&resp);
+            return Ok(resp);
+        }
+        if let Some(resp) = self
+            .try_respond_partitioned_copy_statements(client, &portal.statement.statement.0)
+            .await?
+        {
+            activity.observe(&resp);
+            return Ok(resp);
+        }
+        if let Some(resp) = self
+            .try_respond_copy_statements(client, &portal.statement.statement.0)
+            .await?
+        {
+            activity.observe(&resp);
+            return Ok(resp);
+        }
+        if let Some(resp) = self
+            .try_respond_maintenance_statements(client, &portal.statement.statement.0)
+            .await?
+        {
+            activity.observe(&resp);
+            return Ok(resp);
+        }
+        #[cfg(feature = "postgres-fdw")]
+        if let Some(resp) = self
+            .try_respond_foreign_statements(client, &portal.statement.statement.0)
+            .await?
+        {
+            activity.observe(&resp);
+            return Ok(resp);
+        }
+
+        if let Some(resp) = self.try_respond_show_statements(client, &query).await? {
+            activity.observe(&resp);
+            return Ok(resp);
+        }
+
+        // Check if we're in a failed transaction and block non-transaction
+        // commands
+        if client.transaction_status() == TransactionStatus::Error {
+            return Err(PgWireError::UserError(Box::new(
+                pgwire::error::ErrorInfo::new(
+                    "ERROR".to_string(),
+                    "25P01".to_string(),
+                    "current transaction is aborted, commands ignored until end of transaction block".to_string(),
+                ),
+            )));
+        }
+
+        let (_, plan, _) = &portal.statement.statement;
+        let bind_span = self.telemetry.phase(client, Phase::Bind);
+        let (param_values, plan) = bind_parameters(portal, plan)?;
+        drop(bind_span);
+        let plan = self
+            .query_hooks
+            .before_execute(&HookContext::new(client), plan)
+            .await?;
+        let plan_span = self.telemetry.phase(client, Phase::Plan);
+        let session_context = self.session_context(client);
+        let mut state = session_context.state();
+        let plan = self.bind_start_times(client, &mut state, plan)?;
+        // policies go in before projection pushdown narrows the scans
+        let policed = self
+            .apply_row_policies(client, &state, plan.clone())
+            .await?;
+        let optimised = state
+            .optimize(&policed)
+            .map_err(|e| PgWireError::ApiError(Box::new(e)))?;
+        drop(plan_span);
+
+        let limits = self.query_limits(client).await;
+        activity.wait_event(Some(STATEMENT_ADMISSION_WAIT));
+        let permit = self.scheduler.acquire(username(client), &limits).await;
+        activity.wait_event(None);
+        let permit = permit?;
+
+        // the stored plan of EXPLAIN statements is the plan of the explained
+        // statement
+        if let Some(explain) = ExplainOptions::from_sql(&portal.statement.statement.0) {
+            let (options, statement) = explain?;
+            self.check_query_permission(client, &statement.to_string())
+                .await?;
+            let resp = self
+                .explain_plan(
+                    client,
+                    plan,
+                    &options,
+                    query_id(&statement),
+                    &portal.result_column_format,
+                    &limits,
+                )
+                .await?;
+            return Ok(Response::Query(activity.hold_for(permit.hold_for(resp))));
+        }
+
+        // INSERT ... RETURNING is planned again, the stored plan only
+        // describes the returned rows
+        let returning = statement.as_ref().and_then(split_returning);
+        let dataframe = if let Some((insert, returning)) = &returning {
+            self.execute_insert_returning(client, insert.clone(), returning, Some(&param_values))
+                .await?
+        } else {
+            let timeout = Self::get_statement_timeout(client);
+            if let Some(timeout_duration) = timeout {
+                tokio::time::timeout(
+                    timeout_duration,
+                    session_context.execute_logical_plan(optimised),
+                )
+                .await
+                .map_err(|_| {
+                    PgWireError::UserError(Box::new(pgwire::error::ErrorInfo::new(
+                        "ERROR".to_string(),
+                        "57014".to_string(), // query_canceled error code
+                        "canceling statement due to statement timeout".to_string(),
+                    )))
+                })?
+                .map_err(|e| PgWireError::ApiError(Box::new(e)))?
+            } else {
+                session_context
+                    .execute_logical_plan(optimised)
+                    .await
+                    .map_err(|e| PgWireError::ApiError(Box::new(e)))?
+            }
+        };
+        if let LogicalPlan::Ddl(_) = &plan {
+            self.catalog_changed(&session_context, &schema_change(&plan));
+        }
+        let invalidation = self
+            .result_cache
+            .as_ref()
+            .filter(|_| {
+                matches!(plan, LogicalPlan::Dml(_) | LogicalPlan::Copy(_)) || returning.is_some()
+            })
+            .map(|result_cache| result_cache.invalidate());
+        // the start times of the stored plan were bound before planning,
+        // those of the returned rows weren't
+        let dataframe = if returning.is_some() {
+            self.apply_session_policies(client, dataframe).await?
+        } else {
+            let (mut dataframe_state, dataframe_plan) = dataframe.into_parts();
+            dataframe_state
+                .execution_props_mut()
+                .query_execution_start_time = state.execution_props().query_execution_start_time;
+            self.apply_bound_session_policies(client, dataframe_state, dataframe_plan)
+                .await?
+        };
+        let dataframe = limits.apply_memory_limit(dataframe);
+        // the result depends on the values of the parameters too
+        let query = format!("{} {param_values:?}", portal.statement.statement.0);
+        let logical_plan = dataframe.logical_plan().clone();
+        let (resp, plan) = self
+            .telemetry
+            .phase(client, Phase::Execute)
+            .run(async {
+                let dataframe = if returning.is_some() {
+                    dataframe
+                } else {
+                    self.cached_result(client, query, dataframe).await?
+                };
+                df::encode_dataframe_with_plan(
+                    dataframe,
+                    &portal.result_column_format,
+                    &self.encode_options(client),
+                )
+                .await
+            })
+            .await
+            .map_err(map_resource_error)?;
+        let resp = match invalidation {
+            Some(invalidation) => invalidation.hold_for(resp),
+            None => resp,
+        };
+        let resp = self.telemetry.phase(client, Phase::Encode).hold_for(resp);
+        let resp = activity.hold_for(permit.hold_for(limits.guard_response(resp)));
+        let resp = self.notify_progress(client, resp, plan).await?;
+        let mut resp = self.send_lock_warnings(client, resp, &logical_plan).await?;
+        if returning.is_some() {
+            resp.set_command_tag("INSERT 0");
+        }
+        Ok(Response::Query(resp))
+    }
+}
+
+#[async_trait]
+impl SimpleQueryHandler for DfSessionService {
+    async fn on_query<C>(&self, client: &mut C, query: Query) -> PgWireResult<()>
+    where
+        C: ClientInfo + ClientPortalStore + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+        C::Error: Debug,
+        PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    {
+        let mut client = WireDebugClient::new(client, self.wire_debug);
+        client.log_frontend(&query);
+        self._on_query(&mut client, query).await
+    }
+
+    async fn do_query<'a, C>(&self, client: &mut C, query: &str) -> PgWireResult<Vec<Response<'a>>>
+    where
+        C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+        C::Error: Debug,
+        PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    {
+        if let Some(resp) = self.try_respond_replication_command(client, query).await? {
+            return Ok(vec![resp]);
+        }
+
+        let started = Instant::now();
+        let context = HookContext::new(client);
+        let mut query = self.query_hooks.before_parse(&context, query).await?;
+        if !standard_conforming_strings(client) {
+            query = escape_backslash_literals(&query).into_owned();
+        }
+        let Some(statements) = split_statements(&query) else {
+            let result = self.run_simple_query(client, &query).await;
+            let resp = self
+                .query_hooks
+                .after_execute(context, query, started, result)
+                .await?;
+            self.send_notifications(client).await?;
+            return Ok(vec![resp]);
+        };
+
+        // poolers and drivers send several statements in one query, like the
+        // SET statements of a session, they run in turn up to the first error
+        let mut responses = Vec::with_capacity(statements.len());
+        for statement in statements {
+            let started = Instant::now();
+            let context = HookContext::new(client);
+            let result = self.run_simple_query(client, &statement).await;
+            match self
+                .query_hooks
+                .after_execute(context, statement, started, result)
+                .await
+            {
+                Ok(resp) => {
+                    // the following statements run in the transaction status
+                    // left by this one
+                    let status = client.transaction_status();
+                    match &resp {
+                        Response::TransactionStart(_) => {
+                            client.set_transaction_status(status.to_in_transaction_state())
+                        }
+                        Response::TransactionEnd(_) => {
+                            client.set_transaction_status(status.to_idle_state())
+                        }
+                        _ => {}
+                    }
+                    responses.push(resp);
+                }
+                Err(error) => {
+                    let error = into_sqlstate_error(error);
+                    info!("Sending error: {error}");
+                    responses.push(Response::Error(Box::new(error.into())));
+                    break;
+                }
+            }
+        }
+        self.send_notifications(client).await?;
+        Ok(responses)
+    }
+}
+
+#[async_trait]
+impl ExtendedQueryHandler for DfSessionService {
+    type Statement = (String, LogicalPlan, Vec<String>);
+    type QueryParser = Parser;
+
+    fn query_parser(&self) -> Arc<Self::QueryParser> {
+        self.parser.clone()
+    }
+
+    // the message handlers log the messages in wire debug mode and buffer
+    // their responses up to the next Flush or Sync, otherwise they are the
+    // default handlers of pgwire
+
+    async fn on_parse<C>(&self, client: &mut C, message: Parse) -> PgWireResult<()>
+    where
+        C: ClientInfo + ClientPortalStore + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+        C::PortalStore: PortalStore<Statement = Self::Statement>,
+        C::Error: Debug,
+        PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    {
+        let mut client = PipelineClient::new(client);
+        let mut client = WireDebugClient::new(&mut client, self.wire_debug);
+        client.log_frontend(&message);
+        self.close_discarded(&client);
+        let types = message
+            .type_oids
+            .iter()
+            .map(|oid| Type::from_oid(*oid).unwrap_or(Type::UNKNOWN))
+            .collect::<Vec<_>>();
+        let statement = self
+            .parser
+            .parse_sql(&client, &message.query, &types)
+            .await?;
+        let name = message.name.unwrap_or_else(|| DEFAULT_NAME.to_owned());
+        self.prepared_statements.parsed(client.socket_addr(), &name);
+        client
+            .portal_store()
+            .put_statement(Arc::new(StoredStatement::new(name, statement, types)));
+        client
+            .send(PgWireBackendMessage::ParseComplete(ParseComplete::new()))
+            .await?;
+        Ok(())
+    }
+
+    async fn on_bind<C>(&self, client: &mut C, message: Bind) -> PgWireResult<()>
+    where
+        C: ClientInfo + ClientPortalStore + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+        C::PortalStore: PortalStore<Statement = Self::Statement>,
+        C::Error: Debug,
+        PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    {
+        let mut client = PipelineClient::new(client);
+        let mut client = WireDebugClient::new(&mut client, self.wire_debug);
+        client.log_frontend(&message);
+        self.close_discarded(&client);
+        let statement_name = message.statement_name.as_deref().unwrap_or(DEFAULT_NAME);
+        let statement = client
+            .portal_store()
+            .get_statement(statement_name)
+            .ok_or_else(|| PgWireError::StatementNotFound(statement_name.to_owned()))?;
+        let portal = Portal::try_new(&message, statement)?;
+        self.prepared_statements
+            .bound(client.socket_addr(), &portal.name);
+        client.portal_store().put_portal(Arc::new(portal));
+        client
+            .send(PgWireBackendMessage::BindComplete(BindComplete::new()))
+            .await?;
+        Ok(())
+    }
+
+    async fn on_execute<C>(&self, client: &mut C, message: Execute) -> PgWireResult<()>
+    where
+        C: ClientInfo + ClientPortalStore + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+        C::PortalStore: PortalStore<Statement = Self::Statement>,
+        C::Error: Debug,
+        PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    {
+        let mut client = PipelineClient::new(client);
+        let mut client = WireDebugClient::new(&mut client, self.wire_debug);
+        client.log_frontend(&message);
+        self.close_discarded(&client);
+        self._on_execute(&mut client, message).await
+    }
+
+    async fn on_describe<C>(&self, client: &mut C, message: Describe) -> PgWireResult<()>
+    where
+        C: ClientInfo + ClientPortalStore + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+        C::PortalStore: PortalStore<Statement = Self::Statement>,
+        C::Error: Debug,
+        PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    {
+        let mut client = PipelineClient::new(client);
+        let mut client = WireDebugClient::new(&mut client, self.wire_debug);
+        client.log_frontend(&message);
+        self.close_discarded(&client);
+        self._on_describe(&mut client, message).await
+    }
+
+    async fn on_flush<C>(&self, client: &mut C, message: Flush) -> PgWireResult<()>
+    where
+        C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+        C::Error: Debug,
+        PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    {
+        let mut client = WireDebugClient::new(client, self.wire_debug);
+        client.log_frontend(&message);
+        client.flush().await?;
+        Ok(())
+    }
+
+    async fn on_sync<C>(&self, client: &mut C, message: PgSync) -> PgWireResult<()>
+    where
+        C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+        C::Error: Debug,
+        PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    {
+        let mut client = WireDebugClient::new(client, self.wire_debug);
+        client.log_frontend(&message);
+        let ready = ReadyForQuery::new(client.transaction_status());
+        client
+            .send(PgWireBackendMessage::ReadyForQuery(ready))
+            .await?;
+        client.flush().await?;
+        Ok(())
+    }
+
+    async fn on_close<C>(&self, client: &mut C, message: Close) -> PgWireResult<()>
+    where
+        C: ClientInfo + ClientPortalStore + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+        C::PortalStore: PortalStore<Statement = Self::Statement>,
+        C::Error: Debug,
+        PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    {
+        let mut client = PipelineClient::new(client);
+        let mut client = WireDebugClient::new(&mut client, self.wire_debug);
+        client.log_frontend(&message);
+        self.close_discarded(&client);
+        let name = message.name.as_deref().unwrap_or(DEFAULT_NAME);
+        let session = client.socket_addr();
+        match message.target_type {
+            TARGET_TYPE_BYTE_STATEMENT => {
+                self.prepared_statements.closed_statement(session, name);
+                client.portal_store().rm_statement(name);
+            }
+            TARGET_TYPE_BYTE_PORTAL => {
+                self.prepared_statements.closed_portal(session, name);
+                client.portal_store().rm_portal(name);
+            }
+            _ => {}
+        }
+        client
+            .send(PgWireBackendMessage::CloseComplete(CloseComplete::new()))
+            .await?;
+        Ok(())
+    }
+
+    async fn do_describe_statement<C>(
+        &self,
+        client: &mut C,
+        target: &StoredStatement<Self::Statement>,
+    ) -> PgWireResult<DescribeStatementResponse>
+    where
+        C: ClientInfo + Unpin + Send + Sync,
+    {
+        let (query, plan, _) = &target.statement;
+        let fields = if ExplainOptions::from_sql(query).is_some() {
+            arrow_schema_to_pg_fields(&explain::explain_schema(), &Format::UnifiedBinary)?
+        } else {
+            let fields =
+                arrow_schema_to_pg_fields(plan.schema().as_arrow(), &Format::UnifiedBinary)?;
+            with_column_origins(fields, plan, &self.session_context(client))
+        };
+        let params = plan
+            .get_parameter_types()
+            .map_err(|e| PgWireError::ApiError(Box::new(e)))?;
+
+        let mut param_types = Vec::with_capacity(params.len());
+        for param_type in ordered_param_types(&params).iter() {
+            // Fixed: Use &params
+            if let Some(datatype) = param_type {
+                let pgtype = into_pg_type(datatype)?;
+                param_types.push(pgtype);
+            } else {
+                param_types.push(Type::UNKNOWN);
+            }
+        }
+
+        Ok(DescribeStatementResponse::new(param_types, fields))
+    }
+
+    async fn do_describe_portal<C>(
+        &self,
+        client: &mut C,
+        target: &Portal<Self::Statement>,
+    ) -> PgWireResult<DescribePortalResponse>
+    where
+        C: ClientInfo + Unpin + Send + Sync,
+    {
+        let (query, plan, _) = &target.statement.statement;
+        let format = &target.result_column_format;
+        let fields = if ExplainOptions::from_sql(query).is_some() {
+            arrow_schema_to_pg_fields(&explain::explain_schema(), format)?
+        } else {
+            let (_, plan) = bind_parameters(target, plan)?;
+            let fields = arrow_schema_to_pg_fields(plan.schema().as_arrow(), format)?;
+            with_column_origins(fields, &plan, &self.session_context(client))
+        };
+
+        Ok(DescribePortalResponse::new(fields))
+    }
+
+    async fn do_query<'a, C>(
+        &self,
+        client: &mut C,
+        portal: &Portal<Self::Statement>,
+        _max_rows: usize,
+    ) -> PgWireResult<Response<'a>>
+    where
+        C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+        C::Error: Debug,
+        PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    {
+        let started = Instant::now();
+        let context = HookContext::new(client);
+        let result = self.run_portal(client, portal).await;
+        let resp = self
+            .query_hooks
+            .after_execute(
+                context,
+                portal.statement.statement.0.clone(),
+                started,
+                result,
+            )
+            .await?;
+        self.send_notifications(client).await?;
+        Ok(resp)
+    }
+}
+
+/// Isolation levels of transactions, which all run as read committed
+const ISOLATION_LEVELS: [&str; 4] = [
+    "read uncommitted",
+    "read committed",
+    "repeatable read",
+    "serializable",
+];
+
+/// The access modes of transactions, accepted but not enforced
+const ACCESS_MODES: [&str; 4] = ["read only", "read write", "not deferrable", "deferrable"];
+
+/// The isolation level of lowercased transaction modes, like
+/// `isolation level serializable, read write`, if they set one
+fn isolation_level(modes: &str) -> PgWireResult<Option<&'static str>> {
+    let invalid = || {
+        PgWireError::UserError(Box::new(pgwire::error::ErrorInfo::new(
+            "ERROR".to_string(),
+            "42601".to_string(), // syntax_error
+            format!("invalid transaction modes: {modes}"),
+        )))
+    };
+    let mut level = None;
+    let mut rest = modes.trim().trim_end_matches(';').trim_end();
+    // modes are separated by commas or, as PostgreSQL allows, by spaces
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix("isolation level ") {
+            let after = after.trim_start();
+            let found = ISOLATION_LEVELS
+                .into_iter()
+                .find(|isolation_level| after.starts_with(isolation_level))
+                .ok_or_else(invalid)?;
+            level = Some(found);
+            rest = &after[found.len()..];
+        } else if let Some(mode) = ACCESS_MODES.into_iter().find(|mode| rest.starts_with(mode)) {
+            rest = &rest[mode.len()..];
+        } else {
+            return Err(invalid());
+        }
+        rest = rest.trim_start();
+        if let Some(after) = rest.strip_prefix(',') {
+            rest = after.trim_start();
+            if rest.is_empty() {
+                return Err(invalid());
+            }
+        }
+    }
+    Ok(level)
+}
+
+/// The commands starting transactions, which may be followed by modes
+const BEGIN_COMMANDS: [&str; 4] = [
+    "begin transaction",
+    "begin work",
+    "start transaction",
+    "begin",
+];
+
+/// Split a lowercased transaction statement into its command and the
+/// transaction modes following it, tolerating a trailing semicolon
+fn split_transaction_statement(query_lower: &str) -> (&str, Option<&str>) {
+    let query = query_lower.trim().trim_end_matches(';').trim_end();
+    BEGIN_COMMANDS
+        .into_iter()
+        .find_map(|command| {
+            let modes = query.strip_prefix(command)?.strip_prefix(' ')?;
+            Some((command, Some(modes.trim())))
+        })
+        .unwrap_or((query, None))
+}
+
+/// Whether a lowercased statement is answered by
+/// `try_respond_transaction_statements`
+fn is_transaction_statement(query_lower: &str) -> bool {
+    matches!(
+        split_transaction_statement(query_lower).0,
+        "begin"
+            | "begin transaction"
+            | "begin work"
+            | "start transaction"
+            | "commit"
+            | "commit transaction"
+            | "commit work"
+            | "end"
+            | "end transaction"
+            | "rollback"
+            | "rollback transaction"
+            | "rollback work"
+            | "abort"
+    )
+}
+
+/// The statements of a query of several statements, `None` for a single
+/// statement or SQL the parser rejects, which runs as is
+fn split_statements(query: &str) -> Option<Vec<String>> {
+    let statements = parse(query)
+        .ok()
+        .filter(|statements| statements.len() > 1)?;
+    Some(statements.iter().map(ToString::to_string).collect())
+}
+
+/// The catalog name of a database, normalized like DataFusion normalizes
+/// identifiers
+fn database_name(name: &ObjectName) -> String {
+    match name.0.last().and_then(|part| part.as_ident()) {
+        Some(ident) => IdentNormalizer::new(true).normalize(ident.clone()),
+        None => name.to_string(),
+    }
+}
+
+/// Plan of statements answered without executing a plan
+fn dummy_plan() -> LogicalPlan {
+    LogicalPlan::EmptyRelation(datafusion::logical_expr::EmptyRelation {
+        produce_one_row: false,
+        schema: Arc::new(datafusion::common::DFSchema::empty()),
+    })
+}
+
+/// Parameters reported to clients with `ParameterStatus` when a `SET`
+/// changes them, with their default values
+///
+/// libpq and drivers cache these values, e.g. to escape strings by
+/// `standard_conforming_strings`, so they must not drift silently.
+const REPORTED_PARAMETERS: &[(&str, &str)] = &[
+    ("application_name", ""),
+    ("client_encoding", "UTF8"),
+    ("DateStyle", "ISO, YMD"),
+    ("IntervalStyle", "postgres"),
+    ("standard_conforming_strings", "on"),
+    ("TimeZone", "UTC"),
+];
+
+/// The reported parameter `statement` sets, with its new value
+fn reported_parameter(statement: &SqlStatement) -> Option<(&'static str, String)> {
+    let (name, values) = match statement {
+        SqlStatement::SetTimeZone { value, .. } => ("timezone".to_string(), vec![value]),
+        SqlStatement::SetVariable {
+            variables: OneOrManyWithParens::One(name),
+            value,
+            ..
+        } => (name.to_string(), value.iter().collect()),
+        _ => return None,
+    };
+    let (name, default) = REPORTED_PARAMETERS
+        .iter()
+        .find(|(parameter, _)| parameter.eq_ignore_ascii_case(&name))?;
+    let value = set_value_text(values);
+    let value = match *name {
+        _ if value.eq_ignore_ascii_case("default") => default.to_string(),
+        "client_encoding" => value.to_uppercase(),
+        _ => value,
+    };
+    Some((name, value))
+}
+
+/// The text of the values of a `SET`, like `ISO, MDY`
+fn set_value_text<'a>(values: impl IntoIterator<Item = &'a Expr>) -> String {
+    values
+        .into_iter()
+        .map(|value| match value {
+            Expr::Value(value) => match &value.value {
+                Value::SingleQuotedString(text) | Value::DoubleQuotedString(text) => text.clone(),
+                value => value.to_string(),
+            },
+            Expr::Identifier(ident) => ident.value.clone(),
+            value => value.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Report the new value of the parameter a `SET` changed, as postgres does
+async fn send_parameter_status<C>(client: &mut C, statement: &SqlStatement) -> PgWireResult<()>
+where
+    C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send,
+    PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+{
+    let Some((name, mut value)) = reported_parameter(statement) else {
+        return Ok(());
+    };
+    // the settings handled by the session are reported by their canonical
+    // values
+    if name == METADATA_TIME_ZONE {
+        value = time_zone(client);
+    } else if name == METADATA_DATESTYLE {
+        value = datestyle(client).to_string();
+    } else if name == METADATA_STANDARD_CONFORMING_STRINGS {
+        value = on_off(standard_conforming_strings(client)).to_string();
+    } else if name == METADATA_INTERVAL_STYLE {
+        value = interval_style(client).name().to_string();
+    }
+    // pg_stat_activity reads the application name of the session metadata
+    if name == "application_name" {
+        client
+            .metadata_mut()
+            .insert(name.to_string(), value.clone());
+    }
+    client
+        .send(PgWireBackendMessage::ParameterStatus(ParameterStatus::new(
+            name.to_string(),
+            value,
+        )))
+        .await?;
+    Ok(())
+}
+
+/// Warn the client about clauses the rewrite rules dropped
+async fn send_ignored_clause_notices<C>(client: &mut C, notices: &[String]) -> PgWireResult<()>
+where
+    C: Sink<PgWireBackendMessage> + Unpin + Send,
+    PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+{
+    for notice in notices {
+        client
+            .send(PgWireBackendMessage::NoticeResponse(NoticeResponse::from(
+                ErrorInfo::new("WARNING".to_string(), "01000".to_string(), notice.clone()),
+            )))
+            .await?;
+    }
+    Ok(())
+}
+
+pub struct Parser {
+    session_contexts: Arc<SessionContexts>,
+    sql_rewrite_rules: Vec<Arc<dyn SqlStatementRewriteRule>>,
+    authorizer: Arc<dyn Authorizer>,
+    plan_cache: Option<Arc<PlanCache>>,
+    telemetry: Telemetry,
+    query_hooks: QueryHooks,
+}
+
+#[async_trait]
+impl QueryParser for Parser {
+    type Statement = (String, LogicalPlan, Vec<String>);
+
+    async fn parse_sql<C>(
+        &self,
+        client: &C,
+        sql: &str,
+        _types: &[Type],
+    ) -> PgWireResult<Self::Statement>
+    where
+        C: ClientInfo + Unpin + Send + Sync,
+    {
+        let mut sql = self
+            .query_hooks
+            .before_parse(&HookContext::new(client), sql)
+            .await?;
+        if !standard_conforming_strings(client) {
+            sql = escape_backslash_literals(&sql).into_owned();
+        }
+        let sql = sql.as_str();
+        log::debug!("Received parse extended query: {sql}"); // Log for debugging
+
+        // Check for transaction commands that shouldn't be parsed by DataFusion
+        let sql_lower = sql.to_lowercase();
+        if is_transaction_statement(&sql_lower) {
+            // Return a dummy plan for transaction commands - they'll be handled by transaction handler
+            return Ok((sql.to_string(), dummy_plan(), Vec::new()));
+        }
+        // and so are the statements sqlparser doesn't parse
+        if StorageStatement::matches(&sql_lower) {
+            StorageStatement::parse(sql)?;
+            return Ok((sql.to_string(), dummy_plan(), Vec::new()));
+        }
+        if SettingStatement::matches(&sql_lower) {
+            SettingStatement::parse(sql)?;
+            return Ok((sql.to_string(), dummy_plan(), Vec::new()));
+        }
+        if is_create_external_table(&sql_lower) {
+            parse_create_external_table(sql)?;
+            return Ok((sql.to_string(), dummy_plan(), Vec::new()));
+        }
+        if is_alter_table_refresh_schema(&sql_lower) {
+            parse_alter_table_refresh_schema(sql)?;
+            return Ok((sql.to_string(), dummy_plan(), Vec::new()));
+        }
+        if let Some(partitioned) = partitioned_copy(sql) {
+            check_statement_rules(self.authorizer.as_ref(), client, &partitioned?.0).await?;
+            return Ok((sql.to_string(), dummy_plan(), Vec::new()));
+        }
+        if let Some(copy) = CopyStatement::parse(sql) {
+            check_statement_rules(self.authorizer.as_ref(), client, &copy?.statement()).await?;
+            return Ok((sql.to_string(), dummy_plan(), Vec::new()));
+        }
+        if is_maintenance_command(&sql_lower) {
+            parse_maintenance_command(sql)?;
+            return Ok((sql.to_string(), dummy_plan(), Vec::new()));
+        }
+        #[cfg(feature = "postgres-fdw")]
+        if ForeignStatement::matches(&sql_lower) {
+            ForeignStatement::parse(sql)?;
+            return Ok((sql.to_string(), dummy_plan(), Vec::new()));
+        }
+
+        let parse_span = self.telemetry.phase(client, Phase::Parse);
+        let statements = parse(sql).map_err(|e| syntax_error(e, sql))?;
+        // an empty query is stored as empty text, and answered as such
+        let Some(statement) = statements.into_iter().next() else {
+            return Ok((String::new(), dummy_plan(), Vec::new()));
+        };
+
+        // Attempt to rewrite
+        let (statement, notices) = rewrite_with_notices(statement, &self.sql_rewrite_rules);
+        let mut statement = TemporalLiteralRewrite::new(date_order(client)).rewrite(statement);
+
+        check_statement_rules(self.authorizer.as_ref(), client, &statement).await?;
+        if let Some(unsupported) = unsupported_on_conflict(&statement) {
+            return Err(unsupported.into_error());
+        }
+
+        let query = statement.to_string();
+        // notification, DISCARD and database commands are answered by
+        // `DfSessionService` too
+        if matches!(
+            statement,
+            SqlStatement::LISTEN { .. }
+                | SqlStatement::UNLISTEN { .. }
+                | SqlStatement::NOTIFY { .. }
+                | SqlStatement::Discard { .. }
+                | SqlStatement::SetTransaction { .. }
+                | SqlStatement::CreateDatabase { .. }
+                | SqlStatement::Drop {
+                    object_type: ObjectType::Database,
+                    ..
+                }
+        ) || RoleStatement::from_statement(&statement).is_some()
+            || SettingStatement::from_statement(&statement).is_some()
+        {
+            return Ok((query, dummy_plan(), notices));
+        }
+        // EXPLAIN is answered by `DfSessionService`, which needs the plan of
+        // the explained statement
+        if let Some(explain) = ExplainOptions::from_statement(&statement) {
+            statement = explain?.1;
+        }
+        drop(parse_span);
+
+        let copy_to = copy_to_statement(&statement).transpose()?;
+        let returning = split_returning(&statement);
+        let state = session_state(&self.session_contexts.for_client(client), client);
+        let logical_plan = self
+            .telemetry
+            .phase(client, Phase::Plan)
+            .run(async {
+                if let Some((insert, returning)) = returning {
+                    let insert = statement_to_plan(&state, insert).await?;
+                    return returning_plan(&state, insert, &returning);
+                }
+                match (&self.plan_cache, copy_to) {
+                    (_, Some(copy_to)) => state.statement_to_plan(Statement::CopyTo(copy_to)).await,
+                    (Some(plan_cache), None) if matches!(statement, SqlStatement::Query(_)) => {
+                        plan_cache.get_or_plan(&state, client, statement).await
+                    }
+                    _ => statement_to_plan(&state, statement).await,
+                }
+            })
+            .await
+            .map_err(|e| PgWireError::ApiError(Box::new(e)))?;
+        Ok((query, logical_plan, notices))
+    }
+}
+
+/// Check whether the session user may run the statement
+async fn check_statement_rules<C>(
+    authorizer: &dyn Authorizer,
+    client: &C,
+    statement: &SqlStatement,
+) -> PgWireResult<()>
+where
+    C: ClientInfo,
+{
+    authorizer
+        .authorize_statement(authorized_user(client), statement)
+        .await
+}
+
+/// Check a statement sqlparser doesn't parse against the statement rules, in
+/// its SQL text as sent
+async fn check_command_rules<C>(
+    authorizer: &dyn Authorizer,
+    client: &C,
+    kind: StatementKind,
+    sql: &str,
+) -> PgWireResult<()>
+where
+    C: ClientInfo,
+{
+    let sql = sql.trim().trim_end_matches(';').trim_end();
+    authorizer
+        .authorize_command(authorized_user(client), kind, sql)
+        .await
+}
+
+/// The count of the rows written by INSERT or COPY, in the `count` column of
+/// their result
+fn rows_affected(result: &[datafusion::arrow::record_batch::RecordBatch]) -> usize {
+    result
+        .first()
+        .and_then(|batch| batch.column_by_name("count"))
+        .and_then(|col| {
+            col.as_any()
+                .downcast_ref::<datafusion::arrow::array::UInt64Array>()
+        })
+        .map_or(0, |array| array.value(0) as usize)
+}
+
+/// The `DateStyle` of the session, which clients like JDBC also send in
+/// their startup parameters
+fn datestyle<C: ClientInfo>(client: &C) -> DateStyle {
+    client
+        .metadata()
+        .get(METADATA_DATESTYLE)
+        .and_then(|datestyle| DateStyle::parse(datestyle, DateStyle::default()).ok())
+        .unwrap_or_default()
+}
+
+/// The order of the fields of dates set by the `DateStyle` of the session
+fn date_order<C: ClientInfo>(client: &C) -> DateOrder {
+    datestyle(client).order()
+}
+
+/// The text of `statement_timeout` in `SHOW` and `pg_settings`
+fn statement_timeout_setting(timeout: Option<std::time::Duration>) -> String {
+    match timeout {
+        Some(duration) => format!("{}ms", duration.as_millis()),
+        None => "0".to_string(),
+    }
+}
+
+/// The text of a boolean setting
+fn on_off(on: bool) -> &'static str {
+    if on {
+        "on"
+    } else {
+        "off"
+    }
+}
+
+/// The time zone of the session, set by `SET TIME ZONE`
+fn time_zone<C: ClientInfo>(client: &C) -> String {
+    client
+        .metadata()
+        .get(METADATA_TIME_ZONE)
+        .cloned()
+        .unwrap_or_else(|| "UTC".to_string())
+}
+
+/// The state to plan the statements of `client` with, in the time zone of
+/// the session
+fn session_state<C: ClientInfo>(session_context: &SessionContext, client: &C) -> SessionState {
+    let mut state = session_context.state();
+    if let Some(time_zone) = client.metadata().get(METADATA_TIME_ZONE) {
+        state.config_mut().options_mut().execution.time_zone = time_zone.clone();
+    }
+    state
+}
+
+/// The session user, `anonymous` if the client sent none
+fn username<C: ClientInfo>(client: &C) -> &str {
+    client
+        .metadata()
+        .get(METADATA_USER)
+        .map(|s| s.as_str())
+        .unwrap_or("anonymous")
+}
+
+/// The user the statements of the session are authorized as, the session
+/// user unless it was registered for the session only, like the users of
+/// tokens with their own roles
+fn authorized_user<C: ClientInfo>(client: &C) -> &str {
+    let username = username(client);
+    match client.metadata().get(METADATA_AUTHORIZED_USER) {
+        // clients may send the key as startup parameter, but can't name the
+        // session users of other sessions
+        Some(user) if *user == session_user_name(username, &client.socket_addr()) => user,
+        _ => username,
+    }
+}
+
+/// The values bound to the parameters of `portal`, and `plan` with them
+fn bind_parameters<S: Clone>(
+    portal: &Portal<S>,
+    plan: &LogicalPlan,
+) -> PgWireResult<(ParamValues, LogicalPlan)> {
+    let param_types = plan
+        .get_parameter_types()
+        .map_err(|e| PgWireError::ApiError(Box::new(e)))?;
+    let param_values = df::deserialize_parameters(portal, &ordered_param_types(&param_types))?;
+    // the schemas are planned again, for the types of results that are
+    // known once their parameters are bound, like those of `SELECT $1`
+    let plan = plan
+        .clone()
+        .replace_params_with_values(&param_values)
+        .and_then(|plan| {
+            plan.transform_up_with_subqueries(|plan| plan.recompute_schema().map(Transformed::yes))
+                .data()
+        })
+        .map_err(|e| PgWireError::ApiError(Box::new(e)))?;
+    Ok((param_values, plan))
+}
+
+fn ordered_param_types(types: &HashMap<String, Option<DataType>>) -> Vec<Option<&DataType>> {
+    // Datafusion stores the parameters as a map.  In our case, the keys will be
+    // `$1`, `$2` etc.  The values will be the parameter types.
+    let mut types = types.iter().collect::<Vec<_>>();
+    types.sort_by(|a, b| a.0.cmp(b.0));
+    types.into_iter().map(|pt| pt.1.as_ref()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::{AuthManager, ScramCredentials};
+    use datafusion::arrow::array::Int32Array;
+    use datafusion::arrow::datatypes::{Field, Schema, SchemaRef};
+    use datafusion::arrow::record_batch::RecordBatch;
+    use datafusion::catalog::streaming::StreamingTable;
+    use datafusion::execution::{SendableRecordBatchStream, TaskContext};
+    use datafusion::physical_plan::stream::RecordBatchStreamAdapter;
+    use datafusion::physical_plan::streaming::PartitionStream;
+    use datafusion::prelude::SessionContext;
+    use futures::StreamExt;
+    use std::collections::HashMap;
+    use std::time::Duration;
+
+    struct MockClient {
+        metadata: HashMap<String, String>,
+        socket_addr: std::net::SocketAddr,
+        // messages sent to the client outside of responses
+        sent: Vec<PgWireBackendMessage>,
+        transaction_status: TransactionStatus,
+        state: PgWireConnectionState,
+        portal_store: pgwire::api::store::MemPortalStore<(String, LogicalPlan, Vec<String>)>,
+    }
+
+    impl MockClient {
+        fn new() -> Self {
+            Self {
+                metadata: HashMap::new(),
+                socket_addr: "127.0.0.1:5432".parse().unwrap(),
+                sent: Vec::new(),
+                transaction_status: TransactionStatus::Idle,
+                state: PgWireConnectionState::ReadyForQuery,
+                portal_store: Default::default(),
+            }
+        }
+    }
+
+    impl pgwire::api::ClientPortalStore for MockClient {
+        type PortalStore = pgwire::api::store::MemPortalStore<(String, LogicalPlan, Vec<String>)>;
+
+        fn portal_store(&self) -> &Self::PortalStore {
+            &self.portal_store
+        }
+    }
+
+    impl Sink<PgWireBackendMessage> for MockClient {
+        type Error = PgWireError;
+
+        fn poll_ready(
+            self: std::pin::Pin<&mut Self>,
+            _cx: &mut std::task::Context<'_>,
+        ) -> std::task::Poll<Result<(), Self::Error>> {
+            std::task::Poll::Ready(Ok(()))
+        }
+
+        fn start_send(
+            mut self: std::pin::Pin<&mut Self>,
+            item: PgWireBackendMessage,
+        ) -> Result<(), Self::Error> {
+            self.sent.push(item);
+            Ok(())
+        }
+
+        fn poll_flush(
+            self: std::pin::Pin<&mut Self>,
+            _cx: &mut std::task::Context<'_>,
+        ) -> std::task::Poll<Result<(), Self::Error>> {
+            std::task::Poll::Ready(Ok(()))
+        }
+
+        fn poll_close(
+            self: std::pin::Pin<&mut Self>,
+            _cx: &mut std::task::Context<'_>,
+        ) -> std::task::Poll<Result<(), Self::Error>> {
+            std::task::Poll::Ready(Ok(()))
+        }
+    }
+
+    impl ClientInfo for MockClient {
+        fn socket_addr(&self) -> std::net::SocketAddr {
+            self.socket_addr
+        }
+
+        fn is_secure(&self) -> bool {
+            false
+        }
+
+        fn protocol_version(&self) -> pgwire::messages::ProtocolVersion {
+            pgwire::messages::ProtocolVersion::PROTOCOL3_0
+        }
+
+        fn set_protocol_version(&mut self, _version: pgwire::messages::ProtocolVersion) {}
+
+        fn pid_and_secret_key(&self) -> (i32, pgwire::messages::startup::SecretKey) {
+            (0, pgwire::messages::startup::SecretKey::I32(0))
+        }
+
+        fn set_pid_and_secret_key(
+            &mut self,
+            _pid: i32,
+            _secret_key: pgwire::messages::startup::SecretKey,
+        ) {
+        }
+
+        fn state(&self) -> PgWireConnectionState {
+            self.state
+        }
+
+        fn set_state(&mut self, new_state: PgWireConnectionState) {
+            self.state = new_state;
+        }
+
+        fn transaction_status(&self) -> TransactionStatus {
+            self.transaction_status
+        }
+
+        fn set_transaction_status(&mut self, new_status: TransactionStatus) {
+            self.transaction_status = new_status;
+        }
+
+        fn metadata(&self) -> &HashMap<String, String> {
+            &self.metadata
+        }
+
+        fn metadata_mut(&mut self) -> &mut HashMap<String, String> {
+            &mut self.metadata
+        }
+
+        fn client_certificates<'a>(&self) -> Option<&[rustls_pki_types::CertificateDer<'a>]> {
+            None
+        }
+    }
+
+    #[tokio::test]
+    async fn test_statement_timeout_set_and_show() {
+        let session_context = Arc::new(SessionContext::new());
+        let auth_manager = Arc::new(AuthManager::new());
+        let service = DfSessionService::new(session_context, auth_manager);
+        let mut client = MockClient::new();
+
+        // Test setting timeout to 5000ms
+        let set_response = service
+            .try_respond_set_statements(&mut client, "set statement_timeout '5000ms'")
+            .await
+            .unwrap();
+        assert!(set_response.is_some());
+
+        // Verify the timeout was set in client metadata
+        let timeout = DfSessionService::get_statement_timeout(&client);
+        assert_eq!(timeout, Some(Duration::from_millis(5000)));
+
+        // Test SHOW statement_timeout
+        let show_response = service
+            .try_respond_show_statements(&client, "show statement_timeout")
+            .await
+            .unwrap();
+        assert!(show_response.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_statement_timeout_disable() {
+        let session_context = Arc::new(SessionContext::new());
+        let auth_manager = Arc::new(AuthManager::new());
+        let service = DfSessionService::new(session_context, auth_manager);
+        let mut client = MockClient::new();
+
+        // Set timeout first
+        service
+            .try_respond_set_statements(&mut client, "set statement_timeout '1000ms'")
+            .await
+            .unwrap();
+
+        // Disable timeout with 0
+        service
+            .try_respond_set_statements(&mut client, "set statement_timeout '0'")
+            .await
+            .unwrap();
+
+        let timeout = DfSessionService::get_statement_timeout(&client);
+        assert_eq!(timeout, None);
+    }
+
+    #[tokio::test]
+    async fn test_result_size_settings() {
+        let session_context = Arc::new(SessionContext::new());
+        let auth_manager = Arc::new(AuthManager::new());
+        let service = DfSessionService::new(session_context, auth_manager).with_encode_options(
+            EncodeOptions {
+                batch_size: None,
+                buffer_size: 4096,
+                max_field_size: 1024,
+                ..EncodeOptions::default()
+            },
+        );
+        let mut client = MockClient::new();
+
+        service
+            .try_respond_set_statements(&mut client, "set result_batch_size = 100")
+            .await
+            .unwrap();
+        service
+            .try_respond_set_statements(&mut client, "set result_buffer_size to 65536;")
+            .await
+            .unwrap();
+        service
+            .try_respond_set_statements(&mut client, "set max_field_size = 1048576")
+            .await
+            .unwrap();
+        assert_eq!(
+            service.encode_options(&client),
+            EncodeOptions {
+                batch_size: Some(100),
+                buffer_size: 65536,
+                max_field_size: 1048576,
+                ..EncodeOptions::default()
+            }
+        );
+
+        service
+            .try_respond_set_statements(&mut client, "set result_buffer_size = default")
+            .await
+            .unwrap();
+        assert_eq!(service.encode_options(&client).buffer_size, 4096);
+
+        let Err(err) = service
+            .try_respond_set_statements(&mut client, "set result_batch_size = 0")
+            .await
+        else {
+            panic!("invalid batch size accepted");
+        };
+        assert!(matches!(err, PgWireError::UserError(info) if info.code == "22023"));
+        assert_eq!(service.encode_options(&client).batch_size, Some(100));
+    }
+
+    #[tokio::test]
+    async fn test_plan_cache() {
+        use crate::plan_cache::{PlanCache, PlanCacheScope};
+
+        let session_context = Arc::new(SessionContext::new());
+        session_context
+            .sql("CREATE TABLE users (id INT)")
+            .await
+            .unwrap();
+        let plan_cache = Arc::new(PlanCache::new(16, PlanCacheScope::Session));
+        let service = DfSessionService::new(session_context, Arc::new(AuthManager::new()))
+            .with_plan_cache(plan_cache.clone());
+        let client = MockClient::new();
+
+        // normalized SQL shares the cached plan
+        let parser = service.query_parser();
+        for sql in [
+            "SELECT id FROM users WHERE id = $1",
+            "select id  from users where id = $1",
+        ] {
+            parser.parse_sql(&client, sql, &[]).await.unwrap();
+        }
+        assert_eq!(plan_cache.len(), 1);
+
+        // DDL clears the cache
+        let statement = crate::sql::parse("DROP TABLE users").unwrap().remove(0);
+        service.execute_statement(&client, statement).await.unwrap();
+        assert!(plan_cache.is_empty());
+        assert!(parser
+            .parse_sql(&client, "SELECT id FROM users", &[])
+            .await
+            .is_err());
+    }
+
+    #[derive(Debug)]
+    struct NeverEndingPartition(SchemaRef);
+
+    impl PartitionStream for NeverEndingPartition {
+        fn schema(&self) -> &SchemaRef {
+            &self.0
+        }
+
+        fn execute(&self, _ctx: Arc<TaskContext>) -> SendableRecordBatchStream {
+            let batch =
+                RecordBatch::try_new(self.0.clone(), vec![Arc::new(Int32Array::from(vec![1, 2]))]);
+            let batches =
+                futures::stream::once(async move { Ok(batch?) }).chain(futures::stream::pending());
+            Box::pin(RecordBatchStreamAdapter::new(self.0.clone(), batches))
+        }
+    }
+
+    /// Partition producing a batch after a delay
+    #[derive(Debug)]
+    struct SlowPartition(SchemaRef, Duration);
+
+    impl PartitionStream for SlowPartition {
+        fn schema(&self) -> &SchemaRef {
+            &self.0
+        }
+
+        fn execute(&self, _ctx: Arc<TaskContext>) -> SendableRecordBatchStream {
+            let batch =
+                RecordBatch::try_new(self.0.clone(), vec![Arc::new(Int32Array::from(vec![1]))]);
+            let delay = self.1;
+            let batches = futures::stream::once(async move {
+                tokio::time::sleep(delay).await;
+                Ok(batch?)
+            });
+            Box::pin(RecordBatchStreamAdapter::new(self.0.clone(), batches))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_progress_notices() {
+        let schema = Arc::new(Schema::new(vec![Field::new("n", DataType::Int32, false)]));
+        let table = StreamingTable::try_new(
+            schema.clone(),
+            vec![Arc::new(SlowPartition(schema, Duration::from_millis(300)))],
+        )
+        .unwrap();
+        let session_context = Arc::new(SessionContext::new());
+        session_context
+            .register_table("events", Arc::new(table))
+            .unwrap();
+        let service = DfSessionService::new(session_context, Arc::new(AuthManager::new()))
+            .with_progress_notices(Duration::from_millis(50));
+        let mut client = MockClient::new();
+        client
+            .metadata
+            .insert(METADATA_USER.to_string(), "postgres".to_string());
+
+        let rows = query_rows(&service, &mut client, "SELECT n FROM events").await;
+        assert_eq!(rows, vec!["1"]);
+        let notices = client
+            .sent
+            .iter()
+            .filter_map(|message| match message {
+                PgWireBackendMessage::NoticeResponse(notice) => Some(notice),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert!(!notices.is_empty());
+        assert!(format!("{:?}", notices[0]).contains("query running for"));
+
+        // fast queries send no notice
+        client.sent.clear();
+        query_rows(&service, &mut client, "SELECT 1").await;
+        assert!(client.sent.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_wire_debug() {
+        let service = DfSessionService::new(
+            Arc::new(SessionContext::new()),
+            Arc::new(AuthManager::new()),
+        )
+        .with_wire_debug(WireDebug::On);
+        let mut client = MockClient::new();
+        client
+            .metadata
+            .insert(METADATA_USER.to_string(), "postgres".to_string());
+
+        assert_eq!(
+            query_rows(&service, &mut client, "SHOW wire_debug").await,
+            vec!["on"]
+        );
+        SimpleQueryHandler::do_query(&service, &mut client, "SET wire_debug = hex")
+            .await
+            .unwrap();
+        assert_eq!(
+            query_rows(&service, &mut client, "SHOW wire_debug").await,
+            vec!["hex"]
+        );
+        assert!(
+            SimpleQueryHandler::do_query(&service, &mut client, "SET wire_debug = loud")
+                .await
+                .is_err()
+        );
+        SimpleQueryHandler::do_query(&service, &mut client, "SET wire_debug TO default")
+            .await
+            .unwrap();
+        assert_eq!(
+            query_rows(&service, &mut client, "SHOW wire_debug").await,
+            vec!["on"]
+        );
+
+        // logging passes all messages through to the client
+        client.sent.clear();
+        service
+            .on_query(&mut client, Query::new("SELECT 1".to_string()))
+            .await
+            .unwrap();
+        assert!(matches!(
+            client.sent.as_slice(),
+            [
+                PgWireBackendMessage::RowDescription(_),
+                PgWireBackendMessage::DataRow(_),
+                PgWireBackendMessage::CommandComplete(_),
+                PgWireBackendMessage::ReadyForQuery(_)
+            ]
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_ignored_clause_notices() {
+        let service = DfSessionService::new(
+            Arc::new(SessionContext::new()),
+            Arc::new(AuthManager::new()),
+        );
+        let mut client = MockClient::new();
+        client
+            .metadata
+            .insert(METADATA_USER.to_string(), "postgres".to_string());
+
+        let sql = "SELECT column1 COLLATE \"C\" FROM (VALUES ('a')) AS t FOR UPDATE";
+        let rows = query_rows(&service, &mut client, sql).await;
+        assert_eq!(rows, vec!["a"]);
+        let notices = client
+            .sent
+            .iter()
+            .filter_map(|message| match message {
+                PgWireBackendMessage::NoticeResponse(notice) => Some(format!("{notice:?}")),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(notices.len(), 2);
+        assert!(notices[0].contains("ignoring unsupported FOR UPDATE"));
+        assert!(notices[1].contains("ignoring unsupported COLLATE \\\"C\\\""));
+
+        // the extended protocol sends them on execute
+        let (query, _, notices) = service
+            .query_parser()
+            .parse_sql(&client, sql, &[])
+            .await
+            .unwrap();
+        assert_eq!(query, "SELECT column1 FROM (VALUES ('a')) AS t");
+        assert_eq!(
+            notices,
+            vec![
+                "ignoring unsupported FOR UPDATE",
+                "ignoring unsupported COLLATE \"C\""
+            ]
+        );
+
+        // collations after casts and the sort operators of ORDER BY
+        let sql = "SELECT column1::text COLLATE \"en_US\" AS c FROM (VALUES ('a'), ('b')) \
+                   ORDER BY c USING > NULLS LAST";
+        let (query, _, notices) = service
+            .query_parser()
+            .parse_sql(&client, sql, &[])
+            .await
+            .unwrap();
+        assert_eq!(
+            query,
+            "SELECT column1::TEXT AS c FROM (VALUES ('a'), ('b')) \
+             ORDER BY c DESC NULLS LAST"
+        );
+        assert_eq!(notices, vec!["ignoring unsupported COLLATE \"en_US\""]);
+        let rows = query_rows(&service, &mut client, sql).await;
+        assert_eq!(rows, vec!["b", "a"]);
+    }
+
+    #[tokio::test]
+    async fn test_empty_queries() {
+        let service = DfSessionService::new(
+            Arc::new(SessionContext::new()),
+            Arc::new(AuthManager::new()),
+        );
+        let mut client = MockClient::new();
+        client
+            .metadata
+            .insert(METADATA_USER.to_string(), "postgres".to_string());
+
+        for sql in ["", "-- keepalive", "/* ping */ ;"] {
+            let resp = SimpleQueryHandler::do_query(&service, &mut client, sql)
+                .await
+                .unwrap();
+            assert!(matches!(resp.as_slice(), [Response::EmptyQuery]), "{sql}");
+        }
+
+        // the extended protocol stores them as empty text
+        let statement = service
+            .query_parser()
+            .parse_sql(&client, "-- keepalive", &[])
+            .await
+            .unwrap();
+        assert_eq!(statement.0, "");
+        let statement = Arc::new(StoredStatement::new(String::new(), statement, vec![]));
+        let portal =
+            Portal::try_new(&Bind::new(None, None, vec![], vec![], vec![]), statement).unwrap();
+        let resp = service.run_portal(&mut client, &portal).await.unwrap();
+        assert!(matches!(resp, Response::EmptyQuery));
+    }
+
+    #[tokio::test]
+    async fn test_row_policies_of_statements() {
+        use crate::policy::{ColumnMask, ColumnMaskingRule, RoleRowFilter};
+
+        let session_context = Arc::new(SessionContext::new());
+        session_context
+            .sql(
+                "CREATE TABLE orders (id INT, tenant VARCHAR, note VARCHAR) \
+                 AS VALUES (1, 'a', 'x'), (2, 'b', 'y'), (3, 'a', 'z')",
+            )
+            .await
+            .unwrap();
+        let auth_manager = Arc::new(AuthManager::new());
+        auth_manager
+            .create_role(crate::auth::RoleConfig {
+                name: "tenant_a".to_string(),
+                is_superuser: false,
+                can_login: true,
+                can_create_db: false,
+                can_create_role: false,
+                can_create_user: false,
+                can_replication: false,
+            })
+            .await
+            .unwrap();
+        auth_manager
+            .grant_permission(
+                "tenant_a",
+                Permission::All,
+                ResourceType::All,
+                "postgres",
+                false,
+            )
+            .await
+            .unwrap();
+        auth_manager
+            .add_user(crate::auth::User {
+                username: "alice".to_string(),
+                password_hash: String::new(),
+                roles: vec!["tenant_a".to_string()],
+                is_superuser: false,
+                can_login: true,
+                connection_limit: None,
+            })
+            .await
+            .unwrap();
+        auth_manager
+            .add_row_filter_policy(
+                "orders",
+                Arc::new(
+                    RoleRowFilter::new()
+                        .with_role_predicate("tenant_a", col("tenant").eq(lit("a"))),
+                ),
+            )
+            .await;
+        auth_manager
+            .add_column_masking_rule(ColumnMaskingRule::new(
+                "tenant_a",
+                "orders",
+                "note",
+                ColumnMask::Redact("***".to_string()),
+            ))
+            .await;
+        let service = DfSessionService::new(session_context, auth_manager);
+        let mut alice = MockClient::new();
+        alice
+            .metadata
+            .insert(METADATA_USER.to_string(), "alice".to_string());
+        let mut admin = MockClient::new();
+        admin.socket_addr = "127.0.0.1:5433".parse().unwrap();
+        admin
+            .metadata
+            .insert(METADATA_USER.to_string(), "postgres".to_string());
+
+        // statements reading the table only see the rows and values of queries
+        for statement in [
+            "CREATE TABLE stolen AS SELECT * FROM orders",
+            "CREATE VIEW stolen_view AS SELECT * FROM orders",
+            "CREATE TABLE copied (id INT, tenant VARCHAR, note VARCHAR)",
+            "INSERT INTO copied SELECT * FROM orders",
+        ] {
+            let responses = SimpleQueryHandler::do_query(&service, &mut alice, statement)
+                .await
+                .unwrap();
+            for response in responses {
+                if let Response::Query(resp) = response {
+                    resp.data_rows().collect::<Vec<_>>().await;
+                }
+            }
+        }
+        for table in ["stolen", "stolen_view", "copied"] {
+            assert_eq!(
+                query_rows(
+                    &service,
+                    &mut admin,
+                    &format!("SELECT concat(id, note) FROM {table} ORDER BY id")
+                )
+                .await,
+                ["1***", "3***"],
+                "{table}"
+            );
+        }
+
+        // and the extended protocol filters scans that don't select the
+        // filtered column
+        let statement = service
+            .query_parser()
+            .parse_sql(
+                &alice,
+                "SELECT id FROM orders WHERE id > $1 ORDER BY id",
+                &[],
+            )
+            .await
+            .unwrap();
+        let statement = Arc::new(StoredStatement::new(String::new(), statement, vec![]));
+        let bind = Bind::new(
+            None,
+            None,
+            vec![],
+            vec![Some(bytes::Bytes::from_static(b"0"))],
+            vec![],
+        );
+        let portal = Portal::try_new(&bind, statement).unwrap();
+        let Response::Query(resp) = service.run_portal(&mut alice, &portal).await.unwrap() else {
+            panic!("expected rows");
+        };
+        let rows = resp
+            .data_rows()
+            .map(|row| first_column_text(&row.unwrap()))
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(rows, ["1", "3"]);
+    }
+
+    #[tokio::test]
+    async fn test_describe_portal() {
+        let service = DfSessionService::new(
+            Arc::new(SessionContext::new()),
+            Arc::new(AuthManager::new()),
+        );
+        let mut client = MockClient::new();
+        client
+            .metadata
+            .insert(METADATA_USER.to_string(), "postgres".to_string());
+
+        let statement = service
+            .query_parser()
+            .parse_sql(&client, "SELECT $1, 'b' AS t", &[])
+            .await
+            .unwrap();
+        let statement = Arc::new(StoredStatement::new(
+            String::new(),
+            statement,
+            vec![Type::INT8],
+        ));
+        // binary results for the first column, text for the second
+        let bind = Bind::new(
+            None,
+            None,
+            vec![],
+            vec![Some(bytes::Bytes::from_static(b"42"))],
+            vec![1, 0],
+        );
+        let portal = Portal::try_new(&bind, statement).unwrap();
+        let resp = service
+            .do_describe_portal(&mut client, &portal)
+            .await
+            .unwrap();
+        let fields = resp
+            .fields
+            .iter()
+            .map(|field| (field.name(), field.datatype().clone(), field.format()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            fields,
+            [
+                ("$1", Type::INT8, FieldFormat::Binary),
+                ("t", Type::TEXT, FieldFormat::Text),
+            ]
+        );
+
+        // statements without rows are described as such
+        let statement = service
+            .query_parser()
+            .parse_sql(&client, "BEGIN", &[])
+            .await
+            .unwrap();
+        let statement = Arc::new(StoredStatement::new(String::new(), statement, vec![]));
+        let portal =
+            Portal::try_new(&Bind::new(None, None, vec![], vec![], vec![]), statement).unwrap();
+        let resp = service
+            .do_describe_portal(&mut client, &portal)
+            .await
+            .unwrap();
+        assert!(resp.fields.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_parameter_status_on_set() {
+        let service = DfSessionService::new(
+            Arc::new(SessionContext::new()),
+            Arc::new(AuthManager::new()),
+        );
+        let mut client = MockClient::new();
+        client
+            .metadata
+            .insert(METADATA_USER.to_string(), "postgres".to_string());
+
+        for (sql, name, value) in [
+            (
+                "SET application_name = 'MyApp'",
+                "application_name",
+                "MyApp",
+            ),
+            ("SET client_encoding TO utf8", "client_encoding", "UTF8"),
+            ("SET DateStyle = ISO, MDY", "DateStyle", "ISO, MDY"),
+            ("SET TIME ZONE 'Europe/Paris'", "TimeZone", "Europe/Paris"),
+            (
+                "SET standard_conforming_strings TO DEFAULT",
+                "standard_conforming_strings",
+                "on",
+            ),
+        ] {
+            client.sent.clear();
+            SimpleQueryHandler::do_query(&service, &mut client, sql)
+                .await
+                .unwrap();
+            assert!(
+                matches!(
+                    client.sent.as_slice(),
+                    [PgWireBackendMessage::ParameterStatus(status)]
+                        if status.name == name && status.value == value
+                ),
+                "{sql}: {:?}",
+                client.sent
+            );
+        }
+        assert_eq!(client.metadata["application_name"], "MyApp");
+
+        // other settings are not reported
+        client.sent.clear();
+        SimpleQueryHandler::do_query(&service, &mut client, "SET statement_timeout = '5s'")
+            .await
+            .unwrap();
+        assert!(client.sent.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_standard_conforming_strings() {
+        let service = DfSessionService::new(
+            Arc::new(SessionContext::new()),
+            Arc::new(AuthManager::new()),
+        );
+        let mut client = MockClient::new();
+        let mut startup = pgwire::messages::startup::Startup::new();
+        startup
+            .parameters
+            .insert("user".to_string(), "postgres".to_string());
+        SimpleStartupHandler
+            .on_startup(&mut client, PgWireFrontendMessage::Startup(startup))
+            .await
+            .unwrap();
+        // libpq escapes strings by the value reported at startup
+        assert!(client.sent.iter().any(|message| matches!(
+            message,
+            PgWireBackendMessage::ParameterStatus(status)
+                if status.name == "standard_conforming_strings" && status.value == "on"
+        )));
+        assert_eq!(
+            query_rows(&service, &mut client, r"SELECT 'a\tb'").await,
+            [r"a\tb"]
+        );
+
+        client.sent.clear();
+        SimpleQueryHandler::do_query(
+            &service,
+            &mut client,
+            "SET standard_conforming_strings = false",
+        )
+        .await
+        .unwrap();
+        assert!(matches!(
+            client.sent.as_slice(),
+            [PgWireBackendMessage::ParameterStatus(status)]
+                if status.name == "standard_conforming_strings" && status.value == "off"
+        ));
+        assert_eq!(
+            query_rows(&service, &mut client, r"SELECT 'a\tb' || 'it\'s'").await,
+            ["a\tbit's"]
+        );
+        assert_eq!(
+            query_rows(&service, &mut client, "SHOW standard_conforming_strings").await,
+            ["off"]
+        );
+
+        let err = SimpleQueryHandler::do_query(
+            &service,
+            &mut client,
+            "SET standard_conforming_strings = maybe",
+        )
+        .await
+        .err()
+        .unwrap();
+        let PgWireError::UserError(info) = err else {
+            panic!("expected a user error");
+        };
+        assert_eq!(info.code, "22023");
+        assert_eq!(
+            info.message,
+            "parameter \"standard_conforming_strings\" requires a Boolean value"
+        );
+
+        SimpleQueryHandler::do_query(
+            &service,
+            &mut client,
+            "SET standard_conforming_strings TO DEFAULT",
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            query_rows(&service, &mut client, r"SELECT 'a\tb'").await,
+            [r"a\tb"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_extra_float_digits() {
+        let service = DfSessionService::new(
+            Arc::new(SessionContext::new()),
+            Arc::new(AuthManager::new()),
+        );
+        let mut client = MockClient::new();
+        client
+            .metadata
+            .insert(METADATA_USER.to_string(), "postgres".to_string());
+        async fn floats(service: &DfSessionService, client: &mut MockClient) -> Vec<String> {
+            let mut rows = query_rows(service, client, "SELECT 0.1::float8 + 0.2::float8").await;
+            rows.extend(query_rows(service, client, "SELECT 1::float4 / 3::float4").await);
+            rows
+        }
+        assert_eq!(
+            floats(&service, &mut client).await,
+            ["0.30000000000000004", "0.33333334"]
+        );
+
+        // the legacy output of postgres before 12
+        SimpleQueryHandler::do_query(&service, &mut client, "SET extra_float_digits = 0")
+            .await
+            .unwrap();
+        assert_eq!(floats(&service, &mut client).await, ["0.3", "0.333333"]);
+        SimpleQueryHandler::do_query(&service, &mut client, "SET extra_float_digits TO -3")
+            .await
+            .unwrap();
+        assert_eq!(floats(&service, &mut client).await, ["0.3", "0.333"]);
+        assert_eq!(
+            query_rows(&service, &mut client, "SHOW extra_float_digits").await,
+            ["-3"]
+        );
+
+        // as JDBC connects
+        SimpleQueryHandler::do_query(&service, &mut client, "SET extra_float_digits = 3")
+            .await
+            .unwrap();
+        assert_eq!(
+            floats(&service, &mut client).await,
+            ["0.30000000000000004", "0.33333334"]
+        );
+
+        for (value, message) in [
+            (
+                "4",
+                "4 is outside the valid range for parameter \"extra_float_digits\" (-15 .. 3)",
+            ),
+            (
+                "'many'",
+                "invalid value for parameter \"extra_float_digits\": \"many\"",
+            ),
+        ] {
+            let err = SimpleQueryHandler::do_query(
+                &service,
+                &mut client,
+                &format!("SET extra_float_digits = {value}"),
+            )
+            .await
+            .err()
+            .unwrap();
+            let PgWireError::UserError(info) = err else {
+                panic!("expected a user error");
+            };
+            assert_eq!(info.code, "22023");
+            assert_eq!(info.message, message);
+        }
+        assert_eq!(
+            query_rows(&service, &mut client, "SHOW extra_float_digits").await,
+            ["3"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_interval_style() {
+        let service = DfSessionService::new(
+            Arc::new(SessionContext::new()),
+            Arc::new(AuthManager::new()),
+        );
+        let mut client = MockClient::new();
+        let mut startup = pgwire::messages::startup::Startup::new();
+        startup
+            .parameters
+            .insert("user".to_string(), "postgres".to_string());
+        SimpleStartupHandler
+            .on_startup(&mut client, PgWireFrontendMessage::Startup(startup))
+            .await
+            .unwrap();
+        assert!(client.sent.iter().any(|message| matches!(
+            message,
+            PgWireBackendMessage::ParameterStatus(status)
+                if status.name == "IntervalStyle" && status.value == "postgres"
+        )));
+        const INTERVAL: &str =
+            "SELECT INTERVAL '1 year 2 months 3 days 4 hours 5 minutes 6.5 seconds'";
+        assert_eq!(
+            query_rows(&service, &mut client, INTERVAL).await,
+            ["1 year 2 mons 3 days 04:05:06.5"]
+        );
+
+        for (style, text) in [
+            ("iso_8601", "P1Y2M3DT4H5M6.5S"),
+            ("SQL_STANDARD", "+1-2 +3 +4:05:06.5"),
+            (
+                "'postgres_verbose'",
+                "@ 1 year 2 mons 3 days 4 hours 5 mins 6.5 secs",
+            ),
+        ] {
+            client.sent.clear();
+            SimpleQueryHandler::do_query(
+                &service,
+                &mut client,
+                &format!("SET IntervalStyle = {style}"),
+            )
+            .await
+            .unwrap();
+            let name = style.trim_matches('\'').to_lowercase();
+            assert!(matches!(
+                client.sent.as_slice(),
+                [PgWireBackendMessage::ParameterStatus(status)]
+                    if status.name == "IntervalStyle" && status.value == name
+            ));
+            assert_eq!(query_rows(&service, &mut client, INTERVAL).await, [text]);
+            assert_eq!(
+                query_rows(&service, &mut client, "SHOW IntervalStyle").await,
+                [name]
+            );
+        }
+
+        let err = SimpleQueryHandler::do_query(&service, &mut client, "SET IntervalStyle = iso")
+            .await
+            .err()
+            .unwrap();
+        let PgWireError::UserError(info) = err else {
+            panic!("expected a user error");
+        };
+        assert_eq!(info.code, "22023");
+        assert_eq!(
+            info.message,
+            "invalid value for parameter \"IntervalStyle\": \"iso\""
+        );
+
+        SimpleQueryHandler::do_query(&service, &mut client, "SET IntervalStyle TO DEFAULT")
+            .await
+            .unwrap();
+        assert_eq!(
+            query_rows(&service, &mut client, INTERVAL).await,
+            ["1 year 2 mons 3 days 04:05:06.5"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_set_time_zone() {
+        let session_context = Arc::new(SessionContext::new());
+        crate::pg_catalog::setup_pg_catalog(&session_context, "datafusion").unwrap();
+        let service = DfSessionService::new(session_context, Arc::new(AuthManager::new()));
+        let mut client = MockClient::new();
+        client
+            .metadata
+            .insert(METADATA_USER.to_string(), "postgres".to_string());
+        const NOON: &str = "SELECT CAST('2024-07-01 12:00:00+00:00' AS TIMESTAMPTZ)";
+
+        assert_eq!(
+            query_rows(&service, &mut client, NOON).await,
+            ["2024-07-01 12:00:00.000000+00"]
+        );
+
+        // names are matched case insensitively, and reported as listed
+        client.sent.clear();
+        SimpleQueryHandler::do_query(&service, &mut client, "SET TIME ZONE 'europe/berlin'")
+            .await
+            .unwrap();
+        assert!(matches!(
+            client.sent.as_slice(),
+            [PgWireBackendMessage::ParameterStatus(status)]
+                if status.name == "TimeZone" && status.value == "Europe/Berlin"
+        ));
+        assert_eq!(
+            query_rows(&service, &mut client, "SHOW TimeZone").await,
+            ["Europe/Berlin"]
+        );
+        // timestamptz are shown and local times read in the zone
+        assert_eq!(
+            query_rows(&service, &mut client, NOON).await,
+            ["2024-07-01 14:00:00.000000+02"]
+        );
+        assert_eq!(
+            query_rows(
+                &service,
+                &mut client,
+                "SELECT arrow_cast(CAST('2024-07-01 12:00:00' AS TIMESTAMPTZ), 'Utf8')"
+            )
+            .await,
+            ["2024-07-01T12:00:00+02:00"]
+        );
+
+        SimpleQueryHandler::do_query(
+            &service,
+            &mut client,
+            "SET timezone TO INTERVAL '-03:00' HOUR TO MINUTE",
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            query_rows(&service, &mut client, NOON).await,
+            ["2024-07-01 09:00:00.000000-03"]
+        );
+
+        let err =
+            SimpleQueryHandler::do_query(&service, &mut client, "SET TIME ZONE 'Mars/Olympus'")
+                .await
+                .err()
+                .unwrap();
+        let PgWireError::UserError(info) = err else {
+            panic!("expected a user error");
+        };
+        assert_eq!(info.code, "22023");
+        assert_eq!(
+            info.message,
+            "invalid value for parameter \"TimeZone\": \"Mars/Olympus\""
+        );
+        assert_eq!(
+            query_rows(&service, &mut client, "SHOW timezone").await,
+            ["-03:00"]
+        );
+
+        SimpleQueryHandler::do_query(&service, &mut client, "SET TIME ZONE LOCAL")
+            .await
+            .unwrap();
+        assert_eq!(
+            query_rows(&service, &mut client, "SHOW time zone").await,
+            ["UTC"]
+        );
+
+        assert_eq!(
+            query_rows(
+                &service,
+                &mut client,
+                "SELECT abbrev FROM pg_catalog.pg_timezone_names WHERE name = 'Asia/Tokyo'"
+            )
+            .await,
+            ["JST"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rows_streamed_before_query_completes() {
+        let schema = Arc::new(Schema::new(vec![Field::new("n", DataType::Int32, false)]));
+        let table =
+            StreamingTable::try_new(schema.clone(), vec![Arc::new(NeverEndingPartition(schema))])
+                .unwrap();
+        let ctx = SessionContext::new();
+        ctx.register_table("events", Arc::new(table)).unwrap();
+
+        let df = ctx.sql("SELECT n FROM events").await.unwrap();
+        let resp = df::encode_dataframe(df, &Format::UnifiedText)
+            .await
+            .unwrap();
+        let mut rows = resp.data_rows();
+
+        // rows of the first batch arrive while the query is still running
+        for _ in 0..2 {
+            let row = tokio::time::timeout(Duration::from_secs(5), rows.next())
+                .await
+                .expect("row not streamed");
+            assert!(row.unwrap().is_ok());
+        }
+        assert!(tokio::time::timeout(Duration::from_millis(50), rows.next())
+            .await
+            .is_err());
+    }
+
+    fn first_column_text(row: &pgwire::messages::data::DataRow) -> String {
+        let len = i32::from_be_bytes(row.data[..4].try_into().unwrap()) as usize;
+        String::from_utf8(row.data[4..4 + len].to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_partitions_encoded_concurrently() {
+        let schema = Arc::new(Schema::new(vec![Field::new("n", DataType::Int32, false)]));
+        let partitions = (0..4)
+            .map(|p| {
+                let values = (0..100).map(|i| p * 100 + i).collect::<Vec<i32>>();
+                vec![
+                    RecordBatch::try_new(schema.clone(), vec![Arc::new(Int32Array::from(values))])
+                        .unwrap(),
+                ]
+            })
+            .collect();
+        let table = datafusion::datasource::MemTable::try_new(schema, partitions).unwrap();
+        let ctx = SessionContext::new_with_config(SessionConfig::new().with_target_partitions(4));
+        ctx.register_table("numbers", Arc::new(table)).unwrap();
+
+        let df = ctx.sql("SELECT n FROM numbers").await.unwrap();
+        let resp = df::encode_dataframe(df, &Format::UnifiedText)
+            .await
+            .unwrap();
+        let mut values = resp
+            .data_rows()
+            .map(|row| first_column_text(&row.unwrap()).parse::<i32>().unwrap())
+            .collect::<Vec<_>>()
+            .await;
+        values.sort();
+        assert_eq!(values, (0..400).collect::<Vec<_>>());
+
+        // ORDER BY keeps its order
+        let df = ctx
+            .sql("SELECT n FROM numbers ORDER BY n DESC")
+            .await
+            .unwrap();
+        let resp = df::encode_dataframe(df, &Format::UnifiedText)
+            .await
+            .unwrap();
+        let values = resp
+            .data_rows()
+            .map(|row| first_column_text(&row.unwrap()).parse::<i32>().unwrap())
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(values, (0..400).rev().collect::<Vec<_>>());
+    }
+
+    /// Run a query through the simple query protocol and collect the text of
+    /// the first column
+    #[tokio::test]
+    async fn test_custom_authorizer() {
+        /// Allows queries only
+        struct QueriesOnly;
+
+        #[async_trait]
+        impl Authorizer for QueriesOnly {
+            async fn authorize_statement(
+                &self,
+                username: &str,
+                statement: &SqlStatement,
+            ) -> PgWireResult<()> {
+                match statement {
+                    SqlStatement::Query(_) => Ok(()),
+                    _ => Err(PgWireError::UserError(Box::new(ErrorInfo::new(
+                        "ERROR".to_string(),
+                        "42501".to_string(),
+                        format!("{username} may only query"),
+                    )))),
+                }
+            }
+
+            async fn authorize_table(
+                &self,
+                _username: &str,
+                _permission: Permission,
+                _resource: ResourceType,
+            ) -> PgWireResult<()> {
+                Ok(())
+            }
+        }
+
+        let service = DfSessionService::new(
+            Arc::new(SessionContext::new()),
+            Arc::new(AuthManager::new()),
+        )
+        .with_authorizer(Arc::new(QueriesOnly));
+        let mut client = MockClient::new();
+        client
+            .metadata
+            .insert(METADATA_USER.to_string(), "postgres".to_string());
+
+        assert_eq!(
+            query_rows(&service, &mut client, "SELECT 1").await,
+            vec!["1"]
+        );
+        let sql = "CREATE TABLE t (a INT)";
+        let Err(PgWireError::UserError(error)) =
+            SimpleQueryHandler::do_query(&service, &mut client, sql).await
+        else {
+            panic!("expected the statement to be denied");
+        };
+        assert_eq!(error.code, "42501");
+        assert_eq!(error.message, "postgres may only query");
+        assert!(service
+            .query_parser()
+            .parse_sql(&client, sql, &[])
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_query_hooks() {
+        use crate::hooks::{ExecutionMeta, HookContext, QueryHook};
+
+        #[derive(Default)]
+        struct Recorder {
+            executed: std::sync::Mutex<Vec<ExecutionMeta>>,
+        }
+
+        #[async_trait]
+        impl QueryHook for Recorder {
+            async fn before_parse(
+                &self,
+                _context: &HookContext,
+                sql: &str,
+            ) -> PgWireResult<Option<String>> {
+                Ok(Some(sql.replace("'old'", "'new'")))
+            }
+
+            async fn before_execute(
+                &self,
+                context: &HookContext,
+                plan: &LogicalPlan,
+            ) -> PgWireResult<Option<LogicalPlan>> {
+                if let LogicalPlan::Ddl(_) = plan {
+                    return Err(PgWireError::UserError(Box::new(ErrorInfo::new(
+                        "ERROR".to_string(),
+                        "42501".to_string(),
+                        format!("no DDL for {}", context.user.as_deref().unwrap_or("")),
+                    ))));
+                }
+                Ok(None)
+            }
+
+            async fn after_execute(&self, _context: &HookContext, meta: &ExecutionMeta) {
+                self.executed.lock().unwrap().push(meta.clone());
+            }
+        }
+
+        let recorder = Arc::new(Recorder::default());
+        let service = DfSessionService::new(
+            Arc::new(SessionContext::new()),
+            Arc::new(AuthManager::new()),
+        )
+        .with_query_hook(recorder.clone());
+        let mut client = MockClient::new();
+        client
+            .metadata
+            .insert(METADATA_USER.to_string(), "postgres".to_string());
+
+        let rows = query_rows(&service, &mut client, "SELECT 'old' UNION ALL SELECT 'b'").await;
+        assert_eq!(rows, vec!["new", "b"]);
+        let Err(PgWireError::UserError(error)) =
+            SimpleQueryHandler::do_query(&service, &mut client, "CREATE TABLE t (a INT)").await
+        else {
+            panic!("expected the statement to be blocked");
+        };
+        assert_eq!(error.message, "no DDL for postgres");
+        assert!(!service.session_context(&client).table_exist("t").unwrap());
+
+        let executed = recorder.executed.lock().unwrap().clone();
+        assert_eq!(executed.len(), 2);
+        assert_eq!(executed[0].query, "SELECT 'new' UNION ALL SELECT 'b'");
+        assert_eq!(executed[0].rows, 2);
+        assert_eq!(executed[0].error, None);
+        assert_eq!(executed[1].rows, 0);
+        assert_eq!(executed[1].error.as_deref(), Some("no DDL for postgres"));
+
+        // the extended protocol rewrites on parse
+        let (query, _, _) = service
+            .query_parser()
+            .parse_sql(&client, "SELECT 'old'", &[])
+            .await
+            .unwrap();
+        assert_eq!(query, "SELECT 'new'");
+    }
+
+    #[tokio::test]
+    async fn test_session_context_factory() {
+        use crate::session::ConnectionInfo;
+
+        let factory = |connection: &ConnectionInfo| {
+            let session_context = SessionContext::new();
+            let user = connection.user.clone().unwrap_or_default();
+            let batch = RecordBatch::try_new(
+                Arc::new(Schema::new(vec![Field::new(
+                    "owner",
+                    DataType::Utf8,
+                    false,
+                )])),
+                vec![Arc::new(datafusion::arrow::array::StringArray::from(vec![
+                    user,
+                ]))],
+            )
+            .unwrap();
+            session_context.register_batch("mine", batch).unwrap();
+            Arc::new(session_context)
+        };
+        let auth_manager = Arc::new(AuthManager::new());
+        for user in ["alice", "bob"] {
+            auth_manager
+                .add_user(crate::auth::User {
+                    username: user.to_string(),
+                    password_hash: String::new(),
+                    roles: Vec::new(),
+                    is_superuser: true,
+                    can_login: true,
+                    connection_limit: None,
+                })
+                .await
+                .unwrap();
+        }
+        let service = DfSessionService::new(Arc::new(SessionContext::new()), auth_manager)
+            .with_session_context_factory(Arc::new(factory));
+
+        let mut alice = MockClient::new();
+        alice
+            .metadata
+            .insert(METADATA_USER.to_string(), "alice".to_string());
+        let mut bob = MockClient::new();
+        bob.socket_addr = "127.0.0.1:5433".parse().unwrap();
+        bob.metadata
+            .insert(METADATA_USER.to_string(), "bob".to_string());
+
+        let sql = "SELECT owner FROM mine";
+        assert_eq!(query_rows(&service, &mut alice, sql).await, vec!["alice"]);
+        assert_eq!(query_rows(&service, &mut bob, sql).await, vec!["bob"]);
+
+        // a connection keeps its context, DDL doesn't leak to others
+        query_rows(&service, &mut alice, "CREATE TABLE t AS SELECT 1").await;
+        assert!(service.session_context(&alice).table_exist("t").unwrap());
+        assert!(!service.session_context(&bob).table_exist("t").unwrap());
+
+        // closed connections drop their context
+        service.end_session(&alice.socket_addr);
+        assert!(!service.session_context(&alice).table_exist("t").unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_tenant_resolver() {
+        use crate::limits::QueryLimits;
+        use crate::session::ConnectionInfo;
+        use crate::tenant::Tenant;
+        use datafusion::catalog::{CatalogProvider, MemoryCatalogProvider, MemorySchemaProvider};
+
+        let session_context = Arc::new(SessionContext::new());
+        for tenant in ["acme", "globex"] {
+            let catalog = MemoryCatalogProvider::new();
+            catalog
+                .register_schema("app", Arc::new(MemorySchemaProvider::new()))
+                .unwrap();
+            session_context.register_catalog(tenant, Arc::new(catalog));
+            session_context
+                .sql(&format!(
+                    "CREATE TABLE {tenant}.app.t AS VALUES ('{tenant}')"
+                ))
+                .await
+                .unwrap();
+            crate::pg_catalog::setup_pg_catalog(&session_context, tenant).unwrap();
+        }
+
+        let resolver = |connection: &ConnectionInfo| {
+            let catalog = match connection.user.as_deref() {
+                Some("alice") => "acme",
+                _ => "globex",
+            };
+            Tenant::new()
+                .with_catalogs([catalog])
+                .with_default_catalog(catalog)
+                .with_search_path("app")
+                .with_limits(QueryLimits::new().with_max_rows(1))
+        };
+        let auth_manager = Arc::new(AuthManager::new());
+        for user in ["alice", "bob"] {
+            auth_manager
+                .add_user(crate::auth::User {
+                    username: user.to_string(),
+                    password_hash: String::new(),
+                    roles: Vec::new(),
+                    is_superuser: true,
+                    can_login: true,
+                    connection_limit: None,
+                })
+                .await
+                .unwrap();
+        }
+        let service = DfSessionService::new(session_context, auth_manager)
+            .with_tenant_resolver(Arc::new(resolver));
+
+        let mut alice = MockClient::new();
+        alice
+            .metadata
+            .insert(METADATA_USER.to_string(), "alice".to_string());
+        let mut bob = MockClient::new();
+        bob.socket_addr = "127.0.0.1:5433".parse().unwrap();
+        bob.metadata
+            .insert(METADATA_USER.to_string(), "bob".to_string());
+
+        // unqualified names resolve in the catalog and schema of the tenant
+        let sql = "SELECT * FROM t";
+        assert_eq!(query_rows(&service, &mut alice, sql).await, vec!["acme"]);
+        assert_eq!(query_rows(&service, &mut bob, sql).await, vec!["globex"]);
+        assert_eq!(
+            query_rows(&service, &mut alice, "SHOW search_path").await,
+            vec!["app"]
+        );
+
+        // other tenants are neither listed nor resolved
+        assert_eq!(
+            query_rows(
+                &service,
+                &mut alice,
+                "SELECT datname FROM pg_catalog.pg_database"
+            )
+            .await,
+            vec!["acme"]
+        );
+        assert!(
+            SimpleQueryHandler::do_query(&service, &mut alice, "SELECT * FROM globex.app.t")
+                .await
+                .is_err()
+        );
+
+        assert_eq!(service.query_limits(&alice).await.max_rows, Some(1));
+    }
+
+    #[tokio::test]
+    async fn test_listen_notify() {
+        let session_context = Arc::new(SessionContext::new());
+        let service = DfSessionService::new(session_context, Arc::new(AuthManager::new()));
+        let mut listener = MockClient::new();
+        listener
+            .metadata
+            .insert(METADATA_USER.to_string(), "postgres".to_string());
+        let mut other = MockClient::new();
+        other.socket_addr = "127.0.0.1:5433".parse().unwrap();
+        other
+            .metadata
+            .insert(METADATA_USER.to_string(), "postgres".to_string());
+
+        fn notifications(client: &mut MockClient) -> Vec<(String, String)> {
+            client
+                .sent
+                .drain(..)
+                .filter_map(|message| match message {
+                    PgWireBackendMessage::NotificationResponse(n) => Some((n.channel, n.payload)),
+                    _ => None,
+                })
+                .collect()
+        }
+
+        SimpleQueryHandler::do_query(&service, &mut listener, "LISTEN schema_changed")
+            .await
+            .unwrap();
+        SimpleQueryHandler::do_query(&service, &mut listener, "LISTEN jobs")
+            .await
+            .unwrap();
+        SimpleQueryHandler::do_query(&service, &mut other, "CREATE TABLE t (a INT)")
+            .await
+            .unwrap();
+        SimpleQueryHandler::do_query(&service, &mut other, "NOTIFY jobs, 'done'")
+            .await
+            .unwrap();
+        assert!(notifications(&mut other).is_empty());
+
+        // delivered along the response to the next statement
+        assert!(notifications(&mut listener).is_empty());
+        query_rows(&service, &mut listener, "SELECT 1").await;
+        assert_eq!(
+            notifications(&mut listener),
+            vec![
+                ("schema_changed".to_string(), "CREATE TABLE t".to_string()),
+                ("jobs".to_string(), "done".to_string()),
+            ]
+        );
+
+        SimpleQueryHandler::do_query(&service, &mut listener, "UNLISTEN *")
+            .await
+            .unwrap();
+        service.notify("jobs", "again");
+        query_rows(&service, &mut listener, "SELECT 1").await;
+        assert!(notifications(&mut listener).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_connection_listener() {
+        use crate::events::{ConnectionEvent, ConnectionListener};
+        use pgwire::messages::startup::PasswordMessageFamily;
+
+        #[derive(Default)]
+        struct Recorder(std::sync::Mutex<Vec<(&'static str, ConnectionEvent)>>);
+
+        impl ConnectionListener for Recorder {
+            fn on_connect(&self, event: &ConnectionEvent) {
+                self.0.lock().unwrap().push(("connect", event.clone()));
+            }
+
+            fn on_disconnect(&self, event: &ConnectionEvent) {
+                self.0.lock().unwrap().push(("disconnect", event.clone()));
+            }
+
+            fn on_auth_failure(&self, event: &ConnectionEvent, _error: &PgWireError) {
+                self.0.lock().unwrap().push(("auth failure", event.clone()));
+            }
+        }
+
+        let recorder = Arc::new(Recorder::default());
+        let auth_manager = Arc::new(AuthManager::new());
+        let service = DfSessionService::new(Arc::new(SessionContext::new()), auth_manager.clone())
+            .with_connection_listener(recorder.clone());
+        let startup_handler = TracedStartupHandler::new(
+            Arc::new(DfStartupHandler::Password(PasswordStartupHandler::new(
+                auth_manager,
+            ))),
+            &service,
+        );
+        // users unknown to the auth manager fail to authenticate
+        let mut client = MockClient::new();
+        client
+            .metadata
+            .insert(METADATA_USER.to_string(), "mallory".to_string());
+
+        service.start_session(client.socket_addr);
+        let password = PgWireFrontendMessage::PasswordMessageFamily(PasswordMessageFamily::Raw(
+            bytes::BytesMut::from(&b"wrong\0"[..]),
+        ));
+        assert!(startup_handler
+            .on_startup(&mut client, password)
+            .await
+            .is_err());
+        service.end_session(&client.socket_addr);
+
+        let events = recorder.0.lock().unwrap();
+        let kinds = events.iter().map(|(kind, _)| *kind).collect::<Vec<_>>();
+        assert_eq!(kinds, vec!["connect", "auth failure", "disconnect"]);
+        assert!(events
+            .iter()
+            .all(|(_, event)| event.peer == client.socket_addr && event.session_id == Some(1)));
+        assert_eq!(events[1].1.user.as_deref(), Some("mallory"));
+    }
+
+    #[cfg(feature = "jwt")]
+    #[tokio::test]
+    async fn test_jwt_sessions() {
+        use crate::jwt::JwtConfig;
+        use crate::policy::RoleRowFilter;
+        use jsonwebtoken::jwk::JwkSet;
+        use jsonwebtoken::{encode, EncodingKey, Header};
+        use pgwire::messages::startup::PasswordMessageFamily;
+
+        let session_context = Arc::new(SessionContext::new());
+        session_context
+            .sql("CREATE TABLE events AS VALUES ('a'), ('b')")
+            .await
+            .unwrap();
+        session_context.register_catalog(
+            "other",
+            Arc::new(datafusion::catalog::MemoryCatalogProvider::new()),
+        );
+        for sql in [
+            "CREATE SCHEMA other.s",
+            "CREATE TABLE other.s.t AS VALUES (1)",
+        ] {
+            session_context.sql(sql).await.unwrap();
+        }
+        let auth_manager = Arc::new(AuthManager::new());
+        for role in ["ra", "rb"] {
+            auth_manager
+                .create_role(crate::auth::RoleConfig {
+                    name: role.to_string(),
+                    is_superuser: false,
+                    can_login: false,
+                    can_create_db: false,
+                    can_create_role: false,
+                    can_create_user: false,
+                    can_replication: false,
+                })
+                .await
+                .unwrap();
+            auth_manager
+                .grant_permission(
+                    role,
+                    Permission::Select,
+                    ResourceType::All,
+                    "postgres",
+                    false,
+                )
+                .await
+                .unwrap();
+        }
+        auth_manager
+            .add_row_filter_policy(
+                "events",
+                Arc::new(
+                    RoleRowFilter::new()
+                        .with_role_predicate("ra", col("column1").eq(lit("a")))
+                        .with_role_predicate("rb", col("column1").eq(lit("b"))),
+                ),
+            )
+            .await;
+        let service = DfSessionService::new(session_context, auth_manager.clone());
+
+        let keys: JwkSet = serde_json::from_value(serde_json::json!({
+            "keys": [{"kty": "oct", "kid": "test", "alg": "HS256", "k": "dGVzdC1zZWNyZXQ"}]
+        }))
+        .unwrap();
+        let authenticator = Arc::new(JwtAuthenticator::with_static_keys(
+            JwtConfig::new("https://idp.example.com/.well-known/jwks.json"),
+            keys,
+        ));
+        let startup_handler = JwtStartupHandler::new(authenticator, auth_manager.clone());
+        let login = |port: u16, claims: serde_json::Value| {
+            let startup_handler = &startup_handler;
+            async move {
+                let mut client = MockClient::new();
+                client.socket_addr = format!("127.0.0.1:{port}").parse().unwrap();
+                client
+                    .metadata
+                    .insert(METADATA_USER.to_string(), "alice".to_string());
+                let header = Header {
+                    kid: Some("test".to_string()),
+                    ..Default::default()
+                };
+                let token =
+                    encode(&header, &claims, &EncodingKey::from_secret(b"test-secret")).unwrap();
+                let password =
+                    PgWireFrontendMessage::PasswordMessageFamily(PasswordMessageFamily::Raw(
+                        bytes::BytesMut::from(format!("{token}\0").as_bytes()),
+                    ));
+                startup_handler
+                    .on_startup(&mut client, password)
+                    .await
+                    .unwrap();
+                client
+            }
+        };
+        let exp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+            + 600;
+        let mut a = login(
+            5433,
+            serde_json::json!({"sub": "alice", "exp": exp, "roles": ["ra"], "catalogs": ["datafusion"]}),
+        )
+        .await;
+        let mut b = login(
+            5434,
+            serde_json::json!({"sub": "alice", "exp": exp, "roles": ["rb"]}),
+        )
+        .await;
+
+        // the roles of each token only apply to its own session
+        let events = "SELECT column1 FROM events";
+        assert_eq!(query_rows(&service, &mut a, events).await, ["a"]);
+        assert_eq!(query_rows(&service, &mut b, events).await, ["b"]);
+        assert_eq!(query_rows(&service, &mut a, events).await, ["a"]);
+        assert!(auth_manager.get_user("alice").await.is_none());
+
+        // and the catalogs of the token confine the queries of the session
+        let other = "SELECT column1 FROM other.s.t";
+        assert!(SimpleQueryHandler::do_query(&service, &mut a, other)
+            .await
+            .is_err());
+        assert_eq!(query_rows(&service, &mut b, other).await, ["1"]);
+
+        service.end_session(&a.socket_addr);
+        let session_user = a.metadata.get(METADATA_AUTHORIZED_USER).unwrap();
+        assert!(auth_manager.get_user(session_user).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_mounted_handlers() {
+        // handlers of an application serving its own pgwire connections
+        struct AppHandlers {
+            session_service: Arc<DfSessionService>,
+        }
+
+        impl PgWireServerHandlers for AppHandlers {
+            fn simple_query_handler(&self) -> Arc<impl SimpleQueryHandler> {
+                self.session_service.clone()
+            }
+
+            fn copy_handler(&self) -> Arc<impl CopyHandler> {
+                Arc::new(DfCopyHandler::new(self.session_service.clone()))
+            }
+
+            fn error_handler(&self) -> Arc<impl ErrorHandler> {
+                Arc::new(DfErrorHandler::new(&self.session_service))
+            }
+        }
+
+        let service = DfSessionService::new(
+            Arc::new(SessionContext::new()),
+            Arc::new(AuthManager::new()),
+        );
+        let handlers = AppHandlers {
+            session_service: Arc::new(service),
+        };
+        let mut client = MockClient::new();
+        client
+            .metadata
+            .insert(METADATA_USER.to_string(), "postgres".to_string());
+        handlers.session_service.start_session(client.socket_addr);
+
+        let resp = handlers
+            .simple_query_handler()
+            .do_query(&mut client, "SELECT 1")
+            .await
+            .unwrap();
+        assert!(matches!(resp.as_slice(), [Response::Query(_)]));
+        assert_eq!(
+            handlers
+                .session_service
+                .activity_registry()
+                .sessions()
+                .len(),
+            1
+        );
+
+        let err = handlers
+            .copy_handler()
+            .on_copy_data(&mut client, CopyData::default())
+            .await
+            .unwrap_err();
+        let PgWireError::UserError(info) = err else {
+            panic!("expected user error");
+        };
+        assert_eq!(info.code, "08P01");
+
+        handlers.session_service.end_session(&client.socket_addr);
+        assert!(handlers
+            .session_service
+            .activity_registry()
+            .sessions()
+            .is_empty());
+    }
+
+    async fn query_rows(
+        service: &DfSessionService,
+        client: &mut MockClient,
+        sql: &str,
+    ) -> Vec<String> {
+        let mut resp = SimpleQueryHandler::do_query(service, client, sql)
+            .await
+            .unwrap();
+        let Response::Query(resp) = resp.remove(0) else {
+            panic!("expected rows");
+        };
+        resp.data_rows()
+            .map(|row| first_column_text(&row.unwrap()))
+            .collect::<Vec<_>>()
+            .await
+    }
+
+    #[tokio::test]
+    async fn test_advisory_locks() {
+        const LOCKS: &str = "SELECT concat(pid, ':', classid, ':', objid, ':', objsubid, ':', \
+                             mode, ':', granted) FROM pg_catalog.pg_locks ORDER BY pid, objid";
+        let session_context = Arc::new(SessionContext::new());
+        crate::pg_catalog::setup_pg_catalog(&session_context, "datafusion").unwrap();
+        let service = DfSessionService::new(session_context, Arc::new(AuthManager::new()));
+        let mut alice = MockClient::new();
+        alice
+            .metadata
+            .insert(METADATA_USER.to_string(), "postgres".to_string());
+        let mut bob = MockClient::new();
+        bob.socket_addr = "127.0.0.1:5433".parse().unwrap();
+        bob.metadata
+            .insert(METADATA_USER.to_string(), "postgres".to_string());
+        service.start_session(alice.socket_addr);
+        service.start_session(bob.socket_addr);
+
+        let try_lock = "SELECT pg_try_advisory_lock(42)";
+        assert_eq!(query_rows(&service, &mut alice, try_lock).await, ["t"]);
+        assert_eq!(query_rows(&service, &mut bob, try_lock).await, ["f"]);
+        assert_eq!(
+            query_rows(
+                &service,
+                &mut bob,
+                "SELECT pg_advisory_lock_shared(1, 2) IS NULL"
+            )
+            .await,
+            ["t"]
+        );
+        assert_eq!(
+            query_rows(&service, &mut alice, LOCKS).await,
+            ["1:0:42:1:ExclusiveLock:true", "2:1:2:2:ShareLock:true"]
+        );
+        assert_eq!(
+            query_rows(&service, &mut alice, "SELECT pg_advisory_unlock(42)").await,
+            ["t"]
+        );
+        assert_eq!(
+            query_rows(&service, &mut alice, "SELECT pg_advisory_unlock(42)").await,
+            ["f"]
+        );
+        let warnings: Vec<_> = alice
+            .sent
+            .iter()
+            .filter_map(|message| match message {
+                PgWireBackendMessage::NoticeResponse(notice) => Some(notice.fields.clone()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains(&(b'S', "WARNING".to_string())));
+        assert!(warnings[0].contains(&(
+            b'M',
+            "you don't own a lock of type ExclusiveLock".to_string()
+        )));
+        assert_eq!(query_rows(&service, &mut bob, try_lock).await, ["t"]);
+
+        // transaction level locks are held until the transaction block ends
+        let try_xact_lock = "SELECT pg_try_advisory_xact_lock(7)";
+        SimpleQueryHandler::do_query(&service, &mut bob, "BEGIN")
+            .await
+            .unwrap();
+        bob.transaction_status = TransactionStatus::Transaction;
+        assert_eq!(query_rows(&service, &mut bob, try_xact_lock).await, ["t"]);
+        assert_eq!(query_rows(&service, &mut alice, try_xact_lock).await, ["f"]);
+        SimpleQueryHandler::do_query(&service, &mut bob, "COMMIT")
+            .await
+            .unwrap();
+        bob.transaction_status = TransactionStatus::Idle;
+        // and outside of one only while the statement runs
+        assert_eq!(query_rows(&service, &mut alice, try_xact_lock).await, ["t"]);
+        assert_eq!(query_rows(&service, &mut bob, try_xact_lock).await, ["t"]);
+
+        // the locks of a session are released when it disconnects
+        service.end_session(&bob.socket_addr);
+        assert_eq!(query_rows(&service, &mut alice, try_lock).await, ["t"]);
+        assert_eq!(
+            query_rows(&service, &mut alice, LOCKS).await,
+            ["1:0:42:1:ExclusiveLock:true"]
+        );
+        assert_eq!(
+            query_rows(
+                &service,
+                &mut alice,
+                "SELECT pg_advisory_unlock_all() IS NULL"
+            )
+            .await,
+            ["t"]
+        );
+        assert!(query_rows(&service, &mut alice, LOCKS).await.is_empty());
+
+        // a waiting session leaves the worker of the current thread runtime
+        // to the session holding the lock
+        let lock = "SELECT pg_advisory_lock(5) IS NULL";
+        assert_eq!(query_rows(&service, &mut alice, lock).await, ["t"]);
+        service.start_session(bob.socket_addr);
+        let service = Arc::new(service);
+        let waiting = tokio::spawn({
+            let service = service.clone();
+            async move { query_rows(&service, &mut bob, lock).await }
+        });
+        while query_rows(&service, &mut alice, LOCKS).await.len() < 2 {
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(
+            query_rows(&service, &mut alice, "SELECT pg_advisory_unlock(5)").await,
+            ["t"]
+        );
+        assert_eq!(waiting.await.unwrap(), ["t"]);
+    }
+
+    #[tokio::test]
+    async fn test_pg_stat_activity() {
+        const SESSIONS: &str =
+            "SELECT concat(state, ': ', query) FROM pg_catalog.pg_stat_activity ORDER BY pid";
+        let schema = Arc::new(Schema::new(vec![Field::new("n", DataType::Int32, false)]));
+        let table =
+            StreamingTable::try_new(schema.clone(), vec![Arc::new(NeverEndingPartition(schema))])
+                .unwrap();
+        let session_context = Arc::new(SessionContext::new());
+        session_context
+            .register_table("events", Arc::new(table))
+            .unwrap();
+        crate::pg_catalog::setup_pg_catalog(&session_context, "datafusion").unwrap();
+        let service = DfSessionService::new(session_context, Arc::new(AuthManager::new()));
+
+        let mut stuck = MockClient::new();
+        stuck
+            .metadata
+            .insert(METADATA_USER.to_string(), "postgres".to_string());
+        let mut monitor = MockClient::new();
+        monitor.socket_addr = "127.0.0.1:5433".parse().unwrap();
+        monitor
+            .metadata
+            .insert(METADATA_USER.to_string(), "postgres".to_string());
+
+        // rows of the query are never all sent, so it stays active
+        let resp = SimpleQueryHandler::do_query(&service, &mut stuck, "SELECT n FROM events")
+            .await
+            .unwrap();
+
+        let rows = query_rows(&service, &mut monitor, SESSIONS).await;
+        assert_eq!(rows[0], "active: SELECT n FROM events");
+        assert!(rows[1].starts_with("active: SELECT concat(state"));
+
+        drop(resp);
+        let rows = query_rows(&service, &mut monitor, SESSIONS).await;
+        assert_eq!(rows[0], "idle: SELECT n FROM events");
+
+        service.activity_registry().end_session(&stuck.socket_addr);
+        assert_eq!(query_rows(&service, &mut monitor, SESSIONS).await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_show_settings() {
+        const PG_SETTINGS: &str = "SELECT concat(name, '=', setting) FROM pg_catalog.pg_settings \
+                                   WHERE name IN ('result_batch_size', 'wire_debug')";
+        let session_context = Arc::new(SessionContext::new());
+        crate::pg_catalog::setup_pg_catalog(&session_context, "datafusion").unwrap();
+        let service = DfSessionService::new(session_context, Arc::new(AuthManager::new()));
+        let mut client = MockClient::new();
+        client
+            .metadata
+            .insert(METADATA_USER.to_string(), "postgres".to_string());
+
+        SimpleQueryHandler::do_query(&service, &mut client, "SET result_batch_size = 100")
+            .await
+            .unwrap();
+        let mut responses = SimpleQueryHandler::do_query(&service, &mut client, "SHOW ALL")
+            .await
+            .unwrap();
+        let Response::Query(resp) = responses.remove(0) else {
+            panic!("expected rows");
+        };
+        let names = resp
+            .row_schema()
+            .iter()
+            .map(|field| field.name().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["name", "setting", "description"]);
+        let settings = resp
+            .data_rows()
+            .map(|row| first_column_text(&row.unwrap()))
+            .collect::<Vec<_>>()
+            .await;
+        assert!(settings.contains(&"standard_conforming_strings".to_string()));
+
+        // SHOW and pg_settings show the values of the session
+        assert_eq!(
+            query_rows(&service, &mut client, "SHOW result_batch_size").await,
+            ["100"]
+        );
+        assert_eq!(
+            query_rows(&service, &mut client, "SHOW TimeZone").await,
+            query_rows(&service, &mut client, "SHOW time zone").await,
+        );
+        assert_eq!(
+            query_rows(&service, &mut client, PG_SETTINGS).await,
+            ["result_batch_size=100", "wire_debug=off"]
+        );
+        assert_eq!(
+            query_rows(
+                &service,
+                &mut client,
+                "SELECT concat(setting, ':', vartype, ':', context, ':', source) \
+                 FROM pg_catalog.pg_settings WHERE name = 'server_version_num'"
+            )
+            .await,
+            ["150000:integer:internal:default"]
+        );
+        SimpleQueryHandler::do_query(&service, &mut client, "SET statement_timeout = '5000ms'")
+            .await
+            .unwrap();
+        assert_eq!(
+            query_rows(
+                &service,
+                &mut client,
+                "SELECT concat(setting, unit, ':', source) FROM pg_catalog.pg_settings \
+                 WHERE name = 'statement_timeout'"
+            )
+            .await,
+            ["5000ms:session"]
+        );
+        assert_eq!(
+            query_rows(
+                &service,
+                &mut client,
+                "SELECT array_to_string(enumvals, ',') FROM pg_catalog.pg_settings \
+                 WHERE name = 'IntervalStyle'"
+            )
+            .await,
+            ["postgres,postgres_verbose,sql_standard,iso_8601"]
+        );
+
+        let err = SimpleQueryHandler::do_query(&service, &mut client, "SHOW no_such_setting")
+            .await
+            .err()
+            .unwrap();
+        let PgWireError::UserError(info) = err else {
+            panic!("expected a user error");
+        };
+        assert_eq!(info.code, "42704");
+        assert_eq!(
+            info.message,
+            "unrecognized configuration parameter \"no_such_setting\""
+        );
+    }
+
+    #[tokio::test]
+    async fn test_transaction_isolation() {
+        const ISOLATION: &str = "SHOW TRANSACTION ISOLATION LEVEL";
+        let service = DfSessionService::new(
+            Arc::new(SessionContext::new()),
+            Arc::new(AuthManager::new()),
+        );
+        let mut client = MockClient::new();
+        client
+            .metadata
+            .insert(METADATA_USER.to_string(), "postgres".to_string());
+        assert_eq!(
+            query_rows(&service, &mut client, ISOLATION).await,
+            ["read committed"]
+        );
+
+        for sql in [
+            "SET SESSION CHARACTERISTICS AS TRANSACTION ISOLATION LEVEL SERIALIZABLE",
+            "BEGIN ISOLATION LEVEL REPEATABLE READ",
+        ] {
+            SimpleQueryHandler::do_query(&service, &mut client, sql)
+                .await
+                .unwrap();
+        }
+        client.transaction_status = TransactionStatus::Transaction;
+        assert_eq!(
+            query_rows(&service, &mut client, ISOLATION).await,
+            ["repeatable read"]
+        );
+        SimpleQueryHandler::do_query(
+            &service,
+            &mut client,
+            "SET TRANSACTION ISOLATION LEVEL READ UNCOMMITTED",
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            query_rows(&service, &mut client, "SHOW transaction_isolation").await,
+            ["read uncommitted"]
+        );
+
+        // after the transaction, the level of the session applies
+        client.transaction_status = TransactionStatus::Idle;
+        assert_eq!(
+            query_rows(&service, &mut client, ISOLATION).await,
+            ["serializable"]
+        );
+        SimpleQueryHandler::do_query(
+            &service,
+            &mut client,
+            "SET default_transaction_isolation TO DEFAULT",
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            query_rows(&service, &mut client, "SHOW default_transaction_isolation").await,
+            ["read committed"]
+        );
+
+        let err = SimpleQueryHandler::do_query(
+            &service,
+            &mut client,
+            "SET default_transaction_isolation = 'eventual'",
+        )
+        .await
+        .err()
+        .unwrap();
+        let PgWireError::UserError(info) = err else {
+            panic!("expected a user error");
+        };
+        assert_eq!(info.code, "22023");
+
+        // modes other than the isolation level are accepted, invalid ones not
+        assert_eq!(
+            isolation_level("read only, isolation level serializable;").unwrap(),
+            Some("serializable")
+        );
+        assert_eq!(isolation_level("not deferrable read write").unwrap(), None);
+        for modes in ["isolation level eventual", "read only,", "read sometimes"] {
+            assert!(isolation_level(modes).is_err(), "{modes}");
+        }
+        assert_eq!(
+            split_transaction_statement("begin read only;"),
+            ("begin", Some("read only"))
+        );
+        assert_eq!(split_transaction_statement("commit ; "), ("commit", None));
+    }
+
+    #[tokio::test]
+    async fn test_replication_handler() {
+        struct IdentifySystem;
+
+        #[async_trait]
+        impl ReplicationHandler for IdentifySystem {
+            async fn on_command<'a>(
+                &self,
+                _context: &HookContext,
+                mode: ReplicationMode,
+                command: &str,
+            ) -> PgWireResult<Option<Response<'a>>> {
+                assert_eq!(mode, ReplicationMode::Logical);
+                Ok((command == "IDENTIFY_SYSTEM")
+                    .then(|| Response::Execution(Tag::new("IDENTIFY_SYSTEM"))))
+            }
+        }
+
+        let service = DfSessionService::new(
+            Arc::new(SessionContext::new()),
+            Arc::new(AuthManager::new()),
+        )
+        .with_replication_handler(Arc::new(IdentifySystem));
+        let mut client = MockClient::new();
+        client
+            .metadata
+            .insert(METADATA_USER.to_string(), "postgres".to_string());
+
+        // regular connections don't reach the handler
+        let err = SimpleQueryHandler::do_query(&service, &mut client, "IDENTIFY_SYSTEM")
+            .await
+            .err();
+        assert!(err.is_some());
+
+        client
+            .metadata
+            .insert(REPLICATION_PARAMETER.to_string(), "database".to_string());
+        let responses = SimpleQueryHandler::do_query(&service, &mut client, "IDENTIFY_SYSTEM;")
+            .await
+            .unwrap();
+        assert!(matches!(responses[..], [Response::Execution(_)]));
+        // SQL the handler leaves alone runs as usual
+        let responses = SimpleQueryHandler::do_query(&service, &mut client, "SELECT 1")
+            .await
+            .unwrap();
+        assert!(matches!(responses[..], [Response::Query(_)]));
+    }
+
+    #[tokio::test]
+    async fn test_discard() {
+        let service = DfSessionService::new(
+            Arc::new(SessionContext::new()),
+            Arc::new(AuthManager::new()),
+        )
+        .with_plan_cache(Arc::new(crate::plan_cache::PlanCache::new(
+            16,
+            crate::plan_cache::PlanCacheScope::Session,
+        )));
+        let mut client = MockClient::new();
+        client
+            .metadata
+            .insert(METADATA_USER.to_string(), "postgres".to_string());
+
+        for sql in [
+            "SET result_batch_size = 100",
+            "SET wire_debug = on",
+            "LISTEN events",
+            "SELECT 1",
+        ] {
+            SimpleQueryHandler::do_query(&service, &mut client, sql)
+                .await
+                .unwrap();
+        }
+        assert_eq!(service.plan_cache.as_ref().unwrap().len(), 1);
+
+        // DISCARD PLANS only drops the plans
+        let mut responses = SimpleQueryHandler::do_query(&service, &mut client, "DISCARD PLANS")
+            .await
+            .unwrap();
+        assert!(matches!(responses.remove(0), Response::Execution(_)));
+        assert!(service.plan_cache.as_ref().unwrap().is_empty());
+        assert_eq!(
+            query_rows(&service, &mut client, "SHOW result_batch_size").await,
+            ["100"]
+        );
+
+        // DISCARD ALL resets the session
+        SimpleQueryHandler::do_query(&service, &mut client, "DISCARD ALL")
+            .await
+            .unwrap();
+        assert_eq!(
+            query_rows(&service, &mut client, "SHOW wire_debug").await,
+            ["off"]
+        );
+        assert_ne!(
+            query_rows(&service, &mut client, "SHOW result_batch_size").await,
+            ["100"]
+        );
+        SimpleQueryHandler::do_query(&service, &mut client, "NOTIFY events")
+            .await
+            .unwrap();
+        assert!(service.notifications.take(&client.socket_addr).is_empty());
+        assert_eq!(client.metadata[METADATA_USER], "postgres");
+
+        // but not in a transaction
+        client.transaction_status = TransactionStatus::Transaction;
+        let err = SimpleQueryHandler::do_query(&service, &mut client, "DISCARD ALL")
+            .await
+            .err()
+            .unwrap();
+        let PgWireError::UserError(info) = err else {
+            panic!("expected a user error");
+        };
+        assert_eq!(info.code, "25001");
+    }
+
+    #[tokio::test]
+    async fn test_create_database() {
+        let session_context = Arc::new(SessionContext::new());
+        crate::pg_catalog::setup_pg_catalog(&session_context, "datafusion").unwrap();
+        let auth_manager = Arc::new(AuthManager::new());
+        auth_manager
+            .add_user(crate::auth::User {
+                username: "viewer".to_string(),
+                password_hash: String::new(),
+                roles: Vec::new(),
+                is_superuser: false,
+                can_login: true,
+                connection_limit: None,
+            })
+            .await
+            .unwrap();
+        let service = DfSessionService::new(session_context.clone(), auth_manager);
+        let mut admin = MockClient::new();
+        admin
+            .metadata
+            .insert(METADATA_USER.to_string(), "postgres".to_string());
+
+        let mut responses =
+            SimpleQueryHandler::do_query(&service, &mut admin, "CREATE DATABASE Analytics")
+                .await
+                .unwrap();
+        assert!(matches!(responses.remove(0), Response::Execution(_)));
+        assert_eq!(
+            query_rows(
+                &service,
+                &mut admin,
+                "SELECT datname FROM pg_catalog.pg_database WHERE datname = 'analytics'"
+            )
+            .await,
+            ["analytics"]
+        );
+
+        // clients connected to the database resolve names in its catalog
+        session_context
+            .sql("CREATE TABLE analytics.public.events AS SELECT 7 AS n")
+            .await
+            .unwrap();
+        let mut analyst = MockClient::new();
+        analyst.socket_addr = "127.0.0.1:5433".parse().unwrap();
+        analyst
+            .metadata
+            .insert(METADATA_USER.to_string(), "postgres".to_string());
+        analyst.metadata.insert(
+            pgwire::api::METADATA_DATABASE.to_string(),
+            "analytics".to_string(),
+        );
+        assert_eq!(
+            query_rows(&service, &mut analyst, "SELECT n FROM events").await,
+            ["7"]
+        );
+        session_context
+            .sql("DROP TABLE analytics.public.events")
+            .await
+            .unwrap();
+
+        let code = |result: PgWireResult<Vec<Response>>| {
+            let Err(PgWireError::UserError(info)) = result else {
+                panic!("expected a user error");
+            };
+            info.code
+        };
+        let drop_sql = "DROP DATABASE analytics";
+        assert_eq!(
+            code(SimpleQueryHandler::do_query(&service, &mut admin, drop_sql).await),
+            "55006"
+        );
+        service.end_session(&analyst.socket_addr);
+
+        // only roles allowed to create databases may drop them
+        let mut viewer = MockClient::new();
+        viewer.socket_addr = "127.0.0.1:5434".parse().unwrap();
+        viewer
+            .metadata
+            .insert(METADATA_USER.to_string(), "viewer".to_string());
+        assert_eq!(
+            code(SimpleQueryHandler::do_query(&service, &mut viewer, drop_sql).await),
+            "42501"
+        );
+
+        // and not in a transaction
+        admin.transaction_status = TransactionStatus::Transaction;
+        assert_eq!(
+            code(SimpleQueryHandler::do_query(&service, &mut admin, drop_sql).await),
+            "25001"
+        );
+        admin.transaction_status = TransactionStatus::Idle;
+
+        SimpleQueryHandler::do_query(&service, &mut admin, drop_sql)
+            .await
+            .unwrap();
+        assert!(session_context.catalog("analytics").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_role_statements() {
+        const ROLES: &str = "SELECT concat(rolname, ' ', rolcanlogin, ' ', rolcreatedb, ' ', \
+                             rolconnlimit) FROM pg_catalog.pg_roles WHERE rolname = 'analyst'";
+        let session_context = Arc::new(SessionContext::new());
+        crate::pg_catalog::setup_pg_catalog(&session_context, "datafusion").unwrap();
+        let credentials_file = std::env::temp_dir().join(format!(
+            "datafusion-postgres-roles-{}.conf",
+            std::process::id()
+        ));
+        let auth_manager = Arc::new(
+            AuthManager::new()
+                .with_credentials_file(&credentials_file)
+                .unwrap(),
+        );
+        let service = DfSessionService::new(session_context, auth_manager.clone());
+        let mut admin = MockClient::new();
+        admin
+            .metadata
+            .insert(METADATA_USER.to_string(), "postgres".to_string());
+        let code = |result: PgWireResult<Vec<Response>>| {
+            let Err(PgWireError::UserError(info)) = result else {
+                panic!("expected a user error");
+            };
+            info.code
+        };
+
+        SimpleQueryHandler::do_query(
+            &service,
+            &mut admin,
+            "CREATE ROLE analyst WITH LOGIN PASSWORD 's3cret' CONNECTION LIMIT 2",
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            query_rows(&service, &mut admin, ROLES).await,
+            ["analyst true false 2"]
+        );
+        assert!(auth_manager
+            .authenticate("analyst", "s3cret")
+            .await
+            .unwrap());
+        let create = "CREATE ROLE analyst";
+        assert_eq!(
+            code(SimpleQueryHandler::do_query(&service, &mut admin, create).await),
+            "42710"
+        );
+
+        // roles without CREATEROLE may only change their own password
+        let mut analyst = MockClient::new();
+        analyst.socket_addr = "127.0.0.1:5433".parse().unwrap();
+        analyst
+            .metadata
+            .insert(METADATA_USER.to_string(), "analyst".to_string());
+        assert_eq!(
+            code(SimpleQueryHandler::do_query(&service, &mut analyst, "CREATE ROLE other").await),
+            "42501"
+        );
+        assert_eq!(
+            code(
+                SimpleQueryHandler::do_query(&service, &mut analyst, "ALTER ROLE analyst CREATEDB")
+                    .await
+            ),
+            "42501"
+        );
+        SimpleQueryHandler::do_query(&service, &mut analyst, "ALTER ROLE analyst PASSWORD 'new'")
+            .await
+            .unwrap();
+        assert!(auth_manager.authenticate("analyst", "new").await.unwrap());
+
+        SimpleQueryHandler::do_query(
+            &service,
+            &mut admin,
+            "ALTER ROLE analyst CREATEDB CONNECTION LIMIT 10",
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            query_rows(&service, &mut admin, ROLES).await,
+            ["analyst true true 10"]
+        );
+
+        // the roles are written to the credentials file
+        let credentials =
+            crate::roles::parse_credentials(&std::fs::read_to_string(&credentials_file).unwrap())
+                .unwrap();
+        let analyst_credentials = credentials
+            .iter()
+            .find(|role| role.name == "analyst")
+            .unwrap();
+        // as verifiers, not the cleartext passwords
+        assert!(
+            ScramCredentials::from_verifier(&analyst_credentials.password)
+                .unwrap()
+                .verify("new")
+        );
+        assert_eq!(analyst_credentials.attributes.create_db, Some(true));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&credentials_file)
+                .unwrap()
+                .permissions()
+                .mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        // passwords can't add lines to the file, nor names fields
+        SimpleQueryHandler::do_query(
+            &service,
+            &mut analyst,
+            "ALTER ROLE analyst PASSWORD E'x:\\nevil:pw:SUPERUSER LOGIN'",
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            code(
+                SimpleQueryHandler::do_query(&service, &mut admin, "CREATE ROLE \"a:b\" LOGIN")
+                    .await
+            ),
+            "42602"
+        );
+        let reloaded = AuthManager::new()
+            .with_credentials_file(&credentials_file)
+            .unwrap();
+        assert!(reloaded.get_user("evil").await.is_none());
+        assert!(reloaded
+            .authenticate("analyst", "x:\nevil:pw:SUPERUSER LOGIN")
+            .await
+            .unwrap());
+
+        SimpleQueryHandler::do_query(&service, &mut admin, "DROP ROLE analyst")
+            .await
+            .unwrap();
+        assert!(query_rows(&service, &mut admin, ROLES).await.is_empty());
+        assert_eq!(
+            code(SimpleQueryHandler::do_query(&service, &mut admin, "DROP ROLE analyst").await),
+            "42704"
+        );
+        SimpleQueryHandler::do_query(&service, &mut admin, "DROP ROLE IF EXISTS analyst")
+            .await
+            .unwrap();
+        std::fs::remove_file(&credentials_file).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_setting_defaults() {
+        const SETTINGS: &str = "SELECT concat(setdatabase <> 0, ' ', setrole <> 0, ' ', \
+                                array_to_string(setconfig, ',')) \
+                                FROM pg_catalog.pg_db_role_setting ORDER BY 1";
+        let session_context = Arc::new(SessionContext::new());
+        crate::pg_catalog::setup_pg_catalog(&session_context, "datafusion").unwrap();
+        session_context
+            .sql("CREATE SCHEMA reporting")
+            .await
+            .unwrap();
+        session_context
+            .sql("CREATE TABLE reporting.sales AS VALUES (1)")
+            .await
+            .unwrap();
+        let auth_manager = Arc::new(AuthManager::new());
+        let service = DfSessionService::new(session_context, auth_manager.clone());
+        let mut admin = MockClient::new();
+        admin
+            .metadata
+            .insert(METADATA_USER.to_string(), "postgres".to_string());
+        let code = |result: PgWireResult<Vec<Response>>| {
+            let Err(PgWireError::UserError(info)) = result else {
+                panic!("expected a user error");
+            };
+            info.code
+        };
+
+        for statement in [
+            "CREATE ROLE analyst LOGIN",
+            "ALTER ROLE ALL SET TimeZone = 'UTC'",
+            "ALTER DATABASE datafusion SET search_path = reporting",
+            "ALTER ROLE analyst SET statement_timeout = '30s'",
+            "ALTER ROLE analyst IN DATABASE datafusion SET timezone TO 'Europe/Berlin'",
+        ] {
+            SimpleQueryHandler::do_query(&service, &mut admin, statement)
+                .await
+                .unwrap();
+        }
+        auth_manager
+            .grant_permission(
+                "analyst",
+                Permission::Select,
+                ResourceType::All,
+                "postgres",
+                false,
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            query_rows(&service, &mut admin, SETTINGS).await,
+            [
+                "false false TimeZone=UTC",
+                "false true statement_timeout=30s",
+                "true false search_path=reporting",
+                "true true TimeZone=Europe/Berlin",
+            ]
+        );
+        assert_eq!(
+            code(
+                SimpleQueryHandler::do_query(
+                    &service,
+                    &mut admin,
+                    "ALTER DATABASE nope SET search_path = a"
+                )
+                .await
+            ),
+            "3D000"
+        );
+
+        // roles may change their own defaults only
+        let mut analyst = MockClient::new();
+        analyst.socket_addr = "127.0.0.1:5433".parse().unwrap();
+        analyst.state = PgWireConnectionState::AwaitingStartup;
+        analyst
+            .metadata
+            .insert(METADATA_USER.to_string(), "analyst".to_string());
+        for statement in [
+            "ALTER DATABASE datafusion RESET ALL",
+            "ALTER ROLE ALL RESET TimeZone",
+            "ALTER ROLE postgres SET statement_timeout = 0",
+        ] {
+            assert_eq!(
+                code(SimpleQueryHandler::do_query(&service, &mut analyst, statement).await),
+                "42501"
+            );
+        }
+        analyst.metadata.clear();
+
+        // sessions start with the defaults of their role and database
+        let startup_handler = TracedStartupHandler::new(
+            Arc::new(DfStartupHandler::Simple(SimpleStartupHandler)),
+            &service,
+        );
+        let startup = |parameters: &[(&str, &str)]| {
+            let mut startup = pgwire::messages::startup::Startup::new();
+            for (name, value) in [("user", "analyst"), ("database", "datafusion")]
+                .iter()
+                .chain(parameters)
+            {
+                startup
+                    .parameters
+                    .insert(name.to_string(), value.to_string());
+            }
+            PgWireFrontendMessage::Startup(startup)
+        };
+        startup_handler
+            .on_startup(&mut analyst, startup(&[]))
+            .await
+            .unwrap();
+        assert_eq!(analyst.metadata[METADATA_TIME_ZONE], "Europe/Berlin");
+        assert!(analyst.sent.iter().any(|message| matches!(
+            message,
+            PgWireBackendMessage::ParameterStatus(status)
+                if status.name == "TimeZone" && status.value == "Europe/Berlin"
+        )));
+        assert_eq!(
+            DfSessionService::get_statement_timeout(&analyst),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(
+            query_rows(&service, &mut analyst, "SHOW search_path").await,
+            ["reporting"]
+        );
+        assert_eq!(
+            query_rows(&service, &mut analyst, "SELECT count(*) FROM sales").await,
+            ["1"]
+        );
+
+        // RESET goes back to the defaults, shown with their source
+        const RESET_VALS: &str = "SELECT concat_ws(':', name, setting, reset_val, source) \
+             FROM pg_catalog.pg_settings \
+             WHERE name IN ('search_path', 'statement_timeout', 'TimeZone') ORDER BY name";
+        assert_eq!(
+            query_rows(&service, &mut analyst, RESET_VALS).await,
+            [
+                "TimeZone:Europe/Berlin:Europe/Berlin:database user",
+                "search_path:reporting:reporting:database",
+                "statement_timeout:30000:30000:user",
+            ]
+        );
+        SimpleQueryHandler::do_query(&service, &mut analyst, "SET TimeZone = 'UTC'")
+            .await
+            .unwrap();
+        assert_eq!(
+            query_rows(&service, &mut analyst, RESET_VALS).await[0],
+            "TimeZone:UTC:Europe/Berlin:session"
+        );
+
+        // settings the client sends override the defaults
+        let mut client = MockClient::new();
+        client.socket_addr = "127.0.0.1:5434".parse().unwrap();
+        client.state = PgWireConnectionState::AwaitingStartup;
+        let parameters = [("TimeZone", "Asia/Tokyo"), ("statement_timeout", "0")];
+        startup_handler
+            .on_startup(&mut client, startup(&parameters))
+            .await
+            .unwrap();
+        assert_eq!(client.metadata[METADATA_TIME_ZONE], "Asia/Tokyo");
+        assert_eq!(DfSessionService::get_statement_timeout(&client), None);
+
+        // the defaults of dropped roles are dropped too
+        SimpleQueryHandler::do_query(&service, &mut admin, "DROP ROLE analyst")
+            .await
+            .unwrap();
+        assert_eq!(
+            query_rows(&service, &mut admin, SETTINGS).await,
+            [
+                "false false TimeZone=UTC",
+                "true false search_path=reporting"
+            ]
+        );
+    }
+
+    #[cfg(feature = "postgres-fdw")]
+    #[tokio::test]
+    async fn test_foreign_tables() {
+        // another server plays the upstream postgres
+        let upstream = Arc::new(SessionContext::new());
+        upstream
+            .sql("CREATE TABLE orders (id INT, customer_id INT, total DOUBLE) AS VALUES (1, 1, 9.5), (2, 2, 20.0), (3, 1, 0.5)")
+            .await
+            .unwrap();
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let server = crate::DfPostgresServer::builder()
+            .session_context(upstream)
+            .listen(format!("127.0.0.1:{port}"))
+            .build()
+            .unwrap();
+        let serving = tokio::spawn(async move { server.serve().await });
+
+        let session_context = Arc::new(SessionContext::new());
+        session_context
+            .sql("CREATE TABLE customers (id INT, name VARCHAR) AS VALUES (1, 'ann'), (2, 'bob')")
+            .await
+            .unwrap();
+        let service = DfSessionService::new(session_context, Arc::new(AuthManager::new()));
+        let mut client = MockClient::new();
+        client
+            .metadata
+            .insert(METADATA_USER.to_string(), "postgres".to_string());
+
+        let create_server = format!(
+            "CREATE SERVER shop FOREIGN DATA WRAPPER postgres_fdw \
+             OPTIONS (host '127.0.0.1', port '{port}', user 'postgres')"
+        );
+        for sql in [
+            create_server.as_str(),
+            "CREATE FOREIGN TABLE remote_orders (id INT, customer_id INT, total DOUBLE PRECISION) \
+             SERVER shop OPTIONS (table_name 'orders')",
+        ] {
+            let mut responses = SimpleQueryHandler::do_query(&service, &mut client, sql)
+                .await
+                .unwrap();
+            assert!(matches!(responses.remove(0), Response::Execution(_)));
+        }
+
+        assert_eq!(
+            query_rows(
+                &service,
+                &mut client,
+                "SELECT concat(c.name, ': ', o.total) FROM remote_orders o \
+                 JOIN customers c ON o.customer_id = c.id WHERE o.total > 1 ORDER BY o.id"
+            )
+            .await,
+            ["ann: 9.5", "bob: 20.0"]
+        );
+        assert_eq!(
+            query_rows(&service, &mut client, "SELECT count(*) FROM remote_orders").await,
+            ["3"]
+        );
+
+        let Err(PgWireError::UserError(info)) = SimpleQueryHandler::do_query(
+            &service,
+            &mut client,
+            "CREATE FOREIGN TABLE remote_orders (id INT) SERVER shop",
+        )
+        .await
+        else {
+            panic!("expected the table to exist");
+        };
+        assert_eq!(info.code, "42P07");
+        serving.abort();
+    }
+
+    #[tokio::test]
+    async fn test_create_external_table() {
+        use datafusion::catalog::{MemTable, Session, TableProvider, TableProviderFactory};
+        use datafusion::logical_expr::CreateExternalTable;
+
+        /// Delta Lake tables, as the versions of their snapshot
+        #[derive(Debug, Default)]
+        struct SnapshotTables {
+            locations: std::sync::Mutex<Vec<(String, Option<String>)>>,
+        }
+
+        #[async_trait]
+        impl TableProviderFactory for SnapshotTables {
+            async fn create(
+                &self,
+                _state: &dyn Session,
+                cmd: &CreateExternalTable,
+            ) -> datafusion::error::Result<Arc<dyn TableProvider>> {
+                let version = cmd.options.get("format.version").cloned();
+                self.locations
+                    .lock()
+                    .unwrap()
+                    .push((cmd.location.clone(), version.clone()));
+                let schema = Arc::new(Schema::new(vec![Field::new(
+                    "version",
+                    DataType::Utf8,
+                    true,
+                )]));
+                let batch = RecordBatch::try_new(
+                    schema.clone(),
+                    vec![Arc::new(datafusion::arrow::array::StringArray::from(vec![
+                        version,
+                    ]))],
+                )?;
+                Ok(Arc::new(MemTable::try_new(schema, vec![vec![batch]])?))
+            }
+        }
+
+        let factory = Arc::new(SnapshotTables::default());
+        let session_context = SessionContext::new();
+        session_context
+            .state_ref()
+            .write()
+            .table_factories_mut()
+            .insert("DELTA".to_string(), factory.clone());
+        session_context.register_object_store(
+            &url::Url::parse("s3://lake").unwrap(),
+            Arc::new(object_store::memory::InMemory::new()),
+        );
+        let service =
+            DfSessionService::new(Arc::new(session_context), Arc::new(AuthManager::new()));
+        let mut client = MockClient::new();
+        client
+            .metadata
+            .insert(METADATA_USER.to_string(), "postgres".to_string());
+
+        let mut responses = SimpleQueryHandler::do_query(
+            &service,
+            &mut client,
+            "CREATE EXTERNAL TABLE events STORED AS DELTA LOCATION 's3://lake/events' \
+             OPTIONS ('version' '12')",
+        )
+        .await
+        .unwrap();
+        assert!(matches!(responses.remove(0), Response::Execution(_)));
+        assert_eq!(
+            factory.locations.lock().unwrap().as_slice(),
+            [("s3://lake/events".to_string(), Some("12".to_string()))]
+        );
+        assert_eq!(
+            query_rows(&service, &mut client, "SELECT version FROM events").await,
+            ["12"]
+        );
+
+        let Err(PgWireError::UserError(info)) = SimpleQueryHandler::do_query(
+            &service,
+            &mut client,
+            "CREATE EXTERNAL TABLE snapshots STORED AS HUDI LOCATION 's3://lake/snapshots'",
+        )
+        .await
+        else {
+            panic!("expected an unsupported format");
+        };
+        assert_eq!(info.code, "0A000");
+    }
+
+    #[tokio::test]
+    async fn test_external_table_locations() {
+        let auth_manager = Arc::new(AuthManager::new());
+        for (username, roles) in [
+            ("analyst", vec!["analyst".to_string()]),
+            (
+                "loader",
+                vec!["loader".to_string(), "pg_read_server_files".to_string()],
+            ),
+        ] {
+            auth_manager
+                .create_role(crate::auth::RoleConfig {
+                    name: username.to_string(),
+                    is_superuser: false,
+                    can_login: true,
+                    can_create_db: false,
+                    can_create_role: false,
+                    can_create_user: false,
+                    can_replication: false,
+                })
+                .await
+                .unwrap();
+            auth_manager
+                .add_user(crate::auth::User {
+                    username: username.to_string(),
+                    password_hash: String::new(),
+                    roles,
+                    is_superuser: false,
+                    can_login: true,
+                    connection_limit: None,
+                })
+                .await
+                .unwrap();
+            auth_manager
+                .grant_permission(
+                    username,
+                    Permission::All,
+                    ResourceType::All,
+                    "postgres",
+                    false,
+                )
+                .await
+                .unwrap();
+        }
+        let import_root = std::env::temp_dir().join(format!(
+            "datafusion-postgres-imports-{}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&import_root).unwrap();
+        let cities = import_root.join("cities.csv");
+        std::fs::write(&cities, "city\nLagos\n").unwrap();
+        let service = DfSessionService::new(Arc::new(SessionContext::new()), auth_manager)
+            .with_import_root(&import_root);
+
+        // reading files of the server takes privileges
+        let mut analyst = MockClient::new();
+        analyst
+            .metadata
+            .insert(METADATA_USER.to_string(), "analyst".to_string());
+        let Err(PgWireError::UserError(info)) = SimpleQueryHandler::do_query(
+            &service,
+            &mut analyst,
+            "CREATE EXTERNAL TABLE passwd STORED AS CSV LOCATION '/etc/passwd'",
+        )
+        .await
+        else {
+            panic!("expected a permission error");
+        };
+        assert_eq!(info.code, "42501");
+        assert!(info.message.contains("pg_read_server_files"));
+
+        // and local files only under the import root of the server
+        let mut loader = MockClient::new();
+        loader
+            .metadata
+            .insert(METADATA_USER.to_string(), "loader".to_string());
+        for (location, code) in [
+            ("/etc/passwd", "42501"),
+            ("file:///etc/passwd", "42501"),
+            (&format!("{}/../passwd", import_root.display()), "42501"),
+            ("cities.csv", "42602"),
+            ("s3://elsewhere/passwd", "42501"),
+        ] {
+            let Err(PgWireError::UserError(info)) = SimpleQueryHandler::do_query(
+                &service,
+                &mut loader,
+                &format!("CREATE EXTERNAL TABLE passwd STORED AS CSV LOCATION '{location}'"),
+            )
+            .await
+            else {
+                panic!("expected {location} to be refused");
+            };
+            assert_eq!(info.code, code, "{location}");
+        }
+
+        SimpleQueryHandler::do_query(
+            &service,
+            &mut loader,
+            &format!(
+                "CREATE EXTERNAL TABLE cities STORED AS CSV LOCATION '{}' \
+                 OPTIONS ('format.has_header' 'true')",
+                cities.display()
+            ),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            query_rows(&service, &mut loader, "SELECT city FROM cities").await,
+            ["Lagos"]
+        );
+        std::fs::remove_dir_all(&import_root).unwrap();
+    }
+
+    #[cfg(feature = "deltalake")]
+    #[tokio::test]
+    async fn test_delta_tables() {
+        use object_store::ObjectStore;
+        use serde_json::json;
+
+        let session_context = Arc::new(SessionContext::new());
+        let store = Arc::new(object_store::memory::InMemory::new());
+        session_context
+            .register_object_store(&url::Url::parse("memory://").unwrap(), store.clone());
+        let service = DfSessionService::new(session_context, Arc::new(AuthManager::new()));
+        let mut client = MockClient::new();
+        client
+            .metadata
+            .insert(METADATA_USER.to_string(), "postgres".to_string());
+
+        // a file per day, added by the first two commits
+        let mut adds = vec![];
+        for (id, day) in [(1, "2024-06-01"), (2, "2024-06-02")] {
+            let path = format!("day={day}/part-{id}.parquet");
+            SimpleQueryHandler::do_query(
+                &service,
+                &mut client,
+                &format!(
+                    "COPY (SELECT {id} AS id, 'event {id}' AS name) \
+                     TO 'memory:///events/{path}' (FORMAT parquet)"
+                ),
+            )
+            .await
+            .unwrap();
+            let size = store
+                .head(&format!("events/{path}").into())
+                .await
+                .unwrap()
+                .size;
+            adds.push(json!({
+                "add": {
+                    "path": path,
+                    "partitionValues": {"day": day},
+                    "size": size,
+                    "modificationTime": 0,
+                    "dataChange": true,
+                }
+            }));
+        }
+        let schema = json!({
+            "type": "struct",
+            "fields": [
+                {"name": "id", "type": "long", "nullable": true, "metadata": {}},
+                {"name": "name", "type": "string", "nullable": true, "metadata": {}},
+                {"name": "day", "type": "date", "nullable": true, "metadata": {}},
+            ],
+        });
+        let commits = [
+            vec![
+                json!({"protocol": {"minReaderVersion": 1, "minWriterVersion": 2}}),
+                json!({
+                    "metaData": {
+                        "id": "events",
+                        "format": {"provider": "parquet", "options": {}},
+                        "schemaString": schema.to_string(),
+                        "partitionColumns": ["day"],
+                        "configuration": {},
+                    }
+                }),
+                adds[0].clone(),
+            ],
+            vec![adds[1].clone()],
+            vec![json!({"remove": {"path": adds[0]["add"]["path"], "dataChange": true}})],
+        ];
+        for (version, actions) in commits.iter().enumerate() {
+            let log = actions
+                .iter()
+                .map(|action| action.to_string())
+                .collect::<Vec<_>>()
+                .join("\n");
+            store
+                .put(
+                    &format!("events/_delta_log/{version:020}.json").into(),
+                    log.into(),
+                )
+                .await
+                .unwrap();
+        }
+
+        for (table, options, rows) in [
+            (
+                "events_v0",
+                "OPTIONS ('version' '0')",
+                vec!["1 event 1 2024-06-01"],
+            ),
+            (
+                "events_v1",
+                "OPTIONS ('version' '1')",
+                vec!["1 event 1 2024-06-01", "2 event 2 2024-06-02"],
+            ),
+            ("events", "", vec!["2 event 2 2024-06-02"]),
+        ] {
+            SimpleQueryHandler::do_query(
+                &service,
+                &mut client,
+                &format!(
+                    "CREATE EXTERNAL TABLE {table} STORED AS DELTA \
+                     LOCATION 'memory:///events' {options}"
+                ),
+            )
+            .await
+            .unwrap();
+            assert_eq!(
+                query_rows(
+                    &service,
+                    &mut client,
+                    &format!("SELECT concat_ws(' ', id, name, day) FROM {table} ORDER BY id"),
+                )
+                .await,
+                rows,
+                "{table}"
+            );
+        }
+
+        assert!(SimpleQueryHandler::do_query(
+            &service,
+            &mut client,
+            "CREATE EXTERNAL TABLE events_v9 STORED AS DELTA LOCATION 'memory:///events' \
+             OPTIONS ('version' '9')",
+        )
+        .await
+        .is_err());
+    }
+
+    #[cfg(feature = "iceberg")]
+    #[tokio::test]
+    async fn test_iceberg_tables() {
+        use apache_avro::types::Value as AvroValue;
+        use object_store::ObjectStore;
+        use serde_json::json;
+
+        let session_context = Arc::new(SessionContext::new());
+        let store = Arc::new(object_store::memory::InMemory::new());
+        session_context
+            .register_object_store(&url::Url::parse("memory://").unwrap(), store.clone());
+        let service = DfSessionService::new(session_context, Arc::new(AuthManager::new()));
+        let mut client = MockClient::new();
+        client
+            .metadata
+            .insert(METADATA_USER.to_string(), "postgres".to_string());
+
+        let avro = |schema: &str, records: Vec<Vec<(&str, AvroValue)>>| {
+            let schema = apache_avro::Schema::parse_str(schema).unwrap();
+            let mut writer = apache_avro::Writer::new(&schema, Vec::new());
+            for record in records {
+                writer
+                    .append(AvroValue::Record(
+                        record
+                            .into_iter()
+                            .map(|(name, value)| (name.to_string(), value))
+                            .collect(),
+                    ))
+                    .unwrap();
+            }
+            writer.into_inner().unwrap()
+        };
+        let manifest_schema = r#"{"type": "record", "name": "manifest_entry", "fields": [
+            {"name": "status", "type": "int"},
+            {"name": "data_file", "type": {"type": "record", "name": "r2", "fields": [
+                {"name": "content", "type": "int"},
+                {"name": "file_path", "type": "string"},
+                {"name": "file_format", "type": "string"},
+                {"name": "record_count", "type": "long"},
+                {"name": "file_size_in_bytes", "type": "long"}
+            ]}}
+        ]}"#;
+        let manifest_list_schema = r#"{"type": "record", "name": "manifest_file", "fields": [
+            {"name": "manifest_path", "type": "string"},
+            {"name": "manifest_length", "type": "long"},
+            {"name": "content", "type": "int"},
+            {"name": "added_snapshot_id", "type": "long"}
+        ]}"#;
+
+        // the first snapshot adds a file, the second replaces it by another
+        let mut data_files = vec![];
+        for id in [1, 2] {
+            let path = format!("orders/data/{id:05}.parquet");
+            SimpleQueryHandler::do_query(
+                &service,
+                &mut client,
+                &format!(
+                    "COPY (SELECT {id} AS id, 'item {id}' AS item) \
+                     TO 'memory:///{path}' (FORMAT parquet)"
+                ),
+            )
+            .await
+            .unwrap();
+            let size = store.head(&path.as_str().into()).await.unwrap().size;
+            data_files.push(AvroValue::Record(vec![
+                ("content".to_string(), AvroValue::Int(0)),
+                (
+                    "file_path".to_string(),
+                    AvroValue::String(format!("memory:///{path}")),
+                ),
+                (
+                    "file_format".to_string(),
+                    AvroValue::String("PARQUET".into()),
+                ),
+                ("record_count".to_string(), AvroValue::Long(1)),
+                (
+                    "file_size_in_bytes".to_string(),
+                    AvroValue::Long(size as i64),
+                ),
+            ]));
+        }
+        let manifests = [
+            vec![vec![
+                ("status", AvroValue::Int(1)),
+                ("data_file", data_files[0].clone()),
+            ]],
+            vec![
+                vec![
+                    ("status", AvroValue::Int(2)),
+                    ("data_file", data_files[0].clone()),
+                ],
+                vec![
+                    ("status", AvroValue::Int(1)),
+                    ("data_file", data_files[1].clone()),
+                ],
+            ],
+        ];
+        let mut snapshots = vec![];
+        for (snapshot_id, entries) in (1..).zip(manifests) {
+            let manifest = avro(manifest_schema, entries);
+            let manifest_path = format!("orders/metadata/manifest-{snapshot_id}.avro");
+            let manifest_list = avro(
+                manifest_list_schema,
+                vec![vec![
+                    (
+                        "manifest_path",
+                        AvroValue::String(format!("memory:///{manifest_path}")),
+                    ),
+                    ("manifest_length", AvroValue::Long(manifest.len() as i64)),
+                    ("content", AvroValue::Int(0)),
+                    ("added_snapshot_id", AvroValue::Long(snapshot_id)),
+                ]],
+            );
+            store
+                .put(&manifest_path.into(), manifest.into())
+                .await
+                .unwrap();
+            let manifest_list_path = format!("orders/metadata/snap-{snapshot_id}.avro");
+            store
+                .put(&manifest_list_path.as_str().into(), manifest_list.into())
+                .await
+                .unwrap();
+            snapshots.push(json!({
+                "snapshot-id": snapshot_id,
+                "timestamp-ms": 0,
+                "manifest-list": format!("memory:///{manifest_list_path}"),
+                "schema-id": 0,
+            }));
+            let metadata = json!({
+                "format-version": 2,
+                "location": "memory:///orders",
+                "current-snapshot-id": snapshot_id,
+                "current-schema-id": 0,
+                "schemas": [{
+                    "type": "struct",
+                    "schema-id": 0,
+                    "fields": [
+                        {"id": 1, "name": "id", "required": false, "type": "long"},
+                        {"id": 2, "name": "item", "required": false, "type": "string"},
+                    ],
+                }],
+                "snapshots": snapshots,
+            });
+            store
+                .put(
+                    &format!("orders/metadata/v{snapshot_id}.metadata.json").into(),
+                    metadata.to_string().into(),
+                )
+                .await
+                .unwrap();
+        }
+
+        for (table, options, rows) in [
+            ("orders_1", "OPTIONS ('snapshot-id' '1')", ["1 item 1"]),
+            ("orders", "", ["2 item 2"]),
+        ] {
+            SimpleQueryHandler::do_query(
+                &service,
+                &mut client,
+                &format!(
+                    "CREATE EXTERNAL TABLE {table} STORED AS ICEBERG \
+                     LOCATION 'memory:///orders' {options}"
+                ),
+            )
+            .await
+            .unwrap();
+            assert_eq!(
+                query_rows(
+                    &service,
+                    &mut client,
+                    &format!("SELECT concat_ws(' ', id, item) FROM {table}"),
+                )
+                .await,
+                rows,
+                "{table}"
+            );
+        }
+
+        assert!(SimpleQueryHandler::do_query(
+            &service,
+            &mut client,
+            "CREATE EXTERNAL TABLE orders_9 STORED AS ICEBERG LOCATION 'memory:///orders' \
+             OPTIONS ('snapshot-id' '9')",
+        )
+        .await
+        .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_statement_rules_of_unparsed_statements() {
+        use crate::policy::StatementRule;
+
+        let auth_manager = Arc::new(AuthManager::new());
+        auth_manager
+            .add_user(crate::auth::User {
+                username: "analyst".to_string(),
+                password_hash: String::new(),
+                roles: vec!["analyst".to_string()],
+                is_superuser: false,
+                can_login: true,
+                connection_limit: None,
+            })
+            .await
+            .unwrap();
+        for rule in [
+            StatementRule::deny("analyst").with_kind(StatementKind::CreateTable),
+            StatementRule::deny("analyst").with_kind(StatementKind::Alter),
+            StatementRule::deny("analyst")
+                .with_pattern("(?i)^analyze")
+                .unwrap(),
+        ] {
+            auth_manager.add_statement_rule(rule).await;
+        }
+        let service = DfSessionService::new(Arc::new(SessionContext::new()), auth_manager);
+        let mut client = MockClient::new();
+        client
+            .metadata
+            .insert(METADATA_USER.to_string(), "analyst".to_string());
+
+        for sql in [
+            "CREATE EXTERNAL TABLE events STORED AS CSV LOCATION '/tmp/events.csv'",
+            "ALTER DATABASE datafusion SET statement_timeout = '5s'",
+            "ALTER TABLE events REFRESH SCHEMA",
+            "analyze events;",
+        ] {
+            let Err(PgWireError::UserError(info)) =
+                SimpleQueryHandler::do_query(&service, &mut client, sql).await
+            else {
+                panic!("expected {sql} to be denied");
+            };
+            assert_eq!(info.code, "42501", "{sql}");
+            assert!(
+                info.message.starts_with("statement denied by policy"),
+                "{sql}"
+            );
+        }
+        // statements matching no rule are allowed
+        SimpleQueryHandler::do_query(&service, &mut client, "VACUUM")
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_storage_credentials() {
+        let session_context = Arc::new(SessionContext::new());
+        let service = DfSessionService::new(session_context.clone(), Arc::new(AuthManager::new()));
+        let mut client = MockClient::new();
+        client
+            .metadata
+            .insert(METADATA_USER.to_string(), "postgres".to_string());
+
+        SimpleQueryHandler::do_query(
+            &service,
+            &mut client,
+            "CREATE STORAGE CREDENTIAL scratch LOCATION 'memory:///'",
+        )
+        .await
+        .unwrap();
+        // external tables are read from the registered store
+        let url = datafusion::execution::object_store::ObjectStoreUrl::parse("memory://").unwrap();
+        session_context
+            .runtime_env()
+            .object_store(&url)
+            .unwrap()
+            .put(
+                &object_store::path::Path::from("cities.csv"),
+                "name\nlagos\noslo\n".into(),
+            )
+            .await
+            .unwrap();
+        SimpleQueryHandler::do_query(
+            &service,
+            &mut client,
+            "CREATE EXTERNAL TABLE cities STORED AS CSV LOCATION 'memory:///cities.csv' \
+             OPTIONS ('format.has_header' 'true')",
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            query_rows(
+                &service,
+                &mut client,
+                "SELECT name FROM cities ORDER BY name"
+            )
+            .await,
+            ["lagos", "oslo"]
+        );
+
+        let Err(PgWireError::UserError(info)) = SimpleQueryHandler::do_query(
+            &service,
+            &mut client,
+            "CREATE STORAGE CREDENTIAL lake LOCATION 'hdfs://lake'",
+        )
+        .await
+        else {
+            panic!("expected an unsupported location");
+        };
+        assert_eq!(info.code, "0A000");
+        SimpleQueryHandler::do_query(&service, &mut client, "DROP STORAGE CREDENTIAL scratch")
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_refresh_table_schema() {
+        use object_store::ObjectStore;
+
+        let session_context = Arc::new(SessionContext::new());
+        crate::pg_catalog::setup_pg_catalog(&session_context, "datafusion").unwrap();
+        let service = DfSessionService::new(session_context.clone(), Arc::new(AuthManager::new()));
+        let mut client = MockClient::new();
+        client
+            .metadata
+            .insert(METADATA_USER.to_string(), "postgres".to_string());
+
+        let store = Arc::new(object_store::memory::InMemory::new());
+        session_context
+            .register_object_store(&url::Url::parse("memory://").unwrap(), store.clone());
+        let path = object_store::path::Path::from("events/1.csv");
+        store.put(&path, "id\n1\n".into()).await.unwrap();
+        SimpleQueryHandler::do_query(
+            &service,
+            &mut client,
+            "CREATE EXTERNAL TABLE events STORED AS CSV LOCATION 'memory:///events/' \
+             OPTIONS ('format.has_header' 'true')",
+        )
+        .await
+        .unwrap();
+
+        // a column is added to the files of the table
+        store.put(&path, "id,kind\n1,click\n".into()).await.unwrap();
+        assert!(
+            SimpleQueryHandler::do_query(&service, &mut client, "SELECT kind FROM events")
+                .await
+                .is_err()
+        );
+        SimpleQueryHandler::do_query(&service, &mut client, "ALTER TABLE events REFRESH SCHEMA")
+            .await
+            .unwrap();
+        assert_eq!(
+            query_rows(&service, &mut client, "SELECT kind FROM events").await,
+            ["click"]
+        );
+
+        store
+            .put(&path, "id,kind,page\n1,click,home\n".into())
+            .await
+            .unwrap();
+        assert_eq!(
+            query_rows(
+                &service,
+                &mut client,
+                "SELECT pg_catalog.refresh_table_schema('public.events')"
+            )
+            .await,
+            ["t"]
+        );
+        assert_eq!(
+            query_rows(&service, &mut client, "SELECT page FROM events").await,
+            ["home"]
+        );
+
+        SimpleQueryHandler::do_query(&service, &mut client, "CREATE TABLE plain (id INT)")
+            .await
+            .unwrap();
+        let Err(PgWireError::UserError(info)) =
+            SimpleQueryHandler::do_query(&service, &mut client, "ALTER TABLE plain REFRESH SCHEMA")
+                .await
+        else {
+            panic!("expected a table of files");
+        };
+        assert_eq!(info.code, "42809");
+    }
+
+    #[tokio::test]
+    async fn test_result_cache() {
+        use crate::result_cache::ResultCache;
+
+        let session_context = Arc::new(SessionContext::new());
+        let result_cache = Arc::new(ResultCache::new(1024 * 1024, Duration::from_secs(60)));
+        let service = DfSessionService::new(session_context, Arc::new(AuthManager::new()))
+            .with_result_cache(result_cache.clone());
+        let mut client = MockClient::new();
+        client
+            .metadata
+            .insert(METADATA_USER.to_string(), "postgres".to_string());
+
+        SimpleQueryHandler::do_query(&service, &mut client, "CREATE TABLE hits (page TEXT)")
+            .await
+            .unwrap();
+        SimpleQueryHandler::do_query(&service, &mut client, "INSERT INTO hits VALUES ('home')")
+            .await
+            .unwrap();
+        for _ in 0..2 {
+            assert_eq!(
+                query_rows(&service, &mut client, "SELECT page FROM hits").await,
+                ["home"]
+            );
+        }
+        assert_eq!(result_cache.len(), 1);
+
+        // DML clears the cache
+        SimpleQueryHandler::do_query(&service, &mut client, "INSERT INTO hits VALUES ('docs')")
+            .await
+            .unwrap();
+        assert!(result_cache.is_empty());
+        assert_eq!(
+            query_rows(&service, &mut client, "SELECT page FROM hits ORDER BY page").await,
+            ["docs", "home"]
+        );
+        // and so does DDL
+        SimpleQueryHandler::do_query(&service, &mut client, "DROP TABLE hits")
+            .await
+            .unwrap();
+        assert!(result_cache.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_copy_to_file() {
+        let session_context = Arc::new(SessionContext::new());
+        session_context.register_object_store(
+            &url::Url::parse("memory://").unwrap(),
+            Arc::new(object_store::memory::InMemory::new()),
+        );
+        let auth_manager = Arc::new(AuthManager::new());
+        auth_manager
+            .add_user(crate::auth::User {
+                username: "viewer".to_string(),
+                password_hash: String::new(),
+                roles: Vec::new(),
+                is_superuser: false,
+                can_login: true,
+                connection_limit: None,
+            })
+            .await
+            .unwrap();
+        let service = DfSessionService::new(session_context, auth_manager);
+        let mut client = MockClient::new();
+        client
+            .metadata
+            .insert(METADATA_USER.to_string(), "postgres".to_string());
+
+        SimpleQueryHandler::do_query(
+            &service,
+            &mut client,
+            "CREATE TABLE hits AS VALUES ('home', 3), ('docs', 1)",
+        )
+        .await
+        .unwrap();
+        for (copy, format) in [
+            (
+                "COPY (SELECT column1 AS page FROM hits WHERE column2 > 2) \
+                 TO 'memory:///exports/hits.json' (FORMAT json)",
+                "JSON",
+            ),
+            (
+                "COPY hits (column1) TO 'memory:///exports/hits.csv' (FORMAT csv, HEADER)",
+                "CSV OPTIONS ('format.has_header' 'true')",
+            ),
+        ] {
+            let mut responses = SimpleQueryHandler::do_query(&service, &mut client, copy)
+                .await
+                .unwrap();
+            assert!(matches!(responses.remove(0), Response::Execution(_)));
+            let location = copy.split('\'').nth(1).unwrap();
+            let table = if format == "JSON" {
+                "exported"
+            } else {
+                "exported_csv"
+            };
+            SimpleQueryHandler::do_query(
+                &service,
+                &mut client,
+                &format!("CREATE EXTERNAL TABLE {table} STORED AS {format} LOCATION '{location}'"),
+            )
+            .await
+            .unwrap();
+        }
+        assert_eq!(
+            query_rows(&service, &mut client, "SELECT page FROM exported").await,
+            ["home"]
+        );
+        assert_eq!(
+            query_rows(
+                &service,
+                &mut client,
+                "SELECT column1 FROM exported_csv ORDER BY column1"
+            )
+            .await,
+            ["docs", "home"]
+        );
+
+        let Err(PgWireError::UserError(info)) = SimpleQueryHandler::do_query(
+            &service,
+            &mut client,
+            "COPY hits TO '/tmp/hits.txt' (FORMAT text)",
+        )
+        .await
+        else {
+            panic!("expected an unsupported format");
+        };
+        assert_eq!(info.code, "0A000");
+
+        // writing files of the server takes privileges
+        let mut viewer = MockClient::new();
+        viewer
+            .metadata
+            .insert(METADATA_USER.to_string(), "viewer".to_string());
+        let Err(PgWireError::UserError(info)) = SimpleQueryHandler::do_query(
+            &service,
+            &mut viewer,
+            "COPY hits TO 'memory:///exports/stolen.csv'",
+        )
+        .await
+        else {
+            panic!("expected a permission error");
+        };
+        assert_eq!(info.code, "42501");
+
+        // local files only under the export root of the server
+        let Err(PgWireError::UserError(info)) =
+            SimpleQueryHandler::do_query(&service, &mut client, "COPY hits TO '/tmp/hits.csv'")
+                .await
+        else {
+            panic!("expected a permission error");
+        };
+        assert_eq!(info.code, "42501");
+        let export_root = std::env::temp_dir().join(format!(
+            "datafusion-postgres-exports-{}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&export_root).unwrap();
+        let service = service.with_copy_export_root(&export_root);
+        let export = export_root.join("hits.csv");
+        SimpleQueryHandler::do_query(
+            &service,
+            &mut client,
+            &format!("COPY hits TO '{}'", export.display()),
+        )
+        .await
+        .unwrap();
+        assert!(export.exists());
+        std::fs::remove_dir_all(&export_root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_partitioned_writes() {
+        use object_store::ObjectStore;
+
+        let session_context = Arc::new(SessionContext::new());
+        let store = Arc::new(object_store::memory::InMemory::new());
+        session_context
+            .register_object_store(&url::Url::parse("memory://").unwrap(), store.clone());
+        let auth_manager = Arc::new(AuthManager::new());
+        let service = DfSessionService::new(session_context, auth_manager.clone());
+        let mut client = MockClient::new();
+        client
+            .metadata
+            .insert(METADATA_USER.to_string(), "postgres".to_string());
+
+        for sql in [
+            "CREATE EXTERNAL TABLE events (kind VARCHAR, n INT, day VARCHAR) STORED AS JSON \
+             PARTITIONED BY (day) LOCATION 'memory:///events/'",
+            "INSERT INTO events VALUES ('click', 1, '2024-06-01'), ('view', 2, '2024-06-02')",
+            "COPY (SELECT 'click' AS kind, 3 AS n, '2024-06-03' AS day) TO 'memory:///events/' \
+             PARTITIONED BY (day) (FORMAT json)",
+        ] {
+            SimpleQueryHandler::do_query(&service, &mut client, sql)
+                .await
+                .unwrap();
+        }
+
+        let listing = store
+            .list_with_delimiter(Some(&"events".into()))
+            .await
+            .unwrap();
+        let mut partitions = listing
+            .common_prefixes
+            .iter()
+            .map(|prefix| prefix.to_string())
+            .collect::<Vec<_>>();
+        partitions.sort();
+        assert_eq!(
+            partitions,
+            [
+                "events/day=2024-06-01",
+                "events/day=2024-06-02",
+                "events/day=2024-06-03"
+            ]
+        );
+        assert_eq!(
+            query_rows(
+                &service,
+                &mut client,
+                "SELECT day FROM events WHERE kind = 'click' ORDER BY day"
+            )
+            .await,
+            ["2024-06-01", "2024-06-03"]
+        );
+
+        let Err(PgWireError::UserError(info)) = SimpleQueryHandler::do_query(
+            &service,
+            &mut client,
+            "COPY events FROM '/tmp/events/' PARTITIONED BY (day)",
+        )
+        .await
+        else {
+            panic!("expected an unsupported statement");
+        };
+        assert_eq!(info.code, "0A000");
+        // the row filters of the session apply to the partitions written
+        auth_manager
+            .create_role(crate::auth::RoleConfig {
+                name: "writer".to_string(),
+                is_superuser: false,
+                can_login: true,
+                can_create_db: false,
+                can_create_role: false,
+                can_create_user: false,
+                can_replication: false,
+            })
+            .await
+            .unwrap();
+        auth_manager
+            .add_user(crate::auth::User {
+                username: "writer".to_string(),
+                password_hash: String::new(),
+                roles: vec!["writer".to_string(), "pg_write_server_files".to_string()],
+                is_superuser: false,
+                can_login: true,
+                connection_limit: None,
+            })
+            .await
+            .unwrap();
+        auth_manager
+            .grant_permission(
+                "writer",
+                Permission::Select,
+                ResourceType::All,
+                "postgres",
+                false,
+            )
+            .await
+            .unwrap();
+        auth_manager
+            .add_row_filter_policy(
+                "events",
+                Arc::new(
+                    crate::policy::RoleRowFilter::new()
+                        .with_role_predicate("writer", col("kind").eq(lit("view"))),
+                ),
+            )
+            .await;
+        let mut writer = MockClient::new();
+        writer
+            .metadata
+            .insert(METADATA_USER.to_string(), "writer".to_string());
+        SimpleQueryHandler::do_query(
+            &service,
+            &mut writer,
+            "COPY (SELECT * FROM events) TO 'memory:///filtered/' PARTITIONED BY (day) \
+             (FORMAT json)",
+        )
+        .await
+        .unwrap();
+        let listing = store
+            .list_with_delimiter(Some(&"filtered".into()))
+            .await
+            .unwrap();
+        let partitions = listing
+            .common_prefixes
+            .iter()
+            .map(|prefix| prefix.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(partitions, ["filtered/day=2024-06-02"]);
+    }
+
+    #[tokio::test]
+    async fn test_copy_stdin_stdout() {
+        let service = Arc::new(DfSessionService::new(
+            Arc::new(SessionContext::new()),
+            Arc::new(AuthManager::new()),
+        ));
+        let copy_handler = DfCopyHandler::new(service.clone());
+        let mut client = MockClient::new();
+        client
+            .metadata
+            .insert(METADATA_USER.to_string(), "postgres".to_string());
+        SimpleQueryHandler::do_query(
+            service.as_ref(),
+            &mut client,
+            "CREATE TABLE events (id INT, kind VARCHAR, note VARCHAR)",
+        )
+        .await
+        .unwrap();
+
+        let mut responses = SimpleQueryHandler::do_query(
+            service.as_ref(),
+            &mut client,
+            "COPY events (id, kind, note) FROM STDIN (FORMAT csv, NULL 'NA', FORCE_NOT_NULL (kind))",
+        )
+        .await
+        .unwrap();
+        let Response::CopyIn(copy) = responses.remove(0) else {
+            panic!("expected COPY FROM STDIN to start");
+        };
+        assert_eq!(copy.columns, 3);
+        for data in ["1,click,\n2,NA,\"NA\"\n", "3,\"a,b\",NA\n"] {
+            copy_handler
+                .on_copy_data(&mut client, CopyData::new(data.as_bytes().to_vec().into()))
+                .await
+                .unwrap();
+        }
+        copy_handler
+            .on_copy_done(&mut client, CopyDone::new())
+            .await
+            .unwrap();
+        let Some(PgWireBackendMessage::CommandComplete(complete)) = client.sent.pop() else {
+            panic!("expected the copy to complete");
+        };
+        assert_eq!(complete.tag, "COPY 3");
+        assert_eq!(
+            query_rows(
+                service.as_ref(),
+                &mut client,
+                "SELECT concat(kind, '/', coalesce(note, 'null')) FROM events ORDER BY id"
+            )
+            .await,
+            ["click/", "NA/NA", "a,b/null"]
+        );
+
+        for (copy, lines) in [
+            (
+                "COPY (SELECT * FROM events ORDER BY id) TO STDOUT \
+                 (FORMAT csv, HEADER, FORCE_QUOTE (kind))",
+                vec![
+                    "id,kind,note\n",
+                    "1,\"click\",\"\"\n",
+                    "2,\"NA\",NA\n",
+                    "3,\"a,b\",\n",
+                ],
+            ),
+            (
+                "COPY (SELECT id, note FROM events ORDER BY id) TO STDOUT WITH DELIMITER '|'",
+                vec!["1|\n", "2|NA\n", "3|\\N\n"],
+            ),
+        ] {
+            client.sent.clear();
+            let mut responses = SimpleQueryHandler::do_query(service.as_ref(), &mut client, copy)
+                .await
+                .unwrap();
+            let Response::Execution(tag) = responses.remove(0) else {
+                panic!("expected the copied rows to be counted");
+            };
+            assert_eq!(
+                pgwire::messages::response::CommandComplete::from(tag).tag,
+                "COPY 3"
+            );
+            assert!(matches!(
+                client.sent.first(),
+                Some(PgWireBackendMessage::CopyOutResponse(_))
+            ));
+            assert!(matches!(
+                client.sent.last(),
+                Some(PgWireBackendMessage::CopyDone(_))
+            ));
+            let data = client
+                .sent
+                .iter()
+                .filter_map(|message| match message {
+                    PgWireBackendMessage::CopyData(data) => {
+                        Some(String::from_utf8(data.data.to_vec()).unwrap())
+                    }
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(data, lines);
+        }
+
+        // errors in the data are located by their line
+        SimpleQueryHandler::do_query(service.as_ref(), &mut client, "COPY events FROM STDIN")
+            .await
+            .unwrap();
+        copy_handler
+            .on_copy_data(&mut client, CopyData::new("4\tx\t\\N\n5\ty\n".into()))
+            .await
+            .unwrap();
+        let Err(PgWireError::UserError(info)) = copy_handler
+            .on_copy_done(&mut client, CopyDone::new())
+            .await
+        else {
+            panic!("expected missing data");
+        };
+        assert_eq!(info.code, "22P04");
+        assert_eq!(info.where_context.as_deref(), Some("COPY events, line 2"));
 
-        let (_, plan) = &portal.statement.statement;
+        let Err(PgWireError::UserError(info)) = copy_handler
+            .on_copy_data(&mut client, CopyData::new("6\tz\t\\N\n".into()))
+            .await
+        else {
+            panic!("expected no copy in progress");
+        };
+        assert_eq!(info.code, "08P01");
 
-        let param_types = plan
-            .get_parameter_types()
-            .map_err(|e| PgWireError::ApiError(Box::new(e)))?;
+        for (copy, code) in [
+            (
+                "COPY events FROM STDIN (FORMAT csv, FORCE_QUOTE *)",
+                "0A000",
+            ),
+            (
+                "COPY events TO STDOUT (FORMAT csv, FORCE_QUOTE (missing))",
+                "42P10",
+            ),
+            ("COPY events (missing) FROM STDIN", "42703"),
+        ] {
+            let Err(PgWireError::UserError(info)) =
+                SimpleQueryHandler::do_query(service.as_ref(), &mut client, copy).await
+            else {
+                panic!("expected {copy} to fail");
+            };
+            assert_eq!(info.code, code, "{copy}");
+        }
+    }
 
-        let param_values = df::deserialize_parameters(portal, &ordered_param_types(&param_types))?; // Fixed: Use &param_types
+    #[tokio::test]
+    async fn test_insert_returning() {
+        let service = DfSessionService::new(
+            Arc::new(SessionContext::new()),
+            Arc::new(AuthManager::new()),
+        );
+        let mut client = MockClient::new();
+        client
+            .metadata
+            .insert(METADATA_USER.to_string(), "postgres".to_string());
+        SimpleQueryHandler::do_query(
+            &service,
+            &mut client,
+            "CREATE TABLE users (id INT, name VARCHAR)",
+        )
+        .await
+        .unwrap();
 
-        let plan = plan
-            .clone()
-            .replace_params_with_values(&param_values)
-            .map_err(|e| PgWireError::ApiError(Box::new(e)))?; // Fixed: Use
-                                                               // &param_values
-        let optimised = self
-            .session_context
-            .state()
-            .optimize(&plan)
-            .map_err(|e| PgWireError::ApiError(Box::new(e)))?;
+        let mut responses = SimpleQueryHandler::do_query(
+            &service,
+            &mut client,
+            "INSERT INTO users VALUES (1, 'ada'), (2, 'grace') RETURNING id + 100 AS key",
+        )
+        .await
+        .unwrap();
+        let Response::Query(resp) = responses.remove(0) else {
+            panic!("expected the returned rows");
+        };
+        assert_eq!(resp.command_tag(), "INSERT 0");
+        assert_eq!(resp.row_schema()[0].name(), "key");
+        let rows = resp
+            .data_rows()
+            .map(|row| first_column_text(&row.unwrap()))
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(rows, ["101", "102"]);
 
-        let dataframe = {
-            let timeout = Self::get_statement_timeout(client);
-            if let Some(timeout_duration) = timeout {
-                tokio::time::timeout(
-                    timeout_duration,
-                    self.session_context.execute_logical_plan(optimised),
-                )
-                .await
-                .map_err(|_| {
-                    PgWireError::UserError(Box::new(pgwire::error::ErrorInfo::new(
-                        "ERROR".to_string(),
-                        "57014".to_string(), // query_canceled error code
-                        "canceling statement due to statement timeout".to_string(),
-                    )))
-                })?
-                .map_err(|e| PgWireError::ApiError(Box::new(e)))?
-            } else {
-                self.session_context
-                    .execute_logical_plan(optimised)
-                    .await
-                    .map_err(|e| PgWireError::ApiError(Box::new(e)))?
-            }
+        // the extended protocol describes the returned rows when parsing,
+        // and inserts when executing
+        let statement = service
+            .query_parser()
+            .parse_sql(
+                &client,
+                "INSERT INTO users VALUES ($1, 'edsger') RETURNING name",
+                &[],
+            )
+            .await
+            .unwrap();
+        assert_eq!(statement.1.schema().field(0).name(), "name");
+        let statement = Arc::new(StoredStatement::new(String::new(), statement, vec![]));
+        let bind = Bind::new(
+            None,
+            None,
+            vec![],
+            vec![Some(bytes::Bytes::from_static(b"3"))],
+            vec![],
+        );
+        let portal = Portal::try_new(&bind, statement).unwrap();
+        let Response::Query(resp) = service.run_portal(&mut client, &portal).await.unwrap() else {
+            panic!("expected the returned rows");
         };
-        let resp = df::encode_dataframe(dataframe, &portal.result_column_format).await?;
-        Ok(Response::Query(resp))
+        assert_eq!(resp.command_tag(), "INSERT 0");
+        let rows = resp
+            .data_rows()
+            .map(|row| first_column_text(&row.unwrap()))
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(rows, ["edsger"]);
+
+        assert_eq!(
+            query_rows(&service, &mut client, "SELECT name FROM users ORDER BY id").await,
+            ["ada", "grace", "edsger"]
+        );
     }
-}
 
-pub struct Parser {
-    session_context: Arc<SessionContext>,
-    sql_rewrite_rules: Vec<Arc<dyn SqlStatementRewriteRule>>,
-}
+    #[tokio::test]
+    async fn test_insert_on_conflict() {
+        let service = DfSessionService::new(
+            Arc::new(SessionContext::new()),
+            Arc::new(AuthManager::new()),
+        );
+        let mut client = MockClient::new();
+        client
+            .metadata
+            .insert(METADATA_USER.to_string(), "postgres".to_string());
+        for sql in [
+            "CREATE TABLE users (id INT PRIMARY KEY, name VARCHAR, email VARCHAR)",
+            "INSERT INTO users VALUES (1, 'ada', 'ada@example.com')",
+            // the second row conflicts with the first one of the statement
+            "INSERT INTO users VALUES (1, 'ada', 'ada@example.org'), (2, 'grace', 'grace@example.com'), \
+             (2, 'hopper', 'hopper@example.com') ON CONFLICT DO NOTHING",
+            "INSERT INTO users VALUES (3, 'edsger', 'ada@example.com') ON CONFLICT (email) DO NOTHING",
+        ] {
+            SimpleQueryHandler::do_query(&service, &mut client, sql)
+                .await
+                .unwrap();
+        }
+        assert_eq!(
+            query_rows(&service, &mut client, "SELECT name FROM users ORDER BY id").await,
+            ["ada", "grace"]
+        );
+        assert_eq!(
+            query_rows(
+                &service,
+                &mut client,
+                "INSERT INTO users VALUES (2, 'grace', NULL), (4, 'barbara', NULL) \
+                 ON CONFLICT (id) DO NOTHING RETURNING name"
+            )
+            .await,
+            ["barbara"]
+        );
 
-#[async_trait]
-impl QueryParser for Parser {
-    type Statement = (String, LogicalPlan);
+        client.sent.clear();
+        let Err(PgWireError::UserError(info)) = SimpleQueryHandler::do_query(
+            &service,
+            &mut client,
+            "INSERT INTO users VALUES (1, 'ada', NULL) ON CONFLICT (id) DO UPDATE SET name = 'ada'",
+        )
+        .await
+        else {
+            panic!("expected an unsupported clause");
+        };
+        assert_eq!(info.code, "0A000");
+        assert_eq!(
+            info.message,
+            "INSERT ... ON CONFLICT DO UPDATE is not supported"
+        );
+        assert!(matches!(
+            client.sent.as_slice(),
+            [PgWireBackendMessage::NoticeResponse(_)]
+        ));
+    }
 
-    async fn parse_sql<C>(
-        &self,
-        _client: &C,
-        sql: &str,
-        _types: &[Type],
-    ) -> PgWireResult<Self::Statement> {
-        log::debug!("Received parse extended query: {sql}"); // Log for debugging
+    #[tokio::test]
+    async fn test_temporal_casts() {
+        let service = DfSessionService::new(
+            Arc::new(SessionContext::new()),
+            Arc::new(AuthManager::new()),
+        );
+        let mut client = MockClient::new();
+        client
+            .metadata
+            .insert(METADATA_USER.to_string(), "postgres".to_string());
 
-        // Check for transaction commands that shouldn't be parsed by DataFusion
-        let sql_lower = sql.to_lowercase();
-        let sql_trimmed = sql_lower.trim();
-        if matches!(
-            sql_trimmed,
-            "begin"
-                | "begin transaction"
-                | "begin work"
-                | "start transaction"
-                | "commit"
-                | "commit transaction"
-                | "commit work"
-                | "end"
-                | "end transaction"
-                | "rollback"
-                | "rollback transaction"
-                | "rollback work"
-                | "abort"
-        ) {
-            // Return a dummy plan for transaction commands - they'll be handled by transaction handler
-            let dummy_schema = datafusion::common::DFSchema::empty();
-            let dummy_plan = datafusion::logical_expr::LogicalPlan::EmptyRelation(
-                datafusion::logical_expr::EmptyRelation {
-                    produce_one_row: false,
-                    schema: std::sync::Arc::new(dummy_schema),
-                },
-            );
-            return Ok((sql.to_string(), dummy_plan));
+        for (sql, expected) in [
+            ("SELECT '05/01/2024'::date", "2024-05-01"),
+            ("SELECT 'epoch'::date", "1970-01-01"),
+            (
+                "SELECT CAST('2024-01-05 10:00:00+02' AS timestamptz)",
+                "2024-01-05 08:00:00.000000+00",
+            ),
+            (
+                "SELECT TIMESTAMP 'Jan 5, 2024 10:00 PM'",
+                "2024-01-05 22:00:00.000000",
+            ),
+        ] {
+            assert_eq!(query_rows(&service, &mut client, sql).await, [expected]);
         }
+        assert_eq!(
+            query_rows(
+                &service,
+                &mut client,
+                "SELECT 'today'::date = CAST(now() AS DATE)"
+            )
+            .await,
+            ["t"]
+        );
 
-        let mut statements = parse(sql).map_err(|e| PgWireError::ApiError(Box::new(e)))?;
-        let mut statement = statements.remove(0);
-
-        // Attempt to rewrite
-        statement = rewrite(statement, &self.sql_rewrite_rules);
-
-        let query = statement.to_string();
+        // the order of the fields follows DateStyle
+        SimpleQueryHandler::do_query(&service, &mut client, "SET DateStyle = 'ISO, DMY'")
+            .await
+            .unwrap();
+        assert_eq!(
+            query_rows(&service, &mut client, "SELECT '05/01/2024'::date").await,
+            ["2024-01-05"]
+        );
+        assert_eq!(
+            query_rows(&service, &mut client, "SHOW DateStyle").await,
+            ["ISO, DMY"]
+        );
 
-        let context = &self.session_context;
-        let state = context.state();
-        let logical_plan = state
-            .statement_to_plan(Statement::Statement(Box::new(statement)))
+        // values keep the part they don't name, and are reported in full
+        client.sent.clear();
+        SimpleQueryHandler::do_query(&service, &mut client, "SET datestyle TO iso")
             .await
-            .map_err(|e| PgWireError::ApiError(Box::new(e)))?;
-        Ok((query, logical_plan))
-    }
-}
+            .unwrap();
+        assert!(matches!(
+            client.sent.as_slice(),
+            [PgWireBackendMessage::ParameterStatus(status)]
+                if status.name == "DateStyle" && status.value == "ISO, DMY"
+        ));
+        SimpleQueryHandler::do_query(&service, &mut client, "SET DateStyle = German")
+            .await
+            .unwrap();
+        assert_eq!(
+            query_rows(&service, &mut client, "SHOW datestyle").await,
+            ["German, DMY"]
+        );
 
-fn ordered_param_types(types: &HashMap<String, Option<DataType>>) -> Vec<Option<&DataType>> {
-    // Datafusion stores the parameters as a map.  In our case, the keys will be
-    // `$1`, `$2` etc.  The values will be the parameter types.
-    let mut types = types.iter().collect::<Vec<_>>();
-    types.sort_by(|a, b| a.0.cmp(b.0));
-    types.into_iter().map(|pt| pt.1.as_ref()).collect()
-}
+        let err = SimpleQueryHandler::do_query(&service, &mut client, "SET DateStyle = 'ISO, SQL'")
+            .await
+            .err()
+            .unwrap();
+        let PgWireError::UserError(info) = err else {
+            panic!("expected a user error");
+        };
+        assert_eq!(info.code, "22023");
+        assert_eq!(
+            info.message,
+            "invalid value for parameter \"DateStyle\": \"ISO, SQL\""
+        );
+        assert_eq!(
+            info.detail.as_deref(),
+            Some("Conflicting \"datestyle\" specifications.")
+        );
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::auth::AuthManager;
-    use datafusion::prelude::SessionContext;
-    use std::collections::HashMap;
-    use std::time::Duration;
+        SimpleQueryHandler::do_query(&service, &mut client, "SET DateStyle TO DEFAULT")
+            .await
+            .unwrap();
+        assert_eq!(
+            query_rows(&service, &mut client, "SHOW DateStyle").await,
+            ["ISO, YMD"]
+        );
 
-    struct MockClient {
-        metadata: HashMap<String, String>,
+        // JDBC sends `DateStyle=ISO` with its startup parameters
+        let mut jdbc = MockClient::new();
+        jdbc.metadata
+            .insert(METADATA_USER.to_string(), "postgres".to_string());
+        jdbc.metadata
+            .insert(METADATA_DATESTYLE.to_string(), "ISO".to_string());
+        assert_eq!(
+            query_rows(&service, &mut jdbc, "SHOW DateStyle").await,
+            ["ISO, YMD"]
+        );
     }
 
-    impl MockClient {
-        fn new() -> Self {
-            Self {
-                metadata: HashMap::new(),
-            }
+    #[tokio::test]
+    async fn test_extract() {
+        let session_context = Arc::new(SessionContext::new());
+        crate::pg_catalog::setup_pg_catalog(&session_context, "datafusion").unwrap();
+        let service = DfSessionService::new(session_context, Arc::new(AuthManager::new()));
+        let mut client = MockClient::new();
+        client
+            .metadata
+            .insert(METADATA_USER.to_string(), "postgres".to_string());
+
+        for (sql, expected) in [
+            ("SELECT EXTRACT(DOW FROM DATE '2024-01-07')", "0"),
+            ("SELECT EXTRACT(ISODOW FROM DATE '2024-01-07')", "7"),
+            ("SELECT EXTRACT(WEEK FROM DATE '2023-01-01')", "52"),
+            ("SELECT EXTRACT(ISOYEAR FROM DATE '2023-01-01')", "2022"),
+            (
+                "SELECT EXTRACT(SECOND FROM TIMESTAMP '2024-01-07 10:00:09.25')",
+                "9.25",
+            ),
+            (
+                "SELECT EXTRACT(EPOCH FROM INTERVAL '1 day 2 hours')",
+                "93600",
+            ),
+            ("SELECT date_part('quarter', DATE '2024-08-01')", "3"),
+        ] {
+            assert_eq!(
+                query_rows(&service, &mut client, sql).await,
+                [expected],
+                "{sql}"
+            );
         }
     }
 
-    impl ClientInfo for MockClient {
-        fn socket_addr(&self) -> std::net::SocketAddr {
-            "127.0.0.1:5432".parse().unwrap()
-        }
+    #[tokio::test]
+    async fn test_start_times() {
+        let session_context = Arc::new(SessionContext::new());
+        crate::pg_catalog::setup_pg_catalog(&session_context, "datafusion").unwrap();
+        let service = DfSessionService::new(session_context, Arc::new(AuthManager::new()));
+        let mut client = MockClient::new();
+        client
+            .metadata
+            .insert(METADATA_USER.to_string(), "postgres".to_string());
 
-        fn is_secure(&self) -> bool {
-            false
-        }
+        SimpleQueryHandler::do_query(&service, &mut client, "BEGIN")
+            .await
+            .unwrap();
+        client.transaction_status = TransactionStatus::Transaction;
+        let now = query_rows(&service, &mut client, "SELECT now()").await;
+        let statement = query_rows(&service, &mut client, "SELECT statement_timestamp()").await;
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        // now() is the start of the transaction, the same for all its
+        // statements
+        assert_eq!(
+            query_rows(&service, &mut client, "SELECT current_timestamp").await,
+            now
+        );
+        assert_eq!(
+            query_rows(&service, &mut client, "SELECT transaction_timestamp()").await,
+            now
+        );
+        assert_ne!(
+            query_rows(&service, &mut client, "SELECT statement_timestamp()").await,
+            statement
+        );
+        assert_eq!(
+            query_rows(
+                &service,
+                &mut client,
+                "SELECT statement_timestamp() > now() AND clock_timestamp() >= statement_timestamp()"
+            )
+            .await,
+            ["t"]
+        );
 
-        fn protocol_version(&self) -> pgwire::messages::ProtocolVersion {
-            pgwire::messages::ProtocolVersion::PROTOCOL3_0
-        }
+        SimpleQueryHandler::do_query(&service, &mut client, "COMMIT")
+            .await
+            .unwrap();
+        client.transaction_status = TransactionStatus::Idle;
+        assert_ne!(query_rows(&service, &mut client, "SELECT now()").await, now);
+        assert_eq!(
+            query_rows(
+                &service,
+                &mut client,
+                "SELECT EXTRACT(YEAR FROM age(TIMESTAMP '2001-04-10', TIMESTAMP '1957-06-13'))"
+            )
+            .await,
+            ["43"]
+        );
+    }
 
-        fn set_protocol_version(&mut self, _version: pgwire::messages::ProtocolVersion) {}
+    #[tokio::test]
+    async fn test_maintenance_commands() {
+        let session_context = Arc::new(SessionContext::new());
+        crate::pg_catalog::setup_pg_catalog(&session_context, "datafusion").unwrap();
+        session_context
+            .sql("CREATE TABLE events (id INT, name VARCHAR) AS VALUES (3, 'c'), (1, NULL), (2, 'a'), (NULL, 'b')")
+            .await
+            .unwrap();
+        let service = DfSessionService::new(session_context, Arc::new(AuthManager::new()));
+        let mut client = MockClient::new();
+        client
+            .metadata
+            .insert(METADATA_USER.to_string(), "postgres".to_string());
 
-        fn pid_and_secret_key(&self) -> (i32, pgwire::messages::startup::SecretKey) {
-            (0, pgwire::messages::startup::SecretKey::I32(0))
+        // VACUUM and CLUSTER only say they do nothing
+        for query in ["VACUUM", "VACUUM (VERBOSE, FULL) events", "CLUSTER events"] {
+            let resp = SimpleQueryHandler::do_query(&service, &mut client, query)
+                .await
+                .unwrap();
+            assert!(matches!(resp[0], Response::Execution(_)));
+            assert!(matches!(
+                client.sent.pop(),
+                Some(PgWireBackendMessage::NoticeResponse(_))
+            ));
         }
+        assert_eq!(
+            query_rows(
+                &service,
+                &mut client,
+                "SELECT reltuples FROM pg_catalog.pg_class WHERE relname = 'events'"
+            )
+            .await,
+            ["0"]
+        );
 
-        fn set_pid_and_secret_key(
-            &mut self,
-            _pid: i32,
-            _secret_key: pgwire::messages::startup::SecretKey,
-        ) {
-        }
+        SimpleQueryHandler::do_query(&service, &mut client, "ANALYZE events")
+            .await
+            .unwrap();
+        assert!(client.sent.is_empty());
+        assert_eq!(
+            query_rows(
+                &service,
+                &mut client,
+                "SELECT reltuples FROM pg_catalog.pg_class WHERE relname = 'events'"
+            )
+            .await,
+            ["4"]
+        );
+        assert_eq!(
+            query_rows(
+                &service,
+                &mut client,
+                "SELECT concat_ws(',', attname, null_frac, histogram_bounds) \
+                 FROM pg_catalog.pg_stats WHERE tablename = 'events' ORDER BY attname"
+            )
+            .await,
+            ["id,0.25,{1,3}", "name,0.25,{a,c}"]
+        );
 
-        fn state(&self) -> pgwire::api::PgWireConnectionState {
-            pgwire::api::PgWireConnectionState::ReadyForQuery
-        }
+        SimpleQueryHandler::do_query(&service, &mut client, "VACUUM (ANALYZE) events (id)")
+            .await
+            .unwrap();
+        assert_eq!(
+            query_rows(
+                &service,
+                &mut client,
+                "SELECT concat_ws(',', relname, n_live_tup, analyze_count, last_analyze IS NOT NULL) \
+                 FROM pg_catalog.pg_stat_user_tables"
+            )
+            .await,
+            ["events,4,2,true"]
+        );
+        assert_eq!(
+            query_rows(
+                &service,
+                &mut client,
+                "SELECT count(*) FROM pg_catalog.pg_stats WHERE tablename = 'events'"
+            )
+            .await,
+            ["1"]
+        );
 
-        fn set_state(&mut self, _new_state: pgwire::api::PgWireConnectionState) {}
+        // extended queries are answered the same way
+        assert!(service
+            .query_parser()
+            .parse_sql(&client, "ANALYZE", &[])
+            .await
+            .is_ok());
 
-        fn transaction_status(&self) -> pgwire::messages::response::TransactionStatus {
-            pgwire::messages::response::TransactionStatus::Idle
-        }
+        SimpleQueryHandler::do_query(&service, &mut client, "BEGIN")
+            .await
+            .unwrap();
+        client.transaction_status = TransactionStatus::Transaction;
+        let Err(PgWireError::UserError(info)) =
+            SimpleQueryHandler::do_query(&service, &mut client, "VACUUM events").await
+        else {
+            panic!("expected VACUUM to fail in a transaction block");
+        };
+        assert_eq!(info.code, "25001");
+    }
 
-        fn set_transaction_status(
-            &mut self,
-            _new_status: pgwire::messages::response::TransactionStatus,
-        ) {
-        }
+    #[tokio::test]
+    async fn test_interval_operators() {
+        let session_context = Arc::new(SessionContext::new());
+        crate::pg_catalog::setup_pg_catalog(&session_context, "datafusion").unwrap();
+        session_context
+            .sql("CREATE TABLE events (id INT, created_at TIMESTAMP)")
+            .await
+            .unwrap();
+        let service = DfSessionService::new(session_context, Arc::new(AuthManager::new()));
+        let mut client = MockClient::new();
+        client
+            .metadata
+            .insert(METADATA_USER.to_string(), "postgres".to_string());
 
-        fn metadata(&self) -> &HashMap<String, String> {
-            &self.metadata
+        SimpleQueryHandler::do_query(
+            &service,
+            &mut client,
+            "INSERT INTO events VALUES (1, now() - INTERVAL '3 days'), (2, now() - INTERVAL '10 days')",
+        )
+        .await
+        .unwrap();
+        for (sql, expected) in [
+            (
+                "SELECT id FROM events WHERE created_at > now() - INTERVAL '7 days'",
+                vec!["1"],
+            ),
+            (
+                "SELECT id FROM events WHERE now() - created_at > INTERVAL '1 week' ORDER BY id",
+                vec!["2"],
+            ),
+            ("SELECT INTERVAL '1 hour' * 1.5", vec!["01:30:00"]),
+            ("SELECT INTERVAL '1 month' / 2", vec!["15 days"]),
+            (
+                "SELECT TIMESTAMP '2024-01-05 10:00:00' - TIMESTAMP '2024-01-01 00:00:00'",
+                vec!["4 days 10:00:00"],
+            ),
+            ("SELECT INTERVAL '1 day' = INTERVAL '24 hours'", vec!["t"]),
+            ("SELECT DATE '2024-03-01' - DATE '2024-02-01'", vec!["29"]),
+        ] {
+            assert_eq!(
+                query_rows(&service, &mut client, sql).await,
+                expected,
+                "{sql}"
+            );
         }
+    }
 
-        fn metadata_mut(&mut self) -> &mut HashMap<String, String> {
-            &mut self.metadata
+    #[tokio::test]
+    async fn test_json_operators() {
+        let session_context = Arc::new(SessionContext::new());
+        crate::pg_catalog::setup_pg_catalog(&session_context, "datafusion").unwrap();
+        session_context
+            .sql(
+                r#"CREATE TABLE events (id INT, payload VARCHAR) AS VALUES
+                   (1, '{"user": {"name": "ann"}, "tags": ["a", "b"]}'),
+                   (2, '{"user": {"name": "bob"}, "tags": ["c"]}')"#,
+            )
+            .await
+            .unwrap();
+        let service = DfSessionService::new(session_context, Arc::new(AuthManager::new()));
+        let mut client = MockClient::new();
+        client
+            .metadata
+            .insert(METADATA_USER.to_string(), "postgres".to_string());
+
+        for (sql, expected) in [
+            (
+                "SELECT payload::jsonb -> 'user' ->> 'name' FROM events ORDER BY id",
+                vec!["ann", "bob"],
+            ),
+            (
+                "SELECT id FROM events WHERE payload #>> '{tags,0}' = 'c'",
+                vec!["2"],
+            ),
+            (
+                "SELECT jsonb_array_elements_text(payload -> 'tags') FROM events ORDER BY 1",
+                vec!["a", "b", "c"],
+            ),
+            (
+                "SELECT jsonb_build_object('id', id) FROM events ORDER BY id",
+                vec![r#"{"id": 1}"#, r#"{"id": 2}"#],
+            ),
+            (
+                r#"SELECT key FROM jsonb_each('{"b": 1, "a": 2}'::jsonb)"#,
+                vec!["a", "b"],
+            ),
+        ] {
+            assert_eq!(
+                query_rows(&service, &mut client, sql).await,
+                expected,
+                "{sql}"
+            );
         }
+    }
 
-        fn client_certificates<'a>(&self) -> Option<&[rustls_pki_types::CertificateDer<'a>]> {
-            None
+    #[tokio::test]
+    async fn test_window_clauses() {
+        let session_context = Arc::new(SessionContext::new());
+        session_context
+            .sql("CREATE TABLE t (g INT, x INT) AS VALUES (1, 1), (1, 2), (2, 3), (2, 4)")
+            .await
+            .unwrap();
+        let service = DfSessionService::new(session_context, Arc::new(AuthManager::new()));
+        let mut client = MockClient::new();
+        client
+            .metadata
+            .insert(METADATA_USER.to_string(), "postgres".to_string());
+
+        for (sql, expected) in [
+            (
+                "SELECT count(*) FILTER (WHERE x > 1) OVER (PARTITION BY g) FROM t ORDER BY x",
+                vec!["1", "1", "2", "2"],
+            ),
+            (
+                "SELECT coalesce(sum(x) FILTER (WHERE x % 2 = 0) OVER (ORDER BY x), 0) \
+                 FROM t ORDER BY x",
+                vec!["0", "2", "2", "6"],
+            ),
+            ("SELECT sum(x) FILTER (WHERE x > 1) FROM t", vec!["9"]),
+            (
+                "SELECT sum(x) OVER (ORDER BY g GROUPS BETWEEN CURRENT ROW AND 1 FOLLOWING \
+                 EXCLUDE NO OTHERS) FROM t ORDER BY x",
+                vec!["10", "10", "7", "7"],
+            ),
+        ] {
+            assert_eq!(
+                query_rows(&service, &mut client, sql).await,
+                expected,
+                "{sql}"
+            );
         }
+
+        let sql =
+            "SELECT sum(x) OVER (ORDER BY x ROWS UNBOUNDED PRECEDING EXCLUDE CURRENT ROW) FROM t";
+        let Err(PgWireError::UserError(info)) =
+            SimpleQueryHandler::do_query(&service, &mut client, sql).await
+        else {
+            panic!("expected an error");
+        };
+        assert_eq!(info.code, "0A000");
+        assert_eq!(
+            info.message,
+            "frame exclusion EXCLUDE CURRENT ROW is not supported"
+        );
     }
 
     #[tokio::test]
-    async fn test_statement_timeout_set_and_show() {
+    async fn test_session_statistics() {
         let session_context = Arc::new(SessionContext::new());
-        let auth_manager = Arc::new(AuthManager::new());
-        let service = DfSessionService::new(session_context, auth_manager);
+        crate::pg_catalog::setup_pg_catalog(&session_context, "datafusion").unwrap();
+        let service = DfSessionService::new(session_context, Arc::new(AuthManager::new()));
         let mut client = MockClient::new();
+        client
+            .metadata
+            .insert(METADATA_USER.to_string(), "postgres".to_string());
+        let mut monitor = MockClient::new();
+        monitor.socket_addr = "127.0.0.1:5433".parse().unwrap();
+        monitor
+            .metadata
+            .insert(METADATA_USER.to_string(), "postgres".to_string());
 
-        // Test setting timeout to 5000ms
-        let set_response = service
-            .try_respond_set_statements(&mut client, "set statement_timeout '5000ms'")
+        query_rows(&service, &mut client, "SELECT 1 UNION ALL SELECT 2").await;
+        query_rows(&service, &mut client, "CREATE TABLE t (id INT)").await;
+        SimpleQueryHandler::do_query(&service, &mut client, "SELECT * FROM missing")
             .await
+            .err()
             .unwrap();
-        assert!(set_response.is_some());
 
-        // Verify the timeout was set in client metadata
-        let timeout = DfSessionService::get_statement_timeout(&client);
-        assert_eq!(timeout, Some(Duration::from_millis(5000)));
+        let rows = query_rows(
+            &service,
+            &mut monitor,
+            "SELECT concat_ws(',', select_count, ddl_count, rows_returned, error_count) \
+             FROM pg_catalog.pg_stat_activity ORDER BY pid",
+        )
+        .await;
+        assert_eq!(rows[0], "2,1,2,1");
 
-        // Test SHOW statement_timeout
-        let show_response = service
-            .try_respond_show_statements(&client, "show statement_timeout")
+        let rows = query_rows(
+            &service,
+            &mut monitor,
+            "SELECT concat_ws(',', pg_stat_get_backend_pid(s.backendid), \
+             pg_stat_get_backend_statements(s.backendid), \
+             pg_stat_get_backend_errors(s.backendid)) \
+             FROM (SELECT pg_stat_get_backend_idset AS backendid \
+             FROM pg_stat_get_backend_idset()) AS s ORDER BY 1",
+        )
+        .await;
+        assert_eq!(rows[0], "1,3,1");
+        assert_eq!(rows.len(), 2);
+
+        // the functions return void, sent as NULL
+        let rows = query_rows(&service, &mut monitor, "SELECT pg_stat_reset() IS NULL").await;
+        assert_eq!(rows[0], "t");
+        let rows = query_rows(
+            &service,
+            &mut monitor,
+            "SELECT concat_ws(',', select_count, ddl_count, rows_returned, error_count) \
+             FROM pg_catalog.pg_stat_activity ORDER BY pid",
+        )
+        .await;
+        assert_eq!(rows[0], "0,0,0,0");
+        assert!(service.activity.stats_reset().is_some());
+
+        // runs of a statement with different constants add up in
+        // pg_stat_statements
+        query_rows(&service, &mut client, "SELECT 1").await;
+        query_rows(&service, &mut client, "SELECT 2").await;
+        let query_id = service
+            .activity_registry()
+            .query_id(&client.socket_addr)
+            .unwrap();
+        let statement = format!(
+            "SELECT concat_ws(',', query, calls, rows, min_exec_time <= max_exec_time) \
+             FROM pg_catalog.pg_stat_statements WHERE queryid = {query_id}"
+        );
+        let rows = query_rows(&service, &mut monitor, &statement).await;
+        assert_eq!(rows, vec!["SELECT $1,2,2,true"]);
+
+        query_rows(
+            &service,
+            &mut monitor,
+            "SELECT pg_catalog.pg_stat_statements_reset()",
+        )
+        .await;
+        let rows = query_rows(&service, &mut monitor, &statement).await;
+        assert!(rows.is_empty());
+        let rows = query_rows(
+            &service,
+            &mut monitor,
+            "SELECT select_count FROM pg_catalog.pg_stat_activity ORDER BY pid",
+        )
+        .await;
+        assert_eq!(rows[0], "0");
+
+        query_rows(
+            &service,
+            &mut monitor,
+            "SELECT pg_stat_reset_shared('bgwriter') IS NULL",
+        )
+        .await;
+        // the target is checked while the query executes
+        let mut resp = SimpleQueryHandler::do_query(
+            &service,
+            &mut monitor,
+            "SELECT pg_stat_reset_shared('everything')",
+        )
+        .await
+        .unwrap();
+        let Response::Query(resp) = resp.remove(0) else {
+            panic!("expected rows");
+        };
+        let rows = resp.data_rows().collect::<Vec<_>>().await;
+        let Some(Err(error)) = rows.into_iter().next() else {
+            panic!("expected an unknown reset target to fail");
+        };
+        let PgWireError::UserError(info) = crate::errors::into_sqlstate_error(error) else {
+            panic!("expected a SQLSTATE");
+        };
+        assert_eq!(info.code, "22023");
+        assert_eq!(info.message, "unrecognized reset target: \"everything\"");
+    }
+
+    #[tokio::test]
+    async fn test_explain() {
+        let session_context = Arc::new(SessionContext::new());
+        session_context
+            .sql("CREATE TABLE t (id INT) AS VALUES (1), (2), (3)")
             .await
             .unwrap();
-        assert!(show_response.is_some());
+        let service = DfSessionService::new(session_context, Arc::new(AuthManager::new()));
+        let mut client = MockClient::new();
+        client
+            .metadata
+            .insert(METADATA_USER.to_string(), "postgres".to_string());
+
+        let rows = query_rows(
+            &service,
+            &mut client,
+            "EXPLAIN SELECT id FROM t WHERE id > 1",
+        )
+        .await;
+        assert_eq!(rows[1], "  ->  FilterExec: id@0 > 1  (rows=3)");
+        assert!(rows.iter().all(|row| !row.contains("actual time")));
+
+        let rows = query_rows(
+            &service,
+            &mut client,
+            "EXPLAIN (ANALYZE, COSTS OFF) SELECT id FROM t WHERE id > 1",
+        )
+        .await;
+        assert!(rows[0].contains("rows=2 loops=1"));
+        assert!(rows[rows.len() - 1].starts_with("Execution Time: "));
+
+        let err = SimpleQueryHandler::do_query(
+            &service,
+            &mut client,
+            "EXPLAIN (FORMAT XML) SELECT id FROM t",
+        )
+        .await
+        .err()
+        .unwrap();
+        assert!(err.to_string().contains("not supported"));
+
+        // the identifier of a statement doesn't depend on its constants
+        query_rows(&service, &mut client, "SELECT id FROM t WHERE id > 1").await;
+        let query_id = service
+            .activity_registry()
+            .query_id(&client.socket_addr)
+            .unwrap();
+        let rows = query_rows(
+            &service,
+            &mut client,
+            "EXPLAIN VERBOSE SELECT id FROM t WHERE id > 2",
+        )
+        .await;
+        assert!(rows.contains(&format!("Query Identifier: {query_id}")));
     }
 
     #[tokio::test]
-    async fn test_statement_timeout_disable() {
+    async fn test_column_origins() {
+        const QUERY: &str = "SELECT u.name, id AS user_id, id + 1 FROM users u WHERE id > 0";
         let session_context = Arc::new(SessionContext::new());
-        let auth_manager = Arc::new(AuthManager::new());
-        let service = DfSessionService::new(session_context, auth_manager);
+        session_context
+            .sql("CREATE TABLE users (id INT, name VARCHAR) AS VALUES (1, 'a')")
+            .await
+            .unwrap();
+        crate::pg_catalog::setup_pg_catalog(&session_context, "datafusion").unwrap();
+        let users_oid = session_context
+            .catalog("datafusion")
+            .and_then(|catalog| catalog.schema("pg_catalog"))
+            .unwrap()
+            .as_any()
+            .downcast_ref::<PgCatalogSchemaProvider>()
+            .unwrap()
+            .oid_registry()
+            .table_oid("datafusion", "public", "users") as i32;
+        let service = DfSessionService::new(session_context, Arc::new(AuthManager::new()));
         let mut client = MockClient::new();
+        client
+            .metadata
+            .insert(METADATA_USER.to_string(), "postgres".to_string());
+        let origins = |fields: &[FieldInfo]| {
+            fields
+                .iter()
+                .map(|field| (field.table_id(), field.column_id()))
+                .collect::<Vec<_>>()
+        };
+        let expected = vec![
+            (Some(users_oid), Some(2)),
+            (Some(users_oid), Some(1)),
+            (None, None),
+        ];
 
-        // Set timeout first
-        service
-            .try_respond_set_statements(&mut client, "set statement_timeout '1000ms'")
+        let mut responses = SimpleQueryHandler::do_query(&service, &mut client, QUERY)
             .await
             .unwrap();
+        let Response::Query(resp) = responses.remove(0) else {
+            panic!("expected a query response");
+        };
+        assert_eq!(origins(&resp.row_schema()), expected);
 
-        // Disable timeout with 0
-        service
-            .try_respond_set_statements(&mut client, "set statement_timeout '0'")
+        let statement = service.parser.parse_sql(&client, QUERY, &[]).await.unwrap();
+        let (_, plan, _) = statement;
+        let fields =
+            arrow_schema_to_pg_fields(plan.schema().as_arrow(), &Format::UnifiedBinary).unwrap();
+        let fields = with_column_origins(fields, &plan, &service.session_context(&client));
+        assert_eq!(origins(&fields), expected);
+    }
+
+    #[derive(Debug, Default)]
+    struct CollectingExporter(std::sync::Mutex<Vec<crate::telemetry::PhaseSpanData>>);
+
+    impl SpanExporter for CollectingExporter {
+        fn export(&self, span: crate::telemetry::PhaseSpanData) {
+            self.0.lock().unwrap().push(span);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_phase_spans() {
+        let exporter = Arc::new(CollectingExporter::default());
+        let service = DfSessionService::new(
+            Arc::new(SessionContext::new()),
+            Arc::new(AuthManager::new()),
+        )
+        .with_span_exporter(exporter.clone());
+        let mut client = MockClient::new();
+        client
+            .metadata
+            .insert(METADATA_USER.to_string(), "postgres".to_string());
+
+        let mut resp = SimpleQueryHandler::do_query(&service, &mut client, "SELECT 1")
             .await
             .unwrap();
+        let Response::Query(resp) = resp.remove(0) else {
+            panic!("expected rows");
+        };
+        // rows are still being encoded
+        assert_eq!(exporter.0.lock().unwrap().len(), 3);
+        resp.data_rows().collect::<Vec<_>>().await;
 
-        let timeout = DfSessionService::get_statement_timeout(&client);
-        assert_eq!(timeout, None);
+        let spans = exporter.0.lock().unwrap();
+        assert_eq!(
+            spans.iter().map(|span| span.phase).collect::<Vec<_>>(),
+            vec![Phase::Parse, Phase::Plan, Phase::Execute, Phase::Encode]
+        );
+        assert!(spans
+            .iter()
+            .all(|span| span.user.as_deref() == Some("postgres")
+                && span.session == client.socket_addr));
     }
 }
@@ -0,0 +1,254 @@
+//! Connection rules by client address and encryption, like the `host`,
+//! `hostssl` and `hostnossl` lines of `pg_hba.conf`.
+//!
+//! Rules are checked in order when a client sends its startup message. The
+//! first rule matching the address of the client and whether its connection
+//! is encrypted with TLS lets it go on to authenticate, clients no rule
+//! matches are refused. Without rules all clients may connect.
+//!
+//! ```text
+//! host      127.0.0.1/32
+//! host      ::1/128
+//! hostssl   all
+//! ```
+//!
+//! lets local clients connect with or without TLS and requires TLS of all
+//! others.
+
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+use pgwire::error::{ErrorInfo, PgWireError, PgWireResult};
+
+/// Connections a rule matches
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionType {
+    /// Connections with or without TLS
+    Host,
+    /// Connections encrypted with TLS
+    HostSsl,
+    /// Connections without TLS
+    HostNoSsl,
+}
+
+impl ConnectionType {
+    fn matches(self, ssl: bool) -> bool {
+        match self {
+            ConnectionType::Host => true,
+            ConnectionType::HostSsl => ssl,
+            ConnectionType::HostNoSsl => !ssl,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ConnectionType::Host => "host",
+            ConnectionType::HostSsl => "hostssl",
+            ConnectionType::HostNoSsl => "hostnossl",
+        }
+    }
+}
+
+/// Network of client addresses, like `10.0.0.0/8` or `2001:db8::/32`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cidr {
+    address: IpAddr,
+    prefix_len: u8,
+}
+
+impl Cidr {
+    pub fn contains(&self, addr: IpAddr) -> bool {
+        match (self.address, addr.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(addr)) => {
+                let mask = u32::MAX
+                    .checked_shl(32 - self.prefix_len as u32)
+                    .unwrap_or(0);
+                u32::from(network) & mask == u32::from(addr) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(addr)) => {
+                let mask = u128::MAX
+                    .checked_shl(128 - self.prefix_len as u32)
+                    .unwrap_or(0);
+                u128::from(network) & mask == u128::from(addr) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = String;
+
+    /// Parse a network, or a single address
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid IP address or network \"{s}\"");
+        let (address, prefix_len) = match s.split_once('/') {
+            Some((address, prefix_len)) => (address, Some(prefix_len)),
+            None => (s, None),
+        };
+        let address = address.parse::<IpAddr>().map_err(|_| invalid())?;
+        let max_len = if address.is_ipv4() { 32 } else { 128 };
+        let prefix_len = match prefix_len {
+            Some(prefix_len) => prefix_len
+                .parse::<u8>()
+                .ok()
+                .filter(|len| *len <= max_len)
+                .ok_or_else(invalid)?,
+            None => max_len,
+        };
+        Ok(Cidr {
+            address,
+            prefix_len,
+        })
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.address, self.prefix_len)
+    }
+}
+
+/// Rule letting the clients of its address connect, with or without TLS
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HbaRule {
+    pub connection_type: ConnectionType,
+    /// Network of the clients, `None` for all
+    pub address: Option<Cidr>,
+}
+
+impl HbaRule {
+    pub fn new(connection_type: ConnectionType, address: Option<Cidr>) -> Self {
+        HbaRule {
+            connection_type,
+            address,
+        }
+    }
+
+    fn matches(&self, addr: IpAddr, ssl: bool) -> bool {
+        self.connection_type.matches(ssl)
+            && self.address.is_none_or(|network| network.contains(addr))
+    }
+}
+
+impl FromStr for HbaRule {
+    type Err = String;
+
+    /// Parse a rule like `hostssl 0.0.0.0/0` or `host all`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fields = s.split_whitespace().collect::<Vec<_>>();
+        let [connection_type, address] = fields[..] else {
+            return Err(format!(
+                "invalid connection rule \"{s}\", expected a connection type and an address"
+            ));
+        };
+        let connection_type = match connection_type.to_lowercase().as_str() {
+            "host" => ConnectionType::Host,
+            "hostssl" => ConnectionType::HostSsl,
+            "hostnossl" => ConnectionType::HostNoSsl,
+            _ => return Err(format!("invalid connection type \"{connection_type}\"")),
+        };
+        let address = match address {
+            "all" => None,
+            address => Some(address.parse()?),
+        };
+        Ok(HbaRule::new(connection_type, address))
+    }
+}
+
+impl fmt::Display for HbaRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.address {
+            Some(address) => write!(f, "{} {address}", self.connection_type.as_str()),
+            None => write!(f, "{} all", self.connection_type.as_str()),
+        }
+    }
+}
+
+/// Refuse the connection of `user` to `database` from `addr` unless a rule
+/// matches it
+pub(crate) fn check_connection(
+    rules: &[HbaRule],
+    addr: IpAddr,
+    ssl: bool,
+    user: &str,
+    database: &str,
+) -> PgWireResult<()> {
+    if rules.is_empty() || rules.iter().any(|rule| rule.matches(addr, ssl)) {
+        return Ok(());
+    }
+    let encryption = if ssl { "SSL on" } else { "SSL off" };
+    Err(PgWireError::UserError(Box::new(ErrorInfo::new(
+        "FATAL".to_string(),
+        "28000".to_string(), // invalid_authorization_specification
+        format!(
+            "no pg_hba.conf entry for host \"{}\", user \"{user}\", database \"{database}\", {encryption}",
+            addr.to_canonical()
+        ),
+    ))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_rules() {
+        let rule = "hostssl 10.0.0.0/8".parse::<HbaRule>().unwrap();
+        assert_eq!(rule.connection_type, ConnectionType::HostSsl);
+        assert_eq!(rule.to_string(), "hostssl 10.0.0.0/8");
+        assert_eq!(
+            "HOST ::1".parse::<HbaRule>().unwrap().to_string(),
+            "host ::1/128"
+        );
+        assert_eq!(
+            "hostnossl all".parse::<HbaRule>().unwrap(),
+            HbaRule::new(ConnectionType::HostNoSsl, None)
+        );
+        for rule in [
+            "hostssl",
+            "local all",
+            "host 10.0.0.0/33",
+            "host example.com",
+        ] {
+            assert!(rule.parse::<HbaRule>().is_err(), "{rule}");
+        }
+    }
+
+    #[test]
+    fn test_check_connection() {
+        let rules = ["host 127.0.0.1/32", "host ::1", "hostssl all"]
+            .iter()
+            .map(|rule| rule.parse().unwrap())
+            .collect::<Vec<HbaRule>>();
+        let check = |addr: &str, ssl| {
+            check_connection(&rules, addr.parse().unwrap(), ssl, "alice", "sales")
+        };
+        assert!(check("127.0.0.1", false).is_ok());
+        assert!(check("::1", false).is_ok());
+        assert!(check("::ffff:127.0.0.1", false).is_ok());
+        assert!(check("192.0.2.1", true).is_ok());
+
+        let Err(PgWireError::UserError(info)) = check("::ffff:192.0.2.1", false) else {
+            panic!("expected a plaintext connection to be refused");
+        };
+        assert_eq!(info.severity, "FATAL");
+        assert_eq!(info.code, "28000");
+        assert_eq!(
+            info.message,
+            "no pg_hba.conf entry for host \"192.0.2.1\", user \"alice\", database \"sales\", SSL off"
+        );
+
+        let plaintext_only = [HbaRule::new(ConnectionType::HostNoSsl, None)];
+        assert!(check_connection(
+            &plaintext_only,
+            "192.0.2.1".parse().unwrap(),
+            true,
+            "a",
+            "b"
+        )
+        .is_err());
+        assert!(check_connection(&[], "192.0.2.1".parse().unwrap(), false, "a", "b").is_ok());
+    }
+}
@@ -0,0 +1,215 @@
+//! Hooks around the statements of sessions.
+//!
+//! A [`QueryHook`] registered with `DfPostgresServerBuilder::query_hook` or
+//! `DfSessionService::with_query_hook` sees every statement before it is
+//! parsed, its logical plan before it is executed and its outcome once the
+//! client received its result. Hooks can rewrite or block statements, e.g. to
+//! route, cache or audit queries outside the crate.
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use datafusion::logical_expr::LogicalPlan;
+use futures::{stream, StreamExt};
+use pgwire::api::results::{QueryResponse, Response};
+use pgwire::api::{ClientInfo, METADATA_DATABASE, METADATA_USER};
+use pgwire::error::{PgWireError, PgWireResult};
+
+/// Session running a statement
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HookContext {
+    /// Address of the client, identifying its session
+    pub session: SocketAddr,
+    pub user: Option<String>,
+    pub database: Option<String>,
+}
+
+impl HookContext {
+    pub(crate) fn new<C: ClientInfo>(client: &C) -> Self {
+        let metadata = client.metadata();
+        HookContext {
+            session: client.socket_addr(),
+            user: metadata.get(METADATA_USER).cloned(),
+            database: metadata.get(METADATA_DATABASE).cloned(),
+        }
+    }
+}
+
+/// Outcome of a statement, as handed to [`QueryHook::after_execute`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExecutionMeta {
+    /// The statement, as returned by the `before_parse` hooks
+    pub query: String,
+    /// Time from the start of the statement until its last row was sent
+    pub elapsed: Duration,
+    /// Result rows sent to the client
+    pub rows: u64,
+    /// Message of the error the statement failed with
+    pub error: Option<String>,
+}
+
+/// Callbacks around the statements of sessions
+///
+/// All callbacks default to doing nothing. Errors returned by the `before`
+/// callbacks fail the statement with that error, so hooks can block
+/// statements with a postgres error code of their choosing.
+#[async_trait]
+pub trait QueryHook: Send + Sync {
+    /// Called with the SQL of a statement before it is parsed, returns the
+    /// SQL to run instead, if any
+    async fn before_parse(
+        &self,
+        _context: &HookContext,
+        _sql: &str,
+    ) -> PgWireResult<Option<String>> {
+        Ok(None)
+    }
+
+    /// Called with the logical plan of a statement before it is executed,
+    /// returns the plan to execute instead, if any
+    ///
+    /// Statements answered by the server itself, like `SET`, `SHOW` and
+    /// transaction control, have no plan.
+    async fn before_execute(
+        &self,
+        _context: &HookContext,
+        _plan: &LogicalPlan,
+    ) -> PgWireResult<Option<LogicalPlan>> {
+        Ok(None)
+    }
+
+    /// Called once the statement failed or all its rows were sent
+    ///
+    /// Not called for statements whose client disconnects before reading all
+    /// rows.
+    async fn after_execute(&self, _context: &HookContext, _meta: &ExecutionMeta) {}
+}
+
+/// The hooks of a session service, called in registration order
+#[derive(Clone, Default)]
+pub(crate) struct QueryHooks {
+    hooks: Vec<Arc<dyn QueryHook>>,
+}
+
+impl QueryHooks {
+    pub(crate) fn push(&mut self, hook: Arc<dyn QueryHook>) {
+        self.hooks.push(hook);
+    }
+
+    /// The SQL to run for `sql`, each hook seeing the SQL of the previous one
+    pub(crate) async fn before_parse(
+        &self,
+        context: &HookContext,
+        sql: &str,
+    ) -> PgWireResult<String> {
+        let mut sql = sql.to_string();
+        for hook in &self.hooks {
+            if let Some(rewritten) = hook.before_parse(context, &sql).await? {
+                sql = rewritten;
+            }
+        }
+        Ok(sql)
+    }
+
+    /// The plan to execute for `plan`, each hook seeing the plan of the
+    /// previous one
+    pub(crate) async fn before_execute(
+        &self,
+        context: &HookContext,
+        mut plan: LogicalPlan,
+    ) -> PgWireResult<LogicalPlan> {
+        for hook in &self.hooks {
+            if let Some(replaced) = hook.before_execute(context, &plan).await? {
+                plan = replaced;
+            }
+        }
+        Ok(plan)
+    }
+
+    /// Hand the outcome of the statement of `result` to the hooks, after its
+    /// rows are sent for query responses
+    pub(crate) async fn after_execute<'a>(
+        &self,
+        context: HookContext,
+        query: String,
+        started: Instant,
+        result: PgWireResult<Response<'a>>,
+    ) -> PgWireResult<Response<'a>> {
+        if self.hooks.is_empty() {
+            return result;
+        }
+        let mut meta = ExecutionMeta {
+            query,
+            elapsed: Duration::ZERO,
+            rows: 0,
+            error: None,
+        };
+        match result {
+            Ok(Response::Query(resp)) => {
+                let hooks = self.clone();
+                Ok(Response::Query(
+                    hooks.after_rows(context, meta, started, resp),
+                ))
+            }
+            Ok(resp) => {
+                meta.elapsed = started.elapsed();
+                self.notify(&context, &meta).await;
+                Ok(resp)
+            }
+            Err(e) => {
+                meta.elapsed = started.elapsed();
+                meta.error = Some(error_message(&e));
+                self.notify(&context, &meta).await;
+                Err(e)
+            }
+        }
+    }
+
+    fn after_rows<'a>(
+        self,
+        context: HookContext,
+        meta: ExecutionMeta,
+        started: Instant,
+        resp: QueryResponse<'a>,
+    ) -> QueryResponse<'a> {
+        let fields = resp.row_schema();
+        // the hooks are taken once they are notified
+        let state = (resp.data_rows(), Some((self, context, meta)));
+        let data_rows = stream::unfold(state, move |(mut rows, mut hooks)| async move {
+            let row = rows.next().await;
+            match (&row, hooks.take()) {
+                (Some(Ok(_)), Some(mut state)) => {
+                    state.2.rows += 1;
+                    hooks = Some(state);
+                }
+                // pgwire stops sending rows after an error
+                (Some(Err(_)) | None, Some((hooks, context, mut meta))) => {
+                    meta.elapsed = started.elapsed();
+                    meta.error = row
+                        .as_ref()
+                        .and_then(|row| row.as_ref().err())
+                        .map(error_message);
+                    hooks.notify(&context, &meta).await;
+                }
+                (_, None) => {}
+            }
+            row.map(|row| (row, (rows, hooks)))
+        });
+        QueryResponse::new(fields, Box::pin(data_rows))
+    }
+
+    async fn notify(&self, context: &HookContext, meta: &ExecutionMeta) {
+        for hook in &self.hooks {
+            hook.after_execute(context, meta).await;
+        }
+    }
+}
+
+fn error_message(error: &PgWireError) -> String {
+    match error {
+        PgWireError::UserError(info) => info.message.clone(),
+        e => e.to_string(),
+    }
+}
@@ -0,0 +1,553 @@
+use std::fmt::Debug;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use futures::sink::{Sink, SinkExt};
+use getset::{Getters, Setters, WithSetters};
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use log::warn;
+use pgwire::api::auth::{
+    finish_authentication, protocol_negotiation, save_startup_parameters_to_metadata,
+    StartupHandler,
+};
+use pgwire::api::{ClientInfo, PgWireConnectionState, METADATA_DATABASE, METADATA_USER};
+use pgwire::error::{ErrorInfo, PgWireError, PgWireResult};
+use pgwire::messages::startup::Authentication;
+use pgwire::messages::{PgWireBackendMessage, PgWireFrontendMessage};
+use serde_json::Value;
+use tokio::sync::RwLock;
+
+use crate::auth::{AuthManager, ServerParameters, User, METADATA_AUTHORIZED_USER};
+use crate::session::METADATA_SESSION_CATALOGS;
+
+/// Configuration of JSON Web Token authentication
+///
+/// Clients present the token as their password. Its signature is verified
+/// with the keys published at `jwks_url`, and its expiry, issuer and audience
+/// are validated before the claims are mapped to the session user, its roles
+/// and the catalogs it may connect to.
+#[derive(Getters, Setters, WithSetters, Debug, Clone)]
+#[getset(get = "pub", set = "pub", set_with = "pub")]
+pub struct JwtConfig {
+    /// URL of the JSON Web Key Set used to verify token signatures
+    jwks_url: String,
+    /// Accepted `iss` claims, any issuer if empty
+    issuers: Vec<String>,
+    /// Accepted `aud` claims, any audience if empty
+    audiences: Vec<String>,
+    /// Signing algorithms accepted with keys which don't name theirs with
+    /// `alg`, keys with `alg` only accept that one
+    algorithms: Vec<Algorithm>,
+    /// Claim holding the user name, which must match the startup user
+    username_claim: String,
+    /// Claim holding the roles of the user, a string or an array of strings
+    roles_claim: String,
+    /// Claim holding the catalogs the user may connect to and query, all
+    /// catalogs if the token does not have it
+    catalogs_claim: String,
+    /// Allowed clock skew when validating `exp` and `nbf`
+    leeway: Duration,
+    /// How long fetched keys are used before fetching them again
+    jwks_refresh_interval: Duration,
+}
+
+impl JwtConfig {
+    pub fn new(jwks_url: &str) -> JwtConfig {
+        JwtConfig {
+            jwks_url: jwks_url.to_string(),
+            issuers: Vec::new(),
+            audiences: Vec::new(),
+            algorithms: vec![Algorithm::RS256],
+            username_claim: "sub".to_string(),
+            roles_claim: "roles".to_string(),
+            catalogs_claim: "catalogs".to_string(),
+            leeway: Duration::from_secs(60),
+            jwks_refresh_interval: Duration::from_secs(300),
+        }
+    }
+}
+
+// Keys are fetched again for unknown key ids at most this often
+const JWKS_MIN_REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Debug)]
+struct JwksCache {
+    keys: JwkSet,
+    fetched_at: Option<Instant>,
+}
+
+/// Identity of a user authenticated by a token
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JwtIdentity {
+    pub username: String,
+    pub roles: Vec<String>,
+    /// Catalogs the user may connect to, `None` for all catalogs
+    pub catalogs: Option<Vec<String>>,
+}
+
+/// Validates tokens and maps their claims to identities
+#[derive(Debug)]
+pub struct JwtAuthenticator {
+    config: JwtConfig,
+    jwks: RwLock<JwksCache>,
+    /// None for static keys
+    http_client: Option<reqwest::Client>,
+}
+
+impl JwtAuthenticator {
+    pub fn new(config: JwtConfig) -> Self {
+        JwtAuthenticator {
+            config,
+            jwks: RwLock::new(JwksCache {
+                keys: JwkSet { keys: Vec::new() },
+                fetched_at: None,
+            }),
+            http_client: Some(reqwest::Client::new()),
+        }
+    }
+
+    /// Create an authenticator verifying tokens with static keys instead of
+    /// fetching them from the JWKS endpoint
+    pub fn with_static_keys(config: JwtConfig, keys: JwkSet) -> Self {
+        JwtAuthenticator {
+            config,
+            jwks: RwLock::new(JwksCache {
+                keys,
+                fetched_at: Some(Instant::now()),
+            }),
+            http_client: None,
+        }
+    }
+
+    pub fn config(&self) -> &JwtConfig {
+        &self.config
+    }
+
+    /// Validate the token and return the identity of its user
+    pub async fn authenticate(&self, token: &str) -> Result<JwtIdentity, String> {
+        let header = decode_header(token).map_err(|e| format!("invalid token: {e}"))?;
+        let (key, key_algorithm) = self.decoding_key(header.kid.as_deref()).await?;
+
+        // the header of the token is checked against the algorithm of the
+        // key, it can't choose one itself
+        let algorithms = match key_algorithm {
+            Some(algorithm) => vec![algorithm],
+            None => self.config.algorithms.clone(),
+        };
+        if !algorithms.contains(&header.alg) {
+            return Err(format!(
+                "invalid token: algorithm {:?} is not accepted",
+                header.alg
+            ));
+        }
+        let mut validation = Validation::new(header.alg);
+        validation.algorithms = algorithms;
+        validation.leeway = self.config.leeway.as_secs();
+        if self.config.audiences.is_empty() {
+            validation.validate_aud = false;
+        } else {
+            validation.set_audience(&self.config.audiences);
+        }
+        if !self.config.issuers.is_empty() {
+            validation.set_issuer(&self.config.issuers);
+        }
+
+        let claims = decode::<Value>(token, &key, &validation)
+            .map_err(|e| format!("invalid token: {e}"))?
+            .claims;
+        self.identity(&claims)
+    }
+
+    fn identity(&self, claims: &Value) -> Result<JwtIdentity, String> {
+        fn strings(value: &Value) -> Option<Vec<String>> {
+            match value {
+                Value::String(s) => Some(vec![s.clone()]),
+                Value::Array(values) => Some(
+                    values
+                        .iter()
+                        .filter_map(|v| v.as_str().map(str::to_string))
+                        .collect(),
+                ),
+                _ => None,
+            }
+        }
+
+        let username = claims
+            .get(&self.config.username_claim)
+            .and_then(Value::as_str)
+            .ok_or_else(|| format!("token has no \"{}\" claim", self.config.username_claim))?
+            .to_string();
+        let roles = claims
+            .get(&self.config.roles_claim)
+            .and_then(strings)
+            .unwrap_or_default();
+        let catalogs = claims.get(&self.config.catalogs_claim).and_then(strings);
+
+        Ok(JwtIdentity {
+            username,
+            roles,
+            catalogs,
+        })
+    }
+
+    async fn decoding_key(
+        &self,
+        kid: Option<&str>,
+    ) -> Result<(DecodingKey, Option<Algorithm>), String> {
+        {
+            let jwks = self.jwks.read().await;
+            let fresh = self.http_client.is_none()
+                || jwks
+                    .fetched_at
+                    .is_some_and(|t| t.elapsed() < self.config.jwks_refresh_interval);
+            if fresh {
+                if let Some(key) = find_key(&jwks.keys, kid) {
+                    return key;
+                }
+            }
+        }
+
+        let mut jwks = self.jwks.write().await;
+        let can_refresh = jwks
+            .fetched_at
+            .is_none_or(|t| t.elapsed() >= JWKS_MIN_REFRESH_INTERVAL);
+        if let Some(http_client) = self.http_client.as_ref().filter(|_| can_refresh) {
+            match self.fetch_jwks(http_client).await {
+                Ok(keys) => {
+                    jwks.keys = keys;
+                    jwks.fetched_at = Some(Instant::now());
+                }
+                // keep using the previous keys while the endpoint is down
+                Err(e) => warn!("Failed to fetch JWKS from {}: {e}", self.config.jwks_url),
+            }
+        }
+
+        find_key(&jwks.keys, kid).unwrap_or_else(|| Err("no key to verify token".to_string()))
+    }
+
+    async fn fetch_jwks(&self, http_client: &reqwest::Client) -> reqwest::Result<JwkSet> {
+        http_client
+            .get(&self.config.jwks_url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+    }
+}
+
+/// The key of `kid` and its signing algorithm, if it names one
+fn find_key(
+    keys: &JwkSet,
+    kid: Option<&str>,
+) -> Option<Result<(DecodingKey, Option<Algorithm>), String>> {
+    let jwk = match kid {
+        Some(kid) => keys.find(kid),
+        // tokens without key id are only accepted from single key sets
+        None if keys.keys.len() == 1 => keys.keys.first(),
+        None => None,
+    }?;
+    let algorithm = match jwk.common.key_algorithm {
+        Some(key_algorithm) => match key_algorithm.to_string().parse::<Algorithm>() {
+            Ok(algorithm) => Some(algorithm),
+            Err(_) => return Some(Err(format!("key algorithm {key_algorithm} can't sign"))),
+        },
+        None => None,
+    };
+    Some(
+        DecodingKey::from_jwk(jwk)
+            .map(|key| (key, algorithm))
+            .map_err(|e| format!("invalid key: {e}")),
+    )
+}
+
+/// Startup handler authenticating users with a token sent as password
+///
+/// On success the user is registered in the `AuthManager` for the session
+/// only, with the roles from the token, so grants and policies of those roles
+/// apply to the session but not to other sessions of the user. Its queries
+/// are confined to the catalogs of the token.
+pub struct JwtStartupHandler {
+    authenticator: Arc<JwtAuthenticator>,
+    auth_manager: Arc<AuthManager>,
+    parameter_provider: ServerParameters,
+}
+
+impl JwtStartupHandler {
+    pub fn new(authenticator: Arc<JwtAuthenticator>, auth_manager: Arc<AuthManager>) -> Self {
+        JwtStartupHandler {
+            authenticator,
+            auth_manager,
+            parameter_provider: ServerParameters::default(),
+        }
+    }
+
+    async fn login<C>(&self, client: &mut C, token: &str) -> PgWireResult<()>
+    where
+        C: ClientInfo,
+    {
+        let user = client.metadata().get(METADATA_USER).cloned();
+        let auth_failed = |reason: &str| {
+            PgWireError::UserError(Box::new(ErrorInfo::new(
+                "FATAL".to_string(),
+                "28P01".to_string(), // invalid_password
+                format!(
+                    "token authentication failed for user \"{}\": {reason}",
+                    user.as_deref().unwrap_or_default()
+                ),
+            )))
+        };
+
+        let identity = self
+            .authenticator
+            .authenticate(token)
+            .await
+            .map_err(|e| auth_failed(&e))?;
+        if user.as_ref().is_some_and(|u| *u != identity.username) {
+            return Err(auth_failed("user does not match token"));
+        }
+
+        if let (Some(catalogs), Some(database)) =
+            (&identity.catalogs, client.metadata().get(METADATA_DATABASE))
+        {
+            if !catalogs.contains(database) {
+                return Err(PgWireError::UserError(Box::new(ErrorInfo::new(
+                    "FATAL".to_string(),
+                    "28000".to_string(), // invalid_authorization_specification
+                    format!(
+                        "user \"{}\" is not allowed to connect to database \"{database}\"",
+                        identity.username
+                    ),
+                ))));
+            }
+        }
+
+        let user = match self.auth_manager.get_user(&identity.username).await {
+            Some(user) if !user.can_login => {
+                return Err(auth_failed("user is not allowed to login"));
+            }
+            Some(user) => User {
+                roles: identity.roles,
+                ..user
+            },
+            None => User {
+                username: identity.username.clone(),
+                password_hash: String::new(),
+                roles: identity.roles,
+                is_superuser: false,
+                can_login: true,
+                connection_limit: None,
+            },
+        };
+        let session_user = self
+            .auth_manager
+            .add_session_user(&client.socket_addr(), user);
+        let metadata = client.metadata_mut();
+        metadata.insert(METADATA_USER.to_string(), identity.username);
+        metadata.insert(METADATA_AUTHORIZED_USER.to_string(), session_user);
+        match identity.catalogs {
+            Some(catalogs) => {
+                let catalogs = serde_json::to_string(&catalogs)
+                    .map_err(|e| PgWireError::ApiError(Box::new(e)))?;
+                metadata.insert(METADATA_SESSION_CATALOGS.to_string(), catalogs);
+            }
+            None => {
+                metadata.remove(METADATA_SESSION_CATALOGS);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl StartupHandler for JwtStartupHandler {
+    async fn on_startup<C>(
+        &self,
+        client: &mut C,
+        message: PgWireFrontendMessage,
+    ) -> PgWireResult<()>
+    where
+        C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send,
+        C::Error: Debug,
+        PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    {
+        match message {
+            PgWireFrontendMessage::Startup(ref startup) => {
+                protocol_negotiation(client, startup).await?;
+                save_startup_parameters_to_metadata(client, startup);
+                client.set_state(PgWireConnectionState::AuthenticationInProgress);
+                client
+                    .send(PgWireBackendMessage::Authentication(
+                        Authentication::CleartextPassword,
+                    ))
+                    .await?;
+            }
+            PgWireFrontendMessage::PasswordMessageFamily(pwd) => {
+                let pwd = pwd.into_password()?;
+                self.login(client, &pwd.password).await?;
+                finish_authentication(client, &self.parameter_provider).await?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jsonwebtoken::{encode, EncodingKey, Header};
+    use serde_json::json;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    const SECRET: &[u8] = b"test-secret";
+
+    fn authenticator() -> JwtAuthenticator {
+        let keys: JwkSet = serde_json::from_value(json!({
+            "keys": [{
+                "kty": "oct",
+                "kid": "test",
+                "alg": "HS256",
+                // base64url of "test-secret"
+                "k": "dGVzdC1zZWNyZXQ",
+            }]
+        }))
+        .unwrap();
+        let config = JwtConfig::new("https://idp.example.com/.well-known/jwks.json")
+            .with_audiences(vec!["datafusion".to_string()])
+            .with_issuers(vec!["https://idp.example.com".to_string()]);
+        JwtAuthenticator::with_static_keys(config, keys)
+    }
+
+    fn token(claims: Value) -> String {
+        let header = Header {
+            kid: Some("test".to_string()),
+            ..Default::default()
+        };
+        encode(&header, &claims, &EncodingKey::from_secret(SECRET)).unwrap()
+    }
+
+    fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+    }
+
+    #[tokio::test]
+    async fn test_authenticate() {
+        let authenticator = authenticator();
+
+        let identity = authenticator
+            .authenticate(&token(json!({
+                "sub": "alice",
+                "aud": "datafusion",
+                "iss": "https://idp.example.com",
+                "exp": now() + 600,
+                "roles": ["analyst", "reporter"],
+                "catalogs": "datafusion",
+            })))
+            .await
+            .unwrap();
+        assert_eq!(
+            identity,
+            JwtIdentity {
+                username: "alice".to_string(),
+                roles: vec!["analyst".to_string(), "reporter".to_string()],
+                catalogs: Some(vec!["datafusion".to_string()]),
+            }
+        );
+
+        // expired
+        assert!(authenticator
+            .authenticate(&token(json!({
+                "sub": "alice",
+                "aud": "datafusion",
+                "iss": "https://idp.example.com",
+                "exp": now() - 600,
+            })))
+            .await
+            .is_err());
+
+        // wrong audience
+        assert!(authenticator
+            .authenticate(&token(json!({
+                "sub": "alice",
+                "aud": "other",
+                "iss": "https://idp.example.com",
+                "exp": now() + 600,
+            })))
+            .await
+            .is_err());
+
+        // wrong signature
+        let header = Header {
+            kid: Some("test".to_string()),
+            ..Default::default()
+        };
+        let forged = encode(
+            &header,
+            &json!({
+                "sub": "alice",
+                "aud": "datafusion",
+                "iss": "https://idp.example.com",
+                "exp": now() + 600,
+            }),
+            &EncodingKey::from_secret(b"other-secret"),
+        )
+        .unwrap();
+        assert!(authenticator.authenticate(&forged).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_algorithms() {
+        let claims = json!({
+            "sub": "alice",
+            "aud": "datafusion",
+            "iss": "https://idp.example.com",
+            "exp": now() + 600,
+        });
+        let token_with = |alg| {
+            let header = Header {
+                kid: Some("test".to_string()),
+                ..Header::new(alg)
+            };
+            encode(&header, &claims, &EncodingKey::from_secret(SECRET)).unwrap()
+        };
+
+        // the key only accepts its own algorithm
+        let authenticator = authenticator();
+        assert!(authenticator
+            .authenticate(&token_with(Algorithm::HS256))
+            .await
+            .is_ok());
+        let err = authenticator
+            .authenticate(&token_with(Algorithm::HS384))
+            .await
+            .unwrap_err();
+        assert_eq!(err, "invalid token: algorithm HS384 is not accepted");
+
+        // keys without alg accept the configured algorithms
+        let keys: JwkSet = serde_json::from_value(json!({
+            "keys": [{"kty": "oct", "kid": "test", "k": "dGVzdC1zZWNyZXQ"}]
+        }))
+        .unwrap();
+        let config = authenticator.config().clone();
+        let authenticator = JwtAuthenticator::with_static_keys(config.clone(), keys.clone());
+        assert!(authenticator
+            .authenticate(&token_with(Algorithm::HS256))
+            .await
+            .is_err());
+        let authenticator = JwtAuthenticator::with_static_keys(
+            config.with_algorithms(vec![Algorithm::HS256]),
+            keys,
+        );
+        assert!(authenticator
+            .authenticate(&token_with(Algorithm::HS256))
+            .await
+            .is_ok());
+    }
+}
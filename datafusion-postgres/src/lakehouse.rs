@@ -0,0 +1,150 @@
+//! Tables of lakehouse formats, registered with `CREATE EXTERNAL TABLE`.
+//!
+//! The `deltalake` feature registers a `DELTA` table factory, and the
+//! `iceberg` feature an `ICEBERG` one, for the sessions which don't register
+//! factories of their own. Both read the metadata of a table at its
+//! location and scan the Parquet files of one of its snapshots, the latest
+//! unless `OPTIONS` pick an older one to time travel to:
+//!
+//! ```sql
+//! CREATE EXTERNAL TABLE events STORED AS DELTA LOCATION 's3://lake/events'
+//!     OPTIONS ('version' '12');
+//! CREATE EXTERNAL TABLE orders STORED AS ICEBERG LOCATION 's3://lake/orders'
+//!     OPTIONS ('snapshot-id' '3051729675574597004');
+//! ```
+//!
+//! The snapshot is fixed when the table is created. Files the metadata
+//! refers to are only read under the location of the table, and formats
+//! the readers don't know, like deletion vectors of Delta or delete files
+//! of Iceberg, are refused rather than read wrong.
+
+use std::any::Any;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use datafusion::arrow::datatypes::{Field, Schema, SchemaRef};
+use datafusion::catalog::{Session, TableProvider};
+use datafusion::common::plan_err;
+use datafusion::datasource::listing::{ListingTableUrl, PartitionedFile};
+use datafusion::datasource::physical_plan::{FileScanConfigBuilder, ParquetSource};
+use datafusion::datasource::source::DataSourceExec;
+use datafusion::datasource::TableType;
+use datafusion::error::Result;
+use datafusion::execution::object_store::ObjectStoreUrl;
+use datafusion::logical_expr::Expr;
+use datafusion::physical_plan::ExecutionPlan;
+use object_store::path::Path;
+use object_store::ObjectStore;
+use url::Url;
+
+#[cfg(feature = "deltalake")]
+mod delta;
+#[cfg(feature = "iceberg")]
+mod iceberg;
+
+#[cfg(feature = "deltalake")]
+pub(crate) use delta::DeltaTableFactory;
+#[cfg(feature = "iceberg")]
+pub(crate) use iceberg::IcebergTableFactory;
+
+/// The Parquet files of a snapshot of a lakehouse table
+#[derive(Debug)]
+struct SnapshotTable {
+    schema: SchemaRef,
+    file_schema: SchemaRef,
+    partition_cols: Vec<Field>,
+    store_url: ObjectStoreUrl,
+    files: Vec<PartitionedFile>,
+}
+
+impl SnapshotTable {
+    /// A table of `files` with the columns of `file_schema`, followed by
+    /// the columns of `partition_cols` whose values are the partition
+    /// values of each file
+    fn new(
+        store_url: ObjectStoreUrl,
+        file_schema: SchemaRef,
+        partition_cols: Vec<Field>,
+        files: Vec<PartitionedFile>,
+    ) -> Self {
+        let schema = Arc::new(Schema::new(
+            file_schema
+                .fields()
+                .iter()
+                .map(|field| field.as_ref().clone())
+                .chain(partition_cols.iter().cloned())
+                .collect::<Vec<_>>(),
+        ));
+        Self {
+            schema,
+            file_schema,
+            partition_cols,
+            store_url,
+            files,
+        }
+    }
+}
+
+#[async_trait]
+impl TableProvider for SnapshotTable {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn table_type(&self) -> TableType {
+        TableType::Base
+    }
+
+    async fn scan(
+        &self,
+        _state: &dyn Session,
+        projection: Option<&Vec<usize>>,
+        _filters: &[Expr],
+        limit: Option<usize>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        let config = FileScanConfigBuilder::new(
+            self.store_url.clone(),
+            self.file_schema.clone(),
+            Arc::new(ParquetSource::default()),
+        )
+        .with_file_group(self.files.clone().into())
+        .with_table_partition_cols(self.partition_cols.clone())
+        .with_projection(projection.cloned())
+        .with_limit(limit)
+        .build();
+        Ok(DataSourceExec::from_data_source(config))
+    }
+}
+
+/// The path of a file the metadata of `table` refers to by `path`, which is
+/// relative to the location of the table or absolute
+///
+/// Files outside of the location aren't read, so the metadata of a table
+/// can't point at other files of the server.
+fn table_file(table: &ListingTableUrl, path: &str) -> Result<Path> {
+    // one letter schemes are the drives of windows paths
+    let absolute = std::path::Path::new(path).is_absolute()
+        || Url::parse(path).is_ok_and(|url| url.scheme().len() > 1);
+    let file = if absolute {
+        ListingTableUrl::parse(path)?
+    } else {
+        ListingTableUrl::parse(format!("{}/{path}", table.as_str().trim_end_matches('/')))?
+    };
+    if file.object_store() != table.object_store() || !file.prefix().prefix_matches(table.prefix())
+    {
+        return plan_err!(
+            "file \"{path}\" is outside of the table location \"{}\"",
+            table.as_str()
+        );
+    }
+    Ok(file.prefix().clone())
+}
+
+async fn read_file(store: &dyn ObjectStore, path: &Path) -> Result<Bytes> {
+    Ok(store.get(path).await?.bytes().await?)
+}
@@ -0,0 +1,248 @@
+//! Delta Lake tables, read from the commits of their `_delta_log`.
+//!
+//! The log is replayed up to the version of the `version` option, or up to
+//! its last commit. Logs starting at a checkpoint, because their first
+//! commits were cleaned up, and tables needing reader features like column
+//! mapping or deletion vectors aren't supported.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::{TimeZone, Utc};
+use datafusion::arrow::datatypes::{DataType, Field, Schema, TimeUnit};
+use datafusion::catalog::{Session, TableProvider, TableProviderFactory};
+use datafusion::common::{not_impl_err, plan_datafusion_err, plan_err, ScalarValue};
+use datafusion::datasource::listing::{ListingTableUrl, PartitionedFile};
+use datafusion::error::Result;
+use datafusion::logical_expr::CreateExternalTable;
+use futures::TryStreamExt;
+use object_store::ObjectMeta;
+use serde_json::Value;
+
+use super::{read_file, table_file, SnapshotTable};
+
+/// Reader features of the protocol which don't change how files are read
+const READER_FEATURES: [&str; 2] = ["timestampNtz", "vacuumProtocolCheck"];
+
+/// The table factory of `STORED AS DELTA`
+#[derive(Debug, Default)]
+pub(crate) struct DeltaTableFactory;
+
+#[async_trait]
+impl TableProviderFactory for DeltaTableFactory {
+    async fn create(
+        &self,
+        state: &dyn Session,
+        cmd: &CreateExternalTable,
+    ) -> Result<Arc<dyn TableProvider>> {
+        if !cmd.schema.fields().is_empty() {
+            return plan_err!(
+                "the columns of Delta table {} are read from its log",
+                cmd.name
+            );
+        }
+        let version = cmd
+            .options
+            .get("format.version")
+            .map(|version| {
+                version
+                    .parse::<u64>()
+                    .map_err(|_| plan_datafusion_err!("invalid Delta table version \"{version}\""))
+            })
+            .transpose()?;
+
+        let table = ListingTableUrl::parse(&cmd.location)?;
+        let store = state.runtime_env().object_store(table.object_store())?;
+        let log = table.prefix().child("_delta_log");
+        let mut commits = store
+            .list(Some(&log))
+            .try_filter_map(|meta| async move {
+                let version = meta
+                    .location
+                    .filename()
+                    .and_then(|name| name.strip_suffix(".json"))
+                    .filter(|name| name.len() == 20)
+                    .and_then(|name| name.parse::<u64>().ok());
+                Ok(version.map(|version| (version, meta.location)))
+            })
+            .try_collect::<Vec<_>>()
+            .await?;
+        commits.sort();
+        if commits.is_empty() {
+            return plan_err!("no Delta table at \"{}\"", cmd.location);
+        }
+        if commits.iter().enumerate().any(|(i, (v, _))| *v != i as u64) {
+            return not_impl_err!(
+                "Delta table \"{}\" has a log starting at a checkpoint",
+                cmd.location
+            );
+        }
+        let last = commits.len() as u64 - 1;
+        let version = version.unwrap_or(last);
+        if version > last {
+            return plan_err!(
+                "version {version} of Delta table \"{}\" does not exist, the last one is {last}",
+                cmd.location
+            );
+        }
+
+        let mut metadata = None;
+        let mut adds = BTreeMap::new();
+        for (_, path) in &commits[..=version as usize] {
+            let commit = read_file(store.as_ref(), path).await?;
+            for line in String::from_utf8_lossy(&commit).lines() {
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let action = serde_json::from_str::<Value>(line)
+                    .map_err(|e| plan_datafusion_err!("invalid Delta log entry: {e}"))?;
+                if let Some(protocol) = action.get("protocol") {
+                    check_protocol(protocol)?;
+                } else if let Some(meta_data) = action.get("metaData") {
+                    metadata = Some(meta_data.clone());
+                } else if let Some(add) = action.get("add") {
+                    adds.insert(action_path(add)?, add.clone());
+                } else if let Some(remove) = action.get("remove") {
+                    adds.remove(&action_path(remove)?);
+                }
+            }
+        }
+        let Some(metadata) = metadata else {
+            return plan_err!("Delta table \"{}\" has no metadata", cmd.location);
+        };
+
+        let schema_string = metadata["schemaString"]
+            .as_str()
+            .ok_or_else(|| plan_datafusion_err!("Delta table metadata without schema"))?;
+        let schema = serde_json::from_str::<Value>(schema_string)
+            .map_err(|e| plan_datafusion_err!("invalid Delta table schema: {e}"))?;
+        let partition_columns = metadata["partitionColumns"]
+            .as_array()
+            .map(|columns| columns.iter().filter_map(Value::as_str).collect::<Vec<_>>())
+            .unwrap_or_default();
+        let mut file_fields = Vec::new();
+        let mut partition_cols = Vec::new();
+        for field in schema["fields"].as_array().into_iter().flatten() {
+            let name = field["name"]
+                .as_str()
+                .ok_or_else(|| plan_datafusion_err!("Delta table column without name"))?;
+            let field = Field::new(
+                name,
+                delta_type(&field["type"])?,
+                field["nullable"].as_bool().unwrap_or(true),
+            );
+            if partition_columns.contains(&name) {
+                partition_cols.push(field);
+            } else {
+                file_fields.push(field);
+            }
+        }
+
+        let files = adds
+            .into_iter()
+            .map(|(path, add)| {
+                let values = partition_cols
+                    .iter()
+                    .map(
+                        |field| match add["partitionValues"][field.name()].as_str() {
+                            Some(value) => {
+                                ScalarValue::try_from_string(value.to_string(), field.data_type())
+                            }
+                            None => ScalarValue::try_from(field.data_type()),
+                        },
+                    )
+                    .collect::<Result<Vec<_>>>()?;
+                let mut file = PartitionedFile::from(ObjectMeta {
+                    location: table_file(&table, &path)?,
+                    last_modified: Utc
+                        .timestamp_millis_opt(add["modificationTime"].as_i64().unwrap_or(0))
+                        .single()
+                        .unwrap_or_default(),
+                    size: add["size"].as_u64().unwrap_or(0),
+                    e_tag: None,
+                    version: None,
+                });
+                file.partition_values = values;
+                Ok(file)
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Arc::new(SnapshotTable::new(
+            table.object_store(),
+            Arc::new(Schema::new(file_fields)),
+            partition_cols,
+            files,
+        )))
+    }
+}
+
+fn action_path(action: &Value) -> Result<String> {
+    action["path"]
+        .as_str()
+        .map(str::to_string)
+        .ok_or_else(|| plan_datafusion_err!("Delta log action without path"))
+}
+
+/// Refuse tables whose files can't be read without reader features
+fn check_protocol(protocol: &Value) -> Result<()> {
+    let reader_version = protocol["minReaderVersion"].as_u64().unwrap_or(1);
+    let features = protocol["readerFeatures"]
+        .as_array()
+        .map(|features| {
+            features
+                .iter()
+                .filter_map(Value::as_str)
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    match reader_version {
+        1 => Ok(()),
+        3 => match features
+            .iter()
+            .find(|feature| !READER_FEATURES.contains(feature))
+        {
+            Some(feature) => not_impl_err!("Delta reader feature {feature} is not supported"),
+            None => Ok(()),
+        },
+        _ => not_impl_err!("Delta reader version {reader_version} is not supported"),
+    }
+}
+
+/// The Arrow type of a column of a Delta table schema
+fn delta_type(delta_type: &Value) -> Result<DataType> {
+    let Some(name) = delta_type.as_str() else {
+        return not_impl_err!("nested Delta column type {delta_type} is not supported");
+    };
+    Ok(match name {
+        "string" => DataType::Utf8,
+        "long" => DataType::Int64,
+        "integer" => DataType::Int32,
+        "short" => DataType::Int16,
+        "byte" => DataType::Int8,
+        "float" => DataType::Float32,
+        "double" => DataType::Float64,
+        "boolean" => DataType::Boolean,
+        "binary" => DataType::Binary,
+        "date" => DataType::Date32,
+        "timestamp" => DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into())),
+        "timestamp_ntz" => DataType::Timestamp(TimeUnit::Microsecond, None),
+        _ => match name
+            .strip_prefix("decimal(")
+            .and_then(|rest| rest.strip_suffix(')'))
+            .and_then(|rest| rest.split_once(','))
+        {
+            Some((precision, scale)) => DataType::Decimal128(
+                precision
+                    .trim()
+                    .parse()
+                    .map_err(|_| plan_datafusion_err!("invalid Delta column type {name}"))?,
+                scale
+                    .trim()
+                    .parse()
+                    .map_err(|_| plan_datafusion_err!("invalid Delta column type {name}"))?,
+            ),
+            None => return not_impl_err!("Delta column type {name} is not supported"),
+        },
+    })
+}
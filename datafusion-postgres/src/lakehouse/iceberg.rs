@@ -0,0 +1,305 @@
+//! Iceberg tables, read from their metadata files and manifests.
+//!
+//! The metadata file of a table is the one `metadata/version-hint.text`
+//! names, or the latest of `metadata/*.metadata.json`. Its current snapshot
+//! is scanned, or the one of the `snapshot-id` option. Snapshots with delete
+//! files, of format version 2 row-level deletes, aren't supported.
+
+use std::sync::Arc;
+
+use apache_avro::types::Value as AvroValue;
+use apache_avro::Reader;
+use async_trait::async_trait;
+use datafusion::arrow::datatypes::{DataType, Field, Schema, TimeUnit};
+use datafusion::catalog::{Session, TableProvider, TableProviderFactory};
+use datafusion::common::{not_impl_err, plan_datafusion_err, plan_err};
+use datafusion::datasource::listing::{ListingTableUrl, PartitionedFile};
+use datafusion::error::Result;
+use datafusion::logical_expr::CreateExternalTable;
+use futures::TryStreamExt;
+use object_store::path::Path;
+use object_store::{ObjectMeta, ObjectStore};
+use serde_json::Value;
+
+use super::{read_file, table_file, SnapshotTable};
+
+/// `status` of the manifest entries of deleted files
+const DELETED: i32 = 2;
+
+/// The table factory of `STORED AS ICEBERG`
+#[derive(Debug, Default)]
+pub(crate) struct IcebergTableFactory;
+
+#[async_trait]
+impl TableProviderFactory for IcebergTableFactory {
+    async fn create(
+        &self,
+        state: &dyn Session,
+        cmd: &CreateExternalTable,
+    ) -> Result<Arc<dyn TableProvider>> {
+        if !cmd.schema.fields().is_empty() {
+            return plan_err!(
+                "the columns of Iceberg table {} are read from its metadata",
+                cmd.name
+            );
+        }
+        let snapshot_id = cmd
+            .options
+            .get("format.snapshot-id")
+            .map(|id| {
+                id.parse::<i64>()
+                    .map_err(|_| plan_datafusion_err!("invalid Iceberg snapshot id \"{id}\""))
+            })
+            .transpose()?;
+
+        let table = ListingTableUrl::parse(&cmd.location)?;
+        let store = state.runtime_env().object_store(table.object_store())?;
+        let metadata_path = metadata_file(store.as_ref(), &table).await?;
+        let metadata =
+            serde_json::from_slice::<Value>(&read_file(store.as_ref(), &metadata_path).await?)
+                .map_err(|e| plan_datafusion_err!("invalid Iceberg table metadata: {e}"))?;
+
+        let snapshot_id = snapshot_id.or_else(|| {
+            metadata["current-snapshot-id"]
+                .as_i64()
+                .filter(|id| *id != -1)
+        });
+        let snapshot = match snapshot_id {
+            Some(id) => Some(
+                metadata["snapshots"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .find(|snapshot| snapshot["snapshot-id"].as_i64() == Some(id))
+                    .ok_or_else(|| {
+                        plan_datafusion_err!(
+                            "snapshot {id} of Iceberg table \"{}\" does not exist",
+                            cmd.location
+                        )
+                    })?,
+            ),
+            None => None,
+        };
+
+        let schema_id = snapshot
+            .and_then(|snapshot| snapshot["schema-id"].as_i64())
+            .or_else(|| metadata["current-schema-id"].as_i64());
+        let schema = metadata["schemas"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .find(|schema| schema["schema-id"].as_i64() == schema_id)
+            .unwrap_or(&metadata["schema"]);
+        let fields = schema["fields"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|field| {
+                let name = field["name"]
+                    .as_str()
+                    .ok_or_else(|| plan_datafusion_err!("Iceberg table column without name"))?;
+                Ok(Field::new(
+                    name,
+                    iceberg_type(&field["type"])?,
+                    !field["required"].as_bool().unwrap_or(false),
+                ))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut files = Vec::new();
+        if let Some(snapshot) = snapshot {
+            for manifest in manifests(store.as_ref(), &table, snapshot).await? {
+                for entry in avro_records(&read_file(store.as_ref(), &manifest).await?)? {
+                    if avro_field(&entry, "status").and_then(avro_int) == Some(DELETED) {
+                        continue;
+                    }
+                    let data_file = avro_field(&entry, "data_file").ok_or_else(|| {
+                        plan_datafusion_err!("Iceberg manifest entry without file")
+                    })?;
+                    files.push(data_file_of(&table, data_file)?);
+                }
+            }
+        }
+
+        Ok(Arc::new(SnapshotTable::new(
+            table.object_store(),
+            Arc::new(Schema::new(fields)),
+            vec![],
+            files,
+        )))
+    }
+}
+
+/// The current metadata file of the table at `table`
+async fn metadata_file(store: &dyn ObjectStore, table: &ListingTableUrl) -> Result<Path> {
+    let metadata = table.prefix().child("metadata");
+    if let Ok(hint) = read_file(store, &metadata.child("version-hint.text")).await {
+        let version = String::from_utf8_lossy(&hint).trim().to_string();
+        return Ok(metadata.child(format!("v{version}.metadata.json")));
+    }
+    let files = store
+        .list(Some(&metadata))
+        .try_filter(|meta| {
+            let is_metadata = meta
+                .location
+                .filename()
+                .is_some_and(|name| name.ends_with(".metadata.json"));
+            async move { is_metadata }
+        })
+        .try_collect::<Vec<_>>()
+        .await?;
+    // named `v<version>.metadata.json` or `<version>-<uuid>.metadata.json`
+    files
+        .into_iter()
+        .max_by_key(|meta| {
+            meta.location
+                .filename()
+                .unwrap_or_default()
+                .trim_start_matches('v')
+                .split(['.', '-'])
+                .next()
+                .and_then(|version| version.parse::<u64>().ok())
+        })
+        .map(|meta| meta.location)
+        .ok_or_else(|| plan_datafusion_err!("no Iceberg table at \"{}\"", table.as_str()))
+}
+
+/// The data manifests of `snapshot`
+async fn manifests(
+    store: &dyn ObjectStore,
+    table: &ListingTableUrl,
+    snapshot: &Value,
+) -> Result<Vec<Path>> {
+    // snapshots of format version 1 may list their manifests themselves
+    if let Some(manifests) = snapshot["manifests"].as_array() {
+        return manifests
+            .iter()
+            .filter_map(Value::as_str)
+            .map(|path| table_file(table, path))
+            .collect();
+    }
+    let Some(manifest_list) = snapshot["manifest-list"].as_str() else {
+        return plan_err!("Iceberg snapshot without manifests");
+    };
+    let manifest_list = read_file(store, &table_file(table, manifest_list)?).await?;
+    avro_records(&manifest_list)?
+        .iter()
+        .map(|manifest| {
+            if avro_field(manifest, "content").and_then(avro_int) == Some(1) {
+                return not_impl_err!("Iceberg tables with delete files are not supported");
+            }
+            match avro_field(manifest, "manifest_path") {
+                Some(AvroValue::String(path)) => table_file(table, path),
+                _ => plan_err!("Iceberg manifest list entry without path"),
+            }
+        })
+        .collect()
+}
+
+/// The Parquet file of a `data_file` of a manifest entry
+fn data_file_of(table: &ListingTableUrl, data_file: &AvroValue) -> Result<PartitionedFile> {
+    if avro_field(data_file, "content")
+        .and_then(avro_int)
+        .is_some_and(|content| content != 0)
+    {
+        return not_impl_err!("Iceberg tables with delete files are not supported");
+    }
+    match avro_field(data_file, "file_format") {
+        Some(AvroValue::String(format)) if format.eq_ignore_ascii_case("parquet") => {}
+        Some(AvroValue::String(format)) => {
+            return not_impl_err!("Iceberg data files of format {format} are not supported")
+        }
+        _ => return plan_err!("Iceberg data file without format"),
+    }
+    let Some(AvroValue::String(path)) = avro_field(data_file, "file_path") else {
+        return plan_err!("Iceberg data file without path");
+    };
+    let size = match avro_field(data_file, "file_size_in_bytes") {
+        Some(AvroValue::Long(size)) => *size as u64,
+        _ => return plan_err!("Iceberg data file \"{path}\" without size"),
+    };
+    Ok(PartitionedFile::from(ObjectMeta {
+        location: table_file(table, path)?,
+        last_modified: Default::default(),
+        size,
+        e_tag: None,
+        version: None,
+    }))
+}
+
+fn avro_records(file: &[u8]) -> Result<Vec<AvroValue>> {
+    let invalid = |e| plan_datafusion_err!("invalid Iceberg manifest: {e}");
+    Reader::new(file)
+        .map_err(invalid)?
+        .map(|record| record.map_err(invalid))
+        .collect()
+}
+
+/// The field `name` of `record`, unwrapped of the union of optional fields
+fn avro_field<'a>(record: &'a AvroValue, name: &str) -> Option<&'a AvroValue> {
+    let AvroValue::Record(fields) = record else {
+        return None;
+    };
+    let (_, value) = fields.iter().find(|(field, _)| field == name)?;
+    match value {
+        AvroValue::Union(_, value) => Some(value),
+        value => Some(value),
+    }
+}
+
+fn avro_int(value: &AvroValue) -> Option<i32> {
+    match value {
+        AvroValue::Int(value) => Some(*value),
+        _ => None,
+    }
+}
+
+/// The Arrow type of a column of an Iceberg table schema
+fn iceberg_type(iceberg_type: &Value) -> Result<DataType> {
+    let Some(name) = iceberg_type.as_str() else {
+        return not_impl_err!("nested Iceberg column type {iceberg_type} is not supported");
+    };
+    Ok(match name {
+        "boolean" => DataType::Boolean,
+        "int" => DataType::Int32,
+        "long" => DataType::Int64,
+        "float" => DataType::Float32,
+        "double" => DataType::Float64,
+        "date" => DataType::Date32,
+        "time" => DataType::Time64(TimeUnit::Microsecond),
+        "timestamp" => DataType::Timestamp(TimeUnit::Microsecond, None),
+        "timestamptz" => DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into())),
+        "string" => DataType::Utf8,
+        "uuid" => DataType::FixedSizeBinary(16),
+        "binary" => DataType::Binary,
+        _ => {
+            if let Some(length) = name
+                .strip_prefix("fixed[")
+                .and_then(|rest| rest.strip_suffix(']'))
+            {
+                DataType::FixedSizeBinary(
+                    length
+                        .parse()
+                        .map_err(|_| plan_datafusion_err!("invalid Iceberg column type {name}"))?,
+                )
+            } else if let Some((precision, scale)) = name
+                .strip_prefix("decimal(")
+                .and_then(|rest| rest.strip_suffix(')'))
+                .and_then(|rest| rest.split_once(','))
+            {
+                DataType::Decimal128(
+                    precision
+                        .trim()
+                        .parse()
+                        .map_err(|_| plan_datafusion_err!("invalid Iceberg column type {name}"))?,
+                    scale
+                        .trim()
+                        .parse()
+                        .map_err(|_| plan_datafusion_err!("invalid Iceberg column type {name}"))?,
+                )
+            } else {
+                return not_impl_err!("Iceberg column type {name} is not supported");
+            }
+        }
+    })
+}
@@ -1,41 +1,178 @@
+pub mod activity;
+mod advisory_locks;
+pub mod analyze;
+pub mod cert_auth;
+mod column_origins;
+mod copy;
+mod copy_to;
+mod database;
+mod discard;
+pub mod errors;
+pub mod events;
+mod explain;
+mod external;
+#[cfg(feature = "postgres-fdw")]
+pub mod foreign;
 mod handlers;
+pub mod hba;
+pub mod hooks;
+#[cfg(feature = "jwt")]
+pub mod jwt;
+#[cfg(any(feature = "deltalake", feature = "iceberg"))]
+mod lakehouse;
+pub mod limits;
+mod negotiation;
+pub mod notify;
+mod on_conflict;
 pub mod pg_catalog;
+pub mod pgbench;
+mod pipeline;
+pub mod plan_cache;
+pub mod policy;
+mod progress;
+mod proxy_protocol;
+pub mod rate_limit;
+mod refresh;
+pub mod replication;
+pub mod result_cache;
+mod returning;
+pub mod role_settings;
+pub mod roles;
+pub mod scheduler;
+pub mod server;
+pub mod session;
 mod sql;
+mod storage;
+pub mod telemetry;
+pub mod tenant;
+mod time_zone;
+pub mod tls;
+pub mod wire_debug;
 
 use std::fs::File;
 use std::io::{BufReader, Error as IOError, ErrorKind};
+use std::pin::pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 
 use datafusion::prelude::SessionContext;
 
 pub mod auth;
+use futures::future::{select, Either};
 use getset::{Getters, Setters, WithSetters};
 use log::{info, warn};
 use pgwire::api::PgWireServerHandlers;
 use pgwire::tokio::process_socket;
-use rustls_pemfile::{certs, pkcs8_private_keys};
-use rustls_pki_types::{CertificateDer, PrivateKeyDer};
+use rustls_pemfile::certs;
 use tokio::net::TcpListener;
-use tokio::sync::Semaphore;
-use tokio_rustls::rustls::{self, ServerConfig};
+use tokio::sync::{watch, Semaphore};
+use tokio::time::timeout;
+use tokio_rustls::rustls::server::WebPkiClientVerifier;
+use tokio_rustls::rustls::{self, RootCertStore, ServerConfig};
 use tokio_rustls::TlsAcceptor;
 
-use crate::auth::AuthManager;
-use handlers::HandlerFactory;
-pub use handlers::{DfSessionService, Parser};
+use crate::activity::DEFAULT_TRACK_ACTIVITY_QUERY_SIZE;
+use crate::hba::HbaRule;
+#[cfg(feature = "jwt")]
+use crate::jwt::JwtConfig;
+use crate::negotiation::{refuse_connection, refuse_unsupported_protocol};
+use crate::plan_cache::PlanCacheScope;
+use crate::proxy_protocol::{read_proxy_header, PROXY_HEADER_TIMEOUT};
+use crate::rate_limit::{RateLimit, RateLimiter};
+use crate::telemetry::SpanExporter;
+use crate::tls::{SniCertResolver, SniCertificate};
+use crate::wire_debug::WireDebug;
+use arrow_pg::datatypes::df::EncodeOptions;
+pub use handlers::{
+    DfCopyHandler, DfErrorHandler, DfSessionService, DfStartupHandler, HandlerFactory, Parser,
+    SimpleStartupHandler, TracedStartupHandler,
+};
+pub use server::{DfPostgresServer, DfPostgresServerBuilder};
+pub use sql::{normalize, query_id};
 
 /// re-exports
 pub use arrow_pg;
 pub use pgwire;
 
-#[derive(Getters, Setters, WithSetters, Debug)]
+#[derive(Getters, Setters, WithSetters, Debug, Clone)]
 #[getset(get = "pub", set = "pub", set_with = "pub")]
 pub struct ServerOptions {
     host: String,
     port: u16,
     tls_cert_path: Option<String>,
     tls_key_path: Option<String>,
+    /// CA certificates client certificates are verified against, requested
+    /// from clients when set
+    tls_client_ca_path: Option<String>,
+    /// Certificates served to clients by the SNI hostname they connect to,
+    /// the certificate and key paths are served to other clients
+    tls_sni_certificates: Vec<SniCertificate>,
+    /// Reload certificate and key files when they change on disk. Reloaded
+    /// and SNI certificates differ from the certificate SCRAM-SHA-256-PLUS
+    /// binds to, so channel binding is only offered without either.
+    tls_reload: bool,
     max_connections: usize,
+    /// Connection attempts allowed per client address
+    connection_rate_limit: Option<RateLimit>,
+    /// Read the client address of connections from the PROXY protocol v1 or
+    /// v2 header a load balancer like HAProxy sends ahead of them. Only for
+    /// listeners reached through such a proxy, as clients could send any
+    /// address.
+    proxy_protocol: bool,
+    /// Rules of the client addresses that may connect with or without TLS,
+    /// like the `host`, `hostssl` and `hostnossl` lines of `pg_hba.conf`.
+    /// Empty lets all clients connect.
+    hba_rules: Vec<HbaRule>,
+    /// File of the roles `CREATE ROLE`, `ALTER ROLE` and `DROP ROLE`
+    /// manage, read when the server starts and written after each change.
+    /// Only for the `AuthManager` the server creates, see [`roles`].
+    credentials_file: Option<String>,
+    /// Directory of the local files `COPY ... TO` may write, `None` allows
+    /// only URLs of registered object stores
+    copy_export_root: Option<String>,
+    /// Directory of the local files `CREATE EXTERNAL TABLE` may read, `None`
+    /// allows only URLs of registered object stores
+    import_root: Option<String>,
+    /// Statements allowed per user and client address
+    statement_rate_limit: Option<RateLimit>,
+    /// Rows per record batch of query results, `None` for the batch size of
+    /// the `SessionContext`
+    result_batch_size: Option<usize>,
+    /// Bytes of encoded rows handed over to a connection at once
+    result_buffer_size: usize,
+    /// Bytes of the largest value sent in a field of a query result
+    max_field_size: usize,
+    /// Number of query plans to cache, 0 disables the plan cache
+    plan_cache_size: usize,
+    plan_cache_scope: PlanCacheScope,
+    /// Bytes of query results to cache, 0 disables the result cache
+    result_cache_size: usize,
+    /// Time results are served from the result cache
+    result_cache_ttl: Duration,
+    /// Execution slots statements share by the scheduling weight of their
+    /// roles, `None` for no limit
+    execution_slots: Option<u32>,
+    /// Statements that may wait for execution slots or for the concurrency
+    /// limit of their user, more are refused with SQLSTATE 53300. `None`
+    /// for no limit.
+    max_waiting_statements: Option<usize>,
+    /// Time statements may wait before they are canceled with SQLSTATE
+    /// 57014, `None` for no limit
+    statement_wait_timeout: Option<Duration>,
+    /// Bytes of statement text shown in `pg_stat_activity.query`
+    track_activity_query_size: usize,
+    /// Hook receiving the spans of request phases, e.g. to export them to
+    /// OpenTelemetry
+    span_exporter: Option<Arc<dyn SpanExporter>>,
+    /// Interval of the progress notices sent while a query hasn't produced
+    /// its first row, `None` sends none
+    progress_notice_interval: Option<Duration>,
+    /// Log the protocol messages of all sessions
+    wire_debug: WireDebug,
+    /// Authenticate clients with JSON Web Tokens sent as password
+    #[cfg(feature = "jwt")]
+    jwt: Option<JwtConfig>,
 }
 
 impl ServerOptions {
@@ -51,46 +188,90 @@ impl Default for ServerOptions {
             port: 5432,
             tls_cert_path: None,
             tls_key_path: None,
+            tls_client_ca_path: None,
+            tls_sni_certificates: vec![],
+            tls_reload: true,
             max_connections: 0, // 0 = no limit
+            connection_rate_limit: None,
+            proxy_protocol: false,
+            hba_rules: vec![],
+            credentials_file: None,
+            copy_export_root: None,
+            import_root: None,
+            statement_rate_limit: None,
+            result_batch_size: None,
+            result_buffer_size: EncodeOptions::default().buffer_size,
+            max_field_size: EncodeOptions::default().max_field_size,
+            plan_cache_size: 0,
+            plan_cache_scope: PlanCacheScope::default(),
+            result_cache_size: 0,
+            result_cache_ttl: Duration::from_secs(60),
+            execution_slots: None,
+            max_waiting_statements: None,
+            statement_wait_timeout: None,
+            track_activity_query_size: DEFAULT_TRACK_ACTIVITY_QUERY_SIZE,
+            span_exporter: None,
+            progress_notice_interval: None,
+            wire_debug: WireDebug::Off,
+            #[cfg(feature = "jwt")]
+            jwt: None,
         }
     }
 }
 
-/// Set up TLS configuration if certificate and key paths are provided
-fn setup_tls(cert_path: &str, key_path: &str) -> Result<TlsAcceptor, IOError> {
+/// Set up TLS configuration serving the default certificate and key paths
+/// and the certificates of SNI hostnames, reloaded when they change if
+/// `reload`, verifying the certificates of clients against the CA
+/// certificates of `client_ca_path`
+fn setup_tls(
+    default: Option<(&str, &str)>,
+    sni_certificates: &[SniCertificate],
+    reload: bool,
+    client_ca_path: Option<&str>,
+) -> Result<TlsAcceptor, IOError> {
     // Install ring crypto provider for rustls
     let _ = rustls::crypto::ring::default_provider().install_default();
 
-    let cert = certs(&mut BufReader::new(File::open(cert_path)?))
-        .collect::<Result<Vec<CertificateDer>, IOError>>()?;
+    let resolver = SniCertResolver::new(default, sni_certificates, reload)?;
 
-    let key = pkcs8_private_keys(&mut BufReader::new(File::open(key_path)?))
-        .map(|key| key.map(PrivateKeyDer::from))
-        .collect::<Result<Vec<PrivateKeyDer>, IOError>>()?
-        .into_iter()
-        .next()
-        .ok_or_else(|| IOError::new(ErrorKind::InvalidInput, "No private key found"))?;
-
-    let config = ServerConfig::builder()
-        .with_no_client_auth()
-        .with_single_cert(cert, key)
-        .map_err(|err| IOError::new(ErrorKind::InvalidInput, err))?;
+    let builder = ServerConfig::builder();
+    let builder = match client_ca_path {
+        Some(client_ca_path) => {
+            let mut roots = RootCertStore::empty();
+            for ca_cert in certs(&mut BufReader::new(File::open(client_ca_path)?)) {
+                roots
+                    .add(ca_cert?)
+                    .map_err(|err| IOError::new(ErrorKind::InvalidInput, err))?;
+            }
+            // clients without a certificate may still connect, it's up to
+            // the authentication to require one
+            let verifier = WebPkiClientVerifier::builder(Arc::new(roots))
+                .allow_unauthenticated()
+                .build()
+                .map_err(|err| IOError::new(ErrorKind::InvalidInput, err))?;
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+    let config = builder.with_cert_resolver(Arc::new(resolver));
 
     Ok(TlsAcceptor::from(Arc::new(config)))
 }
 
 /// Serve the Datafusion `SessionContext` with Postgres protocol.
+///
+/// Use [`DfPostgresServer::builder`] to embed the server in a larger service
+/// and shut it down.
 pub async fn serve(
     session_context: Arc<SessionContext>,
     opts: &ServerOptions,
 ) -> Result<(), std::io::Error> {
-    // Create authentication manager
-    let auth_manager = Arc::new(AuthManager::new());
-
-    // Create the handler factory with authentication
-    let factory = Arc::new(HandlerFactory::new(session_context, auth_manager));
-
-    serve_with_handlers(factory, opts).await
+    DfPostgresServer::builder()
+        .session_context(session_context)
+        .options(opts.clone())
+        .build()?
+        .serve()
+        .await
 }
 
 /// Serve with custom pgwire handlers
@@ -102,27 +283,90 @@ pub async fn serve_with_handlers(
     handlers: Arc<impl PgWireServerHandlers + Sync + Send + 'static>,
     opts: &ServerOptions,
 ) -> Result<(), std::io::Error> {
-    // Set up TLS if configured
-    let tls_acceptor =
-        if let (Some(cert_path), Some(key_path)) = (&opts.tls_cert_path, &opts.tls_key_path) {
-            match setup_tls(cert_path, key_path) {
-                Ok(acceptor) => {
+    // never shut down
+    let (_shutdown, shutdown_receiver) = watch::channel(false);
+    let listener = Listener {
+        addr: format!("{}:{}", opts.host, opts.port),
+        tls_acceptor: tls_acceptor(opts),
+        connections: Arc::new(AtomicUsize::new(0)),
+        shutdown: shutdown_receiver,
+    };
+    serve_connections(handlers, opts, listener, None).await
+}
+
+/// TLS acceptor of the certificate and key paths and the SNI certificates of
+/// the options, if set
+fn tls_acceptor(opts: &ServerOptions) -> Option<TlsAcceptor> {
+    let default = match (&opts.tls_cert_path, &opts.tls_key_path) {
+        (Some(cert_path), Some(key_path)) => Some((cert_path.as_str(), key_path.as_str())),
+        _ => None,
+    };
+    if default.is_some() || !opts.tls_sni_certificates.is_empty() {
+        let client_ca_path = opts.tls_client_ca_path.as_deref();
+        match setup_tls(
+            default,
+            &opts.tls_sni_certificates,
+            opts.tls_reload,
+            client_ca_path,
+        ) {
+            Ok(acceptor) => {
+                if let Some((cert_path, key_path)) = default {
                     info!("TLS enabled using cert: {cert_path} and key: {key_path}");
-                    Some(acceptor)
                 }
-                Err(e) => {
-                    warn!("Failed to setup TLS: {e}. Running without encryption.");
-                    None
+                for certificate in &opts.tls_sni_certificates {
+                    info!(
+                        "TLS enabled for {} using cert: {} and key: {}",
+                        certificate.hostname, certificate.cert_path, certificate.key_path
+                    );
+                }
+                if let Some(client_ca_path) = client_ca_path {
+                    info!("Verifying client certificates against CA: {client_ca_path}");
                 }
+                Some(acceptor)
             }
-        } else {
-            info!("TLS not configured. Running without encryption.");
-            None
-        };
+            Err(e) => {
+                warn!("Failed to setup TLS: {e}. Running without encryption.");
+                None
+            }
+        }
+    } else {
+        info!("TLS not configured. Running without encryption.");
+        None
+    }
+}
+
+/// Where and how connections are accepted
+struct Listener {
+    addr: String,
+    tls_acceptor: Option<TlsAcceptor>,
+    /// Number of open connections
+    connections: Arc<AtomicUsize>,
+    /// Stops accepting connections once set
+    shutdown: watch::Receiver<bool>,
+}
+
+/// Decrements the connection count when a connection ends
+struct ConnectionGuard(Arc<AtomicUsize>);
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Accept connections, recording their sessions in the session service if
+/// given
+async fn serve_connections(
+    handlers: Arc<impl PgWireServerHandlers + Sync + Send + 'static>,
+    opts: &ServerOptions,
+    mut listener: Listener,
+    sessions: Option<Arc<DfSessionService>>,
+) -> Result<(), std::io::Error> {
+    let tls_acceptor = listener.tls_acceptor;
 
     // Bind to the specified host and port
-    let server_addr = format!("{}:{}", opts.host, opts.port);
-    let listener = TcpListener::bind(&server_addr).await?;
+    let server_addr = listener.addr;
+    let tcp_listener = TcpListener::bind(&server_addr).await?;
     if tls_acceptor.is_some() {
         info!("Listening on {server_addr} with TLS encryption");
     } else {
@@ -137,21 +381,83 @@ pub async fn serve_with_handlers(
         None
     };
 
-    // Accept incoming connections
+    // Connection attempt rate limiter (if configured)
+    let connection_rate_limiter = opts
+        .connection_rate_limit
+        .map(|limit| Arc::new(RateLimiter::new(limit)));
+    let proxy_protocol = opts.proxy_protocol;
+
+    // Accept incoming connections until shut down
     loop {
-        match listener.accept().await {
-            Ok((socket, addr)) => {
+        // a dropped sender shuts the server down too
+        if *listener.shutdown.borrow() || listener.shutdown.has_changed().is_err() {
+            info!("Shut down, no longer listening on {server_addr}");
+            return Ok(());
+        }
+        let accepted = match select(
+            pin!(tcp_listener.accept()),
+            pin!(listener.shutdown.changed()),
+        )
+        .await
+        {
+            Either::Left((accepted, _)) => accepted,
+            Either::Right(_) => continue,
+        };
+        match accepted {
+            Ok((mut socket, peer)) => {
                 let factory_ref = handlers.clone();
                 let tls_acceptor_ref = tls_acceptor.clone();
                 let limiter_ref = connection_limiter.clone();
+                let rate_limiter_ref = connection_rate_limiter.clone();
+                let sessions_ref = sessions.clone();
+                listener.connections.fetch_add(1, Ordering::Relaxed);
+                let connection = ConnectionGuard(listener.connections.clone());
 
                 tokio::spawn(async move {
+                    let _connection = connection;
+                    // the address of the client a proxy forwards
+                    let addr = if proxy_protocol {
+                        let rejection =
+                            match timeout(PROXY_HEADER_TIMEOUT, read_proxy_header(&mut socket))
+                                .await
+                            {
+                                Ok(Ok(client_addr)) => Ok(client_addr.unwrap_or(peer)),
+                                Ok(Err(e)) => Err(format!("invalid PROXY protocol header: {e}")),
+                                Err(_) => Err("no PROXY protocol header".to_string()),
+                            };
+                        match rejection {
+                            Ok(addr) => addr,
+                            Err(message) => {
+                                warn!("Connection rejected from {peer}: {message}");
+                                // protocol_violation
+                                let _ = refuse_connection(&mut socket, "08P01", message).await;
+                                return;
+                            }
+                        }
+                    } else {
+                        peer
+                    };
+                    if let Some(ref rate_limiter) = rate_limiter_ref {
+                        if !rate_limiter.try_acquire(addr.ip()) {
+                            warn!(
+                                "Connection rejected from {addr}: connection rate limit exceeded"
+                            );
+                            let message = "connection rate limit exceeded".to_string();
+                            // too_many_connections
+                            let _ = refuse_connection(&mut socket, "53300", message).await;
+                            return;
+                        }
+                    }
+
                     // Check connection limit if configured
                     let _permit = if let Some(ref semaphore) = limiter_ref {
                         match semaphore.try_acquire() {
                             Ok(permit) => Some(permit),
                             Err(_) => {
                                 warn!("Connection rejected from {addr}: max connections ({max_conn_count}) reached");
+                                let message = "sorry, too many clients already".to_string();
+                                // too_many_connections
+                                let _ = refuse_connection(&mut socket, "53300", message).await;
                                 return;
                             }
                         }
@@ -159,9 +465,19 @@ pub async fn serve_with_handlers(
                         None
                     };
 
-                    if let Err(e) = process_socket(socket, tls_acceptor_ref, factory_ref).await {
+                    if let Some(sessions) = &sessions_ref {
+                        sessions.start_proxied_session(peer, addr);
+                    }
+                    let processed = match refuse_unsupported_protocol(&mut socket).await {
+                        Ok(false) => process_socket(socket, tls_acceptor_ref, factory_ref).await,
+                        refused => refused.map(|_| ()),
+                    };
+                    if let Err(e) = processed {
                         warn!("Error processing socket from {addr}: {e}");
                     }
+                    if let Some(sessions) = &sessions_ref {
+                        sessions.end_session(&peer);
+                    }
                     // Permit is automatically released when _permit is dropped
                 });
             }
@@ -191,4 +507,17 @@ mod tests {
         let opts_no_limit = ServerOptions::new().with_max_connections(0);
         assert_eq!(opts_no_limit.max_connections, 0);
     }
+
+    #[test]
+    fn test_server_options_rate_limits() {
+        let opts = ServerOptions::new();
+        assert!(opts.connection_rate_limit().is_none());
+        assert!(opts.statement_rate_limit().is_none());
+
+        let opts = ServerOptions::new()
+            .with_connection_rate_limit(Some(RateLimit::new(10.0, 20).unwrap()))
+            .with_statement_rate_limit(Some(RateLimit::new(100.0, 200).unwrap()));
+        assert_eq!(opts.connection_rate_limit().unwrap().burst, 20);
+        assert_eq!(opts.statement_rate_limit().unwrap().per_second, 100.0);
+    }
 }
@@ -0,0 +1,269 @@
+use std::sync::Arc;
+
+use datafusion::error::DataFusionError;
+use datafusion::execution::memory_pool::GreedyMemoryPool;
+use datafusion::execution::runtime_env::RuntimeEnv;
+use datafusion::execution::SessionStateBuilder;
+use datafusion::prelude::DataFrame;
+use futures::StreamExt;
+use pgwire::api::results::QueryResponse;
+use pgwire::error::{ErrorInfo, PgWireError};
+
+/// Resource limits of a single query
+///
+/// Limits are configured per role with `AuthManager::set_role_query_limits`.
+/// A query exceeding its memory limit is aborted with SQLSTATE 53200
+/// (out_of_memory), one returning more than `max_rows` rows with SQLSTATE
+/// 53400 (configuration_limit_exceeded). Statements over the concurrency limit
+/// of the user wait for one of the running statements to finish.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct QueryLimits {
+    /// Maximum memory in bytes operators of the query may reserve, like
+    /// postgres' `work_mem`. Operators able to spill go to disk instead of
+    /// failing.
+    pub memory_limit: Option<usize>,
+    /// Maximum number of rows the query may return
+    pub max_rows: Option<usize>,
+    /// Maximum number of statements the user may run at once across all of
+    /// their connections
+    pub max_concurrent_statements: Option<usize>,
+    /// Execution slots a statement takes when the server limits them, so
+    /// heavy batch roles leave room for interactive ones. Defaults to 1.
+    pub scheduling_weight: Option<u32>,
+}
+
+impl QueryLimits {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_memory_limit(mut self, bytes: usize) -> Self {
+        self.memory_limit = Some(bytes);
+        self
+    }
+
+    pub fn with_max_rows(mut self, rows: usize) -> Self {
+        self.max_rows = Some(rows);
+        self
+    }
+
+    pub fn with_max_concurrent_statements(mut self, statements: usize) -> Self {
+        self.max_concurrent_statements = Some(statements);
+        self
+    }
+
+    pub fn with_scheduling_weight(mut self, weight: u32) -> Self {
+        self.scheduling_weight = Some(weight);
+        self
+    }
+
+    /// Combine limits of two roles, keeping the more generous one of each
+    /// limit configured by both
+    pub(crate) fn union(self, other: QueryLimits) -> QueryLimits {
+        fn most_generous(a: Option<usize>, b: Option<usize>) -> Option<usize> {
+            match (a, b) {
+                (Some(a), Some(b)) => Some(a.max(b)),
+                (a, b) => a.or(b),
+            }
+        }
+
+        QueryLimits {
+            memory_limit: most_generous(self.memory_limit, other.memory_limit),
+            max_rows: most_generous(self.max_rows, other.max_rows),
+            max_concurrent_statements: most_generous(
+                self.max_concurrent_statements,
+                other.max_concurrent_statements,
+            ),
+            // the lightest weight is the most generous
+            scheduling_weight: match (self.scheduling_weight, other.scheduling_weight) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            },
+        }
+    }
+
+    /// Cap the limits with `other`, keeping the stricter one of each limit
+    /// configured by both
+    pub(crate) fn intersection(self, other: QueryLimits) -> QueryLimits {
+        fn strictest(a: Option<usize>, b: Option<usize>) -> Option<usize> {
+            match (a, b) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            }
+        }
+
+        QueryLimits {
+            memory_limit: strictest(self.memory_limit, other.memory_limit),
+            max_rows: strictest(self.max_rows, other.max_rows),
+            max_concurrent_statements: strictest(
+                self.max_concurrent_statements,
+                other.max_concurrent_statements,
+            ),
+            scheduling_weight: match (self.scheduling_weight, other.scheduling_weight) {
+                (Some(a), Some(b)) => Some(a.max(b)),
+                (a, b) => a.or(b),
+            },
+        }
+    }
+
+    /// Run the dataframe with a memory pool of its own, sized to the memory
+    /// limit
+    pub(crate) fn apply_memory_limit(&self, df: DataFrame) -> DataFrame {
+        let Some(memory_limit) = self.memory_limit else {
+            return df;
+        };
+
+        let (state, plan) = df.into_parts();
+        let runtime = state.runtime_env();
+        let runtime = Arc::new(RuntimeEnv {
+            memory_pool: Arc::new(GreedyMemoryPool::new(memory_limit)),
+            disk_manager: runtime.disk_manager.clone(),
+            cache_manager: runtime.cache_manager.clone(),
+            object_store_registry: runtime.object_store_registry.clone(),
+        });
+        let state = SessionStateBuilder::new_from_existing(state)
+            .with_runtime_env(runtime)
+            .build();
+        DataFrame::new(state, plan)
+    }
+
+    /// Abort the response once it exceeds the row limit, and report memory
+    /// exhaustion with the postgres error code
+    pub(crate) fn guard_response<'a>(&self, resp: QueryResponse<'a>) -> QueryResponse<'a> {
+        let max_rows = self.max_rows;
+        let fields = resp.row_schema();
+        let mut rows = 0usize;
+        let data_rows = resp.data_rows().map(move |row| {
+            let row = row.map_err(map_resource_error)?;
+            rows += 1;
+            match max_rows {
+                Some(max_rows) if rows > max_rows => {
+                    Err(PgWireError::UserError(Box::new(ErrorInfo::new(
+                        "ERROR".to_string(),
+                        "53400".to_string(), // configuration_limit_exceeded
+                        format!("query returned more than {max_rows} rows"),
+                    ))))
+                }
+                _ => Ok(row),
+            }
+        });
+        // stop at the first error instead of polling the plan further
+        let mut failed = false;
+        let data_rows = data_rows.take_while(move |row| {
+            let take = !failed;
+            failed = row.is_err();
+            futures::future::ready(take)
+        });
+        QueryResponse::new(fields, data_rows)
+    }
+}
+
+/// Map a datafusion memory exhaustion error to SQLSTATE 53200
+pub(crate) fn map_resource_error(error: PgWireError) -> PgWireError {
+    let PgWireError::ApiError(source) = &error else {
+        return error;
+    };
+    match source
+        .downcast_ref::<DataFusionError>()
+        .map(DataFusionError::find_root)
+    {
+        Some(DataFusionError::ResourcesExhausted(message)) => {
+            PgWireError::UserError(Box::new(ErrorInfo::new(
+                "ERROR".to_string(),
+                "53200".to_string(), // out_of_memory
+                format!("out of memory: {message}"),
+            )))
+        }
+        _ => error,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datafusion::execution::memory_pool::MemoryLimit;
+    use datafusion::prelude::SessionContext;
+    use pgwire::api::portal::Format;
+
+    fn sqlstate(error: &PgWireError) -> Option<&str> {
+        match error {
+            PgWireError::UserError(info) => Some(&info.code),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_union() {
+        let analyst = QueryLimits::new()
+            .with_memory_limit(1024)
+            .with_max_rows(10)
+            .with_scheduling_weight(4);
+        let reporter = QueryLimits::new()
+            .with_max_rows(100)
+            .with_max_concurrent_statements(2)
+            .with_scheduling_weight(1);
+        assert_eq!(
+            analyst.union(reporter),
+            QueryLimits {
+                memory_limit: Some(1024),
+                max_rows: Some(100),
+                max_concurrent_statements: Some(2),
+                scheduling_weight: Some(1),
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_memory_limit() {
+        let ctx = SessionContext::new();
+        let df = ctx.sql("SELECT 1").await.unwrap();
+        let df = QueryLimits::new()
+            .with_memory_limit(4096)
+            .apply_memory_limit(df);
+        let (state, _) = df.into_parts();
+        assert!(matches!(
+            state.runtime_env().memory_pool.memory_limit(),
+            MemoryLimit::Finite(4096)
+        ));
+
+        let error = map_resource_error(PgWireError::ApiError(Box::new(
+            DataFusionError::ResourcesExhausted("sort".to_string()),
+        )));
+        assert_eq!(sqlstate(&error), Some("53200"));
+    }
+
+    #[tokio::test]
+    async fn test_row_limit() {
+        let ctx = SessionContext::new();
+        let limits = QueryLimits::new().with_max_rows(2);
+
+        let df = ctx
+            .sql("SELECT * FROM generate_series(1, 2)")
+            .await
+            .unwrap();
+        let resp = arrow_pg::datatypes::df::encode_dataframe(df, &Format::UnifiedText)
+            .await
+            .unwrap();
+        let rows = limits
+            .guard_response(resp)
+            .data_rows()
+            .collect::<Vec<_>>()
+            .await;
+        assert!(rows.iter().all(|row| row.is_ok()));
+
+        let df = ctx
+            .sql("SELECT * FROM generate_series(1, 5)")
+            .await
+            .unwrap();
+        let resp = arrow_pg::datatypes::df::encode_dataframe(df, &Format::UnifiedText)
+            .await
+            .unwrap();
+        let rows = limits
+            .guard_response(resp)
+            .data_rows()
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(rows.len(), 3);
+        assert_eq!(sqlstate(rows[2].as_ref().unwrap_err()), Some("53400"));
+    }
+}
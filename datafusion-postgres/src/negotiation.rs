@@ -0,0 +1,137 @@
+//! Negotiation of the protocol version with clients asking for one the
+//! server does not speak
+//!
+//! Clients newer than the server ask for a later minor version of the
+//! protocol or for `_pq_.` protocol extensions. They are answered with
+//! `NegotiateProtocolVersion`, listing the newest minor version the server
+//! speaks and the extensions it ignored, and go on with that version.
+//! Clients of another major version can't be served, but are told so with a
+//! FATAL error they understand rather than a closed connection.
+
+use std::fmt::Debug;
+use std::time::Duration;
+
+use bytes::{BufMut, BytesMut};
+use futures::{Sink, SinkExt};
+use pgwire::api::ClientInfo;
+use pgwire::error::{ErrorInfo, PgWireError, PgWireResult};
+use pgwire::messages::response::ErrorResponse;
+use pgwire::messages::startup::{NegotiateProtocolVersion, Startup};
+use pgwire::messages::{Message, PgWireBackendMessage, ProtocolVersion};
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+
+/// Prefix of the startup parameters naming protocol extensions
+const PROTOCOL_EXTENSION_PREFIX: &str = "_pq_.";
+
+/// Major version of the codes of the SSL, GSS encryption and cancel requests
+/// sent in place of a startup message
+const REQUEST_CODE_MAJOR: u16 = 1234;
+
+/// Time clients have to send their first message, as long as pgwire gives
+/// them to start up
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// First byte of a TLS handshake of clients connecting with direct SSL
+const TLS_HANDSHAKE: u8 = 0x16;
+
+/// Negotiate the protocol version of the startup message of a client
+///
+/// Sends `NegotiateProtocolVersion` when the client asked for a minor
+/// version the server doesn't speak, or for protocol extensions, and rewrites
+/// `startup` to the negotiated version without the extensions for the
+/// startup handlers of pgwire to go on with it.
+pub(crate) async fn negotiate_protocol<C>(client: &mut C, startup: &mut Startup) -> PgWireResult<()>
+where
+    C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send,
+    C::Error: Debug,
+    PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+{
+    let (major, latest_minor) = ProtocolVersion::default().version_number();
+    if startup.protocol_number_major != major {
+        return Ok(());
+    }
+
+    let requested_minor = startup.protocol_number_minor;
+    // 3.1 was never released, clients asking for it speak 3.0
+    let minor = if requested_minor >= latest_minor {
+        latest_minor
+    } else {
+        0
+    };
+    let extensions = startup
+        .parameters
+        .keys()
+        .filter(|name| name.starts_with(PROTOCOL_EXTENSION_PREFIX))
+        .cloned()
+        .collect::<Vec<_>>();
+    startup
+        .parameters
+        .retain(|name, _| !name.starts_with(PROTOCOL_EXTENSION_PREFIX));
+
+    if minor != requested_minor || !extensions.is_empty() {
+        client
+            .send(PgWireBackendMessage::NegotiateProtocolVersion(
+                NegotiateProtocolVersion::new(minor as i32, extensions),
+            ))
+            .await?;
+    }
+    startup.protocol_number_minor = minor;
+    Ok(())
+}
+
+/// Refuse a client whose first message is the startup message of another
+/// major version of the protocol, which pgwire can't decode
+///
+/// Peeks at the first message without consuming it. Returns whether the
+/// connection is to be closed: the client was refused, with a FATAL error in
+/// the format of its version, or sent nothing before the startup timeout.
+pub(crate) async fn refuse_unsupported_protocol(socket: &mut TcpStream) -> std::io::Result<bool> {
+    let mut header = [0u8; 8];
+    let Ok(peeked) = timeout(STARTUP_TIMEOUT, socket.peek(&mut header)).await else {
+        return Ok(true);
+    };
+    if peeked? < header.len() || header[0] == TLS_HANDSHAKE {
+        return Ok(false);
+    }
+    let major = u16::from_be_bytes([header[4], header[5]]);
+    let minor = u16::from_be_bytes([header[6], header[7]]);
+    let (latest_major, latest_minor) = ProtocolVersion::default().version_number();
+    if major == latest_major || major == REQUEST_CODE_MAJOR {
+        return Ok(false);
+    }
+
+    let message = format!(
+        "unsupported frontend protocol {major}.{minor}: server supports {latest_major}.0 to {latest_major}.{latest_minor}"
+    );
+    let mut buf = BytesMut::new();
+    if major < latest_major {
+        // the error message of the protocol 2.0
+        buf.put_u8(b'E');
+        buf.put_slice(format!("FATAL:  {message}\n").as_bytes());
+        buf.put_u8(0);
+    } else {
+        refuse_connection(socket, "0A000", message).await?; // feature_not_supported
+        return Ok(true);
+    }
+    socket.write_all(&buf).await?;
+    socket.shutdown().await?;
+    Ok(true)
+}
+
+/// Refuse a client before its startup with a FATAL error, which clients
+/// report rather than a closed connection
+pub(crate) async fn refuse_connection(
+    socket: &mut TcpStream,
+    code: &str,
+    message: String,
+) -> std::io::Result<()> {
+    let mut buf = BytesMut::new();
+    let info = ErrorInfo::new("FATAL".to_string(), code.to_string(), message);
+    ErrorResponse::from(info)
+        .encode(&mut buf)
+        .map_err(std::io::Error::other)?;
+    socket.write_all(&buf).await?;
+    socket.shutdown().await
+}
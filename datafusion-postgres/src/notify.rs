@@ -0,0 +1,118 @@
+//! Asynchronous notifications, as with `LISTEN` and `NOTIFY` of postgres.
+//!
+//! Sessions subscribe to channels with `LISTEN`. Notifications sent with
+//! `NOTIFY`, or by the server on [`SCHEMA_CHANGED_CHANNEL`] when tables,
+//! schemas or catalogs change, are queued for every session listening on the
+//! channel and delivered along the response to its next statement, which is
+//! when clients like psql check for them.
+
+use std::collections::{HashMap, HashSet};
+use std::net::SocketAddr;
+use std::sync::Mutex;
+
+use datafusion::logical_expr::{DdlStatement, LogicalPlan};
+use pgwire::messages::response::NotificationResponse;
+
+/// Channel notified when tables, schemas or catalogs are created or dropped,
+/// so long-lived clients can refresh their metadata
+pub const SCHEMA_CHANGED_CHANNEL: &str = "schema_changed";
+
+/// Payload of the schema change notification of the DDL `plan`, like
+/// `CREATE TABLE public.t`
+pub(crate) fn schema_change(plan: &LogicalPlan) -> String {
+    let LogicalPlan::Ddl(ddl) = plan else {
+        return String::new();
+    };
+    match ddl {
+        DdlStatement::CreateExternalTable(create) => format!("CREATE TABLE {}", create.name),
+        DdlStatement::CreateMemoryTable(create) => format!("CREATE TABLE {}", create.name),
+        DdlStatement::CreateView(create) => format!("CREATE VIEW {}", create.name),
+        DdlStatement::CreateCatalogSchema(create) => {
+            format!("CREATE SCHEMA {}", create.schema_name)
+        }
+        DdlStatement::CreateCatalog(create) => format!("CREATE DATABASE {}", create.catalog_name),
+        DdlStatement::DropTable(drop) => format!("DROP TABLE {}", drop.name),
+        DdlStatement::DropView(drop) => format!("DROP VIEW {}", drop.name),
+        DdlStatement::DropCatalogSchema(drop) => format!("DROP SCHEMA {}", drop.name),
+        ddl => ddl.name().to_string(),
+    }
+}
+
+/// The channels and pending notifications of sessions
+#[derive(Debug, Default)]
+pub(crate) struct Notifications {
+    sessions: Mutex<HashMap<SocketAddr, Listener>>,
+}
+
+#[derive(Debug, Default)]
+struct Listener {
+    channels: HashSet<String>,
+    pending: Vec<Notification>,
+}
+
+#[derive(Debug, Clone)]
+struct Notification {
+    pid: i32,
+    channel: String,
+    payload: String,
+}
+
+impl Notifications {
+    pub(crate) fn listen(&self, session: SocketAddr, channel: &str) {
+        self.sessions
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry(session)
+            .or_default()
+            .channels
+            .insert(channel.to_string());
+    }
+
+    /// Stop listening on `channel`, or on all channels for `*`
+    pub(crate) fn unlisten(&self, session: SocketAddr, channel: &str) {
+        let mut sessions = self.sessions.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(listener) = sessions.get_mut(&session) {
+            if channel == "*" {
+                listener.channels.clear();
+            } else {
+                listener.channels.remove(channel);
+            }
+        }
+    }
+
+    /// Queue a notification for the sessions listening on `channel`, `pid`
+    /// being the process ID of the notifying session
+    pub(crate) fn notify(&self, pid: i32, channel: &str, payload: &str) {
+        let notification = Notification {
+            pid,
+            channel: channel.to_string(),
+            payload: payload.to_string(),
+        };
+        let mut sessions = self.sessions.lock().unwrap_or_else(|e| e.into_inner());
+        for listener in sessions.values_mut() {
+            if listener.channels.contains(channel) {
+                listener.pending.push(notification.clone());
+            }
+        }
+    }
+
+    /// The notifications pending for `session`, in the order they were sent
+    pub(crate) fn take(&self, session: &SocketAddr) -> Vec<NotificationResponse> {
+        let mut sessions = self.sessions.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(listener) = sessions.get_mut(session) else {
+            return Vec::new();
+        };
+        listener
+            .pending
+            .drain(..)
+            .map(|n| NotificationResponse::new(n.pid, n.channel, n.payload))
+            .collect()
+    }
+
+    pub(crate) fn end_session(&self, session: &SocketAddr) {
+        self.sessions
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(session);
+    }
+}
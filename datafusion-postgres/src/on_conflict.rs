@@ -0,0 +1,236 @@
+//! `INSERT ... ON CONFLICT`, which DataFusion doesn't plan.
+//!
+//! Tables of DataFusion don't enforce keys, so `ON CONFLICT (id) DO NOTHING`
+//! is answered by inserting only the rows whose `id` is in neither the table
+//! nor a row inserted before them. Without columns, the rows conflict on the
+//! primary key and unique columns of the table, like those of
+//! `CREATE TABLE users (id INT PRIMARY KEY, ...)`. `DO UPDATE` and
+//! `ON CONSTRAINT` are refused with a `0A000` error.
+
+use std::sync::Arc;
+
+use datafusion::common::{plan_err, Column, Constraint, Result};
+use datafusion::execution::context::SessionState;
+use datafusion::logical_expr::{DmlStatement, Expr, JoinType, LogicalPlan, LogicalPlanBuilder};
+use datafusion::sql::parser::Statement as DFStatement;
+use datafusion::sql::planner::IdentNormalizer;
+use datafusion::sql::sqlparser::ast::{
+    ConflictTarget, Ident, Insert, OnConflict, OnConflictAction, OnInsert, Statement,
+};
+use pgwire::error::{ErrorInfo, PgWireError};
+use pgwire::messages::response::NoticeResponse;
+
+const ALTERNATIVES: &str = "Use ON CONFLICT DO NOTHING to skip the rows that conflict, \
+                            or delete them before inserting.";
+
+/// An `ON CONFLICT` clause DataFusion can't honor
+#[derive(Debug)]
+pub(crate) struct UnsupportedOnConflict(&'static str);
+
+impl UnsupportedOnConflict {
+    /// The notice suggesting alternatives, sent before the error
+    pub(crate) fn notice(&self) -> NoticeResponse {
+        NoticeResponse::from(ErrorInfo::new(
+            "NOTICE".to_string(),
+            "00000".to_string(),
+            ALTERNATIVES.to_string(),
+        ))
+    }
+
+    pub(crate) fn into_error(self) -> PgWireError {
+        let mut info = ErrorInfo::new(
+            "ERROR".to_string(),
+            "0A000".to_string(), // feature_not_supported
+            format!("INSERT ... {} is not supported", self.0),
+        );
+        info.hint = Some(ALTERNATIVES.to_string());
+        PgWireError::UserError(Box::new(info))
+    }
+}
+
+/// The `ON CONFLICT` clause of `statement` which isn't supported
+pub(crate) fn unsupported_on_conflict(statement: &Statement) -> Option<UnsupportedOnConflict> {
+    let Statement::Insert(Insert {
+        on: Some(on_insert),
+        ..
+    }) = statement
+    else {
+        return None;
+    };
+    match on_insert {
+        OnInsert::OnConflict(OnConflict {
+            conflict_target: Some(ConflictTarget::OnConstraint(_)),
+            ..
+        }) => Some(UnsupportedOnConflict("ON CONFLICT ON CONSTRAINT")),
+        OnInsert::OnConflict(OnConflict {
+            action: OnConflictAction::DoUpdate(_),
+            ..
+        }) => Some(UnsupportedOnConflict("ON CONFLICT DO UPDATE")),
+        OnInsert::OnConflict(_) => None,
+        _ => Some(UnsupportedOnConflict("ON DUPLICATE KEY UPDATE")),
+    }
+}
+
+/// Plan `statement` like `SessionState::statement_to_plan`, skipping the rows
+/// of an `INSERT ... ON CONFLICT DO NOTHING` which conflict
+pub(crate) async fn statement_to_plan(
+    state: &SessionState,
+    statement: Statement,
+) -> Result<LogicalPlan> {
+    match split_on_conflict(&statement) {
+        Some((insert, columns)) => {
+            let insert = state
+                .statement_to_plan(DFStatement::Statement(Box::new(insert)))
+                .await?;
+            skip_conflicts(insert, columns.as_deref())
+        }
+        None => {
+            state
+                .statement_to_plan(DFStatement::Statement(Box::new(statement)))
+                .await
+        }
+    }
+}
+
+/// The `INSERT` of `statement` without its `ON CONFLICT DO NOTHING`, and the
+/// columns the rows conflict on, `None` for other statements
+fn split_on_conflict(statement: &Statement) -> Option<(Statement, Option<Vec<Ident>>)> {
+    let Statement::Insert(insert) = statement else {
+        return None;
+    };
+    let Some(OnInsert::OnConflict(OnConflict {
+        conflict_target,
+        action: OnConflictAction::DoNothing,
+    })) = &insert.on
+    else {
+        return None;
+    };
+    let columns = match conflict_target {
+        Some(ConflictTarget::Columns(columns)) => Some(columns.clone()),
+        Some(ConflictTarget::OnConstraint(_)) => return None,
+        None => None,
+    };
+    let insert = Insert {
+        on: None,
+        ..insert.clone()
+    };
+    Some((Statement::Insert(insert), columns))
+}
+
+/// The `INSERT` plan `insert` skipping the rows conflicting on `columns`, or
+/// else on the primary key and unique columns of the table
+fn skip_conflicts(insert: LogicalPlan, columns: Option<&[Ident]>) -> Result<LogicalPlan> {
+    let LogicalPlan::Dml(insert) = insert else {
+        return plan_err!("ON CONFLICT is only supported by INSERT");
+    };
+    let schema = insert.target.schema();
+    let keys = match columns {
+        Some(columns) => {
+            let normalizer = IdentNormalizer::default();
+            let key = columns
+                .iter()
+                .map(|column| {
+                    let name = normalizer.normalize(column.clone());
+                    schema.field_with_name(&name)?;
+                    Ok(name)
+                })
+                .collect::<Result<Vec<_>>>()?;
+            vec![key]
+        }
+        None => insert
+            .target
+            .constraints()
+            .into_iter()
+            .flat_map(|constraints| constraints.iter())
+            .map(|constraint| match constraint {
+                Constraint::PrimaryKey(indices) | Constraint::Unique(indices) => indices
+                    .iter()
+                    .map(|i| schema.field(*i).name().clone())
+                    .collect(),
+            })
+            .collect(),
+    };
+
+    let mut rows = LogicalPlanBuilder::from(insert.input.as_ref().clone()).alias("inserted")?;
+    for key in keys {
+        let existing =
+            LogicalPlanBuilder::scan("existing", insert.target.clone(), None)?.build()?;
+        let on = key
+            .iter()
+            .map(|name| Expr::Column(Column::new(Some("inserted"), name)))
+            .collect();
+        let columns = rows
+            .schema()
+            .columns()
+            .into_iter()
+            .map(Expr::Column)
+            .collect();
+        rows = rows.distinct_on(on, columns, None)?.join(
+            existing,
+            JoinType::LeftAnti,
+            (
+                key.iter()
+                    .map(|name| Column::new(Some("inserted"), name))
+                    .collect(),
+                key.iter()
+                    .map(|name| Column::new(Some("existing"), name))
+                    .collect(),
+            ),
+            None,
+        )?;
+    }
+    Ok(LogicalPlan::Dml(DmlStatement {
+        input: Arc::new(rows.build()?),
+        ..insert
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql::parse;
+
+    fn parse_statement(sql: &str) -> Statement {
+        parse(sql).unwrap().remove(0)
+    }
+
+    #[test]
+    fn test_on_conflict_clauses() {
+        let (insert, columns) = split_on_conflict(&parse_statement(
+            "INSERT INTO users VALUES (1, 'ada') ON CONFLICT (id) DO NOTHING",
+        ))
+        .unwrap();
+        assert_eq!(insert.to_string(), "INSERT INTO users VALUES (1, 'ada')");
+        assert_eq!(columns, Some(vec![Ident::new("id")]));
+        let (_, columns) = split_on_conflict(&parse_statement(
+            "INSERT INTO users VALUES (1, 'ada') ON CONFLICT DO NOTHING",
+        ))
+        .unwrap();
+        assert_eq!(columns, None);
+        assert!(split_on_conflict(&parse_statement("INSERT INTO users VALUES (1)")).is_none());
+
+        for (sql, clause) in [
+            (
+                "INSERT INTO users VALUES (1, 'ada') ON CONFLICT (id) DO UPDATE SET name = 'ada'",
+                "ON CONFLICT DO UPDATE",
+            ),
+            (
+                "INSERT INTO users VALUES (1, 'ada') ON CONFLICT ON CONSTRAINT users_pkey DO NOTHING",
+                "ON CONFLICT ON CONSTRAINT",
+            ),
+        ] {
+            let unsupported = unsupported_on_conflict(&parse_statement(sql)).unwrap();
+            assert_eq!(unsupported.0, clause);
+            let PgWireError::UserError(info) = unsupported.into_error() else {
+                panic!("expected a user error");
+            };
+            assert_eq!(info.code, "0A000");
+            assert_eq!(info.message, format!("INSERT ... {clause} is not supported"));
+            assert!(info.hint.is_some());
+        }
+        assert!(unsupported_on_conflict(&parse_statement(
+            "INSERT INTO users VALUES (1, 'ada') ON CONFLICT DO NOTHING"
+        ))
+        .is_none());
+    }
+}
@@ -1,31 +1,59 @@
-use std::collections::HashMap;
-use std::sync::atomic::AtomicU32;
-use std::sync::Arc;
+use std::collections::{BTreeSet, HashMap};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, RwLock};
 
 use async_trait::async_trait;
 use datafusion::arrow::array::{
-    as_boolean_array, ArrayRef, BooleanArray, BooleanBuilder, RecordBatch, StringArray,
-    StringBuilder,
+    as_boolean_array, ArrayRef, AsArray, BooleanArray, BooleanBuilder, Int32Array, RecordBatch,
+    StringArray, StringBuilder,
 };
 use datafusion::arrow::datatypes::{DataType, Field, SchemaRef};
 use datafusion::arrow::ipc::reader::FileReader;
 use datafusion::catalog::streaming::StreamingTable;
 use datafusion::catalog::{CatalogProviderList, MemTable, SchemaProvider, TableFunctionImpl};
 use datafusion::common::utils::SingleRowListArrayBuilder;
+use datafusion::common::{plan_err, Constraints, ScalarValue};
 use datafusion::datasource::{TableProvider, ViewTable};
 use datafusion::error::{DataFusionError, Result};
+use datafusion::execution::{FunctionRegistry, SessionStateBuilder, TaskContext};
+use datafusion::logical_expr::planner::ExprPlanner;
 use datafusion::logical_expr::{ColumnarValue, ScalarUDF, Volatility};
+use datafusion::optimizer::AnalyzerRule;
 use datafusion::physical_plan::streaming::PartitionStream;
 use datafusion::prelude::{create_udf, Expr, SessionContext};
 use postgres_types::Oid;
-use tokio::sync::RwLock;
 
+use crate::activity::ActivityRegistry;
+use crate::advisory_locks::advisory_lock_udfs;
+use crate::auth::{AuthManager, ResourceType};
+use crate::tenant::TenantCatalogList;
+use key_filter::{FilteredTable, KeyFilteredTableProvider};
+
+mod array_udf;
+pub(crate) mod clock_udf;
+mod coercion;
+mod date_part_udf;
+mod format_udf;
+mod information_schema;
+mod interval_udf;
+mod json_udf;
+mod key_filter;
 mod pg_attribute;
 mod pg_class;
+mod pg_constraint;
 mod pg_database;
+mod pg_db_role_setting;
 mod pg_get_expr_udf;
+mod pg_locks;
 mod pg_namespace;
-mod pg_settings;
+mod pg_proc;
+mod pg_roles;
+pub(crate) mod pg_settings;
+mod pg_stat_activity;
+mod pg_stat_statements;
+mod pg_stats;
+pub(crate) mod pg_timezone_names;
+mod vector_types;
 
 const PG_CATALOG_TABLE_PG_AGGREGATE: &str = "pg_aggregate";
 const PG_CATALOG_TABLE_PG_AM: &str = "pg_am";
@@ -80,15 +108,28 @@ const PG_CATALOG_TABLE_PG_SEQUENCE: &str = "pg_sequence";
 const PG_CATALOG_TABLE_PG_SHDEPEND: &str = "pg_shdepend";
 const PG_CATALOG_TABLE_PG_SHDESCRIPTION: &str = "pg_shdescription";
 const PG_CATALOG_TABLE_PG_SHSECLABEL: &str = "pg_shseclabel";
+const PG_CATALOG_TABLE_PG_STAT_ACTIVITY: &str = "pg_stat_activity";
 const PG_CATALOG_TABLE_PG_STATISTIC: &str = "pg_statistic";
 const PG_CATALOG_TABLE_PG_STATISTIC_EXT: &str = "pg_statistic_ext";
 const PG_CATALOG_TABLE_PG_STATISTIC_EXT_DATA: &str = "pg_statistic_ext_data";
 const PG_CATALOG_TABLE_PG_SUBSCRIPTION: &str = "pg_subscription";
 const PG_CATALOG_TABLE_PG_SUBSCRIPTION_REL: &str = "pg_subscription_rel";
 const PG_CATALOG_TABLE_PG_TABLESPACE: &str = "pg_tablespace";
+const PG_CATALOG_TABLE_PG_TIMEZONE_NAMES: &str = "pg_timezone_names";
 const PG_CATALOG_TABLE_PG_TRIGGER: &str = "pg_trigger";
 const PG_CATALOG_TABLE_PG_USER_MAPPING: &str = "pg_user_mapping";
+const PG_CATALOG_VIEW_PG_LOCKS: &str = "pg_locks";
+const PG_CATALOG_VIEW_PG_ROLES: &str = "pg_roles";
 const PG_CATALOG_VIEW_PG_SETTINGS: &str = "pg_settings";
+const PG_CATALOG_VIEW_PG_STATS: &str = "pg_stats";
+const PG_CATALOG_VIEW_PG_STAT_STATEMENTS: &str = "pg_stat_statements";
+const PG_CATALOG_VIEW_PG_STAT_USER_TABLES: &str = "pg_stat_user_tables";
+
+// OIDs of the exported rows of postgres, used for rows added to pg_catalog
+const PG_CATALOG_NAMESPACE_OID: Oid = 11;
+const INFORMATION_SCHEMA_NAMESPACE_OID: Oid = 13283;
+const BOOTSTRAP_SUPERUSER_OID: Oid = 10;
+const INTERNAL_LANGUAGE_OID: Oid = 12;
 
 /// Determine PostgreSQL table type (relkind) from DataFusion TableProvider
 fn get_table_type(table: &Arc<dyn TableProvider>) -> &'static str {
@@ -175,32 +216,449 @@ pub const PG_CATALOG_TABLES: &[&str] = &[
     PG_CATALOG_TABLE_PG_SHDEPEND,
     PG_CATALOG_TABLE_PG_SHDESCRIPTION,
     PG_CATALOG_TABLE_PG_SHSECLABEL,
+    PG_CATALOG_TABLE_PG_STAT_ACTIVITY,
     PG_CATALOG_TABLE_PG_STATISTIC,
     PG_CATALOG_TABLE_PG_STATISTIC_EXT,
     PG_CATALOG_TABLE_PG_STATISTIC_EXT_DATA,
     PG_CATALOG_TABLE_PG_SUBSCRIPTION,
     PG_CATALOG_TABLE_PG_SUBSCRIPTION_REL,
     PG_CATALOG_TABLE_PG_TABLESPACE,
+    PG_CATALOG_TABLE_PG_TIMEZONE_NAMES,
     PG_CATALOG_TABLE_PG_TRIGGER,
     PG_CATALOG_TABLE_PG_USER_MAPPING,
+    PG_CATALOG_VIEW_PG_LOCKS,
+    PG_CATALOG_VIEW_PG_ROLES,
     PG_CATALOG_VIEW_PG_SETTINGS,
+    PG_CATALOG_VIEW_PG_STATS,
+    PG_CATALOG_VIEW_PG_STAT_STATEMENTS,
+    PG_CATALOG_VIEW_PG_STAT_USER_TABLES,
 ];
 
-#[derive(Debug, Hash, Eq, PartialEq, PartialOrd, Ord)]
-enum OidCacheKey {
+#[derive(Debug, Clone, Hash, Eq, PartialEq, PartialOrd, Ord)]
+enum OidKey {
     Catalog(String),
     Schema(String, String),
-    /// Table by schema and table name
+    /// Table by catalog, schema and table name
     Table(String, String, String),
+    /// Constraint by the OID of its table and its name
+    Constraint(Oid, String),
+    Role(String),
+}
+
+/// Field metadata declaring a foreign key of a column, like `customers(id)`
+/// or `sales.customers(id)`, listed in `pg_constraint`
+///
+/// DataFusion doesn't keep the foreign keys of `CREATE TABLE`, so tables
+/// declare them in the metadata of their fields. Tables without a schema
+/// name are looked up in the schema of the referencing table.
+pub const REFERENCES_METADATA_KEY: &str = "pg_references";
+
+/// Registry of the OIDs of catalogs, schemas, tables and constraints
+///
+/// An object gets its OID the first time it is seen and keeps it for the
+/// lifetime of the process, so all catalog tables, `regclass` lookups and
+/// sessions agree on the OID of an object, e.g. `pg_attribute.attrelid`
+/// joins with `pg_class.oid`. `pg_catalog` and `information_schema` have the
+/// OIDs of postgres, which the exported rows like `pg_proc` refer to.
+///
+/// The registry also caches the schema of tables resolved by `pg_class` and
+/// `pg_attribute`, so schemas inferred from remote sources are only fetched
+/// once. The cache is cleared when DDL runs through the server, call
+/// [`OidRegistry::clear_relations`] after changing tables directly on the
+/// `SessionContext`.
+#[derive(Debug)]
+pub struct OidRegistry {
+    next_oid: AtomicU32,
+    oids: RwLock<Oids>,
+    relations: RwLock<HashMap<Oid, RelationInfo>>,
+}
+
+#[derive(Debug, Default)]
+struct Oids {
+    by_key: HashMap<OidKey, Oid>,
+    by_oid: HashMap<Oid, OidKey>,
+}
+
+/// Cached description of a table
+#[derive(Debug, Clone)]
+pub(crate) struct RelationInfo {
+    pub(crate) schema: SchemaRef,
+    pub(crate) relkind: &'static str,
+    /// `table_type` of `information_schema.tables`
+    pub(crate) table_type: &'static str,
+    pub(crate) is_insertable_into: bool,
+    /// Primary key and unique constraints of the table
+    pub(crate) constraints: Constraints,
+}
+
+impl Default for OidRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OidRegistry {
+    pub fn new() -> Self {
+        OidRegistry {
+            // first OID postgres assigns to user objects
+            next_oid: AtomicU32::new(16384),
+            oids: RwLock::new(Oids::default()),
+            relations: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// OIDs of the system schemas in the exported rows of postgres, so
+    /// `pg_namespace` joins with e.g. `pg_proc.pronamespace` and
+    /// `pg_type.typnamespace`
+    ///
+    /// Shared by the `pg_catalog` and `information_schema` of all catalogs,
+    /// as their rows are the same.
+    fn system_oid(key: &OidKey) -> Option<Oid> {
+        match key {
+            OidKey::Schema(_, schema) if schema == "pg_catalog" => Some(PG_CATALOG_NAMESPACE_OID),
+            OidKey::Schema(_, schema) if schema == "information_schema" => {
+                Some(INFORMATION_SCHEMA_NAMESPACE_OID)
+            }
+            OidKey::Role(role) if role == "postgres" => Some(BOOTSTRAP_SUPERUSER_OID),
+            _ => None,
+        }
+    }
+
+    fn oid(&self, key: OidKey) -> Oid {
+        if let Some(oid) = Self::system_oid(&key) {
+            return oid;
+        }
+        if let Some(oid) = self
+            .oids
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .by_key
+            .get(&key)
+        {
+            return *oid;
+        }
+
+        let mut oids = self.oids.write().unwrap_or_else(|e| e.into_inner());
+        if let Some(oid) = oids.by_key.get(&key) {
+            return *oid;
+        }
+        let oid = self.next_oid.fetch_add(1, Ordering::Relaxed);
+        oids.by_oid.insert(oid, key.clone());
+        oids.by_key.insert(key, oid);
+        oid
+    }
+
+    pub fn catalog_oid(&self, catalog: &str) -> Oid {
+        self.oid(OidKey::Catalog(catalog.to_string()))
+    }
+
+    pub fn schema_oid(&self, catalog: &str, schema: &str) -> Oid {
+        self.oid(OidKey::Schema(catalog.to_string(), schema.to_string()))
+    }
+
+    pub fn table_oid(&self, catalog: &str, schema: &str, table: &str) -> Oid {
+        self.oid(OidKey::Table(
+            catalog.to_string(),
+            schema.to_string(),
+            table.to_string(),
+        ))
+    }
+
+    pub fn constraint_oid(&self, table_oid: Oid, constraint: &str) -> Oid {
+        self.oid(OidKey::Constraint(table_oid, constraint.to_string()))
+    }
+
+    pub fn role_oid(&self, role: &str) -> Oid {
+        self.oid(OidKey::Role(role.to_string()))
+    }
+
+    /// Catalog, schema and table name of the table with the OID, if one was
+    /// assigned
+    pub fn table_name(&self, oid: Oid) -> Option<(String, String, String)> {
+        match self
+            .oids
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .by_oid
+            .get(&oid)
+        {
+            Some(OidKey::Table(catalog, schema, table)) => {
+                Some((catalog.clone(), schema.clone(), table.clone()))
+            }
+            _ => None,
+        }
+    }
+
+    /// Catalog and name of the schema with the OID, if one was assigned
+    pub fn schema_name(&self, oid: Oid) -> Option<(String, String)> {
+        match self
+            .oids
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .by_oid
+            .get(&oid)
+        {
+            Some(OidKey::Schema(catalog, schema)) => Some((catalog.clone(), schema.clone())),
+            _ => None,
+        }
+    }
+
+    /// Schema and kind of a table, only resolving its `TableProvider` if they
+    /// are not cached yet
+    pub(crate) async fn relation(&self, table: &FilteredTable) -> Result<Option<RelationInfo>> {
+        if let Some(relation) = self
+            .relations
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(&table.table_oid)
+        {
+            return Ok(Some(relation.clone()));
+        }
+
+        let Some(provider) = table.schema.table(&table.table_name).await? else {
+            return Ok(None);
+        };
+        let relkind = get_table_type_with_name(&provider, &table.table_name, &table.schema_name);
+        let (mut table_type, is_insertable_into) =
+            information_schema::table_type(&provider, relkind);
+        if relkind == "r" && is_temp_schema(&table.schema_name) {
+            table_type = "LOCAL TEMPORARY";
+        }
+        let relation = RelationInfo {
+            schema: provider.schema(),
+            relkind,
+            table_type,
+            is_insertable_into,
+            constraints: provider.constraints().cloned().unwrap_or_default(),
+        };
+        self.relations
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(table.table_oid, relation.clone());
+        Ok(Some(relation))
+    }
+
+    /// Drop the cached schemas of all tables
+    pub fn clear_relations(&self) {
+        self.relations
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .clear();
+    }
+}
+
+/// Check if the schema is a temporary namespace `pg_temp_N`, holding the
+/// temporary tables of the session `N`
+pub(crate) fn is_temp_schema(schema_name: &str) -> bool {
+    schema_name
+        .strip_prefix("pg_temp_")
+        .is_some_and(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()))
+}
+
+/// Visibility of catalog objects for the user running a query
+///
+/// When attached to the `SessionConfig` as an extension, `pg_class`,
+/// `pg_namespace` and `pg_attribute` only list objects the user holds some
+/// privilege on, and like `pg_database` only those in the catalogs of the
+/// tenant of the user. Without it, all objects are listed.
+#[derive(Debug, Clone)]
+pub struct CatalogVisibility {
+    username: String,
+    auth_manager: Arc<AuthManager>,
+    catalogs: Option<Vec<String>>,
+}
+
+impl CatalogVisibility {
+    pub fn new(username: String, auth_manager: Arc<AuthManager>) -> Self {
+        Self {
+            username,
+            auth_manager,
+            catalogs: None,
+        }
+    }
+
+    /// Only list objects of `catalogs`, or of all catalogs if `None`
+    pub fn with_catalogs(mut self, catalogs: Option<Vec<String>>) -> Self {
+        self.catalogs = catalogs;
+        self
+    }
+
+    /// Check if objects of the catalog are listed to the user
+    pub fn is_catalog_visible(&self, catalog_name: &str) -> bool {
+        self.catalogs
+            .as_ref()
+            .is_none_or(|catalogs| catalogs.iter().any(|catalog| catalog == catalog_name))
+    }
+
+    /// The catalogs of `catalog_list` visible to the user
+    pub fn catalog_list(
+        &self,
+        catalog_list: &Arc<dyn CatalogProviderList>,
+    ) -> Arc<dyn CatalogProviderList> {
+        match &self.catalogs {
+            Some(catalogs) => Arc::new(TenantCatalogList::new(
+                catalog_list.clone(),
+                catalogs.clone(),
+            )),
+            None => catalog_list.clone(),
+        }
+    }
+
+    /// Get the visibility attached to the session of current task
+    pub fn from_task_context(ctx: &TaskContext) -> Option<Arc<CatalogVisibility>> {
+        ctx.session_config().get_extension::<CatalogVisibility>()
+    }
+
+    pub fn username(&self) -> &str {
+        &self.username
+    }
+
+    /// The users and roles of the server, which `pg_roles` lists
+    pub fn auth_manager(&self) -> &Arc<AuthManager> {
+        &self.auth_manager
+    }
+
+    /// Check if the user is a superuser, who sees the activity of all users
+    pub async fn is_superuser(&self) -> bool {
+        self.auth_manager
+            .get_user(&self.username)
+            .await
+            .is_some_and(|user| user.is_superuser)
+    }
+
+    fn is_system_schema(schema_name: &str) -> bool {
+        schema_name == "pg_catalog" || schema_name == "information_schema"
+    }
+
+    /// Check if the schema, or any table in it, is accessible to the user
+    pub async fn is_schema_visible(&self, schema_name: &str, schema: &dyn SchemaProvider) -> bool {
+        if Self::is_system_schema(schema_name)
+            || self
+                .auth_manager
+                .has_any_permission(
+                    &self.username,
+                    ResourceType::Schema(schema_name.to_string()),
+                )
+                .await
+        {
+            return true;
+        }
+
+        for table_name in schema.table_names() {
+            if self.is_table_visible(schema_name, &table_name).await {
+                return true;
+            }
+        }
+
+        false
+    }
+
+    /// Check if the table is accessible to the user
+    pub async fn is_table_visible(&self, schema_name: &str, table_name: &str) -> bool {
+        if Self::is_system_schema(schema_name) {
+            return true;
+        }
+
+        // grants may refer to the table with or without schema qualifier
+        for resource in [
+            ResourceType::Table(format!("{schema_name}.{table_name}")),
+            ResourceType::Table(table_name.to_string()),
+        ] {
+            if self
+                .auth_manager
+                .has_any_permission(&self.username, resource)
+                .await
+            {
+                return true;
+            }
+        }
+
+        false
+    }
+}
+
+/// Names of the system functions installed by [`setup_pg_catalog`]
+pub const SYSTEM_FUNCTIONS: &[&str] = &[
+    "current_schema",
+    "current_schemas",
+    "format_type",
+    "has_table_privilege",
+    "pg_get_expr",
+    "pg_get_keywords",
+    "pg_get_partkeydef",
+    "pg_get_serial_sequence",
+    "pg_get_userbyid",
+    "pg_is_other_temp_schema",
+    "pg_my_temp_schema",
+    "pg_table_is_visible",
+    "refresh_table_schema",
+    "session_user",
+    "to_regclass",
+    "version",
+];
+
+/// The system functions registered in a session
+///
+/// [`setup_system_functions`] registers the functions of the set and attaches
+/// it to the `SessionConfig` as an extension, so `pg_proc` lists no system
+/// function the session can't call. Without it, all are listed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SystemFunctions {
+    names: BTreeSet<String>,
+}
+
+impl Default for SystemFunctions {
+    fn default() -> Self {
+        Self::all()
+    }
+}
+
+impl SystemFunctions {
+    /// All of [`SYSTEM_FUNCTIONS`]
+    pub fn all() -> Self {
+        SystemFunctions {
+            names: SYSTEM_FUNCTIONS
+                .iter()
+                .map(|name| name.to_string())
+                .collect(),
+        }
+    }
+
+    pub fn none() -> Self {
+        SystemFunctions {
+            names: BTreeSet::new(),
+        }
+    }
+
+    /// Add a function of [`SYSTEM_FUNCTIONS`], other names are ignored
+    pub fn with_function(mut self, name: &str) -> Self {
+        if SYSTEM_FUNCTIONS.contains(&name) {
+            self.names.insert(name.to_string());
+        }
+        self
+    }
+
+    pub fn without_function(mut self, name: &str) -> Self {
+        self.names.remove(name);
+        self
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.names.contains(name)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.names.iter().map(String::as_str)
+    }
 }
 
 // Create custom schema provider for pg_catalog
 #[derive(Debug)]
 pub struct PgCatalogSchemaProvider {
     catalog_list: Arc<dyn CatalogProviderList>,
-    oid_counter: Arc<AtomicU32>,
-    oid_cache: Arc<RwLock<HashMap<OidCacheKey, Oid>>>,
+    oid_registry: Arc<OidRegistry>,
     static_tables: Arc<PgCatalogStaticTables>,
+    extensions: Arc<PgCatalogExtensions>,
 }
 
 #[async_trait]
@@ -210,148 +668,125 @@ impl SchemaProvider for PgCatalogSchemaProvider {
     }
 
     fn table_names(&self) -> Vec<String> {
-        PG_CATALOG_TABLES.iter().map(ToString::to_string).collect()
+        let mut names: Vec<String> = PG_CATALOG_TABLES.iter().map(ToString::to_string).collect();
+        names.extend(self.extensions.table_names());
+        names
     }
 
     async fn table(&self, name: &str) -> Result<Option<Arc<dyn TableProvider>>> {
-        match name.to_ascii_lowercase().as_str() {
-            PG_CATALOG_TABLE_PG_AGGREGATE => Ok(Some(self.static_tables.pg_aggregate.clone())),
-            PG_CATALOG_TABLE_PG_AM => Ok(Some(self.static_tables.pg_am.clone())),
-            PG_CATALOG_TABLE_PG_AMOP => Ok(Some(self.static_tables.pg_amop.clone())),
-            PG_CATALOG_TABLE_PG_AMPROC => Ok(Some(self.static_tables.pg_amproc.clone())),
-            PG_CATALOG_TABLE_PG_CAST => Ok(Some(self.static_tables.pg_cast.clone())),
-            PG_CATALOG_TABLE_PG_COLLATION => Ok(Some(self.static_tables.pg_collation.clone())),
-            PG_CATALOG_TABLE_PG_CONVERSION => Ok(Some(self.static_tables.pg_conversion.clone())),
-            PG_CATALOG_TABLE_PG_LANGUAGE => Ok(Some(self.static_tables.pg_language.clone())),
-            PG_CATALOG_TABLE_PG_OPCLASS => Ok(Some(self.static_tables.pg_opclass.clone())),
-            PG_CATALOG_TABLE_PG_OPERATOR => Ok(Some(self.static_tables.pg_operator.clone())),
-            PG_CATALOG_TABLE_PG_OPFAMILY => Ok(Some(self.static_tables.pg_opfamily.clone())),
-            PG_CATALOG_TABLE_PG_PROC => Ok(Some(self.static_tables.pg_proc.clone())),
-            PG_CATALOG_TABLE_PG_RANGE => Ok(Some(self.static_tables.pg_range.clone())),
-            PG_CATALOG_TABLE_PG_TS_CONFIG => Ok(Some(self.static_tables.pg_ts_config.clone())),
-            PG_CATALOG_TABLE_PG_TS_DICT => Ok(Some(self.static_tables.pg_ts_dict.clone())),
-            PG_CATALOG_TABLE_PG_TS_PARSER => Ok(Some(self.static_tables.pg_ts_parser.clone())),
-            PG_CATALOG_TABLE_PG_TS_TEMPLATE => Ok(Some(self.static_tables.pg_ts_template.clone())),
-            PG_CATALOG_TABLE_PG_TYPE => Ok(Some(self.static_tables.pg_type.clone())),
-            PG_CATALOG_TABLE_PG_ATTRDEF => Ok(Some(self.static_tables.pg_attrdef.clone())),
-            PG_CATALOG_TABLE_PG_AUTH_MEMBERS => {
-                Ok(Some(self.static_tables.pg_auth_members.clone()))
+        let name = name.to_ascii_lowercase();
+        if let Some(table) = self.extensions.table(&name) {
+            return Ok(Some(table));
+        }
+        if let Some(table) = self.static_table(&name) {
+            let table = self.extensions.with_rows(&name, table).await?;
+            if name == PG_CATALOG_TABLE_PG_PROC {
+                return Ok(Some(Arc::new(pg_proc::PgProcTable::new(table))));
             }
-            PG_CATALOG_TABLE_PG_AUTHID => Ok(Some(self.static_tables.pg_authid.clone())),
-
-            PG_CATALOG_TABLE_PG_CONSTRAINT => Ok(Some(self.static_tables.pg_constraint.clone())),
+            return Ok(Some(table));
+        }
 
-            PG_CATALOG_TABLE_PG_DB_ROLE_SETTING => {
-                Ok(Some(self.static_tables.pg_db_role_setting.clone()))
-            }
-            PG_CATALOG_TABLE_PG_DEFAULT_ACL => Ok(Some(self.static_tables.pg_default_acl.clone())),
-            PG_CATALOG_TABLE_PG_DEPEND => Ok(Some(self.static_tables.pg_depend.clone())),
-            PG_CATALOG_TABLE_PG_DESCRIPTION => Ok(Some(self.static_tables.pg_description.clone())),
-            PG_CATALOG_TABLE_PG_ENUM => Ok(Some(self.static_tables.pg_enum.clone())),
-            PG_CATALOG_TABLE_PG_EVENT_TRIGGER => {
-                Ok(Some(self.static_tables.pg_event_trigger.clone()))
-            }
-            PG_CATALOG_TABLE_PG_EXTENSION => Ok(Some(self.static_tables.pg_extension.clone())),
-            PG_CATALOG_TABLE_PG_FOREIGN_DATA_WRAPPER => {
-                Ok(Some(self.static_tables.pg_foreign_data_wrapper.clone()))
-            }
-            PG_CATALOG_TABLE_PG_FOREIGN_SERVER => {
-                Ok(Some(self.static_tables.pg_foreign_server.clone()))
-            }
-            PG_CATALOG_TABLE_PG_FOREIGN_TABLE => {
-                Ok(Some(self.static_tables.pg_foreign_table.clone()))
-            }
-            PG_CATALOG_TABLE_PG_INDEX => Ok(Some(self.static_tables.pg_index.clone())),
-            PG_CATALOG_TABLE_PG_INHERITS => Ok(Some(self.static_tables.pg_inherits.clone())),
-            PG_CATALOG_TABLE_PG_INIT_PRIVS => Ok(Some(self.static_tables.pg_init_privs.clone())),
-            PG_CATALOG_TABLE_PG_LARGEOBJECT => Ok(Some(self.static_tables.pg_largeobject.clone())),
-            PG_CATALOG_TABLE_PG_LARGEOBJECT_METADATA => {
-                Ok(Some(self.static_tables.pg_largeobject_metadata.clone()))
-            }
-            PG_CATALOG_TABLE_PG_PARTITIONED_TABLE => {
-                Ok(Some(self.static_tables.pg_partitioned_table.clone()))
-            }
-            PG_CATALOG_TABLE_PG_POLICY => Ok(Some(self.static_tables.pg_policy.clone())),
-            PG_CATALOG_TABLE_PG_PUBLICATION => Ok(Some(self.static_tables.pg_publication.clone())),
-            PG_CATALOG_TABLE_PG_PUBLICATION_NAMESPACE => {
-                Ok(Some(self.static_tables.pg_publication_namespace.clone()))
-            }
-            PG_CATALOG_TABLE_PG_PUBLICATION_REL => {
-                Ok(Some(self.static_tables.pg_publication_rel.clone()))
-            }
-            PG_CATALOG_TABLE_PG_REPLICATION_ORIGIN => {
-                Ok(Some(self.static_tables.pg_replication_origin.clone()))
-            }
-            PG_CATALOG_TABLE_PG_REWRITE => Ok(Some(self.static_tables.pg_rewrite.clone())),
-            PG_CATALOG_TABLE_PG_SECLABEL => Ok(Some(self.static_tables.pg_seclabel.clone())),
-            PG_CATALOG_TABLE_PG_SEQUENCE => Ok(Some(self.static_tables.pg_sequence.clone())),
-            PG_CATALOG_TABLE_PG_SHDEPEND => Ok(Some(self.static_tables.pg_shdepend.clone())),
-            PG_CATALOG_TABLE_PG_SHDESCRIPTION => {
-                Ok(Some(self.static_tables.pg_shdescription.clone()))
-            }
-            PG_CATALOG_TABLE_PG_SHSECLABEL => Ok(Some(self.static_tables.pg_shseclabel.clone())),
-            PG_CATALOG_TABLE_PG_STATISTIC => Ok(Some(self.static_tables.pg_statistic.clone())),
-            PG_CATALOG_TABLE_PG_STATISTIC_EXT => {
-                Ok(Some(self.static_tables.pg_statistic_ext.clone()))
-            }
-            PG_CATALOG_TABLE_PG_STATISTIC_EXT_DATA => {
-                Ok(Some(self.static_tables.pg_statistic_ext_data.clone()))
-            }
-            PG_CATALOG_TABLE_PG_SUBSCRIPTION => {
-                Ok(Some(self.static_tables.pg_subscription.clone()))
+        match name.as_str() {
+            PG_CATALOG_TABLE_PG_ATTRIBUTE => {
+                let table = pg_attribute::PgAttributeTable::new(
+                    self.catalog_list.clone(),
+                    self.oid_registry.clone(),
+                );
+                Ok(Some(Arc::new(KeyFilteredTableProvider::new(table))))
             }
-            PG_CATALOG_TABLE_PG_SUBSCRIPTION_REL => {
-                Ok(Some(self.static_tables.pg_subscription_rel.clone()))
+            PG_CATALOG_TABLE_PG_CLASS => {
+                let table = pg_class::PgClassTable::new(
+                    self.catalog_list.clone(),
+                    self.oid_registry.clone(),
+                );
+                Ok(Some(Arc::new(KeyFilteredTableProvider::new(table))))
             }
-            PG_CATALOG_TABLE_PG_TABLESPACE => Ok(Some(self.static_tables.pg_tablespace.clone())),
-            PG_CATALOG_TABLE_PG_TRIGGER => Ok(Some(self.static_tables.pg_trigger.clone())),
-            PG_CATALOG_TABLE_PG_USER_MAPPING => {
-                Ok(Some(self.static_tables.pg_user_mapping.clone()))
+            PG_CATALOG_TABLE_PG_CONSTRAINT => {
+                let table = pg_constraint::PgConstraintTable::new(
+                    self.catalog_list.clone(),
+                    self.oid_registry.clone(),
+                );
+                Ok(Some(Arc::new(KeyFilteredTableProvider::new(table))))
             }
-
-            PG_CATALOG_TABLE_PG_ATTRIBUTE => {
-                let table = Arc::new(pg_attribute::PgAttributeTable::new(
+            PG_CATALOG_TABLE_PG_DATABASE => {
+                let table = Arc::new(pg_database::PgDatabaseTable::new(
                     self.catalog_list.clone(),
-                    self.oid_counter.clone(),
-                    self.oid_cache.clone(),
+                    self.oid_registry.clone(),
                 ));
                 Ok(Some(Arc::new(
                     StreamingTable::try_new(Arc::clone(table.schema()), vec![table]).unwrap(),
                 )))
             }
-            PG_CATALOG_TABLE_PG_CLASS => {
-                let table = Arc::new(pg_class::PgClassTable::new(
+            PG_CATALOG_TABLE_PG_NAMESPACE => {
+                let table = Arc::new(pg_namespace::PgNamespaceTable::new(
                     self.catalog_list.clone(),
-                    self.oid_counter.clone(),
-                    self.oid_cache.clone(),
+                    self.oid_registry.clone(),
                 ));
                 Ok(Some(Arc::new(
                     StreamingTable::try_new(Arc::clone(table.schema()), vec![table]).unwrap(),
                 )))
             }
-            PG_CATALOG_TABLE_PG_DATABASE => {
-                let table = Arc::new(pg_database::PgDatabaseTable::new(
-                    self.catalog_list.clone(),
-                    self.oid_counter.clone(),
-                    self.oid_cache.clone(),
+            PG_CATALOG_TABLE_PG_STAT_ACTIVITY => {
+                let table = Arc::new(pg_stat_activity::PgStatActivityTable::new(
+                    self.oid_registry.clone(),
                 ));
                 Ok(Some(Arc::new(
                     StreamingTable::try_new(Arc::clone(table.schema()), vec![table]).unwrap(),
                 )))
             }
-            PG_CATALOG_TABLE_PG_NAMESPACE => {
-                let table = Arc::new(pg_namespace::PgNamespaceTable::new(
-                    self.catalog_list.clone(),
-                    self.oid_counter.clone(),
-                    self.oid_cache.clone(),
+            PG_CATALOG_VIEW_PG_STAT_STATEMENTS => {
+                let table = Arc::new(pg_stat_statements::PgStatStatementsTable::new(
+                    self.oid_registry.clone(),
+                ));
+                Ok(Some(Arc::new(
+                    StreamingTable::try_new(Arc::clone(table.schema()), vec![table]).unwrap(),
+                )))
+            }
+            PG_CATALOG_TABLE_PG_TIMEZONE_NAMES => {
+                let table = Arc::new(pg_timezone_names::PgTimezoneNamesTable::new());
+                Ok(Some(Arc::new(
+                    StreamingTable::try_new(Arc::clone(table.schema()), vec![table]).unwrap(),
+                )))
+            }
+            PG_CATALOG_TABLE_PG_DB_ROLE_SETTING => {
+                let table = Arc::new(pg_db_role_setting::PgDbRoleSettingTable::new(
+                    self.oid_registry.clone(),
                 ));
                 Ok(Some(Arc::new(
                     StreamingTable::try_new(Arc::clone(table.schema()), vec![table]).unwrap(),
                 )))
             }
+            PG_CATALOG_VIEW_PG_LOCKS => {
+                let table = Arc::new(pg_locks::PgLocksTable::new(self.oid_registry.clone()));
+                Ok(Some(Arc::new(
+                    StreamingTable::try_new(Arc::clone(table.schema()), vec![table]).unwrap(),
+                )))
+            }
+            PG_CATALOG_VIEW_PG_ROLES => {
+                let table = Arc::new(pg_roles::PgRolesTable::new(self.oid_registry.clone()));
+                Ok(Some(Arc::new(
+                    StreamingTable::try_new(Arc::clone(table.schema()), vec![table]).unwrap(),
+                )))
+            }
             PG_CATALOG_VIEW_PG_SETTINGS => {
-                let table = pg_settings::PgSettingsView::try_new()?;
-                Ok(Some(Arc::new(table.try_into_memtable()?)))
+                let table = Arc::new(pg_settings::PgSettingsTable::new());
+                Ok(Some(Arc::new(
+                    StreamingTable::try_new(Arc::clone(table.schema()), vec![table]).unwrap(),
+                )))
+            }
+            PG_CATALOG_VIEW_PG_STATS => {
+                let table = Arc::new(pg_stats::PgStatsTable::new());
+                Ok(Some(Arc::new(
+                    StreamingTable::try_new(Arc::clone(table.schema()), vec![table]).unwrap(),
+                )))
+            }
+            PG_CATALOG_VIEW_PG_STAT_USER_TABLES => {
+                let table = Arc::new(pg_stats::PgStatUserTablesTable::new(
+                    self.oid_registry.clone(),
+                ));
+                Ok(Some(Arc::new(
+                    StreamingTable::try_new(Arc::clone(table.schema()), vec![table]).unwrap(),
+                )))
             }
 
             _ => Ok(None),
@@ -359,7 +794,8 @@ impl SchemaProvider for PgCatalogSchemaProvider {
     }
 
     fn table_exist(&self, name: &str) -> bool {
-        PG_CATALOG_TABLES.contains(&name.to_ascii_lowercase().as_str())
+        let name = name.to_ascii_lowercase();
+        PG_CATALOG_TABLES.contains(&name.as_str()) || self.extensions.table(&name).is_some()
     }
 }
 
@@ -370,11 +806,304 @@ impl PgCatalogSchemaProvider {
     ) -> Result<PgCatalogSchemaProvider> {
         Ok(Self {
             catalog_list,
-            oid_counter: Arc::new(AtomicU32::new(16384)),
-            oid_cache: Arc::new(RwLock::new(HashMap::new())),
+            oid_registry: Arc::new(OidRegistry::new()),
             static_tables,
+            extensions: Arc::new(PgCatalogExtensions::default()),
         })
     }
+
+    /// The built-in table of `name` whose rows are loaded from postgres
+    /// exports, if it is one
+    fn static_table(&self, name: &str) -> Option<Arc<dyn TableProvider>> {
+        match name {
+            PG_CATALOG_TABLE_PG_AGGREGATE => Some(self.static_tables.pg_aggregate.clone()),
+            PG_CATALOG_TABLE_PG_AM => Some(self.static_tables.pg_am.clone()),
+            PG_CATALOG_TABLE_PG_AMOP => Some(self.static_tables.pg_amop.clone()),
+            PG_CATALOG_TABLE_PG_AMPROC => Some(self.static_tables.pg_amproc.clone()),
+            PG_CATALOG_TABLE_PG_CAST => Some(self.static_tables.pg_cast.clone()),
+            PG_CATALOG_TABLE_PG_COLLATION => Some(self.static_tables.pg_collation.clone()),
+            PG_CATALOG_TABLE_PG_CONVERSION => Some(self.static_tables.pg_conversion.clone()),
+            PG_CATALOG_TABLE_PG_LANGUAGE => Some(self.static_tables.pg_language.clone()),
+            PG_CATALOG_TABLE_PG_OPCLASS => Some(self.static_tables.pg_opclass.clone()),
+            PG_CATALOG_TABLE_PG_OPERATOR => Some(self.static_tables.pg_operator.clone()),
+            PG_CATALOG_TABLE_PG_OPFAMILY => Some(self.static_tables.pg_opfamily.clone()),
+            PG_CATALOG_TABLE_PG_PROC => Some(self.static_tables.pg_proc.clone()),
+            PG_CATALOG_TABLE_PG_RANGE => Some(self.static_tables.pg_range.clone()),
+            PG_CATALOG_TABLE_PG_TS_CONFIG => Some(self.static_tables.pg_ts_config.clone()),
+            PG_CATALOG_TABLE_PG_TS_DICT => Some(self.static_tables.pg_ts_dict.clone()),
+            PG_CATALOG_TABLE_PG_TS_PARSER => Some(self.static_tables.pg_ts_parser.clone()),
+            PG_CATALOG_TABLE_PG_TS_TEMPLATE => Some(self.static_tables.pg_ts_template.clone()),
+            PG_CATALOG_TABLE_PG_TYPE => Some(self.static_tables.pg_type.clone()),
+            PG_CATALOG_TABLE_PG_ATTRDEF => Some(self.static_tables.pg_attrdef.clone()),
+            PG_CATALOG_TABLE_PG_AUTH_MEMBERS => Some(self.static_tables.pg_auth_members.clone()),
+            PG_CATALOG_TABLE_PG_AUTHID => Some(self.static_tables.pg_authid.clone()),
+
+            PG_CATALOG_TABLE_PG_DEFAULT_ACL => Some(self.static_tables.pg_default_acl.clone()),
+            PG_CATALOG_TABLE_PG_DEPEND => Some(self.static_tables.pg_depend.clone()),
+            PG_CATALOG_TABLE_PG_DESCRIPTION => Some(self.static_tables.pg_description.clone()),
+            PG_CATALOG_TABLE_PG_ENUM => Some(self.static_tables.pg_enum.clone()),
+            PG_CATALOG_TABLE_PG_EVENT_TRIGGER => Some(self.static_tables.pg_event_trigger.clone()),
+            PG_CATALOG_TABLE_PG_EXTENSION => Some(self.static_tables.pg_extension.clone()),
+            PG_CATALOG_TABLE_PG_FOREIGN_DATA_WRAPPER => {
+                Some(self.static_tables.pg_foreign_data_wrapper.clone())
+            }
+            PG_CATALOG_TABLE_PG_FOREIGN_SERVER => {
+                Some(self.static_tables.pg_foreign_server.clone())
+            }
+            PG_CATALOG_TABLE_PG_FOREIGN_TABLE => Some(self.static_tables.pg_foreign_table.clone()),
+            PG_CATALOG_TABLE_PG_INDEX => Some(self.static_tables.pg_index.clone()),
+            PG_CATALOG_TABLE_PG_INHERITS => Some(self.static_tables.pg_inherits.clone()),
+            PG_CATALOG_TABLE_PG_INIT_PRIVS => Some(self.static_tables.pg_init_privs.clone()),
+            PG_CATALOG_TABLE_PG_LARGEOBJECT => Some(self.static_tables.pg_largeobject.clone()),
+            PG_CATALOG_TABLE_PG_LARGEOBJECT_METADATA => {
+                Some(self.static_tables.pg_largeobject_metadata.clone())
+            }
+            PG_CATALOG_TABLE_PG_PARTITIONED_TABLE => {
+                Some(self.static_tables.pg_partitioned_table.clone())
+            }
+            PG_CATALOG_TABLE_PG_POLICY => Some(self.static_tables.pg_policy.clone()),
+            PG_CATALOG_TABLE_PG_PUBLICATION => Some(self.static_tables.pg_publication.clone()),
+            PG_CATALOG_TABLE_PG_PUBLICATION_NAMESPACE => {
+                Some(self.static_tables.pg_publication_namespace.clone())
+            }
+            PG_CATALOG_TABLE_PG_PUBLICATION_REL => {
+                Some(self.static_tables.pg_publication_rel.clone())
+            }
+            PG_CATALOG_TABLE_PG_REPLICATION_ORIGIN => {
+                Some(self.static_tables.pg_replication_origin.clone())
+            }
+            PG_CATALOG_TABLE_PG_REWRITE => Some(self.static_tables.pg_rewrite.clone()),
+            PG_CATALOG_TABLE_PG_SECLABEL => Some(self.static_tables.pg_seclabel.clone()),
+            PG_CATALOG_TABLE_PG_SEQUENCE => Some(self.static_tables.pg_sequence.clone()),
+            PG_CATALOG_TABLE_PG_SHDEPEND => Some(self.static_tables.pg_shdepend.clone()),
+            PG_CATALOG_TABLE_PG_SHDESCRIPTION => Some(self.static_tables.pg_shdescription.clone()),
+            PG_CATALOG_TABLE_PG_SHSECLABEL => Some(self.static_tables.pg_shseclabel.clone()),
+            PG_CATALOG_TABLE_PG_STATISTIC => Some(self.static_tables.pg_statistic.clone()),
+            PG_CATALOG_TABLE_PG_STATISTIC_EXT => Some(self.static_tables.pg_statistic_ext.clone()),
+            PG_CATALOG_TABLE_PG_STATISTIC_EXT_DATA => {
+                Some(self.static_tables.pg_statistic_ext_data.clone())
+            }
+            PG_CATALOG_TABLE_PG_SUBSCRIPTION => Some(self.static_tables.pg_subscription.clone()),
+            PG_CATALOG_TABLE_PG_SUBSCRIPTION_REL => {
+                Some(self.static_tables.pg_subscription_rel.clone())
+            }
+            PG_CATALOG_TABLE_PG_TABLESPACE => Some(self.static_tables.pg_tablespace.clone()),
+            PG_CATALOG_TABLE_PG_TRIGGER => Some(self.static_tables.pg_trigger.clone()),
+            PG_CATALOG_TABLE_PG_USER_MAPPING => Some(self.static_tables.pg_user_mapping.clone()),
+
+            _ => None,
+        }
+    }
+
+    /// Share the OID registry with other catalogs of the server
+    pub fn with_oid_registry(mut self, oid_registry: Arc<OidRegistry>) -> Self {
+        self.oid_registry = oid_registry;
+        self
+    }
+
+    pub fn oid_registry(&self) -> &Arc<OidRegistry> {
+        &self.oid_registry
+    }
+
+    /// Share the rows and tables added to pg_catalog with other catalogs of
+    /// the server
+    pub fn with_extensions(mut self, extensions: Arc<PgCatalogExtensions>) -> Self {
+        self.extensions = extensions;
+        self
+    }
+
+    pub fn extensions(&self) -> &Arc<PgCatalogExtensions> {
+        &self.extensions
+    }
+
+    /// Add rows to a built-in table, like `pg_type` or `pg_proc`
+    ///
+    /// `batch` must have the columns of the table, in order.
+    pub fn add_rows(&self, table: &str, batch: RecordBatch) -> Result<()> {
+        let name = table.to_ascii_lowercase();
+        let Some(table) = self.static_table(&name) else {
+            return plan_err!("Rows can't be added to pg_catalog.{name}");
+        };
+        let batch = RecordBatch::try_new(table.schema(), batch.columns().to_vec())?;
+        self.extensions.add_rows(name, batch);
+        Ok(())
+    }
+
+    /// Add a row to a built-in table, like `pg_type` or `pg_proc`
+    ///
+    /// Columns missing from `values` are null, or zero and empty strings
+    /// when they are not nullable.
+    pub fn add_row(&self, table: &str, values: &[(&str, ScalarValue)]) -> Result<()> {
+        let name = table.to_ascii_lowercase();
+        let Some(table) = self.static_table(&name) else {
+            return plan_err!("Rows can't be added to pg_catalog.{name}");
+        };
+        let schema = table.schema();
+        if let Some((column, _)) = values
+            .iter()
+            .find(|(column, _)| schema.column_with_name(column).is_none())
+        {
+            return plan_err!("Column {column} not found in pg_catalog.{name}");
+        }
+
+        let columns = schema
+            .fields()
+            .iter()
+            .map(|field| {
+                let value = match values.iter().find(|(column, _)| column == field.name()) {
+                    Some((_, value)) => value.cast_to(field.data_type())?,
+                    None if field.is_nullable() => ScalarValue::try_from(field.data_type())?,
+                    None if field.data_type() == &DataType::Utf8 => ScalarValue::from(""),
+                    None => ScalarValue::new_zero(field.data_type())?,
+                };
+                value.to_array()
+            })
+            .collect::<Result<Vec<_>>>()?;
+        self.extensions
+            .add_rows(name, RecordBatch::try_new(schema, columns)?);
+        Ok(())
+    }
+
+    /// Advertise a type of an extension, like `geometry` of PostGIS, to
+    /// clients as a variable length base type in `pg_type`
+    pub fn add_type(&self, oid: Oid, name: &str) -> Result<()> {
+        self.add_row(
+            PG_CATALOG_TABLE_PG_TYPE,
+            &[
+                ("oid", ScalarValue::from(oid)),
+                ("typname", ScalarValue::from(name)),
+                ("typnamespace", ScalarValue::from(PG_CATALOG_NAMESPACE_OID)),
+                ("typowner", ScalarValue::from(BOOTSTRAP_SUPERUSER_OID)),
+                ("typlen", ScalarValue::from(-1i16)),
+                ("typtype", ScalarValue::from("b")),
+                ("typcategory", ScalarValue::from("U")),
+                ("typisdefined", ScalarValue::from(true)),
+                ("typdelim", ScalarValue::from(",")),
+                ("typsubscript", ScalarValue::from("-")),
+                ("typinput", ScalarValue::from(format!("{name}_in"))),
+                ("typoutput", ScalarValue::from(format!("{name}_out"))),
+                ("typreceive", ScalarValue::from(format!("{name}_recv"))),
+                ("typsend", ScalarValue::from(format!("{name}_send"))),
+                ("typmodin", ScalarValue::from("-")),
+                ("typmodout", ScalarValue::from("-")),
+                ("typanalyze", ScalarValue::from("-")),
+                ("typalign", ScalarValue::from("i")),
+                ("typstorage", ScalarValue::from("x")),
+                ("typtypmod", ScalarValue::from(-1i32)),
+            ],
+        )
+    }
+
+    /// Advertise a function of an extension to clients in `pg_proc`
+    pub fn add_function(
+        &self,
+        oid: Oid,
+        name: &str,
+        arg_types: &[Oid],
+        return_type: Oid,
+    ) -> Result<()> {
+        self.add_row(
+            PG_CATALOG_TABLE_PG_PROC,
+            &[
+                ("oid", ScalarValue::from(oid)),
+                ("proname", ScalarValue::from(name)),
+                ("pronamespace", ScalarValue::from(PG_CATALOG_NAMESPACE_OID)),
+                ("proowner", ScalarValue::from(BOOTSTRAP_SUPERUSER_OID)),
+                ("prolang", ScalarValue::from(INTERNAL_LANGUAGE_OID)),
+                ("procost", ScalarValue::from(1f32)),
+                ("prosupport", ScalarValue::from("-")),
+                ("prokind", ScalarValue::from("f")),
+                ("provolatile", ScalarValue::from("v")),
+                ("proparallel", ScalarValue::from("u")),
+                ("pronargs", ScalarValue::from(arg_types.len() as i16)),
+                ("prorettype", ScalarValue::from(return_type)),
+                ("proargtypes", vector_types::oid_vector(arg_types)),
+                ("prosrc", ScalarValue::from(name)),
+            ],
+        )
+    }
+
+    /// Add a table of an extension to pg_catalog, like `spatial_ref_sys`
+    pub fn register_table(&self, name: &str, table: Arc<dyn TableProvider>) -> Result<()> {
+        let name = name.to_ascii_lowercase();
+        if PG_CATALOG_TABLES.contains(&name.as_str()) {
+            return plan_err!("pg_catalog.{name} already exists");
+        }
+        self.extensions.register_table(name, table);
+        Ok(())
+    }
+}
+
+/// Rows and tables added to pg_catalog, by embedders emulating extensions
+///
+/// Added with the methods of [`PgCatalogSchemaProvider`], which validate them
+/// against the built-in tables.
+#[derive(Debug, Default)]
+pub struct PgCatalogExtensions {
+    rows: RwLock<HashMap<String, Vec<RecordBatch>>>,
+    tables: RwLock<HashMap<String, Arc<dyn TableProvider>>>,
+}
+
+impl PgCatalogExtensions {
+    fn add_rows(&self, table: String, batch: RecordBatch) {
+        self.rows
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry(table)
+            .or_default()
+            .push(batch);
+    }
+
+    fn register_table(&self, name: String, table: Arc<dyn TableProvider>) {
+        self.tables
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(name, table);
+    }
+
+    fn table_names(&self) -> Vec<String> {
+        self.tables
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .keys()
+            .cloned()
+            .collect()
+    }
+
+    fn table(&self, name: &str) -> Option<Arc<dyn TableProvider>> {
+        self.tables
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(name)
+            .cloned()
+    }
+
+    /// The built-in `table` of `name` with the rows added to it
+    async fn with_rows(
+        &self,
+        name: &str,
+        table: Arc<dyn TableProvider>,
+    ) -> Result<Arc<dyn TableProvider>> {
+        let rows = match self
+            .rows
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(name)
+        {
+            Some(rows) => rows.clone(),
+            None => return Ok(table),
+        };
+        let Some(mem_table) = table.as_any().downcast_ref::<MemTable>() else {
+            return Ok(table);
+        };
+
+        let mut batches = Vec::new();
+        for partition in &mem_table.batches {
+            batches.extend(partition.read().await.iter().cloned());
+        }
+        batches.extend(rows);
+        Ok(Arc::new(MemTable::try_new(table.schema(), vec![batches])?))
+    }
 }
 
 /// A table that reads data from Avro bytes
@@ -397,6 +1126,7 @@ impl ArrowTable {
         for batch in reader {
             batches.push(batch?);
         }
+        let (schema, batches) = vector_types::emulate_vector_types(schema, batches)?;
 
         Ok(Self {
             schema,
@@ -918,6 +1648,190 @@ pub fn create_session_user_udf() -> ScalarUDF {
     )
 }
 
+/// Resolve a relation name to its catalog, schema and table, if it exists
+///
+/// Unqualified names are looked up in `pg_catalog` and then `public` of the
+/// default catalog, like with the default `search_path`.
+fn resolve_relation(
+    catalog_list: &dyn CatalogProviderList,
+    default_catalog: &str,
+    name: &str,
+) -> Option<(String, String, String)> {
+    let parts = name
+        .split('.')
+        .map(
+            |part| match part.strip_prefix('"').and_then(|p| p.strip_suffix('"')) {
+                Some(quoted) => quoted.to_string(),
+                None => part.to_lowercase(),
+            },
+        )
+        .collect::<Vec<_>>();
+    let (catalog_name, schema_names, table_name) = match parts.as_slice() {
+        [table] => (default_catalog, vec!["pg_catalog", "public"], table),
+        [schema, table] => (default_catalog, vec![schema.as_str()], table),
+        [catalog, schema, table] => (catalog.as_str(), vec![schema.as_str()], table),
+        _ => return None,
+    };
+
+    let catalog = catalog_list.catalog(catalog_name)?;
+    schema_names.into_iter().find_map(|schema_name| {
+        catalog
+            .schema(schema_name)
+            .filter(|schema| schema.table_exist(table_name))
+            .map(|_| {
+                (
+                    catalog_name.to_string(),
+                    schema_name.to_string(),
+                    table_name.to_string(),
+                )
+            })
+    })
+}
+
+/// Resolve a relation name to its OID, or NULL if it does not exist
+///
+/// Unqualified names are looked up in `pg_catalog` and then `public` of the
+/// given catalog, like with the default `search_path`.
+pub fn create_to_regclass_udf(
+    catalog_list: Arc<dyn CatalogProviderList>,
+    oid_registry: Arc<OidRegistry>,
+    default_catalog: &str,
+) -> ScalarUDF {
+    let default_catalog = default_catalog.to_string();
+    let func = move |args: &[ColumnarValue]| {
+        let args = ColumnarValue::values_to_arrays(args)?;
+        let names = args[0].as_string::<i32>();
+
+        let oids = names
+            .iter()
+            .map(|name| {
+                let (catalog, schema, table) =
+                    resolve_relation(catalog_list.as_ref(), &default_catalog, name?)?;
+                Some(oid_registry.table_oid(&catalog, &schema, &table) as i32)
+            })
+            .collect::<Int32Array>();
+        Ok(ColumnarValue::Array(Arc::new(oids)))
+    };
+
+    create_udf(
+        "to_regclass",
+        vec![DataType::Utf8],
+        DataType::Int32,
+        Volatility::Stable,
+        Arc::new(func),
+    )
+}
+
+/// Name of the sequence of a serial or identity column, or NULL if the column
+/// has none
+///
+/// Tables can't have sequences attached, so this is NULL for the columns of
+/// all tables. Django and Rails call it when resetting sequences after
+/// loading data, and skip the columns without one. Unknown tables are an
+/// error, like in postgres.
+pub fn create_pg_get_serial_sequence_udf(
+    catalog_list: Arc<dyn CatalogProviderList>,
+    default_catalog: &str,
+) -> ScalarUDF {
+    let default_catalog = default_catalog.to_string();
+    let func = move |args: &[ColumnarValue]| {
+        let args = ColumnarValue::values_to_arrays(args)?;
+        let tables = args[0].as_string::<i32>();
+
+        let mut builder = StringBuilder::new();
+        for table in tables.iter() {
+            if let Some(table) = table {
+                if resolve_relation(catalog_list.as_ref(), &default_catalog, table).is_none() {
+                    return plan_err!("table '{table}' not found");
+                }
+            }
+            builder.append_null();
+        }
+        Ok(ColumnarValue::Array(Arc::new(builder.finish())))
+    };
+
+    create_udf(
+        "pg_get_serial_sequence",
+        vec![DataType::Utf8, DataType::Utf8],
+        DataType::Utf8,
+        Volatility::Stable,
+        Arc::new(func),
+    )
+}
+
+/// OID of the temporary namespace of the session, or 0 if it has none
+///
+/// Sessions can't create temporary tables, so they never own a `pg_temp_N`
+/// namespace.
+pub fn create_pg_my_temp_schema_udf() -> ScalarUDF {
+    let func =
+        move |_args: &[ColumnarValue]| Ok(ColumnarValue::Scalar(ScalarValue::Int32(Some(0))));
+
+    create_udf(
+        "pg_my_temp_schema",
+        vec![],
+        DataType::Int32,
+        Volatility::Stable,
+        Arc::new(func),
+    )
+}
+
+/// Check if the namespace with the OID is the temporary namespace of
+/// another session
+///
+/// As sessions have no temporary namespace of their own, this holds for all
+/// `pg_temp_N` namespaces, so psql leaves their tables out of its listings.
+pub fn create_pg_is_other_temp_schema_udf(oid_registry: Arc<OidRegistry>) -> ScalarUDF {
+    let func = move |args: &[ColumnarValue]| {
+        let args = ColumnarValue::values_to_arrays(args)?;
+        let oids = datafusion::arrow::compute::cast(&args[0], &DataType::Int64)?;
+        let is_other = oids
+            .as_primitive::<datafusion::arrow::datatypes::Int64Type>()
+            .iter()
+            .map(|oid| {
+                oid.map(|oid| {
+                    Oid::try_from(oid)
+                        .ok()
+                        .and_then(|oid| oid_registry.schema_name(oid))
+                        .is_some_and(|(_, schema)| is_temp_schema(&schema))
+                })
+            })
+            .collect::<BooleanArray>();
+        Ok(ColumnarValue::Array(Arc::new(is_other)))
+    };
+
+    create_udf(
+        "pg_is_other_temp_schema",
+        vec![DataType::Int64],
+        DataType::Boolean,
+        Volatility::Stable,
+        Arc::new(func),
+    )
+}
+
+/// Refresh the schema of a table of files, like `ALTER TABLE ... REFRESH SCHEMA`
+///
+/// The refresh is async, so sessions run it when the function is called
+/// alone, as in `SELECT refresh_table_schema('events')`. Anywhere else, the
+/// function fails.
+pub fn create_refresh_table_schema_udf() -> ScalarUDF {
+    let func = move |_args: &[ColumnarValue]| -> Result<ColumnarValue> {
+        Err(DataFusionError::NotImplemented(
+            "refresh_table_schema can only be called alone, as in \
+             SELECT refresh_table_schema('events')"
+                .to_string(),
+        ))
+    };
+
+    create_udf(
+        "refresh_table_schema",
+        vec![DataType::Utf8],
+        DataType::Boolean,
+        Volatility::Volatile,
+        Arc::new(func),
+    )
+}
+
 pub fn create_pg_get_partkeydef_udf() -> ScalarUDF {
     let func = move |args: &[ColumnarValue]| {
         let args = ColumnarValue::values_to_arrays(args)?;
@@ -943,43 +1857,1183 @@ pub fn create_pg_get_partkeydef_udf() -> ScalarUDF {
     )
 }
 
-/// Install pg_catalog and postgres UDFs to current `SessionContext`
+/// Install pg_catalog and information_schema in the catalog `catalog_name`
+/// created after `setup_pg_catalog`, sharing the pg_catalog of the default
+/// catalog
+///
+/// Does nothing when the default catalog has no pg_catalog.
+pub(crate) fn setup_created_catalog(
+    session_context: &SessionContext,
+    catalog_name: &str,
+) -> Result<()> {
+    let state = session_context.state();
+    let default_catalog = &state.config_options().catalog.default_catalog;
+    let Some(pg_catalog) = session_context
+        .catalog(default_catalog)
+        .and_then(|catalog| catalog.schema("pg_catalog"))
+    else {
+        return Ok(());
+    };
+    let Some(provider) = pg_catalog
+        .as_any()
+        .downcast_ref::<PgCatalogSchemaProvider>()
+    else {
+        return Ok(());
+    };
+    let information_schema = information_schema::PgInformationSchemaProvider::new(
+        catalog_name,
+        state.catalog_list().clone(),
+        provider.oid_registry().clone(),
+    );
+    let catalog = session_context.catalog(catalog_name).ok_or_else(|| {
+        DataFusionError::Configuration(format!(
+            "Catalog not found when registering pg_catalog: {catalog_name}"
+        ))
+    })?;
+    catalog.register_schema(
+        information_schema::INFORMATION_SCHEMA,
+        Arc::new(information_schema),
+    )?;
+    catalog.register_schema("pg_catalog", pg_catalog)?;
+    Ok(())
+}
+
+/// Install pg_catalog, information_schema and postgres UDFs to current
+/// `SessionContext`
+///
+/// The `information_schema` of postgres replaces the one of DataFusion, so
+/// `datafusion.catalog.information_schema` is disabled.
 pub fn setup_pg_catalog(
     session_context: &SessionContext,
     catalog_name: &str,
 ) -> Result<(), Box<DataFusionError>> {
+    // planned before DataFusion, which plans `@>` and `<@` of arrays without
+    // coercing their elements, like the coercion of comparisons runs before
+    // the one of DataFusion. The state is rebuilt once, before registering
+    // any function, as rebuilding it registers the functions again by name.
+    let state_ref = session_context.state_ref();
+    let mut state = state_ref.write();
+    if !state.scalar_functions().contains_key("arraycontains") {
+        let mut planners: Vec<Arc<dyn ExprPlanner>> =
+            vec![Arc::new(array_udf::PgArrayOperatorPlanner::new())];
+        planners.extend(state.expr_planners().iter().cloned());
+        let mut analyzer_rules: Vec<Arc<dyn AnalyzerRule + Send + Sync>> =
+            vec![Arc::new(coercion::PgCoercionRule)];
+        analyzer_rules.extend(state.analyzer().rules.iter().cloned());
+        let session_id = state.session_id().to_string();
+        *state = SessionStateBuilder::new_from_existing(state.clone())
+            .with_session_id(session_id)
+            .with_expr_planners(planners)
+            .with_analyzer_rules(analyzer_rules)
+            .build();
+    }
+    drop(state);
+
     let static_tables = Arc::new(PgCatalogStaticTables::try_new()?);
-    let pg_catalog = PgCatalogSchemaProvider::try_new(
-        session_context.state().catalog_list().clone(),
-        static_tables.clone(),
+    let catalog_list = session_context.state().catalog_list().clone();
+    let pg_catalog = PgCatalogSchemaProvider::try_new(catalog_list.clone(), static_tables.clone())?;
+    let information_schema = information_schema::PgInformationSchemaProvider::new(
+        catalog_name,
+        catalog_list,
+        pg_catalog.oid_registry().clone(),
+    );
+    let catalog = session_context.catalog(catalog_name).ok_or_else(|| {
+        DataFusionError::Configuration(format!(
+            "Catalog not found when registering pg_catalog: {catalog_name}"
+        ))
+    })?;
+    catalog.register_schema("pg_catalog", Arc::new(pg_catalog))?;
+    catalog.register_schema(
+        information_schema::INFORMATION_SCHEMA,
+        Arc::new(information_schema),
     )?;
     session_context
+        .state_ref()
+        .write()
+        .config_mut()
+        .options_mut()
+        .catalog
+        .information_schema = false;
+
+    session_context.register_udf(array_udf::ArrayBoundUDF::new(false).into_scalar_udf());
+    session_context.register_udf(array_udf::ArrayBoundUDF::new(true).into_scalar_udf());
+    session_context.register_udf(array_udf::CardinalityUDF::new().into_scalar_udf());
+    for operator in [
+        array_udf::ArrayOperator::Contains,
+        array_udf::ArrayOperator::Contained,
+        array_udf::ArrayOperator::Overlap,
+    ] {
+        session_context
+            .register_udf(array_udf::ArrayContainmentUDF::new(operator).into_scalar_udf());
+    }
+    session_context.register_udf(format_udf::ToCharUDF::new().into_scalar_udf());
+    session_context.register_udf(format_udf::ParseDateUDF::new(false).into_scalar_udf());
+    session_context.register_udf(format_udf::ParseDateUDF::new(true).into_scalar_udf());
+    session_context.register_udf(format_udf::ToNumberUDF::new().into_scalar_udf());
+    session_context.register_udf(date_part_udf::DatePartUDF::new().into_scalar_udf());
+    session_context.register_udf(clock_udf::StartTimestampUDF::transaction().into_scalar_udf());
+    session_context.register_udf(clock_udf::StartTimestampUDF::statement().into_scalar_udf());
+    session_context.register_udf(clock_udf::ClockTimestampUDF::new().into_scalar_udf());
+    session_context.register_udf(clock_udf::AgeUDF::new().into_scalar_udf());
+    session_context.register_udf(clock_udf::TimezoneUDF::new().into_scalar_udf());
+    session_context.register_udf(interval_udf::IntervalMulUDF::new(false).into_scalar_udf());
+    session_context.register_udf(interval_udf::IntervalMulUDF::new(true).into_scalar_udf());
+    session_context.register_udf(interval_udf::IntervalCmpUDF::new().into_scalar_udf());
+    session_context.register_udf(interval_udf::TimestampMiUDF::new().into_scalar_udf());
+    for as_text in [false, true] {
+        session_context.register_udf(json_udf::JsonExtractPathUDF::new(as_text).into_scalar_udf());
+        session_context
+            .register_udf(json_udf::JsonArrayElementsUDF::new(as_text).into_scalar_udf());
+    }
+    session_context.register_udf(json_udf::JsonBuildObjectUDF::new().into_scalar_udf());
+    for udf in advisory_lock_udfs() {
+        session_context.register_udf(udf);
+    }
+    session_context
+        .state_ref()
+        .write()
+        .register_expr_planner(Arc::new(array_udf::PgArraySubscriptPlanner::new()))?;
+    session_context
+        .state_ref()
+        .write()
+        .register_expr_planner(Arc::new(interval_udf::PgIntervalPlanner::new()))?;
+    session_context
+        .state_ref()
+        .write()
+        .register_expr_planner(Arc::new(json_udf::PgJsonPlanner::new()))?;
+    session_context.register_udtf("generate_series", Arc::new(array_udf::PgGenerateSeriesFunc));
+    for (name, as_text) in [
+        ("jsonb_array_elements", false),
+        ("json_array_elements", false),
+        ("jsonb_array_elements_text", true),
+        ("json_array_elements_text", true),
+    ] {
+        session_context.register_udtf(name, Arc::new(json_udf::JsonArrayElementsFunc { as_text }));
+    }
+    for (name, as_text) in [
+        ("jsonb_each", false),
+        ("json_each", false),
+        ("jsonb_each_text", true),
+        ("json_each_text", true),
+    ] {
+        session_context.register_udtf(name, Arc::new(json_udf::JsonEachFunc { as_text }));
+    }
+
+    setup_system_functions(session_context, catalog_name, &SystemFunctions::all())
+}
+
+/// Register the system functions of `functions` to current `SessionContext`,
+/// deregistering the others, and list exactly those in `pg_proc`
+///
+/// Session context factories call it to gate functions per session, e.g. to
+/// leave out `pg_get_userbyid` for some tenants. The pg_catalog of
+/// `catalog_name` must be installed with [`setup_pg_catalog`].
+pub fn setup_system_functions(
+    session_context: &SessionContext,
+    catalog_name: &str,
+    functions: &SystemFunctions,
+) -> Result<(), Box<DataFusionError>> {
+    let pg_catalog = session_context
         .catalog(catalog_name)
+        .and_then(|catalog| catalog.schema("pg_catalog"))
         .ok_or_else(|| {
             DataFusionError::Configuration(format!(
-                "Catalog not found when registering pg_catalog: {catalog_name}"
+                "pg_catalog not found in catalog {catalog_name}"
             ))
-        })?
-        .register_schema("pg_catalog", Arc::new(pg_catalog))?;
-
-    session_context.register_udf(create_current_schema_udf());
-    session_context.register_udf(create_current_schemas_udf());
-    session_context.register_udf(create_version_udf());
-    session_context.register_udf(create_pg_get_userbyid_udf());
-    session_context.register_udf(create_has_table_privilege_2param_udf());
-    session_context.register_udf(create_pg_table_is_visible());
-    session_context.register_udf(create_format_type_udf());
-    session_context.register_udf(create_session_user_udf());
-    session_context.register_udtf("pg_get_keywords", static_tables.pg_get_keywords.clone());
-    session_context.register_udf(pg_get_expr_udf::PgGetExprUDF::new().into_scalar_udf());
-    session_context.register_udf(create_pg_get_partkeydef_udf());
+        })?;
+    let Some(pg_catalog) = pg_catalog
+        .as_any()
+        .downcast_ref::<PgCatalogSchemaProvider>()
+    else {
+        return Err(Box::new(DataFusionError::Configuration(format!(
+            "pg_catalog of catalog {catalog_name} is not set up by setup_pg_catalog"
+        ))));
+    };
+
+    let udfs = [
+        ("current_schema", create_current_schema_udf()),
+        ("current_schemas", create_current_schemas_udf()),
+        ("version", create_version_udf()),
+        ("pg_get_userbyid", create_pg_get_userbyid_udf()),
+        (
+            "has_table_privilege",
+            create_has_table_privilege_2param_udf(),
+        ),
+        ("pg_table_is_visible", create_pg_table_is_visible()),
+        ("format_type", create_format_type_udf()),
+        ("session_user", create_session_user_udf()),
+        (
+            "pg_get_expr",
+            pg_get_expr_udf::PgGetExprUDF::new().into_scalar_udf(),
+        ),
+        ("pg_get_partkeydef", create_pg_get_partkeydef_udf()),
+        (
+            "pg_get_serial_sequence",
+            create_pg_get_serial_sequence_udf(pg_catalog.catalog_list.clone(), catalog_name),
+        ),
+        ("pg_my_temp_schema", create_pg_my_temp_schema_udf()),
+        ("refresh_table_schema", create_refresh_table_schema_udf()),
+        (
+            "pg_is_other_temp_schema",
+            create_pg_is_other_temp_schema_udf(pg_catalog.oid_registry.clone()),
+        ),
+        (
+            "to_regclass",
+            create_to_regclass_udf(
+                pg_catalog.catalog_list.clone(),
+                pg_catalog.oid_registry.clone(),
+                catalog_name,
+            ),
+        ),
+    ];
+    for (name, udf) in udfs {
+        if functions.contains(name) {
+            session_context.register_udf(udf);
+        } else {
+            session_context.deregister_udf(udf.name());
+        }
+    }
+    if functions.contains("pg_get_keywords") {
+        session_context.register_udtf(
+            "pg_get_keywords",
+            pg_catalog.static_tables.pg_get_keywords.clone(),
+        );
+    } else {
+        session_context.deregister_udtf("pg_get_keywords");
+    }
 
+    session_context
+        .state_ref()
+        .write()
+        .config_mut()
+        .set_extension(Arc::new(functions.clone()));
     Ok(())
 }
 
+/// Install the `pg_stat_get_backend_*` functions reading the sessions of
+/// `activity`, and the `pg_stat_reset` functions zeroing their statistics,
+/// to current `SessionContext`
+pub fn setup_pg_stat_backend_functions(
+    session_context: &SessionContext,
+    activity: Arc<ActivityRegistry>,
+) {
+    let (udfs, idset) = pg_stat_activity::pg_stat_backend_functions(activity);
+    for udf in udfs {
+        session_context.register_udf(udf);
+    }
+    session_context.register_udtf("pg_stat_get_backend_idset", idset);
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::auth::{Permission, RoleConfig, User};
+    use datafusion::arrow::array::AsArray;
+    use datafusion::prelude::DataFrame;
+
+    async fn visible_relnames(ctx: &SessionContext, visibility: CatalogVisibility) -> Vec<String> {
+        visible_names(ctx, "SELECT relname FROM pg_catalog.pg_class WHERE relnamespace IN (SELECT oid FROM pg_catalog.pg_namespace WHERE nspname = 'public')", visibility).await
+    }
+
+    async fn visible_names(
+        ctx: &SessionContext,
+        sql: &str,
+        visibility: CatalogVisibility,
+    ) -> Vec<String> {
+        let df = ctx.sql(sql).await.unwrap();
+        let (mut state, plan) = df.into_parts();
+        state.config_mut().set_extension(Arc::new(visibility));
+        let batches = DataFrame::new(state, plan).collect().await.unwrap();
+
+        let mut names = batches
+            .iter()
+            .flat_map(|b| {
+                b.column(0)
+                    .as_string::<i32>()
+                    .iter()
+                    .map(|v| v.unwrap().to_string())
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+
+    #[tokio::test]
+    async fn test_catalog_visibility() {
+        let ctx = SessionContext::new();
+        ctx.sql("CREATE TABLE visible_tbl (a INT)").await.unwrap();
+        ctx.sql("CREATE TABLE hidden_tbl (a INT)").await.unwrap();
+        ctx.sql("CREATE VIEW visible_view AS SELECT a FROM visible_tbl")
+            .await
+            .unwrap();
+        ctx.sql("CREATE VIEW hidden_view AS SELECT a FROM hidden_tbl")
+            .await
+            .unwrap();
+        setup_pg_catalog(&ctx, "datafusion").unwrap();
+
+        let auth_manager = Arc::new(AuthManager::new());
+        auth_manager
+            .create_role(RoleConfig {
+                name: "analyst".to_string(),
+                is_superuser: false,
+                can_login: true,
+                can_create_db: false,
+                can_create_role: false,
+                can_create_user: false,
+                can_replication: false,
+            })
+            .await
+            .unwrap();
+        auth_manager
+            .grant_permission(
+                "analyst",
+                Permission::Select,
+                ResourceType::Table("public.visible_tbl".to_string()),
+                "postgres",
+                false,
+            )
+            .await
+            .unwrap();
+        auth_manager
+            .grant_permission(
+                "analyst",
+                Permission::Select,
+                ResourceType::Table("public.visible_view".to_string()),
+                "postgres",
+                false,
+            )
+            .await
+            .unwrap();
+        auth_manager
+            .add_user(User {
+                username: "alice".to_string(),
+                password_hash: "".to_string(),
+                roles: vec!["analyst".to_string()],
+                is_superuser: false,
+                can_login: true,
+                connection_limit: None,
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(
+            visible_relnames(
+                &ctx,
+                CatalogVisibility::new("postgres".to_string(), auth_manager.clone())
+            )
+            .await,
+            vec!["hidden_tbl", "hidden_view", "visible_tbl", "visible_view"]
+        );
+        assert_eq!(
+            visible_relnames(
+                &ctx,
+                CatalogVisibility::new("alice".to_string(), auth_manager.clone())
+            )
+            .await,
+            vec!["visible_tbl", "visible_view"]
+        );
+
+        // information_schema lists the same relations
+        for (sql, expected) in [
+            (
+                "SELECT table_name FROM information_schema.tables WHERE table_schema = 'public'",
+                vec!["visible_tbl", "visible_view"],
+            ),
+            (
+                "SELECT table_name FROM information_schema.columns WHERE table_schema = 'public'",
+                vec!["visible_tbl", "visible_view"],
+            ),
+            (
+                "SELECT table_name FROM information_schema.views WHERE table_schema = 'public'",
+                vec!["visible_view"],
+            ),
+        ] {
+            assert_eq!(
+                visible_names(
+                    &ctx,
+                    sql,
+                    CatalogVisibility::new("alice".to_string(), auth_manager.clone())
+                )
+                .await,
+                expected,
+                "{sql}"
+            );
+        }
+        assert_eq!(
+            visible_names(
+                &ctx,
+                "SELECT table_name FROM information_schema.views WHERE table_schema = 'public'",
+                CatalogVisibility::new("postgres".to_string(), auth_manager.clone())
+            )
+            .await,
+            vec!["hidden_view", "visible_view"]
+        );
+        assert!(visible_relnames(
+            &ctx,
+            CatalogVisibility::new("nobody".to_string(), auth_manager)
+        )
+        .await
+        .is_empty());
+    }
+
+    async fn query_i32(ctx: &SessionContext, sql: &str) -> Vec<Option<i32>> {
+        let batches = ctx.sql(sql).await.unwrap().collect().await.unwrap();
+        batches
+            .iter()
+            .flat_map(|b| {
+                b.column(0)
+                    .as_primitive::<datafusion::arrow::datatypes::Int32Type>()
+                    .iter()
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_stable_oids() {
+        let ctx = SessionContext::new();
+        ctx.sql("CREATE TABLE users (id INT, name VARCHAR)")
+            .await
+            .unwrap();
+        setup_pg_catalog(&ctx, "datafusion").unwrap();
+
+        // pg_attribute refers to the same oid as pg_class
+        let class_oid = "SELECT c.oid FROM pg_catalog.pg_class c WHERE c.relname = 'users'";
+        let oid = query_i32(&ctx, class_oid).await;
+        assert_eq!(oid.len(), 1);
+        let attnums = query_i32(
+            &ctx,
+            "SELECT CAST(a.attnum AS INT) AS n FROM pg_catalog.pg_attribute a JOIN pg_catalog.pg_class c ON a.attrelid = c.oid WHERE c.relname = 'users' ORDER BY n",
+        )
+        .await;
+        assert_eq!(attnums, vec![Some(1), Some(2)]);
+
+        // oids don't change after scanning other catalog tables
+        query_i32(&ctx, "SELECT oid FROM pg_catalog.pg_namespace").await;
+        query_i32(&ctx, "SELECT oid FROM pg_catalog.pg_database").await;
+        ctx.sql("CREATE TABLE orders (id INT)").await.unwrap();
+        assert_eq!(query_i32(&ctx, class_oid).await, oid);
+
+        // regclass resolution uses the same registry
+        assert_eq!(
+            query_i32(&ctx, "SELECT to_regclass('users')").await,
+            oid.clone()
+        );
+        assert_eq!(
+            query_i32(&ctx, "SELECT to_regclass('public.users')").await,
+            oid
+        );
+        assert_eq!(
+            query_i32(&ctx, "SELECT to_regclass('missing')").await,
+            vec![None]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_pg_get_serial_sequence() {
+        let ctx = SessionContext::new();
+        ctx.sql("CREATE TABLE users (id INT, name VARCHAR)")
+            .await
+            .unwrap();
+        setup_pg_catalog(&ctx, "datafusion").unwrap();
+
+        // no column has a sequence to reset
+        let batches = ctx
+            .sql("SELECT pg_get_serial_sequence('public.users', 'id') IS NULL")
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+        assert!(batches[0].column(0).as_boolean().value(0));
+
+        let err = ctx
+            .sql("SELECT pg_get_serial_sequence('missing', 'id')")
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.strip_backtrace(),
+            "Error during planning: table 'missing' not found"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_information_schema_columns() {
+        let ctx = SessionContext::new_with_config(
+            datafusion::prelude::SessionConfig::new().with_information_schema(true),
+        );
+        ctx.sql(
+            "CREATE TABLE t (id INT NOT NULL, name VARCHAR, price DECIMAL(10, 2), at TIMESTAMP)",
+        )
+        .await
+        .unwrap();
+        setup_pg_catalog(&ctx, "datafusion").unwrap();
+
+        let batches = ctx
+            .sql(
+                "SELECT table_catalog, column_name, ordinal_position, is_nullable, data_type, \
+                 udt_name, numeric_precision, numeric_scale, datetime_precision, is_identity \
+                 FROM information_schema.columns WHERE table_name = 't' ORDER BY ordinal_position",
+            )
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+        datafusion::assert_batches_eq!(
+            [
+                "+---------------+-------------+------------------+-------------+-----------------------------+-----------+-------------------+---------------+--------------------+-------------+",
+                "| table_catalog | column_name | ordinal_position | is_nullable | data_type                   | udt_name  | numeric_precision | numeric_scale | datetime_precision | is_identity |",
+                "+---------------+-------------+------------------+-------------+-----------------------------+-----------+-------------------+---------------+--------------------+-------------+",
+                "| datafusion    | id          | 1                | NO          | integer                     | int4      | 32                | 0             |                    | NO          |",
+                "| datafusion    | name        | 2                | YES         | text                        | text      |                   |               |                    | NO          |",
+                "| datafusion    | price       | 3                | YES         | numeric                     | numeric   | 10                | 2             |                    | NO          |",
+                "| datafusion    | at          | 4                | YES         | timestamp without time zone | timestamp |                   |               | 6                  | NO          |",
+                "+---------------+-------------+------------------+-------------+-----------------------------+-----------+-------------------+---------------+--------------------+-------------+",
+            ],
+            &batches
+        );
+
+        // the other views and SHOW statements keep working
+        assert!(ctx
+            .sql("SHOW TABLES")
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .is_ok());
+        assert!(ctx
+            .sql("SHOW COLUMNS FROM t")
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_information_schema_tables() {
+        let ctx = SessionContext::new();
+        ctx.sql("CREATE TABLE t (id INT)").await.unwrap();
+        ctx.sql("CREATE VIEW v AS SELECT id FROM t").await.unwrap();
+        ctx.register_csv(
+            "climate",
+            concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/../tests-integration/delhiclimate.csv"
+            ),
+            datafusion::prelude::CsvReadOptions::new(),
+        )
+        .await
+        .unwrap();
+        setup_pg_catalog(&ctx, "datafusion").unwrap();
+
+        let batches = ctx
+            .sql(
+                "SELECT table_schema, table_name, table_type, is_insertable_into \
+                 FROM information_schema.tables \
+                 WHERE table_schema = 'public' OR table_name IN ('pg_class', 'columns') \
+                 ORDER BY table_name",
+            )
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+        datafusion::assert_batches_eq!(
+            [
+                "+--------------------+------------+------------+--------------------+",
+                "| table_schema       | table_name | table_type | is_insertable_into |",
+                "+--------------------+------------+------------+--------------------+",
+                "| public             | climate    | FOREIGN    | YES                |",
+                "| information_schema | columns    | VIEW       | NO                 |",
+                "| pg_catalog         | pg_class   | BASE TABLE | NO                 |",
+                "| public             | t          | BASE TABLE | YES                |",
+                "| public             | v          | VIEW       | NO                 |",
+                "+--------------------+------------+------------+--------------------+",
+            ],
+            &batches
+        );
+    }
+
+    #[tokio::test]
+    async fn test_information_schema_schemata_and_routines() {
+        let ctx = SessionContext::new();
+        setup_pg_catalog(&ctx, "datafusion").unwrap();
+
+        let batches = ctx
+            .sql("SELECT catalog_name, schema_name, schema_owner FROM information_schema.schemata")
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+        datafusion::assert_batches_eq!(
+            [
+                "+--------------+--------------------+--------------+",
+                "| catalog_name | schema_name        | schema_owner |",
+                "+--------------+--------------------+--------------+",
+                "| datafusion   | information_schema | postgres     |",
+                "| datafusion   | pg_catalog         | postgres     |",
+                "| datafusion   | public             | postgres     |",
+                "+--------------+--------------------+--------------+",
+            ],
+            &batches
+        );
+
+        let batches = ctx
+            .sql(
+                "SELECT routine_schema, routine_name, data_type, is_deterministic \
+                 FROM information_schema.routines \
+                 WHERE routine_name IN ('version', 'pg_get_userbyid') ORDER BY routine_name",
+            )
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+        datafusion::assert_batches_eq!(
+            [
+                "+----------------+-----------------+-----------+------------------+",
+                "| routine_schema | routine_name    | data_type | is_deterministic |",
+                "+----------------+-----------------+-----------+------------------+",
+                "| pg_catalog     | pg_get_userbyid | text      | NO               |",
+                "| pg_catalog     | version         | text      | YES              |",
+                "+----------------+-----------------+-----------+------------------+",
+            ],
+            &batches
+        );
+
+        let batches = ctx
+            .sql(
+                "SELECT p.ordinal_position, p.data_type \
+                 FROM information_schema.routines r \
+                 JOIN information_schema.parameters p ON p.specific_name = r.specific_name \
+                 WHERE r.routine_name = 'pg_get_userbyid'",
+            )
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+        datafusion::assert_batches_eq!(
+            [
+                "+------------------+-----------+",
+                "| ordinal_position | data_type |",
+                "+------------------+-----------+",
+                "| 1                | integer   |",
+                "+------------------+-----------+",
+            ],
+            &batches
+        );
+    }
+
+    #[tokio::test]
+    async fn test_information_schema_constraints() {
+        let ctx = SessionContext::new();
+        ctx.sql("CREATE TABLE t (id INT PRIMARY KEY, a INT, b INT, UNIQUE (a, b))")
+            .await
+            .unwrap();
+        setup_pg_catalog(&ctx, "datafusion").unwrap();
+
+        let batches = ctx
+            .sql(
+                "SELECT tc.constraint_name, tc.constraint_type, k.column_name, k.ordinal_position \
+                 FROM information_schema.table_constraints tc \
+                 JOIN information_schema.key_column_usage k \
+                 ON k.constraint_name = tc.constraint_name AND k.table_name = tc.table_name \
+                 WHERE tc.table_name = 't' ORDER BY tc.constraint_name, k.ordinal_position",
+            )
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+        datafusion::assert_batches_eq!(
+            [
+                "+-----------------+-----------------+-------------+------------------+",
+                "| constraint_name | constraint_type | column_name | ordinal_position |",
+                "+-----------------+-----------------+-------------+------------------+",
+                "| t_a_b_key       | UNIQUE          | a           | 1                |",
+                "| t_a_b_key       | UNIQUE          | b           | 2                |",
+                "| t_pkey          | PRIMARY KEY     | id          | 1                |",
+                "+-----------------+-----------------+-------------+------------------+",
+            ],
+            &batches
+        );
+
+        let batches = ctx
+            .sql("SELECT constraint_name, delete_rule FROM information_schema.referential_constraints")
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+        assert_eq!(batches.iter().map(|b| b.num_rows()).sum::<usize>(), 0);
+        assert_eq!(batches[0].num_columns(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_pg_constraint() {
+        let ctx = SessionContext::new();
+        ctx.sql("CREATE TABLE customers (id INT PRIMARY KEY, email TEXT, UNIQUE (email))")
+            .await
+            .unwrap();
+        let references = |target: &str| {
+            HashMap::from([(REFERENCES_METADATA_KEY.to_string(), target.to_string())])
+        };
+        let schema = Arc::new(datafusion::arrow::datatypes::Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new("customer_id", DataType::Int32, true)
+                .with_metadata(references("public.customers(id)")),
+            Field::new("referrer", DataType::Utf8, true)
+                .with_metadata(references("customers(email)")),
+            Field::new("coupon", DataType::Int32, true).with_metadata(references("coupons(id)")),
+        ]));
+        let orders = MemTable::try_new(schema, vec![vec![]])
+            .unwrap()
+            .with_constraints(Constraints::new_unverified(vec![
+                datafusion::common::Constraint::PrimaryKey(vec![0]),
+            ]));
+        ctx.register_table("orders", Arc::new(orders)).unwrap();
+        setup_pg_catalog(&ctx, "datafusion").unwrap();
+
+        let batches = ctx
+            .sql(
+                "SELECT c.conname, c.contype, t.relname, c.conkey, f.relname AS frelname, \
+                 c.confkey, c.confmatchtype \
+                 FROM pg_catalog.pg_constraint c \
+                 JOIN pg_catalog.pg_class t ON t.oid = c.conrelid \
+                 LEFT JOIN pg_catalog.pg_class f ON f.oid = c.confrelid \
+                 ORDER BY c.conname",
+            )
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+        datafusion::assert_batches_eq!(
+            [
+                "+-------------------------+---------+-----------+--------+-----------+---------+---------------+",
+                "| conname                 | contype | relname   | conkey | frelname  | confkey | confmatchtype |",
+                "+-------------------------+---------+-----------+--------+-----------+---------+---------------+",
+                "| customers_email_key     | u       | customers | [2]    |           |         |               |",
+                "| customers_pkey          | p       | customers | [1]    |           |         |               |",
+                "| orders_customer_id_fkey | f       | orders    | [2]    | customers | [1]     | s             |",
+                "| orders_pkey             | p       | orders    | [1]    |           |         |               |",
+                "| orders_referrer_fkey    | f       | orders    | [3]    | customers | [2]     | s             |",
+                "+-------------------------+---------+-----------+--------+-----------+---------+---------------+",
+            ],
+            &batches
+        );
+
+        // constraints of a table by its oid
+        let batches = ctx
+            .sql("SELECT oid FROM pg_catalog.pg_class WHERE relname = 'orders'")
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+        let oid = batches[0]
+            .column(0)
+            .as_primitive::<datafusion::arrow::datatypes::Int32Type>()
+            .value(0);
+        let batches = ctx
+            .sql(&format!(
+                "SELECT count(*) FROM pg_catalog.pg_constraint WHERE conrelid = {oid}"
+            ))
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+        datafusion::assert_batches_eq!(
+            [
+                "+----------+",
+                "| count(*) |",
+                "+----------+",
+                "| 3        |",
+                "+----------+",
+            ],
+            &batches
+        );
+    }
+
+    #[tokio::test]
+    async fn test_temp_schemas() {
+        let ctx = SessionContext::new();
+        let catalog = ctx.catalog("datafusion").unwrap();
+        catalog
+            .register_schema(
+                "pg_temp_3",
+                Arc::new(datafusion::catalog::MemorySchemaProvider::new()),
+            )
+            .unwrap();
+        for sql in [
+            "CREATE TABLE t (a INT)",
+            "CREATE TABLE pg_temp_3.scratch (a INT)",
+        ] {
+            ctx.sql(sql).await.unwrap();
+        }
+        setup_pg_catalog(&ctx, "datafusion").unwrap();
+
+        let batches = ctx
+            .sql(
+                "SELECT n.nspname, pg_is_other_temp_schema(n.oid) AS is_other, \
+                 c.relname, c.relpersistence, pg_my_temp_schema() AS my_temp \
+                 FROM pg_catalog.pg_class c \
+                 JOIN pg_catalog.pg_namespace n ON n.oid = c.relnamespace \
+                 WHERE n.nspname IN ('public', 'pg_temp_3') ORDER BY n.nspname",
+            )
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+        datafusion::assert_batches_eq!(
+            [
+                "+-----------+----------+---------+----------------+---------+",
+                "| nspname   | is_other | relname | relpersistence | my_temp |",
+                "+-----------+----------+---------+----------------+---------+",
+                "| pg_temp_3 | true     | scratch | t              | 0       |",
+                "| public    | false    | t       | p              | 0       |",
+                "+-----------+----------+---------+----------------+---------+",
+            ],
+            &batches
+        );
+
+        let batches = ctx
+            .sql(
+                "SELECT table_type FROM information_schema.tables \
+                 WHERE table_schema = 'pg_temp_3'",
+            )
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+        datafusion::assert_batches_eq!(
+            [
+                "+-----------------+",
+                "| table_type      |",
+                "+-----------------+",
+                "| LOCAL TEMPORARY |",
+                "+-----------------+",
+            ],
+            &batches
+        );
+        assert!(!is_temp_schema("pg_temp_"));
+        assert!(!is_temp_schema("pg_toast_temp_3"));
+    }
+
+    #[tokio::test]
+    async fn test_system_functions() {
+        const LISTED: &str = "SELECT CAST(count(*) AS INT) FROM pg_catalog.pg_proc \
+                              WHERE proname IN ('version', 'pg_get_userbyid', 'current_schema')";
+        let ctx = SessionContext::new();
+        setup_pg_catalog(&ctx, "datafusion").unwrap();
+        let all = query_i32(&ctx, LISTED).await;
+        assert!(ctx.sql("SELECT version()").await.is_ok());
+
+        // a read-only tenant derived from the same state, without some
+        // functions
+        let tenant = SessionContext::new_with_state(ctx.state());
+        let functions = SystemFunctions::all()
+            .without_function("version")
+            .without_function("pg_get_userbyid");
+        setup_system_functions(&tenant, "datafusion", &functions).unwrap();
+        assert!(tenant.sql("SELECT version()").await.is_err());
+        assert!(tenant.sql("SELECT current_schema()").await.is_ok());
+        let current_schema = query_i32(
+            &tenant,
+            "SELECT CAST(count(*) AS INT) FROM pg_catalog.pg_proc WHERE proname = 'current_schema'",
+        )
+        .await;
+        assert_eq!(query_i32(&tenant, LISTED).await, current_schema);
+        assert!(current_schema[0].unwrap() > 0 && current_schema < all);
+
+        // the context it was derived from is left as is
+        assert!(ctx.sql("SELECT version()").await.is_ok());
+        assert_eq!(query_i32(&ctx, LISTED).await, all);
+    }
+
+    #[tokio::test]
+    async fn test_extensions() {
+        let ctx = SessionContext::new();
+        setup_pg_catalog(&ctx, "datafusion").unwrap();
+        let schema = ctx
+            .catalog("datafusion")
+            .unwrap()
+            .schema("pg_catalog")
+            .unwrap();
+        let pg_catalog = schema
+            .as_any()
+            .downcast_ref::<PgCatalogSchemaProvider>()
+            .unwrap();
+
+        pg_catalog.add_type(90001, "vector").unwrap();
+        pg_catalog
+            .add_function(90002, "l2_distance", &[90001, 90001], 701)
+            .unwrap();
+        assert!(pg_catalog
+            .add_row("pg_type", &[("no_such_column", ScalarValue::from(1))])
+            .is_err());
+
+        let batch = RecordBatch::try_from_iter(vec![(
+            "srid",
+            Arc::new(Int32Array::from(vec![4326])) as ArrayRef,
+        )])
+        .unwrap();
+        pg_catalog
+            .register_table(
+                "spatial_ref_sys",
+                Arc::new(MemTable::try_new(batch.schema(), vec![vec![batch]]).unwrap()),
+            )
+            .unwrap();
+        assert!(pg_catalog
+            .register_table("pg_type", pg_catalog.static_tables.pg_type.clone())
+            .is_err());
+
+        assert_eq!(
+            query_i32(
+                &ctx,
+                "SELECT CAST(oid AS INT) FROM pg_catalog.pg_type WHERE typname = 'vector'"
+            )
+            .await,
+            vec![Some(90001)]
+        );
+        // built-in rows are still there
+        assert_eq!(
+            query_i32(
+                &ctx,
+                "SELECT CAST(oid AS INT) FROM pg_catalog.pg_type WHERE typname = 'int4'"
+            )
+            .await,
+            vec![Some(23)]
+        );
+        assert_eq!(
+            query_i32(
+                &ctx,
+                "SELECT CAST(pronargs AS INT) FROM pg_catalog.pg_proc WHERE proname = 'l2_distance'"
+            )
+            .await,
+            vec![Some(2)]
+        );
+        let batches = ctx
+            .sql("SELECT proargtypes FROM pg_catalog.pg_proc WHERE proname = 'l2_distance'")
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+        let proargtypes = batches[0].column(0).as_list::<i32>().value(0);
+        assert_eq!(
+            proargtypes
+                .as_primitive::<datafusion::arrow::datatypes::UInt32Type>()
+                .values(),
+            &[90001, 90001]
+        );
+        assert_eq!(
+            query_i32(&ctx, "SELECT srid FROM pg_catalog.spatial_ref_sys").await,
+            vec![Some(4326)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_vector_types() {
+        let ctx = SessionContext::new();
+        setup_pg_catalog(&ctx, "datafusion").unwrap();
+
+        let df = ctx
+            .sql(
+                "SELECT i.indkey, p.proargtypes, s.stavalues1 \
+                 FROM pg_catalog.pg_index i, pg_catalog.pg_proc p, pg_catalog.pg_statistic s \
+                 WHERE i.indnatts = 3 AND p.proname = 'pg_get_userbyid' \
+                 AND s.stavalues1 = '{f,t}' LIMIT 1",
+            )
+            .await
+            .unwrap();
+        let pg_types = df
+            .schema()
+            .fields()
+            .iter()
+            .map(|field| arrow_pg::datatypes::field_into_pg_type(field).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            pg_types,
+            vec![
+                postgres_types::Type::INT2_VECTOR,
+                postgres_types::Type::OID_VECTOR,
+                postgres_types::Type::ANYARRAY,
+            ]
+        );
+        let batches = df.collect().await.unwrap();
+        let indkey = batches[0].column(0).as_list::<i32>().value(0);
+        assert_eq!(indkey.len(), 3);
+        assert_eq!(indkey.data_type(), &DataType::Int16);
+    }
+
+    #[tokio::test]
+    async fn test_oid_types() {
+        let ctx = SessionContext::new();
+        ctx.sql("CREATE TABLE t (a INT)").await.unwrap();
+        setup_pg_catalog(&ctx, "datafusion").unwrap();
+
+        let df = ctx
+            .sql(
+                "SELECT c.oid, c.relnamespace, a.atttypid, t.oid AS typ, c.relnatts \
+                 FROM pg_catalog.pg_class c \
+                 JOIN pg_catalog.pg_attribute a ON a.attrelid = c.oid \
+                 JOIN pg_catalog.pg_type t ON t.oid = a.atttypid \
+                 WHERE c.relname = 't'",
+            )
+            .await
+            .unwrap();
+        let pg_types = df
+            .schema()
+            .fields()
+            .iter()
+            .map(|field| arrow_pg::datatypes::field_into_pg_type(field).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            pg_types,
+            vec![
+                postgres_types::Type::OID,
+                postgres_types::Type::OID,
+                postgres_types::Type::OID,
+                postgres_types::Type::OID,
+                postgres_types::Type::INT2,
+            ]
+        );
+        let batches = df.collect().await.unwrap();
+        assert_eq!(batches.iter().map(|b| b.num_rows()).sum::<usize>(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_key_filter_pushdown() {
+        let ctx = SessionContext::new();
+        ctx.sql("CREATE TABLE users (id INT, name VARCHAR)")
+            .await
+            .unwrap();
+        ctx.sql("CREATE TABLE orders (id INT)").await.unwrap();
+        setup_pg_catalog(&ctx, "datafusion").unwrap();
+
+        let oid = query_i32(&ctx, "SELECT to_regclass('users')").await[0].unwrap();
+        let attnums = query_i32(
+            &ctx,
+            &format!("SELECT CAST(attnum AS INT) AS n FROM pg_catalog.pg_attribute WHERE attrelid = {oid} ORDER BY n"),
+        )
+        .await;
+        assert_eq!(attnums, vec![Some(1), Some(2)]);
+
+        let oids = query_i32(
+            &ctx,
+            &format!(
+                "SELECT oid FROM pg_catalog.pg_class WHERE oid IN ({oid}, 1) AND relname = 'users'"
+            ),
+        )
+        .await;
+        assert_eq!(oids, vec![Some(oid)]);
+
+        // the filter is still applied to the generated rows
+        let oids = query_i32(
+            &ctx,
+            &format!(
+                "SELECT oid FROM pg_catalog.pg_class WHERE oid = {oid} AND relname = 'orders'"
+            ),
+        )
+        .await;
+        assert!(oids.is_empty());
+    }
+
+    /// Schema provider counting the tables it resolves
+    #[derive(Debug)]
+    struct CountingSchemaProvider {
+        inner: datafusion::catalog::MemorySchemaProvider,
+        resolved: std::sync::Mutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl SchemaProvider for CountingSchemaProvider {
+        fn as_any(&self) -> &dyn std::any::Any {
+            self
+        }
+
+        fn table_names(&self) -> Vec<String> {
+            self.inner.table_names()
+        }
+
+        async fn table(&self, name: &str) -> Result<Option<Arc<dyn TableProvider>>> {
+            self.resolved.lock().unwrap().push(name.to_string());
+            self.inner.table(name).await
+        }
+
+        fn table_exist(&self, name: &str) -> bool {
+            self.inner.table_exist(name)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_lazy_relation_resolution() {
+        use datafusion::arrow::datatypes::Schema;
+
+        let remote = Arc::new(CountingSchemaProvider {
+            inner: datafusion::catalog::MemorySchemaProvider::new(),
+            resolved: std::sync::Mutex::new(Vec::new()),
+        });
+        for (name, columns) in [("users", 2), ("orders", 1)] {
+            let fields = (0..columns)
+                .map(|i| Field::new(format!("c{i}"), DataType::Int32, true))
+                .collect::<Vec<_>>();
+            let table = MemTable::try_new(Arc::new(Schema::new(fields)), vec![vec![]]).unwrap();
+            remote
+                .inner
+                .register_table(name.to_string(), Arc::new(table))
+                .unwrap();
+        }
+
+        let ctx = SessionContext::new();
+        ctx.catalog("datafusion")
+            .unwrap()
+            .register_schema("remote", remote.clone())
+            .unwrap();
+        setup_pg_catalog(&ctx, "datafusion").unwrap();
+        let resolved = || std::mem::take(&mut *remote.resolved.lock().unwrap());
+
+        // relname filters only resolve the named table
+        let oids = query_i32(
+            &ctx,
+            "SELECT oid FROM pg_catalog.pg_class WHERE relname = 'users'",
+        )
+        .await;
+        assert_eq!(resolved(), vec!["users"]);
+        let oid = oids[0].unwrap();
+
+        // the schema resolved by pg_class is reused by pg_attribute
+        let sql = format!("SELECT attrelid FROM pg_catalog.pg_attribute WHERE attrelid = {oid}");
+        assert_eq!(query_i32(&ctx, &sql).await.len(), 2);
+        assert_eq!(query_i32(&ctx, &sql).await.len(), 2);
+        assert!(resolved().is_empty());
+
+        let registry = ctx
+            .catalog("datafusion")
+            .unwrap()
+            .schema("pg_catalog")
+            .unwrap()
+            .as_any()
+            .downcast_ref::<PgCatalogSchemaProvider>()
+            .unwrap()
+            .oid_registry()
+            .clone();
+        registry.clear_relations();
+        assert_eq!(query_i32(&ctx, &sql).await.len(), 2);
+        assert_eq!(resolved(), vec!["users"]);
+    }
 
     #[test]
     fn test_load_arrow_data() {
@@ -1077,11 +3131,6 @@ mod test {
         )
         .expect("Failed to load ipc data");
 
-        let _ = ArrowTable::from_ipc_data(
-            include_bytes!("../../pg_catalog_arrow_exports/pg_constraint.feather").to_vec(),
-        )
-        .expect("Failed to load ipc data");
-
         let _ = ArrowTable::from_ipc_data(
             include_bytes!("../../pg_catalog_arrow_exports/pg_db_role_setting.feather").to_vec(),
         )
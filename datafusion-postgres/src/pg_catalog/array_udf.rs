@@ -0,0 +1,720 @@
+//! Postgres array introspection and subscripts over arrow lists.
+//!
+//! `array_lower`, `array_upper` and `cardinality` follow postgres: arrays
+//! start at 1, and the dimensions of nested lists are those of their first
+//! element. `arr[i]` and `arr[i:j]` are planned by [`PgArraySubscriptPlanner`]
+//! with 1-based, inclusive bounds, out of range subscripts giving `NULL` and
+//! slices clamped to the array.
+//!
+//! The operators `@>`, `<@` and `&&` of arrays are planned by
+//! [`PgArrayOperatorPlanner`] to `arraycontains`, `arraycontained` and
+//! `arrayoverlap`, which compare the elements of all dimensions.
+//!
+//! `generate_series` takes these `int4` subscripts as bounds, so arrays can be
+//! iterated with `generate_series(1, array_upper(arr, 1))`.
+
+use std::any::Any;
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use datafusion::arrow::array::{
+    new_null_array, Array, ArrayRef, AsArray, BooleanArray, GenericListArray, Int32Array,
+    ListArray, UInt32Array,
+};
+use datafusion::arrow::buffer::OffsetBuffer;
+use datafusion::arrow::compute::{cast, take};
+use datafusion::arrow::datatypes::{DataType, Field, FieldRef, Int64Type};
+use datafusion::catalog::{TableFunctionImpl, TableProvider};
+use datafusion::common::{plan_err, DFSchema, ScalarValue};
+use datafusion::error::Result;
+use datafusion::functions_table::generate_series::GenerateSeriesFunc;
+use datafusion::logical_expr::expr::ScalarFunction;
+use datafusion::logical_expr::planner::{
+    ExprPlanner, PlannerResult, RawBinaryExpr, RawFieldAccessExpr,
+};
+use datafusion::logical_expr::type_coercion::binary::comparison_coercion;
+use datafusion::logical_expr::{
+    ColumnarValue, Expr, ExprSchemable, GetFieldAccess, ScalarFunctionArgs, ScalarUDF,
+    ScalarUDFImpl, Signature, Volatility,
+};
+use datafusion::sql::sqlparser::ast::BinaryOperator;
+
+/// Field of the elements of a list type
+fn element_field(data_type: &DataType) -> Result<FieldRef> {
+    match data_type {
+        DataType::List(field) | DataType::LargeList(field) | DataType::FixedSizeList(field, _) => {
+            Ok(field.clone())
+        }
+        data_type => plan_err!("Expected an array, got {data_type}"),
+    }
+}
+
+/// The list of `array`, cast to a `ListArray`
+fn as_list(array: &ArrayRef) -> Result<ListArray> {
+    let field = element_field(array.data_type())?;
+    let array = cast(array, &DataType::List(field))?;
+    Ok(array.as_list::<i32>().clone())
+}
+
+/// Integers of `array` as `i64`s
+fn as_i64s(array: &ArrayRef) -> Result<Vec<Option<i64>>> {
+    let array = cast(array, &DataType::Int64)?;
+    Ok(array.as_primitive::<Int64Type>().iter().collect())
+}
+
+/// Call `f` on the arguments as arrays, returning a scalar when all
+/// arguments are scalars
+pub(super) fn invoke(
+    args: ScalarFunctionArgs,
+    f: impl FnOnce(&[ArrayRef]) -> Result<ArrayRef>,
+) -> Result<ColumnarValue> {
+    let is_scalar = args
+        .args
+        .iter()
+        .all(|arg| matches!(arg, ColumnarValue::Scalar(_)));
+    let arrays = ColumnarValue::values_to_arrays(&args.args)?;
+    let result = f(&arrays)?;
+    if is_scalar {
+        Ok(ColumnarValue::Scalar(ScalarValue::try_from_array(
+            &result, 0,
+        )?))
+    } else {
+        Ok(ColumnarValue::Array(result))
+    }
+}
+
+/// Length of dimension `dim` of the array in `list` at `idx`, `None` for
+/// arrays without that dimension
+fn dimension_length(list: &ListArray, idx: usize, dim: i64) -> Option<usize> {
+    if list.is_null(idx) || dim < 1 {
+        return None;
+    }
+    let mut values = list.value(idx);
+    for _ in 1..dim {
+        if values.is_empty() || values.is_null(0) {
+            return None;
+        }
+        let nested = as_list(&values).ok()?;
+        values = nested.value(0);
+    }
+    (!values.is_empty()).then_some(values.len())
+}
+
+/// Number of elements of `values`, counting those of nested lists
+fn element_count(values: &ArrayRef) -> usize {
+    match as_list(values) {
+        Ok(nested) => (0..nested.len())
+            .filter(|idx| nested.is_valid(*idx))
+            .map(|idx| element_count(&nested.value(idx)))
+            .sum(),
+        Err(_) => values.len(),
+    }
+}
+
+/// `array_lower(anyarray, int)` and `array_upper(anyarray, int)`
+#[derive(Debug)]
+pub(crate) struct ArrayBoundUDF {
+    signature: Signature,
+    name: &'static str,
+    is_upper: bool,
+}
+
+impl ArrayBoundUDF {
+    pub(crate) fn new(is_upper: bool) -> Self {
+        ArrayBoundUDF {
+            signature: Signature::any(2, Volatility::Immutable),
+            name: if is_upper {
+                "array_upper"
+            } else {
+                "array_lower"
+            },
+            is_upper,
+        }
+    }
+
+    pub(crate) fn into_scalar_udf(self) -> ScalarUDF {
+        ScalarUDF::new_from_impl(self)
+    }
+}
+
+impl ScalarUDFImpl for ArrayBoundUDF {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn signature(&self) -> &Signature {
+        &self.signature
+    }
+
+    fn return_type(&self, arg_types: &[DataType]) -> Result<DataType> {
+        element_field(&arg_types[0])?;
+        Ok(DataType::Int32)
+    }
+
+    fn invoke_with_args(&self, args: ScalarFunctionArgs) -> Result<ColumnarValue> {
+        invoke(args, |arrays| {
+            let list = as_list(&arrays[0])?;
+            let dims = as_i64s(&arrays[1])?;
+            let bounds = (0..list.len())
+                .map(|idx| {
+                    let length = dimension_length(&list, idx, dims[idx]?)?;
+                    Some(if self.is_upper { length as i32 } else { 1 })
+                })
+                .collect::<Int32Array>();
+            Ok(Arc::new(bounds))
+        })
+    }
+}
+
+/// `cardinality(anyarray)`, the number of elements of all dimensions
+#[derive(Debug)]
+pub(crate) struct CardinalityUDF {
+    signature: Signature,
+}
+
+impl CardinalityUDF {
+    pub(crate) fn new() -> Self {
+        CardinalityUDF {
+            signature: Signature::any(1, Volatility::Immutable),
+        }
+    }
+
+    pub(crate) fn into_scalar_udf(self) -> ScalarUDF {
+        ScalarUDF::new_from_impl(self)
+    }
+}
+
+impl ScalarUDFImpl for CardinalityUDF {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn name(&self) -> &str {
+        "cardinality"
+    }
+
+    fn signature(&self) -> &Signature {
+        &self.signature
+    }
+
+    fn return_type(&self, arg_types: &[DataType]) -> Result<DataType> {
+        element_field(&arg_types[0])?;
+        Ok(DataType::Int32)
+    }
+
+    fn invoke_with_args(&self, args: ScalarFunctionArgs) -> Result<ColumnarValue> {
+        invoke(args, |arrays| {
+            let list = as_list(&arrays[0])?;
+            let counts = (0..list.len())
+                .map(|idx| {
+                    list.is_valid(idx)
+                        .then(|| element_count(&list.value(idx)) as i32)
+                })
+                .collect::<Int32Array>();
+            Ok(Arc::new(counts))
+        })
+    }
+}
+
+/// `arr[i]`, the element at the 1-based subscript
+#[derive(Debug)]
+pub(crate) struct ArraySubscriptUDF {
+    signature: Signature,
+}
+
+impl ArraySubscriptUDF {
+    pub(crate) fn new() -> Self {
+        ArraySubscriptUDF {
+            signature: Signature::any(2, Volatility::Immutable),
+        }
+    }
+}
+
+impl ScalarUDFImpl for ArraySubscriptUDF {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn name(&self) -> &str {
+        "pg_array_subscript"
+    }
+
+    fn signature(&self) -> &Signature {
+        &self.signature
+    }
+
+    fn return_type(&self, arg_types: &[DataType]) -> Result<DataType> {
+        Ok(element_field(&arg_types[0])?.data_type().clone())
+    }
+
+    fn invoke_with_args(&self, args: ScalarFunctionArgs) -> Result<ColumnarValue> {
+        invoke(args, |arrays| {
+            let list = as_list(&arrays[0])?;
+            let subscripts = as_i64s(&arrays[1])?;
+            let offsets = list.value_offsets();
+            let indices = (0..list.len())
+                .map(|idx| {
+                    let subscript = subscripts[idx]?;
+                    let length = (offsets[idx + 1] - offsets[idx]) as i64;
+                    (list.is_valid(idx) && (1..=length).contains(&subscript))
+                        .then(|| (offsets[idx] as i64 + subscript - 1) as u32)
+                })
+                .collect::<UInt32Array>();
+            Ok(take(list.values().as_ref(), &indices, None)?)
+        })
+    }
+}
+
+/// `arr[i:j]`, the elements between the 1-based subscripts, inclusive
+#[derive(Debug)]
+pub(crate) struct ArraySliceUDF {
+    signature: Signature,
+}
+
+impl ArraySliceUDF {
+    pub(crate) fn new() -> Self {
+        ArraySliceUDF {
+            signature: Signature::any(3, Volatility::Immutable),
+        }
+    }
+}
+
+impl ScalarUDFImpl for ArraySliceUDF {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn name(&self) -> &str {
+        "pg_array_slice"
+    }
+
+    fn signature(&self) -> &Signature {
+        &self.signature
+    }
+
+    fn return_type(&self, arg_types: &[DataType]) -> Result<DataType> {
+        Ok(DataType::List(element_field(&arg_types[0])?))
+    }
+
+    fn invoke_with_args(&self, args: ScalarFunctionArgs) -> Result<ColumnarValue> {
+        invoke(args, |arrays| {
+            let list = as_list(&arrays[0])?;
+            let (lowers, uppers) = (as_i64s(&arrays[1])?, as_i64s(&arrays[2])?);
+            let offsets = list.value_offsets();
+
+            let mut indices = Vec::new();
+            let mut lengths = Vec::with_capacity(list.len());
+            let mut valid = Vec::with_capacity(list.len());
+            for idx in 0..list.len() {
+                let (Some(lower), Some(upper)) = (lowers[idx], uppers[idx]) else {
+                    lengths.push(0);
+                    valid.push(false);
+                    continue;
+                };
+                let length = (offsets[idx + 1] - offsets[idx]) as i64;
+                let (lower, upper) = (lower.max(1), upper.min(length));
+                let start = offsets[idx] as i64;
+                indices.extend((lower..=upper).map(|subscript| (start + subscript - 1) as u32));
+                lengths.push((upper - lower + 1).max(0) as usize);
+                valid.push(list.is_valid(idx));
+            }
+
+            let field = element_field(list.data_type())?;
+            let values = if indices.is_empty() {
+                new_null_array(field.data_type(), 0)
+            } else {
+                take(list.values().as_ref(), &UInt32Array::from(indices), None)?
+            };
+            let slices = GenericListArray::<i32>::try_new(
+                field,
+                OffsetBuffer::from_lengths(lengths),
+                values,
+                Some(valid.into()),
+            )?;
+            Ok(Arc::new(slices))
+        })
+    }
+}
+
+/// Postgres array operators planned to functions by [`PgArrayOperatorPlanner`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum ArrayOperator {
+    /// `a @> b`, `arraycontains(a, b)`
+    Contains,
+    /// `a <@ b`, `arraycontained(a, b)`
+    Contained,
+    /// `a && b`, `arrayoverlap(a, b)`
+    Overlap,
+}
+
+/// Element type and number of dimensions of a (nested) list type
+fn base_type(data_type: &DataType) -> (DataType, usize) {
+    match element_field(data_type) {
+        Ok(field) => {
+            let (base, dims) = base_type(field.data_type());
+            (base, dims + 1)
+        }
+        Err(_) => (data_type.clone(), 0),
+    }
+}
+
+/// List type of `dims` dimensions of `base` elements
+fn list_type(base: DataType, dims: usize) -> DataType {
+    (0..dims).fold(base, |data_type, _| {
+        DataType::List(Arc::new(Field::new_list_field(data_type, true)))
+    })
+}
+
+/// The elements of all dimensions of the (nested) list in `array`
+fn flatten(array: ArrayRef) -> Result<ArrayRef> {
+    if element_field(array.data_type()).is_err() {
+        return Ok(array);
+    }
+    let list = as_list(&array)?;
+    let offsets = list.value_offsets();
+    let (start, end) = (offsets[0] as usize, offsets[list.len()] as usize);
+    flatten(list.values().slice(start, end - start))
+}
+
+/// The non-null elements of `array`, and whether it has a null element
+fn elements(array: ArrayRef) -> Result<(HashSet<ScalarValue>, bool)> {
+    let values = flatten(array)?;
+    let mut elements = HashSet::with_capacity(values.len());
+    for idx in 0..values.len() {
+        if values.is_valid(idx) {
+            elements.insert(ScalarValue::try_from_array(&values, idx)?);
+        }
+    }
+    Ok((elements, values.null_count() > 0))
+}
+
+/// `arraycontains`, `arraycontained` and `arrayoverlap`, comparing the
+/// elements of arrays of any dimensions like postgres
+///
+/// Null elements never match, so `ARRAY[1, NULL] @> ARRAY[NULL]` is false.
+#[derive(Debug)]
+pub(crate) struct ArrayContainmentUDF {
+    signature: Signature,
+    operator: ArrayOperator,
+}
+
+impl ArrayContainmentUDF {
+    pub(crate) fn new(operator: ArrayOperator) -> Self {
+        ArrayContainmentUDF {
+            signature: Signature::user_defined(Volatility::Immutable),
+            operator,
+        }
+    }
+
+    pub(crate) fn into_scalar_udf(self) -> ScalarUDF {
+        ScalarUDF::new_from_impl(self)
+    }
+}
+
+impl ScalarUDFImpl for ArrayContainmentUDF {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn name(&self) -> &str {
+        match self.operator {
+            ArrayOperator::Contains => "arraycontains",
+            ArrayOperator::Contained => "arraycontained",
+            ArrayOperator::Overlap => "arrayoverlap",
+        }
+    }
+
+    fn signature(&self) -> &Signature {
+        &self.signature
+    }
+
+    fn coerce_types(&self, arg_types: &[DataType]) -> Result<Vec<DataType>> {
+        let [left, right] = arg_types else {
+            return plan_err!("{} takes 2 arguments, got {}", self.name(), arg_types.len());
+        };
+        let ((left_base, left_dims), (right_base, right_dims)) =
+            (base_type(left), base_type(right));
+        if left_dims == 0 || right_dims == 0 {
+            return plan_err!("Function {}({left}, {right}) does not exist", self.name());
+        }
+        let Some(base) = comparison_coercion(&left_base, &right_base) else {
+            return plan_err!("Cannot compare the elements of {left} and {right}");
+        };
+        Ok(vec![
+            list_type(base.clone(), left_dims),
+            list_type(base, right_dims),
+        ])
+    }
+
+    fn return_type(&self, _arg_types: &[DataType]) -> Result<DataType> {
+        Ok(DataType::Boolean)
+    }
+
+    fn invoke_with_args(&self, args: ScalarFunctionArgs) -> Result<ColumnarValue> {
+        invoke(args, |arrays| {
+            let (left, right) = match self.operator {
+                ArrayOperator::Contained => (as_list(&arrays[1])?, as_list(&arrays[0])?),
+                _ => (as_list(&arrays[0])?, as_list(&arrays[1])?),
+            };
+            let results = (0..left.len())
+                .map(|idx| {
+                    if left.is_null(idx) || right.is_null(idx) {
+                        return Ok(None);
+                    }
+                    let (left, _) = elements(left.value(idx))?;
+                    let (right, right_nulls) = elements(right.value(idx))?;
+                    Ok(Some(match self.operator {
+                        ArrayOperator::Overlap => !left.is_disjoint(&right),
+                        _ => !right_nulls && right.is_subset(&left),
+                    }))
+                })
+                .collect::<Result<BooleanArray>>()?;
+            Ok(Arc::new(results))
+        })
+    }
+}
+
+/// `generate_series` of DataFusion, also taking the `int4` subscripts of
+/// arrays as bounds, like in `generate_series(1, array_upper(arr, 1))`
+#[derive(Debug)]
+pub(crate) struct PgGenerateSeriesFunc;
+
+impl TableFunctionImpl for PgGenerateSeriesFunc {
+    fn call(&self, exprs: &[Expr]) -> Result<Arc<dyn TableProvider>> {
+        let exprs = exprs
+            .iter()
+            .map(|expr| match expr {
+                Expr::Literal(value, metadata)
+                    if value.data_type().is_integer() && value.data_type() != DataType::Int64 =>
+                {
+                    let value = if value.is_null() {
+                        ScalarValue::Null
+                    } else {
+                        value.cast_to(&DataType::Int64)?
+                    };
+                    Ok(Expr::Literal(value, metadata.clone()))
+                }
+                expr => Ok(expr.clone()),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        GenerateSeriesFunc {}.call(&exprs)
+    }
+}
+
+/// Plans the subscripts `arr[i]` and `arr[i:j]` of arrays
+///
+/// Registered after the planners of DataFusion, so it only plans the
+/// subscripts when DataFusion is built without its array functions.
+#[derive(Debug)]
+pub(crate) struct PgArraySubscriptPlanner {
+    subscript: Arc<ScalarUDF>,
+    slice: Arc<ScalarUDF>,
+}
+
+impl PgArraySubscriptPlanner {
+    pub(crate) fn new() -> Self {
+        PgArraySubscriptPlanner {
+            subscript: Arc::new(ScalarUDF::new_from_impl(ArraySubscriptUDF::new())),
+            slice: Arc::new(ScalarUDF::new_from_impl(ArraySliceUDF::new())),
+        }
+    }
+}
+
+impl ExprPlanner for PgArraySubscriptPlanner {
+    fn plan_field_access(
+        &self,
+        expr: RawFieldAccessExpr,
+        _schema: &DFSchema,
+    ) -> Result<PlannerResult<RawFieldAccessExpr>> {
+        let RawFieldAccessExpr { field_access, expr } = expr;
+        match field_access {
+            GetFieldAccess::ListIndex { key } => Ok(PlannerResult::Planned(Expr::ScalarFunction(
+                ScalarFunction::new_udf(self.subscript.clone(), vec![expr, *key]),
+            ))),
+            GetFieldAccess::ListRange {
+                start,
+                stop,
+                stride,
+            } if matches!(
+                stride.as_ref(),
+                Expr::Literal(ScalarValue::Int64(Some(1)), _)
+            ) =>
+            {
+                Ok(PlannerResult::Planned(Expr::ScalarFunction(
+                    ScalarFunction::new_udf(self.slice.clone(), vec![expr, *start, *stop]),
+                )))
+            }
+            field_access => Ok(PlannerResult::Original(RawFieldAccessExpr {
+                field_access,
+                expr,
+            })),
+        }
+    }
+}
+
+/// Plans the array operators `@>`, `<@` and `&&` to the functions of
+/// [`ArrayContainmentUDF`]
+///
+/// Registered before the planners of DataFusion, which plan `@>` and `<@` of
+/// arrays without coercing their elements. `||` of arrays is left to the
+/// `array_concat`, `array_append` and `array_prepend` of DataFusion.
+#[derive(Debug)]
+pub(crate) struct PgArrayOperatorPlanner {
+    contains: Arc<ScalarUDF>,
+    overlap: Arc<ScalarUDF>,
+}
+
+impl PgArrayOperatorPlanner {
+    pub(crate) fn new() -> Self {
+        PgArrayOperatorPlanner {
+            contains: Arc::new(ArrayContainmentUDF::new(ArrayOperator::Contains).into_scalar_udf()),
+            overlap: Arc::new(ArrayContainmentUDF::new(ArrayOperator::Overlap).into_scalar_udf()),
+        }
+    }
+}
+
+impl ExprPlanner for PgArrayOperatorPlanner {
+    fn plan_binary_op(
+        &self,
+        expr: RawBinaryExpr,
+        schema: &DFSchema,
+    ) -> Result<PlannerResult<RawBinaryExpr>> {
+        // `@>` of json and parameters of unknown types are left to DataFusion
+        let is_array = |expr: &Expr| {
+            expr.get_type(schema)
+                .is_ok_and(|data_type| element_field(&data_type).is_ok())
+        };
+        if !is_array(&expr.left) || !is_array(&expr.right) {
+            return Ok(PlannerResult::Original(expr));
+        }
+
+        let RawBinaryExpr { op, left, right } = expr;
+        let (udf, args) = match op {
+            BinaryOperator::AtArrow => (&self.contains, vec![left, right]),
+            BinaryOperator::ArrowAt => (&self.contains, vec![right, left]),
+            BinaryOperator::PGOverlap => (&self.overlap, vec![left, right]),
+            op => return Ok(PlannerResult::Original(RawBinaryExpr { op, left, right })),
+        };
+        Ok(PlannerResult::Planned(Expr::ScalarFunction(
+            ScalarFunction::new_udf(udf.clone(), args),
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use datafusion::prelude::SessionContext;
+
+    use super::*;
+    use crate::pg_catalog::setup_pg_catalog;
+
+    async fn query(ctx: &SessionContext, sql: &str) -> Vec<String> {
+        let batches = ctx.sql(sql).await.unwrap().collect().await.unwrap();
+        datafusion::arrow::util::pretty::pretty_format_batches(&batches)
+            .unwrap()
+            .to_string()
+            .lines()
+            .map(str::to_string)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_array_functions() {
+        let ctx = SessionContext::new();
+        setup_pg_catalog(&ctx, "datafusion").unwrap();
+        let values =
+            ListArray::from_iter_primitive::<datafusion::arrow::datatypes::Int32Type, _, _>([
+                Some(vec![Some(10), Some(20), Some(30), Some(40)]),
+                Some(vec![]),
+                None,
+            ]);
+        let batch = datafusion::arrow::array::RecordBatch::try_from_iter([(
+            "arr",
+            Arc::new(values) as ArrayRef,
+        )])
+        .unwrap();
+        ctx.register_batch("t", batch).unwrap();
+
+        assert_eq!(
+            query(
+                &ctx,
+                "SELECT array_lower(arr, 1) AS lower, array_upper(arr, 1) AS upper, \
+                 cardinality(arr) AS n, arr[2] AS second, arr[5] AS fifth, \
+                 arr[2:3] AS middle, arr[0:9] AS clamped FROM t",
+            )
+            .await,
+            vec![
+                "+-------+-------+---+--------+-------+----------+------------------+",
+                "| lower | upper | n | second | fifth | middle   | clamped          |",
+                "+-------+-------+---+--------+-------+----------+------------------+",
+                "| 1     | 4     | 4 | 20     |       | [20, 30] | [10, 20, 30, 40] |",
+                "|       |       | 0 |        |       | []       | []               |",
+                "|       |       |   |        |       |          |                  |",
+                "+-------+-------+---+--------+-------+----------+------------------+",
+            ]
+        );
+
+        assert_eq!(
+            query(
+                &ctx,
+                "SELECT (current_schemas(false))[s.r] AS nspname \
+                 FROM generate_series(1, array_upper(current_schemas(false), 1)) AS s(r)",
+            )
+            .await,
+            vec![
+                "+---------+",
+                "| nspname |",
+                "+---------+",
+                "| public  |",
+                "+---------+",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_array_operators() {
+        let ctx = SessionContext::new();
+        setup_pg_catalog(&ctx, "datafusion").unwrap();
+        let tags =
+            ListArray::from_iter_primitive::<datafusion::arrow::datatypes::Int32Type, _, _>([
+                Some(vec![Some(1), Some(2), None]),
+                Some(vec![Some(3)]),
+                None,
+            ]);
+        let wanted =
+            ListArray::from_iter_primitive::<datafusion::arrow::datatypes::Int64Type, _, _>([
+                Some(vec![Some(2), Some(2)]),
+                Some(vec![]),
+                Some(vec![Some(1)]),
+            ]);
+        let batch = datafusion::arrow::array::RecordBatch::try_from_iter([
+            ("tags", Arc::new(tags) as ArrayRef),
+            ("wanted", Arc::new(wanted) as ArrayRef),
+        ])
+        .unwrap();
+        ctx.register_batch("t", batch).unwrap();
+
+        assert_eq!(
+            query(
+                &ctx,
+                "SELECT tags @> wanted AS contains, wanted <@ tags AS contained, \
+                 tags && wanted AS overlap, wanted @> tags AS reversed, \
+                 arraycontained(tags, tags) AS nulls FROM t",
+            )
+            .await,
+            vec![
+                "+----------+-----------+---------+----------+-------+",
+                "| contains | contained | overlap | reversed | nulls |",
+                "+----------+-----------+---------+----------+-------+",
+                "| true     | true      | true    | false    | false |",
+                "| true     | true      | false   | false    | true  |",
+                "|          |           |         |          |       |",
+                "+----------+-----------+---------+----------+-------+",
+            ]
+        );
+    }
+}
@@ -0,0 +1,591 @@
+//! Postgres current time functions and `age`.
+//!
+//! `now()`, `current_timestamp` and `transaction_timestamp()` answer the start
+//! of the transaction, `statement_timestamp()` the start of the statement and
+//! `clock_timestamp()` the time it is evaluated, as a `timestamptz`. The
+//! handlers bind them to the times tracked in `pg_stat_activity` with
+//! [`bind_statement_timestamp`] and the query execution start time, which
+//! sessions not served by them leave to the start of planning.
+//!
+//! `age(a, b)` subtracts timestamps to a "symbolic" interval of years, months
+//! and days, like `age('2001-04-10', '1957-06-13')` is
+//! `43 years 9 mons 27 days`, and `age(a)` subtracts from the current date at
+//! midnight in UTC.
+//!
+//! `timezone(zone, timestamp)` converts between time zones like
+//! `timestamp AT TIME ZONE zone` in postgres: the local time in `zone` of a
+//! `timestamptz`, and the `timestamptz` of a `timestamp` read as a local time
+//! in `zone`. Zones are the names of `pg_timezone_names` or intervals, like
+//! `timezone(INTERVAL '-08:00', ts)`.
+
+use std::any::Any;
+use std::sync::Arc;
+
+use chrono::{DateTime, Datelike, NaiveDate, NaiveDateTime, Timelike, Utc};
+use datafusion::arrow::array::{
+    Array, ArrayRef, AsArray, IntervalMonthDayNanoArray, TimestampMicrosecondArray,
+    TimestampNanosecondArray,
+};
+use datafusion::arrow::datatypes::{
+    DataType, IntervalMonthDayNano, IntervalMonthDayNanoType, IntervalUnit, TimeUnit,
+    TimestampMicrosecondType,
+};
+use datafusion::common::tree_node::{Transformed, TreeNode};
+use datafusion::common::{exec_err, internal_err, plan_err, ScalarValue};
+use datafusion::error::{DataFusionError, Result};
+use datafusion::logical_expr::expr::ScalarFunction;
+use datafusion::logical_expr::simplify::{ExprSimplifyResult, SimplifyInfo};
+use datafusion::logical_expr::{
+    ColumnarValue, Expr, LogicalPlan, ScalarFunctionArgs, ScalarUDF, ScalarUDFImpl, Signature,
+    Volatility,
+};
+
+use super::array_udf::invoke;
+use super::format_udf::Zone;
+use super::pg_timezone_names::time_zone_name;
+
+const NANOS_PER_DAY: i64 = 86_400_000_000_000;
+
+/// The `timestamptz` of the current time functions
+fn timestamptz() -> DataType {
+    DataType::Timestamp(TimeUnit::Nanosecond, Some("+00:00".into()))
+}
+
+fn timestamptz_value(time: DateTime<Utc>) -> ScalarValue {
+    ScalarValue::TimestampNanosecond(time.timestamp_nanos_opt(), Some("+00:00".into()))
+}
+
+/// `transaction_timestamp()` and `statement_timestamp()`, the start of the
+/// transaction or statement
+#[derive(Debug)]
+pub(crate) struct StartTimestampUDF {
+    signature: Signature,
+    name: &'static str,
+    // the start of the statement bound by the handlers, else the query
+    // execution start time
+    start: Option<DateTime<Utc>>,
+}
+
+impl StartTimestampUDF {
+    pub(crate) fn transaction() -> Self {
+        Self::new("transaction_timestamp", None)
+    }
+
+    pub(crate) fn statement() -> Self {
+        Self::new("statement_timestamp", None)
+    }
+
+    fn new(name: &'static str, start: Option<DateTime<Utc>>) -> Self {
+        StartTimestampUDF {
+            signature: Signature::nullary(Volatility::Stable),
+            name,
+            start,
+        }
+    }
+
+    pub(crate) fn into_scalar_udf(self) -> ScalarUDF {
+        ScalarUDF::new_from_impl(self)
+    }
+}
+
+impl ScalarUDFImpl for StartTimestampUDF {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn signature(&self) -> &Signature {
+        &self.signature
+    }
+
+    fn return_type(&self, _arg_types: &[DataType]) -> Result<DataType> {
+        Ok(timestamptz())
+    }
+
+    fn invoke_with_args(&self, _args: ScalarFunctionArgs) -> Result<ColumnarValue> {
+        internal_err!("{}() should be simplified before execution", self.name)
+    }
+
+    fn simplify(&self, _args: Vec<Expr>, info: &dyn SimplifyInfo) -> Result<ExprSimplifyResult> {
+        let start = self
+            .start
+            .unwrap_or(info.execution_props().query_execution_start_time);
+        Ok(ExprSimplifyResult::Simplified(Expr::Literal(
+            timestamptz_value(start),
+            None,
+        )))
+    }
+}
+
+/// `plan` with its calls of `statement_timestamp()` answering `start`
+pub(crate) fn bind_statement_timestamp(
+    plan: LogicalPlan,
+    start: DateTime<Utc>,
+) -> Result<LogicalPlan> {
+    let bound =
+        Arc::new(StartTimestampUDF::new("statement_timestamp", Some(start)).into_scalar_udf());
+    plan.transform_up_with_subqueries(|plan| {
+        plan.map_expressions(|expr| {
+            expr.transform_up(|expr| match expr {
+                Expr::ScalarFunction(ScalarFunction { func, args })
+                    if func.inner().as_any().is::<StartTimestampUDF>()
+                        && func.name() == "statement_timestamp" =>
+                {
+                    Ok(Transformed::yes(Expr::ScalarFunction(ScalarFunction {
+                        func: bound.clone(),
+                        args,
+                    })))
+                }
+                expr => Ok(Transformed::no(expr)),
+            })
+        })
+    })
+    .map(|transformed| transformed.data)
+}
+
+/// `clock_timestamp()`, the time of the call
+#[derive(Debug)]
+pub(crate) struct ClockTimestampUDF {
+    signature: Signature,
+}
+
+impl ClockTimestampUDF {
+    pub(crate) fn new() -> Self {
+        ClockTimestampUDF {
+            signature: Signature::nullary(Volatility::Volatile),
+        }
+    }
+
+    pub(crate) fn into_scalar_udf(self) -> ScalarUDF {
+        ScalarUDF::new_from_impl(self)
+    }
+}
+
+impl ScalarUDFImpl for ClockTimestampUDF {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn name(&self) -> &str {
+        "clock_timestamp"
+    }
+
+    fn signature(&self) -> &Signature {
+        &self.signature
+    }
+
+    fn return_type(&self, _arg_types: &[DataType]) -> Result<DataType> {
+        Ok(timestamptz())
+    }
+
+    fn invoke_with_args(&self, args: ScalarFunctionArgs) -> Result<ColumnarValue> {
+        let times = (0..args.number_rows)
+            .map(|_| Utc::now().timestamp_nanos_opt())
+            .collect::<TimestampNanosecondArray>()
+            .with_timezone("+00:00");
+        Ok(ColumnarValue::Array(Arc::new(times)))
+    }
+}
+
+/// `age(timestamp, timestamp)` and `age(timestamp)`
+#[derive(Debug)]
+pub(crate) struct AgeUDF {
+    signature: Signature,
+}
+
+impl AgeUDF {
+    pub(crate) fn new() -> Self {
+        AgeUDF {
+            signature: Signature::user_defined(Volatility::Stable),
+        }
+    }
+
+    pub(crate) fn into_scalar_udf(self) -> ScalarUDF {
+        ScalarUDF::new_from_impl(self)
+    }
+}
+
+impl ScalarUDFImpl for AgeUDF {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn name(&self) -> &str {
+        "age"
+    }
+
+    fn signature(&self) -> &Signature {
+        &self.signature
+    }
+
+    fn coerce_types(&self, arg_types: &[DataType]) -> Result<Vec<DataType>> {
+        if !matches!(arg_types.len(), 1 | 2) {
+            return plan_err!("age takes 1 or 2 arguments, got {}", arg_types.len());
+        }
+        // timestamps with a time zone are subtracted in the zone of the first
+        let tz = arg_types.iter().find_map(|arg_type| match arg_type {
+            DataType::Timestamp(_, Some(tz)) => Some(tz.clone()),
+            _ => None,
+        });
+        arg_types
+            .iter()
+            .map(|arg_type| match arg_type {
+                DataType::Timestamp(..)
+                | DataType::Date32
+                | DataType::Date64
+                | DataType::Utf8
+                | DataType::LargeUtf8
+                | DataType::Utf8View
+                | DataType::Null => Ok(DataType::Timestamp(TimeUnit::Microsecond, tz.clone())),
+                arg_type => plan_err!("Function age({arg_type}) does not exist"),
+            })
+            .collect()
+    }
+
+    fn return_type(&self, _arg_types: &[DataType]) -> Result<DataType> {
+        Ok(DataType::Interval(IntervalUnit::MonthDayNano))
+    }
+
+    fn simplify(&self, args: Vec<Expr>, info: &dyn SimplifyInfo) -> Result<ExprSimplifyResult> {
+        let [value] = args.as_slice() else {
+            return Ok(ExprSimplifyResult::Original(args));
+        };
+        let DataType::Timestamp(_, tz) = info.get_data_type(value)? else {
+            return Ok(ExprSimplifyResult::Original(args));
+        };
+        let today = info
+            .execution_props()
+            .query_execution_start_time
+            .date_naive()
+            .and_time(Default::default());
+        let today = Expr::Literal(
+            ScalarValue::TimestampMicrosecond(Some(today.and_utc().timestamp_micros()), tz),
+            None,
+        );
+        Ok(ExprSimplifyResult::Simplified(Expr::ScalarFunction(
+            ScalarFunction::new_udf(
+                Arc::new(AgeUDF::new().into_scalar_udf()),
+                vec![today, value.clone()],
+            ),
+        )))
+    }
+
+    fn invoke_with_args(&self, args: ScalarFunctionArgs) -> Result<ColumnarValue> {
+        if args.args.len() != 2 {
+            return internal_err!("age(timestamp) should be simplified before execution");
+        }
+        let tz = match args.args[0].data_type() {
+            DataType::Timestamp(_, tz) => tz,
+            _ => None,
+        };
+        let zone = tz.as_deref().map(Zone::parse).transpose()?;
+        invoke(args, |arrays| {
+            let local = |micros: i64| match DateTime::from_timestamp_micros(micros) {
+                Some(utc) => Ok(match &zone {
+                    Some(zone) => zone.moment(utc.naive_utc()).local,
+                    None => utc.naive_utc(),
+                }),
+                None => exec_err!("Timestamp out of range: {micros}"),
+            };
+            let later = arrays[0].as_primitive::<TimestampMicrosecondType>();
+            let earlier = arrays[1].as_primitive::<TimestampMicrosecondType>();
+            let ages = later
+                .iter()
+                .zip(earlier.iter())
+                .map(|(a, b)| match (a, b) {
+                    (Some(a), Some(b)) => Ok(Some(age(local(a)?, local(b)?))),
+                    _ => Ok(None),
+                })
+                .collect::<Result<IntervalMonthDayNanoArray>>()?;
+            Ok(Arc::new(ages) as ArrayRef)
+        })
+    }
+}
+
+/// `timezone(zone, timestamp)`
+#[derive(Debug)]
+pub(crate) struct TimezoneUDF {
+    signature: Signature,
+}
+
+impl TimezoneUDF {
+    pub(crate) fn new() -> Self {
+        TimezoneUDF {
+            signature: Signature::user_defined(Volatility::Immutable),
+        }
+    }
+
+    pub(crate) fn into_scalar_udf(self) -> ScalarUDF {
+        ScalarUDF::new_from_impl(self)
+    }
+}
+
+/// The zone of the time zone name or interval `zone` at `idx`
+fn zone_at(zone: &ArrayRef, idx: usize) -> Result<Zone> {
+    if let Some(offsets) = zone.as_primitive_opt::<IntervalMonthDayNanoType>() {
+        let offset = offsets.value(idx);
+        if offset.months != 0 {
+            return Err(DataFusionError::Configuration(
+                "interval time zone must not include months".to_string(),
+            ));
+        }
+        let seconds = offset.days as i64 * 86_400 + offset.nanoseconds / 1_000_000_000;
+        return Ok(Zone::Fixed(seconds as i32));
+    }
+    let name = zone.as_string::<i32>().value(idx);
+    Zone::parse(time_zone_name(name).unwrap_or(name))
+        .map_err(|_| DataFusionError::Configuration(format!("time zone \"{name}\" not recognized")))
+}
+
+impl ScalarUDFImpl for TimezoneUDF {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn name(&self) -> &str {
+        "timezone"
+    }
+
+    fn signature(&self) -> &Signature {
+        &self.signature
+    }
+
+    fn coerce_types(&self, arg_types: &[DataType]) -> Result<Vec<DataType>> {
+        let [zone, timestamp] = arg_types else {
+            return plan_err!("timezone takes 2 arguments, got {}", arg_types.len());
+        };
+        let zone = match zone {
+            DataType::Utf8 | DataType::LargeUtf8 | DataType::Utf8View | DataType::Null => {
+                DataType::Utf8
+            }
+            DataType::Interval(_) => DataType::Interval(IntervalUnit::MonthDayNano),
+            zone => return plan_err!("Function timezone({zone}, {timestamp}) does not exist"),
+        };
+        let timestamp = match timestamp {
+            DataType::Timestamp(_, tz) => DataType::Timestamp(TimeUnit::Microsecond, tz.clone()),
+            DataType::Date32 | DataType::Date64 => DataType::Timestamp(TimeUnit::Microsecond, None),
+            DataType::Utf8 | DataType::LargeUtf8 | DataType::Utf8View | DataType::Null => {
+                DataType::Timestamp(TimeUnit::Microsecond, Some("+00:00".into()))
+            }
+            timestamp => return plan_err!("Function timezone({zone}, {timestamp}) does not exist"),
+        };
+        Ok(vec![zone, timestamp])
+    }
+
+    fn return_type(&self, arg_types: &[DataType]) -> Result<DataType> {
+        Ok(match arg_types.get(1) {
+            Some(DataType::Timestamp(_, Some(_))) => {
+                DataType::Timestamp(TimeUnit::Microsecond, None)
+            }
+            _ => DataType::Timestamp(TimeUnit::Microsecond, Some("+00:00".into())),
+        })
+    }
+
+    fn invoke_with_args(&self, args: ScalarFunctionArgs) -> Result<ColumnarValue> {
+        let DataType::Timestamp(_, tz) = args.return_field.data_type().clone() else {
+            return internal_err!("timezone should return a timestamp");
+        };
+        invoke(args, |arrays| {
+            let timestamps = arrays[1].as_primitive::<TimestampMicrosecondType>();
+            let converted = (0..timestamps.len())
+                .map(|idx| {
+                    if arrays[0].is_null(idx) || timestamps.is_null(idx) {
+                        return Ok(None);
+                    }
+                    let zone = zone_at(&arrays[0], idx)?;
+                    let micros = timestamps.value(idx);
+                    let Some(time) = DateTime::from_timestamp_micros(micros) else {
+                        return exec_err!("Timestamp out of range: {micros}");
+                    };
+                    let converted = match tz {
+                        // the local time of a timestamptz
+                        None => Some(zone.moment(time.naive_utc()).local),
+                        // the instant of a local time
+                        Some(_) => zone.utc(time.naive_utc()),
+                    };
+                    Ok(converted.map(|time| time.and_utc().timestamp_micros()))
+                })
+                .collect::<Result<TimestampMicrosecondArray>>()?;
+            Ok(Arc::new(converted.with_timezone_opt(tz.clone())) as ArrayRef)
+        })
+    }
+}
+
+fn days_in_month(year: i32, month: u32) -> i32 {
+    let (next_year, next_month) = if month == 12 {
+        (year + 1, 1)
+    } else {
+        (year, month + 1)
+    };
+    match (
+        NaiveDate::from_ymd_opt(year, month, 1),
+        NaiveDate::from_ymd_opt(next_year, next_month, 1),
+    ) {
+        (Some(first), Some(next)) => (next - first).num_days() as i32,
+        _ => 31,
+    }
+}
+
+/// `a - b` in years, months and days, like postgres borrowing the days of
+/// the month of the earlier timestamp
+fn age(a: NaiveDateTime, b: NaiveDateTime) -> IntervalMonthDayNano {
+    let negative = a < b;
+    let (later, earlier) = if negative { (b, a) } else { (a, b) };
+
+    let time_nanos = |t: NaiveDateTime| {
+        t.num_seconds_from_midnight() as i64 * 1_000_000_000 + t.nanosecond() as i64
+    };
+    let mut nanos = time_nanos(later) - time_nanos(earlier);
+    let mut days = later.day() as i32 - earlier.day() as i32;
+    let mut months =
+        (later.year() - earlier.year()) * 12 + later.month() as i32 - earlier.month() as i32;
+    if nanos < 0 {
+        nanos += NANOS_PER_DAY;
+        days -= 1;
+    }
+    if days < 0 {
+        days += days_in_month(earlier.year(), earlier.month());
+        months -= 1;
+    }
+
+    if negative {
+        IntervalMonthDayNano::new(-months, -days, -nanos)
+    } else {
+        IntervalMonthDayNano::new(months, days, nanos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use datafusion::arrow::datatypes::{IntervalMonthDayNanoType, TimestampNanosecondType};
+    use datafusion::prelude::{DataFrame, SessionContext};
+
+    use super::*;
+    use crate::pg_catalog::setup_pg_catalog;
+
+    async fn age_of(ctx: &SessionContext, args: &str) -> IntervalMonthDayNano {
+        let sql = format!("SELECT age({args})");
+        let batches = ctx.sql(&sql).await.unwrap().collect().await.unwrap();
+        batches[0]
+            .column(0)
+            .as_primitive::<IntervalMonthDayNanoType>()
+            .value(0)
+    }
+
+    #[tokio::test]
+    async fn test_clock_functions() {
+        let ctx = SessionContext::new();
+        setup_pg_catalog(&ctx, "datafusion").unwrap();
+
+        for (args, expected) in [
+            (
+                "TIMESTAMP '2001-04-10', TIMESTAMP '1957-06-13'",
+                IntervalMonthDayNano::new(43 * 12 + 9, 27, 0),
+            ),
+            (
+                "TIMESTAMP '1957-06-13', TIMESTAMP '2001-04-10'",
+                IntervalMonthDayNano::new(-(43 * 12 + 9), -27, 0),
+            ),
+            (
+                "'2024-03-01', '2024-01-31'",
+                IntervalMonthDayNano::new(1, 1, 0),
+            ),
+            (
+                "TIMESTAMP '2024-01-02 01:00:00', TIMESTAMP '2024-01-01 23:30:00'",
+                IntervalMonthDayNano::new(0, 0, 90 * 60 * 1_000_000_000),
+            ),
+        ] {
+            assert_eq!(age_of(&ctx, args).await, expected, "age({args})");
+        }
+
+        // the current date at midnight is subtracted from
+        let state = ctx.state();
+        let today = state
+            .execution_props()
+            .query_execution_start_time
+            .date_naive();
+        let yesterday = today.pred_opt().unwrap();
+        let age = age_of(&ctx, &format!("DATE '{yesterday}'")).await;
+        assert_eq!(age, IntervalMonthDayNano::new(0, 1, 0));
+
+        let batches = ctx
+            .sql("SELECT now() = transaction_timestamp(), statement_timestamp() = now(), clock_timestamp() >= now()")
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+        for column in batches[0].columns() {
+            assert!(column.as_boolean().value(0));
+        }
+
+        // the handlers bind the start of the statement
+        let start = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        let df = ctx.sql("SELECT statement_timestamp()").await.unwrap();
+        let (state, plan) = df.into_parts();
+        let plan = bind_statement_timestamp(plan, start).unwrap();
+        let batches = DataFrame::new(state, plan).collect().await.unwrap();
+        let times = batches[0]
+            .column(0)
+            .as_primitive::<TimestampNanosecondType>();
+        assert_eq!(times.value(0), start.timestamp_nanos_opt().unwrap());
+        assert_eq!(batches[0].schema().field(0).name(), "statement_timestamp()");
+    }
+
+    #[tokio::test]
+    async fn test_timezone() {
+        let ctx = SessionContext::new();
+        setup_pg_catalog(&ctx, "datafusion").unwrap();
+        let timezone = |args: &str| {
+            let ctx = ctx.clone();
+            let sql = format!("SELECT arrow_cast(timezone({args}), 'Utf8')");
+            async move {
+                let batches = ctx.sql(&sql).await?.collect().await?;
+                Result::<_>::Ok(batches[0].column(0).as_string::<i32>().value(0).to_string())
+            }
+        };
+
+        // local times are read in the zone
+        assert_eq!(
+            timezone("'Europe/Berlin', TIMESTAMP '2024-07-01 12:00:00'")
+                .await
+                .unwrap(),
+            "2024-07-01T10:00:00Z"
+        );
+        assert_eq!(
+            timezone("'europe/berlin', TIMESTAMP '2024-01-01 12:00:00'")
+                .await
+                .unwrap(),
+            "2024-01-01T11:00:00Z"
+        );
+        // and timestamptz are converted to local times of the zone
+        assert_eq!(
+            timezone("'America/New_York', TIMESTAMPTZ '2024-07-01 12:00:00+00:00'")
+                .await
+                .unwrap(),
+            "2024-07-01T08:00:00"
+        );
+        assert_eq!(
+            timezone("INTERVAL '-8 hours', TIMESTAMPTZ '2024-07-01 12:00:00+00:00'")
+                .await
+                .unwrap(),
+            "2024-07-01T04:00:00"
+        );
+
+        let error = timezone("'Mars/Olympus', TIMESTAMP '2024-07-01 12:00:00'")
+            .await
+            .unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("time zone \"Mars/Olympus\" not recognized"));
+        let error = timezone("INTERVAL '1 month', TIMESTAMP '2024-07-01 12:00:00'")
+            .await
+            .unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("interval time zone must not include months"));
+    }
+}
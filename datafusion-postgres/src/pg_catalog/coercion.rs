@@ -0,0 +1,273 @@
+//! Postgres coercion of the operands of comparisons.
+//!
+//! DataFusion compares numbers with strings as strings, so
+//! `int_col = '05'` finds no rows and `int_col < '9'` compares digits. In
+//! postgres a quoted literal has no type until it is compared, and then it is
+//! read as the type of the other operand. [`PgCoercionRule`] runs before the
+//! coercion of DataFusion and
+//!
+//! - casts the string literals compared with numbers or booleans to the type
+//!   of the other operand, in comparisons, `IN` lists and `BETWEEN`, so
+//!   `int_col = '05'` compares integers and `bool_col = 't'` is a boolean
+//!   comparison. Literals which aren't valid for the type fail like
+//!   `int_col = 'abc'` does in postgres.
+//! - refuses comparisons of typed strings with numbers, like `text_col = 123`,
+//!   with the `operator does not exist` error of postgres, where DataFusion
+//!   would compare them as strings
+//!
+//! Numbers of different types are already compared like postgres does, as
+//! the wider of the two types.
+
+use datafusion::arrow::datatypes::DataType;
+use datafusion::common::config::ConfigOptions;
+use datafusion::common::tree_node::{Transformed, TreeNode};
+use datafusion::common::{plan_err, DFSchema, Diagnostic, ScalarValue};
+use datafusion::error::Result;
+use datafusion::logical_expr::expr::InList;
+use datafusion::logical_expr::expr_rewriter::NamePreserver;
+use datafusion::logical_expr::utils::merge_schema;
+use datafusion::logical_expr::{
+    cast, Between, BinaryExpr, Expr, ExprSchemable, LogicalPlan, Operator,
+};
+use datafusion::optimizer::AnalyzerRule;
+
+/// Resolve the string literals compared with numbers and booleans to their
+/// type, and refuse the comparisons of strings with numbers
+#[derive(Debug, Default)]
+pub(crate) struct PgCoercionRule;
+
+impl AnalyzerRule for PgCoercionRule {
+    fn analyze(&self, plan: LogicalPlan, _config: &ConfigOptions) -> Result<LogicalPlan> {
+        let transformed = plan.transform_up_with_subqueries(|plan| {
+            let mut schema = merge_schema(&plan.inputs());
+            if let LogicalPlan::TableScan(scan) = &plan {
+                schema.merge(&DFSchema::try_from_qualified_schema(
+                    scan.table_name.clone(),
+                    &scan.source.schema(),
+                )?);
+            }
+
+            let name_preserver = NamePreserver::new(&plan);
+            plan.map_expressions(|expr| {
+                let name = name_preserver.save(&expr);
+                expr.transform_up(|expr| coerce(expr, &schema))
+                    .map(|expr| expr.update_data(|expr| name.restore(expr)))
+            })
+        })?;
+        if transformed.transformed {
+            transformed.data.recompute_schema()
+        } else {
+            Ok(transformed.data)
+        }
+    }
+
+    fn name(&self) -> &str {
+        "pg_coercion"
+    }
+}
+
+/// Whether `expr` is a quoted literal, whose type postgres resolves from the
+/// other operand
+fn is_unknown(expr: &Expr) -> bool {
+    matches!(
+        expr,
+        Expr::Literal(
+            ScalarValue::Utf8(Some(_))
+                | ScalarValue::LargeUtf8(Some(_))
+                | ScalarValue::Utf8View(Some(_)),
+            _
+        )
+    )
+}
+
+fn is_string(data_type: &DataType) -> bool {
+    matches!(
+        data_type,
+        DataType::Utf8 | DataType::LargeUtf8 | DataType::Utf8View
+    )
+}
+
+fn is_comparison(op: Operator) -> bool {
+    matches!(
+        op,
+        Operator::Eq
+            | Operator::NotEq
+            | Operator::Lt
+            | Operator::LtEq
+            | Operator::Gt
+            | Operator::GtEq
+            | Operator::IsDistinctFrom
+            | Operator::IsNotDistinctFrom
+    )
+}
+
+fn type_name(data_type: &DataType) -> String {
+    arrow_pg::datatypes::into_pg_type(data_type).map_or_else(
+        |_| data_type.to_string(),
+        |pg_type| pg_type.name().to_string(),
+    )
+}
+
+/// The operands of `left op right`, with a quoted literal read as the type of
+/// the other operand
+fn coerce_operands(
+    left: Expr,
+    op: Operator,
+    right: Expr,
+    schema: &DFSchema,
+) -> Result<Transformed<(Expr, Expr)>> {
+    // the columns of outer queries are left to DataFusion
+    let (Ok(left_type), Ok(right_type)) = (left.get_type(schema), right.get_type(schema)) else {
+        return Ok(Transformed::no((left, right)));
+    };
+    let resolves = |data_type: &DataType| data_type.is_numeric() || data_type == &DataType::Boolean;
+
+    if is_unknown(&left) && resolves(&right_type) {
+        Ok(Transformed::yes((cast(left, right_type), right)))
+    } else if is_unknown(&right) && resolves(&left_type) {
+        Ok(Transformed::yes((left, cast(right, left_type))))
+    } else if (is_string(&left_type) && right_type.is_numeric())
+        || (left_type.is_numeric() && is_string(&right_type))
+    {
+        let message = format!(
+            "operator does not exist: {} {op} {}",
+            type_name(&left_type),
+            type_name(&right_type)
+        );
+        plan_err!("{message}").map_err(|error| {
+            error.with_diagnostic(
+                Diagnostic::new_error(message, None)
+                    .with_help("You might need to add explicit type casts.", None),
+            )
+        })
+    } else {
+        Ok(Transformed::no((left, right)))
+    }
+}
+
+fn coerce(expr: Expr, schema: &DFSchema) -> Result<Transformed<Expr>> {
+    match expr {
+        Expr::BinaryExpr(BinaryExpr { left, op, right }) if is_comparison(op) => Ok(
+            coerce_operands(*left, op, *right, schema)?.update_data(|(left, right)| {
+                Expr::BinaryExpr(BinaryExpr::new(Box::new(left), op, Box::new(right)))
+            }),
+        ),
+        Expr::InList(InList {
+            expr,
+            list,
+            negated,
+        }) => {
+            let mut transformed = false;
+            let list = list
+                .into_iter()
+                .map(|item| {
+                    let operands = coerce_operands((*expr).clone(), Operator::Eq, item, schema)?;
+                    transformed |= operands.transformed;
+                    Ok(operands.data.1)
+                })
+                .collect::<Result<Vec<_>>>()?;
+            let in_list = Expr::InList(InList {
+                expr,
+                list,
+                negated,
+            });
+            Ok(if transformed {
+                Transformed::yes(in_list)
+            } else {
+                Transformed::no(in_list)
+            })
+        }
+        Expr::Between(Between {
+            expr,
+            negated,
+            low,
+            high,
+        }) => {
+            let low = coerce_operands((*expr).clone(), Operator::GtEq, *low, schema)?;
+            let high = coerce_operands((*expr).clone(), Operator::LtEq, *high, schema)?;
+            let transformed = low.transformed || high.transformed;
+            let between = Expr::Between(Between::new(
+                expr,
+                negated,
+                Box::new(low.data.1),
+                Box::new(high.data.1),
+            ));
+            Ok(if transformed {
+                Transformed::yes(between)
+            } else {
+                Transformed::no(between)
+            })
+        }
+        _ => Ok(Transformed::no(expr)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use datafusion::prelude::SessionContext;
+    use pgwire::error::PgWireError;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_comparison_coercion() {
+        let ctx = SessionContext::new();
+        crate::pg_catalog::setup_pg_catalog(&ctx, "datafusion").unwrap();
+        ctx.sql(
+            "CREATE TABLE t (i INT, s VARCHAR, n DECIMAL(10, 2), b BOOLEAN) AS VALUES \
+             (5, '10', 1.10, true), (10, '9', 2.50, false)",
+        )
+        .await
+        .unwrap();
+        let ints = |sql: &'static str| {
+            let ctx = ctx.clone();
+            async move {
+                let batches = ctx.sql(sql).await?.collect().await?;
+                let rows = batches
+                    .iter()
+                    .flat_map(|batch| {
+                        let column = batch.column(0).as_any();
+                        let ints = column
+                            .downcast_ref::<datafusion::arrow::array::Int32Array>()
+                            .unwrap();
+                        ints.iter().flatten().collect::<Vec<_>>()
+                    })
+                    .collect::<Vec<_>>();
+                Result::<_>::Ok(rows)
+            }
+        };
+
+        assert_eq!(ints("SELECT i FROM t WHERE i = '05'").await.unwrap(), [5]);
+        assert_eq!(ints("SELECT i FROM t WHERE '9' < i").await.unwrap(), [10]);
+        assert_eq!(ints("SELECT i FROM t WHERE n = '1.1'").await.unwrap(), [5]);
+        assert_eq!(ints("SELECT i FROM t WHERE b = 'f'").await.unwrap(), [10]);
+        assert_eq!(
+            ints("SELECT i FROM t WHERE i IN ('07', '010') ORDER BY i")
+                .await
+                .unwrap(),
+            [10]
+        );
+        assert_eq!(
+            ints("SELECT i FROM t WHERE i BETWEEN '4' AND '06'")
+                .await
+                .unwrap(),
+            [5]
+        );
+        // strings are still compared as strings
+        assert_eq!(ints("SELECT i FROM t WHERE s < '5'").await.unwrap(), [5]);
+
+        let error = ints("SELECT i FROM t WHERE i = '5.5'").await.unwrap_err();
+        assert!(error.to_string().contains("Cannot cast string '5.5'"));
+        let error = ints("SELECT i FROM t WHERE s = 10").await.unwrap_err();
+        let error = crate::errors::into_sqlstate_error(PgWireError::ApiError(Box::new(error)));
+        let PgWireError::UserError(info) = error else {
+            panic!("unexpected error {error:?}");
+        };
+        assert_eq!(info.code, "42883");
+        assert_eq!(info.message, "operator does not exist: text = int8");
+        assert_eq!(
+            info.hint.as_deref(),
+            Some("You might need to add explicit type casts.")
+        );
+    }
+}
@@ -0,0 +1,510 @@
+//! Postgres `date_part`, which `EXTRACT` is rewritten to.
+//!
+//! The fields of timestamps, dates, times and intervals are those of postgres,
+//! answered as `double precision`: `second` has the fraction of the second,
+//! `dow` counts from Sunday as 0 and `isodow` from Monday as 1, `week` and
+//! `isoyear` are those of ISO 8601, and the `epoch` of an interval counts
+//! years of 365.25 days and months of 30 days. Timestamps with a time zone
+//! are read in their zone, and dates as timestamps at midnight.
+//! `EXTRACT(field FROM value)` is rewritten to `date_part('field', value)` by
+//! [`crate::sql::RewriteExtract`], as DataFusion plans it with its builtin.
+
+use std::any::Any;
+use std::fmt;
+use std::sync::Arc;
+
+use chrono::{DateTime, Datelike, Timelike};
+use datafusion::arrow::array::{new_null_array, Array, ArrayRef, AsArray, Float64Array};
+use datafusion::arrow::compute::cast;
+use datafusion::arrow::datatypes::{
+    DataType, DurationNanosecondType, IntervalMonthDayNanoType, IntervalUnit,
+    Time64MicrosecondType, TimeUnit, TimestampMicrosecondType,
+};
+use datafusion::common::{exec_err, not_impl_err, plan_err};
+use datafusion::error::{DataFusionError, Result};
+use datafusion::logical_expr::{
+    ColumnarValue, ScalarFunctionArgs, ScalarUDF, ScalarUDFImpl, Signature, Volatility,
+};
+
+use super::array_udf::invoke;
+use super::format_udf::{Moment, Zone, JULIAN_CE};
+
+const MICROS_PER_SECOND: f64 = 1_000_000.0;
+const SECONDS_PER_DAY: f64 = 86_400.0;
+
+/// The units of `date_part`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Unit {
+    Microseconds,
+    Milliseconds,
+    Second,
+    Minute,
+    Hour,
+    Day,
+    Week,
+    Month,
+    Quarter,
+    Year,
+    Decade,
+    Century,
+    Millennium,
+    Dow,
+    Isodow,
+    Doy,
+    Isoyear,
+    Julian,
+    Epoch,
+    Timezone,
+    TimezoneHour,
+    TimezoneMinute,
+}
+
+impl Unit {
+    /// The unit of a name or abbreviation postgres accepts
+    fn parse(name: &str) -> Option<Self> {
+        let unit = match name {
+            "microsecond" | "microseconds" | "us" | "usec" | "usecs" | "useconds" => {
+                Unit::Microseconds
+            }
+            "millisecond" | "milliseconds" | "ms" | "msec" | "msecs" | "mseconds" => {
+                Unit::Milliseconds
+            }
+            "second" | "seconds" | "s" | "sec" | "secs" => Unit::Second,
+            "minute" | "minutes" | "m" | "min" | "mins" => Unit::Minute,
+            "hour" | "hours" | "h" | "hr" | "hrs" => Unit::Hour,
+            "day" | "days" | "d" => Unit::Day,
+            "week" | "weeks" | "w" => Unit::Week,
+            "month" | "months" | "mon" | "mons" => Unit::Month,
+            "quarter" | "qtr" => Unit::Quarter,
+            "year" | "years" | "y" | "yr" | "yrs" => Unit::Year,
+            "decade" | "decades" | "dec" | "decs" => Unit::Decade,
+            "century" | "centuries" | "c" | "cent" => Unit::Century,
+            "millennium" | "millennia" | "millenniums" | "mil" | "mils" => Unit::Millennium,
+            "dow" => Unit::Dow,
+            "isodow" => Unit::Isodow,
+            "doy" => Unit::Doy,
+            "isoyear" => Unit::Isoyear,
+            "julian" | "j" => Unit::Julian,
+            "epoch" => Unit::Epoch,
+            "timezone" => Unit::Timezone,
+            "timezone_h" | "timezone_hour" => Unit::TimezoneHour,
+            "timezone_m" | "timezone_minute" => Unit::TimezoneMinute,
+            _ => return None,
+        };
+        Some(unit)
+    }
+}
+
+/// The postgres types of the values of `date_part`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Timestamp,
+    TimestampTz,
+    Time,
+    Interval,
+}
+
+impl Kind {
+    fn of(data_type: &DataType) -> Option<Self> {
+        match data_type {
+            DataType::Timestamp(_, Some(_)) => Some(Kind::TimestampTz),
+            DataType::Timestamp(_, None) | DataType::Date32 | DataType::Date64 => {
+                Some(Kind::Timestamp)
+            }
+            DataType::Time32(_) | DataType::Time64(_) => Some(Kind::Time),
+            DataType::Interval(_) | DataType::Duration(_) => Some(Kind::Interval),
+            _ => None,
+        }
+    }
+
+    fn supports(self, unit: Unit) -> bool {
+        match self {
+            Kind::TimestampTz => true,
+            Kind::Timestamp => !matches!(
+                unit,
+                Unit::Timezone | Unit::TimezoneHour | Unit::TimezoneMinute
+            ),
+            Kind::Time => matches!(
+                unit,
+                Unit::Microseconds
+                    | Unit::Milliseconds
+                    | Unit::Second
+                    | Unit::Minute
+                    | Unit::Hour
+                    | Unit::Epoch
+            ),
+            Kind::Interval => !matches!(
+                unit,
+                Unit::Week
+                    | Unit::Dow
+                    | Unit::Isodow
+                    | Unit::Doy
+                    | Unit::Isoyear
+                    | Unit::Julian
+                    | Unit::Timezone
+                    | Unit::TimezoneHour
+                    | Unit::TimezoneMinute
+            ),
+        }
+    }
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Kind::Timestamp => "timestamp without time zone",
+            Kind::TimestampTz => "timestamp with time zone",
+            Kind::Time => "time without time zone",
+            Kind::Interval => "interval",
+        })
+    }
+}
+
+/// Seconds, milliseconds or microseconds of the minute, with their fraction
+fn seconds_part(unit: Unit, micros_of_minute: i64) -> f64 {
+    let seconds = micros_of_minute as f64 / MICROS_PER_SECOND;
+    match unit {
+        Unit::Microseconds => micros_of_minute as f64,
+        Unit::Milliseconds => micros_of_minute as f64 / 1_000.0,
+        _ => seconds,
+    }
+}
+
+/// `unit` of the timestamp at `utc_micros` since the epoch, read at `moment`
+fn timestamp_part(unit: Unit, moment: &Moment, utc_micros: i64) -> f64 {
+    let local = &moment.local;
+    let offset = moment.zone.as_ref().map_or(0, |(offset, _)| *offset);
+    let year = local.year() as i64;
+    let micros_of_day =
+        local.num_seconds_from_midnight() as i64 * 1_000_000 + local.nanosecond() as i64 / 1_000;
+    let value = match unit {
+        Unit::Microseconds | Unit::Milliseconds | Unit::Second => {
+            return seconds_part(unit, micros_of_day % 60_000_000)
+        }
+        Unit::Minute => local.minute() as i64,
+        Unit::Hour => local.hour() as i64,
+        Unit::Day => local.day() as i64,
+        Unit::Week => local.iso_week().week() as i64,
+        Unit::Month => local.month() as i64,
+        Unit::Quarter => local.month0() as i64 / 3 + 1,
+        Unit::Year => year,
+        Unit::Decade => year.div_euclid(10),
+        Unit::Century => (year + 99).div_euclid(100),
+        Unit::Millennium => (year + 999).div_euclid(1000),
+        Unit::Dow => local.weekday().num_days_from_sunday() as i64,
+        Unit::Isodow => local.weekday().number_from_monday() as i64,
+        Unit::Doy => local.ordinal() as i64,
+        Unit::Isoyear => local.iso_week().year() as i64,
+        Unit::Julian => {
+            let day = (local.num_days_from_ce() as i64 + JULIAN_CE) as f64;
+            return day + micros_of_day as f64 / MICROS_PER_SECOND / SECONDS_PER_DAY;
+        }
+        Unit::Epoch => return utc_micros as f64 / MICROS_PER_SECOND,
+        Unit::Timezone => offset as i64,
+        Unit::TimezoneHour => offset as i64 / 3600,
+        Unit::TimezoneMinute => offset as i64 / 60 % 60,
+    };
+    value as f64
+}
+
+/// `unit` of the time of day `micros` since midnight
+fn time_part(unit: Unit, micros: i64) -> f64 {
+    match unit {
+        Unit::Hour => (micros / 3_600_000_000) as f64,
+        Unit::Minute => (micros / 60_000_000 % 60) as f64,
+        Unit::Epoch => micros as f64 / MICROS_PER_SECOND,
+        unit => seconds_part(unit, micros % 60_000_000),
+    }
+}
+
+/// `unit` of the interval of `months`, `days` and `nanos`
+fn interval_part(unit: Unit, months: i32, days: i32, nanos: i64) -> f64 {
+    let micros = nanos / 1_000;
+    let years = (months / 12) as i64;
+    let value = match unit {
+        Unit::Hour => micros / 3_600_000_000,
+        Unit::Minute => micros / 60_000_000 % 60,
+        Unit::Day => days as i64,
+        Unit::Month => (months % 12) as i64,
+        Unit::Quarter => (months % 12 / 3 + 1) as i64,
+        Unit::Year => years,
+        Unit::Decade => years / 10,
+        Unit::Century => years / 100,
+        Unit::Millennium => years / 1000,
+        Unit::Epoch => {
+            return years as f64 * 365.25 * SECONDS_PER_DAY
+                + (months % 12) as f64 * 30.0 * SECONDS_PER_DAY
+                + days as f64 * SECONDS_PER_DAY
+                + nanos as f64 / 1e9;
+        }
+        unit => return seconds_part(unit, micros % 60_000_000),
+    };
+    value as f64
+}
+
+/// `date_part(text, timestamp | date | time | interval)`
+#[derive(Debug)]
+pub(crate) struct DatePartUDF {
+    signature: Signature,
+    aliases: Vec<String>,
+}
+
+impl DatePartUDF {
+    pub(crate) fn new() -> Self {
+        DatePartUDF {
+            signature: Signature::user_defined(Volatility::Immutable),
+            aliases: vec!["datepart".to_string()],
+        }
+    }
+
+    pub(crate) fn into_scalar_udf(self) -> ScalarUDF {
+        ScalarUDF::new_from_impl(self)
+    }
+}
+
+impl ScalarUDFImpl for DatePartUDF {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn name(&self) -> &str {
+        "date_part"
+    }
+
+    fn aliases(&self) -> &[String] {
+        &self.aliases
+    }
+
+    fn signature(&self) -> &Signature {
+        &self.signature
+    }
+
+    fn coerce_types(&self, arg_types: &[DataType]) -> Result<Vec<DataType>> {
+        let [_, value] = arg_types else {
+            return plan_err!("date_part takes 2 arguments, got {}", arg_types.len());
+        };
+        let value = match value {
+            // text is read as a timestamp, like an unknown literal in postgres
+            DataType::Utf8 | DataType::LargeUtf8 | DataType::Utf8View => {
+                DataType::Timestamp(TimeUnit::Nanosecond, None)
+            }
+            DataType::Null => DataType::Null,
+            value if Kind::of(value).is_some() => value.clone(),
+            value => return plan_err!("Function date_part(text, {value}) does not exist"),
+        };
+        Ok(vec![DataType::Utf8, value])
+    }
+
+    fn return_type(&self, _arg_types: &[DataType]) -> Result<DataType> {
+        Ok(DataType::Float64)
+    }
+
+    fn invoke_with_args(&self, args: ScalarFunctionArgs) -> Result<ColumnarValue> {
+        let name = match &args.args[0] {
+            ColumnarValue::Scalar(unit) => unit.try_as_str().flatten().map(str::to_lowercase),
+            ColumnarValue::Array(_) => None,
+        };
+        let Some(name) = name else {
+            return exec_err!("First argument of date_part must be a constant text");
+        };
+        let name = name.trim_matches(|c| c == '\'' || c == '"').to_string();
+        let data_type = args.args[1].data_type();
+        let Some(kind) = Kind::of(&data_type) else {
+            return invoke(args, |arrays| {
+                Ok(new_null_array(&DataType::Float64, arrays[1].len()))
+            });
+        };
+        let Some(unit) = Unit::parse(&name) else {
+            // invalid_parameter_value, like postgres
+            return Err(DataFusionError::Configuration(format!(
+                "unit \"{name}\" not recognized for type {kind}"
+            )));
+        };
+        if !kind.supports(unit) {
+            return not_impl_err!("unit \"{name}\" not supported for type {kind}");
+        }
+
+        invoke(args, |arrays| {
+            let values = &arrays[1];
+            let parts: Float64Array = match values.data_type() {
+                DataType::Timestamp(..) | DataType::Date32 | DataType::Date64 => {
+                    let tz = match values.data_type() {
+                        DataType::Timestamp(_, tz) => tz.clone(),
+                        _ => None,
+                    };
+                    let zone = tz.as_deref().map(Zone::parse).transpose()?;
+                    let micros = cast(values, &DataType::Timestamp(TimeUnit::Microsecond, tz))?;
+                    let micros = micros.as_primitive::<TimestampMicrosecondType>();
+                    micros
+                        .iter()
+                        .map(|micros| {
+                            let Some(micros) = micros else {
+                                return Ok(None);
+                            };
+                            let Some(utc) = DateTime::from_timestamp_micros(micros) else {
+                                return exec_err!("Timestamp out of range: {micros}");
+                            };
+                            let moment = match &zone {
+                                Some(zone) => zone.moment(utc.naive_utc()),
+                                None => Moment {
+                                    local: utc.naive_utc(),
+                                    zone: None,
+                                },
+                            };
+                            Ok(Some(timestamp_part(unit, &moment, micros)))
+                        })
+                        .collect::<Result<_>>()?
+                }
+                DataType::Time32(_) | DataType::Time64(_) => {
+                    let micros = cast(values, &DataType::Time64(TimeUnit::Microsecond))?;
+                    let micros = micros.as_primitive::<Time64MicrosecondType>();
+                    micros
+                        .iter()
+                        .map(|m| m.map(|m| time_part(unit, m)))
+                        .collect()
+                }
+                DataType::Duration(_) => {
+                    let nanos = cast(values, &DataType::Duration(TimeUnit::Nanosecond))?;
+                    let nanos = nanos.as_primitive::<DurationNanosecondType>();
+                    nanos
+                        .iter()
+                        .map(|n| n.map(|n| interval_part(unit, 0, 0, n)))
+                        .collect()
+                }
+                _ => {
+                    let intervals = cast(values, &DataType::Interval(IntervalUnit::MonthDayNano))?;
+                    let intervals = intervals.as_primitive::<IntervalMonthDayNanoType>();
+                    intervals
+                        .iter()
+                        .map(|interval| {
+                            interval.map(|i| interval_part(unit, i.months, i.days, i.nanoseconds))
+                        })
+                        .collect()
+                }
+            };
+            Ok(Arc::new(parts) as ArrayRef)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use datafusion::arrow::datatypes::Float64Type;
+    use datafusion::prelude::SessionContext;
+
+    use super::*;
+    use crate::pg_catalog::setup_pg_catalog;
+
+    async fn date_part(ctx: &SessionContext, unit: &str, value: &str) -> Result<Option<f64>> {
+        let sql = format!("SELECT date_part('{unit}', {value})");
+        let batches = ctx.sql(&sql).await?.collect().await?;
+        let parts = batches[0].column(0).as_primitive::<Float64Type>();
+        Ok(parts.is_valid(0).then(|| parts.value(0)))
+    }
+
+    #[tokio::test]
+    async fn test_date_part() {
+        let ctx = SessionContext::new();
+        setup_pg_catalog(&ctx, "datafusion").unwrap();
+
+        // a Sunday, the last day of ISO week 1
+        let sunday = "TIMESTAMP '2024-01-07 20:07:09.25'";
+        for (unit, expected) in [
+            ("year", 2024.0),
+            ("quarter", 1.0),
+            ("month", 1.0),
+            ("day", 7.0),
+            ("hour", 20.0),
+            ("minute", 7.0),
+            ("second", 9.25),
+            ("milliseconds", 9250.0),
+            ("microseconds", 9_250_000.0),
+            ("dow", 0.0),
+            ("isodow", 7.0),
+            ("doy", 7.0),
+            ("week", 1.0),
+            ("isoyear", 2024.0),
+            ("decade", 202.0),
+            ("century", 21.0),
+            ("millennium", 3.0),
+            ("epoch", 1_704_658_029.25),
+            ("julian", 2_460_317.0 + 72_429.25 / 86_400.0),
+            ("SECS", 9.25),
+        ] {
+            assert_eq!(
+                date_part(&ctx, unit, sunday).await.unwrap(),
+                Some(expected),
+                "{unit}"
+            );
+        }
+        let new_year = "TIMESTAMP '2023-01-01 00:00:00'";
+        assert_eq!(date_part(&ctx, "week", new_year).await.unwrap(), Some(52.0));
+        assert_eq!(
+            date_part(&ctx, "isoyear", new_year).await.unwrap(),
+            Some(2022.0)
+        );
+        assert_eq!(
+            date_part(&ctx, "dow", "DATE '2024-01-08'").await.unwrap(),
+            Some(1.0)
+        );
+        assert_eq!(
+            date_part(&ctx, "hour", "DATE '2024-01-08'").await.unwrap(),
+            Some(0.0)
+        );
+        assert_eq!(date_part(&ctx, "year", "NULL").await.unwrap(), None);
+
+        let plus_two = "arrow_cast(TIMESTAMP '2024-07-01 12:00:00', \
+                     'Timestamp(Microsecond, Some(\"+02:00\"))')";
+        assert_eq!(
+            date_part(&ctx, "timezone", plus_two).await.unwrap(),
+            Some(7200.0)
+        );
+        assert_eq!(
+            date_part(&ctx, "timezone_hour", plus_two).await.unwrap(),
+            Some(2.0)
+        );
+        let epoch = date_part(&ctx, "epoch", plus_two).await.unwrap().unwrap();
+        let hour = date_part(&ctx, "hour", plus_two).await.unwrap().unwrap();
+        assert_eq!((epoch as i64 / 3600 % 24 + 2) as f64, hour);
+
+        let interval = "INTERVAL '1 year 2 months 3 days 4 hours 5 minutes 6.5 seconds'";
+        for (unit, expected) in [
+            ("year", 1.0),
+            ("month", 2.0),
+            ("day", 3.0),
+            ("hour", 4.0),
+            ("minute", 5.0),
+            ("second", 6.5),
+            (
+                "epoch",
+                365.25 * 86_400.0 + 60.0 * 86_400.0 + 3.0 * 86_400.0 + 14_706.5,
+            ),
+        ] {
+            assert_eq!(
+                date_part(&ctx, unit, interval).await.unwrap(),
+                Some(expected),
+                "{unit}"
+            );
+        }
+        assert_eq!(
+            date_part(&ctx, "minute", "TIME '10:30:15'").await.unwrap(),
+            Some(30.0)
+        );
+
+        let error = date_part(&ctx, "dow", interval).await.unwrap_err();
+        assert!(
+            matches!(error.find_root(), DataFusionError::NotImplemented(_)),
+            "{error}"
+        );
+        let error = date_part(&ctx, "fortnight", sunday).await.unwrap_err();
+        assert!(
+            matches!(error.find_root(), DataFusionError::Configuration(_)),
+            "{error}"
+        );
+        let error = date_part(&ctx, "timezone", sunday).await.unwrap_err();
+        assert!(
+            matches!(error.find_root(), DataFusionError::NotImplemented(_)),
+            "{error}"
+        );
+    }
+}
@@ -0,0 +1,1593 @@
+//! Postgres data type formatting functions.
+//!
+//! `to_char`, `to_timestamp`, `to_date` and `to_number` take the templates of
+//! postgres instead of the chrono formats of the DataFusion builtins they
+//! replace, so reports ported from postgres label and bucket rows the same:
+//!
+//! ```sql
+//! SELECT to_char(created_at, 'FMMonth YYYY'), to_char(amount, 'FM999,990.00')
+//! FROM orders;
+//! SELECT to_timestamp('05 Jan 2024 08:30 PM', 'DD Mon YYYY HH12:MI AM');
+//! ```
+//!
+//! Timestamps with a time zone are formatted in their zone, whose `TZ` is the
+//! abbreviation of a named zone, `UTC` or else the offset like `OF`. Parsed
+//! timestamps are in UTC unless the input has a `TZ` or `OF`. `to_number`
+//! answers a `double precision`. Calls with other arities than postgres'
+//! keep the DataFusion builtins, e.g. `to_timestamp(1700000000)`.
+
+use std::any::Any;
+use std::cell::Cell;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use chrono::{
+    DateTime, Datelike, NaiveDate, NaiveDateTime, NaiveTime, Offset, TimeDelta, TimeZone, Timelike,
+};
+use chrono_tz::OffsetName;
+use datafusion::arrow::array::{
+    new_null_array, Array, ArrayRef, AsArray, Date32Array, Float64Array, StringArray,
+    TimestampNanosecondArray,
+};
+use datafusion::arrow::compute::cast;
+use datafusion::arrow::datatypes::{
+    DataType, Decimal128Type, Float64Type, Int64Type, TimeUnit, TimestampMicrosecondType,
+};
+use datafusion::arrow::error::ArrowError;
+use datafusion::common::{exec_err, not_impl_err, plan_err};
+use datafusion::error::{DataFusionError, Result};
+use datafusion::logical_expr::{
+    ColumnarValue, ScalarFunctionArgs, ScalarUDF, ScalarUDFImpl, Signature, Volatility,
+};
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::{Decimal, RoundingStrategy};
+
+use super::array_udf::invoke;
+
+const MONTHS: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+const DAYS: [&str; 7] = [
+    "Sunday",
+    "Monday",
+    "Tuesday",
+    "Wednesday",
+    "Thursday",
+    "Friday",
+    "Saturday",
+];
+/// Width of the names of months and days, to which they are padded
+const NAME_WIDTH: usize = 9;
+/// Julian day of 0000-12-31, the day before the common era
+pub(super) const JULIAN_CE: i64 = 1_721_425;
+/// Time zone of the timestamps `to_timestamp` answers
+const UTC: &str = "+00:00";
+
+fn invalid_input(message: String) -> DataFusionError {
+    DataFusionError::ArrowError(Box::new(ArrowError::ParseError(message)), None)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Case {
+    Upper,
+    Title,
+    Lower,
+}
+
+impl Case {
+    fn apply(self, text: &str) -> String {
+        match self {
+            Case::Upper => text.to_uppercase(),
+            Case::Title => text.to_string(),
+            Case::Lower => text.to_lowercase(),
+        }
+    }
+}
+
+/// Template patterns of dates and times
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DateField {
+    Hour12,
+    Hour24,
+    Minute,
+    Second,
+    Millisecond,
+    Microsecond,
+    SecondOfDay,
+    Meridiem {
+        upper: bool,
+        dots: bool,
+    },
+    /// Last digits of the year
+    Year(u32),
+    IsoYear,
+    Century,
+    Quarter,
+    Month,
+    MonthName(Case),
+    MonthAbbrev(Case),
+    DayName(Case),
+    DayAbbrev(Case),
+    DayOfYear,
+    DayOfMonth,
+    DayOfWeek,
+    IsoDayOfWeek,
+    WeekOfMonth,
+    WeekOfYear,
+    IsoWeek,
+    Julian,
+    ZoneName(Case),
+    ZoneOffset,
+}
+
+/// Patterns in the order they are matched, longest first, and whether they
+/// are case sensitive
+const DATE_PATTERNS: &[(&str, DateField, bool)] = &[
+    ("MONTH", DateField::MonthName(Case::Upper), true),
+    ("Month", DateField::MonthName(Case::Title), true),
+    ("month", DateField::MonthName(Case::Lower), true),
+    ("MON", DateField::MonthAbbrev(Case::Upper), true),
+    ("Mon", DateField::MonthAbbrev(Case::Title), true),
+    ("mon", DateField::MonthAbbrev(Case::Lower), true),
+    ("DAY", DateField::DayName(Case::Upper), true),
+    ("Day", DateField::DayName(Case::Title), true),
+    ("day", DateField::DayName(Case::Lower), true),
+    ("DY", DateField::DayAbbrev(Case::Upper), true),
+    ("Dy", DateField::DayAbbrev(Case::Title), true),
+    ("dy", DateField::DayAbbrev(Case::Lower), true),
+    (
+        "A.M.",
+        DateField::Meridiem {
+            upper: true,
+            dots: true,
+        },
+        true,
+    ),
+    (
+        "a.m.",
+        DateField::Meridiem {
+            upper: false,
+            dots: true,
+        },
+        true,
+    ),
+    (
+        "P.M.",
+        DateField::Meridiem {
+            upper: true,
+            dots: true,
+        },
+        true,
+    ),
+    (
+        "p.m.",
+        DateField::Meridiem {
+            upper: false,
+            dots: true,
+        },
+        true,
+    ),
+    (
+        "AM",
+        DateField::Meridiem {
+            upper: true,
+            dots: false,
+        },
+        true,
+    ),
+    (
+        "am",
+        DateField::Meridiem {
+            upper: false,
+            dots: false,
+        },
+        true,
+    ),
+    (
+        "PM",
+        DateField::Meridiem {
+            upper: true,
+            dots: false,
+        },
+        true,
+    ),
+    (
+        "pm",
+        DateField::Meridiem {
+            upper: false,
+            dots: false,
+        },
+        true,
+    ),
+    ("TZ", DateField::ZoneName(Case::Upper), true),
+    ("tz", DateField::ZoneName(Case::Lower), true),
+    ("OF", DateField::ZoneOffset, true),
+    ("HH24", DateField::Hour24, false),
+    ("HH12", DateField::Hour12, false),
+    ("HH", DateField::Hour12, false),
+    ("MI", DateField::Minute, false),
+    ("MS", DateField::Millisecond, false),
+    ("MM", DateField::Month, false),
+    ("SSSSS", DateField::SecondOfDay, false),
+    ("SSSS", DateField::SecondOfDay, false),
+    ("SS", DateField::Second, false),
+    ("US", DateField::Microsecond, false),
+    ("IYYY", DateField::IsoYear, false),
+    ("YYYY", DateField::Year(4), false),
+    ("YYY", DateField::Year(3), false),
+    ("YY", DateField::Year(2), false),
+    ("Y", DateField::Year(1), false),
+    ("DDD", DateField::DayOfYear, false),
+    ("DD", DateField::DayOfMonth, false),
+    ("D", DateField::DayOfWeek, false),
+    ("ID", DateField::IsoDayOfWeek, false),
+    ("IW", DateField::IsoWeek, false),
+    ("WW", DateField::WeekOfYear, false),
+    ("W", DateField::WeekOfMonth, false),
+    ("CC", DateField::Century, false),
+    ("Q", DateField::Quarter, false),
+    ("J", DateField::Julian, false),
+];
+
+impl DateField {
+    /// Digits of numeric fields, to which they are padded, `None` for the
+    /// unpadded ones
+    fn width(self) -> Option<usize> {
+        match self {
+            DateField::Hour12
+            | DateField::Hour24
+            | DateField::Minute
+            | DateField::Second
+            | DateField::Month
+            | DateField::DayOfMonth
+            | DateField::WeekOfYear
+            | DateField::IsoWeek
+            | DateField::Century => Some(2),
+            DateField::Millisecond | DateField::DayOfYear => Some(3),
+            DateField::Microsecond => Some(6),
+            DateField::Year(digits) => Some(digits as usize),
+            DateField::IsoYear => Some(4),
+            DateField::DayOfWeek
+            | DateField::IsoDayOfWeek
+            | DateField::WeekOfMonth
+            | DateField::Quarter => Some(1),
+            _ => None,
+        }
+    }
+
+    fn is_numeric(self) -> bool {
+        self.width().is_some() || matches!(self, DateField::SecondOfDay | DateField::Julian)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum DateItem {
+    Field {
+        field: DateField,
+        pattern: &'static str,
+        /// Padded, unless prefixed by `FM`
+        fill: bool,
+        /// The case of the `TH` suffix
+        ordinal: Option<Case>,
+    },
+    Text(String),
+}
+
+fn starts_with_ignore_case(text: &str, prefix: &str) -> bool {
+    text.get(..prefix.len())
+        .is_some_and(|start| start.eq_ignore_ascii_case(prefix))
+}
+
+/// The text of the double-quoted string starting `template`, where a
+/// backslash escapes the next character, and the rest of the template
+fn quoted(template: &str) -> (String, &str) {
+    let mut text = String::new();
+    let mut chars = template.char_indices().skip(1);
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return (text, &template[i + 1..]),
+            '\\' => {
+                if let Some((_, c)) = chars.next() {
+                    text.push(c);
+                }
+            }
+            c => text.push(c),
+        }
+    }
+    (text, "")
+}
+
+fn parse_date_template(template: &str) -> Vec<DateItem> {
+    let mut items = Vec::new();
+    let mut text = String::new();
+    let mut fill = true;
+    let mut rest = template;
+    while let Some(c) = rest.chars().next() {
+        if c == '"' {
+            let (quoted, after) = quoted(rest);
+            text.push_str(&quoted);
+            rest = after;
+            continue;
+        }
+        if starts_with_ignore_case(rest, "FM") {
+            fill = false;
+            rest = &rest[2..];
+            continue;
+        }
+        if starts_with_ignore_case(rest, "FX") {
+            rest = &rest[2..];
+            continue;
+        }
+        let matched = DATE_PATTERNS.iter().find(|(pattern, _, case_sensitive)| {
+            if *case_sensitive {
+                rest.starts_with(pattern)
+            } else {
+                starts_with_ignore_case(rest, pattern)
+            }
+        });
+        if let Some((pattern, field, _)) = matched {
+            rest = &rest[pattern.len()..];
+            let ordinal = if !field.is_numeric() {
+                None
+            } else if rest.starts_with("TH") {
+                Some(Case::Upper)
+            } else if rest.starts_with("th") {
+                Some(Case::Lower)
+            } else {
+                None
+            };
+            if ordinal.is_some() {
+                rest = &rest[2..];
+            }
+            if !text.is_empty() {
+                items.push(DateItem::Text(std::mem::take(&mut text)));
+            }
+            items.push(DateItem::Field {
+                field: *field,
+                pattern,
+                fill,
+                ordinal,
+            });
+            fill = true;
+            continue;
+        }
+        rest = &rest[c.len_utf8()..];
+        if c == '\\' {
+            if let Some(escaped) = rest.chars().next() {
+                text.push(escaped);
+                rest = &rest[escaped.len_utf8()..];
+            }
+        } else {
+            text.push(c);
+        }
+    }
+    if !text.is_empty() {
+        items.push(DateItem::Text(text));
+    }
+    items
+}
+
+fn ordinal_suffix(value: i64) -> &'static str {
+    match (value % 100, value % 10) {
+        (11..=13, _) => "th",
+        (_, 1) => "st",
+        (_, 2) => "nd",
+        (_, 3) => "rd",
+        _ => "th",
+    }
+}
+
+/// `+02` for whole hours, else `+05:30`
+fn format_offset(seconds: i32) -> String {
+    let sign = if seconds < 0 { '-' } else { '+' };
+    let minutes = seconds.abs() / 60;
+    match minutes % 60 {
+        0 => format!("{sign}{:02}", minutes / 60),
+        rest => format!("{sign}{:02}:{rest:02}", minutes / 60),
+    }
+}
+
+/// The seconds east of UTC of the `+HH[[:]MM]` starting `text`, and its
+/// length
+fn take_offset(text: &str) -> Option<(i32, usize)> {
+    let sign = match text.chars().next()? {
+        '+' => 1,
+        '-' => -1,
+        _ => return None,
+    };
+    let digits = |text: &str, max: usize| {
+        let len = text
+            .bytes()
+            .take(max)
+            .take_while(u8::is_ascii_digit)
+            .count();
+        (len > 0).then(|| (text[..len].parse::<i32>().unwrap_or(0), len))
+    };
+    let (hours, hours_len) = digits(&text[1..], 2)?;
+    let mut len = 1 + hours_len;
+    let mut minutes = 0;
+    let after_colon = text[len..].strip_prefix(':');
+    if let Some((value, minutes_len)) = digits(after_colon.unwrap_or(&text[len..]), 2) {
+        minutes = value;
+        len += minutes_len + usize::from(after_colon.is_some());
+    }
+    Some((sign * (hours * 3600 + minutes * 60), len))
+}
+
+/// The time zone of a timestamp type
+pub(super) enum Zone {
+    Fixed(i32),
+    Named(chrono_tz::Tz),
+}
+
+impl Zone {
+    pub(super) fn parse(tz: &str) -> Result<Self> {
+        if let Some((seconds, len)) = take_offset(tz) {
+            if len == tz.len() {
+                return Ok(Zone::Fixed(seconds));
+            }
+        }
+        match chrono_tz::Tz::from_str(tz) {
+            Ok(tz) => Ok(Zone::Named(tz)),
+            Err(_) => exec_err!("Invalid time zone {tz}"),
+        }
+    }
+
+    pub(super) fn moment(&self, utc: NaiveDateTime) -> Moment {
+        let (offset, abbreviation) = match self {
+            Zone::Fixed(0) => (0, "UTC".to_string()),
+            Zone::Fixed(seconds) => (*seconds, format_offset(*seconds)),
+            Zone::Named(tz) => {
+                let offset = tz.offset_from_utc_datetime(&utc);
+                let seconds = offset.fix().local_minus_utc();
+                let abbreviation = offset
+                    .abbreviation()
+                    .map(str::to_string)
+                    .unwrap_or_else(|| format_offset(seconds));
+                (seconds, abbreviation)
+            }
+        };
+        Moment {
+            local: utc + TimeDelta::seconds(offset.into()),
+            zone: Some((offset, abbreviation)),
+        }
+    }
+
+    /// The UTC time of the local time `local`, the earlier of ambiguous
+    /// local times, and skipped local times in the offset before the skip
+    pub(super) fn utc(&self, local: NaiveDateTime) -> Option<NaiveDateTime> {
+        match self {
+            Zone::Fixed(seconds) => Some(local - TimeDelta::seconds((*seconds).into())),
+            Zone::Named(tz) => tz
+                .from_local_datetime(&local)
+                .earliest()
+                .map(|time| time.naive_utc())
+                .or_else(|| {
+                    let hour = TimeDelta::hours(1);
+                    let before = tz.from_local_datetime(&(local - hour)).earliest()?;
+                    Some(before.naive_utc() + hour)
+                }),
+        }
+    }
+}
+
+/// A local date and time, and the offset and abbreviation of its zone
+pub(super) struct Moment {
+    pub(super) local: NaiveDateTime,
+    pub(super) zone: Option<(i32, String)>,
+}
+
+fn numeric_value(field: DateField, local: &NaiveDateTime) -> Option<i64> {
+    let value = match field {
+        DateField::Hour24 => local.hour() as i64,
+        DateField::Hour12 => ((local.hour() + 11) % 12 + 1) as i64,
+        DateField::Minute => local.minute() as i64,
+        DateField::Second => local.second() as i64,
+        DateField::Millisecond => (local.nanosecond() / 1_000_000) as i64,
+        DateField::Microsecond => (local.nanosecond() / 1_000) as i64,
+        DateField::SecondOfDay => local.num_seconds_from_midnight() as i64,
+        DateField::Year(4) => local.year() as i64,
+        DateField::Year(digits) => local.year() as i64 % 10i64.pow(digits),
+        DateField::IsoYear => local.iso_week().year() as i64,
+        DateField::Century => (local.year() as i64 + 99) / 100,
+        DateField::Quarter => (local.month0() / 3 + 1) as i64,
+        DateField::Month => local.month() as i64,
+        DateField::DayOfYear => local.ordinal() as i64,
+        DateField::DayOfMonth => local.day() as i64,
+        DateField::DayOfWeek => (local.weekday().num_days_from_sunday() + 1) as i64,
+        DateField::IsoDayOfWeek => local.weekday().number_from_monday() as i64,
+        DateField::WeekOfMonth => (local.day0() / 7 + 1) as i64,
+        DateField::WeekOfYear => (local.ordinal0() / 7 + 1) as i64,
+        DateField::IsoWeek => local.iso_week().week() as i64,
+        DateField::Julian => local.num_days_from_ce() as i64 + JULIAN_CE,
+        _ => return None,
+    };
+    Some(value)
+}
+
+fn format_moment(items: &[DateItem], moment: &Moment) -> String {
+    let local = &moment.local;
+    let mut out = String::new();
+    for item in items {
+        let (field, fill, ordinal) = match item {
+            DateItem::Text(text) => {
+                out.push_str(text);
+                continue;
+            }
+            DateItem::Field {
+                field,
+                fill,
+                ordinal,
+                ..
+            } => (*field, *fill, *ordinal),
+        };
+        if let Some(value) = numeric_value(field, local) {
+            let width = if fill { field.width().unwrap_or(0) } else { 0 };
+            out.push_str(&format!("{value:0width$}"));
+            if let Some(case) = ordinal {
+                out.push_str(&case.apply(ordinal_suffix(value)));
+            }
+            continue;
+        }
+        let month = MONTHS[local.month0() as usize];
+        let day = DAYS[local.weekday().num_days_from_sunday() as usize];
+        let text = match field {
+            DateField::MonthName(case) => case.apply(month),
+            DateField::MonthAbbrev(case) => case.apply(&month[..3]),
+            DateField::DayName(case) => case.apply(day),
+            DateField::DayAbbrev(case) => case.apply(&day[..3]),
+            DateField::Meridiem { upper, dots } => {
+                let text = match (local.hour() < 12, dots) {
+                    (true, false) => "AM",
+                    (false, false) => "PM",
+                    (true, true) => "A.M.",
+                    (false, true) => "P.M.",
+                };
+                if upper {
+                    text.to_string()
+                } else {
+                    text.to_lowercase()
+                }
+            }
+            DateField::ZoneName(case) => moment
+                .zone
+                .as_ref()
+                .map(|(_, abbreviation)| case.apply(abbreviation))
+                .unwrap_or_default(),
+            DateField::ZoneOffset => moment
+                .zone
+                .as_ref()
+                .map(|(offset, _)| format_offset(*offset))
+                .unwrap_or_default(),
+            _ => String::new(),
+        };
+        let padded = fill && matches!(field, DateField::MonthName(_) | DateField::DayName(_));
+        if padded {
+            out.push_str(&format!("{text:NAME_WIDTH$}"));
+        } else {
+            out.push_str(&text);
+        }
+    }
+    out
+}
+
+/// The fields read by `to_timestamp` and `to_date`
+#[derive(Debug, Default)]
+struct ParsedFields {
+    year: Option<i32>,
+    month: Option<u32>,
+    day: Option<u32>,
+    day_of_year: Option<u32>,
+    julian: Option<i64>,
+    hour: u32,
+    hour12: bool,
+    pm: Option<bool>,
+    minute: u32,
+    second: u32,
+    nanosecond: u32,
+    offset: Option<i32>,
+}
+
+/// The year of the last `digits` digits of a year nearest to 2020, like
+/// postgres
+fn adjust_year(value: i64, digits: u32) -> i64 {
+    match digits {
+        1 => 2000 + value,
+        2 if value < 70 => 2000 + value,
+        2 => 1900 + value,
+        3 if value < 520 => 2000 + value,
+        3 => 1000 + value,
+        _ => value,
+    }
+}
+
+/// Index of the name of `names` starting `text`, ignoring case, and the
+/// length of the name
+fn take_name(text: &str, names: &[&str], abbreviated: bool) -> Option<(usize, usize)> {
+    names.iter().enumerate().find_map(|(i, name)| {
+        let name = if abbreviated { &name[..3] } else { name };
+        starts_with_ignore_case(text, name).then_some((i, name.len()))
+    })
+}
+
+fn parse_moment(items: &[DateItem], input: &str) -> Result<(NaiveDateTime, Option<i32>)> {
+    let mut fields = ParsedFields::default();
+    let mut rest = input.trim_start();
+    for item in items {
+        let (field, pattern, ordinal) = match item {
+            DateItem::Text(text) => {
+                for c in text.chars() {
+                    if c.is_whitespace() {
+                        rest = rest.trim_start();
+                    } else if let Some(next) = rest.chars().next() {
+                        // separators match any separator, like in postgres
+                        if next == c || !next.is_ascii_digit() {
+                            rest = &rest[next.len_utf8()..];
+                        }
+                    }
+                }
+                continue;
+            }
+            DateItem::Field {
+                field,
+                pattern,
+                ordinal,
+                ..
+            } => (*field, *pattern, *ordinal),
+        };
+        rest = rest.trim_start();
+        let invalid = |rest: &str| {
+            let value: String = rest.chars().take_while(|c| c.is_alphanumeric()).collect();
+            invalid_input(format!("invalid value \"{value}\" for \"{pattern}\""))
+        };
+        if field.is_numeric() {
+            let len = rest
+                .bytes()
+                .take(field.width().unwrap_or(usize::MAX))
+                .take_while(u8::is_ascii_digit)
+                .count();
+            let Ok(value) = rest[..len].parse::<i64>() else {
+                return Err(invalid(rest));
+            };
+            rest = &rest[len..];
+            if ordinal.is_some()
+                && rest
+                    .get(..2)
+                    .is_some_and(|s| s.chars().all(char::is_alphabetic))
+            {
+                rest = &rest[2..];
+            }
+            let fraction =
+                |width: u32, unit: i64| (value * 10i64.pow(width - len as u32) * unit) as u32;
+            match field {
+                DateField::Hour24 => fields.hour = value as u32,
+                DateField::Hour12 => {
+                    fields.hour = value as u32;
+                    fields.hour12 = true;
+                }
+                DateField::Minute => fields.minute = value as u32,
+                DateField::Second => fields.second = value as u32,
+                DateField::Millisecond => fields.nanosecond = fraction(3, 1_000_000),
+                DateField::Microsecond => fields.nanosecond = fraction(6, 1_000),
+                DateField::SecondOfDay => {
+                    fields.hour = (value / 3600) as u32;
+                    fields.minute = (value / 60 % 60) as u32;
+                    fields.second = (value % 60) as u32;
+                }
+                DateField::Year(digits) => {
+                    let year = if len as u32 > digits {
+                        value
+                    } else {
+                        adjust_year(value, digits)
+                    };
+                    fields.year = Some(year as i32);
+                }
+                DateField::IsoYear => fields.year = Some(value as i32),
+                DateField::Month => fields.month = Some(value as u32),
+                DateField::DayOfMonth => fields.day = Some(value as u32),
+                DateField::DayOfYear => fields.day_of_year = Some(value as u32),
+                DateField::Julian => fields.julian = Some(value),
+                // implied by the date
+                _ => {}
+            }
+            continue;
+        }
+        match field {
+            DateField::MonthName(_) | DateField::MonthAbbrev(_) => {
+                let Some((month, len)) =
+                    take_name(rest, &MONTHS, false).or_else(|| take_name(rest, &MONTHS, true))
+                else {
+                    return Err(invalid(rest));
+                };
+                fields.month = Some(month as u32 + 1);
+                rest = &rest[len..];
+            }
+            DateField::DayName(_) | DateField::DayAbbrev(_) => {
+                let Some((_, len)) =
+                    take_name(rest, &DAYS, false).or_else(|| take_name(rest, &DAYS, true))
+                else {
+                    return Err(invalid(rest));
+                };
+                rest = &rest[len..];
+            }
+            DateField::Meridiem { .. } => {
+                let Some((i, len)) = take_name(rest, &["a.m.", "p.m.", "am", "pm"], false) else {
+                    return Err(invalid(rest));
+                };
+                fields.pm = Some(i % 2 == 1);
+                rest = &rest[len..];
+            }
+            DateField::ZoneName(_) | DateField::ZoneOffset => {
+                if let Some((offset, len)) = take_offset(rest) {
+                    fields.offset = Some(offset);
+                    rest = &rest[len..];
+                } else if let Some((_, len)) = take_name(rest, &["UTC", "GMT", "Z"], false) {
+                    fields.offset = Some(0);
+                    rest = &rest[len..];
+                } else {
+                    return Err(invalid(rest));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let out_of_range = || invalid_input(format!("date/time field value out of range: \"{input}\""));
+    let date = match (fields.julian, fields.day_of_year, fields.month) {
+        (Some(julian), _, _) => i32::try_from(julian - JULIAN_CE)
+            .ok()
+            .and_then(NaiveDate::from_num_days_from_ce_opt),
+        (None, Some(day_of_year), None) => {
+            NaiveDate::from_yo_opt(fields.year.unwrap_or(1), day_of_year)
+        }
+        _ => NaiveDate::from_ymd_opt(
+            fields.year.unwrap_or(1),
+            fields.month.unwrap_or(1),
+            fields.day.unwrap_or(1),
+        ),
+    }
+    .ok_or_else(out_of_range)?;
+    let mut hour = fields.hour;
+    if fields.hour12 || fields.pm.is_some() {
+        if !(1..=12).contains(&hour) {
+            return Err(invalid_input(format!(
+                "hour \"{hour}\" is invalid for the 12-hour clock"
+            )));
+        }
+        hour = hour % 12 + if fields.pm == Some(true) { 12 } else { 0 };
+    }
+    let time = NaiveTime::from_hms_nano_opt(hour, fields.minute, fields.second, fields.nanosecond)
+        .ok_or_else(out_of_range)?;
+    Ok((date.and_time(time), fields.offset))
+}
+
+/// Template patterns of numbers
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum NumItem {
+    /// `9`, or `0` for a digit printed even when a leading zero
+    Digit {
+        zero: bool,
+    },
+    Point,
+    Group,
+    /// `MI`
+    Minus,
+    /// `PL`
+    Plus,
+    /// `SG`, or `S` after the digits
+    Sign,
+    /// `PR`, negative values in angle brackets
+    Brackets,
+    /// `EEEE`
+    Exponent,
+    Ordinal(Case),
+    Text(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct NumTemplate {
+    items: Vec<NumItem>,
+    /// Padded, unless prefixed by `FM`
+    fill: bool,
+    /// `S` before the digits, printing the sign of positive values too
+    leading_sign: bool,
+}
+
+fn parse_num_template(template: &str) -> Result<NumTemplate> {
+    let mut items = Vec::new();
+    let mut fill = true;
+    let mut leading_sign = false;
+    let mut rest = template;
+    while let Some(c) = rest.chars().next() {
+        if c == '"' {
+            let (quoted, after) = quoted(rest);
+            items.push(NumItem::Text(quoted));
+            rest = after;
+            continue;
+        }
+        let keyword = [
+            "FM", "EEEE", "TH", "PR", "MI", "PL", "SG", "RN", "S", "D", "G", "L", "V",
+        ]
+        .into_iter()
+        .find(|keyword| starts_with_ignore_case(rest, keyword));
+        let (item, len) = match keyword {
+            Some("FM") => {
+                fill = false;
+                rest = &rest[2..];
+                continue;
+            }
+            Some("S")
+                if !items
+                    .iter()
+                    .any(|item| matches!(item, NumItem::Digit { .. })) =>
+            {
+                leading_sign = true;
+                rest = &rest[1..];
+                continue;
+            }
+            Some(keyword @ ("RN" | "L" | "V")) => {
+                return not_impl_err!("Number format pattern {keyword} is not supported");
+            }
+            Some("EEEE") => (NumItem::Exponent, 4),
+            Some("TH") => {
+                let case = if rest.starts_with('t') {
+                    Case::Lower
+                } else {
+                    Case::Upper
+                };
+                (NumItem::Ordinal(case), 2)
+            }
+            Some("PR") => (NumItem::Brackets, 2),
+            Some("MI") => (NumItem::Minus, 2),
+            Some("PL") => (NumItem::Plus, 2),
+            Some(keyword @ ("SG" | "S")) => (NumItem::Sign, keyword.len()),
+            Some("D") => (NumItem::Point, 1),
+            Some("G") => (NumItem::Group, 1),
+            _ => match c {
+                '9' => (NumItem::Digit { zero: false }, 1),
+                '0' => (NumItem::Digit { zero: true }, 1),
+                '.' => (NumItem::Point, 1),
+                ',' => (NumItem::Group, 1),
+                c => (NumItem::Text(c.to_string()), c.len_utf8()),
+            },
+        };
+        if item == NumItem::Point && items.contains(&NumItem::Point) {
+            return plan_err!("Multiple decimal points in number format {template}");
+        }
+        items.push(item);
+        rest = &rest[len..];
+    }
+    Ok(NumTemplate {
+        items,
+        fill,
+        leading_sign,
+    })
+}
+
+impl NumTemplate {
+    fn int_digits(&self) -> usize {
+        self.items
+            .iter()
+            .take_while(|item| **item != NumItem::Point)
+            .filter(|item| matches!(item, NumItem::Digit { .. }))
+            .count()
+    }
+
+    /// The patterns of the digits after the point, whether each is a `0`
+    fn fraction(&self) -> Vec<bool> {
+        self.items
+            .iter()
+            .skip_while(|item| **item != NumItem::Point)
+            .filter_map(|item| match item {
+                NumItem::Digit { zero } => Some(*zero),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn has_sign(&self) -> bool {
+        self.leading_sign
+            || self.items.iter().any(|item| {
+                matches!(
+                    item,
+                    NumItem::Minus | NumItem::Plus | NumItem::Sign | NumItem::Brackets
+                )
+            })
+    }
+}
+
+/// `value` as `template` prints it, `None` printing the overflow of all
+/// digits as `#`
+fn format_number(template: &NumTemplate, value: Option<Decimal>) -> String {
+    let fraction = template.fraction();
+    let int_digits = template.int_digits();
+    let has_point = template.items.contains(&NumItem::Point);
+    let exponent = template.items.contains(&NumItem::Exponent);
+
+    let round = |value: Decimal| {
+        value.round_dp_with_strategy(
+            fraction.len() as u32,
+            RoundingStrategy::MidpointAwayFromZero,
+        )
+    };
+
+    // the mantissa of EEEE, rounded below 10
+    let (rounded, exp) = match value.map(|value| (value, value.to_f64())) {
+        Some((value, Some(float))) if exponent && !value.is_zero() => {
+            let mantissa = |exp: i32| Decimal::try_from(float / 10f64.powi(exp)).ok().map(round);
+            let exp = float.abs().log10().floor() as i32;
+            match mantissa(exp) {
+                Some(m) if m.abs() >= Decimal::TEN => (mantissa(exp + 1), exp + 1),
+                m => (m, exp),
+            }
+        }
+        _ => (value.map(round), 0),
+    };
+    let negative = rounded.is_some_and(|value| value.is_sign_negative() && !value.is_zero());
+    let digits = rounded
+        .map(|value| format!("{:.*}", fraction.len(), value.abs()))
+        .unwrap_or_default();
+    let (int_part, frac_part) = digits.split_once('.').unwrap_or((&digits, ""));
+    let int_part = if int_part == "0" && has_point {
+        ""
+    } else {
+        int_part
+    };
+    let overflow = rounded.is_none() || int_part.len() > int_digits;
+    let lead = int_digits.saturating_sub(int_part.len());
+    let zero_start = template
+        .items
+        .iter()
+        .take_while(|item| **item != NumItem::Point)
+        .filter(|item| matches!(item, NumItem::Digit { .. }))
+        .position(|item| *item == NumItem::Digit { zero: true })
+        .unwrap_or(usize::MAX);
+    // trailing zeros of `9`s are dropped by FM
+    let frac_printed = if template.fill {
+        fraction.len()
+    } else {
+        let zeros = frac_part.bytes().rev().zip(fraction.iter().rev());
+        fraction.len()
+            - zeros
+                .take_while(|(digit, zero)| *digit == b'0' && !**zero)
+                .count()
+    };
+    let blank = |out: &mut String| {
+        if template.fill {
+            out.push(' ');
+        }
+    };
+
+    let mut out = String::new();
+    // the sign is printed before the first digit or point
+    let started = Cell::new(false);
+    let start = |out: &mut String| {
+        if started.replace(true) {
+            return;
+        }
+        if template.leading_sign {
+            out.push(if negative { '-' } else { '+' });
+        } else if !template.has_sign() {
+            if negative {
+                out.push('-');
+            } else {
+                blank(out);
+            }
+        }
+        if template.items.contains(&NumItem::Brackets) {
+            if negative {
+                out.push('<');
+            } else {
+                blank(out);
+            }
+        }
+    };
+    let (mut int_pos, mut frac_pos, mut after_point) = (0, 0, false);
+    for item in &template.items {
+        match item {
+            NumItem::Digit { .. } if overflow => {
+                start(&mut out);
+                out.push('#');
+            }
+            NumItem::Digit { .. } if after_point => {
+                if frac_pos < frac_printed {
+                    out.push(frac_part.as_bytes()[frac_pos] as char);
+                }
+                frac_pos += 1;
+            }
+            NumItem::Digit { .. } => {
+                if int_pos >= lead {
+                    start(&mut out);
+                    out.push(int_part.as_bytes()[int_pos - lead] as char);
+                } else if int_pos >= zero_start {
+                    start(&mut out);
+                    out.push('0');
+                } else {
+                    blank(&mut out);
+                }
+                int_pos += 1;
+            }
+            NumItem::Point => {
+                start(&mut out);
+                out.push('.');
+                after_point = true;
+            }
+            NumItem::Group => {
+                if started.get() {
+                    out.push(',');
+                } else {
+                    blank(&mut out);
+                }
+            }
+            NumItem::Minus if negative => out.push('-'),
+            NumItem::Plus if !negative => out.push('+'),
+            NumItem::Minus | NumItem::Plus => blank(&mut out),
+            NumItem::Sign => out.push(if negative { '-' } else { '+' }),
+            NumItem::Brackets if negative => out.push('>'),
+            NumItem::Brackets => blank(&mut out),
+            NumItem::Exponent if overflow => out.push_str("####"),
+            NumItem::Exponent => out.push_str(&format!("e{exp:+03}")),
+            NumItem::Ordinal(case) => {
+                let value = int_part.parse::<i64>().unwrap_or(0);
+                out.push_str(&case.apply(ordinal_suffix(value)));
+            }
+            NumItem::Text(text) => out.push_str(text),
+        }
+    }
+    out
+}
+
+/// The first character of `rest` when one of `chars`, consuming it
+fn take_char(rest: &mut &str, chars: &[char]) -> Option<char> {
+    let c = rest.chars().next().filter(|c| chars.contains(c))?;
+    *rest = &rest[c.len_utf8()..];
+    Some(c)
+}
+
+/// The number of `input` read with `template`, `None` without digits
+fn parse_number(template: &NumTemplate, input: &str) -> Option<f64> {
+    let mut rest = input.trim_start();
+    let mut digits = String::new();
+    let mut negative = false;
+    let mut exp = 0;
+    if template.leading_sign || template.items.contains(&NumItem::Brackets) {
+        negative = matches!(take_char(&mut rest, &['-', '+', '<']), Some('-' | '<'));
+    }
+    for item in &template.items {
+        match item {
+            NumItem::Digit { .. } => {
+                if take_char(&mut rest, &['-']).is_some() {
+                    negative = true;
+                }
+                let _ = take_char(&mut rest, &['+', ' ']);
+                if let Some(digit) = rest.chars().next().filter(char::is_ascii_digit) {
+                    digits.push(digit);
+                    rest = &rest[1..];
+                }
+            }
+            NumItem::Point => {
+                if take_char(&mut rest, &['.']).is_some() {
+                    digits.push('.');
+                }
+            }
+            NumItem::Group => {
+                let _ = take_char(&mut rest, &[',']);
+            }
+            NumItem::Minus | NumItem::Plus | NumItem::Sign => {
+                if take_char(&mut rest, &['-', '+', ' ']) == Some('-') {
+                    negative = true;
+                }
+            }
+            NumItem::Brackets => {
+                let _ = take_char(&mut rest, &['>']);
+            }
+            NumItem::Exponent => {
+                if take_char(&mut rest, &['e', 'E']).is_some() {
+                    let len = rest
+                        .char_indices()
+                        .take_while(|(i, c)| c.is_ascii_digit() || (*i == 0 && "+-".contains(*c)))
+                        .count();
+                    exp = rest[..len].parse::<i32>().unwrap_or(0);
+                    rest = &rest[len..];
+                }
+            }
+            NumItem::Ordinal(_) => {
+                let len = rest
+                    .chars()
+                    .take(2)
+                    .take_while(|c| c.is_alphabetic())
+                    .count();
+                rest = &rest[len..];
+            }
+            NumItem::Text(text) => {
+                for _ in text.chars() {
+                    if let Some(c) = rest.chars().next() {
+                        rest = &rest[c.len_utf8()..];
+                    }
+                }
+            }
+        }
+    }
+    if !digits.bytes().any(|digit| digit.is_ascii_digit()) {
+        return None;
+    }
+    let value = digits.parse::<f64>().ok()? * 10f64.powi(exp);
+    Some(if negative { -value } else { value })
+}
+
+/// Templates of `array` as strings
+fn templates(array: &ArrayRef) -> Result<StringArray> {
+    Ok(cast(array, &DataType::Utf8)?.as_string::<i32>().clone())
+}
+
+/// `f` of the values and the templates of each row, parsing each template
+/// only when it differs from the one of the previous row
+fn map_rows<T, P, R>(
+    values: impl Iterator<Item = Option<T>>,
+    templates: &StringArray,
+    parse: impl Fn(&str) -> Result<P>,
+    mut f: impl FnMut(T, &P) -> Result<Option<R>>,
+) -> Result<Vec<Option<R>>> {
+    let mut parsed: Option<(&str, P)> = None;
+    values
+        .zip(templates.iter())
+        .map(|(value, template)| {
+            let (Some(value), Some(template)) = (value, template) else {
+                return Ok(None);
+            };
+            if parsed.as_ref().is_none_or(|(last, _)| *last != template) {
+                parsed = Some((template, parse(template)?));
+            }
+            f(value, &parsed.as_ref().unwrap().1)
+        })
+        .collect()
+}
+
+/// `to_char(timestamp | date | numeric, text)`
+#[derive(Debug)]
+pub(crate) struct ToCharUDF {
+    signature: Signature,
+}
+
+impl ToCharUDF {
+    pub(crate) fn new() -> Self {
+        ToCharUDF {
+            signature: Signature::any(2, Volatility::Immutable),
+        }
+    }
+
+    pub(crate) fn into_scalar_udf(self) -> ScalarUDF {
+        ScalarUDF::new_from_impl(self)
+    }
+}
+
+impl ScalarUDFImpl for ToCharUDF {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn name(&self) -> &str {
+        "to_char"
+    }
+
+    fn signature(&self) -> &Signature {
+        &self.signature
+    }
+
+    fn return_type(&self, arg_types: &[DataType]) -> Result<DataType> {
+        match &arg_types[0] {
+            DataType::Timestamp(..) | DataType::Date32 | DataType::Date64 | DataType::Null => {
+                Ok(DataType::Utf8)
+            }
+            data_type if data_type.is_numeric() => Ok(DataType::Utf8),
+            data_type => plan_err!("Function to_char({data_type}, text) does not exist"),
+        }
+    }
+
+    fn invoke_with_args(&self, args: ScalarFunctionArgs) -> Result<ColumnarValue> {
+        invoke(args, |arrays| {
+            let templates = templates(&arrays[1])?;
+            let strings = match arrays[0].data_type() {
+                DataType::Null => return Ok(new_null_array(&DataType::Utf8, arrays[0].len())),
+                DataType::Timestamp(..) | DataType::Date32 | DataType::Date64 => {
+                    let tz = match arrays[0].data_type() {
+                        DataType::Timestamp(_, tz) => tz.clone(),
+                        _ => None,
+                    };
+                    let zone = tz.as_deref().map(Zone::parse).transpose()?;
+                    let micros = cast(&arrays[0], &DataType::Timestamp(TimeUnit::Microsecond, tz))?;
+                    let micros = micros.as_primitive::<TimestampMicrosecondType>();
+                    map_rows(
+                        micros.iter(),
+                        &templates,
+                        |template| Ok(parse_date_template(template)),
+                        |micros, items| {
+                            let Some(utc) = DateTime::from_timestamp_micros(micros) else {
+                                return exec_err!("Timestamp out of range: {micros}");
+                            };
+                            let moment = match &zone {
+                                Some(zone) => zone.moment(utc.naive_utc()),
+                                None => Moment {
+                                    local: utc.naive_utc(),
+                                    zone: None,
+                                },
+                            };
+                            Ok(Some(format_moment(items, &moment)))
+                        },
+                    )?
+                }
+                DataType::Decimal128(_, scale) if *scale >= 0 => {
+                    let scale = *scale as u32;
+                    let decimals = arrays[0].as_primitive::<Decimal128Type>();
+                    map_rows(
+                        decimals.iter(),
+                        &templates,
+                        parse_num_template,
+                        |value, template| {
+                            let value = Decimal::try_from_i128_with_scale(value, scale).ok();
+                            Ok(Some(format_number(template, value)))
+                        },
+                    )?
+                }
+                data_type if data_type.is_integer() => {
+                    let ints = cast(&arrays[0], &DataType::Int64)?;
+                    let ints = ints.as_primitive::<Int64Type>();
+                    map_rows(
+                        ints.iter(),
+                        &templates,
+                        parse_num_template,
+                        |value, template| {
+                            Ok(Some(format_number(template, Some(Decimal::from(value)))))
+                        },
+                    )?
+                }
+                _ => {
+                    let floats = cast(&arrays[0], &DataType::Float64)?;
+                    let floats = floats.as_primitive::<Float64Type>();
+                    map_rows(
+                        floats.iter(),
+                        &templates,
+                        parse_num_template,
+                        |value, template| {
+                            let value = Decimal::try_from(value).ok();
+                            Ok(Some(format_number(template, value)))
+                        },
+                    )?
+                }
+            };
+            Ok(Arc::new(StringArray::from(strings)))
+        })
+    }
+}
+
+/// `to_timestamp(text, text)` and `to_date(text, text)`, keeping the
+/// DataFusion builtin of the same name for other arities
+#[derive(Debug)]
+pub(crate) struct ParseDateUDF {
+    signature: Signature,
+    builtin: Arc<ScalarUDF>,
+    is_date: bool,
+}
+
+impl ParseDateUDF {
+    pub(crate) fn new(is_date: bool) -> Self {
+        let builtin = if is_date {
+            datafusion::functions::datetime::to_date()
+        } else {
+            datafusion::functions::datetime::to_timestamp()
+        };
+        ParseDateUDF {
+            signature: Signature::variadic_any(Volatility::Immutable),
+            builtin,
+            is_date,
+        }
+    }
+
+    pub(crate) fn into_scalar_udf(self) -> ScalarUDF {
+        ScalarUDF::new_from_impl(self)
+    }
+}
+
+impl ScalarUDFImpl for ParseDateUDF {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn name(&self) -> &str {
+        self.builtin.name()
+    }
+
+    fn signature(&self) -> &Signature {
+        &self.signature
+    }
+
+    fn return_type(&self, arg_types: &[DataType]) -> Result<DataType> {
+        match (arg_types.len(), self.is_date) {
+            (2, true) => Ok(DataType::Date32),
+            (2, false) => Ok(DataType::Timestamp(
+                TimeUnit::Nanosecond,
+                Some(Arc::from(UTC)),
+            )),
+            _ => self.builtin.return_type(arg_types),
+        }
+    }
+
+    fn invoke_with_args(&self, args: ScalarFunctionArgs) -> Result<ColumnarValue> {
+        if args.args.len() != 2 {
+            return self.builtin.invoke_with_args(args);
+        }
+        invoke(args, |arrays| {
+            let inputs = templates(&arrays[0])?;
+            let templates = templates(&arrays[1])?;
+            let moments = map_rows(
+                inputs.iter(),
+                &templates,
+                |template| Ok(parse_date_template(template)),
+                |input, items| parse_moment(items, input).map(Some),
+            )?;
+            if self.is_date {
+                let epoch = NaiveDate::default();
+                let days = moments.into_iter().map(|moment| {
+                    moment.map(|(local, _)| (local.date() - epoch).num_days() as i32)
+                });
+                return Ok(Arc::new(days.collect::<Date32Array>()));
+            }
+            let nanos = moments
+                .into_iter()
+                .map(|moment| {
+                    let Some((local, offset)) = moment else {
+                        return Ok(None);
+                    };
+                    let utc = local - TimeDelta::seconds(offset.unwrap_or(0).into());
+                    match utc.and_utc().timestamp_nanos_opt() {
+                        Some(nanos) => Ok(Some(nanos)),
+                        None => Err(invalid_input(format!("timestamp out of range: \"{utc}\""))),
+                    }
+                })
+                .collect::<Result<TimestampNanosecondArray>>()?;
+            Ok(Arc::new(nanos.with_timezone(UTC)))
+        })
+    }
+}
+
+/// `to_number(text, text)`
+#[derive(Debug)]
+pub(crate) struct ToNumberUDF {
+    signature: Signature,
+}
+
+impl ToNumberUDF {
+    pub(crate) fn new() -> Self {
+        ToNumberUDF {
+            signature: Signature::any(2, Volatility::Immutable),
+        }
+    }
+
+    pub(crate) fn into_scalar_udf(self) -> ScalarUDF {
+        ScalarUDF::new_from_impl(self)
+    }
+}
+
+impl ScalarUDFImpl for ToNumberUDF {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn name(&self) -> &str {
+        "to_number"
+    }
+
+    fn signature(&self) -> &Signature {
+        &self.signature
+    }
+
+    fn return_type(&self, _arg_types: &[DataType]) -> Result<DataType> {
+        Ok(DataType::Float64)
+    }
+
+    fn invoke_with_args(&self, args: ScalarFunctionArgs) -> Result<ColumnarValue> {
+        invoke(args, |arrays| {
+            let inputs = templates(&arrays[0])?;
+            let templates = templates(&arrays[1])?;
+            let numbers = map_rows(
+                inputs.iter(),
+                &templates,
+                parse_num_template,
+                |input, template| match parse_number(template, input) {
+                    Some(number) => Ok(Some(number)),
+                    None => Err(invalid_input(format!(
+                        "invalid input syntax for type numeric: \"{input}\""
+                    ))),
+                },
+            )?;
+            Ok(Arc::new(Float64Array::from(numbers)))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use datafusion::arrow::util::display::{ArrayFormatter, FormatOptions};
+    use datafusion::prelude::SessionContext;
+
+    use super::*;
+    use crate::pg_catalog::setup_pg_catalog;
+
+    fn format_date(datetime: &str, template: &str) -> String {
+        let utc = NaiveDateTime::parse_from_str(datetime, "%Y-%m-%d %H:%M:%S%.f").unwrap();
+        let moment = Zone::parse("+00:00").unwrap().moment(utc);
+        format_moment(&parse_date_template(template), &moment)
+    }
+
+    fn format(value: &str, template: &str) -> String {
+        let template = parse_num_template(template).unwrap();
+        format_number(&template, Some(Decimal::from_str(value).unwrap()))
+    }
+
+    #[test]
+    fn test_dates() {
+        let at = "2024-01-05 20:07:09.123456";
+        for (template, expected) in [
+            ("YYYY-MM-DD HH24:MI:SS.MS", "2024-01-05 20:07:09.123"),
+            ("FMMonth YYYY", "January 2024"),
+            ("Month", "January  "),
+            ("MON Mon mon", "JAN Jan jan"),
+            ("Day, FMDD", "Friday   , 5"),
+            ("FMDDth \"of\" FMMonth", "5th of January"),
+            ("HH12:MI AM, hh12 p.m.", "08:07 PM, 08 p.m."),
+            ("YY-Q-WW-IW-ID-D-DDD", "24-1-01-01-5-6-005"),
+            ("US SSSS J CC", "123456 72429 2460315 21"),
+            (
+                "YYYY-MM-DD\"T\"HH24:MI:SSOF TZ",
+                "2024-01-05T20:07:09+00 UTC",
+            ),
+        ] {
+            assert_eq!(format_date(at, template), expected, "{template}");
+        }
+        assert_eq!(
+            format_date("2024-03-22 00:00:00", "FMDDth DDTH"),
+            "22nd 22ND"
+        );
+
+        let utc =
+            NaiveDateTime::parse_from_str("2024-07-01 12:00:00", "%Y-%m-%d %H:%M:%S").unwrap();
+        let items = parse_date_template("HH24:MI TZ OF");
+        let paris = Zone::parse("Europe/Paris").unwrap().moment(utc);
+        assert_eq!(format_moment(&items, &paris), "14:00 CEST +02");
+        let india = Zone::parse("+05:30").unwrap().moment(utc);
+        assert_eq!(format_moment(&items, &india), "17:30 +05:30 +05:30");
+
+        let parse = |input: &str, template: &str| {
+            parse_moment(&parse_date_template(template), input)
+                .map(|(local, offset)| (local.to_string(), offset))
+        };
+        for (input, template, expected) in [
+            ("2024-01-05", "YYYY-MM-DD", "2024-01-05 00:00:00"),
+            (
+                "05 Jan 2024 08:30 PM",
+                "DD Mon YYYY HH12:MI AM",
+                "2024-01-05 20:30:00",
+            ),
+            ("January 5, 2024", "Month DD, YYYY", "2024-01-05 00:00:00"),
+            (
+                "20240105 12:00:00.25",
+                "YYYYMMDD HH24:MI:SS.MS",
+                "2024-01-05 12:00:00.250",
+            ),
+            ("2024/1/5", "YYYY-MM-DD", "2024-01-05 00:00:00"),
+            ("5th of Jan 24", "DDth \"of\" Mon YY", "2024-01-05 00:00:00"),
+            ("2024-005", "YYYY-DDD", "2024-01-05 00:00:00"),
+            ("2460315", "J", "2024-01-05 00:00:00"),
+        ] {
+            assert_eq!(
+                parse(input, template).unwrap(),
+                (expected.to_string(), None),
+                "{input}"
+            );
+        }
+        assert_eq!(
+            parse("2024-01-05 10:00 -05", "YYYY-MM-DD HH24:MI OF").unwrap(),
+            ("2024-01-05 10:00:00".to_string(), Some(-5 * 3600))
+        );
+        assert!(parse("2024-13-05", "YYYY-MM-DD").is_err());
+        assert!(parse("2024-xx-05", "YYYY-MM-DD").is_err());
+        assert!(parse("13:00", "HH12:MI").is_err());
+    }
+
+    #[test]
+    fn test_numbers() {
+        for (value, template, expected) in [
+            ("5", "99", "  5"),
+            ("-5", "99", " -5"),
+            ("0", "999", "   0"),
+            ("0.5", "9.99", "  .50"),
+            ("0.5", "0.99", " 0.50"),
+            ("-0.1", "99.99", "  -.10"),
+            ("1.5", "FM9.99", "1.5"),
+            ("5", "FM9.99", "5."),
+            ("5", "0999", " 0005"),
+            ("1234567.891", "9,999,999.99", " 1,234,567.89"),
+            ("1234.5", "FM999,990.00", "1,234.50"),
+            ("12345", "999", " ###"),
+            ("-12", "S999", " -12"),
+            ("12", "999S", " 12+"),
+            ("-12", "999MI", " 12-"),
+            ("-485", "999PR", "<485>"),
+            ("485", "999PR", " 485 "),
+            ("12", "FM999PR", "12"),
+            ("12345", "9.99EEEE", " 1.23e+04"),
+            ("21", "FM99th", "21st"),
+            ("12.5", "\"$\"FM990.00", "$12.50"),
+        ] {
+            assert_eq!(format(value, template), expected, "{value} {template}");
+        }
+        assert!(parse_num_template("9.9.9").is_err());
+        assert!(parse_num_template("RN").is_err());
+
+        let parse = |input: &str, template: &str| {
+            parse_number(&parse_num_template(template).unwrap(), input)
+        };
+        assert_eq!(parse("12,454.8-", "99G999D9S"), Some(-12454.8));
+        assert_eq!(parse(" 1,234.50", "9,999.99"), Some(1234.5));
+        assert_eq!(parse("-42", "999"), Some(-42.0));
+        assert_eq!(parse("<42>", "999PR"), Some(-42.0));
+        assert_eq!(parse("12345", "99"), Some(12.0));
+        assert_eq!(parse("abc", "999"), None);
+    }
+
+    /// The first value of the result of `sql`
+    async fn value(ctx: &SessionContext, sql: &str) -> Result<String> {
+        let batches = ctx.sql(sql).await?.collect().await?;
+        let formatter = ArrayFormatter::try_new(batches[0].column(0), &FormatOptions::default())?;
+        let value = formatter.value(0).to_string();
+        Ok(value)
+    }
+
+    #[tokio::test]
+    async fn test_formatting_functions() {
+        let ctx = SessionContext::new();
+        setup_pg_catalog(&ctx, "datafusion").unwrap();
+
+        for (sql, expected) in [
+            (
+                "SELECT to_char(TIMESTAMP '2024-01-05 20:07:09', 'FMMonth YYYY, HH12:MI AM')",
+                "January 2024, 08:07 PM",
+            ),
+            (
+                "SELECT to_char(DATE '2024-01-05', 'Dy DD Mon')",
+                "Fri 05 Jan",
+            ),
+            (
+                "SELECT to_char(CAST(1234.5 AS DECIMAL(10, 2)), 'FM999,990.00')",
+                "1,234.50",
+            ),
+            ("SELECT to_char(42, '999')", "  42"),
+            ("SELECT to_char(2.5::double, '0.0')", " 2.5"),
+            (
+                "SELECT to_timestamp('05 Jan 2024 08:30 PM', 'DD Mon YYYY HH12:MI AM')",
+                "2024-01-05T20:30:00Z",
+            ),
+            ("SELECT to_date('2024/01/05', 'YYYY/MM/DD')", "2024-01-05"),
+            ("SELECT to_number('12,454.8-', '99G999D9S')", "-12454.8"),
+            ("SELECT to_timestamp(0)", "1970-01-01T00:00:00"),
+            ("SELECT to_date('2024-01-05')", "2024-01-05"),
+        ] {
+            assert_eq!(value(&ctx, sql).await.unwrap(), expected, "{sql}");
+        }
+        let error = value(&ctx, "SELECT to_date('2024-13-05', 'YYYY-MM-DD')")
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("out of range"), "{error}");
+    }
+}
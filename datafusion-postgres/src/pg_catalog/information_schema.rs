@@ -0,0 +1,190 @@
+//! The `information_schema` of postgres.
+//!
+//! ORMs and BI tools introspect tables through `information_schema`, reading
+//! columns DataFusion's own views lack. The views of postgres are generated
+//! from the same data as `pg_catalog` and the functions of the session, the
+//! others are served by DataFusion. Like `pg_catalog`, the views of postgres
+//! list only the objects the session user may access.
+
+use std::any::Any;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use datafusion::catalog::information_schema::InformationSchemaProvider;
+use datafusion::catalog::streaming::StreamingTable;
+use datafusion::catalog::{CatalogProviderList, SchemaProvider};
+use datafusion::datasource::TableProvider;
+use datafusion::error::Result;
+use datafusion::physical_plan::streaming::PartitionStream;
+
+use super::key_filter::KeyFilteredTableProvider;
+use super::{CatalogVisibility, OidRegistry};
+use crate::tenant::TenantCatalogList;
+
+mod columns;
+mod key_column_usage;
+mod parameters;
+mod referential_constraints;
+mod routines;
+mod schemata;
+pub(super) mod table_constraints;
+mod tables;
+mod views;
+
+pub(crate) use tables::table_type;
+
+pub(crate) const INFORMATION_SCHEMA: &str = "information_schema";
+
+const INFORMATION_SCHEMA_COLUMNS: &str = "columns";
+const INFORMATION_SCHEMA_TABLES: &str = "tables";
+const INFORMATION_SCHEMA_VIEWS: &str = "views";
+const INFORMATION_SCHEMA_SCHEMATA: &str = "schemata";
+const INFORMATION_SCHEMA_ROUTINES: &str = "routines";
+const INFORMATION_SCHEMA_PARAMETERS: &str = "parameters";
+const INFORMATION_SCHEMA_TABLE_CONSTRAINTS: &str = "table_constraints";
+const INFORMATION_SCHEMA_KEY_COLUMN_USAGE: &str = "key_column_usage";
+const INFORMATION_SCHEMA_REFERENTIAL_CONSTRAINTS: &str = "referential_constraints";
+
+/// The views served in addition to the ones of DataFusion
+const POSTGRES_ONLY_TABLES: &[&str] = &[
+    INFORMATION_SCHEMA_TABLE_CONSTRAINTS,
+    INFORMATION_SCHEMA_KEY_COLUMN_USAGE,
+    INFORMATION_SCHEMA_REFERENTIAL_CONSTRAINTS,
+];
+
+/// `information_schema` of a catalog, describing the objects of that
+/// catalog only like the `information_schema` of a postgres database
+///
+/// DataFusion serves its own `information_schema` instead when
+/// `datafusion.catalog.information_schema` is enabled, so `setup_pg_catalog`
+/// disables it.
+#[derive(Debug)]
+pub(crate) struct PgInformationSchemaProvider {
+    catalog_name: String,
+    catalog_list: Arc<dyn CatalogProviderList>,
+    oid_registry: Arc<OidRegistry>,
+    datafusion: InformationSchemaProvider,
+}
+
+impl PgInformationSchemaProvider {
+    pub(crate) fn new(
+        catalog_name: &str,
+        catalog_list: Arc<dyn CatalogProviderList>,
+        oid_registry: Arc<OidRegistry>,
+    ) -> Self {
+        PgInformationSchemaProvider {
+            catalog_name: catalog_name.to_string(),
+            datafusion: InformationSchemaProvider::new(catalog_list.clone()),
+            catalog_list,
+            oid_registry,
+        }
+    }
+}
+
+#[async_trait]
+impl SchemaProvider for PgInformationSchemaProvider {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn table_names(&self) -> Vec<String> {
+        let mut names = self.datafusion.table_names();
+        names.extend(POSTGRES_ONLY_TABLES.iter().map(|name| name.to_string()));
+        names
+    }
+
+    async fn table(&self, name: &str) -> Result<Option<Arc<dyn TableProvider>>> {
+        match name.to_ascii_lowercase().as_str() {
+            INFORMATION_SCHEMA_COLUMNS => {
+                let table = columns::ColumnsTable::new(
+                    self.catalog_name.clone(),
+                    self.catalog_list.clone(),
+                    self.oid_registry.clone(),
+                );
+                Ok(Some(Arc::new(KeyFilteredTableProvider::new(table))))
+            }
+            INFORMATION_SCHEMA_TABLES => {
+                let table = tables::TablesTable::new(
+                    self.catalog_name.clone(),
+                    self.catalog_list.clone(),
+                    self.oid_registry.clone(),
+                );
+                Ok(Some(Arc::new(KeyFilteredTableProvider::new(table))))
+            }
+            INFORMATION_SCHEMA_VIEWS => {
+                let table = views::ViewsTable::new(
+                    self.catalog_name.clone(),
+                    self.catalog_list.clone(),
+                    self.oid_registry.clone(),
+                );
+                Ok(Some(Arc::new(KeyFilteredTableProvider::new(table))))
+            }
+            INFORMATION_SCHEMA_SCHEMATA => {
+                let table = Arc::new(schemata::SchemataTable::new(
+                    self.catalog_name.clone(),
+                    self.catalog_list.clone(),
+                ));
+                Ok(Some(Arc::new(StreamingTable::try_new(
+                    Arc::clone(table.schema()),
+                    vec![table],
+                )?)))
+            }
+            INFORMATION_SCHEMA_ROUTINES => {
+                let table = Arc::new(routines::RoutinesTable::new(self.catalog_name.clone()));
+                Ok(Some(Arc::new(StreamingTable::try_new(
+                    Arc::clone(table.schema()),
+                    vec![table],
+                )?)))
+            }
+            INFORMATION_SCHEMA_PARAMETERS => {
+                let table = Arc::new(parameters::ParametersTable::new(self.catalog_name.clone()));
+                Ok(Some(Arc::new(StreamingTable::try_new(
+                    Arc::clone(table.schema()),
+                    vec![table],
+                )?)))
+            }
+            INFORMATION_SCHEMA_TABLE_CONSTRAINTS => {
+                let table = table_constraints::TableConstraintsTable::new(
+                    self.catalog_name.clone(),
+                    self.catalog_list.clone(),
+                    self.oid_registry.clone(),
+                );
+                Ok(Some(Arc::new(KeyFilteredTableProvider::new(table))))
+            }
+            INFORMATION_SCHEMA_KEY_COLUMN_USAGE => {
+                let table = key_column_usage::KeyColumnUsageTable::new(
+                    self.catalog_name.clone(),
+                    self.catalog_list.clone(),
+                    self.oid_registry.clone(),
+                );
+                Ok(Some(Arc::new(KeyFilteredTableProvider::new(table))))
+            }
+            INFORMATION_SCHEMA_REFERENTIAL_CONSTRAINTS => Ok(Some(Arc::new(
+                referential_constraints::referential_constraints()?,
+            ))),
+            _ => self.datafusion.table(name).await,
+        }
+    }
+
+    fn table_exist(&self, name: &str) -> bool {
+        POSTGRES_ONLY_TABLES.contains(&name.to_ascii_lowercase().as_str())
+            || self.datafusion.table_exist(name)
+    }
+}
+
+/// The catalog of `catalog_name`, if visible, as postgres only describes the
+/// objects of the current database
+fn current_catalog(
+    catalog_name: &str,
+    catalog_list: &Arc<dyn CatalogProviderList>,
+    visibility: Option<&CatalogVisibility>,
+) -> TenantCatalogList {
+    let visible = visibility.is_none_or(|visibility| visibility.is_catalog_visible(catalog_name));
+    TenantCatalogList::new(
+        catalog_list.clone(),
+        visible
+            .then(|| catalog_name.to_string())
+            .into_iter()
+            .collect(),
+    )
+}
@@ -0,0 +1,308 @@
+use std::sync::Arc;
+
+use datafusion::arrow::array::{ArrayRef, Int32Array, RecordBatch, StringArray};
+use datafusion::arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use datafusion::catalog::CatalogProviderList;
+use datafusion::error::Result;
+use datafusion::execution::{SendableRecordBatchStream, TaskContext};
+use datafusion::physical_plan::stream::RecordBatchStreamAdapter;
+use datafusion::physical_plan::streaming::PartitionStream;
+
+use crate::pg_catalog::key_filter::{KeyColumns, KeyFilter, KeyFilteredTable};
+use crate::pg_catalog::pg_attribute::PgAttributeTable;
+use crate::pg_catalog::{CatalogVisibility, OidRegistry};
+
+use super::current_catalog;
+
+/// `information_schema.columns`, generated from the same relations as
+/// `pg_attribute`
+#[derive(Debug, Clone)]
+pub(crate) struct ColumnsTable {
+    schema: SchemaRef,
+    catalog_name: String,
+    catalog_list: Arc<dyn CatalogProviderList>,
+    oid_registry: Arc<OidRegistry>,
+    key_filter: KeyFilter,
+}
+
+/// A column, described as by postgres
+struct ColumnRow {
+    table_schema: String,
+    table_name: String,
+    column_name: String,
+    ordinal_position: i32,
+    is_nullable: bool,
+    is_updatable: bool,
+    data_type: &'static str,
+    udt_name: &'static str,
+    character_octet_length: Option<i32>,
+    numeric_precision: Option<i32>,
+    numeric_precision_radix: Option<i32>,
+    numeric_scale: Option<i32>,
+    datetime_precision: Option<i32>,
+}
+
+impl ColumnsTable {
+    pub(crate) fn new(
+        catalog_name: String,
+        catalog_list: Arc<dyn CatalogProviderList>,
+        oid_registry: Arc<OidRegistry>,
+    ) -> Self {
+        // the columns of postgres, `cardinal_number` columns as integers and
+        // `sql_identifier`, `character_data` and `yes_or_no` columns as text
+        let text = |name: &str| Field::new(name, DataType::Utf8, true);
+        let number = |name: &str| Field::new(name, DataType::Int32, true);
+        let schema = Arc::new(Schema::new(vec![
+            text("table_catalog"),
+            text("table_schema"),
+            text("table_name"),
+            text("column_name"),
+            number("ordinal_position"),
+            text("column_default"),
+            text("is_nullable"),
+            text("data_type"),
+            number("character_maximum_length"),
+            number("character_octet_length"),
+            number("numeric_precision"),
+            number("numeric_precision_radix"),
+            number("numeric_scale"),
+            number("datetime_precision"),
+            text("interval_type"),
+            number("interval_precision"),
+            text("character_set_catalog"),
+            text("character_set_schema"),
+            text("character_set_name"),
+            text("collation_catalog"),
+            text("collation_schema"),
+            text("collation_name"),
+            text("domain_catalog"),
+            text("domain_schema"),
+            text("domain_name"),
+            text("udt_catalog"),
+            text("udt_schema"),
+            text("udt_name"),
+            text("scope_catalog"),
+            text("scope_schema"),
+            text("scope_name"),
+            number("maximum_cardinality"),
+            text("dtd_identifier"),
+            text("is_self_referencing"),
+            text("is_identity"),
+            text("identity_generation"),
+            text("identity_start"),
+            text("identity_increment"),
+            text("identity_maximum"),
+            text("identity_minimum"),
+            text("identity_cycle"),
+            text("is_generated"),
+            text("generation_expression"),
+            text("is_updatable"),
+        ]));
+
+        Self {
+            schema,
+            catalog_name,
+            catalog_list,
+            oid_registry,
+            key_filter: KeyFilter::default(),
+        }
+    }
+
+    /// Generate record batches based on the current state of the catalog
+    async fn get_data(
+        this: Self,
+        visibility: Option<Arc<CatalogVisibility>>,
+    ) -> Result<RecordBatch> {
+        let mut rows = Vec::new();
+
+        let catalog_list = current_catalog(
+            &this.catalog_name,
+            &this.catalog_list,
+            visibility.as_deref(),
+        );
+        for table in this.key_filter.tables(&catalog_list, &this.oid_registry) {
+            if let Some(visibility) = &visibility {
+                if !visibility
+                    .is_table_visible(&table.schema_name, &table.table_name)
+                    .await
+                {
+                    continue;
+                }
+            }
+
+            let Some(relation) = this.oid_registry.relation(&table).await? else {
+                continue;
+            };
+            for (column_idx, field) in relation.schema.fields().iter().enumerate() {
+                let (type_oid, ..) = PgAttributeTable::datafusion_to_pg_type(field.data_type());
+                let (data_type, udt_name) = type_names(type_oid);
+                let (numeric_precision, numeric_precision_radix, numeric_scale) =
+                    numeric_precision(field.data_type());
+                rows.push(ColumnRow {
+                    table_schema: table.schema_name.clone(),
+                    table_name: table.table_name.clone(),
+                    column_name: field.name().clone(),
+                    ordinal_position: column_idx as i32 + 1,
+                    is_nullable: field.is_nullable(),
+                    is_updatable: relation.relkind == "r",
+                    data_type,
+                    udt_name,
+                    // the maximum size of a text value
+                    character_octet_length: (type_oid == 25).then_some(1073741824),
+                    numeric_precision,
+                    numeric_precision_radix,
+                    numeric_scale,
+                    datetime_precision: datetime_precision(field.data_type()),
+                });
+            }
+        }
+
+        let text = |f: fn(&ColumnRow) -> Option<&str>| -> ArrayRef {
+            Arc::new(rows.iter().map(f).collect::<StringArray>())
+        };
+        let number = |f: fn(&ColumnRow) -> Option<i32>| -> ArrayRef {
+            Arc::new(rows.iter().map(f).collect::<Int32Array>())
+        };
+        let yes_or_no = |yes: bool| Some(if yes { "YES" } else { "NO" });
+        let catalog_name = this.catalog_name.as_str();
+        let catalog = Arc::new(StringArray::from(vec![catalog_name; rows.len()])) as ArrayRef;
+        let null_text = || text(|_| None);
+        let null_number = || number(|_| None);
+
+        let arrays: Vec<ArrayRef> = vec![
+            catalog.clone(),
+            text(|row| Some(row.table_schema.as_str())),
+            text(|row| Some(row.table_name.as_str())),
+            text(|row| Some(row.column_name.as_str())),
+            number(|row| Some(row.ordinal_position)),
+            null_text(), // column_default
+            Arc::new(
+                rows.iter()
+                    .map(|row| yes_or_no(row.is_nullable))
+                    .collect::<StringArray>(),
+            ),
+            text(|row| Some(row.data_type)),
+            null_number(), // character_maximum_length
+            number(|row| row.character_octet_length),
+            number(|row| row.numeric_precision),
+            number(|row| row.numeric_precision_radix),
+            number(|row| row.numeric_scale),
+            number(|row| row.datetime_precision),
+            null_text(),   // interval_type
+            null_number(), // interval_precision
+            null_text(),   // character_set_catalog
+            null_text(),   // character_set_schema
+            null_text(),   // character_set_name
+            null_text(),   // collation_catalog
+            null_text(),   // collation_schema
+            null_text(),   // collation_name
+            null_text(),   // domain_catalog
+            null_text(),   // domain_schema
+            null_text(),   // domain_name
+            catalog,       // udt_catalog
+            text(|_| Some("pg_catalog")),
+            text(|row| Some(row.udt_name)),
+            null_text(),   // scope_catalog
+            null_text(),   // scope_schema
+            null_text(),   // scope_name
+            null_number(), // maximum_cardinality
+            Arc::new(
+                rows.iter()
+                    .map(|row| Some(row.ordinal_position.to_string()))
+                    .collect::<StringArray>(),
+            ), // dtd_identifier
+            text(|_| Some("NO")), // is_self_referencing
+            text(|_| Some("NO")), // is_identity
+            null_text(),   // identity_generation
+            null_text(),   // identity_start
+            null_text(),   // identity_increment
+            null_text(),   // identity_maximum
+            null_text(),   // identity_minimum
+            text(|_| Some("NO")), // identity_cycle
+            text(|_| Some("NEVER")), // is_generated
+            null_text(),   // generation_expression
+            Arc::new(
+                rows.iter()
+                    .map(|row| yes_or_no(row.is_updatable))
+                    .collect::<StringArray>(),
+            ),
+        ];
+
+        let batch = RecordBatch::try_new(this.schema.clone(), arrays)?;
+        Ok(batch)
+    }
+}
+
+/// Name of the postgres type of the OID, as in `data_type`, and of its
+/// underlying type, as in `udt_name`
+pub(super) fn type_names(type_oid: i32) -> (&'static str, &'static str) {
+    match type_oid {
+        16 => ("boolean", "bool"),
+        18 => ("\"char\"", "char"),
+        21 => ("smallint", "int2"),
+        23 => ("integer", "int4"),
+        20 => ("bigint", "int8"),
+        700 => ("real", "float4"),
+        701 => ("double precision", "float8"),
+        1700 => ("numeric", "numeric"),
+        17 => ("bytea", "bytea"),
+        1082 => ("date", "date"),
+        1083 => ("time without time zone", "time"),
+        1114 => ("timestamp without time zone", "timestamp"),
+        _ => ("text", "text"),
+    }
+}
+
+/// Precision, its radix and scale of numeric types
+fn numeric_precision(data_type: &DataType) -> (Option<i32>, Option<i32>, Option<i32>) {
+    match data_type {
+        DataType::Int16 | DataType::UInt8 => (Some(16), Some(2), Some(0)),
+        DataType::Int32 | DataType::UInt16 => (Some(32), Some(2), Some(0)),
+        DataType::Int64 | DataType::UInt32 => (Some(64), Some(2), Some(0)),
+        DataType::Float32 => (Some(24), Some(2), None),
+        DataType::Float64 => (Some(53), Some(2), None),
+        DataType::Decimal128(precision, scale) | DataType::Decimal256(precision, scale) => {
+            (Some(*precision as i32), Some(10), Some(*scale as i32))
+        }
+        _ => (None, None, None),
+    }
+}
+
+/// Fractional digits of the seconds of date and time types
+fn datetime_precision(data_type: &DataType) -> Option<i32> {
+    match data_type {
+        DataType::Date32 | DataType::Date64 => Some(0),
+        DataType::Time32(_) | DataType::Time64(_) | DataType::Timestamp(_, _) => Some(6),
+        _ => None,
+    }
+}
+
+impl PartitionStream for ColumnsTable {
+    fn schema(&self) -> &SchemaRef {
+        &self.schema
+    }
+
+    fn execute(&self, ctx: Arc<TaskContext>) -> SendableRecordBatchStream {
+        let this = self.clone();
+        let visibility = CatalogVisibility::from_task_context(&ctx);
+        Box::pin(RecordBatchStreamAdapter::new(
+            this.schema.clone(),
+            futures::stream::once(async move { Self::get_data(this, visibility).await }),
+        ))
+    }
+}
+
+impl KeyFilteredTable for ColumnsTable {
+    const KEY_COLUMNS: KeyColumns = KeyColumns {
+        oid: None,
+        name: Some("table_name"),
+        namespace: None,
+    };
+
+    fn with_key_filter(&self, key_filter: KeyFilter) -> Self {
+        Self {
+            key_filter,
+            ..self.clone()
+        }
+    }
+}
@@ -0,0 +1,147 @@
+use std::sync::Arc;
+
+use datafusion::arrow::array::{ArrayRef, Int32Array, RecordBatch, StringArray};
+use datafusion::arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use datafusion::catalog::CatalogProviderList;
+use datafusion::error::Result;
+use datafusion::execution::{SendableRecordBatchStream, TaskContext};
+use datafusion::physical_plan::stream::RecordBatchStreamAdapter;
+use datafusion::physical_plan::streaming::PartitionStream;
+
+use crate::pg_catalog::key_filter::{KeyColumns, KeyFilter, KeyFilteredTable};
+use crate::pg_catalog::{CatalogVisibility, OidRegistry};
+
+use super::current_catalog;
+use super::table_constraints::table_constraints;
+
+/// `information_schema.key_column_usage`, the columns of the constraints of
+/// `information_schema.table_constraints`
+#[derive(Debug, Clone)]
+pub(crate) struct KeyColumnUsageTable {
+    schema: SchemaRef,
+    catalog_name: String,
+    catalog_list: Arc<dyn CatalogProviderList>,
+    oid_registry: Arc<OidRegistry>,
+    key_filter: KeyFilter,
+}
+
+impl KeyColumnUsageTable {
+    pub(crate) fn new(
+        catalog_name: String,
+        catalog_list: Arc<dyn CatalogProviderList>,
+        oid_registry: Arc<OidRegistry>,
+    ) -> Self {
+        let text = |name: &str| Field::new(name, DataType::Utf8, true);
+        let number = |name: &str| Field::new(name, DataType::Int32, true);
+        let schema = Arc::new(Schema::new(vec![
+            text("constraint_catalog"),
+            text("constraint_schema"),
+            text("constraint_name"),
+            text("table_catalog"),
+            text("table_schema"),
+            text("table_name"),
+            text("column_name"),
+            number("ordinal_position"),
+            number("position_in_unique_constraint"),
+        ]));
+
+        Self {
+            schema,
+            catalog_name,
+            catalog_list,
+            oid_registry,
+            key_filter: KeyFilter::default(),
+        }
+    }
+
+    /// Generate record batches based on the current state of the catalog
+    async fn get_data(
+        this: Self,
+        visibility: Option<Arc<CatalogVisibility>>,
+    ) -> Result<RecordBatch> {
+        let mut table_schemas = Vec::new();
+        let mut constraint_names = Vec::new();
+        let mut table_names = Vec::new();
+        let mut column_names = Vec::new();
+        let mut ordinal_positions = Vec::new();
+
+        let catalog_list = current_catalog(
+            &this.catalog_name,
+            &this.catalog_list,
+            visibility.as_deref(),
+        );
+        for table in this.key_filter.tables(&catalog_list, &this.oid_registry) {
+            if let Some(visibility) = &visibility {
+                if !visibility
+                    .is_table_visible(&table.schema_name, &table.table_name)
+                    .await
+                {
+                    continue;
+                }
+            }
+
+            let Some(relation) = this.oid_registry.relation(&table).await? else {
+                continue;
+            };
+            for constraint in table_constraints(&table.table_name, &relation) {
+                for (idx, column_name) in constraint.columns.into_iter().enumerate() {
+                    table_schemas.push(table.schema_name.clone());
+                    constraint_names.push(constraint.name.clone());
+                    table_names.push(table.table_name.clone());
+                    column_names.push(column_name);
+                    ordinal_positions.push(idx as i32 + 1);
+                }
+            }
+        }
+
+        let rows = table_names.len();
+        let catalog =
+            Arc::new(StringArray::from(vec![this.catalog_name.as_str(); rows])) as ArrayRef;
+        let table_schemas = Arc::new(StringArray::from(table_schemas)) as ArrayRef;
+        let arrays: Vec<ArrayRef> = vec![
+            catalog.clone(),
+            table_schemas.clone(),
+            Arc::new(StringArray::from(constraint_names)),
+            catalog,
+            table_schemas,
+            Arc::new(StringArray::from(table_names)),
+            Arc::new(StringArray::from(column_names)),
+            Arc::new(Int32Array::from(ordinal_positions)),
+            // only set for foreign keys, which DataFusion does not keep
+            Arc::new(Int32Array::new_null(rows)),
+        ];
+
+        let batch = RecordBatch::try_new(this.schema.clone(), arrays)?;
+        Ok(batch)
+    }
+}
+
+impl PartitionStream for KeyColumnUsageTable {
+    fn schema(&self) -> &SchemaRef {
+        &self.schema
+    }
+
+    fn execute(&self, ctx: Arc<TaskContext>) -> SendableRecordBatchStream {
+        let this = self.clone();
+        let visibility = CatalogVisibility::from_task_context(&ctx);
+        Box::pin(RecordBatchStreamAdapter::new(
+            this.schema.clone(),
+            futures::stream::once(async move { Self::get_data(this, visibility).await }),
+        ))
+    }
+}
+
+impl KeyFilteredTable for KeyColumnUsageTable {
+    const KEY_COLUMNS: KeyColumns = KeyColumns {
+        oid: None,
+        name: Some("table_name"),
+        namespace: None,
+    };
+
+    fn with_key_filter(&self, key_filter: KeyFilter) -> Self {
+        Self {
+            key_filter,
+            ..self.clone()
+        }
+    }
+}
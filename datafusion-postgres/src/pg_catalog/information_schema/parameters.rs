@@ -0,0 +1,119 @@
+use std::sync::Arc;
+
+use datafusion::arrow::array::{ArrayRef, Int32Array, RecordBatch, StringArray};
+use datafusion::arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use datafusion::error::Result;
+use datafusion::execution::{SendableRecordBatchStream, TaskContext};
+use datafusion::physical_plan::stream::RecordBatchStreamAdapter;
+use datafusion::physical_plan::streaming::PartitionStream;
+
+use crate::pg_catalog::CatalogVisibility;
+
+use super::columns::type_names;
+use super::routines::routines;
+
+/// `information_schema.parameters`, the arguments of the routines of
+/// `information_schema.routines`
+#[derive(Debug, Clone)]
+pub(crate) struct ParametersTable {
+    schema: SchemaRef,
+    catalog_name: String,
+}
+
+impl ParametersTable {
+    pub(crate) fn new(catalog_name: String) -> Self {
+        let text = |name: &str| Field::new(name, DataType::Utf8, true);
+        let number = |name: &str| Field::new(name, DataType::Int32, true);
+        let schema = Arc::new(Schema::new(vec![
+            text("specific_catalog"),
+            text("specific_schema"),
+            text("specific_name"),
+            number("ordinal_position"),
+            text("parameter_mode"),
+            text("is_result"),
+            text("as_locator"),
+            text("parameter_name"),
+            text("data_type"),
+            text("udt_catalog"),
+            text("udt_schema"),
+            text("udt_name"),
+            text("parameter_default"),
+        ]));
+
+        Self {
+            schema,
+            catalog_name,
+        }
+    }
+
+    /// Generate record batches based on the functions of the session
+    fn get_data(
+        this: Self,
+        ctx: &TaskContext,
+        visibility: Option<Arc<CatalogVisibility>>,
+    ) -> Result<RecordBatch> {
+        let mut specific_names = Vec::new();
+        let mut ordinal_positions = Vec::new();
+        let mut parameter_names = Vec::new();
+        let mut data_types = Vec::new();
+        let mut udt_names = Vec::new();
+
+        let visible =
+            visibility.is_none_or(|visibility| visibility.is_catalog_visible(&this.catalog_name));
+        if visible {
+            for (_, udf, signatures) in routines(ctx) {
+                let arguments = udf.documentation().and_then(|doc| doc.arguments.as_ref());
+                for signature in signatures {
+                    for (idx, arg_type) in signature.arg_types.into_iter().enumerate() {
+                        let (data_type, udt_name) = type_names(arg_type);
+                        specific_names.push(signature.specific_name.clone());
+                        ordinal_positions.push(idx as i32 + 1);
+                        parameter_names.push(
+                            arguments
+                                .and_then(|arguments| arguments.get(idx))
+                                .map(|(name, _)| name.clone()),
+                        );
+                        data_types.push(data_type);
+                        udt_names.push(udt_name);
+                    }
+                }
+            }
+        }
+
+        let rows = specific_names.len();
+        let text = |value: &str| Arc::new(StringArray::from(vec![value; rows])) as ArrayRef;
+        let arrays: Vec<ArrayRef> = vec![
+            text(&this.catalog_name),
+            text("pg_catalog"),
+            Arc::new(StringArray::from(specific_names)),
+            Arc::new(Int32Array::from(ordinal_positions)),
+            text("IN"), // parameter_mode
+            text("NO"), // is_result
+            text("NO"), // as_locator
+            Arc::new(StringArray::from(parameter_names)),
+            Arc::new(StringArray::from(data_types)),
+            text(&this.catalog_name),
+            text("pg_catalog"),
+            Arc::new(StringArray::from(udt_names)),
+            Arc::new(StringArray::new_null(rows)), // parameter_default
+        ];
+
+        let batch = RecordBatch::try_new(this.schema.clone(), arrays)?;
+        Ok(batch)
+    }
+}
+
+impl PartitionStream for ParametersTable {
+    fn schema(&self) -> &SchemaRef {
+        &self.schema
+    }
+
+    fn execute(&self, ctx: Arc<TaskContext>) -> SendableRecordBatchStream {
+        let this = self.clone();
+        let visibility = CatalogVisibility::from_task_context(&ctx);
+        Box::pin(RecordBatchStreamAdapter::new(
+            this.schema.clone(),
+            futures::stream::once(async move { Self::get_data(this, &ctx, visibility) }),
+        ))
+    }
+}
@@ -0,0 +1,27 @@
+use std::sync::Arc;
+
+use datafusion::arrow::array::RecordBatch;
+use datafusion::arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use datafusion::catalog::MemTable;
+use datafusion::error::Result;
+
+/// `information_schema.referential_constraints`, always empty as DataFusion
+/// does not keep foreign keys
+///
+/// Served with the columns of postgres so introspection joining it with
+/// `table_constraints` completes.
+pub(crate) fn referential_constraints() -> Result<MemTable> {
+    let text = |name: &str| Field::new(name, DataType::Utf8, true);
+    let schema: SchemaRef = Arc::new(Schema::new(vec![
+        text("constraint_catalog"),
+        text("constraint_schema"),
+        text("constraint_name"),
+        text("unique_constraint_catalog"),
+        text("unique_constraint_schema"),
+        text("unique_constraint_name"),
+        text("match_option"),
+        text("update_rule"),
+        text("delete_rule"),
+    ]));
+    MemTable::try_new(schema.clone(), vec![vec![RecordBatch::new_empty(schema)]])
+}
@@ -0,0 +1,188 @@
+use std::collections::BTreeSet;
+use std::sync::Arc;
+
+use datafusion::arrow::array::{ArrayRef, RecordBatch, StringArray};
+use datafusion::arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use datafusion::error::Result;
+use datafusion::execution::{SendableRecordBatchStream, TaskContext};
+use datafusion::logical_expr::{ScalarUDF, Volatility};
+use datafusion::physical_plan::stream::RecordBatchStreamAdapter;
+use datafusion::physical_plan::streaming::PartitionStream;
+
+use crate::pg_catalog::pg_attribute::PgAttributeTable;
+use crate::pg_catalog::CatalogVisibility;
+
+use super::columns::type_names;
+
+/// A signature of a function, the postgres types of its arguments and of its
+/// result
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub(super) struct RoutineSignature {
+    pub(super) specific_name: String,
+    pub(super) arg_types: Vec<i32>,
+    pub(super) return_type: Option<i32>,
+}
+
+/// The functions of the session by name, aliases included, with their
+/// signatures, sorted by name
+///
+/// Like postgres, every overload of a function is a routine of its own,
+/// named by its `specific_name`. Only scalar functions are routines,
+/// aggregate and window functions are not.
+pub(super) fn routines(ctx: &TaskContext) -> Vec<(String, Arc<ScalarUDF>, Vec<RoutineSignature>)> {
+    let mut functions = ctx
+        .scalar_functions()
+        .iter()
+        // functions of `pg_catalog` are registered qualified by the schema
+        .map(|(name, udf)| {
+            let name = name.strip_prefix("pg_catalog.").unwrap_or(name);
+            (name.to_string(), udf.clone())
+        })
+        .collect::<Vec<_>>();
+    functions.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    let pg_type = |data_type: &DataType| PgAttributeTable::datafusion_to_pg_type(data_type).0;
+    functions
+        .into_iter()
+        .map(|(name, udf)| {
+            let example_types = udf.signature().type_signature.get_example_types();
+            let signatures = if example_types.is_empty() {
+                BTreeSet::from([(vec![], None)])
+            } else {
+                example_types
+                    .into_iter()
+                    .map(|arg_types| {
+                        let return_type = udf.return_type(&arg_types).ok();
+                        (
+                            arg_types.iter().map(pg_type).collect::<Vec<_>>(),
+                            return_type.as_ref().map(pg_type),
+                        )
+                    })
+                    .collect::<BTreeSet<_>>()
+            };
+            let signatures = signatures
+                .into_iter()
+                .enumerate()
+                .map(|(idx, (arg_types, return_type))| RoutineSignature {
+                    specific_name: format!("{name}_{}", idx + 1),
+                    arg_types,
+                    return_type,
+                })
+                .collect();
+            (name, udf, signatures)
+        })
+        .collect()
+}
+
+/// `information_schema.routines`, the scalar functions of the session
+#[derive(Debug, Clone)]
+pub(crate) struct RoutinesTable {
+    schema: SchemaRef,
+    catalog_name: String,
+}
+
+impl RoutinesTable {
+    pub(crate) fn new(catalog_name: String) -> Self {
+        // the leading columns of postgres, the following ones describe
+        // features of SQL routines DataFusion has no equivalent of
+        let text = |name: &str| Field::new(name, DataType::Utf8, true);
+        let schema = Arc::new(Schema::new(vec![
+            text("specific_catalog"),
+            text("specific_schema"),
+            text("specific_name"),
+            text("routine_catalog"),
+            text("routine_schema"),
+            text("routine_name"),
+            text("routine_type"),
+            text("data_type"),
+            text("type_udt_catalog"),
+            text("type_udt_schema"),
+            text("type_udt_name"),
+            text("routine_body"),
+            text("routine_definition"),
+            text("external_name"),
+            text("external_language"),
+            text("parameter_style"),
+            text("is_deterministic"),
+            text("sql_data_access"),
+            text("is_null_call"),
+        ]));
+
+        Self {
+            schema,
+            catalog_name,
+        }
+    }
+
+    /// Generate record batches based on the functions of the session
+    fn get_data(
+        this: Self,
+        ctx: &TaskContext,
+        visibility: Option<Arc<CatalogVisibility>>,
+    ) -> Result<RecordBatch> {
+        let mut specific_names = Vec::new();
+        let mut routine_names = Vec::new();
+        let mut data_types = Vec::new();
+        let mut type_udt_names = Vec::new();
+        let mut is_deterministics = Vec::new();
+
+        let visible =
+            visibility.is_none_or(|visibility| visibility.is_catalog_visible(&this.catalog_name));
+        if visible {
+            for (name, udf, signatures) in routines(ctx) {
+                let is_deterministic = udf.signature().volatility == Volatility::Immutable;
+                for signature in signatures {
+                    let type_names = signature.return_type.map(type_names);
+                    specific_names.push(signature.specific_name);
+                    routine_names.push(name.clone());
+                    data_types.push(type_names.map(|(data_type, _)| data_type));
+                    type_udt_names.push(type_names.map(|(_, udt_name)| udt_name));
+                    is_deterministics.push(if is_deterministic { "YES" } else { "NO" });
+                }
+            }
+        }
+
+        let rows = specific_names.len();
+        let text = |value: &str| Arc::new(StringArray::from(vec![value; rows])) as ArrayRef;
+        let nulls = || Arc::new(StringArray::new_null(rows)) as ArrayRef;
+        let arrays: Vec<ArrayRef> = vec![
+            text(&this.catalog_name),
+            text("pg_catalog"),
+            Arc::new(StringArray::from(specific_names)),
+            text(&this.catalog_name),
+            text("pg_catalog"),
+            Arc::new(StringArray::from(routine_names)),
+            text("FUNCTION"),
+            Arc::new(StringArray::from(data_types)),
+            text(&this.catalog_name),
+            text("pg_catalog"),
+            Arc::new(StringArray::from(type_udt_names)),
+            text("EXTERNAL"), // routine_body
+            nulls(),          // routine_definition
+            nulls(),          // external_name
+            text("RUST"),     // external_language
+            text("GENERAL"),  // parameter_style
+            Arc::new(StringArray::from(is_deterministics)),
+            text("NO SQL"), // sql_data_access
+            text("NO"),     // is_null_call
+        ];
+
+        let batch = RecordBatch::try_new(this.schema.clone(), arrays)?;
+        Ok(batch)
+    }
+}
+
+impl PartitionStream for RoutinesTable {
+    fn schema(&self) -> &SchemaRef {
+        &self.schema
+    }
+
+    fn execute(&self, ctx: Arc<TaskContext>) -> SendableRecordBatchStream {
+        let this = self.clone();
+        let visibility = CatalogVisibility::from_task_context(&ctx);
+        Box::pin(RecordBatchStreamAdapter::new(
+            this.schema.clone(),
+            futures::stream::once(async move { Self::get_data(this, &ctx, visibility) }),
+        ))
+    }
+}
@@ -0,0 +1,104 @@
+use std::sync::Arc;
+
+use datafusion::arrow::array::{ArrayRef, RecordBatch, StringArray};
+use datafusion::arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use datafusion::catalog::CatalogProviderList;
+use datafusion::error::Result;
+use datafusion::execution::{SendableRecordBatchStream, TaskContext};
+use datafusion::physical_plan::stream::RecordBatchStreamAdapter;
+use datafusion::physical_plan::streaming::PartitionStream;
+
+use crate::pg_catalog::CatalogVisibility;
+
+use super::current_catalog;
+
+/// `information_schema.schemata`, the schemas of the current catalog as in
+/// `pg_namespace`
+#[derive(Debug, Clone)]
+pub(crate) struct SchemataTable {
+    schema: SchemaRef,
+    catalog_name: String,
+    catalog_list: Arc<dyn CatalogProviderList>,
+}
+
+impl SchemataTable {
+    pub(crate) fn new(catalog_name: String, catalog_list: Arc<dyn CatalogProviderList>) -> Self {
+        let text = |name: &str| Field::new(name, DataType::Utf8, true);
+        let schema = Arc::new(Schema::new(vec![
+            text("catalog_name"),
+            text("schema_name"),
+            text("schema_owner"),
+            text("default_character_set_catalog"),
+            text("default_character_set_schema"),
+            text("default_character_set_name"),
+            text("sql_path"),
+        ]));
+
+        Self {
+            schema,
+            catalog_name,
+            catalog_list,
+        }
+    }
+
+    /// Generate record batches based on the current state of the catalog
+    async fn get_data(
+        this: Self,
+        visibility: Option<Arc<CatalogVisibility>>,
+    ) -> Result<RecordBatch> {
+        let mut schema_names = Vec::new();
+
+        let catalog_list = current_catalog(
+            &this.catalog_name,
+            &this.catalog_list,
+            visibility.as_deref(),
+        );
+        if let Some(catalog) = catalog_list.catalog(&this.catalog_name) {
+            for schema_name in catalog.schema_names() {
+                if let (Some(visibility), Some(schema)) =
+                    (&visibility, catalog.schema(&schema_name))
+                {
+                    if !visibility
+                        .is_schema_visible(&schema_name, schema.as_ref())
+                        .await
+                    {
+                        continue;
+                    }
+                }
+                schema_names.push(schema_name);
+            }
+        }
+        schema_names.sort();
+
+        let rows = schema_names.len();
+        let nulls = || Arc::new(StringArray::new_null(rows)) as ArrayRef;
+        let arrays: Vec<ArrayRef> = vec![
+            Arc::new(StringArray::from(vec![this.catalog_name.as_str(); rows])),
+            Arc::new(StringArray::from(schema_names)),
+            // owner of all schemas, as `nspowner` of `pg_namespace`
+            Arc::new(StringArray::from(vec!["postgres"; rows])),
+            nulls(), // default_character_set_catalog
+            nulls(), // default_character_set_schema
+            nulls(), // default_character_set_name
+            nulls(), // sql_path
+        ];
+
+        let batch = RecordBatch::try_new(this.schema.clone(), arrays)?;
+        Ok(batch)
+    }
+}
+
+impl PartitionStream for SchemataTable {
+    fn schema(&self) -> &SchemaRef {
+        &self.schema
+    }
+
+    fn execute(&self, ctx: Arc<TaskContext>) -> SendableRecordBatchStream {
+        let this = self.clone();
+        let visibility = CatalogVisibility::from_task_context(&ctx);
+        Box::pin(RecordBatchStreamAdapter::new(
+            this.schema.clone(),
+            futures::stream::once(async move { Self::get_data(this, visibility).await }),
+        ))
+    }
+}
@@ -0,0 +1,187 @@
+use std::sync::Arc;
+
+use datafusion::arrow::array::{ArrayRef, RecordBatch, StringArray};
+use datafusion::arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use datafusion::catalog::CatalogProviderList;
+use datafusion::common::Constraint;
+use datafusion::error::Result;
+use datafusion::execution::{SendableRecordBatchStream, TaskContext};
+use datafusion::physical_plan::stream::RecordBatchStreamAdapter;
+use datafusion::physical_plan::streaming::PartitionStream;
+
+use crate::pg_catalog::key_filter::{KeyColumns, KeyFilter, KeyFilteredTable};
+use crate::pg_catalog::{CatalogVisibility, OidRegistry, RelationInfo};
+
+use super::current_catalog;
+
+/// A primary key or unique constraint of a table
+pub(crate) struct TableConstraint {
+    /// Name postgres gives the constraint by default, like `t_pkey` or
+    /// `t_a_b_key`
+    pub(crate) name: String,
+    pub(crate) constraint_type: &'static str,
+    pub(crate) columns: Vec<String>,
+}
+
+/// The constraints DataFusion records of the table `table_name`
+///
+/// DataFusion knows of primary key and unique constraints only, foreign keys
+/// are accepted by `CREATE TABLE` but not kept.
+pub(crate) fn table_constraints(table_name: &str, relation: &RelationInfo) -> Vec<TableConstraint> {
+    let fields = relation.schema.fields();
+    relation
+        .constraints
+        .iter()
+        .map(|constraint| {
+            let (constraint_type, indices) = match constraint {
+                Constraint::PrimaryKey(indices) => ("PRIMARY KEY", indices),
+                Constraint::Unique(indices) => ("UNIQUE", indices),
+            };
+            let columns = indices
+                .iter()
+                .filter_map(|idx| fields.get(*idx).map(|field| field.name().clone()))
+                .collect::<Vec<_>>();
+            let name = match constraint {
+                Constraint::PrimaryKey(_) => format!("{table_name}_pkey"),
+                Constraint::Unique(_) => format!("{table_name}_{}_key", columns.join("_")),
+            };
+            TableConstraint {
+                name,
+                constraint_type,
+                columns,
+            }
+        })
+        .collect()
+}
+
+/// `information_schema.table_constraints`, the primary key and unique
+/// constraints of the relations of `pg_class`
+#[derive(Debug, Clone)]
+pub(crate) struct TableConstraintsTable {
+    schema: SchemaRef,
+    catalog_name: String,
+    catalog_list: Arc<dyn CatalogProviderList>,
+    oid_registry: Arc<OidRegistry>,
+    key_filter: KeyFilter,
+}
+
+impl TableConstraintsTable {
+    pub(crate) fn new(
+        catalog_name: String,
+        catalog_list: Arc<dyn CatalogProviderList>,
+        oid_registry: Arc<OidRegistry>,
+    ) -> Self {
+        let text = |name: &str| Field::new(name, DataType::Utf8, true);
+        let schema = Arc::new(Schema::new(vec![
+            text("constraint_catalog"),
+            text("constraint_schema"),
+            text("constraint_name"),
+            text("table_catalog"),
+            text("table_schema"),
+            text("table_name"),
+            text("constraint_type"),
+            text("is_deferrable"),
+            text("initially_deferred"),
+            text("enforced"),
+            text("nulls_distinct"),
+        ]));
+
+        Self {
+            schema,
+            catalog_name,
+            catalog_list,
+            oid_registry,
+            key_filter: KeyFilter::default(),
+        }
+    }
+
+    /// Generate record batches based on the current state of the catalog
+    async fn get_data(
+        this: Self,
+        visibility: Option<Arc<CatalogVisibility>>,
+    ) -> Result<RecordBatch> {
+        let mut table_schemas = Vec::new();
+        let mut constraint_names = Vec::new();
+        let mut table_names = Vec::new();
+        let mut constraint_types = Vec::new();
+        let mut nulls_distincts = Vec::new();
+
+        let catalog_list = current_catalog(
+            &this.catalog_name,
+            &this.catalog_list,
+            visibility.as_deref(),
+        );
+        for table in this.key_filter.tables(&catalog_list, &this.oid_registry) {
+            if let Some(visibility) = &visibility {
+                if !visibility
+                    .is_table_visible(&table.schema_name, &table.table_name)
+                    .await
+                {
+                    continue;
+                }
+            }
+
+            let Some(relation) = this.oid_registry.relation(&table).await? else {
+                continue;
+            };
+            for constraint in table_constraints(&table.table_name, &relation) {
+                table_schemas.push(table.schema_name.clone());
+                constraint_names.push(constraint.name);
+                table_names.push(table.table_name.clone());
+                constraint_types.push(constraint.constraint_type);
+                // only set for unique constraints
+                nulls_distincts.push((constraint.constraint_type == "UNIQUE").then_some("YES"));
+            }
+        }
+
+        let rows = table_names.len();
+        let text = |value: &str| Arc::new(StringArray::from(vec![value; rows])) as ArrayRef;
+        let table_schemas = Arc::new(StringArray::from(table_schemas)) as ArrayRef;
+        let arrays: Vec<ArrayRef> = vec![
+            text(&this.catalog_name),
+            table_schemas.clone(),
+            Arc::new(StringArray::from(constraint_names)),
+            text(&this.catalog_name),
+            table_schemas,
+            Arc::new(StringArray::from(table_names)),
+            Arc::new(StringArray::from(constraint_types)),
+            text("NO"),  // is_deferrable
+            text("NO"),  // initially_deferred
+            text("YES"), // enforced
+            Arc::new(StringArray::from(nulls_distincts)),
+        ];
+
+        let batch = RecordBatch::try_new(this.schema.clone(), arrays)?;
+        Ok(batch)
+    }
+}
+
+impl PartitionStream for TableConstraintsTable {
+    fn schema(&self) -> &SchemaRef {
+        &self.schema
+    }
+
+    fn execute(&self, ctx: Arc<TaskContext>) -> SendableRecordBatchStream {
+        let this = self.clone();
+        let visibility = CatalogVisibility::from_task_context(&ctx);
+        Box::pin(RecordBatchStreamAdapter::new(
+            this.schema.clone(),
+            futures::stream::once(async move { Self::get_data(this, visibility).await }),
+        ))
+    }
+}
+
+impl KeyFilteredTable for TableConstraintsTable {
+    const KEY_COLUMNS: KeyColumns = KeyColumns {
+        oid: None,
+        name: Some("table_name"),
+        namespace: None,
+    };
+
+    fn with_key_filter(&self, key_filter: KeyFilter) -> Self {
+        Self {
+            key_filter,
+            ..self.clone()
+        }
+    }
+}
@@ -0,0 +1,170 @@
+use std::sync::Arc;
+
+use datafusion::arrow::array::{ArrayRef, RecordBatch, StringArray};
+use datafusion::arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use datafusion::catalog::{CatalogProviderList, MemTable};
+use datafusion::datasource::listing::ListingTable;
+use datafusion::datasource::{TableProvider, TableType};
+use datafusion::error::Result;
+use datafusion::execution::{SendableRecordBatchStream, TaskContext};
+use datafusion::physical_plan::stream::RecordBatchStreamAdapter;
+use datafusion::physical_plan::streaming::PartitionStream;
+
+use crate::pg_catalog::key_filter::{KeyColumns, KeyFilter, KeyFilteredTable};
+use crate::pg_catalog::{CatalogVisibility, OidRegistry};
+
+use super::current_catalog;
+
+/// `table_type` of `information_schema.tables` for a table of `relkind`,
+/// and whether rows can be inserted into it
+///
+/// Views are the relations `pg_class` lists as views. Tables over files or upstream servers are listed as `FOREIGN`.
+/// Memory and file tables support `INSERT`, other providers are assumed not
+/// to.
+pub(crate) fn table_type(provider: &Arc<dyn TableProvider>, relkind: &str) -> (&'static str, bool) {
+    let is_listing = provider.as_any().is::<ListingTable>();
+    #[cfg(feature = "postgres-fdw")]
+    let is_foreign = is_listing || provider.as_any().is::<crate::foreign::RemoteTable>();
+    #[cfg(not(feature = "postgres-fdw"))]
+    let is_foreign = is_listing;
+    let is_insertable_into = relkind == "r" && (provider.as_any().is::<MemTable>() || is_listing);
+    let table_type = match (relkind, provider.table_type()) {
+        ("v", _) => "VIEW",
+        (_, TableType::Temporary) => "LOCAL TEMPORARY",
+        _ if is_foreign => "FOREIGN",
+        _ => "BASE TABLE",
+    };
+    (table_type, is_insertable_into)
+}
+
+/// `information_schema.tables`, generated from the same relations as
+/// `pg_class`
+#[derive(Debug, Clone)]
+pub(crate) struct TablesTable {
+    schema: SchemaRef,
+    catalog_name: String,
+    catalog_list: Arc<dyn CatalogProviderList>,
+    oid_registry: Arc<OidRegistry>,
+    key_filter: KeyFilter,
+}
+
+impl TablesTable {
+    pub(crate) fn new(
+        catalog_name: String,
+        catalog_list: Arc<dyn CatalogProviderList>,
+        oid_registry: Arc<OidRegistry>,
+    ) -> Self {
+        let text = |name: &str| Field::new(name, DataType::Utf8, true);
+        let schema = Arc::new(Schema::new(vec![
+            text("table_catalog"),
+            text("table_schema"),
+            text("table_name"),
+            text("table_type"),
+            text("self_referencing_column_name"),
+            text("reference_generation"),
+            text("user_defined_type_catalog"),
+            text("user_defined_type_schema"),
+            text("user_defined_type_name"),
+            text("is_insertable_into"),
+            text("is_typed"),
+            text("commit_action"),
+        ]));
+
+        Self {
+            schema,
+            catalog_name,
+            catalog_list,
+            oid_registry,
+            key_filter: KeyFilter::default(),
+        }
+    }
+
+    /// Generate record batches based on the current state of the catalog
+    async fn get_data(
+        this: Self,
+        visibility: Option<Arc<CatalogVisibility>>,
+    ) -> Result<RecordBatch> {
+        let mut table_schemas = Vec::new();
+        let mut table_names = Vec::new();
+        let mut table_types = Vec::new();
+        let mut is_insertable_intos = Vec::new();
+
+        let catalog_list = current_catalog(
+            &this.catalog_name,
+            &this.catalog_list,
+            visibility.as_deref(),
+        );
+        for table in this.key_filter.tables(&catalog_list, &this.oid_registry) {
+            if let Some(visibility) = &visibility {
+                if !visibility
+                    .is_table_visible(&table.schema_name, &table.table_name)
+                    .await
+                {
+                    continue;
+                }
+            }
+
+            let Some(relation) = this.oid_registry.relation(&table).await? else {
+                continue;
+            };
+            table_schemas.push(table.schema_name);
+            table_names.push(table.table_name);
+            table_types.push(relation.table_type);
+            is_insertable_intos.push(if relation.is_insertable_into {
+                "YES"
+            } else {
+                "NO"
+            });
+        }
+
+        let rows = table_names.len();
+        let nulls = || Arc::new(StringArray::new_null(rows)) as ArrayRef;
+        let arrays: Vec<ArrayRef> = vec![
+            Arc::new(StringArray::from(vec![this.catalog_name.as_str(); rows])),
+            Arc::new(StringArray::from(table_schemas)),
+            Arc::new(StringArray::from(table_names)),
+            Arc::new(StringArray::from(table_types)),
+            nulls(), // self_referencing_column_name
+            nulls(), // reference_generation
+            nulls(), // user_defined_type_catalog
+            nulls(), // user_defined_type_schema
+            nulls(), // user_defined_type_name
+            Arc::new(StringArray::from(is_insertable_intos)),
+            Arc::new(StringArray::from(vec!["NO"; rows])), // is_typed
+            nulls(),                                       // commit_action
+        ];
+
+        let batch = RecordBatch::try_new(this.schema.clone(), arrays)?;
+        Ok(batch)
+    }
+}
+
+impl PartitionStream for TablesTable {
+    fn schema(&self) -> &SchemaRef {
+        &self.schema
+    }
+
+    fn execute(&self, ctx: Arc<TaskContext>) -> SendableRecordBatchStream {
+        let this = self.clone();
+        let visibility = CatalogVisibility::from_task_context(&ctx);
+        Box::pin(RecordBatchStreamAdapter::new(
+            this.schema.clone(),
+            futures::stream::once(async move { Self::get_data(this, visibility).await }),
+        ))
+    }
+}
+
+impl KeyFilteredTable for TablesTable {
+    const KEY_COLUMNS: KeyColumns = KeyColumns {
+        oid: None,
+        name: Some("table_name"),
+        namespace: None,
+    };
+
+    fn with_key_filter(&self, key_filter: KeyFilter) -> Self {
+        Self {
+            key_filter,
+            ..self.clone()
+        }
+    }
+}
@@ -0,0 +1,142 @@
+use std::sync::Arc;
+
+use datafusion::arrow::array::{ArrayRef, RecordBatch, StringArray};
+use datafusion::arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use datafusion::catalog::CatalogProviderList;
+use datafusion::error::Result;
+use datafusion::execution::{SendableRecordBatchStream, TaskContext};
+use datafusion::physical_plan::stream::RecordBatchStreamAdapter;
+use datafusion::physical_plan::streaming::PartitionStream;
+
+use crate::pg_catalog::key_filter::{KeyColumns, KeyFilter, KeyFilteredTable};
+use crate::pg_catalog::{CatalogVisibility, OidRegistry};
+
+use super::current_catalog;
+
+/// `information_schema.views`, listing the views of the current catalog the
+/// user may access
+#[derive(Debug, Clone)]
+pub(crate) struct ViewsTable {
+    schema: SchemaRef,
+    catalog_name: String,
+    catalog_list: Arc<dyn CatalogProviderList>,
+    oid_registry: Arc<OidRegistry>,
+    key_filter: KeyFilter,
+}
+
+impl ViewsTable {
+    pub(crate) fn new(
+        catalog_name: String,
+        catalog_list: Arc<dyn CatalogProviderList>,
+        oid_registry: Arc<OidRegistry>,
+    ) -> Self {
+        let text = |name: &str| Field::new(name, DataType::Utf8, true);
+        let schema = Arc::new(Schema::new(vec![
+            text("table_catalog"),
+            text("table_schema"),
+            text("table_name"),
+            text("view_definition"),
+            text("check_option"),
+            text("is_updatable"),
+            text("is_insertable_into"),
+            text("is_trigger_updatable"),
+            text("is_trigger_deletable"),
+            text("is_trigger_insertable_into"),
+        ]));
+
+        Self {
+            schema,
+            catalog_name,
+            catalog_list,
+            oid_registry,
+            key_filter: KeyFilter::default(),
+        }
+    }
+
+    /// Generate record batches based on the current state of the catalog
+    async fn get_data(
+        this: Self,
+        visibility: Option<Arc<CatalogVisibility>>,
+    ) -> Result<RecordBatch> {
+        let mut table_schemas = Vec::new();
+        let mut table_names = Vec::new();
+        let mut view_definitions = Vec::new();
+
+        let catalog_list = current_catalog(
+            &this.catalog_name,
+            &this.catalog_list,
+            visibility.as_deref(),
+        );
+        for table in this.key_filter.tables(&catalog_list, &this.oid_registry) {
+            if let Some(visibility) = &visibility {
+                if !visibility
+                    .is_table_visible(&table.schema_name, &table.table_name)
+                    .await
+                {
+                    continue;
+                }
+            }
+
+            let Some(relation) = this.oid_registry.relation(&table).await? else {
+                continue;
+            };
+            if relation.relkind != "v" {
+                continue;
+            }
+            let Some(provider) = table.schema.table(&table.table_name).await? else {
+                continue;
+            };
+            view_definitions.push(provider.get_table_definition().map(str::to_string));
+            table_schemas.push(table.schema_name);
+            table_names.push(table.table_name);
+        }
+
+        let rows = table_names.len();
+        let no = || Arc::new(StringArray::from(vec!["NO"; rows])) as ArrayRef;
+        let arrays: Vec<ArrayRef> = vec![
+            Arc::new(StringArray::from(vec![this.catalog_name.as_str(); rows])),
+            Arc::new(StringArray::from(table_schemas)),
+            Arc::new(StringArray::from(table_names)),
+            Arc::new(StringArray::from(view_definitions)),
+            Arc::new(StringArray::from(vec!["NONE"; rows])), // check_option
+            no(),                                            // is_updatable
+            no(),                                            // is_insertable_into
+            no(),                                            // is_trigger_updatable
+            no(),                                            // is_trigger_deletable
+            no(),                                            // is_trigger_insertable_into
+        ];
+
+        let batch = RecordBatch::try_new(this.schema.clone(), arrays)?;
+        Ok(batch)
+    }
+}
+
+impl PartitionStream for ViewsTable {
+    fn schema(&self) -> &SchemaRef {
+        &self.schema
+    }
+
+    fn execute(&self, ctx: Arc<TaskContext>) -> SendableRecordBatchStream {
+        let this = self.clone();
+        let visibility = CatalogVisibility::from_task_context(&ctx);
+        Box::pin(RecordBatchStreamAdapter::new(
+            this.schema.clone(),
+            futures::stream::once(async move { Self::get_data(this, visibility).await }),
+        ))
+    }
+}
+
+impl KeyFilteredTable for ViewsTable {
+    const KEY_COLUMNS: KeyColumns = KeyColumns {
+        oid: None,
+        name: Some("table_name"),
+        namespace: None,
+    };
+
+    fn with_key_filter(&self, key_filter: KeyFilter) -> Self {
+        Self {
+            key_filter,
+            ..self.clone()
+        }
+    }
+}
@@ -0,0 +1,543 @@
+//! Postgres operators of intervals, timestamps and dates.
+//!
+//! [`PgIntervalPlanner`] plans the operators DataFusion refuses or answers
+//! differently than postgres with the functions postgres implements them
+//! with:
+//!
+//! - `interval * number`, `number * interval` and `interval / number` with
+//!   `interval_mul` and `interval_div`, which spill the fractions of months to
+//!   days of 30 days and of days to hours, so `interval '1 month' * 1.5` is
+//!   `1 mon 15 days`
+//! - comparisons of intervals with `interval_cmp`, which compares months as 30
+//!   days and days as 24 hours, so `interval '1 day' = interval '24 hours'`
+//! - `timestamp - timestamp` with `timestamp_mi`, an interval of days and time
+//!   like `4 days 10:00:00` instead of a duration
+//! - `date - date` as the number of days, `date + integer` and
+//!   `date - integer` as dates, and `date + interval` as a timestamp
+
+use std::any::Any;
+use std::cmp::Ordering;
+use std::sync::Arc;
+
+use datafusion::arrow::array::{ArrayRef, AsArray, Int32Array, IntervalMonthDayNanoArray};
+use datafusion::arrow::datatypes::{
+    DataType, Float64Type, IntervalMonthDayNano, IntervalMonthDayNanoType, IntervalUnit, TimeUnit,
+    TimestampMicrosecondType,
+};
+use datafusion::arrow::error::ArrowError;
+use datafusion::common::{plan_err, DFSchema};
+use datafusion::error::{DataFusionError, Result};
+use datafusion::logical_expr::expr::ScalarFunction;
+use datafusion::logical_expr::planner::{ExprPlanner, PlannerResult, RawBinaryExpr};
+use datafusion::logical_expr::{
+    binary_expr, cast, lit, ColumnarValue, Expr, ExprSchemable, Operator, ScalarFunctionArgs,
+    ScalarUDF, ScalarUDFImpl, Signature, Volatility,
+};
+use datafusion::sql::sqlparser::ast::BinaryOperator;
+
+use super::array_udf::invoke;
+
+const DAYS_PER_MONTH: f64 = 30.0;
+const NANOS_PER_DAY: i64 = 86_400_000_000_000;
+const NANOS_PER_MICRO: i64 = 1_000;
+
+fn interval_type() -> DataType {
+    DataType::Interval(IntervalUnit::MonthDayNano)
+}
+
+fn interval_out_of_range() -> DataFusionError {
+    ArrowError::ArithmeticOverflow("interval out of range".to_string()).into()
+}
+
+/// `value` rounded to microseconds, like the `TSROUND` of postgres
+fn round_micros(value: f64) -> f64 {
+    (value * 1e6).round() / 1e6
+}
+
+/// `span * factor`, like `interval_mul` of postgres
+fn interval_mul(span: IntervalMonthDayNano, factor: f64) -> Result<IntervalMonthDayNano> {
+    let months = span.months as f64 * factor;
+    let days = span.days as f64 * factor;
+    if !(months.is_finite() && days.is_finite())
+        || months.abs() > i32::MAX as f64
+        || days.abs() > i32::MAX as f64
+    {
+        return Err(interval_out_of_range());
+    }
+    let (months, mut days_part) = (months.trunc(), days.trunc());
+
+    // fractions of months spill to days and fractions of days to the time
+    let month_remainder_days =
+        round_micros((span.months as f64 * factor - months) * DAYS_PER_MONTH);
+    let mut seconds_remainder = round_micros(
+        (days - days_part + month_remainder_days - month_remainder_days.trunc()) * 86_400.0,
+    );
+    if seconds_remainder.abs() >= 86_400.0 {
+        let whole_days = (seconds_remainder / 86_400.0).trunc();
+        days_part += whole_days;
+        seconds_remainder -= whole_days * 86_400.0;
+    }
+    days_part += month_remainder_days.trunc();
+
+    let micros = (span.nanoseconds / NANOS_PER_MICRO) as f64 * factor + seconds_remainder * 1e6;
+    let micros = micros.round();
+    if !micros.is_finite() || micros.abs() > (i64::MAX / NANOS_PER_MICRO) as f64 {
+        return Err(interval_out_of_range());
+    }
+    Ok(IntervalMonthDayNano::new(
+        months as i32,
+        days_part as i32,
+        micros as i64 * NANOS_PER_MICRO,
+    ))
+}
+
+/// The value postgres compares intervals by, counting months as 30 days
+fn interval_cmp_value(span: IntervalMonthDayNano) -> i128 {
+    (span.months as i128 * 30 + span.days as i128) * NANOS_PER_DAY as i128
+        + span.nanoseconds as i128
+}
+
+/// `interval_mul(interval, float8)` and `interval_div(interval, float8)`
+#[derive(Debug)]
+pub(crate) struct IntervalMulUDF {
+    signature: Signature,
+    divide: bool,
+}
+
+impl IntervalMulUDF {
+    pub(crate) fn new(divide: bool) -> Self {
+        IntervalMulUDF {
+            signature: Signature::user_defined(Volatility::Immutable),
+            divide,
+        }
+    }
+
+    pub(crate) fn into_scalar_udf(self) -> ScalarUDF {
+        ScalarUDF::new_from_impl(self)
+    }
+}
+
+impl ScalarUDFImpl for IntervalMulUDF {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn name(&self) -> &str {
+        if self.divide {
+            "interval_div"
+        } else {
+            "interval_mul"
+        }
+    }
+
+    fn signature(&self) -> &Signature {
+        &self.signature
+    }
+
+    fn coerce_types(&self, arg_types: &[DataType]) -> Result<Vec<DataType>> {
+        match arg_types {
+            [DataType::Interval(_) | DataType::Null, factor]
+                if factor.is_numeric() || factor == &DataType::Null =>
+            {
+                Ok(vec![interval_type(), DataType::Float64])
+            }
+            _ => plan_err!(
+                "Function {}({}) does not exist",
+                self.name(),
+                arg_types
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        }
+    }
+
+    fn return_type(&self, _arg_types: &[DataType]) -> Result<DataType> {
+        Ok(interval_type())
+    }
+
+    fn invoke_with_args(&self, args: ScalarFunctionArgs) -> Result<ColumnarValue> {
+        invoke(args, |arrays| {
+            let spans = arrays[0].as_primitive::<IntervalMonthDayNanoType>();
+            let factors = arrays[1].as_primitive::<Float64Type>();
+            let products = spans
+                .iter()
+                .zip(factors.iter())
+                .map(|(span, factor)| {
+                    let (Some(span), Some(factor)) = (span, factor) else {
+                        return Ok(None);
+                    };
+                    if !self.divide {
+                        return interval_mul(span, factor).map(Some);
+                    }
+                    if factor == 0.0 {
+                        return Err(ArrowError::DivideByZero.into());
+                    }
+                    interval_mul(span, 1.0 / factor).map(Some)
+                })
+                .collect::<Result<IntervalMonthDayNanoArray>>()?;
+            Ok(Arc::new(products) as ArrayRef)
+        })
+    }
+}
+
+/// `interval_cmp(interval, interval)`, -1, 0 or 1
+#[derive(Debug)]
+pub(crate) struct IntervalCmpUDF {
+    signature: Signature,
+}
+
+impl IntervalCmpUDF {
+    pub(crate) fn new() -> Self {
+        IntervalCmpUDF {
+            signature: Signature::uniform(2, vec![interval_type()], Volatility::Immutable),
+        }
+    }
+
+    pub(crate) fn into_scalar_udf(self) -> ScalarUDF {
+        ScalarUDF::new_from_impl(self)
+    }
+}
+
+impl ScalarUDFImpl for IntervalCmpUDF {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn name(&self) -> &str {
+        "interval_cmp"
+    }
+
+    fn signature(&self) -> &Signature {
+        &self.signature
+    }
+
+    fn return_type(&self, _arg_types: &[DataType]) -> Result<DataType> {
+        Ok(DataType::Int32)
+    }
+
+    fn invoke_with_args(&self, args: ScalarFunctionArgs) -> Result<ColumnarValue> {
+        invoke(args, |arrays| {
+            let left = arrays[0].as_primitive::<IntervalMonthDayNanoType>();
+            let right = arrays[1].as_primitive::<IntervalMonthDayNanoType>();
+            let ordering = left
+                .iter()
+                .zip(right.iter())
+                .map(|(left, right)| {
+                    let ordering = interval_cmp_value(left?).cmp(&interval_cmp_value(right?));
+                    Some(match ordering {
+                        Ordering::Less => -1,
+                        Ordering::Equal => 0,
+                        Ordering::Greater => 1,
+                    })
+                })
+                .collect::<Int32Array>();
+            Ok(Arc::new(ordering) as ArrayRef)
+        })
+    }
+}
+
+/// `timestamp_mi(timestamp, timestamp)`, the interval of days and time
+/// between two timestamps
+#[derive(Debug)]
+pub(crate) struct TimestampMiUDF {
+    signature: Signature,
+}
+
+impl TimestampMiUDF {
+    pub(crate) fn new() -> Self {
+        TimestampMiUDF {
+            signature: Signature::user_defined(Volatility::Immutable),
+        }
+    }
+
+    pub(crate) fn into_scalar_udf(self) -> ScalarUDF {
+        ScalarUDF::new_from_impl(self)
+    }
+}
+
+impl ScalarUDFImpl for TimestampMiUDF {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn name(&self) -> &str {
+        "timestamp_mi"
+    }
+
+    fn signature(&self) -> &Signature {
+        &self.signature
+    }
+
+    fn coerce_types(&self, arg_types: &[DataType]) -> Result<Vec<DataType>> {
+        if arg_types.len() != 2 {
+            return plan_err!("timestamp_mi takes 2 arguments, got {}", arg_types.len());
+        }
+        // timestamps with a time zone are subtracted as instants, the others
+        // as if they were in UTC
+        let tz = arg_types
+            .iter()
+            .any(|arg_type| matches!(arg_type, DataType::Timestamp(_, Some(_))))
+            .then(|| "+00:00".into());
+        arg_types
+            .iter()
+            .map(|arg_type| match arg_type {
+                DataType::Timestamp(..)
+                | DataType::Date32
+                | DataType::Date64
+                | DataType::Utf8
+                | DataType::LargeUtf8
+                | DataType::Utf8View
+                | DataType::Null => Ok(DataType::Timestamp(TimeUnit::Microsecond, tz.clone())),
+                arg_type => plan_err!("Function timestamp_mi({arg_type}) does not exist"),
+            })
+            .collect()
+    }
+
+    fn return_type(&self, _arg_types: &[DataType]) -> Result<DataType> {
+        Ok(interval_type())
+    }
+
+    fn invoke_with_args(&self, args: ScalarFunctionArgs) -> Result<ColumnarValue> {
+        invoke(args, |arrays| {
+            let left = arrays[0].as_primitive::<TimestampMicrosecondType>();
+            let right = arrays[1].as_primitive::<TimestampMicrosecondType>();
+            let intervals = left
+                .iter()
+                .zip(right.iter())
+                .map(|(left, right)| {
+                    let (Some(left), Some(right)) = (left, right) else {
+                        return Ok(None);
+                    };
+                    let nanos = left
+                        .checked_sub(right)
+                        .and_then(|micros| micros.checked_mul(NANOS_PER_MICRO))
+                        .ok_or_else(interval_out_of_range)?;
+                    // whole days are days, like `justify_hours`
+                    Ok(Some(IntervalMonthDayNano::new(
+                        0,
+                        (nanos / NANOS_PER_DAY) as i32,
+                        nanos % NANOS_PER_DAY,
+                    )))
+                })
+                .collect::<Result<IntervalMonthDayNanoArray>>()?;
+            Ok(Arc::new(intervals) as ArrayRef)
+        })
+    }
+}
+
+/// Plans the operators of intervals, timestamps and dates like postgres
+///
+/// DataFusion has no plans for most of them, so the planner is registered
+/// after its planners like [`super::array_udf::PgArraySubscriptPlanner`].
+#[derive(Debug)]
+pub(crate) struct PgIntervalPlanner {
+    interval_mul: Arc<ScalarUDF>,
+    interval_div: Arc<ScalarUDF>,
+    interval_cmp: Arc<ScalarUDF>,
+    timestamp_mi: Arc<ScalarUDF>,
+}
+
+impl PgIntervalPlanner {
+    pub(crate) fn new() -> Self {
+        PgIntervalPlanner {
+            interval_mul: Arc::new(IntervalMulUDF::new(false).into_scalar_udf()),
+            interval_div: Arc::new(IntervalMulUDF::new(true).into_scalar_udf()),
+            interval_cmp: Arc::new(IntervalCmpUDF::new().into_scalar_udf()),
+            timestamp_mi: Arc::new(TimestampMiUDF::new().into_scalar_udf()),
+        }
+    }
+}
+
+fn call(udf: &Arc<ScalarUDF>, args: Vec<Expr>) -> Expr {
+    Expr::ScalarFunction(ScalarFunction::new_udf(udf.clone(), args))
+}
+
+/// Days since the epoch of a date
+fn days(date: Expr) -> Expr {
+    cast(cast(date, DataType::Date32), DataType::Int32)
+}
+
+fn comparison(op: &BinaryOperator) -> Option<Operator> {
+    Some(match op {
+        BinaryOperator::Eq => Operator::Eq,
+        BinaryOperator::NotEq => Operator::NotEq,
+        BinaryOperator::Lt => Operator::Lt,
+        BinaryOperator::LtEq => Operator::LtEq,
+        BinaryOperator::Gt => Operator::Gt,
+        BinaryOperator::GtEq => Operator::GtEq,
+        _ => return None,
+    })
+}
+
+impl ExprPlanner for PgIntervalPlanner {
+    fn plan_binary_op(
+        &self,
+        expr: RawBinaryExpr,
+        schema: &DFSchema,
+    ) -> Result<PlannerResult<RawBinaryExpr>> {
+        // parameters of unknown types are left to DataFusion
+        let (Ok(left_type), Ok(right_type)) =
+            (expr.left.get_type(schema), expr.right.get_type(schema))
+        else {
+            return Ok(PlannerResult::Original(expr));
+        };
+        let is_interval = |data_type: &DataType| matches!(data_type, DataType::Interval(_));
+        let is_date =
+            |data_type: &DataType| matches!(data_type, DataType::Date32 | DataType::Date64);
+        let is_timestamp = |data_type: &DataType| matches!(data_type, DataType::Timestamp(..));
+
+        let RawBinaryExpr { op, left, right } = expr;
+        let planned = match op {
+            BinaryOperator::Multiply if is_interval(&left_type) && right_type.is_numeric() => {
+                call(&self.interval_mul, vec![left, right])
+            }
+            BinaryOperator::Multiply if left_type.is_numeric() && is_interval(&right_type) => {
+                call(&self.interval_mul, vec![right, left])
+            }
+            BinaryOperator::Divide if is_interval(&left_type) && right_type.is_numeric() => {
+                call(&self.interval_div, vec![left, right])
+            }
+            ref op if is_interval(&left_type) && is_interval(&right_type) => match comparison(op) {
+                Some(op) => binary_expr(call(&self.interval_cmp, vec![left, right]), op, lit(0)),
+                None => {
+                    return Ok(PlannerResult::Original(RawBinaryExpr {
+                        op: op.clone(),
+                        left,
+                        right,
+                    }))
+                }
+            },
+            BinaryOperator::Minus if is_date(&left_type) && is_date(&right_type) => {
+                days(left) - days(right)
+            }
+            BinaryOperator::Minus
+                if (is_timestamp(&left_type) || is_date(&left_type))
+                    && (is_timestamp(&right_type) || is_date(&right_type)) =>
+            {
+                call(&self.timestamp_mi, vec![left, right])
+            }
+            BinaryOperator::Plus | BinaryOperator::Minus
+                if is_date(&left_type) && right_type.is_integer() =>
+            {
+                let op = if op == BinaryOperator::Plus {
+                    Operator::Plus
+                } else {
+                    Operator::Minus
+                };
+                let days = binary_expr(days(left), op, cast(right, DataType::Int32));
+                cast(days, DataType::Date32)
+            }
+            BinaryOperator::Plus if left_type.is_integer() && is_date(&right_type) => {
+                cast(cast(left, DataType::Int32) + days(right), DataType::Date32)
+            }
+            BinaryOperator::Plus | BinaryOperator::Minus
+                if is_date(&left_type) && is_interval(&right_type) =>
+            {
+                let op = if op == BinaryOperator::Plus {
+                    Operator::Plus
+                } else {
+                    Operator::Minus
+                };
+                let timestamp = cast(left, DataType::Timestamp(TimeUnit::Nanosecond, None));
+                binary_expr(timestamp, op, right)
+            }
+            BinaryOperator::Plus if is_interval(&left_type) && is_date(&right_type) => {
+                left + cast(right, DataType::Timestamp(TimeUnit::Nanosecond, None))
+            }
+            op => return Ok(PlannerResult::Original(RawBinaryExpr { op, left, right })),
+        };
+        Ok(PlannerResult::Planned(planned))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use datafusion::prelude::SessionContext;
+
+    use super::*;
+    use crate::pg_catalog::setup_pg_catalog;
+
+    async fn query(ctx: &SessionContext, sql: &str) -> Vec<String> {
+        let batches = ctx.sql(sql).await.unwrap().collect().await.unwrap();
+        datafusion::arrow::util::pretty::pretty_format_batches(&batches)
+            .unwrap()
+            .to_string()
+            .lines()
+            .skip(3)
+            .filter(|line| !line.starts_with('+'))
+            .map(|line| line.trim_matches(|c| c == '|' || c == ' ').to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_interval_mul() {
+        let mul = |months, days, nanos, factor| {
+            interval_mul(IntervalMonthDayNano::new(months, days, nanos), factor).unwrap()
+        };
+        // fractions of months spill to days, of days to hours
+        assert_eq!(mul(1, 0, 0, 1.5), IntervalMonthDayNano::new(1, 15, 0));
+        assert_eq!(
+            mul(0, 1, 0, 1.5),
+            IntervalMonthDayNano::new(0, 1, NANOS_PER_DAY / 2)
+        );
+        assert_eq!(
+            mul(0, 0, 3_600_000_000_000, 2.5),
+            IntervalMonthDayNano::new(0, 0, 9_000_000_000_000)
+        );
+        assert_eq!(mul(1, 2, 0, -2.0), IntervalMonthDayNano::new(-2, -4, 0));
+        assert!(interval_mul(IntervalMonthDayNano::new(i32::MAX, 0, 0), 2.0).is_err());
+
+        assert_eq!(
+            interval_cmp_value(IntervalMonthDayNano::new(1, 0, 0)),
+            interval_cmp_value(IntervalMonthDayNano::new(0, 30, 0))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_interval_operators() {
+        let ctx = SessionContext::new();
+        setup_pg_catalog(&ctx, "datafusion").unwrap();
+
+        for (sql, expected) in [
+            ("SELECT INTERVAL '1 day' * 2", "2 days"),
+            ("SELECT 3 * INTERVAL '1 hour'", "3 hours"),
+            ("SELECT INTERVAL '1 month' * 1.5", "1 mons 15 days"),
+            ("SELECT INTERVAL '1 day' / 4", "6 hours"),
+            ("SELECT INTERVAL '1 day' = INTERVAL '24 hours'", "true"),
+            ("SELECT INTERVAL '1 month' = INTERVAL '30 days'", "true"),
+            ("SELECT INTERVAL '1 day' < INTERVAL '25 hours'", "true"),
+            ("SELECT INTERVAL '2 days' - INTERVAL '1 day' > INTERVAL '23 hours'", "true"),
+            (
+                "SELECT TIMESTAMP '2024-01-05 10:00:00' - TIMESTAMP '2024-01-01 00:00:00'",
+                "4 days 10 hours",
+            ),
+            (
+                "SELECT TIMESTAMP '2024-01-05 10:00:00' - TIMESTAMP '2024-01-01 00:00:00' > INTERVAL '4 days'",
+                "true",
+            ),
+            ("SELECT DATE '2024-03-01' - DATE '2024-02-01'", "29"),
+            ("SELECT DATE '2024-02-28' + 2", "2024-03-01"),
+            ("SELECT 1 + DATE '2024-02-28'", "2024-02-29"),
+            ("SELECT DATE '2024-03-01' - 1", "2024-02-29"),
+            ("SELECT DATE '2024-01-02' + INTERVAL '1 hour'", "2024-01-02T01:00:00"),
+            (
+                "SELECT TIMESTAMP '2024-01-01 00:00:00' + INTERVAL '1 day'",
+                "2024-01-02T00:00:00",
+            ),
+            ("SELECT now() - INTERVAL '7 days' < now()", "true"),
+        ] {
+            assert_eq!(query(&ctx, sql).await, [expected], "{sql}");
+        }
+
+        let error = ctx
+            .sql("SELECT INTERVAL '1 day' / 0")
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("Divide by zero"), "{error}");
+    }
+}
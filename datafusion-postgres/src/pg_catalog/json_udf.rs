@@ -0,0 +1,748 @@
+//! Postgres json operators and functions over the text of json documents.
+//!
+//! `json` and `jsonb` values are the text of their documents, like the json
+//! columns of Parquet files, and casts to them are left out by
+//! [`crate::sql::RemoveUnsupportedTypes`]. Documents are written like jsonb,
+//! with the keys of objects ordered by length and then bytes.
+//!
+//! `doc -> 'key'`, `doc -> 0` and `doc #> '{a,0}'` are planned by
+//! [`PgJsonPlanner`] to `jsonb_extract_path`, and their `->>` and `#>>`
+//! variants to `jsonb_extract_path_text`. `jsonb_array_elements` and
+//! `jsonb_each` are table functions of constant documents, and
+//! `jsonb_array_elements(doc)` in select lists is unnested by
+//! [`crate::sql::RewriteJsonSetFunctions`].
+
+use std::any::Any;
+use std::iter;
+use std::sync::Arc;
+
+use datafusion::arrow::array::{
+    Array, ArrayRef, AsArray, ListBuilder, RecordBatch, StringArray, StringBuilder,
+};
+use datafusion::arrow::datatypes::{DataType, Field, Int64Type, Schema};
+use datafusion::arrow::error::ArrowError;
+use datafusion::catalog::{MemTable, TableFunctionImpl, TableProvider};
+use datafusion::common::{exec_err, plan_err, DFSchema, ScalarValue};
+use datafusion::error::{DataFusionError, Result};
+use datafusion::logical_expr::expr::ScalarFunction;
+use datafusion::logical_expr::planner::{ExprPlanner, PlannerResult, RawBinaryExpr};
+use datafusion::logical_expr::{
+    lit, ColumnarValue, Expr, ExprSchemable, ScalarFunctionArgs, ScalarUDF, ScalarUDFImpl,
+    Signature, Volatility,
+};
+use datafusion::sql::sqlparser::ast::BinaryOperator;
+use serde_json::{Map, Number, Value};
+
+use super::array_udf::invoke;
+
+fn invalid_input(message: String) -> DataFusionError {
+    DataFusionError::ArrowError(Box::new(ArrowError::ParseError(message)), None)
+}
+
+fn is_text(data_type: &DataType) -> bool {
+    matches!(
+        data_type,
+        DataType::Utf8 | DataType::LargeUtf8 | DataType::Utf8View | DataType::Null
+    )
+}
+
+/// Parse the json document `text`
+fn parse(text: &str) -> Result<Value> {
+    serde_json::from_str(text)
+        .map_err(|e| invalid_input(format!("invalid input syntax for type json: {e}")))
+}
+
+/// Keys of `object` in the order of jsonb, by length and then bytes
+fn sorted_keys(object: &Map<String, Value>) -> Vec<&String> {
+    let mut keys = object.keys().collect::<Vec<_>>();
+    keys.sort_by(|a, b| a.len().cmp(&b.len()).then_with(|| a.cmp(b)));
+    keys
+}
+
+/// Write `value` like the output of jsonb
+fn write_jsonb(value: &Value, out: &mut String) {
+    match value {
+        Value::Array(values) => {
+            out.push('[');
+            for (idx, value) in values.iter().enumerate() {
+                if idx > 0 {
+                    out.push_str(", ");
+                }
+                write_jsonb(value, out);
+            }
+            out.push(']');
+        }
+        Value::Object(object) => {
+            out.push('{');
+            for (idx, key) in sorted_keys(object).into_iter().enumerate() {
+                if idx > 0 {
+                    out.push_str(", ");
+                }
+                out.push_str(&Value::from(key.as_str()).to_string());
+                out.push_str(": ");
+                write_jsonb(&object[key], out);
+            }
+            out.push('}');
+        }
+        value => out.push_str(&value.to_string()),
+    }
+}
+
+fn to_jsonb(value: &Value) -> String {
+    let mut out = String::new();
+    write_jsonb(value, &mut out);
+    out
+}
+
+/// Text of `value` answered by `->>`, strings without their quotes and
+/// `null` as `NULL`
+fn to_text(value: &Value) -> Option<String> {
+    match value {
+        Value::Null => None,
+        Value::String(s) => Some(s.clone()),
+        value => Some(to_jsonb(value)),
+    }
+}
+
+/// Json of the SQL `value`, numbers and booleans as themselves and other
+/// values as their text
+fn to_json(value: ScalarValue) -> Value {
+    if value.is_null() {
+        return Value::Null;
+    }
+    match value {
+        ScalarValue::Boolean(Some(b)) => Value::Bool(b),
+        ScalarValue::Float32(Some(f)) => float(f as f64),
+        ScalarValue::Float64(Some(f)) => float(f),
+        ScalarValue::Utf8(Some(s))
+        | ScalarValue::LargeUtf8(Some(s))
+        | ScalarValue::Utf8View(Some(s)) => Value::String(s),
+        value if value.data_type().is_numeric() => {
+            let text = value.to_string();
+            serde_json::from_str(&text).unwrap_or(Value::String(text))
+        }
+        value => Value::String(value.to_string()),
+    }
+}
+
+/// Json of `f`, `NaN` and infinities as strings like postgres
+fn float(f: f64) -> Value {
+    Number::from_f64(f).map_or_else(|| Value::String(f.to_string()), Value::Number)
+}
+
+/// Step of a path into a document
+#[derive(Debug, Clone, PartialEq)]
+enum Step {
+    Key(String),
+    Index(i64),
+}
+
+/// The value at `step` of `value`, keys of arrays being their subscripts like
+/// in the paths of `#>`
+fn step<'a>(value: &'a Value, step: &Step) -> Option<&'a Value> {
+    match (value, step) {
+        (Value::Object(object), Step::Key(key)) => object.get(key),
+        (Value::Array(values), Step::Key(key)) => element(values, key.trim().parse().ok()?),
+        (Value::Array(values), Step::Index(idx)) => element(values, *idx),
+        _ => None,
+    }
+}
+
+/// Element `idx` of `values`, negative subscripts counting from the end
+fn element(values: &[Value], idx: i64) -> Option<&Value> {
+    let idx = if idx < 0 {
+        values.len() as i64 + idx
+    } else {
+        idx
+    };
+    values.get(usize::try_from(idx).ok()?)
+}
+
+/// Path of the path arguments `arrays` at `idx`, `None` when an argument or
+/// an element of an array argument is `NULL`
+fn path_at(arrays: &[ArrayRef], idx: usize) -> Option<Vec<Step>> {
+    let mut path = Vec::with_capacity(arrays.len());
+    for array in arrays {
+        if array.is_null(idx) {
+            return None;
+        }
+        match array.data_type() {
+            DataType::Int64 => path.push(Step::Index(array.as_primitive::<Int64Type>().value(idx))),
+            DataType::List(_) => {
+                let keys = array.as_list::<i32>().value(idx);
+                for key in keys.as_string::<i32>() {
+                    path.push(Step::Key(key?.to_string()));
+                }
+            }
+            _ => path.push(Step::Key(array.as_string::<i32>().value(idx).to_string())),
+        }
+    }
+    Some(path)
+}
+
+/// Elements of the text array literal `text`, like `{a,"b c",0}`
+fn parse_text_array(text: &str) -> Result<Vec<String>> {
+    let malformed = || invalid_input(format!("malformed array literal: \"{text}\""));
+    let inner = text
+        .trim()
+        .strip_prefix('{')
+        .and_then(|inner| inner.strip_suffix('}'))
+        .ok_or_else(malformed)?;
+    let mut elements = Vec::new();
+    if inner.trim().is_empty() {
+        return Ok(elements);
+    }
+    let mut chars = inner.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        let mut element = String::new();
+        if chars.next_if_eq(&'"').is_some() {
+            loop {
+                match chars.next().ok_or_else(malformed)? {
+                    '"' => break,
+                    '\\' => element.push(chars.next().ok_or_else(malformed)?),
+                    c => element.push(c),
+                }
+            }
+            while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        } else {
+            while let Some(c) = chars.next_if(|c| *c != ',') {
+                element.push(c);
+            }
+            element.truncate(element.trim_end().len());
+        }
+        elements.push(element);
+        match chars.next() {
+            Some(',') => {}
+            None => return Ok(elements),
+            Some(_) => return Err(malformed()),
+        }
+    }
+}
+
+/// Elements of the json array `document`, as jsonb or as text
+fn array_elements(document: &Value, as_text: bool) -> Result<Vec<Option<String>>> {
+    match document {
+        Value::Array(values) => Ok(values
+            .iter()
+            .map(|value| {
+                if as_text {
+                    to_text(value)
+                } else {
+                    Some(to_jsonb(value))
+                }
+            })
+            .collect()),
+        // invalid_parameter_value, like postgres
+        Value::Object(_) => Err(DataFusionError::Configuration(
+            "cannot extract elements from an object".to_string(),
+        )),
+        _ => Err(DataFusionError::Configuration(
+            "cannot extract elements from a scalar".to_string(),
+        )),
+    }
+}
+
+/// Keys and values of the json object `document`, the values as jsonb or as
+/// text
+fn object_entries(document: &Value, as_text: bool) -> Result<Vec<(String, Option<String>)>> {
+    let Value::Object(object) = document else {
+        // invalid_parameter_value, like postgres
+        return Err(DataFusionError::Configuration(
+            "cannot call jsonb_each on a non-object".to_string(),
+        ));
+    };
+    Ok(sorted_keys(object)
+        .into_iter()
+        .map(|key| {
+            let value = &object[key];
+            let value = if as_text {
+                to_text(value)
+            } else {
+                Some(to_jsonb(value))
+            };
+            (key.clone(), value)
+        })
+        .collect())
+}
+
+/// `jsonb_extract_path(doc, VARIADIC path)` and `jsonb_extract_path_text`,
+/// the value at the keys and array subscripts of `path`
+///
+/// Path arguments are text keys, integer subscripts or text arrays of keys.
+#[derive(Debug)]
+pub(crate) struct JsonExtractPathUDF {
+    signature: Signature,
+    as_text: bool,
+    aliases: Vec<String>,
+}
+
+impl JsonExtractPathUDF {
+    pub(crate) fn new(as_text: bool) -> Self {
+        let alias = if as_text {
+            "json_extract_path_text"
+        } else {
+            "json_extract_path"
+        };
+        JsonExtractPathUDF {
+            signature: Signature::user_defined(Volatility::Immutable),
+            as_text,
+            aliases: vec![alias.to_string()],
+        }
+    }
+
+    pub(crate) fn into_scalar_udf(self) -> ScalarUDF {
+        ScalarUDF::new_from_impl(self)
+    }
+}
+
+impl ScalarUDFImpl for JsonExtractPathUDF {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn name(&self) -> &str {
+        if self.as_text {
+            "jsonb_extract_path_text"
+        } else {
+            "jsonb_extract_path"
+        }
+    }
+
+    fn aliases(&self) -> &[String] {
+        &self.aliases
+    }
+
+    fn signature(&self) -> &Signature {
+        &self.signature
+    }
+
+    fn coerce_types(&self, arg_types: &[DataType]) -> Result<Vec<DataType>> {
+        let Some((document, path)) = arg_types.split_first() else {
+            return plan_err!("{} takes a document and a path", self.name());
+        };
+        if !is_text(document) {
+            return plan_err!("Function {}({document}, ...) does not exist", self.name());
+        }
+        iter::once(Ok(DataType::Utf8))
+            .chain(path.iter().map(|data_type| match data_type {
+                data_type if data_type.is_integer() => Ok(DataType::Int64),
+                data_type if is_text(data_type) => Ok(DataType::Utf8),
+                DataType::List(field)
+                | DataType::LargeList(field)
+                | DataType::FixedSizeList(field, _)
+                    if is_text(field.data_type()) =>
+                {
+                    Ok(DataType::List(Arc::new(Field::new_list_field(
+                        DataType::Utf8,
+                        true,
+                    ))))
+                }
+                data_type => plan_err!("Cannot use {data_type} in a json path"),
+            }))
+            .collect()
+    }
+
+    fn return_type(&self, _arg_types: &[DataType]) -> Result<DataType> {
+        Ok(DataType::Utf8)
+    }
+
+    fn invoke_with_args(&self, args: ScalarFunctionArgs) -> Result<ColumnarValue> {
+        invoke(args, |arrays| {
+            let documents = arrays[0].as_string::<i32>();
+            let mut values = StringBuilder::new();
+            for idx in 0..documents.len() {
+                let value = match path_at(&arrays[1..], idx) {
+                    Some(path) if documents.is_valid(idx) => {
+                        let document = parse(documents.value(idx))?;
+                        path.iter()
+                            .try_fold(&document, |value, key| step(value, key))
+                            .and_then(|value| {
+                                if self.as_text {
+                                    to_text(value)
+                                } else {
+                                    Some(to_jsonb(value))
+                                }
+                            })
+                    }
+                    _ => None,
+                };
+                values.append_option(value);
+            }
+            Ok(Arc::new(values.finish()))
+        })
+    }
+}
+
+/// `jsonb_build_object(key, value, ...)`, the object of alternating keys and
+/// values
+#[derive(Debug)]
+pub(crate) struct JsonBuildObjectUDF {
+    signature: Signature,
+    aliases: Vec<String>,
+}
+
+impl JsonBuildObjectUDF {
+    pub(crate) fn new() -> Self {
+        JsonBuildObjectUDF {
+            signature: Signature::variadic_any(Volatility::Immutable),
+            aliases: vec!["json_build_object".to_string()],
+        }
+    }
+
+    pub(crate) fn into_scalar_udf(self) -> ScalarUDF {
+        ScalarUDF::new_from_impl(self)
+    }
+}
+
+impl ScalarUDFImpl for JsonBuildObjectUDF {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn name(&self) -> &str {
+        "jsonb_build_object"
+    }
+
+    fn aliases(&self) -> &[String] {
+        &self.aliases
+    }
+
+    fn signature(&self) -> &Signature {
+        &self.signature
+    }
+
+    fn return_type(&self, _arg_types: &[DataType]) -> Result<DataType> {
+        Ok(DataType::Utf8)
+    }
+
+    fn invoke_with_args(&self, args: ScalarFunctionArgs) -> Result<ColumnarValue> {
+        if args.args.len() % 2 != 0 {
+            // invalid_parameter_value, like postgres
+            return Err(DataFusionError::Configuration(
+                "argument list must have even number of elements".to_string(),
+            ));
+        }
+        invoke(args, |arrays| {
+            let rows = arrays.first().map_or(1, |array| array.len());
+            let mut objects = StringBuilder::new();
+            for idx in 0..rows {
+                let mut object = Map::new();
+                for pair in arrays.chunks(2) {
+                    let key = match ScalarValue::try_from_array(&pair[0], idx)? {
+                        key if key.is_null() => {
+                            return exec_err!("null value not allowed for object key")
+                        }
+                        ScalarValue::Utf8(Some(key))
+                        | ScalarValue::LargeUtf8(Some(key))
+                        | ScalarValue::Utf8View(Some(key)) => key,
+                        key => key.to_string(),
+                    };
+                    object.insert(key, to_json(ScalarValue::try_from_array(&pair[1], idx)?));
+                }
+                objects.append_value(to_jsonb(&Value::Object(object)));
+            }
+            Ok(Arc::new(objects.finish()))
+        })
+    }
+}
+
+/// `jsonb_array_elements(doc)` and `jsonb_array_elements_text` of select
+/// lists, the array of the elements that [`crate::sql::RewriteJsonSetFunctions`]
+/// unnests into rows
+#[derive(Debug)]
+pub(crate) struct JsonArrayElementsUDF {
+    signature: Signature,
+    as_text: bool,
+    aliases: Vec<String>,
+}
+
+impl JsonArrayElementsUDF {
+    pub(crate) fn new(as_text: bool) -> Self {
+        let alias = if as_text {
+            "json_array_elements_text"
+        } else {
+            "json_array_elements"
+        };
+        JsonArrayElementsUDF {
+            signature: Signature::string(1, Volatility::Immutable),
+            as_text,
+            aliases: vec![alias.to_string()],
+        }
+    }
+
+    pub(crate) fn into_scalar_udf(self) -> ScalarUDF {
+        ScalarUDF::new_from_impl(self)
+    }
+}
+
+impl ScalarUDFImpl for JsonArrayElementsUDF {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn name(&self) -> &str {
+        if self.as_text {
+            "jsonb_array_elements_text"
+        } else {
+            "jsonb_array_elements"
+        }
+    }
+
+    fn aliases(&self) -> &[String] {
+        &self.aliases
+    }
+
+    fn signature(&self) -> &Signature {
+        &self.signature
+    }
+
+    fn return_type(&self, _arg_types: &[DataType]) -> Result<DataType> {
+        Ok(DataType::List(Arc::new(Field::new_list_field(
+            DataType::Utf8,
+            true,
+        ))))
+    }
+
+    fn invoke_with_args(&self, args: ScalarFunctionArgs) -> Result<ColumnarValue> {
+        invoke(args, |arrays| {
+            let documents = arrays[0].as_string::<i32>();
+            let mut lists = ListBuilder::new(StringBuilder::new());
+            for document in documents {
+                match document {
+                    Some(document) => {
+                        let elements = array_elements(&parse(document)?, self.as_text)?;
+                        lists.append_value(elements);
+                    }
+                    None => lists.append_null(),
+                }
+            }
+            Ok(Arc::new(lists.finish()))
+        })
+    }
+}
+
+/// The constant document of the arguments of a table function
+fn constant_document(name: &str, exprs: &[Expr]) -> Result<Option<Value>> {
+    match exprs {
+        [Expr::Literal(value, _)] => match value.try_as_str() {
+            Some(document) => document.map(parse).transpose(),
+            None => plan_err!("{name} takes a json document, got {}", value.data_type()),
+        },
+        _ => plan_err!("{name} takes a single constant json document"),
+    }
+}
+
+/// `jsonb_array_elements(doc)` and `jsonb_array_elements_text` in `FROM`,
+/// the `value` of each element of a constant document
+#[derive(Debug)]
+pub(crate) struct JsonArrayElementsFunc {
+    pub(crate) as_text: bool,
+}
+
+impl TableFunctionImpl for JsonArrayElementsFunc {
+    fn call(&self, exprs: &[Expr]) -> Result<Arc<dyn TableProvider>> {
+        let elements = match constant_document("jsonb_array_elements", exprs)? {
+            Some(document) => array_elements(&document, self.as_text)?,
+            None => Vec::new(),
+        };
+        let schema = Arc::new(Schema::new(vec![Field::new("value", DataType::Utf8, true)]));
+        let batch =
+            RecordBatch::try_new(schema.clone(), vec![Arc::new(StringArray::from(elements))])?;
+        Ok(Arc::new(MemTable::try_new(schema, vec![vec![batch]])?))
+    }
+}
+
+/// `jsonb_each(doc)` and `jsonb_each_text` in `FROM`, the `key` and `value`
+/// of each entry of a constant object
+#[derive(Debug)]
+pub(crate) struct JsonEachFunc {
+    pub(crate) as_text: bool,
+}
+
+impl TableFunctionImpl for JsonEachFunc {
+    fn call(&self, exprs: &[Expr]) -> Result<Arc<dyn TableProvider>> {
+        let entries = match constant_document("jsonb_each", exprs)? {
+            Some(document) => object_entries(&document, self.as_text)?,
+            None => Vec::new(),
+        };
+        let (keys, values): (Vec<_>, Vec<_>) = entries.into_iter().unzip();
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("key", DataType::Utf8, false),
+            Field::new("value", DataType::Utf8, true),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(StringArray::from(keys)),
+                Arc::new(StringArray::from(values)),
+            ],
+        )?;
+        Ok(Arc::new(MemTable::try_new(schema, vec![vec![batch]])?))
+    }
+}
+
+/// Plans the json operators `->`, `->>`, `#>` and `#>>` of text documents
+#[derive(Debug)]
+pub(crate) struct PgJsonPlanner {
+    extract_path: Arc<ScalarUDF>,
+    extract_path_text: Arc<ScalarUDF>,
+}
+
+impl PgJsonPlanner {
+    pub(crate) fn new() -> Self {
+        PgJsonPlanner {
+            extract_path: Arc::new(JsonExtractPathUDF::new(false).into_scalar_udf()),
+            extract_path_text: Arc::new(JsonExtractPathUDF::new(true).into_scalar_udf()),
+        }
+    }
+}
+
+impl ExprPlanner for PgJsonPlanner {
+    fn plan_binary_op(
+        &self,
+        expr: RawBinaryExpr,
+        schema: &DFSchema,
+    ) -> Result<PlannerResult<RawBinaryExpr>> {
+        let udf = match expr.op {
+            BinaryOperator::Arrow | BinaryOperator::HashArrow => &self.extract_path,
+            BinaryOperator::LongArrow | BinaryOperator::HashLongArrow => &self.extract_path_text,
+            _ => return Ok(PlannerResult::Original(expr)),
+        };
+        // parameters of unknown types are left to DataFusion
+        if !expr.left.get_type(schema).is_ok_and(|t| is_text(&t)) {
+            return Ok(PlannerResult::Original(expr));
+        }
+
+        let RawBinaryExpr { op, left, right } = expr;
+        let path = match (op, right) {
+            (
+                BinaryOperator::HashArrow | BinaryOperator::HashLongArrow,
+                Expr::Literal(value, _),
+            ) if value.try_as_str().is_some() => match value.try_as_str().flatten() {
+                Some(path) => parse_text_array(path)?.into_iter().map(lit).collect(),
+                None => vec![lit(ScalarValue::Utf8(None))],
+            },
+            (_, right) => vec![right],
+        };
+        Ok(PlannerResult::Planned(Expr::ScalarFunction(
+            ScalarFunction::new_udf(udf.clone(), iter::once(left).chain(path).collect()),
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use datafusion::prelude::SessionContext;
+
+    use super::*;
+    use crate::pg_catalog::setup_pg_catalog;
+
+    async fn values(ctx: &SessionContext, sql: &str) -> Result<Vec<Option<String>>> {
+        let batches = ctx.sql(sql).await?.collect().await?;
+        let mut values = Vec::new();
+        for batch in batches {
+            let column = datafusion::arrow::compute::cast(batch.column(0), &DataType::Utf8)?;
+            values.extend(
+                column
+                    .as_string::<i32>()
+                    .iter()
+                    .map(|v| v.map(str::to_string)),
+            );
+        }
+        Ok(values)
+    }
+
+    #[test]
+    fn test_parse_text_array() {
+        assert_eq!(
+            parse_text_array(r#"{a, "b c" ,0,"d\"e"}"#).unwrap(),
+            vec!["a", "b c", "0", "d\"e"]
+        );
+        assert!(parse_text_array("{}").unwrap().is_empty());
+        assert!(parse_text_array("a,b").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_json_functions() {
+        let ctx = SessionContext::new();
+        setup_pg_catalog(&ctx, "datafusion").unwrap();
+        ctx.sql(
+            r#"CREATE TABLE docs (doc VARCHAR) AS VALUES
+               ('{"name": "a", "tags": ["x", "y"], "n": {"m": 1.5}}'),
+               ('{"name": null, "tags": []}'),
+               (NULL)"#,
+        )
+        .await
+        .unwrap();
+
+        for (sql, expected) in [
+            (
+                "SELECT doc -> 'name' FROM docs",
+                vec![Some("\"a\""), Some("null"), None],
+            ),
+            (
+                "SELECT doc ->> 'name' FROM docs",
+                vec![Some("a"), None, None],
+            ),
+            (
+                "SELECT doc -> 'tags' -> 0 FROM docs",
+                vec![Some("\"x\""), None, None],
+            ),
+            (
+                "SELECT doc -> 'tags' ->> -1 FROM docs",
+                vec![Some("y"), None, None],
+            ),
+            (
+                "SELECT doc #> '{n,m}' FROM docs",
+                vec![Some("1.5"), None, None],
+            ),
+            (
+                "SELECT doc #>> '{tags,1}' FROM docs",
+                vec![Some("y"), None, None],
+            ),
+            (
+                "SELECT doc -> 'n' FROM docs",
+                vec![Some("{\"m\": 1.5}"), None, None],
+            ),
+            (
+                "SELECT jsonb_extract_path_text(doc, 'tags', '0') FROM docs",
+                vec![Some("x"), None, None],
+            ),
+            (
+                "SELECT unnest(jsonb_array_elements_text(doc -> 'tags')) FROM docs",
+                vec![Some("x"), Some("y")],
+            ),
+            (
+                "SELECT jsonb_build_object('b', 1, 'aa', 'two', 'c', NULL, 'd', true)",
+                vec![Some(r#"{"b": 1, "c": null, "d": true, "aa": "two"}"#)],
+            ),
+            (
+                r#"SELECT value FROM jsonb_array_elements('[1, "a", {"b": [2]}]')"#,
+                vec![Some("1"), Some("\"a\""), Some(r#"{"b": [2]}"#)],
+            ),
+            (
+                r#"SELECT key || '=' || value FROM json_each_text('{"b": 1, "a": "x"}')"#,
+                vec![Some("a=x"), Some("b=1")],
+            ),
+        ] {
+            let expected = expected
+                .into_iter()
+                .map(|v| v.map(str::to_string))
+                .collect::<Vec<_>>();
+            assert_eq!(values(&ctx, sql).await.unwrap(), expected, "{sql}");
+        }
+
+        let error = values(&ctx, "SELECT jsonb_build_object('a')")
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error.find_root(),
+            DataFusionError::Configuration(_)
+        ));
+        let error = values(&ctx, "SELECT '{' -> 'a'").await.unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("invalid input syntax for type json"));
+    }
+}
@@ -0,0 +1,383 @@
+use std::any::Any;
+use std::collections::HashSet;
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use datafusion::arrow::datatypes::SchemaRef;
+use datafusion::catalog::{CatalogProviderList, SchemaProvider, Session};
+use datafusion::datasource::{TableProvider, TableType};
+use datafusion::error::Result;
+use datafusion::logical_expr::{BinaryExpr, Operator, TableProviderFilterPushDown};
+use datafusion::physical_plan::streaming::{PartitionStream, StreamingTableExec};
+use datafusion::physical_plan::ExecutionPlan;
+use datafusion::prelude::Expr;
+use datafusion::scalar::ScalarValue;
+use postgres_types::Oid;
+
+use super::OidRegistry;
+
+/// Names of the key columns of a catalog table, `None` if it has no such
+/// column
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct KeyColumns {
+    /// OID of the relation described by a row, like `pg_class.oid` or
+    /// `pg_attribute.attrelid`
+    pub(crate) oid: Option<&'static str>,
+    /// Name of the relation, like `pg_class.relname`
+    pub(crate) name: Option<&'static str>,
+    /// OID of the namespace of the relation, like `pg_class.relnamespace`
+    pub(crate) namespace: Option<&'static str>,
+}
+
+/// Equality filters on key columns of a catalog table
+///
+/// Catalog tables use it to skip relations, and avoid resolving their
+/// `TableProvider`, when a query only asks for some of them, e.g.
+/// `WHERE attrelid = 16385`. A `None` field does not restrict the rows.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct KeyFilter {
+    pub(crate) oids: Option<HashSet<i32>>,
+    pub(crate) names: Option<HashSet<String>>,
+    pub(crate) namespaces: Option<HashSet<i32>>,
+}
+
+/// A table the key filter of a scan may match
+pub(crate) struct FilteredTable {
+    pub(crate) catalog_name: String,
+    pub(crate) schema_name: String,
+    pub(crate) table_name: String,
+    pub(crate) schema_oid: Oid,
+    pub(crate) table_oid: Oid,
+    pub(crate) schema: Arc<dyn SchemaProvider>,
+}
+
+enum KeyColumn {
+    Oid,
+    Name,
+    Namespace,
+}
+
+impl KeyFilter {
+    /// Extract the filter from conjunctive filters of a scan
+    pub(crate) fn from_exprs(filters: &[Expr], columns: &KeyColumns) -> KeyFilter {
+        let mut key_filter = KeyFilter::default();
+        for filter in filters {
+            if let Some((column, values)) = Self::key_values(filter, columns) {
+                key_filter.restrict(column, values);
+            }
+        }
+        key_filter
+    }
+
+    pub(crate) fn matches_name(&self, name: &str) -> bool {
+        self.names.as_ref().is_none_or(|names| names.contains(name))
+    }
+
+    pub(crate) fn matches_namespace(&self, namespace: i32) -> bool {
+        self.namespaces
+            .as_ref()
+            .is_none_or(|namespaces| namespaces.contains(&namespace))
+    }
+
+    /// Tables of all catalogs matching the filter
+    ///
+    /// Tables filtered by OID are looked up in the registry and tables
+    /// filtered by name are checked with `table_exist`, so only schemas
+    /// without such filters have all their tables listed.
+    pub(crate) fn tables(
+        &self,
+        catalog_list: &dyn CatalogProviderList,
+        oid_registry: &OidRegistry,
+    ) -> Vec<FilteredTable> {
+        let mut tables = Vec::new();
+
+        if let Some(oids) = &self.oids {
+            let mut oids = oids.iter().copied().collect::<Vec<_>>();
+            oids.sort_unstable();
+            for table_oid in oids {
+                let Some((catalog_name, schema_name, table_name)) =
+                    oid_registry.table_name(table_oid as Oid)
+                else {
+                    continue;
+                };
+                let Some(schema) = catalog_list
+                    .catalog(&catalog_name)
+                    .and_then(|catalog| catalog.schema(&schema_name))
+                else {
+                    continue;
+                };
+                let schema_oid = oid_registry.schema_oid(&catalog_name, &schema_name);
+                if self.matches_name(&table_name)
+                    && self.matches_namespace(schema_oid as i32)
+                    && schema.table_exist(&table_name)
+                {
+                    tables.push(FilteredTable {
+                        catalog_name,
+                        schema_name,
+                        table_name,
+                        schema_oid,
+                        table_oid: table_oid as Oid,
+                        schema,
+                    });
+                }
+            }
+            return tables;
+        }
+
+        for catalog_name in catalog_list.catalog_names() {
+            let Some(catalog) = catalog_list.catalog(&catalog_name) else {
+                continue;
+            };
+            for schema_name in catalog.schema_names() {
+                let schema_oid = oid_registry.schema_oid(&catalog_name, &schema_name);
+                if !self.matches_namespace(schema_oid as i32) {
+                    continue;
+                }
+                let Some(schema) = catalog.schema(&schema_name) else {
+                    continue;
+                };
+
+                let table_names = match &self.names {
+                    Some(names) => {
+                        let mut names = names
+                            .iter()
+                            .filter(|name| schema.table_exist(name))
+                            .cloned()
+                            .collect::<Vec<_>>();
+                        names.sort_unstable();
+                        names
+                    }
+                    None => schema.table_names(),
+                };
+                for table_name in table_names {
+                    let table_oid =
+                        oid_registry.table_oid(&catalog_name, &schema_name, &table_name);
+                    tables.push(FilteredTable {
+                        catalog_name: catalog_name.clone(),
+                        schema_name: schema_name.clone(),
+                        table_name,
+                        schema_oid,
+                        table_oid,
+                        schema: schema.clone(),
+                    });
+                }
+            }
+        }
+        tables
+    }
+
+    fn restrict(&mut self, column: KeyColumn, values: Vec<ScalarValue>) {
+        fn intersect<T: Eq + std::hash::Hash + Clone>(
+            current: &mut Option<HashSet<T>>,
+            values: impl Iterator<Item = T>,
+        ) {
+            let values = values.collect::<HashSet<_>>();
+            *current = Some(match current.take() {
+                Some(current) => current.intersection(&values).cloned().collect(),
+                None => values,
+            });
+        }
+
+        match column {
+            KeyColumn::Oid => intersect(&mut self.oids, values.iter().filter_map(as_i32)),
+            KeyColumn::Name => intersect(&mut self.names, values.iter().filter_map(as_string)),
+            KeyColumn::Namespace => {
+                intersect(&mut self.namespaces, values.iter().filter_map(as_i32))
+            }
+        }
+    }
+
+    fn key_column(expr: &Expr, columns: &KeyColumns) -> Option<KeyColumn> {
+        let name = match expr {
+            Expr::Column(column) => column.name.as_str(),
+            Expr::Cast(cast) => return Self::key_column(&cast.expr, columns),
+            Expr::TryCast(cast) => return Self::key_column(&cast.expr, columns),
+            _ => return None,
+        };
+
+        if columns.oid == Some(name) {
+            Some(KeyColumn::Oid)
+        } else if columns.name == Some(name) {
+            Some(KeyColumn::Name)
+        } else if columns.namespace == Some(name) {
+            Some(KeyColumn::Namespace)
+        } else {
+            None
+        }
+    }
+
+    /// Key column and values of an equality or `IN` filter, `None` if some
+    /// value doesn't have the type of the key
+    fn key_values(filter: &Expr, columns: &KeyColumns) -> Option<(KeyColumn, Vec<ScalarValue>)> {
+        let (column, values) = Self::key_literals(filter, columns)?;
+        let valid = values.iter().all(|value| match column {
+            KeyColumn::Oid | KeyColumn::Namespace => as_i32(value).is_some(),
+            KeyColumn::Name => as_string(value).is_some(),
+        });
+        valid.then_some((column, values))
+    }
+
+    fn key_literals(filter: &Expr, columns: &KeyColumns) -> Option<(KeyColumn, Vec<ScalarValue>)> {
+        match filter {
+            Expr::BinaryExpr(BinaryExpr {
+                left,
+                op: Operator::Eq,
+                right,
+            }) => match (left.as_ref(), right.as_ref()) {
+                (column, Expr::Literal(value, _)) | (Expr::Literal(value, _), column) => {
+                    Some((Self::key_column(column, columns)?, vec![value.clone()]))
+                }
+                _ => None,
+            },
+            Expr::InList(in_list) if !in_list.negated => {
+                let column = Self::key_column(&in_list.expr, columns)?;
+                let values = in_list
+                    .list
+                    .iter()
+                    .map(|expr| match expr {
+                        Expr::Literal(value, _) => Some(value.clone()),
+                        _ => None,
+                    })
+                    .collect::<Option<Vec<_>>>()?;
+                Some((column, values))
+            }
+            _ => None,
+        }
+    }
+}
+
+fn as_i32(value: &ScalarValue) -> Option<i32> {
+    match value {
+        ScalarValue::Int8(Some(v)) => Some(*v as i32),
+        ScalarValue::Int16(Some(v)) => Some(*v as i32),
+        ScalarValue::Int32(Some(v)) => Some(*v),
+        ScalarValue::Int64(Some(v)) => i32::try_from(*v).ok(),
+        ScalarValue::UInt8(Some(v)) => Some(*v as i32),
+        ScalarValue::UInt16(Some(v)) => Some(*v as i32),
+        ScalarValue::UInt32(Some(v)) => Some(*v as i32),
+        ScalarValue::UInt64(Some(v)) => i32::try_from(*v).ok(),
+        _ => None,
+    }
+}
+
+fn as_string(value: &ScalarValue) -> Option<String> {
+    match value {
+        ScalarValue::Utf8(Some(v))
+        | ScalarValue::Utf8View(Some(v))
+        | ScalarValue::LargeUtf8(Some(v)) => Some(v.clone()),
+        _ => None,
+    }
+}
+
+/// A catalog table generating only the rows matching a key filter
+pub(crate) trait KeyFilteredTable: PartitionStream + Clone + 'static {
+    const KEY_COLUMNS: KeyColumns;
+
+    fn with_key_filter(&self, key_filter: KeyFilter) -> Self;
+}
+
+/// Table provider pushing key filters down into a catalog table
+#[derive(Debug)]
+pub(crate) struct KeyFilteredTableProvider<T> {
+    table: T,
+}
+
+impl<T: KeyFilteredTable> KeyFilteredTableProvider<T> {
+    pub(crate) fn new(table: T) -> Self {
+        Self { table }
+    }
+}
+
+#[async_trait]
+impl<T: KeyFilteredTable> TableProvider for KeyFilteredTableProvider<T> {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.table.schema().clone()
+    }
+
+    fn table_type(&self) -> TableType {
+        TableType::View
+    }
+
+    fn supports_filters_pushdown(
+        &self,
+        filters: &[&Expr],
+    ) -> Result<Vec<TableProviderFilterPushDown>> {
+        Ok(filters
+            .iter()
+            .map(|filter| {
+                if KeyFilter::key_values(filter, &T::KEY_COLUMNS).is_some() {
+                    // rows are still filtered by datafusion, the table only
+                    // uses the filter to skip relations
+                    TableProviderFilterPushDown::Inexact
+                } else {
+                    TableProviderFilterPushDown::Unsupported
+                }
+            })
+            .collect())
+    }
+
+    async fn scan(
+        &self,
+        _state: &dyn Session,
+        projection: Option<&Vec<usize>>,
+        filters: &[Expr],
+        limit: Option<usize>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        let key_filter = KeyFilter::from_exprs(filters, &T::KEY_COLUMNS);
+        let table = self.table.with_key_filter(key_filter);
+        Ok(Arc::new(StreamingTableExec::try_new(
+            self.schema(),
+            vec![Arc::new(table)],
+            projection,
+            None,
+            false,
+            limit,
+        )?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datafusion::prelude::{col, lit};
+
+    const COLUMNS: KeyColumns = KeyColumns {
+        oid: Some("oid"),
+        name: Some("relname"),
+        namespace: Some("relnamespace"),
+    };
+
+    #[test]
+    fn test_key_filter_from_exprs() {
+        let filter = KeyFilter::from_exprs(
+            &[
+                col("oid").in_list(vec![lit(16385), lit(16386i64)], false),
+                lit(16386).eq(col("oid")),
+                col("relname").eq(lit("users")),
+                // not a key column
+                col("relkind").eq(lit("r")),
+                // not an equality
+                col("relnamespace").gt(lit(1)),
+                // not an oid
+                col("oid").eq(lit("users")),
+            ],
+            &COLUMNS,
+        );
+        assert_eq!(
+            filter,
+            KeyFilter {
+                oids: Some(HashSet::from([16386])),
+                names: Some(HashSet::from(["users".to_string()])),
+                namespaces: None,
+            }
+        );
+        assert!(filter.matches_namespace(99));
+
+        assert_eq!(KeyFilter::from_exprs(&[], &COLUMNS), KeyFilter::default());
+    }
+}
@@ -1,5 +1,3 @@
-use std::collections::HashMap;
-use std::sync::atomic::{AtomicU32, Ordering};
 use std::sync::Arc;
 
 use datafusion::arrow::array::{
@@ -11,33 +9,32 @@ use datafusion::error::Result;
 use datafusion::execution::{SendableRecordBatchStream, TaskContext};
 use datafusion::physical_plan::stream::RecordBatchStreamAdapter;
 use datafusion::physical_plan::streaming::PartitionStream;
-use postgres_types::Oid;
-use tokio::sync::RwLock;
 
-use super::OidCacheKey;
+use super::key_filter::{KeyColumns, KeyFilter, KeyFilteredTable};
+use super::vector_types::oid_field;
+use super::{CatalogVisibility, OidRegistry};
 
 #[derive(Debug, Clone)]
 pub(crate) struct PgAttributeTable {
     schema: SchemaRef,
     catalog_list: Arc<dyn CatalogProviderList>,
-    oid_counter: Arc<AtomicU32>,
-    oid_cache: Arc<RwLock<HashMap<OidCacheKey, Oid>>>,
+    oid_registry: Arc<OidRegistry>,
+    key_filter: KeyFilter,
 }
 
 impl PgAttributeTable {
     pub(crate) fn new(
         catalog_list: Arc<dyn CatalogProviderList>,
-        oid_counter: Arc<AtomicU32>,
-        oid_cache: Arc<RwLock<HashMap<OidCacheKey, Oid>>>,
+        oid_registry: Arc<OidRegistry>,
     ) -> Self {
         // Define the schema for pg_attribute
         // This matches PostgreSQL's pg_attribute table columns
         let schema = Arc::new(Schema::new(vec![
-            Field::new("attrelid", DataType::Int32, false), // OID of the relation this column belongs to
-            Field::new("attname", DataType::Utf8, false),   // Column name
-            Field::new("atttypid", DataType::Int32, false), // OID of the column data type
+            oid_field("attrelid", false), // OID of the relation this column belongs to
+            Field::new("attname", DataType::Utf8, false), // Column name
+            oid_field("atttypid", false), // OID of the column data type
             Field::new("attstattarget", DataType::Int32, false), // Statistics target
-            Field::new("attlen", DataType::Int16, false),   // Length of the type
+            Field::new("attlen", DataType::Int16, false), // Length of the type
             Field::new("attnum", DataType::Int16, false), // Column number (positive for regular columns)
             Field::new("attndims", DataType::Int32, false), // Number of dimensions for array types
             Field::new("attcacheoff", DataType::Int32, false), // Cache offset
@@ -54,7 +51,7 @@ impl PgAttributeTable {
             Field::new("attisdropped", DataType::Boolean, false), // True if column has been dropped
             Field::new("attislocal", DataType::Boolean, false), // True if column is local to this relation
             Field::new("attinhcount", DataType::Int32, false), // Number of direct inheritance ancestors
-            Field::new("attcollation", DataType::Int32, false), // OID of collation
+            oid_field("attcollation", false),                  // OID of collation
             Field::new("attacl", DataType::Utf8, true),        // Access privileges
             Field::new("attoptions", DataType::Utf8, true),    // Attribute-level options
             Field::new("attfdwoptions", DataType::Utf8, true), // Foreign data wrapper options
@@ -64,13 +61,16 @@ impl PgAttributeTable {
         Self {
             schema,
             catalog_list,
-            oid_counter,
-            oid_cache,
+            oid_registry,
+            key_filter: KeyFilter::default(),
         }
     }
 
     /// Generate record batches based on the current state of the catalog
-    async fn get_data(this: Self) -> Result<RecordBatch> {
+    async fn get_data(
+        this: Self,
+        visibility: Option<Arc<CatalogVisibility>>,
+    ) -> Result<RecordBatch> {
         // Vectors to store column data
         let mut attrelids = Vec::new();
         let mut attnames = Vec::new();
@@ -99,74 +99,64 @@ impl PgAttributeTable {
         let mut attfdwoptions: Vec<Option<String>> = Vec::new();
         let mut attmissingvals: Vec<Option<String>> = Vec::new();
 
-        let mut oid_cache = this.oid_cache.write().await;
-        // Every time when call pg_catalog we generate a new cache and drop the
-        // original one in case that schemas or tables were dropped.
-        let mut swap_cache = HashMap::new();
-
-        for catalog_name in this.catalog_list.catalog_names() {
-            if let Some(catalog) = this.catalog_list.catalog(&catalog_name) {
-                for schema_name in catalog.schema_names() {
-                    if let Some(schema_provider) = catalog.schema(&schema_name) {
-                        // Process all tables in this schema
-                        for table_name in schema_provider.table_names() {
-                            let cache_key = OidCacheKey::Table(
-                                catalog_name.clone(),
-                                schema_name.clone(),
-                                table_name.clone(),
-                            );
-                            let table_oid = if let Some(oid) = oid_cache.get(&cache_key) {
-                                *oid
-                            } else {
-                                this.oid_counter.fetch_add(1, Ordering::Relaxed)
-                            };
-                            swap_cache.insert(cache_key, table_oid);
-
-                            if let Some(table) = schema_provider.table(&table_name).await? {
-                                let table_schema = table.schema();
-
-                                // Add column entries for this table
-                                for (column_idx, field) in table_schema.fields().iter().enumerate()
-                                {
-                                    let attnum = (column_idx + 1) as i16; // PostgreSQL column numbers start at 1
-                                    let (pg_type_oid, type_len, by_val, align, storage) =
-                                        Self::datafusion_to_pg_type(field.data_type());
-
-                                    attrelids.push(table_oid as i32);
-                                    attnames.push(field.name().clone());
-                                    atttypids.push(pg_type_oid);
-                                    attstattargets.push(-1); // Default statistics target
-                                    attlens.push(type_len);
-                                    attnums.push(attnum);
-                                    attndimss.push(0); // No array support for now
-                                    attcacheoffs.push(-1); // Not cached
-                                    atttymods.push(-1); // No type modifiers
-                                    attbyvals.push(by_val);
-                                    attaligns.push(align.to_string());
-                                    attstorages.push(storage.to_string());
-                                    attcompressions.push(None); // No compression
-                                    attnotnulls.push(!field.is_nullable());
-                                    atthasdefs.push(false); // No default values
-                                    atthasmissings.push(false); // No missing values
-                                    attidentitys.push("".to_string()); // No identity columns
-                                    attgenerateds.push("".to_string()); // No generated columns
-                                    attisdroppeds.push(false); // Not dropped
-                                    attislocals.push(true); // Local to this relation
-                                    attinhcounts.push(0); // No inheritance
-                                    attcollations.push(0); // Default collation
-                                    attacls.push(None); // No ACLs
-                                    attoptions.push(None); // No options
-                                    attfdwoptions.push(None); // No FDW options
-                                    attmissingvals.push(None); // No missing values
-                                }
-                            }
-                        }
-                    }
+        let catalog_list = match &visibility {
+            Some(visibility) => visibility.catalog_list(&this.catalog_list),
+            None => this.catalog_list.clone(),
+        };
+        // only resolve the tables the query asks for
+        for table in this
+            .key_filter
+            .tables(catalog_list.as_ref(), &this.oid_registry)
+        {
+            if let Some(visibility) = &visibility {
+                if !visibility
+                    .is_table_visible(&table.schema_name, &table.table_name)
+                    .await
+                {
+                    continue;
                 }
             }
-        }
 
-        *oid_cache = swap_cache;
+            let Some(relation) = this.oid_registry.relation(&table).await? else {
+                continue;
+            };
+            let table_oid = table.table_oid;
+            let table_schema = relation.schema;
+
+            // Add column entries for this table
+            for (column_idx, field) in table_schema.fields().iter().enumerate() {
+                let attnum = (column_idx + 1) as i16; // PostgreSQL column numbers start at 1
+                let (pg_type_oid, type_len, by_val, align, storage) =
+                    Self::datafusion_to_pg_type(field.data_type());
+
+                attrelids.push(table_oid as i32);
+                attnames.push(field.name().clone());
+                atttypids.push(pg_type_oid);
+                attstattargets.push(-1); // Default statistics target
+                attlens.push(type_len);
+                attnums.push(attnum);
+                attndimss.push(0); // No array support for now
+                attcacheoffs.push(-1); // Not cached
+                atttymods.push(-1); // No type modifiers
+                attbyvals.push(by_val);
+                attaligns.push(align.to_string());
+                attstorages.push(storage.to_string());
+                attcompressions.push(None); // No compression
+                attnotnulls.push(!field.is_nullable());
+                atthasdefs.push(false); // No default values
+                atthasmissings.push(false); // No missing values
+                attidentitys.push("".to_string()); // No identity columns
+                attgenerateds.push("".to_string()); // No generated columns
+                attisdroppeds.push(false); // Not dropped
+                attislocals.push(true); // Local to this relation
+                attinhcounts.push(0); // No inheritance
+                attcollations.push(0); // Default collation
+                attacls.push(None); // No ACLs
+                attoptions.push(None); // No options
+                attfdwoptions.push(None); // No FDW options
+                attmissingvals.push(None); // No missing values
+            }
+        }
 
         // Create Arrow arrays from the collected data
         let arrays: Vec<ArrayRef> = vec![
@@ -204,7 +194,9 @@ impl PgAttributeTable {
     }
 
     /// Map DataFusion data types to PostgreSQL type information
-    fn datafusion_to_pg_type(data_type: &DataType) -> (i32, i16, bool, &'static str, &'static str) {
+    pub(crate) fn datafusion_to_pg_type(
+        data_type: &DataType,
+    ) -> (i32, i16, bool, &'static str, &'static str) {
         match data_type {
             DataType::Boolean => (16, 1, true, "c", "p"),    // bool
             DataType::Int8 => (18, 1, true, "c", "p"),       // char
@@ -238,11 +230,27 @@ impl PartitionStream for PgAttributeTable {
         &self.schema
     }
 
-    fn execute(&self, _ctx: Arc<TaskContext>) -> SendableRecordBatchStream {
+    fn execute(&self, ctx: Arc<TaskContext>) -> SendableRecordBatchStream {
         let this = self.clone();
+        let visibility = CatalogVisibility::from_task_context(&ctx);
         Box::pin(RecordBatchStreamAdapter::new(
             this.schema.clone(),
-            futures::stream::once(async move { Self::get_data(this).await }),
+            futures::stream::once(async move { Self::get_data(this, visibility).await }),
         ))
     }
 }
+
+impl KeyFilteredTable for PgAttributeTable {
+    const KEY_COLUMNS: KeyColumns = KeyColumns {
+        oid: Some("attrelid"),
+        name: None,
+        namespace: None,
+    };
+
+    fn with_key_filter(&self, key_filter: KeyFilter) -> Self {
+        Self {
+            key_filter,
+            ..self.clone()
+        }
+    }
+}
@@ -1,5 +1,3 @@
-use std::collections::HashMap;
-use std::sync::atomic::{AtomicU32, Ordering};
 use std::sync::Arc;
 
 use datafusion::arrow::array::{
@@ -11,41 +9,41 @@ use datafusion::error::Result;
 use datafusion::execution::{SendableRecordBatchStream, TaskContext};
 use datafusion::physical_plan::stream::RecordBatchStreamAdapter;
 use datafusion::physical_plan::streaming::PartitionStream;
-use postgres_types::Oid;
-use tokio::sync::RwLock;
 
-use super::{get_table_type_with_name, OidCacheKey};
+use super::key_filter::{KeyColumns, KeyFilter, KeyFilteredTable};
+use super::vector_types::oid_field;
+use super::{CatalogVisibility, OidRegistry};
+use crate::analyze::StatisticsRegistry;
 
 #[derive(Debug, Clone)]
 pub(crate) struct PgClassTable {
     schema: SchemaRef,
     catalog_list: Arc<dyn CatalogProviderList>,
-    oid_counter: Arc<AtomicU32>,
-    oid_cache: Arc<RwLock<HashMap<OidCacheKey, Oid>>>,
+    oid_registry: Arc<OidRegistry>,
+    key_filter: KeyFilter,
 }
 
 impl PgClassTable {
     pub(crate) fn new(
         catalog_list: Arc<dyn CatalogProviderList>,
-        oid_counter: Arc<AtomicU32>,
-        oid_cache: Arc<RwLock<HashMap<OidCacheKey, Oid>>>,
+        oid_registry: Arc<OidRegistry>,
     ) -> PgClassTable {
         // Define the schema for pg_class
         // This matches key columns from PostgreSQL's pg_class
         let schema = Arc::new(Schema::new(vec![
-            Field::new("oid", DataType::Int32, false), // Object identifier
+            oid_field("oid", false),                                // Object identifier
             Field::new("relname", DataType::Utf8, false), // Name of the table, index, view, etc.
-            Field::new("relnamespace", DataType::Int32, false), // OID of the namespace that contains this relation
-            Field::new("reltype", DataType::Int32, false), // OID of the data type (composite type) this table describes
-            Field::new("reloftype", DataType::Int32, true), // OID of the composite type for typed table, 0 otherwise
-            Field::new("relowner", DataType::Int32, false), // Owner of the relation
-            Field::new("relam", DataType::Int32, false), // If this is an index, the access method used
-            Field::new("relfilenode", DataType::Int32, false), // Name of the on-disk file of this relation
-            Field::new("reltablespace", DataType::Int32, false), // Tablespace OID for this relation
+            oid_field("relnamespace", false), // OID of the namespace that contains this relation
+            oid_field("reltype", false), // OID of the data type (composite type) this table describes
+            oid_field("reloftype", true), // OID of the composite type for typed table, 0 otherwise
+            oid_field("relowner", false), // Owner of the relation
+            oid_field("relam", false),   // If this is an index, the access method used
+            oid_field("relfilenode", false), // Name of the on-disk file of this relation
+            oid_field("reltablespace", false), // Tablespace OID for this relation
             Field::new("relpages", DataType::Int32, false), // Size of the on-disk representation in pages
             Field::new("reltuples", DataType::Float64, false), // Number of tuples
             Field::new("relallvisible", DataType::Int32, false), // Number of all-visible pages
-            Field::new("reltoastrelid", DataType::Int32, false), // OID of the TOAST table
+            oid_field("reltoastrelid", false),              // OID of the TOAST table
             Field::new("relhasindex", DataType::Boolean, false), // True if this is a table and it has (or recently had) any indexes
             Field::new("relisshared", DataType::Boolean, false), // True if this table is shared across all databases
             Field::new("relpersistence", DataType::Utf8, false), // p=permanent table, u=unlogged table, t=temporary table
@@ -60,7 +58,7 @@ impl PgClassTable {
             Field::new("relispopulated", DataType::Boolean, false), // True if relation is populated (not true for some materialized views)
             Field::new("relreplident", DataType::Utf8, false), // Columns used to form "replica identity" for rows
             Field::new("relispartition", DataType::Boolean, false), // True if table is a partition
-            Field::new("relrewrite", DataType::Int32, true), // OID of a rule that rewrites this relation
+            oid_field("relrewrite", true), // OID of a rule that rewrites this relation
             Field::new("relfrozenxid", DataType::Int32, false), // All transaction IDs before this have been replaced with a permanent ("frozen") transaction ID
             Field::new("relminmxid", DataType::Int32, false), // All Multixact IDs before this have been replaced with a transaction ID
             Field::new("relpartbound", DataType::Utf8, true),
@@ -69,13 +67,17 @@ impl PgClassTable {
         Self {
             schema,
             catalog_list,
-            oid_counter,
-            oid_cache,
+            oid_registry,
+            key_filter: KeyFilter::default(),
         }
     }
 
     /// Generate record batches based on the current state of the catalog
-    async fn get_data(this: PgClassTable) -> Result<RecordBatch> {
+    async fn get_data(
+        this: PgClassTable,
+        visibility: Option<Arc<CatalogVisibility>>,
+        statistics: Option<Arc<StatisticsRegistry>>,
+    ) -> Result<RecordBatch> {
         // Vectors to store column data
         let mut oids = Vec::new();
         let mut relnames = Vec::new();
@@ -109,99 +111,78 @@ impl PgClassTable {
         let mut relminmxids = Vec::new();
         let mut relpartbound = Vec::new();
 
-        let mut oid_cache = this.oid_cache.write().await;
-        // Every time when call pg_catalog we generate a new cache and drop the
-        // original one in case that schemas or tables were dropped.
-        let mut swap_cache = HashMap::new();
+        let catalog_list = match &visibility {
+            Some(visibility) => visibility.catalog_list(&this.catalog_list),
+            None => this.catalog_list.clone(),
+        };
+        // only resolve the tables the query asks for
+        for table in this
+            .key_filter
+            .tables(catalog_list.as_ref(), &this.oid_registry)
+        {
+            // Hidden tables keep their oid so it stays stable across users
+            if let Some(visibility) = &visibility {
+                if !visibility
+                    .is_table_visible(&table.schema_name, &table.table_name)
+                    .await
+                {
+                    continue;
+                }
+            }
 
-        // Iterate through all catalogs and schemas
-        for catalog_name in this.catalog_list.catalog_names() {
-            let cache_key = OidCacheKey::Catalog(catalog_name.clone());
-            let catalog_oid = if let Some(oid) = oid_cache.get(&cache_key) {
-                *oid
-            } else {
-                this.oid_counter.fetch_add(1, Ordering::Relaxed)
+            let Some(relation) = this.oid_registry.relation(&table).await? else {
+                continue;
             };
-            swap_cache.insert(cache_key, catalog_oid);
-
-            if let Some(catalog) = this.catalog_list.catalog(&catalog_name) {
-                for schema_name in catalog.schema_names() {
-                    if let Some(schema) = catalog.schema(&schema_name) {
-                        let cache_key =
-                            OidCacheKey::Schema(catalog_name.clone(), schema_name.clone());
-                        let schema_oid = if let Some(oid) = oid_cache.get(&cache_key) {
-                            *oid
-                        } else {
-                            this.oid_counter.fetch_add(1, Ordering::Relaxed)
-                        };
-                        swap_cache.insert(cache_key, schema_oid);
-
-                        // Add an entry for the schema itself (as a namespace)
-                        // (In a full implementation, this would go in pg_namespace)
+            let table_oid = table.table_oid;
+            let schema_oid = table.schema_oid;
+            let table_name = table.table_name;
+            let table_type = relation.relkind;
+            let column_count = relation.schema.fields().len() as i16;
 
-                        // Now process all tables in this schema
-                        for table_name in schema.table_names() {
-                            let cache_key = OidCacheKey::Table(
-                                catalog_name.clone(),
-                                schema_name.clone(),
-                                table_name.clone(),
-                            );
-                            let table_oid = if let Some(oid) = oid_cache.get(&cache_key) {
-                                *oid
-                            } else {
-                                this.oid_counter.fetch_add(1, Ordering::Relaxed)
-                            };
-                            swap_cache.insert(cache_key, table_oid);
-
-                            if let Some(table) = schema.table(&table_name).await? {
-                                // Determine the correct table type based on the table provider and context
-                                let table_type =
-                                    get_table_type_with_name(&table, &table_name, &schema_name);
-
-                                // Get column count from schema
-                                let column_count = table.schema().fields().len() as i16;
-
-                                // Add table entry
-                                oids.push(table_oid as i32);
-                                relnames.push(table_name.clone());
-                                relnamespaces.push(schema_oid as i32);
-                                reltypes.push(0); // Simplified: we're not tracking data types
-                                reloftypes.push(None);
-                                relowners.push(0); // Simplified: no owner tracking
-                                relams.push(0); // Default access method
-                                relfilenodes.push(table_oid as i32); // Use OID as filenode
-                                reltablespaces.push(0); // Default tablespace
-                                relpages.push(1); // Default page count
-                                reltuples.push(0.0); // No row count stats
-                                relallvisibles.push(0);
-                                reltoastrelids.push(0);
-                                relhasindexes.push(false);
-                                relisshareds.push(false);
-                                relpersistences.push("p".to_string()); // Permanent
-                                relkinds.push(table_type.to_string());
-                                relnattses.push(column_count);
-                                relcheckses.push(0);
-                                relhasruleses.push(false);
-                                relhastriggersses.push(false);
-                                relhassubclasses.push(false);
-                                relrowsecurities.push(false);
-                                relforcerowsecurities.push(false);
-                                relispopulateds.push(true);
-                                relreplidents.push("d".to_string()); // Default
-                                relispartitions.push(false);
-                                relrewrites.push(None);
-                                relfrozenxids.push(0);
-                                relminmxids.push(0);
-                                relpartbound.push("".to_string());
-                            }
-                        }
-                    }
-                }
-            }
+            // Add table entry
+            oids.push(table_oid as i32);
+            relnames.push(table_name.clone());
+            relnamespaces.push(schema_oid as i32);
+            reltypes.push(0); // Simplified: we're not tracking data types
+            reloftypes.push(None);
+            relowners.push(0); // Simplified: no owner tracking
+            relams.push(0); // Default access method
+            relfilenodes.push(table_oid as i32); // Use OID as filenode
+            reltablespaces.push(0); // Default tablespace
+            relpages.push(1); // Default page count
+                              // row count of the last ANALYZE, 0 before any
+            let row_count = statistics.as_ref().and_then(|statistics| {
+                let (catalog, schema, table) = this.oid_registry.table_name(table_oid)?;
+                statistics.table(&catalog, &schema, &table)
+            });
+            reltuples.push(row_count.map_or(0.0, |stats| stats.row_count as f64));
+            relallvisibles.push(0);
+            reltoastrelids.push(0);
+            relhasindexes.push(false);
+            relisshareds.push(false);
+            // permanent, or temporary in a pg_temp_N namespace
+            relpersistences.push(if relation.table_type == "LOCAL TEMPORARY" {
+                "t".to_string()
+            } else {
+                "p".to_string()
+            });
+            relkinds.push(table_type.to_string());
+            relnattses.push(column_count);
+            relcheckses.push(0);
+            relhasruleses.push(false);
+            relhastriggersses.push(false);
+            relhassubclasses.push(false);
+            relrowsecurities.push(false);
+            relforcerowsecurities.push(false);
+            relispopulateds.push(true);
+            relreplidents.push("d".to_string()); // Default
+            relispartitions.push(false);
+            relrewrites.push(None);
+            relfrozenxids.push(0);
+            relminmxids.push(0);
+            relpartbound.push("".to_string());
         }
 
-        *oid_cache = swap_cache;
-
         // Create Arrow arrays from the collected data
         let arrays: Vec<ArrayRef> = vec![
             Arc::new(Int32Array::from(oids)),
@@ -249,11 +230,30 @@ impl PartitionStream for PgClassTable {
         &self.schema
     }
 
-    fn execute(&self, _ctx: Arc<TaskContext>) -> SendableRecordBatchStream {
+    fn execute(&self, ctx: Arc<TaskContext>) -> SendableRecordBatchStream {
         let this = self.clone();
+        let visibility = CatalogVisibility::from_task_context(&ctx);
+        let statistics = ctx.session_config().get_extension::<StatisticsRegistry>();
         Box::pin(RecordBatchStreamAdapter::new(
             this.schema.clone(),
-            futures::stream::once(async move { PgClassTable::get_data(this).await }),
+            futures::stream::once(async move {
+                PgClassTable::get_data(this, visibility, statistics).await
+            }),
         ))
     }
 }
+
+impl KeyFilteredTable for PgClassTable {
+    const KEY_COLUMNS: KeyColumns = KeyColumns {
+        oid: Some("oid"),
+        name: Some("relname"),
+        namespace: Some("relnamespace"),
+    };
+
+    fn with_key_filter(&self, key_filter: KeyFilter) -> Self {
+        Self {
+            key_filter,
+            ..self.clone()
+        }
+    }
+}
@@ -0,0 +1,299 @@
+use std::sync::Arc;
+
+use datafusion::arrow::array::{
+    new_null_array, ArrayRef, BooleanArray, Int16Array, Int32Array, ListArray, RecordBatch,
+    StringArray,
+};
+use datafusion::arrow::datatypes::{DataType, Field, Int16Type, Schema, SchemaRef};
+use datafusion::catalog::CatalogProviderList;
+use datafusion::error::Result;
+use datafusion::execution::{SendableRecordBatchStream, TaskContext};
+use datafusion::physical_plan::stream::RecordBatchStreamAdapter;
+use datafusion::physical_plan::streaming::PartitionStream;
+
+use super::information_schema::table_constraints::table_constraints;
+use super::key_filter::{FilteredTable, KeyColumns, KeyFilter, KeyFilteredTable};
+use super::vector_types::oid_field;
+use super::{CatalogVisibility, OidRegistry, REFERENCES_METADATA_KEY};
+
+/// A constraint of a table, a row of `pg_constraint`
+struct ConstraintRow {
+    name: String,
+    contype: &'static str,
+    conkey: Vec<i16>,
+    confrelid: i32,
+    confkey: Option<Vec<i16>>,
+}
+
+/// The table and column of a `pg_references` field metadata, like
+/// `sales.customers(id)`
+fn parse_references(references: &str) -> Option<(Option<&str>, &str, &str)> {
+    let (table, column) = references.trim().strip_suffix(')')?.split_once('(')?;
+    let (schema, table) = match table.rsplit_once('.') {
+        Some((schema, table)) => (Some(schema.trim()), table.trim()),
+        None => (None, table.trim()),
+    };
+    Some((schema, table, column.trim()))
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct PgConstraintTable {
+    schema: SchemaRef,
+    catalog_list: Arc<dyn CatalogProviderList>,
+    oid_registry: Arc<OidRegistry>,
+    key_filter: KeyFilter,
+}
+
+impl PgConstraintTable {
+    pub(crate) fn new(
+        catalog_list: Arc<dyn CatalogProviderList>,
+        oid_registry: Arc<OidRegistry>,
+    ) -> Self {
+        let int2_array = || DataType::new_list(DataType::Int16, true);
+        let oid_array = || DataType::new_list(DataType::UInt32, true);
+        // This matches PostgreSQL's pg_constraint table columns
+        let schema = Arc::new(Schema::new(vec![
+            oid_field("oid", false),                               // Object identifier
+            Field::new("conname", DataType::Utf8, false),          // Constraint name
+            oid_field("connamespace", false), // OID of the namespace containing the constraint
+            Field::new("contype", DataType::Utf8, false), // p=primary key, u=unique, f=foreign key
+            Field::new("condeferrable", DataType::Boolean, false), // Is the constraint deferrable
+            Field::new("condeferred", DataType::Boolean, false), // Is the constraint deferred by default
+            Field::new("convalidated", DataType::Boolean, false), // Has the constraint been validated
+            oid_field("conrelid", false),                         // Table the constraint is on
+            oid_field("contypid", false), // Domain the constraint is on, 0 for tables
+            oid_field("conindid", false), // Index supporting the constraint
+            oid_field("conparentid", false), // Constraint of the parent partitioned table
+            oid_field("confrelid", false), // Referenced table of a foreign key
+            Field::new("confupdtype", DataType::Utf8, false), // Foreign key update action
+            Field::new("confdeltype", DataType::Utf8, false), // Foreign key deletion action
+            Field::new("confmatchtype", DataType::Utf8, false), // Foreign key match type
+            Field::new("conislocal", DataType::Boolean, false), // Defined locally for the relation
+            Field::new("coninhcount", DataType::Int16, false), // Number of direct inheritance ancestors
+            Field::new("connoinherit", DataType::Boolean, false), // Non-inheritable constraint
+            Field::new("conkey", int2_array(), true),          // Constrained column numbers
+            Field::new("confkey", int2_array(), true), // Referenced column numbers of a foreign key
+            Field::new("conpfeqop", oid_array(), true), // PK = FK equality operators
+            Field::new("conppeqop", oid_array(), true), // PK = PK equality operators
+            Field::new("conffeqop", oid_array(), true), // FK = FK equality operators
+            Field::new("confdelsetcols", int2_array(), true), // Columns set by ON DELETE SET NULL/DEFAULT
+            Field::new("conexclop", oid_array(), true),       // Exclusion operators
+            Field::new("conbin", DataType::Utf8, true),       // Check constraint expression
+        ]));
+
+        Self {
+            schema,
+            catalog_list,
+            oid_registry,
+            key_filter: KeyFilter::default(),
+        }
+    }
+
+    /// The constraints of a table, its primary key and unique constraints
+    /// and the foreign keys of its fields
+    async fn table_constraints(
+        &self,
+        catalog_list: &dyn CatalogProviderList,
+        table: &FilteredTable,
+    ) -> Result<Vec<ConstraintRow>> {
+        let Some(relation) = self.oid_registry.relation(table).await? else {
+            return Ok(vec![]);
+        };
+
+        let attnum =
+            |schema: &Schema, column: &str| schema.index_of(column).ok().map(|idx| idx as i16 + 1);
+        let mut constraints = table_constraints(&table.table_name, &relation)
+            .into_iter()
+            .map(|constraint| ConstraintRow {
+                conkey: constraint
+                    .columns
+                    .iter()
+                    .filter_map(|column| attnum(&relation.schema, column))
+                    .collect(),
+                contype: match constraint.constraint_type {
+                    "PRIMARY KEY" => "p",
+                    _ => "u",
+                },
+                name: constraint.name,
+                confrelid: 0,
+                confkey: None,
+            })
+            .collect::<Vec<_>>();
+
+        for (idx, field) in relation.schema.fields().iter().enumerate() {
+            let Some((schema_name, table_name, column)) = field
+                .metadata()
+                .get(REFERENCES_METADATA_KEY)
+                .and_then(|references| parse_references(references))
+            else {
+                continue;
+            };
+            let schema_name = schema_name.unwrap_or(&table.schema_name);
+            let Some(schema) = catalog_list
+                .catalog(&table.catalog_name)
+                .and_then(|catalog| catalog.schema(schema_name))
+                .filter(|schema| schema.table_exist(table_name))
+            else {
+                continue;
+            };
+            let referenced = FilteredTable {
+                catalog_name: table.catalog_name.clone(),
+                schema_name: schema_name.to_string(),
+                table_name: table_name.to_string(),
+                schema_oid: self
+                    .oid_registry
+                    .schema_oid(&table.catalog_name, schema_name),
+                table_oid: self.oid_registry.table_oid(
+                    &table.catalog_name,
+                    schema_name,
+                    table_name,
+                ),
+                schema,
+            };
+            let Some(referenced_relation) = self.oid_registry.relation(&referenced).await? else {
+                continue;
+            };
+            let Some(confkey) = attnum(&referenced_relation.schema, column) else {
+                continue;
+            };
+            constraints.push(ConstraintRow {
+                name: format!("{}_{}_fkey", table.table_name, field.name()),
+                contype: "f",
+                conkey: vec![idx as i16 + 1],
+                confrelid: referenced.table_oid as i32,
+                confkey: Some(vec![confkey]),
+            });
+        }
+        Ok(constraints)
+    }
+
+    /// Generate record batches based on the current state of the catalog
+    async fn get_data(
+        this: Self,
+        visibility: Option<Arc<CatalogVisibility>>,
+    ) -> Result<RecordBatch> {
+        let mut oids = Vec::new();
+        let mut connames = Vec::new();
+        let mut connamespaces = Vec::new();
+        let mut contypes = Vec::new();
+        let mut conrelids = Vec::new();
+        let mut confrelids = Vec::new();
+        let mut confupdtypes = Vec::new();
+        let mut confmatchtypes = Vec::new();
+        let mut conkeys = Vec::new();
+        let mut confkeys = Vec::new();
+
+        let catalog_list = match &visibility {
+            Some(visibility) => visibility.catalog_list(&this.catalog_list),
+            None => this.catalog_list.clone(),
+        };
+        // only resolve the tables the query asks for
+        for table in this
+            .key_filter
+            .tables(catalog_list.as_ref(), &this.oid_registry)
+        {
+            if let Some(visibility) = &visibility {
+                if !visibility
+                    .is_table_visible(&table.schema_name, &table.table_name)
+                    .await
+                {
+                    continue;
+                }
+            }
+
+            for constraint in this
+                .table_constraints(catalog_list.as_ref(), &table)
+                .await?
+            {
+                let is_foreign_key = constraint.contype == "f";
+                oids.push(
+                    this.oid_registry
+                        .constraint_oid(table.table_oid, &constraint.name)
+                        as i32,
+                );
+                connames.push(constraint.name);
+                connamespaces.push(table.schema_oid as i32);
+                contypes.push(constraint.contype);
+                conrelids.push(table.table_oid as i32);
+                confrelids.push(constraint.confrelid);
+                // no action and simple matching for foreign keys
+                confupdtypes.push(if is_foreign_key { "a" } else { " " });
+                confmatchtypes.push(if is_foreign_key { "s" } else { " " });
+                conkeys.push(Some(
+                    constraint.conkey.into_iter().map(Some).collect::<Vec<_>>(),
+                ));
+                confkeys.push(
+                    constraint
+                        .confkey
+                        .map(|confkey| confkey.into_iter().map(Some).collect::<Vec<_>>()),
+                );
+            }
+        }
+
+        let rows = oids.len();
+        let zeros = || Arc::new(Int32Array::from(vec![0; rows])) as ArrayRef;
+        let flags = |value| Arc::new(BooleanArray::from(vec![value; rows])) as ArrayRef;
+        let nulls = |index: usize| new_null_array(this.schema.field(index).data_type(), rows);
+        let arrays: Vec<ArrayRef> = vec![
+            Arc::new(Int32Array::from(oids)),
+            Arc::new(StringArray::from(connames)),
+            Arc::new(Int32Array::from(connamespaces)),
+            Arc::new(StringArray::from(contypes)),
+            flags(false), // condeferrable
+            flags(false), // condeferred
+            flags(true),  // convalidated
+            Arc::new(Int32Array::from(conrelids)),
+            zeros(), // contypid
+            zeros(), // conindid
+            zeros(), // conparentid
+            Arc::new(Int32Array::from(confrelids)),
+            Arc::new(StringArray::from(confupdtypes.clone())),
+            Arc::new(StringArray::from(confupdtypes)), // confdeltype
+            Arc::new(StringArray::from(confmatchtypes)),
+            flags(true), // conislocal
+            Arc::new(Int16Array::from(vec![0; rows])),
+            flags(false), // connoinherit
+            Arc::new(ListArray::from_iter_primitive::<Int16Type, _, _>(conkeys)),
+            Arc::new(ListArray::from_iter_primitive::<Int16Type, _, _>(confkeys)),
+            nulls(20), // conpfeqop
+            nulls(21), // conppeqop
+            nulls(22), // conffeqop
+            nulls(23), // confdelsetcols
+            nulls(24), // conexclop
+            nulls(25), // conbin
+        ];
+
+        let batch = RecordBatch::try_new(this.schema.clone(), arrays)?;
+        Ok(batch)
+    }
+}
+
+impl PartitionStream for PgConstraintTable {
+    fn schema(&self) -> &SchemaRef {
+        &self.schema
+    }
+
+    fn execute(&self, ctx: Arc<TaskContext>) -> SendableRecordBatchStream {
+        let this = self.clone();
+        let visibility = CatalogVisibility::from_task_context(&ctx);
+        Box::pin(RecordBatchStreamAdapter::new(
+            this.schema.clone(),
+            futures::stream::once(async move { Self::get_data(this, visibility).await }),
+        ))
+    }
+}
+
+impl KeyFilteredTable for PgConstraintTable {
+    const KEY_COLUMNS: KeyColumns = KeyColumns {
+        oid: Some("conrelid"),
+        name: None,
+        namespace: Some("connamespace"),
+    };
+
+    fn with_key_filter(&self, key_filter: KeyFilter) -> Self {
+        Self {
+            key_filter,
+            ..self.clone()
+        }
+    }
+}
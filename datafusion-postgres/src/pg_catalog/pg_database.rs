@@ -1,5 +1,3 @@
-use std::collections::HashMap;
-use std::sync::atomic::{AtomicU32, Ordering};
 use std::sync::Arc;
 
 use datafusion::arrow::array::{ArrayRef, BooleanArray, Int32Array, RecordBatch, StringArray};
@@ -9,54 +7,53 @@ use datafusion::error::Result;
 use datafusion::execution::{SendableRecordBatchStream, TaskContext};
 use datafusion::physical_plan::stream::RecordBatchStreamAdapter;
 use datafusion::physical_plan::streaming::PartitionStream;
-use postgres_types::Oid;
-use tokio::sync::RwLock;
 
-use super::OidCacheKey;
+use super::vector_types::oid_field;
+use super::{CatalogVisibility, OidRegistry};
 
 #[derive(Debug, Clone)]
 pub(crate) struct PgDatabaseTable {
     schema: SchemaRef,
     catalog_list: Arc<dyn CatalogProviderList>,
-    oid_counter: Arc<AtomicU32>,
-    oid_cache: Arc<RwLock<HashMap<OidCacheKey, Oid>>>,
+    oid_registry: Arc<OidRegistry>,
 }
 
 impl PgDatabaseTable {
     pub(crate) fn new(
         catalog_list: Arc<dyn CatalogProviderList>,
-        oid_counter: Arc<AtomicU32>,
-        oid_cache: Arc<RwLock<HashMap<OidCacheKey, Oid>>>,
+        oid_registry: Arc<OidRegistry>,
     ) -> Self {
         // Define the schema for pg_database
         // This matches PostgreSQL's pg_database table columns
         let schema = Arc::new(Schema::new(vec![
-            Field::new("oid", DataType::Int32, false), // Object identifier
-            Field::new("datname", DataType::Utf8, false), // Database name
-            Field::new("datdba", DataType::Int32, false), // Database owner's user ID
-            Field::new("encoding", DataType::Int32, false), // Character encoding
-            Field::new("datcollate", DataType::Utf8, false), // LC_COLLATE for this database
-            Field::new("datctype", DataType::Utf8, false), // LC_CTYPE for this database
+            oid_field("oid", false),                               // Object identifier
+            Field::new("datname", DataType::Utf8, false),          // Database name
+            oid_field("datdba", false),                            // Database owner's user ID
+            Field::new("encoding", DataType::Int32, false),        // Character encoding
+            Field::new("datcollate", DataType::Utf8, false),       // LC_COLLATE for this database
+            Field::new("datctype", DataType::Utf8, false),         // LC_CTYPE for this database
             Field::new("datistemplate", DataType::Boolean, false), // If true, database can be used as a template
             Field::new("datallowconn", DataType::Boolean, false), // If false, no one can connect to this database
             Field::new("datconnlimit", DataType::Int32, false), // Max number of concurrent connections (-1=no limit)
-            Field::new("datlastsysoid", DataType::Int32, false), // Last system OID in database
+            oid_field("datlastsysoid", false),                  // Last system OID in database
             Field::new("datfrozenxid", DataType::Int32, false), // Frozen XID for this database
             Field::new("datminmxid", DataType::Int32, false),   // Minimum multixact ID
-            Field::new("dattablespace", DataType::Int32, false), // Default tablespace for this database
-            Field::new("datacl", DataType::Utf8, true),          // Access privileges
+            oid_field("dattablespace", false), // Default tablespace for this database
+            Field::new("datacl", DataType::Utf8, true), // Access privileges
         ]));
 
         Self {
             schema,
             catalog_list,
-            oid_counter,
-            oid_cache,
+            oid_registry,
         }
     }
 
     /// Generate record batches based on the current state of the catalog
-    async fn get_data(this: PgDatabaseTable) -> Result<RecordBatch> {
+    async fn get_data(
+        this: PgDatabaseTable,
+        visibility: Option<Arc<CatalogVisibility>>,
+    ) -> Result<RecordBatch> {
         // Vectors to store column data
         let mut oids = Vec::new();
         let mut datnames = Vec::new();
@@ -73,20 +70,13 @@ impl PgDatabaseTable {
         let mut dattablespaces = Vec::new();
         let mut datacles: Vec<Option<String>> = Vec::new();
 
-        // to store all schema-oid mapping temporarily before adding to global oid cache
-        let mut catalog_oid_cache = HashMap::new();
-
-        let mut oid_cache = this.oid_cache.write().await;
-
+        let catalog_list = match &visibility {
+            Some(visibility) => visibility.catalog_list(&this.catalog_list),
+            None => this.catalog_list.clone(),
+        };
         // Add a record for each catalog (treating catalogs as "databases")
-        for catalog_name in this.catalog_list.catalog_names() {
-            let cache_key = OidCacheKey::Catalog(catalog_name.clone());
-            let catalog_oid = if let Some(oid) = oid_cache.get(&cache_key) {
-                *oid
-            } else {
-                this.oid_counter.fetch_add(1, Ordering::Relaxed)
-            };
-            catalog_oid_cache.insert(cache_key, catalog_oid);
+        for catalog_name in catalog_list.catalog_names() {
+            let catalog_oid = this.oid_registry.catalog_oid(&catalog_name);
 
             oids.push(catalog_oid as i32);
             datnames.push(catalog_name.clone());
@@ -107,14 +97,12 @@ impl PgDatabaseTable {
         // Always include a "postgres" database entry if not already present
         // (This is for compatibility with tools that expect it)
         let default_datname = "postgres".to_string();
-        if !datnames.contains(&default_datname) {
-            let cache_key = OidCacheKey::Catalog(default_datname.clone());
-            let catalog_oid = if let Some(oid) = oid_cache.get(&cache_key) {
-                *oid
-            } else {
-                this.oid_counter.fetch_add(1, Ordering::Relaxed)
-            };
-            catalog_oid_cache.insert(cache_key, catalog_oid);
+        if !datnames.contains(&default_datname)
+            && visibility
+                .as_ref()
+                .is_none_or(|visibility| visibility.is_catalog_visible(&default_datname))
+        {
+            let catalog_oid = this.oid_registry.catalog_oid(&default_datname);
 
             oids.push(catalog_oid as i32);
             datnames.push(default_datname);
@@ -153,20 +141,6 @@ impl PgDatabaseTable {
         // Create a full record batch
         let full_batch = RecordBatch::try_new(this.schema.clone(), arrays)?;
 
-        // update cache
-        // remove all schema cache and table of the schema which is no longer exists
-        oid_cache.retain(|key, _| match key {
-            OidCacheKey::Catalog(..) => false,
-            OidCacheKey::Schema(catalog, ..) => {
-                catalog_oid_cache.contains_key(&OidCacheKey::Catalog(catalog.clone()))
-            }
-            OidCacheKey::Table(catalog, ..) => {
-                catalog_oid_cache.contains_key(&OidCacheKey::Catalog(catalog.clone()))
-            }
-        });
-        // add new schema cache
-        oid_cache.extend(catalog_oid_cache);
-
         Ok(full_batch)
     }
 }
@@ -176,11 +150,12 @@ impl PartitionStream for PgDatabaseTable {
         &self.schema
     }
 
-    fn execute(&self, _ctx: Arc<TaskContext>) -> SendableRecordBatchStream {
+    fn execute(&self, ctx: Arc<TaskContext>) -> SendableRecordBatchStream {
         let this = self.clone();
+        let visibility = CatalogVisibility::from_task_context(&ctx);
         Box::pin(RecordBatchStreamAdapter::new(
             this.schema.clone(),
-            futures::stream::once(async move { Self::get_data(this).await }),
+            futures::stream::once(async move { Self::get_data(this, visibility).await }),
         ))
     }
 }
@@ -0,0 +1,90 @@
+use std::sync::Arc;
+
+use datafusion::arrow::array::{ArrayRef, Int32Array, ListBuilder, RecordBatch, StringBuilder};
+use datafusion::arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use datafusion::error::Result;
+use datafusion::execution::{SendableRecordBatchStream, TaskContext};
+use datafusion::physical_plan::stream::RecordBatchStreamAdapter;
+use datafusion::physical_plan::streaming::PartitionStream;
+
+use super::vector_types::oid_field;
+use super::OidRegistry;
+use crate::role_settings::DbRoleSettings;
+
+#[derive(Debug, Clone)]
+pub(crate) struct PgDbRoleSettingTable {
+    schema: SchemaRef,
+    oid_registry: Arc<OidRegistry>,
+}
+
+impl PgDbRoleSettingTable {
+    pub(crate) fn new(oid_registry: Arc<OidRegistry>) -> Self {
+        // This matches PostgreSQL's pg_db_role_setting table columns
+        let schema = Arc::new(Schema::new(vec![
+            oid_field("setdatabase", false), // Database the settings apply to, 0 for all
+            oid_field("setrole", false),     // Role the settings apply to, 0 for all
+            Field::new(
+                "setconfig",
+                DataType::List(Arc::new(Field::new_list_field(DataType::Utf8, true))),
+                true,
+            ), // Defaults of settings, as name=value
+        ]));
+
+        Self {
+            schema,
+            oid_registry,
+        }
+    }
+
+    /// Generate record batches from the setting defaults of `ALTER ROLE ...
+    /// SET` and `ALTER DATABASE ... SET`
+    fn get_data(&self, settings: Option<Arc<DbRoleSettings>>) -> Result<RecordBatch> {
+        let mut setdatabases = Vec::new();
+        let mut setroles = Vec::new();
+        let mut setconfigs = ListBuilder::new(StringBuilder::new());
+
+        let entries = settings
+            .map(|settings| settings.entries())
+            .unwrap_or_default();
+        for entry in entries {
+            let database_oid = entry
+                .database
+                .as_deref()
+                .map_or(0, |database| self.oid_registry.catalog_oid(database));
+            let role_oid = entry
+                .role
+                .as_deref()
+                .map_or(0, |role| self.oid_registry.role_oid(role));
+            setdatabases.push(database_oid as i32);
+            setroles.push(role_oid as i32);
+            for (name, value) in entry.settings {
+                setconfigs.values().append_value(format!("{name}={value}"));
+            }
+            setconfigs.append(true);
+        }
+
+        let arrays: Vec<ArrayRef> = vec![
+            Arc::new(Int32Array::from(setdatabases)),
+            Arc::new(Int32Array::from(setroles)),
+            Arc::new(setconfigs.finish()),
+        ];
+
+        let batch = RecordBatch::try_new(self.schema.clone(), arrays)?;
+        Ok(batch)
+    }
+}
+
+impl PartitionStream for PgDbRoleSettingTable {
+    fn schema(&self) -> &SchemaRef {
+        &self.schema
+    }
+
+    fn execute(&self, ctx: Arc<TaskContext>) -> SendableRecordBatchStream {
+        let settings = ctx.session_config().get_extension::<DbRoleSettings>();
+        let batch = self.get_data(settings);
+        Box::pin(RecordBatchStreamAdapter::new(
+            self.schema.clone(),
+            futures::stream::once(async move { batch }),
+        ))
+    }
+}
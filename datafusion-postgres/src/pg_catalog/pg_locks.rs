@@ -0,0 +1,119 @@
+use std::sync::Arc;
+
+use datafusion::arrow::array::{
+    ArrayRef, BooleanArray, Int16Array, Int32Array, RecordBatch, StringArray,
+    TimestampMicrosecondArray,
+};
+use datafusion::arrow::datatypes::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use datafusion::error::Result;
+use datafusion::execution::{SendableRecordBatchStream, TaskContext};
+use datafusion::physical_plan::stream::RecordBatchStreamAdapter;
+use datafusion::physical_plan::streaming::PartitionStream;
+
+use super::vector_types::oid_field;
+use super::OidRegistry;
+use crate::advisory_locks::AdvisoryLocks;
+
+#[derive(Debug, Clone)]
+pub(crate) struct PgLocksTable {
+    schema: SchemaRef,
+    oid_registry: Arc<OidRegistry>,
+}
+
+impl PgLocksTable {
+    pub(crate) fn new(oid_registry: Arc<OidRegistry>) -> Self {
+        // This matches PostgreSQL's pg_locks view columns
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("locktype", DataType::Utf8, false), // Type of the locked object
+            oid_field("database", true),                   // Database of the locked object
+            oid_field("relation", true),                   // Locked relation
+            Field::new("page", DataType::Int32, true),     // Locked page of the relation
+            Field::new("tuple", DataType::Int16, true),    // Locked tuple of the page
+            Field::new("virtualxid", DataType::Utf8, true), // Locked virtual transaction
+            Field::new("transactionid", DataType::Int32, true), // Locked transaction
+            oid_field("classid", true), // High half of bigint advisory keys, or first int key
+            oid_field("objid", true),   // Low half of bigint advisory keys, or second int key
+            Field::new("objsubid", DataType::Int16, true), // 1 for bigint keys, 2 for int pairs
+            Field::new("virtualtransaction", DataType::Utf8, true), // Transaction holding or awaiting the lock
+            Field::new("pid", DataType::Int32, true), // Session holding or awaiting the lock
+            Field::new("mode", DataType::Utf8, false), // Lock mode
+            Field::new("granted", DataType::Boolean, false), // Held, or awaited
+            Field::new("fastpath", DataType::Boolean, false), // Taken by fast path
+            Field::new(
+                "waitstart",
+                DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into())),
+                true,
+            ), // Time the session started waiting for the lock
+        ]));
+
+        Self {
+            schema,
+            oid_registry,
+        }
+    }
+
+    /// Generate record batches from the advisory lock table of the server
+    fn get_data(&self, locks: Option<Arc<AdvisoryLocks>>) -> Result<RecordBatch> {
+        let locks = locks.map(|locks| locks.locks()).unwrap_or_default();
+        let rows = locks.len();
+
+        let mut databases = Vec::with_capacity(rows);
+        let mut classids = Vec::with_capacity(rows);
+        let mut objids = Vec::with_capacity(rows);
+        let mut objsubids = Vec::with_capacity(rows);
+        let mut virtualtransactions = Vec::with_capacity(rows);
+        let mut pids = Vec::with_capacity(rows);
+        let mut modes = Vec::with_capacity(rows);
+        let mut granted = Vec::with_capacity(rows);
+        let mut waitstarts = Vec::with_capacity(rows);
+        for lock in locks {
+            let (classid, objid, objsubid) = lock.key.ids();
+            databases.push(self.oid_registry.catalog_oid(&lock.database) as i32);
+            classids.push(classid as i32);
+            objids.push(objid as i32);
+            objsubids.push(objsubid);
+            virtualtransactions.push(format!("{}/1", lock.pid));
+            pids.push(lock.pid);
+            modes.push(lock.mode.name());
+            granted.push(lock.granted);
+            waitstarts.push(lock.wait_start.map(|since| since.timestamp_micros()));
+        }
+
+        let arrays: Vec<ArrayRef> = vec![
+            Arc::new(StringArray::from(vec!["advisory"; rows])),
+            Arc::new(Int32Array::from(databases)),
+            Arc::new(Int32Array::from(vec![None; rows])),
+            Arc::new(Int32Array::from(vec![None; rows])),
+            Arc::new(Int16Array::from(vec![None; rows])),
+            Arc::new(StringArray::from(vec![None::<&str>; rows])),
+            Arc::new(Int32Array::from(vec![None; rows])),
+            Arc::new(Int32Array::from(classids)),
+            Arc::new(Int32Array::from(objids)),
+            Arc::new(Int16Array::from(objsubids)),
+            Arc::new(StringArray::from(virtualtransactions)),
+            Arc::new(Int32Array::from(pids)),
+            Arc::new(StringArray::from(modes)),
+            Arc::new(BooleanArray::from(granted)),
+            Arc::new(BooleanArray::from(vec![false; rows])),
+            Arc::new(TimestampMicrosecondArray::from(waitstarts).with_timezone("UTC")),
+        ];
+
+        let batch = RecordBatch::try_new(self.schema.clone(), arrays)?;
+        Ok(batch)
+    }
+}
+
+impl PartitionStream for PgLocksTable {
+    fn schema(&self) -> &SchemaRef {
+        &self.schema
+    }
+
+    fn execute(&self, ctx: Arc<TaskContext>) -> SendableRecordBatchStream {
+        let locks = ctx.session_config().get_extension::<AdvisoryLocks>();
+        let batch = self.get_data(locks);
+        Box::pin(RecordBatchStreamAdapter::new(
+            self.schema.clone(),
+            futures::stream::once(async move { batch }),
+        ))
+    }
+}
@@ -1,5 +1,3 @@
-use std::collections::HashMap;
-use std::sync::atomic::{AtomicU32, Ordering};
 use std::sync::Arc;
 
 use datafusion::arrow::array::{ArrayRef, Int32Array, RecordBatch, StringArray};
@@ -9,45 +7,44 @@ use datafusion::error::Result;
 use datafusion::execution::{SendableRecordBatchStream, TaskContext};
 use datafusion::physical_plan::stream::RecordBatchStreamAdapter;
 use datafusion::physical_plan::streaming::PartitionStream;
-use postgres_types::Oid;
-use tokio::sync::RwLock;
 
-use super::OidCacheKey;
+use super::vector_types::oid_field;
+use super::{CatalogVisibility, OidRegistry};
 
 #[derive(Debug, Clone)]
 pub(crate) struct PgNamespaceTable {
     schema: SchemaRef,
     catalog_list: Arc<dyn CatalogProviderList>,
-    oid_counter: Arc<AtomicU32>,
-    oid_cache: Arc<RwLock<HashMap<OidCacheKey, Oid>>>,
+    oid_registry: Arc<OidRegistry>,
 }
 
 impl PgNamespaceTable {
     pub(crate) fn new(
         catalog_list: Arc<dyn CatalogProviderList>,
-        oid_counter: Arc<AtomicU32>,
-        oid_cache: Arc<RwLock<HashMap<OidCacheKey, Oid>>>,
+        oid_registry: Arc<OidRegistry>,
     ) -> Self {
         // Define the schema for pg_namespace
         // This matches the columns from PostgreSQL's pg_namespace
         let schema = Arc::new(Schema::new(vec![
-            Field::new("oid", DataType::Int32, false), // Object identifier
+            oid_field("oid", false),                      // Object identifier
             Field::new("nspname", DataType::Utf8, false), // Name of the namespace (schema)
-            Field::new("nspowner", DataType::Int32, false), // Owner of the namespace
-            Field::new("nspacl", DataType::Utf8, true), // Access privileges
-            Field::new("options", DataType::Utf8, true), // Schema-level options
+            oid_field("nspowner", false),                 // Owner of the namespace
+            Field::new("nspacl", DataType::Utf8, true),   // Access privileges
+            Field::new("options", DataType::Utf8, true),  // Schema-level options
         ]));
 
         Self {
             schema,
             catalog_list,
-            oid_counter,
-            oid_cache,
+            oid_registry,
         }
     }
 
     /// Generate record batches based on the current state of the catalog
-    async fn get_data(this: PgNamespaceTable) -> Result<RecordBatch> {
+    async fn get_data(
+        this: PgNamespaceTable,
+        visibility: Option<Arc<CatalogVisibility>>,
+    ) -> Result<RecordBatch> {
         // Vectors to store column data
         let mut oids = Vec::new();
         let mut nspnames = Vec::new();
@@ -55,22 +52,26 @@ impl PgNamespaceTable {
         let mut nspacls: Vec<Option<String>> = Vec::new();
         let mut options: Vec<Option<String>> = Vec::new();
 
-        // to store all schema-oid mapping temporarily before adding to global oid cache
-        let mut schema_oid_cache = HashMap::new();
-
-        let mut oid_cache = this.oid_cache.write().await;
-
+        let catalog_list = match &visibility {
+            Some(visibility) => visibility.catalog_list(&this.catalog_list),
+            None => this.catalog_list.clone(),
+        };
         // Now add all schemas from DataFusion catalogs
-        for catalog_name in this.catalog_list.catalog_names() {
-            if let Some(catalog) = this.catalog_list.catalog(&catalog_name) {
+        for catalog_name in catalog_list.catalog_names() {
+            if let Some(catalog) = catalog_list.catalog(&catalog_name) {
                 for schema_name in catalog.schema_names() {
-                    let cache_key = OidCacheKey::Schema(catalog_name.clone(), schema_name.clone());
-                    let schema_oid = if let Some(oid) = oid_cache.get(&cache_key) {
-                        *oid
-                    } else {
-                        this.oid_counter.fetch_add(1, Ordering::Relaxed)
-                    };
-                    schema_oid_cache.insert(cache_key, schema_oid);
+                    let schema_oid = this.oid_registry.schema_oid(&catalog_name, &schema_name);
+
+                    if let (Some(visibility), Some(schema)) =
+                        (&visibility, catalog.schema(&schema_name))
+                    {
+                        if !visibility
+                            .is_schema_visible(&schema_name, schema.as_ref())
+                            .await
+                        {
+                            continue;
+                        }
+                    }
 
                     oids.push(schema_oid as i32);
                     nspnames.push(schema_name.clone());
@@ -81,16 +82,6 @@ impl PgNamespaceTable {
             }
         }
 
-        // remove all schema cache and table of the schema which is no longer exists
-        oid_cache.retain(|key, _| match key {
-            OidCacheKey::Catalog(..) => true,
-            OidCacheKey::Schema(..) => false,
-            OidCacheKey::Table(catalog, schema_name, _) => schema_oid_cache
-                .contains_key(&OidCacheKey::Schema(catalog.clone(), schema_name.clone())),
-        });
-        // add new schema cache
-        oid_cache.extend(schema_oid_cache);
-
         // Create Arrow arrays from the collected data
         let arrays: Vec<ArrayRef> = vec![
             Arc::new(Int32Array::from(oids)),
@@ -112,11 +103,12 @@ impl PartitionStream for PgNamespaceTable {
         &self.schema
     }
 
-    fn execute(&self, _ctx: Arc<TaskContext>) -> SendableRecordBatchStream {
+    fn execute(&self, ctx: Arc<TaskContext>) -> SendableRecordBatchStream {
         let this = self.clone();
+        let visibility = CatalogVisibility::from_task_context(&ctx);
         Box::pin(RecordBatchStreamAdapter::new(
             this.schema.clone(),
-            futures::stream::once(async move { Self::get_data(this).await }),
+            futures::stream::once(async move { Self::get_data(this, visibility).await }),
         ))
     }
 }
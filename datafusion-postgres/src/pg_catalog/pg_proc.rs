@@ -0,0 +1,90 @@
+use std::any::Any;
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use datafusion::arrow::array::{AsArray, BooleanArray, RecordBatch};
+use datafusion::arrow::compute::{cast, filter_record_batch};
+use datafusion::arrow::datatypes::{DataType, SchemaRef};
+use datafusion::catalog::{MemTable, Session};
+use datafusion::datasource::{TableProvider, TableType};
+use datafusion::error::Result;
+use datafusion::physical_plan::ExecutionPlan;
+use datafusion::prelude::Expr;
+
+use super::{SystemFunctions, SYSTEM_FUNCTIONS};
+
+/// `pg_proc`, without the system functions left out of the session
+///
+/// The functions registered in the session are read from the
+/// [`SystemFunctions`] extension of its `SessionConfig` when scanned.
+#[derive(Debug)]
+pub(crate) struct PgProcTable {
+    inner: Arc<dyn TableProvider>,
+}
+
+impl PgProcTable {
+    pub(crate) fn new(inner: Arc<dyn TableProvider>) -> Self {
+        PgProcTable { inner }
+    }
+
+    /// The rows of `batch` not describing one of the `hidden` functions
+    fn retain(batch: &RecordBatch, hidden: &HashSet<&str>) -> Result<RecordBatch> {
+        let Some(pronames) = batch.column_by_name("proname") else {
+            return Ok(batch.clone());
+        };
+        let pronames = cast(pronames, &DataType::Utf8)?;
+        let keep = pronames
+            .as_string::<i32>()
+            .iter()
+            .map(|name| Some(name.is_none_or(|name| !hidden.contains(name))))
+            .collect::<BooleanArray>();
+        Ok(filter_record_batch(batch, &keep)?)
+    }
+}
+
+#[async_trait]
+impl TableProvider for PgProcTable {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.inner.schema()
+    }
+
+    fn table_type(&self) -> TableType {
+        self.inner.table_type()
+    }
+
+    async fn scan(
+        &self,
+        state: &dyn Session,
+        projection: Option<&Vec<usize>>,
+        filters: &[Expr],
+        limit: Option<usize>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        let hidden = match state.config().get_extension::<SystemFunctions>() {
+            Some(functions) => SYSTEM_FUNCTIONS
+                .iter()
+                .copied()
+                .filter(|name| !functions.contains(name))
+                .collect::<HashSet<_>>(),
+            None => HashSet::new(),
+        };
+        let mem_table = self.inner.as_any().downcast_ref::<MemTable>();
+        let Some(mem_table) = mem_table.filter(|_| !hidden.is_empty()) else {
+            return self.inner.scan(state, projection, filters, limit).await;
+        };
+
+        let mut batches = Vec::new();
+        for partition in &mem_table.batches {
+            for batch in partition.read().await.iter() {
+                batches.push(Self::retain(batch, &hidden)?);
+            }
+        }
+        MemTable::try_new(self.schema(), vec![batches])?
+            .scan(state, projection, filters, limit)
+            .await
+    }
+}
@@ -0,0 +1,134 @@
+use std::sync::Arc;
+
+use datafusion::arrow::array::{
+    ArrayRef, BooleanArray, Int32Array, RecordBatch, StringArray, TimestampMicrosecondArray,
+};
+use datafusion::arrow::datatypes::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use datafusion::error::Result;
+use datafusion::execution::{SendableRecordBatchStream, TaskContext};
+use datafusion::physical_plan::stream::RecordBatchStreamAdapter;
+use datafusion::physical_plan::streaming::PartitionStream;
+
+use super::vector_types::oid_field;
+use super::{CatalogVisibility, OidRegistry};
+
+/// Password shown for roles, like postgres never shows them
+const PASSWORD_MASK: &str = "********";
+
+#[derive(Debug, Clone)]
+pub(crate) struct PgRolesTable {
+    schema: SchemaRef,
+    oid_registry: Arc<OidRegistry>,
+}
+
+impl PgRolesTable {
+    pub(crate) fn new(oid_registry: Arc<OidRegistry>) -> Self {
+        // This matches PostgreSQL's pg_roles view columns
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("rolname", DataType::Utf8, false), // Role name
+            Field::new("rolsuper", DataType::Boolean, false), // Role has superuser privileges
+            Field::new("rolinherit", DataType::Boolean, false), // Role inherits privileges of its roles
+            Field::new("rolcreaterole", DataType::Boolean, false), // Role can create more roles
+            Field::new("rolcreatedb", DataType::Boolean, false), // Role can create databases
+            Field::new("rolcanlogin", DataType::Boolean, false), // Role can log in
+            Field::new("rolreplication", DataType::Boolean, false), // Role is a replication role
+            Field::new("rolconnlimit", DataType::Int32, false), // Max concurrent connections (-1=no limit)
+            Field::new("rolpassword", DataType::Utf8, true), // Not the password (always ********)
+            Field::new(
+                "rolvaliduntil",
+                DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into())),
+                true,
+            ), // Password expiry time
+            Field::new("rolbypassrls", DataType::Boolean, false), // Role bypasses row-level security
+            Field::new("rolconfig", DataType::Utf8, true), // Role-specific configuration defaults
+            oid_field("oid", false),                       // ID of role
+        ]));
+
+        Self {
+            schema,
+            oid_registry,
+        }
+    }
+
+    /// Generate record batches from the users and roles of the
+    /// `AuthManager` of the session
+    async fn get_data(
+        this: PgRolesTable,
+        visibility: Option<Arc<CatalogVisibility>>,
+    ) -> Result<RecordBatch> {
+        let mut rolnames = Vec::new();
+        let mut rolsupers = Vec::new();
+        let mut rolcreateroles = Vec::new();
+        let mut rolcreatedbs = Vec::new();
+        let mut rolcanlogins = Vec::new();
+        let mut rolreplications = Vec::new();
+        let mut rolconnlimits = Vec::new();
+        let mut rolpasswords = Vec::new();
+        let mut oids = Vec::new();
+
+        if let Some(visibility) = visibility {
+            let auth_manager = visibility.auth_manager();
+            let mut names = auth_manager.list_users().await;
+            names.extend(auth_manager.list_roles().await);
+            names.sort();
+            names.dedup();
+            for name in names {
+                let user = auth_manager.get_user(&name).await;
+                let role = auth_manager.get_role(&name).await;
+                let superuser = user.as_ref().is_some_and(|user| user.is_superuser)
+                    || role.as_ref().is_some_and(|role| role.is_superuser);
+                let can_login = user.as_ref().is_some_and(|user| user.can_login)
+                    || role.as_ref().is_some_and(|role| role.can_login);
+
+                oids.push(this.oid_registry.role_oid(&name) as i32);
+                rolnames.push(name);
+                rolsupers.push(superuser);
+                rolcreateroles.push(role.as_ref().is_some_and(|role| role.can_create_role));
+                rolcreatedbs.push(role.as_ref().is_some_and(|role| role.can_create_db));
+                rolcanlogins.push(can_login);
+                rolreplications.push(role.as_ref().is_some_and(|role| role.can_replication));
+                rolconnlimits.push(
+                    user.as_ref()
+                        .and_then(|user| user.connection_limit)
+                        .unwrap_or(-1),
+                );
+                rolpasswords.push(PASSWORD_MASK);
+            }
+        }
+
+        let rows = rolnames.len();
+        let arrays: Vec<ArrayRef> = vec![
+            Arc::new(StringArray::from(rolnames)),
+            Arc::new(BooleanArray::from(rolsupers)),
+            Arc::new(BooleanArray::from(vec![true; rows])),
+            Arc::new(BooleanArray::from(rolcreateroles)),
+            Arc::new(BooleanArray::from(rolcreatedbs)),
+            Arc::new(BooleanArray::from(rolcanlogins)),
+            Arc::new(BooleanArray::from(rolreplications)),
+            Arc::new(Int32Array::from(rolconnlimits)),
+            Arc::new(StringArray::from(rolpasswords)),
+            Arc::new(TimestampMicrosecondArray::from(vec![None; rows]).with_timezone("UTC")),
+            Arc::new(BooleanArray::from(vec![false; rows])),
+            Arc::new(StringArray::from(vec![None::<String>; rows])),
+            Arc::new(Int32Array::from(oids)),
+        ];
+
+        let batch = RecordBatch::try_new(this.schema.clone(), arrays)?;
+        Ok(batch)
+    }
+}
+
+impl PartitionStream for PgRolesTable {
+    fn schema(&self) -> &SchemaRef {
+        &self.schema
+    }
+
+    fn execute(&self, ctx: Arc<TaskContext>) -> SendableRecordBatchStream {
+        let this = self.clone();
+        let visibility = CatalogVisibility::from_task_context(&ctx);
+        Box::pin(RecordBatchStreamAdapter::new(
+            this.schema.clone(),
+            futures::stream::once(async move { Self::get_data(this, visibility).await }),
+        ))
+    }
+}
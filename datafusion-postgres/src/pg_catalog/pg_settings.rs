@@ -1,115 +1,490 @@
 use std::sync::Arc;
 
-use datafusion::arrow::array::{ArrayRef, BooleanArray, Int32Array, RecordBatch, StringArray};
+use datafusion::arrow::array::{
+    ArrayRef, BooleanArray, Int32Array, ListBuilder, RecordBatch, StringArray, StringBuilder,
+};
 use datafusion::arrow::datatypes::{DataType, Field, Schema, SchemaRef};
-use datafusion::catalog::MemTable;
 use datafusion::error::Result;
+use datafusion::execution::{SendableRecordBatchStream, TaskContext};
+use datafusion::physical_plan::stream::RecordBatchStreamAdapter;
+use datafusion::physical_plan::streaming::PartitionStream;
 
+const LOCALE_AND_FORMATTING: &str = "Client Connection Defaults / Locale and Formatting";
+const STATEMENT_BEHAVIOR: &str = "Client Connection Defaults / Statement Behavior";
+const PRESET_OPTIONS: &str = "Preset Options";
+const CUSTOMIZED_OPTIONS: &str = "Customized Options";
+
+/// A run-time parameter, described like in postgres' `pg_settings`
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct Parameter {
+    pub(crate) name: &'static str,
+    /// Value the parameter has unless a session sets it
+    pub(crate) boot_val: &'static str,
+    /// `bool`, `enum`, `integer`, `real` or `string`
+    vartype: &'static str,
+    /// `internal` for parameters sessions can't change, `user` for the others
+    context: &'static str,
+    category: &'static str,
+    pub(crate) short_desc: &'static str,
+    unit: Option<&'static str>,
+    range: Option<(&'static str, &'static str)>,
+    enumvals: &'static [&'static str],
+}
+
+impl Parameter {
+    const fn new(
+        name: &'static str,
+        boot_val: &'static str,
+        vartype: &'static str,
+        context: &'static str,
+        category: &'static str,
+        short_desc: &'static str,
+    ) -> Self {
+        Parameter {
+            name,
+            boot_val,
+            vartype,
+            context,
+            category,
+            short_desc,
+            unit: None,
+            range: None,
+            enumvals: &[],
+        }
+    }
+
+    const fn with_unit(mut self, unit: &'static str) -> Self {
+        self.unit = Some(unit);
+        self
+    }
+
+    const fn with_range(mut self, min_val: &'static str, max_val: &'static str) -> Self {
+        self.range = Some((min_val, max_val));
+        self
+    }
+
+    const fn with_enumvals(mut self, enumvals: &'static [&'static str]) -> Self {
+        self.enumvals = enumvals;
+        self
+    }
+}
+
+const ISOLATION_LEVELS: &[&str] = &[
+    "serializable",
+    "repeatable read",
+    "read committed",
+    "read uncommitted",
+];
+
+/// The run-time parameters of sessions, ordered by name
+const PARAMETERS: &[Parameter] = &[
+    Parameter::new(
+        "application_name",
+        "",
+        "string",
+        "user",
+        "Reporting and Logging / What to Log",
+        "Sets the application name to be reported in statistics and logs.",
+    ),
+    Parameter::new(
+        "client_encoding",
+        "UTF8",
+        "string",
+        "user",
+        LOCALE_AND_FORMATTING,
+        "Sets the client's character set encoding.",
+    ),
+    Parameter::new(
+        "DateStyle",
+        "ISO, YMD",
+        "string",
+        "user",
+        LOCALE_AND_FORMATTING,
+        "Sets the display format for date and time values.",
+    ),
+    Parameter::new(
+        "default_transaction_isolation",
+        "read committed",
+        "enum",
+        "user",
+        STATEMENT_BEHAVIOR,
+        "Sets the transaction isolation level of each new transaction.",
+    )
+    .with_enumvals(ISOLATION_LEVELS),
+    Parameter::new(
+        "extra_float_digits",
+        "1",
+        "integer",
+        "user",
+        LOCALE_AND_FORMATTING,
+        "Sets the number of digits displayed for floating-point values.",
+    )
+    .with_range("-15", "3"),
+    Parameter::new(
+        "integer_datetimes",
+        "on",
+        "bool",
+        "internal",
+        PRESET_OPTIONS,
+        "Shows whether datetimes are integer based.",
+    ),
+    Parameter::new(
+        "IntervalStyle",
+        "postgres",
+        "enum",
+        "user",
+        LOCALE_AND_FORMATTING,
+        "Sets the display format for interval values.",
+    )
+    .with_enumvals(&["postgres", "postgres_verbose", "sql_standard", "iso_8601"]),
+    Parameter::new(
+        "max_field_size",
+        "1073741823",
+        "integer",
+        "user",
+        CUSTOMIZED_OPTIONS,
+        "Sets the maximum size of a field of a result row, in bytes.",
+    )
+    .with_unit("B"),
+    Parameter::new(
+        "max_identifier_length",
+        "63",
+        "integer",
+        "internal",
+        PRESET_OPTIONS,
+        "Shows the maximum identifier length.",
+    )
+    .with_range("63", "63"),
+    Parameter::new(
+        "result_batch_size",
+        "8192",
+        "integer",
+        "user",
+        CUSTOMIZED_OPTIONS,
+        "Sets the number of rows of the batches results are encoded in.",
+    ),
+    Parameter::new(
+        "result_buffer_size",
+        "8192",
+        "integer",
+        "user",
+        CUSTOMIZED_OPTIONS,
+        "Sets the number of encoded result batches buffered ahead of the client.",
+    ),
+    Parameter::new(
+        "search_path",
+        "public",
+        "string",
+        "user",
+        STATEMENT_BEHAVIOR,
+        "Sets the schema search order for names that are not schema-qualified.",
+    ),
+    Parameter::new(
+        "server_encoding",
+        "UTF8",
+        "string",
+        "internal",
+        PRESET_OPTIONS,
+        "Shows the server (database) character set encoding.",
+    ),
+    Parameter::new(
+        "server_version",
+        "15.0 (DataFusion)",
+        "string",
+        "internal",
+        PRESET_OPTIONS,
+        "Shows the server version.",
+    ),
+    Parameter::new(
+        "server_version_num",
+        "150000",
+        "integer",
+        "internal",
+        PRESET_OPTIONS,
+        "Shows the server version as an integer.",
+    )
+    .with_range("150000", "150000"),
+    Parameter::new(
+        "standard_conforming_strings",
+        "on",
+        "bool",
+        "user",
+        "Version and Platform Compatibility / Previous PostgreSQL Versions",
+        "Causes '...' strings to treat backslashes literally.",
+    ),
+    Parameter::new(
+        "statement_timeout",
+        "0",
+        "integer",
+        "user",
+        STATEMENT_BEHAVIOR,
+        "Sets the maximum allowed duration of any statement.",
+    )
+    .with_unit("ms")
+    .with_range("0", "2147483647"),
+    Parameter::new(
+        "TimeZone",
+        "UTC",
+        "string",
+        "user",
+        LOCALE_AND_FORMATTING,
+        "Sets the time zone for displaying and interpreting time stamps.",
+    ),
+    Parameter::new(
+        "transaction_deferrable",
+        "off",
+        "bool",
+        "user",
+        STATEMENT_BEHAVIOR,
+        "Whether to defer a read-only serializable transaction until it can be executed with no possible serialization failures.",
+    ),
+    Parameter::new(
+        "transaction_isolation",
+        "read committed",
+        "enum",
+        "user",
+        STATEMENT_BEHAVIOR,
+        "Sets the current transaction's isolation level.",
+    )
+    .with_enumvals(ISOLATION_LEVELS),
+    Parameter::new(
+        "transaction_read_only",
+        "off",
+        "bool",
+        "user",
+        STATEMENT_BEHAVIOR,
+        "Sets the current transaction's read-only status.",
+    ),
+    Parameter::new(
+        "wire_debug",
+        "off",
+        "enum",
+        "user",
+        CUSTOMIZED_OPTIONS,
+        "Logs the protocol messages of the session.",
+    )
+    .with_enumvals(&["off", "on", "hex"]),
+];
+
+/// A run-time parameter of a session
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Setting {
+    pub(crate) parameter: &'static Parameter,
+    pub(crate) setting: String,
+    /// The value RESET goes back to, the default of the role or database of
+    /// the session if it has one
+    reset_val: String,
+    /// Where `reset_val` comes from
+    source: String,
+}
+
+impl Setting {
+    pub(crate) fn name(&self) -> &'static str {
+        self.parameter.name
+    }
+
+    /// The value in the unit of the parameter, as `SHOW` shows the unit
+    /// along with the value
+    fn value_in_unit(&self) -> &str {
+        self.in_unit(&self.setting)
+    }
+
+    fn in_unit<'a>(&self, value: &'a str) -> &'a str {
+        match self.parameter.unit {
+            Some(unit) => value.strip_suffix(unit).unwrap_or(value),
+            None => value,
+        }
+    }
+
+    /// Where the current value comes from, `session` once `SET` changes it
+    fn source(&self) -> &str {
+        if self.setting == self.reset_val {
+            &self.source
+        } else {
+            "session"
+        }
+    }
+}
+
+/// The run-time parameters of a session, shown by `SHOW` and listed in
+/// `pg_settings`
+///
+/// The handlers attach the settings of the session to the `SessionConfig` of
+/// its statements as an extension, so both show the same values. Without it,
+/// `pg_settings` lists the defaults.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct SessionSettings {
+    settings: Vec<Setting>,
+}
+
+impl Default for SessionSettings {
+    fn default() -> Self {
+        let settings = PARAMETERS
+            .iter()
+            .map(|parameter| Setting {
+                parameter,
+                setting: parameter.boot_val.to_string(),
+                reset_val: parameter.boot_val.to_string(),
+                source: "default".to_string(),
+            })
+            .collect::<Vec<_>>();
+        SessionSettings { settings }
+    }
+}
+
+impl SessionSettings {
+    /// Set the value of a parameter, other names are ignored
+    pub(crate) fn with_setting(mut self, name: &str, value: impl Into<String>) -> Self {
+        if let Some(setting) = self
+            .settings
+            .iter_mut()
+            .find(|setting| setting.name().eq_ignore_ascii_case(name))
+        {
+            setting.setting = value.into();
+        }
+        self
+    }
+
+    /// Set the value RESET goes back to and its source, the default of the
+    /// role or database of the session
+    pub(crate) fn with_reset_val(
+        mut self,
+        name: &str,
+        value: impl Into<String>,
+        source: &str,
+    ) -> Self {
+        if let Some(setting) = self
+            .settings
+            .iter_mut()
+            .find(|setting| setting.name().eq_ignore_ascii_case(name))
+        {
+            setting.reset_val = value.into();
+            setting.source = source.to_string();
+        }
+        self
+    }
+
+    /// The parameter of a name, which is case insensitive
+    pub(crate) fn get(&self, name: &str) -> Option<&Setting> {
+        self.settings
+            .iter()
+            .find(|setting| setting.name().eq_ignore_ascii_case(name))
+    }
+
+    /// The parameters, ordered by name
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &Setting> {
+        self.settings.iter()
+    }
+}
+
+/// `pg_settings`, listing the settings of the session it is scanned in
 #[derive(Debug, Clone)]
-pub(crate) struct PgSettingsView {
+pub(crate) struct PgSettingsTable {
     schema: SchemaRef,
-    data: Vec<RecordBatch>,
 }
 
-impl PgSettingsView {
-    pub(crate) fn try_new() -> Result<PgSettingsView> {
+impl PgSettingsTable {
+    pub(crate) fn new() -> PgSettingsTable {
+        // This matches PostgreSQL's pg_settings view columns
         let schema = Arc::new(Schema::new(vec![
-            //        name        | setting | unit |                             category                             |                short_
-            //desc                |                                                   extra_desc
-            //| context | vartype | source  | min_val | max_val | enumvals |
-            //boot_val | reset_val | sourcefile | sourceline | pending_restart
-            Field::new("name", DataType::Utf8, true),
-            Field::new("setting", DataType::Utf8, true),
-            Field::new("unit", DataType::Utf8, true),
-            Field::new("category", DataType::Utf8, true),
-            Field::new("short_desc", DataType::Utf8, true),
-            Field::new("extra_desc", DataType::Utf8, true),
-            Field::new("context", DataType::Utf8, true),
-            Field::new("vartype", DataType::Utf8, true),
-            Field::new("source", DataType::Utf8, true),
-            Field::new("min_val", DataType::Utf8, true),
-            Field::new("max_val", DataType::Utf8, true),
-            Field::new("enumvals", DataType::Utf8, true),
-            Field::new("bool_val", DataType::Utf8, true),
-            Field::new("reset_val", DataType::Utf8, true),
-            Field::new("sourcefile", DataType::Utf8, true),
-            Field::new("sourceline", DataType::Int32, true),
-            Field::new("pending_restart", DataType::Boolean, true),
+            Field::new("name", DataType::Utf8, false), // Run-time parameter name
+            Field::new("setting", DataType::Utf8, true), // Current value of the parameter
+            Field::new("unit", DataType::Utf8, true),  // Implicit unit of the parameter
+            Field::new("category", DataType::Utf8, true), // Logical group of the parameter
+            Field::new("short_desc", DataType::Utf8, true), // Brief description of the parameter
+            Field::new("extra_desc", DataType::Utf8, true), // More detailed description
+            Field::new("context", DataType::Utf8, true), // Context required to set the value
+            Field::new("vartype", DataType::Utf8, true), // Parameter type
+            Field::new("source", DataType::Utf8, true), // Source of the current value
+            Field::new("min_val", DataType::Utf8, true), // Minimum allowed value of numeric parameters
+            Field::new("max_val", DataType::Utf8, true), // Maximum allowed value of numeric parameters
+            Field::new("enumvals", DataType::new_list(DataType::Utf8, true), true), // Allowed values of enum parameters
+            Field::new("boot_val", DataType::Utf8, true), // Value assumed at server startup
+            Field::new("reset_val", DataType::Utf8, true), // Value RESET would reset the parameter to
+            Field::new("sourcefile", DataType::Utf8, true), // Configuration file the value was set in
+            Field::new("sourceline", DataType::Int32, true), // Line of the configuration file
+            Field::new("pending_restart", DataType::Boolean, false), // Changed in the configuration file, awaiting a restart
         ]));
 
-        let data = Self::create_data(schema.clone())?;
-
-        Ok(Self { schema, data })
-    }
-
-    fn create_data(schema: Arc<Schema>) -> Result<Vec<RecordBatch>> {
-        let mut name: Vec<Option<&str>> = Vec::new();
-        let mut setting: Vec<Option<&str>> = Vec::new();
-        let mut unit: Vec<Option<&str>> = Vec::new();
-        let mut category: Vec<Option<&str>> = Vec::new();
-        let mut short_desc: Vec<Option<&str>> = Vec::new();
-        let mut extra_desc: Vec<Option<&str>> = Vec::new();
-        let mut context: Vec<Option<&str>> = Vec::new();
-        let mut vartype: Vec<Option<&str>> = Vec::new();
-        let mut source: Vec<Option<&str>> = Vec::new();
-        let mut min_val: Vec<Option<&str>> = Vec::new();
-        let mut max_val: Vec<Option<&str>> = Vec::new();
-        let mut enumvals: Vec<Option<&str>> = Vec::new();
-        let mut bool_val: Vec<Option<&str>> = Vec::new();
-        let mut reset_val: Vec<Option<&str>> = Vec::new();
-        let mut sourcefile: Vec<Option<&str>> = Vec::new();
-        let mut sourceline: Vec<Option<i32>> = Vec::new();
-        let mut pending_restart: Vec<Option<bool>> = Vec::new();
-
-        let data = vec![("standard_conforming_strings", "on")];
-
-        for (setting_name, setting_val) in data {
-            name.push(Some(setting_name));
-            setting.push(Some(setting_val));
-
-            unit.push(None);
-            category.push(None);
-            short_desc.push(None);
-            extra_desc.push(None);
-            context.push(None);
-            vartype.push(None);
-            source.push(None);
-            min_val.push(None);
-            max_val.push(None);
-            enumvals.push(None);
-            bool_val.push(None);
-            reset_val.push(None);
-            sourcefile.push(None);
-            sourceline.push(None);
-            pending_restart.push(None);
+        Self { schema }
+    }
+
+    /// Generate record batches from the settings of the session
+    fn get_data(&self, settings: Option<Arc<SessionSettings>>) -> Result<RecordBatch> {
+        let settings = settings.unwrap_or_default();
+
+        let mut names = Vec::new();
+        let mut values = Vec::new();
+        let mut units = Vec::new();
+        let mut categories = Vec::new();
+        let mut short_descs = Vec::new();
+        let mut contexts = Vec::new();
+        let mut vartypes = Vec::new();
+        let mut sources = Vec::new();
+        let mut min_vals = Vec::new();
+        let mut max_vals = Vec::new();
+        let mut enumvals = ListBuilder::new(StringBuilder::new());
+        let mut boot_vals = Vec::new();
+        let mut reset_vals = Vec::new();
+
+        for setting in settings.iter() {
+            let parameter = setting.parameter;
+            names.push(parameter.name);
+            values.push(setting.value_in_unit());
+            units.push(parameter.unit);
+            categories.push(parameter.category);
+            short_descs.push(parameter.short_desc);
+            contexts.push(parameter.context);
+            vartypes.push(parameter.vartype);
+            sources.push(setting.source());
+            min_vals.push(parameter.range.map(|(min_val, _)| min_val));
+            max_vals.push(parameter.range.map(|(_, max_val)| max_val));
+            if parameter.enumvals.is_empty() {
+                enumvals.append_null();
+            } else {
+                enumvals.append_value(parameter.enumvals.iter().map(|value| Some(*value)));
+            }
+            boot_vals.push(parameter.boot_val);
+            reset_vals.push(setting.in_unit(&setting.reset_val));
         }
 
+        let rows = names.len();
         let arrays: Vec<ArrayRef> = vec![
-            Arc::new(StringArray::from(name)),
-            Arc::new(StringArray::from(setting)),
-            Arc::new(StringArray::from(unit)),
-            Arc::new(StringArray::from(category)),
-            Arc::new(StringArray::from(short_desc)),
-            Arc::new(StringArray::from(extra_desc)),
-            Arc::new(StringArray::from(context)),
-            Arc::new(StringArray::from(vartype)),
-            Arc::new(StringArray::from(source)),
-            Arc::new(StringArray::from(min_val)),
-            Arc::new(StringArray::from(max_val)),
-            Arc::new(StringArray::from(enumvals)),
-            Arc::new(StringArray::from(bool_val)),
-            Arc::new(StringArray::from(reset_val)),
-            Arc::new(StringArray::from(sourcefile)),
-            Arc::new(Int32Array::from(sourceline)),
-            Arc::new(BooleanArray::from(pending_restart)),
+            Arc::new(StringArray::from(names)),
+            Arc::new(StringArray::from(values)),
+            Arc::new(StringArray::from(units)),
+            Arc::new(StringArray::from(categories)),
+            Arc::new(StringArray::from(short_descs)),
+            Arc::new(StringArray::from(vec![None::<&str>; rows])), // extra_desc
+            Arc::new(StringArray::from(contexts)),
+            Arc::new(StringArray::from(vartypes)),
+            Arc::new(StringArray::from(sources)),
+            Arc::new(StringArray::from(min_vals)),
+            Arc::new(StringArray::from(max_vals)),
+            Arc::new(enumvals.finish()),
+            Arc::new(StringArray::from(boot_vals)),
+            Arc::new(StringArray::from(reset_vals)),
+            Arc::new(StringArray::from(vec![None::<&str>; rows])), // sourcefile
+            Arc::new(Int32Array::from(vec![None; rows])),          // sourceline
+            Arc::new(BooleanArray::from(vec![false; rows])),
         ];
 
-        let batch = RecordBatch::try_new(schema.clone(), arrays)?;
+        let batch = RecordBatch::try_new(self.schema.clone(), arrays)?;
+        Ok(batch)
+    }
+}
 
-        Ok(vec![batch])
+impl PartitionStream for PgSettingsTable {
+    fn schema(&self) -> &SchemaRef {
+        &self.schema
     }
 
-    pub(crate) fn try_into_memtable(self) -> Result<MemTable> {
-        MemTable::try_new(self.schema, vec![self.data])
+    fn execute(&self, ctx: Arc<TaskContext>) -> SendableRecordBatchStream {
+        let settings = ctx.session_config().get_extension::<SessionSettings>();
+        let batch = self.get_data(settings);
+        Box::pin(RecordBatchStreamAdapter::new(
+            self.schema.clone(),
+            futures::stream::once(async move { batch }),
+        ))
     }
 }
@@ -0,0 +1,369 @@
+use std::sync::Arc;
+
+use datafusion::arrow::array::{
+    ArrayRef, AsArray, Int32Array, Int64Array, RecordBatch, StringArray, TimestampMicrosecondArray,
+};
+use datafusion::arrow::datatypes::{DataType, Field, Int32Type, Schema, SchemaRef, TimeUnit};
+use datafusion::catalog::{MemTable, TableFunctionImpl, TableProvider};
+use datafusion::common::ScalarValue;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::execution::{SendableRecordBatchStream, TaskContext};
+use datafusion::logical_expr::{ColumnarValue, ScalarUDF, Volatility};
+use datafusion::physical_plan::stream::RecordBatchStreamAdapter;
+use datafusion::physical_plan::streaming::PartitionStream;
+use datafusion::prelude::{create_udf, Expr};
+
+use super::vector_types::oid_field;
+use super::{CatalogVisibility, OidRegistry};
+use crate::activity::{ActivityRegistry, SessionActivity, SessionStatistics};
+
+/// Query text shown for sessions and statements of other users to
+/// non-superusers
+pub(super) const INSUFFICIENT_PRIVILEGE: &str = "<insufficient privilege>";
+
+#[derive(Debug, Clone)]
+pub(crate) struct PgStatActivityTable {
+    schema: SchemaRef,
+    oid_registry: Arc<OidRegistry>,
+}
+
+impl PgStatActivityTable {
+    pub(crate) fn new(oid_registry: Arc<OidRegistry>) -> Self {
+        let timestamp = || DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into()));
+        // This matches PostgreSQL's pg_stat_activity view columns
+        let schema = Arc::new(Schema::new(vec![
+            oid_field("datid", true), // OID of the database the session is connected to
+            Field::new("datname", DataType::Utf8, true), // Name of the database
+            Field::new("pid", DataType::Int32, false), // Process ID of the session
+            Field::new("leader_pid", DataType::Int32, true), // Parallel group leader
+            oid_field("usesysid", true), // OID of the user
+            Field::new("usename", DataType::Utf8, true), // Name of the user
+            Field::new("application_name", DataType::Utf8, true), // Name of the client application
+            Field::new("client_addr", DataType::Utf8, true), // IP address of the client
+            Field::new("client_hostname", DataType::Utf8, true), // Host name of the client
+            Field::new("client_port", DataType::Int32, true), // TCP port of the client
+            Field::new("backend_start", timestamp(), false), // Time the session connected
+            Field::new("xact_start", timestamp(), true), // Start of the current transaction
+            Field::new("query_start", timestamp(), true), // Start of the current or last statement
+            Field::new("state_change", timestamp(), true), // Time the state last changed
+            Field::new("wait_event_type", DataType::Utf8, true), // Type of the event waited for
+            Field::new("wait_event", DataType::Utf8, true), // Name of the event waited for
+            Field::new("state", DataType::Utf8, true), // Current state of the session
+            Field::new("backend_xid", DataType::Int32, true), // Top-level transaction id
+            Field::new("backend_xmin", DataType::Int32, true), // xmin horizon of the session
+            Field::new("query_id", DataType::Int64, true), // Identifier of the statement
+            Field::new("query", DataType::Utf8, true), // Text of the current or last statement
+            Field::new("backend_type", DataType::Utf8, false), // Type of the backend
+            // Statement counters of the session, not in postgres
+            Field::new("select_count", DataType::Int64, true), // SELECT statements run
+            Field::new("insert_count", DataType::Int64, true), // INSERT statements run
+            Field::new("update_count", DataType::Int64, true), // UPDATE statements run
+            Field::new("delete_count", DataType::Int64, true), // DELETE statements run
+            Field::new("ddl_count", DataType::Int64, true),    // DDL statements run
+            Field::new("other_count", DataType::Int64, true),  // Utility statements run
+            Field::new("rows_returned", DataType::Int64, true), // Rows sent in query results
+            Field::new("bytes_sent", DataType::Int64, true),   // Bytes of the rows sent
+            Field::new("error_count", DataType::Int64, true),  // Statements that failed
+        ]));
+
+        Self {
+            schema,
+            oid_registry,
+        }
+    }
+
+    /// Generate record batches from the sessions of the activity registry
+    async fn get_data(
+        this: Self,
+        activity: Option<Arc<ActivityRegistry>>,
+        visibility: Option<Arc<CatalogVisibility>>,
+    ) -> Result<RecordBatch> {
+        let sessions = activity
+            .map(|activity| activity.sessions())
+            .unwrap_or_default();
+        let is_superuser = match &visibility {
+            Some(visibility) => visibility.is_superuser().await,
+            None => true,
+        };
+
+        let mut datids = Vec::new();
+        let mut datnames = Vec::new();
+        let mut pids = Vec::new();
+        let mut usenames = Vec::new();
+        let mut application_names = Vec::new();
+        let mut client_addrs = Vec::new();
+        let mut client_ports = Vec::new();
+        let mut backend_starts = Vec::new();
+        let mut xact_starts = Vec::new();
+        let mut query_starts = Vec::new();
+        let mut state_changes = Vec::new();
+        let mut wait_event_types = Vec::new();
+        let mut wait_events = Vec::new();
+        let mut states = Vec::new();
+        let mut query_ids = Vec::new();
+        let mut queries = Vec::new();
+        let mut backend_types = Vec::new();
+        let mut statistics = Vec::new();
+
+        for session in sessions {
+            // like postgres, only superusers see what other users are running
+            let visible = is_superuser
+                || visibility
+                    .as_ref()
+                    .is_some_and(|v| session.usename.as_deref() == Some(v.username()));
+
+            datids.push(
+                session
+                    .datname
+                    .as_ref()
+                    .map(|datname| this.oid_registry.catalog_oid(datname) as i32),
+            );
+            datnames.push(session.datname);
+            pids.push(session.pid);
+            usenames.push(session.usename);
+            application_names.push(session.application_name);
+            client_addrs.push(visible.then(|| session.client_addr.ip().to_string()));
+            client_ports.push(visible.then(|| session.client_addr.port() as i32));
+            backend_starts.push(session.backend_start.timestamp_micros());
+            let timestamp = |t: Option<chrono::DateTime<chrono::Utc>>| {
+                t.filter(|_| visible).map(|t| t.timestamp_micros())
+            };
+            xact_starts.push(timestamp(session.xact_start));
+            query_starts.push(timestamp(session.query_start));
+            state_changes.push(timestamp(session.state_change));
+            wait_event_types.push(session.wait_event.filter(|_| visible).map(|(t, _)| t));
+            wait_events.push(session.wait_event.filter(|_| visible).map(|(_, e)| e));
+            states.push(
+                session
+                    .state
+                    .filter(|_| visible)
+                    .map(|state| state.as_str()),
+            );
+            query_ids.push(session.query_id.filter(|_| visible));
+            queries.push(if visible {
+                session.query
+            } else {
+                INSUFFICIENT_PRIVILEGE.to_string()
+            });
+            backend_types.push("client backend");
+            statistics.push(visible.then_some(session.statistics));
+        }
+
+        let rows = pids.len();
+        let nulls_i32 = || Arc::new(Int32Array::from(vec![None; rows])) as ArrayRef;
+        let timestamps = |values: Vec<Option<i64>>| {
+            Arc::new(TimestampMicrosecondArray::from(values).with_timezone("UTC")) as ArrayRef
+        };
+
+        let mut arrays: Vec<ArrayRef> = vec![
+            Arc::new(Int32Array::from(datids)),
+            Arc::new(StringArray::from(datnames)),
+            Arc::new(Int32Array::from(pids)),
+            nulls_i32(),
+            nulls_i32(),
+            Arc::new(StringArray::from(usenames)),
+            Arc::new(StringArray::from(application_names)),
+            Arc::new(StringArray::from(client_addrs)),
+            Arc::new(StringArray::from(vec![None::<String>; rows])),
+            Arc::new(Int32Array::from(client_ports)),
+            Arc::new(TimestampMicrosecondArray::from(backend_starts).with_timezone("UTC")),
+            timestamps(xact_starts),
+            timestamps(query_starts),
+            timestamps(state_changes),
+            Arc::new(StringArray::from(wait_event_types)),
+            Arc::new(StringArray::from(wait_events)),
+            Arc::new(StringArray::from(states)),
+            nulls_i32(),
+            nulls_i32(),
+            Arc::new(Int64Array::from(query_ids)),
+            Arc::new(StringArray::from(queries)),
+            Arc::new(StringArray::from(backend_types)),
+        ];
+        let counter = |f: fn(&SessionStatistics) -> u64| {
+            let counts = statistics
+                .iter()
+                .map(|s| s.as_ref().map(|s| f(s) as i64))
+                .collect::<Int64Array>();
+            Arc::new(counts) as ArrayRef
+        };
+        arrays.extend([
+            counter(|s| s.select_statements),
+            counter(|s| s.insert_statements),
+            counter(|s| s.update_statements),
+            counter(|s| s.delete_statements),
+            counter(|s| s.ddl_statements),
+            counter(|s| s.other_statements),
+            counter(|s| s.rows_returned),
+            counter(|s| s.bytes_sent),
+            counter(|s| s.errors),
+        ]);
+
+        let batch = RecordBatch::try_new(this.schema.clone(), arrays)?;
+        Ok(batch)
+    }
+}
+
+impl PartitionStream for PgStatActivityTable {
+    fn schema(&self) -> &SchemaRef {
+        &self.schema
+    }
+
+    fn execute(&self, ctx: Arc<TaskContext>) -> SendableRecordBatchStream {
+        let this = self.clone();
+        let activity = ctx.session_config().get_extension::<ActivityRegistry>();
+        let visibility = CatalogVisibility::from_task_context(&ctx);
+        Box::pin(RecordBatchStreamAdapter::new(
+            this.schema.clone(),
+            futures::stream::once(async move { Self::get_data(this, activity, visibility).await }),
+        ))
+    }
+}
+
+/// Backend ids of the sessions, which are their pids
+#[derive(Debug)]
+struct PgStatGetBackendIdset {
+    activity: Arc<ActivityRegistry>,
+}
+
+impl TableFunctionImpl for PgStatGetBackendIdset {
+    fn call(&self, _args: &[Expr]) -> Result<Arc<dyn TableProvider>> {
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "pg_stat_get_backend_idset",
+            DataType::Int32,
+            false,
+        )]));
+        let pids = self
+            .activity
+            .sessions()
+            .into_iter()
+            .map(|session| session.pid)
+            .collect::<Int32Array>();
+        let batch = RecordBatch::try_new(schema.clone(), vec![Arc::new(pids)])?;
+        Ok(Arc::new(MemTable::try_new(schema, vec![vec![batch]])?))
+    }
+}
+
+/// `pg_stat_get_backend_*(backend_id)` function returning `value` of the
+/// session, NULL for unknown backends
+fn backend_udf(
+    name: &str,
+    activity: Arc<ActivityRegistry>,
+    value: fn(&SessionActivity) -> i64,
+) -> ScalarUDF {
+    let func = move |args: &[ColumnarValue]| {
+        let args = ColumnarValue::values_to_arrays(args)?;
+        let sessions = activity.sessions();
+        let values = args[0]
+            .as_primitive::<Int32Type>()
+            .iter()
+            .map(|backend_id| {
+                let session = sessions.iter().find(|s| Some(s.pid) == backend_id)?;
+                Some(value(session))
+            })
+            .collect::<Int64Array>();
+        Ok(ColumnarValue::Array(Arc::new(values)))
+    };
+
+    create_udf(
+        name,
+        vec![DataType::Int32],
+        DataType::Int64,
+        Volatility::Volatile,
+        Arc::new(func),
+    )
+}
+
+/// Targets of `pg_stat_reset_shared`, the statistics postgres shares between
+/// its processes
+const SHARED_STATISTICS: &[&str] = &[
+    "archiver",
+    "bgwriter",
+    "checkpointer",
+    "io",
+    "recovery_prefetch",
+    "slru",
+    "wal",
+];
+
+/// `pg_stat_reset()` zeroing the statistics of all sessions, and
+/// `pg_stat_statements_reset()` also forgetting the statistics of
+/// `pg_stat_statements` and returning the time of the reset
+fn reset_udfs(activity: Arc<ActivityRegistry>) -> Vec<ScalarUDF> {
+    let reset = activity.clone();
+    let pg_stat_reset = move |_args: &[ColumnarValue]| {
+        reset.reset_statistics();
+        Ok(ColumnarValue::Scalar(ScalarValue::Null))
+    };
+    let pg_stat_statements_reset = move |_args: &[ColumnarValue]| {
+        activity.reset_statements();
+        let reset = activity.reset_statistics();
+        Ok(ColumnarValue::Scalar(ScalarValue::TimestampMicrosecond(
+            Some(reset.timestamp_micros()),
+            Some("UTC".into()),
+        )))
+    };
+    // there are no shared statistics to zero, only the target is checked
+    let pg_stat_reset_shared = move |args: &[ColumnarValue]| {
+        let args = ColumnarValue::values_to_arrays(args)?;
+        for target in args[0].as_string::<i32>().iter().flatten() {
+            if !SHARED_STATISTICS.contains(&target) {
+                return Err(DataFusionError::Configuration(format!(
+                    "unrecognized reset target: \"{target}\""
+                )));
+            }
+        }
+        Ok(ColumnarValue::Scalar(ScalarValue::Null))
+    };
+
+    vec![
+        create_udf(
+            "pg_stat_reset",
+            vec![],
+            DataType::Null,
+            Volatility::Volatile,
+            Arc::new(pg_stat_reset),
+        ),
+        create_udf(
+            "pg_stat_statements_reset",
+            vec![],
+            DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into())),
+            Volatility::Volatile,
+            Arc::new(pg_stat_statements_reset),
+        ),
+        create_udf(
+            "pg_stat_reset_shared",
+            vec![DataType::Utf8],
+            DataType::Null,
+            Volatility::Volatile,
+            Arc::new(pg_stat_reset_shared),
+        ),
+    ]
+}
+
+/// Functions reading and resetting the statistics of sessions of the
+/// registry
+///
+/// Like `pg_stat_get_backend_idset`, backend ids are the pids of sessions.
+pub(crate) fn pg_stat_backend_functions(
+    activity: Arc<ActivityRegistry>,
+) -> (Vec<ScalarUDF>, Arc<dyn TableFunctionImpl>) {
+    let mut udfs = vec![
+        backend_udf("pg_stat_get_backend_pid", activity.clone(), |s| {
+            s.pid as i64
+        }),
+        backend_udf("pg_stat_get_backend_statements", activity.clone(), |s| {
+            s.statistics.statements() as i64
+        }),
+        backend_udf("pg_stat_get_backend_rows_returned", activity.clone(), |s| {
+            s.statistics.rows_returned as i64
+        }),
+        backend_udf("pg_stat_get_backend_bytes_sent", activity.clone(), |s| {
+            s.statistics.bytes_sent as i64
+        }),
+        backend_udf("pg_stat_get_backend_errors", activity.clone(), |s| {
+            s.statistics.errors as i64
+        }),
+    ];
+    udfs.extend(reset_udfs(activity.clone()));
+    (udfs, Arc::new(PgStatGetBackendIdset { activity }))
+}
@@ -0,0 +1,139 @@
+use std::sync::Arc;
+
+use datafusion::arrow::array::{
+    ArrayRef, BooleanArray, Float64Array, Int32Array, Int64Array, RecordBatch, StringArray,
+};
+use datafusion::arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use datafusion::error::Result;
+use datafusion::execution::{SendableRecordBatchStream, TaskContext};
+use datafusion::physical_plan::stream::RecordBatchStreamAdapter;
+use datafusion::physical_plan::streaming::PartitionStream;
+
+use super::pg_stat_activity::INSUFFICIENT_PRIVILEGE;
+use super::vector_types::oid_field;
+use super::{CatalogVisibility, OidRegistry};
+use crate::activity::ActivityRegistry;
+
+#[derive(Debug, Clone)]
+pub(crate) struct PgStatStatementsTable {
+    schema: SchemaRef,
+    oid_registry: Arc<OidRegistry>,
+}
+
+impl PgStatStatementsTable {
+    pub(crate) fn new(oid_registry: Arc<OidRegistry>) -> Self {
+        // This matches the leading columns of PostgreSQL's pg_stat_statements
+        // view
+        let schema = Arc::new(Schema::new(vec![
+            oid_field("userid", true), // OID of the user who ran the statement
+            oid_field("dbid", true),   // OID of the database the statement ran in
+            Field::new("toplevel", DataType::Boolean, false), // Run as a top-level statement
+            Field::new("queryid", DataType::Int64, true), // Identifier of the statement
+            Field::new("query", DataType::Utf8, false), // Normalized text of the statement
+            Field::new("calls", DataType::Int64, false), // Times the statement ran
+            Field::new("total_exec_time", DataType::Float64, false), // Total time spent, in ms
+            Field::new("min_exec_time", DataType::Float64, false), // Minimum time spent, in ms
+            Field::new("max_exec_time", DataType::Float64, false), // Maximum time spent, in ms
+            Field::new("mean_exec_time", DataType::Float64, false), // Mean time spent, in ms
+            Field::new("rows", DataType::Int64, false), // Rows returned by the statement
+        ]));
+
+        Self {
+            schema,
+            oid_registry,
+        }
+    }
+
+    /// Generate record batches from the statement statistics of the activity
+    /// registry
+    async fn get_data(
+        this: Self,
+        activity: Option<Arc<ActivityRegistry>>,
+        visibility: Option<Arc<CatalogVisibility>>,
+    ) -> Result<RecordBatch> {
+        let statements = activity
+            .map(|activity| activity.statements())
+            .unwrap_or_default();
+        let is_superuser = match &visibility {
+            Some(visibility) => visibility.is_superuser().await,
+            None => true,
+        };
+
+        let mut userids = Vec::with_capacity(statements.len());
+        let mut dbids = Vec::with_capacity(statements.len());
+        let mut queryids = Vec::with_capacity(statements.len());
+        let mut queries = Vec::with_capacity(statements.len());
+        let mut calls = Vec::with_capacity(statements.len());
+        let mut total_exec_times = Vec::with_capacity(statements.len());
+        let mut min_exec_times = Vec::with_capacity(statements.len());
+        let mut max_exec_times = Vec::with_capacity(statements.len());
+        let mut mean_exec_times = Vec::with_capacity(statements.len());
+        let mut rows = Vec::with_capacity(statements.len());
+
+        for statement in statements {
+            // like postgres, only superusers see the statements of other users
+            let visible = is_superuser
+                || visibility
+                    .as_ref()
+                    .is_some_and(|v| statement.usename.as_deref() == Some(v.username()));
+
+            userids.push(
+                statement
+                    .usename
+                    .as_ref()
+                    .map(|usename| this.oid_registry.role_oid(usename) as i32),
+            );
+            dbids.push(
+                statement
+                    .datname
+                    .as_ref()
+                    .map(|datname| this.oid_registry.catalog_oid(datname) as i32),
+            );
+            queryids.push(visible.then_some(statement.query_id));
+            queries.push(if visible {
+                statement.query
+            } else {
+                INSUFFICIENT_PRIVILEGE.to_string()
+            });
+            calls.push(statement.calls as i64);
+            total_exec_times.push(statement.total_exec_time);
+            min_exec_times.push(statement.min_exec_time);
+            max_exec_times.push(statement.max_exec_time);
+            mean_exec_times.push(statement.total_exec_time / statement.calls as f64);
+            rows.push(statement.rows as i64);
+        }
+
+        let arrays: Vec<ArrayRef> = vec![
+            Arc::new(Int32Array::from(userids)),
+            Arc::new(Int32Array::from(dbids)),
+            Arc::new(BooleanArray::from(vec![true; calls.len()])),
+            Arc::new(Int64Array::from(queryids)),
+            Arc::new(StringArray::from(queries)),
+            Arc::new(Int64Array::from(calls)),
+            Arc::new(Float64Array::from(total_exec_times)),
+            Arc::new(Float64Array::from(min_exec_times)),
+            Arc::new(Float64Array::from(max_exec_times)),
+            Arc::new(Float64Array::from(mean_exec_times)),
+            Arc::new(Int64Array::from(rows)),
+        ];
+
+        let batch = RecordBatch::try_new(this.schema.clone(), arrays)?;
+        Ok(batch)
+    }
+}
+
+impl PartitionStream for PgStatStatementsTable {
+    fn schema(&self) -> &SchemaRef {
+        &self.schema
+    }
+
+    fn execute(&self, ctx: Arc<TaskContext>) -> SendableRecordBatchStream {
+        let this = self.clone();
+        let activity = ctx.session_config().get_extension::<ActivityRegistry>();
+        let visibility = CatalogVisibility::from_task_context(&ctx);
+        Box::pin(RecordBatchStreamAdapter::new(
+            this.schema.clone(),
+            futures::stream::once(async move { Self::get_data(this, activity, visibility).await }),
+        ))
+    }
+}
@@ -0,0 +1,233 @@
+use std::sync::Arc;
+
+use datafusion::arrow::array::{
+    ArrayRef, BooleanArray, Float32Array, Int32Array, Int64Array, RecordBatch, StringArray,
+    TimestampMicrosecondArray,
+};
+use datafusion::arrow::datatypes::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use datafusion::error::Result;
+use datafusion::execution::{SendableRecordBatchStream, TaskContext};
+use datafusion::physical_plan::stream::RecordBatchStreamAdapter;
+use datafusion::physical_plan::streaming::PartitionStream;
+
+use super::vector_types::oid_field;
+use super::{CatalogVisibility, OidRegistry};
+use crate::analyze::{StatisticsRegistry, TableKey, TableStatistics};
+
+/// The analyzed tables the user may see
+async fn visible_tables(
+    statistics: Option<Arc<StatisticsRegistry>>,
+    visibility: Option<Arc<CatalogVisibility>>,
+) -> Vec<(TableKey, TableStatistics)> {
+    let tables = statistics
+        .map(|statistics| statistics.tables())
+        .unwrap_or_default();
+    let Some(visibility) = visibility else {
+        return tables;
+    };
+    let mut visible = Vec::new();
+    for ((catalog, schema, table), statistics) in tables {
+        if visibility.is_catalog_visible(&catalog)
+            && visibility.is_table_visible(&schema, &table).await
+        {
+            visible.push(((catalog, schema, table), statistics));
+        }
+    }
+    visible
+}
+
+/// `pg_stats`, a row per column of the analyzed tables
+#[derive(Debug, Clone)]
+pub(crate) struct PgStatsTable {
+    schema: SchemaRef,
+}
+
+impl PgStatsTable {
+    pub(crate) fn new() -> Self {
+        // This matches PostgreSQL's pg_stats view columns
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("schemaname", DataType::Utf8, false), // Schema of the table
+            Field::new("tablename", DataType::Utf8, false),  // Name of the table
+            Field::new("attname", DataType::Utf8, false),    // Name of the column
+            Field::new("inherited", DataType::Boolean, false), // Whether child tables are included
+            Field::new("null_frac", DataType::Float32, false), // Fraction of the values that are null
+            Field::new("avg_width", DataType::Int32, true), // Average width in bytes of the values
+            Field::new("n_distinct", DataType::Float32, true), // Number of distinct values
+            Field::new("most_common_vals", DataType::Utf8, true), // Most common values
+            Field::new("most_common_freqs", DataType::Utf8, true), // Frequencies of the most common values
+            Field::new("histogram_bounds", DataType::Utf8, true), // Bounds of the histogram, the minimum and maximum here
+            Field::new("correlation", DataType::Float32, true), // Correlation of physical and logical order
+        ]));
+
+        Self { schema }
+    }
+
+    async fn get_data(
+        this: Self,
+        statistics: Option<Arc<StatisticsRegistry>>,
+        visibility: Option<Arc<CatalogVisibility>>,
+    ) -> Result<RecordBatch> {
+        let mut schemanames = Vec::new();
+        let mut tablenames = Vec::new();
+        let mut attnames = Vec::new();
+        let mut null_fracs = Vec::new();
+        let mut histogram_bounds = Vec::new();
+
+        for ((_, schema, table), statistics) in visible_tables(statistics, visibility).await {
+            for column in statistics.columns {
+                schemanames.push(schema.clone());
+                tablenames.push(table.clone());
+                attnames.push(column.name);
+                null_fracs.push(if statistics.row_count == 0 {
+                    0.0
+                } else {
+                    column.null_count as f32 / statistics.row_count as f32
+                });
+                histogram_bounds.push(
+                    column
+                        .min_value
+                        .zip(column.max_value)
+                        .map(|(min, max)| format!("{{{min},{max}}}")),
+                );
+            }
+        }
+
+        let rows = attnames.len();
+        let arrays: Vec<ArrayRef> = vec![
+            Arc::new(StringArray::from(schemanames)),
+            Arc::new(StringArray::from(tablenames)),
+            Arc::new(StringArray::from(attnames)),
+            Arc::new(BooleanArray::from(vec![false; rows])),
+            Arc::new(Float32Array::from(null_fracs)),
+            Arc::new(Int32Array::from(vec![None; rows])),
+            Arc::new(Float32Array::from(vec![None; rows])),
+            Arc::new(StringArray::from(vec![None::<String>; rows])),
+            Arc::new(StringArray::from(vec![None::<String>; rows])),
+            Arc::new(StringArray::from(histogram_bounds)),
+            Arc::new(Float32Array::from(vec![None; rows])),
+        ];
+
+        let batch = RecordBatch::try_new(this.schema.clone(), arrays)?;
+
+        Ok(batch)
+    }
+}
+
+impl PartitionStream for PgStatsTable {
+    fn schema(&self) -> &SchemaRef {
+        &self.schema
+    }
+
+    fn execute(&self, ctx: Arc<TaskContext>) -> SendableRecordBatchStream {
+        let this = self.clone();
+        let statistics = ctx.session_config().get_extension::<StatisticsRegistry>();
+        let visibility = CatalogVisibility::from_task_context(&ctx);
+        Box::pin(RecordBatchStreamAdapter::new(
+            this.schema.clone(),
+            futures::stream::once(async move {
+                PgStatsTable::get_data(this, statistics, visibility).await
+            }),
+        ))
+    }
+}
+
+/// `pg_stat_user_tables`, a row per analyzed table
+#[derive(Debug, Clone)]
+pub(crate) struct PgStatUserTablesTable {
+    schema: SchemaRef,
+    oid_registry: Arc<OidRegistry>,
+}
+
+impl PgStatUserTablesTable {
+    pub(crate) fn new(oid_registry: Arc<OidRegistry>) -> Self {
+        let timestamp = || DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into()));
+        // This matches the columns of PostgreSQL's pg_stat_user_tables view
+        // that ANALYZE maintains
+        let schema = Arc::new(Schema::new(vec![
+            oid_field("relid", false),                               // OID of the table
+            Field::new("schemaname", DataType::Utf8, false),         // Schema of the table
+            Field::new("relname", DataType::Utf8, false),            // Name of the table
+            Field::new("n_live_tup", DataType::Int64, false), // Estimated number of live rows
+            Field::new("n_dead_tup", DataType::Int64, false), // Estimated number of dead rows
+            Field::new("last_vacuum", timestamp(), true),     // Last time the table was vacuumed
+            Field::new("last_autovacuum", timestamp(), true), // Last time the table was autovacuumed
+            Field::new("last_analyze", timestamp(), true),    // Last time the table was analyzed
+            Field::new("last_autoanalyze", timestamp(), true), // Last time the table was autoanalyzed
+            Field::new("vacuum_count", DataType::Int64, false), // Times the table was vacuumed
+            Field::new("autovacuum_count", DataType::Int64, false), // Times the table was autovacuumed
+            Field::new("analyze_count", DataType::Int64, false),    // Times the table was analyzed
+            Field::new("autoanalyze_count", DataType::Int64, false), // Times the table was autoanalyzed
+        ]));
+
+        Self {
+            schema,
+            oid_registry,
+        }
+    }
+
+    async fn get_data(
+        this: Self,
+        statistics: Option<Arc<StatisticsRegistry>>,
+        visibility: Option<Arc<CatalogVisibility>>,
+    ) -> Result<RecordBatch> {
+        let mut relids = Vec::new();
+        let mut schemanames = Vec::new();
+        let mut relnames = Vec::new();
+        let mut n_live_tups = Vec::new();
+        let mut last_analyzes = Vec::new();
+        let mut analyze_counts = Vec::new();
+
+        for ((catalog, schema, table), statistics) in visible_tables(statistics, visibility).await {
+            relids.push(this.oid_registry.table_oid(&catalog, &schema, &table) as i32);
+            schemanames.push(schema);
+            relnames.push(table);
+            n_live_tups.push(statistics.row_count as i64);
+            last_analyzes.push(Some(statistics.last_analyze.timestamp_micros()));
+            analyze_counts.push(statistics.analyze_count as i64);
+        }
+
+        let rows = relids.len();
+        let nulls_timestamp = || {
+            Arc::new(TimestampMicrosecondArray::from(vec![None; rows]).with_timezone("UTC"))
+                as ArrayRef
+        };
+        let zeros = || Arc::new(Int64Array::from(vec![0; rows])) as ArrayRef;
+        let arrays: Vec<ArrayRef> = vec![
+            Arc::new(Int32Array::from(relids)),
+            Arc::new(StringArray::from(schemanames)),
+            Arc::new(StringArray::from(relnames)),
+            Arc::new(Int64Array::from(n_live_tups)),
+            zeros(),
+            nulls_timestamp(),
+            nulls_timestamp(),
+            Arc::new(TimestampMicrosecondArray::from(last_analyzes).with_timezone("UTC")),
+            nulls_timestamp(),
+            zeros(),
+            zeros(),
+            Arc::new(Int64Array::from(analyze_counts)),
+            zeros(),
+        ];
+
+        let batch = RecordBatch::try_new(this.schema.clone(), arrays)?;
+
+        Ok(batch)
+    }
+}
+
+impl PartitionStream for PgStatUserTablesTable {
+    fn schema(&self) -> &SchemaRef {
+        &self.schema
+    }
+
+    fn execute(&self, ctx: Arc<TaskContext>) -> SendableRecordBatchStream {
+        let this = self.clone();
+        let statistics = ctx.session_config().get_extension::<StatisticsRegistry>();
+        let visibility = CatalogVisibility::from_task_context(&ctx);
+        Box::pin(RecordBatchStreamAdapter::new(
+            this.schema.clone(),
+            futures::stream::once(async move {
+                PgStatUserTablesTable::get_data(this, statistics, visibility).await
+            }),
+        ))
+    }
+}
@@ -0,0 +1,85 @@
+use std::sync::Arc;
+
+use chrono::{TimeZone, Utc};
+use chrono_tz::{OffsetComponents, OffsetName, TZ_VARIANTS};
+use datafusion::arrow::array::{
+    ArrayRef, BooleanArray, IntervalMonthDayNanoArray, RecordBatch, StringArray,
+};
+use datafusion::arrow::datatypes::{
+    DataType, Field, IntervalMonthDayNano, IntervalUnit, Schema, SchemaRef,
+};
+use datafusion::error::Result;
+use datafusion::execution::{SendableRecordBatchStream, TaskContext};
+use datafusion::physical_plan::stream::RecordBatchStreamAdapter;
+use datafusion::physical_plan::streaming::PartitionStream;
+
+/// The name of the time zone `name` as `pg_timezone_names` lists it, which is
+/// found case insensitively like in postgres
+pub(crate) fn time_zone_name(name: &str) -> Option<&'static str> {
+    TZ_VARIANTS
+        .iter()
+        .find(|tz| tz.name().eq_ignore_ascii_case(name))
+        .map(|tz| tz.name())
+}
+
+/// `pg_timezone_names`, the time zones `SET TIME ZONE` and `timezone()`
+/// accept, with their current offset from UTC
+#[derive(Debug, Clone)]
+pub(crate) struct PgTimezoneNamesTable {
+    schema: SchemaRef,
+}
+
+impl PgTimezoneNamesTable {
+    pub(crate) fn new() -> Self {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("name", DataType::Utf8, false), // Time zone name
+            Field::new("abbrev", DataType::Utf8, false), // Time zone abbreviation
+            Field::new(
+                "utc_offset",
+                DataType::Interval(IntervalUnit::MonthDayNano),
+                false,
+            ), // Offset from UTC, positive east of Greenwich
+            Field::new("is_dst", DataType::Boolean, false), // Whether daylight savings is in effect
+        ]));
+
+        Self { schema }
+    }
+
+    fn get_data(this: Self) -> Result<RecordBatch> {
+        let now = Utc::now().naive_utc();
+        let mut names = Vec::with_capacity(TZ_VARIANTS.len());
+        let mut abbrevs = Vec::with_capacity(TZ_VARIANTS.len());
+        let mut utc_offsets = Vec::with_capacity(TZ_VARIANTS.len());
+        let mut is_dsts = Vec::with_capacity(TZ_VARIANTS.len());
+        for tz in TZ_VARIANTS {
+            let offset = tz.offset_from_utc_datetime(&now);
+            let seconds = (offset.base_utc_offset() + offset.dst_offset()).num_seconds();
+            names.push(tz.name());
+            abbrevs.push(offset.abbreviation().unwrap_or_default().to_string());
+            utc_offsets.push(IntervalMonthDayNano::new(0, 0, seconds * 1_000_000_000));
+            is_dsts.push(!offset.dst_offset().is_zero());
+        }
+
+        let arrays: Vec<ArrayRef> = vec![
+            Arc::new(StringArray::from(names)),
+            Arc::new(StringArray::from(abbrevs)),
+            Arc::new(IntervalMonthDayNanoArray::from(utc_offsets)),
+            Arc::new(BooleanArray::from(is_dsts)),
+        ];
+        Ok(RecordBatch::try_new(this.schema.clone(), arrays)?)
+    }
+}
+
+impl PartitionStream for PgTimezoneNamesTable {
+    fn schema(&self) -> &SchemaRef {
+        &self.schema
+    }
+
+    fn execute(&self, _ctx: Arc<TaskContext>) -> SendableRecordBatchStream {
+        let this = self.clone();
+        Box::pin(RecordBatchStreamAdapter::new(
+            this.schema.clone(),
+            futures::stream::once(async move { Self::get_data(this) }),
+        ))
+    }
+}
@@ -0,0 +1,158 @@
+//! Catalog columns of the postgres types `oid`, `int2vector`, `oidvector` and
+//! `anyarray`.
+//!
+//! The static tables store them, and arrays of oids, as text. They are served
+//! as lists, tagged with their postgres type for `arrow_pg` to send them in
+//! the text format of postgres, e.g. `1 2` for the `indkey` of an index on two
+//! columns.
+//!
+//! Oids are `UInt32` in the static tables and `Int32` in the others, tagged
+//! as `oid` so drivers checking the type of e.g. `pg_class.oid` see `oid`.
+
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use arrow_pg::datatypes::PG_TYPE_METADATA_KEY;
+use datafusion::arrow::array::{Array, ArrayRef, AsArray, ListArray, RecordBatch};
+use datafusion::arrow::datatypes::{
+    ArrowPrimitiveType, DataType, Field, Int16Type, Schema, SchemaRef, UInt32Type,
+};
+use datafusion::common::{plan_err, ScalarValue};
+use datafusion::error::Result;
+use postgres_types::Oid;
+
+/// Columns of the static tables stored as text, with their postgres type
+const VECTOR_COLUMNS: &[(&str, &str)] = &[
+    ("indkey", "int2vector"),
+    ("indoption", "int2vector"),
+    ("indclass", "oidvector"),
+    ("indcollation", "oidvector"),
+    ("partattrs", "int2vector"),
+    ("partclass", "oidvector"),
+    ("partcollation", "oidvector"),
+    ("proargtypes", "oidvector"),
+    ("proallargtypes", "oid[]"),
+    ("stxkeys", "int2vector"),
+    ("tgattr", "int2vector"),
+    ("stavalues1", "anyarray"),
+    ("stavalues2", "anyarray"),
+    ("stavalues3", "anyarray"),
+    ("stavalues4", "anyarray"),
+    ("stavalues5", "anyarray"),
+];
+
+/// Metadata tagging a field with its postgres type
+fn pg_type_metadata(pg_type: &str) -> HashMap<String, String> {
+    HashMap::from([(PG_TYPE_METADATA_KEY.to_string(), pg_type.to_string())])
+}
+
+/// Field of the postgres type `pg_type`, over its arrow type
+fn field(name: &str, pg_type: &str) -> Field {
+    let data_type = match pg_type {
+        "int2vector" => DataType::new_list(DataType::Int16, true),
+        "oidvector" | "oid[]" => DataType::new_list(DataType::UInt32, true),
+        _ => DataType::Utf8,
+    };
+    let field = Field::new(name, data_type, true);
+    // arrays are lists anyway
+    if pg_type.ends_with("[]") {
+        return field;
+    }
+    field.with_metadata(pg_type_metadata(pg_type))
+}
+
+/// Field of the postgres type `oid`, over `Int32`
+pub(crate) fn oid_field(name: &str, nullable: bool) -> Field {
+    Field::new(name, DataType::Int32, nullable).with_metadata(pg_type_metadata("oid"))
+}
+
+/// `oidvector` of `oids`, e.g. the `proargtypes` of a function
+pub(crate) fn oid_vector(oids: &[Oid]) -> ScalarValue {
+    let list = ListArray::from_iter_primitive::<UInt32Type, _, _>([Some(
+        oids.iter().map(|oid| Some(*oid)),
+    )]);
+    ScalarValue::List(Arc::new(list))
+}
+
+/// Parse the space separated text of vectors, or the elements of arrays
+/// stored like `[1, 2]`
+fn parse_vectors<T>(array: &ArrayRef, pg_type: &str) -> Result<ArrayRef>
+where
+    T: ArrowPrimitiveType,
+    T::Native: FromStr,
+{
+    let mut vectors = Vec::with_capacity(array.len());
+    for text in array.as_string::<i32>().iter() {
+        let Some(text) = text else {
+            vectors.push(None);
+            continue;
+        };
+        let Ok(values) = text
+            .trim_matches(['[', ']'])
+            .split([' ', ','])
+            .filter(|value| !value.is_empty())
+            .map(|value| value.parse::<T::Native>().map(Some))
+            .collect::<std::result::Result<Vec<_>, _>>()
+        else {
+            return plan_err!("Invalid {pg_type} {text} in the pg_catalog data");
+        };
+        vectors.push(Some(values));
+    }
+    Ok(Arc::new(ListArray::from_iter_primitive::<T, _, _>(vectors)))
+}
+
+/// The schema and batches of a static table with its columns of emulated
+/// types converted
+pub(crate) fn emulate_vector_types(
+    schema: SchemaRef,
+    batches: Vec<RecordBatch>,
+) -> Result<(SchemaRef, Vec<RecordBatch>)> {
+    let emulated = schema
+        .fields()
+        .iter()
+        .map(|field| match field.data_type() {
+            // all unsigned integers of the exported rows are oids
+            DataType::UInt32 => Some("oid"),
+            DataType::Utf8 => VECTOR_COLUMNS
+                .iter()
+                .find(|(name, _)| name == field.name())
+                .map(|(_, pg_type)| *pg_type),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    if emulated.iter().all(Option::is_none) {
+        return Ok((schema, batches));
+    }
+
+    let fields = schema
+        .fields()
+        .iter()
+        .zip(&emulated)
+        .map(|(f, pg_type)| match pg_type {
+            Some("oid") => Arc::new(f.as_ref().clone().with_metadata(pg_type_metadata("oid"))),
+            Some(pg_type) => Arc::new(field(f.name(), pg_type)),
+            None => f.clone(),
+        })
+        .collect::<Vec<_>>();
+    let schema = Arc::new(Schema::new_with_metadata(fields, schema.metadata().clone()));
+    let batches = batches
+        .into_iter()
+        .map(|batch| {
+            let columns = batch
+                .columns()
+                .iter()
+                .zip(&emulated)
+                .map(|(column, pg_type)| match pg_type {
+                    Some(pg_type @ "int2vector") => parse_vectors::<Int16Type>(column, pg_type),
+                    Some(pg_type @ ("oidvector" | "oid[]")) => {
+                        parse_vectors::<UInt32Type>(column, pg_type)
+                    }
+                    _ => Ok(column.clone()),
+                })
+                .collect::<Result<Vec<_>>>()?;
+            Ok(RecordBatch::try_new(schema.clone(), columns)?)
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok((schema, batches))
+}
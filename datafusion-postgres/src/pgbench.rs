@@ -0,0 +1,112 @@
+//! The tables of pgbench, to load test the server with its select-only
+//! script, `pgbench -S`.
+//!
+//! [`setup_pgbench_tables`] registers `pgbench_accounts`,
+//! `pgbench_branches`, `pgbench_tellers` and `pgbench_history` as memory
+//! tables with the rows `pgbench -i` would generate for a scale factor, so
+//! the server can be benchmarked without initializing them over the wire.
+
+use std::sync::Arc;
+
+use datafusion::arrow::array::{ArrayRef, Int32Array, RecordBatch, StringArray};
+use datafusion::arrow::datatypes::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use datafusion::datasource::MemTable;
+use datafusion::error::Result;
+use datafusion::prelude::SessionContext;
+
+/// Accounts of a branch, rows of `pgbench_accounts` per scale factor
+pub const ACCOUNTS_PER_BRANCH: i32 = 100_000;
+
+/// Tellers of a branch, rows of `pgbench_tellers` per scale factor
+pub const TELLERS_PER_BRANCH: i32 = 10;
+
+fn id_balance_filler_schema(id: &str, balance: &str) -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        Field::new(id, DataType::Int32, false),
+        Field::new("bid", DataType::Int32, true),
+        Field::new(balance, DataType::Int32, true),
+        Field::new("filler", DataType::Utf8, true),
+    ]))
+}
+
+/// Rows of the `ids` of a table, with the branch of each id, a zero balance
+/// and `filler`
+fn rows(
+    schema: &SchemaRef,
+    ids: std::ops::RangeInclusive<i32>,
+    per_branch: i32,
+    filler: Option<&str>,
+) -> Result<RecordBatch> {
+    let len = ids.clone().count();
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(Int32Array::from_iter_values(ids.clone())),
+        Arc::new(Int32Array::from_iter_values(
+            ids.map(|id| (id - 1) / per_branch + 1),
+        )),
+        Arc::new(Int32Array::from(vec![0; len])),
+        Arc::new(StringArray::from(vec![filler; len])),
+    ];
+    Ok(RecordBatch::try_new(schema.clone(), columns)?)
+}
+
+/// Register the pgbench tables of `scale` branches in the default schema of
+/// `session_context`, replacing tables of the same names
+pub fn setup_pgbench_tables(session_context: &SessionContext, scale: u32) -> Result<()> {
+    let scale = scale as i32;
+
+    let branches = Arc::new(Schema::new(vec![
+        Field::new("bid", DataType::Int32, false),
+        Field::new("bbalance", DataType::Int32, true),
+        Field::new("filler", DataType::Utf8, true),
+    ]));
+    let branch_rows = RecordBatch::try_new(
+        branches.clone(),
+        vec![
+            Arc::new(Int32Array::from_iter_values(1..=scale)),
+            Arc::new(Int32Array::from(vec![0; scale as usize])),
+            Arc::new(StringArray::from(vec![None::<&str>; scale as usize])),
+        ],
+    )?;
+
+    let tellers = id_balance_filler_schema("tid", "tbalance");
+    let teller_rows = rows(
+        &tellers,
+        1..=scale * TELLERS_PER_BRANCH,
+        TELLERS_PER_BRANCH,
+        None,
+    )?;
+
+    // a batch of accounts per branch, filled with blanks like `pgbench -i`
+    let accounts = id_balance_filler_schema("aid", "abalance");
+    let account_rows = (0..scale)
+        .map(|branch| {
+            let first = branch * ACCOUNTS_PER_BRANCH + 1;
+            let last = (branch + 1) * ACCOUNTS_PER_BRANCH;
+            rows(&accounts, first..=last, ACCOUNTS_PER_BRANCH, Some(""))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let history = Arc::new(Schema::new(vec![
+        Field::new("tid", DataType::Int32, true),
+        Field::new("bid", DataType::Int32, true),
+        Field::new("aid", DataType::Int32, true),
+        Field::new("delta", DataType::Int32, true),
+        Field::new(
+            "mtime",
+            DataType::Timestamp(TimeUnit::Microsecond, None),
+            true,
+        ),
+        Field::new("filler", DataType::Utf8, true),
+    ]));
+
+    for (name, schema, batches) in [
+        ("pgbench_branches", branches, vec![branch_rows]),
+        ("pgbench_tellers", tellers, vec![teller_rows]),
+        ("pgbench_accounts", accounts, account_rows),
+        ("pgbench_history", history, vec![]),
+    ] {
+        let table = MemTable::try_new(schema, vec![batches])?;
+        session_context.register_table(name, Arc::new(table))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,136 @@
+//! Buffering of the responses to pipelined extended query messages.
+//!
+//! Drivers batching statements (Npgsql, libpq pipeline mode) send many
+//! Parse, Bind, Describe and Execute messages before a Flush or Sync. As in
+//! postgres, their responses are buffered and delivered together when the
+//! client asks for them with Flush or Sync, instead of a write per message.
+//! Notices and errors are still delivered at once, for clients to learn
+//! about progress and failures while the batch runs.
+
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures::Sink;
+use pgwire::api::{ClientInfo, ClientPortalStore, PgWireConnectionState};
+use pgwire::messages::response::TransactionStatus;
+use pgwire::messages::startup::SecretKey;
+use pgwire::messages::{PgWireBackendMessage, ProtocolVersion};
+
+/// Client deferring the flushes of the responses to an extended query
+/// message to the next Flush or Sync
+///
+/// Messages are still written out once the buffer of the connection is full,
+/// so the rows of large results don't pile up.
+pub(crate) struct PipelineClient<'c, C> {
+    inner: &'c mut C,
+    /// Whether a message to deliver at once was sent since the last flush
+    flush_pending: bool,
+}
+
+impl<'c, C> PipelineClient<'c, C> {
+    pub(crate) fn new(client: &'c mut C) -> Self {
+        PipelineClient {
+            inner: client,
+            flush_pending: false,
+        }
+    }
+}
+
+impl<C: ClientInfo> ClientInfo for PipelineClient<'_, C> {
+    fn socket_addr(&self) -> std::net::SocketAddr {
+        self.inner.socket_addr()
+    }
+
+    fn is_secure(&self) -> bool {
+        self.inner.is_secure()
+    }
+
+    fn protocol_version(&self) -> ProtocolVersion {
+        self.inner.protocol_version()
+    }
+
+    fn set_protocol_version(&mut self, version: ProtocolVersion) {
+        self.inner.set_protocol_version(version)
+    }
+
+    fn pid_and_secret_key(&self) -> (i32, SecretKey) {
+        self.inner.pid_and_secret_key()
+    }
+
+    fn set_pid_and_secret_key(&mut self, pid: i32, secret_key: SecretKey) {
+        self.inner.set_pid_and_secret_key(pid, secret_key)
+    }
+
+    fn state(&self) -> PgWireConnectionState {
+        self.inner.state()
+    }
+
+    fn set_state(&mut self, new_state: PgWireConnectionState) {
+        self.inner.set_state(new_state)
+    }
+
+    fn transaction_status(&self) -> TransactionStatus {
+        self.inner.transaction_status()
+    }
+
+    fn set_transaction_status(&mut self, new_status: TransactionStatus) {
+        self.inner.set_transaction_status(new_status)
+    }
+
+    fn metadata(&self) -> &HashMap<String, String> {
+        self.inner.metadata()
+    }
+
+    fn metadata_mut(&mut self) -> &mut HashMap<String, String> {
+        self.inner.metadata_mut()
+    }
+
+    fn client_certificates<'a>(&self) -> Option<&[rustls_pki_types::CertificateDer<'a>]> {
+        self.inner.client_certificates()
+    }
+}
+
+impl<C: ClientPortalStore> ClientPortalStore for PipelineClient<'_, C> {
+    type PortalStore = C::PortalStore;
+
+    fn portal_store(&self) -> &Self::PortalStore {
+        self.inner.portal_store()
+    }
+}
+
+impl<C> Sink<PgWireBackendMessage> for PipelineClient<'_, C>
+where
+    C: Sink<PgWireBackendMessage> + Unpin,
+{
+    type Error = C::Error;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut *self.inner).poll_ready(cx)
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, item: PgWireBackendMessage) -> Result<(), Self::Error> {
+        if matches!(
+            item,
+            PgWireBackendMessage::NoticeResponse(_) | PgWireBackendMessage::ErrorResponse(_)
+        ) {
+            self.flush_pending = true;
+        }
+        Pin::new(&mut *self.inner).start_send(item)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        if !self.flush_pending {
+            return Poll::Ready(Ok(()));
+        }
+        let flushed = Pin::new(&mut *self.inner).poll_flush(cx);
+        if let Poll::Ready(Ok(())) = flushed {
+            self.flush_pending = false;
+        }
+        flushed
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut *self.inner).poll_close(cx)
+    }
+}
@@ -0,0 +1,222 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Mutex;
+
+use datafusion::error::Result;
+use datafusion::execution::SessionState;
+use datafusion::logical_expr::LogicalPlan;
+use datafusion::sql::parser::Statement;
+use datafusion::sql::sqlparser::ast::Statement as SqlStatement;
+use pgwire::api::ClientInfo;
+
+/// Whether cached plans are shared by all connections or kept per connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PlanCacheScope {
+    #[default]
+    Session,
+    /// Share plans across connections, for servers whose catalogs only
+    /// change through SQL sent to this server
+    Server,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct PlanCacheKey {
+    // client address of the connection for session scoped caches
+    session: Option<SocketAddr>,
+    // connections with session contexts of their own never share plans
+    session_id: String,
+    default_catalog: String,
+    default_schema: String,
+    // casts to timestamptz are planned in the time zone of the session
+    time_zone: String,
+    sql: String,
+}
+
+#[derive(Debug)]
+struct CachedPlan {
+    plan: LogicalPlan,
+    last_used: u64,
+}
+
+#[derive(Debug, Default)]
+struct Entries {
+    plans: HashMap<PlanCacheKey, CachedPlan>,
+    tick: u64,
+}
+
+/// LRU cache of the logical plans of queries
+///
+/// Plans are keyed by the normalized SQL of the rewritten statement and the
+/// default catalog and schema it was planned with, so repeated queries, like
+/// the parameterized queries of a dashboard, are only planned once. Only
+/// queries are cached, and the whole cache is cleared when DDL runs through
+/// the server. Call [`PlanCache::clear`] after changing the catalog directly
+/// on the `SessionContext`.
+#[derive(Debug)]
+pub struct PlanCache {
+    capacity: usize,
+    scope: PlanCacheScope,
+    entries: Mutex<Entries>,
+}
+
+impl PlanCache {
+    pub fn new(capacity: usize, scope: PlanCacheScope) -> Self {
+        PlanCache {
+            capacity,
+            scope,
+            entries: Mutex::new(Entries::default()),
+        }
+    }
+
+    pub fn scope(&self) -> PlanCacheScope {
+        self.scope
+    }
+
+    pub fn len(&self) -> usize {
+        self.lock().plans.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Drop all cached plans
+    pub fn clear(&self) {
+        self.lock().plans.clear();
+    }
+
+    /// Drop the cached plans of a session, of session scoped caches
+    pub(crate) fn clear_session(&self, session: &SocketAddr) {
+        self.lock()
+            .plans
+            .retain(|key, _| key.session.as_ref() != Some(session));
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Entries> {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Get the plan of a query from the cache, or plan and cache it
+    pub(crate) async fn get_or_plan<C>(
+        &self,
+        state: &SessionState,
+        client: &C,
+        statement: SqlStatement,
+    ) -> Result<LogicalPlan>
+    where
+        C: ClientInfo,
+    {
+        let options = state.config().options();
+        let catalog_options = &options.catalog;
+        let key = PlanCacheKey {
+            session: (self.scope == PlanCacheScope::Session).then(|| client.socket_addr()),
+            session_id: state.session_id().to_string(),
+            default_catalog: catalog_options.default_catalog.clone(),
+            default_schema: catalog_options.default_schema.clone(),
+            time_zone: options.execution.time_zone.clone(),
+            sql: statement.to_string(),
+        };
+
+        if let Some(plan) = self.get(&key) {
+            return Ok(plan);
+        }
+
+        let plan = state
+            .statement_to_plan(Statement::Statement(Box::new(statement)))
+            .await?;
+        self.insert(key, plan.clone());
+        Ok(plan)
+    }
+
+    fn get(&self, key: &PlanCacheKey) -> Option<LogicalPlan> {
+        let mut entries = self.lock();
+        entries.tick += 1;
+        let tick = entries.tick;
+        entries.plans.get_mut(key).map(|cached| {
+            cached.last_used = tick;
+            cached.plan.clone()
+        })
+    }
+
+    fn insert(&self, key: PlanCacheKey, plan: LogicalPlan) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let mut entries = self.lock();
+        if entries.plans.len() >= self.capacity && !entries.plans.contains_key(&key) {
+            let least_recently_used = entries
+                .plans
+                .iter()
+                .min_by_key(|(_, cached)| cached.last_used)
+                .map(|(key, _)| key.clone());
+            if let Some(least_recently_used) = least_recently_used {
+                entries.plans.remove(&least_recently_used);
+            }
+        }
+
+        entries.tick += 1;
+        let last_used = entries.tick;
+        entries.plans.insert(key, CachedPlan { plan, last_used });
+    }
+}
+
+/// Whether running the statement may change the plans of cached queries
+pub(crate) fn invalidates_plans(statement: &SqlStatement) -> bool {
+    !matches!(
+        statement,
+        SqlStatement::Query(_)
+            | SqlStatement::Insert(_)
+            | SqlStatement::Update { .. }
+            | SqlStatement::Delete(_)
+            | SqlStatement::Copy { to: true, .. }
+            | SqlStatement::Explain { .. }
+            | SqlStatement::ShowVariable { .. }
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datafusion::logical_expr::LogicalPlanBuilder;
+
+    fn key(sql: &str) -> PlanCacheKey {
+        PlanCacheKey {
+            session: None,
+            session_id: String::new(),
+            default_catalog: "datafusion".to_string(),
+            default_schema: "public".to_string(),
+            time_zone: "+00:00".to_string(),
+            sql: sql.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_lru_eviction() {
+        let cache = PlanCache::new(2, PlanCacheScope::Server);
+        let plan = LogicalPlanBuilder::empty(false).build().unwrap();
+
+        cache.insert(key("SELECT 1"), plan.clone());
+        cache.insert(key("SELECT 2"), plan.clone());
+        // SELECT 1 is now more recently used than SELECT 2
+        assert!(cache.get(&key("SELECT 1")).is_some());
+        cache.insert(key("SELECT 3"), plan.clone());
+
+        assert_eq!(cache.len(), 2);
+        assert!(cache.get(&key("SELECT 1")).is_some());
+        assert!(cache.get(&key("SELECT 2")).is_none());
+        assert!(cache.get(&key("SELECT 3")).is_some());
+
+        cache.clear();
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_invalidates_plans() {
+        let statement = |sql| crate::sql::parse(sql).unwrap().remove(0);
+        assert!(!invalidates_plans(&statement("SELECT 1")));
+        assert!(!invalidates_plans(&statement("INSERT INTO t VALUES (1)")));
+        assert!(invalidates_plans(&statement("CREATE TABLE t (a INT)")));
+        assert!(invalidates_plans(&statement("DROP TABLE t")));
+    }
+}
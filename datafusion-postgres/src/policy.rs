@@ -0,0 +1,554 @@
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use datafusion::arrow::datatypes::DataType;
+use datafusion::common::tree_node::Transformed;
+use datafusion::common::{Column, ScalarValue, TableReference};
+use datafusion::error::Result;
+use datafusion::execution::FunctionRegistry;
+use datafusion::logical_expr::{cast, LogicalPlan, LogicalPlanBuilder};
+use datafusion::prelude::{lit, Expr};
+use datafusion::sql::sqlparser::ast::Statement;
+use regex::Regex;
+
+/// Row-level security policy of a table
+///
+/// The predicate returned for a user is injected on top of every scan of the
+/// table, so the user only sees rows matching it. Superusers bypass row
+/// filters, like `BYPASSRLS` in postgres.
+pub trait RowFilterPolicy: Debug + Send + Sync {
+    /// Predicate rows must satisfy to be visible to the user
+    ///
+    /// Return `None` to let the user see all rows of the table.
+    fn row_filter(&self, username: &str, roles: &[String]) -> Option<Expr>;
+}
+
+/// Row filter policy registered for a table
+pub type TableRowFilter = (TableReference, Arc<dyn RowFilterPolicy>);
+
+/// Row filter policy mapping roles to predicates
+///
+/// A user sees rows matching the predicate of any of its roles. Users holding
+/// none of the configured roles see no rows at all.
+#[derive(Debug, Default, Clone)]
+pub struct RoleRowFilter {
+    predicates: HashMap<String, Expr>,
+}
+
+impl RoleRowFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the predicate for rows visible to `role`
+    pub fn with_role_predicate(mut self, role: &str, predicate: Expr) -> Self {
+        self.predicates.insert(role.to_string(), predicate);
+        self
+    }
+}
+
+impl RowFilterPolicy for RoleRowFilter {
+    fn row_filter(&self, _username: &str, roles: &[String]) -> Option<Expr> {
+        roles
+            .iter()
+            .filter_map(|role| self.predicates.get(role).cloned())
+            .reduce(Expr::or)
+            .or_else(|| Some(lit(false)))
+    }
+}
+
+/// How a masked column is presented to the user
+#[derive(Debug, Clone)]
+pub enum ColumnMask {
+    /// Replace values with NULL, keeping the column type
+    Null,
+    /// Replace values with a constant text
+    Redact(String),
+    /// Replace values with the hex encoded SHA-256 digest of their text form
+    Hash,
+    /// Replace values with a custom expression, which may refer to the
+    /// original value by the column name
+    Expr(Expr),
+}
+
+/// Column masking rule
+///
+/// The column of the table is masked for users holding `role`. Use the
+/// `public` role to mask the column for every user. Superusers always see
+/// the original values.
+#[derive(Debug, Clone)]
+pub struct ColumnMaskingRule {
+    pub role: String,
+    pub table: TableReference,
+    pub column: String,
+    pub mask: ColumnMask,
+}
+
+impl ColumnMaskingRule {
+    pub fn new(
+        role: &str,
+        table: impl Into<TableReference>,
+        column: &str,
+        mask: ColumnMask,
+    ) -> Self {
+        Self {
+            role: role.to_string(),
+            table: table.into(),
+            column: column.to_string(),
+            mask,
+        }
+    }
+
+    fn applies_to(&self, roles: &[String]) -> bool {
+        self.role == "public" || roles.contains(&self.role)
+    }
+
+    fn masking_expr(
+        &self,
+        column: Expr,
+        data_type: &DataType,
+        functions: &dyn FunctionRegistry,
+    ) -> Result<Expr> {
+        match &self.mask {
+            ColumnMask::Null => Ok(lit(ScalarValue::try_from(data_type)?)),
+            ColumnMask::Redact(text) => Ok(lit(text.clone())),
+            ColumnMask::Hash => {
+                let digest = functions
+                    .udf("sha256")?
+                    .call(vec![cast(column, DataType::Utf8)]);
+                Ok(functions.udf("encode")?.call(vec![digest, lit("hex")]))
+            }
+            ColumnMask::Expr(expr) => Ok(expr.clone()),
+        }
+    }
+}
+
+/// The user and environment table policies are applied for
+pub(crate) struct PolicyContext<'a> {
+    pub(crate) username: &'a str,
+    pub(crate) roles: &'a [String],
+    pub(crate) default_catalog: &'a str,
+    pub(crate) default_schema: &'a str,
+    pub(crate) functions: &'a dyn FunctionRegistry,
+}
+
+/// Inject row filters and column masks into table scans of the plan
+///
+/// Row filters are evaluated against original values, masks are applied on
+/// top of the filtered scan. Scans inside subqueries are covered too. Table
+/// references in the plan and in policies are resolved against the default
+/// catalog and schema before matching.
+pub(crate) fn apply_table_policies(
+    plan: LogicalPlan,
+    row_filters: &[TableRowFilter],
+    column_masks: &[ColumnMaskingRule],
+    ctx: &PolicyContext,
+) -> Result<LogicalPlan> {
+    let column_masks = column_masks
+        .iter()
+        .filter(|rule| rule.applies_to(ctx.roles))
+        .collect::<Vec<_>>();
+    if row_filters.is_empty() && column_masks.is_empty() {
+        return Ok(plan);
+    }
+
+    let resolve = |table: &TableReference| {
+        table
+            .clone()
+            .resolve(ctx.default_catalog, ctx.default_schema)
+    };
+
+    let transformed = plan.transform_up_with_subqueries(|node| {
+        let LogicalPlan::TableScan(scan) = &node else {
+            return Ok(Transformed::no(node));
+        };
+        let table = resolve(&scan.table_name);
+        let table_name = scan.table_name.clone();
+        let schema = scan.projected_schema.clone();
+
+        let predicate = row_filters
+            .iter()
+            .filter(|(policy_table, _)| resolve(policy_table) == table)
+            .filter_map(|(_, policy)| policy.row_filter(ctx.username, ctx.roles))
+            .reduce(Expr::and);
+        let masks = column_masks
+            .iter()
+            .filter(|rule| resolve(&rule.table) == table)
+            .collect::<Vec<_>>();
+
+        if predicate.is_none() && masks.is_empty() {
+            return Ok(Transformed::no(node));
+        }
+
+        let mut builder = LogicalPlanBuilder::from(node);
+        if let Some(predicate) = predicate {
+            builder = builder.filter(predicate)?;
+        }
+        if !masks.is_empty() {
+            let exprs = schema
+                .fields()
+                .iter()
+                .map(|field| {
+                    let column = Expr::Column(Column::new(Some(table_name.clone()), field.name()));
+                    match masks.iter().find(|rule| rule.column == *field.name()) {
+                        Some(rule) => Ok(rule
+                            .masking_expr(column, field.data_type(), ctx.functions)?
+                            .alias_qualified(Some(table_name.clone()), field.name())),
+                        None => Ok(column),
+                    }
+                })
+                .collect::<Result<Vec<_>>>()?;
+            builder = builder.project(exprs)?;
+        }
+
+        Ok(Transformed::yes(builder.build()?))
+    })?;
+
+    if transformed.transformed && !column_masks.is_empty() {
+        // masked columns may change type, which has to be propagated to the
+        // schema of every node above the scan
+        transformed
+            .data
+            .transform_up_with_subqueries(|node| Ok(Transformed::yes(node.recompute_schema()?)))
+            .map(|t| t.data)
+    } else {
+        Ok(transformed.data)
+    }
+}
+
+/// Kind of a SQL statement, as matched by statement rules
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatementKind {
+    Query,
+    Insert,
+    Update,
+    Delete,
+    Copy,
+    CreateTable,
+    CreateView,
+    Drop,
+    Alter,
+    Truncate,
+    Explain,
+    Set,
+    Show,
+    Other,
+}
+
+impl StatementKind {
+    pub fn of(statement: &Statement) -> Self {
+        match statement {
+            Statement::Query(_) => StatementKind::Query,
+            Statement::Insert(_) => StatementKind::Insert,
+            Statement::Update { .. } => StatementKind::Update,
+            Statement::Delete(_) => StatementKind::Delete,
+            Statement::Copy { .. } => StatementKind::Copy,
+            Statement::CreateTable(_) => StatementKind::CreateTable,
+            Statement::CreateView { .. } => StatementKind::CreateView,
+            Statement::Drop { .. } => StatementKind::Drop,
+            Statement::AlterTable { .. } => StatementKind::Alter,
+            Statement::Truncate { .. } => StatementKind::Truncate,
+            Statement::Explain { .. } | Statement::ExplainTable { .. } => StatementKind::Explain,
+            Statement::SetVariable { .. }
+            | Statement::SetTimeZone { .. }
+            | Statement::SetNames { .. }
+            | Statement::SetNamesDefault {}
+            | Statement::SetRole { .. }
+            | Statement::SetTransaction { .. } => StatementKind::Set,
+            Statement::ShowVariable { .. }
+            | Statement::ShowVariables { .. }
+            | Statement::ShowTables { .. }
+            | Statement::ShowColumns { .. } => StatementKind::Show,
+            _ => StatementKind::Other,
+        }
+    }
+}
+
+/// Whether a matching statement rule allows or denies the statement
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatementAction {
+    Allow,
+    Deny,
+}
+
+/// Statement allow/deny rule
+///
+/// A rule matches statements of users holding `role` (`public` for every
+/// user) whose kind is one of the rule's kinds and whose normalized SQL text
+/// matches the rule's pattern. Statements sqlparser doesn't parse, like
+/// `CREATE EXTERNAL TABLE` or `ANALYZE`, are matched in their SQL text as
+/// sent. A rule without kinds or pattern matches any statement.
+///
+/// Rules are checked in registration order before planning, and the first
+/// matching rule decides. Statements matching no rule are allowed.
+#[derive(Debug, Clone)]
+pub struct StatementRule {
+    pub role: String,
+    pub action: StatementAction,
+    pub kinds: Vec<StatementKind>,
+    pub pattern: Option<Regex>,
+}
+
+impl StatementRule {
+    pub fn new(role: &str, action: StatementAction) -> Self {
+        Self {
+            role: role.to_string(),
+            action,
+            kinds: Vec::new(),
+            pattern: None,
+        }
+    }
+
+    pub fn allow(role: &str) -> Self {
+        Self::new(role, StatementAction::Allow)
+    }
+
+    pub fn deny(role: &str) -> Self {
+        Self::new(role, StatementAction::Deny)
+    }
+
+    /// Only match statements of the given kind
+    pub fn with_kind(mut self, kind: StatementKind) -> Self {
+        self.kinds.push(kind);
+        self
+    }
+
+    /// Only match statements whose normalized SQL matches the regex
+    pub fn with_pattern(mut self, pattern: &str) -> std::result::Result<Self, regex::Error> {
+        self.pattern = Some(Regex::new(pattern)?);
+        Ok(self)
+    }
+
+    fn matches(&self, roles: &[String], kind: StatementKind, sql: &str) -> bool {
+        (self.role == "public" || roles.contains(&self.role))
+            && (self.kinds.is_empty() || self.kinds.contains(&kind))
+            && self.pattern.as_ref().is_none_or(|p| p.is_match(sql))
+    }
+}
+
+/// Find the rule denying the statement for a user holding `roles`
+///
+/// The statement is matched in its normalized form, as printed by the parser
+/// after rewriting.
+pub(crate) fn denying_statement_rule<'a>(
+    rules: &'a [StatementRule],
+    roles: &[String],
+    statement: &Statement,
+) -> Option<&'a StatementRule> {
+    if rules.is_empty() {
+        return None;
+    }
+    denying_command_rule(
+        rules,
+        roles,
+        StatementKind::of(statement),
+        &statement.to_string(),
+    )
+}
+
+/// Find the rule denying a statement sqlparser doesn't parse, by its kind
+/// and SQL text
+pub(crate) fn denying_command_rule<'a>(
+    rules: &'a [StatementRule],
+    roles: &[String],
+    kind: StatementKind,
+    sql: &str,
+) -> Option<&'a StatementRule> {
+    rules
+        .iter()
+        .find(|rule| rule.matches(roles, kind, sql))
+        .filter(|rule| rule.action == StatementAction::Deny)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datafusion::arrow::array::AsArray;
+    use datafusion::arrow::datatypes::Int32Type;
+    use datafusion::arrow::record_batch::RecordBatch;
+    use datafusion::prelude::{col, DataFrame, SessionContext};
+
+    async fn visible_ids(ctx: &SessionContext, sql: &str, roles: &[String]) -> Vec<i32> {
+        let policy: Arc<dyn RowFilterPolicy> = Arc::new(
+            RoleRowFilter::new()
+                .with_role_predicate("tenant_a", col("tenant").eq(lit("a")))
+                .with_role_predicate("tenant_b", col("tenant").eq(lit("b"))),
+        );
+        let policies = vec![(TableReference::from("orders"), policy)];
+
+        let (state, plan) = ctx.sql(sql).await.unwrap().into_parts();
+        let policy_ctx = PolicyContext {
+            username: "alice",
+            roles,
+            default_catalog: "datafusion",
+            default_schema: "public",
+            functions: &state,
+        };
+        let plan = apply_table_policies(plan, &policies, &[], &policy_ctx).unwrap();
+        let batches: Vec<RecordBatch> = DataFrame::new(state, plan).collect().await.unwrap();
+
+        let mut ids = batches
+            .iter()
+            .flat_map(|b| b.column(0).as_primitive::<Int32Type>().values().to_vec())
+            .collect::<Vec<_>>();
+        ids.sort();
+        ids
+    }
+
+    #[tokio::test]
+    async fn test_apply_row_filters() {
+        let ctx = SessionContext::new();
+        ctx.sql(
+            "CREATE TABLE orders (id INT, tenant VARCHAR) AS VALUES (1, 'a'), (2, 'b'), (3, 'a')",
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            visible_ids(&ctx, "SELECT id FROM orders", &["tenant_a".to_string()]).await,
+            vec![1, 3]
+        );
+        assert_eq!(
+            visible_ids(
+                &ctx,
+                "SELECT o.id FROM public.orders o",
+                &["tenant_a".to_string(), "tenant_b".to_string()]
+            )
+            .await,
+            vec![1, 2, 3]
+        );
+        assert!(
+            visible_ids(&ctx, "SELECT id FROM orders", &["other".to_string()])
+                .await
+                .is_empty()
+        );
+
+        // scans in subqueries are filtered too
+        assert_eq!(
+            visible_ids(
+                &ctx,
+                "SELECT CAST(column1 AS INT) FROM (VALUES (1), (2), (3)) \
+                 WHERE column1 IN (SELECT id FROM orders)",
+                &["tenant_b".to_string()]
+            )
+            .await,
+            vec![2]
+        );
+        assert_eq!(
+            visible_ids(
+                &ctx,
+                "SELECT CAST((SELECT count(*) FROM orders) AS INT)",
+                &["tenant_a".to_string()]
+            )
+            .await,
+            vec![2]
+        );
+
+        // tables without policy are not filtered
+        assert_eq!(
+            visible_ids(
+                &ctx,
+                "SELECT CAST(column1 AS INT) FROM (VALUES (1), (2), (3))",
+                &["other".to_string()]
+            )
+            .await,
+            vec![1, 2, 3]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_apply_column_masks() {
+        let ctx = SessionContext::new();
+        ctx.sql("CREATE TABLE users (id INT, email VARCHAR, ssn VARCHAR) AS VALUES (1, 'a@example.com', '123')")
+            .await
+            .unwrap();
+
+        let masks = vec![
+            ColumnMaskingRule::new("analyst", "users", "email", ColumnMask::Null),
+            ColumnMaskingRule::new(
+                "analyst",
+                "users",
+                "ssn",
+                ColumnMask::Redact("***".to_string()),
+            ),
+            ColumnMaskingRule::new("auditor", "users", "ssn", ColumnMask::Null),
+        ];
+        let row_filters: Vec<TableRowFilter> = vec![(
+            TableReference::from("users"),
+            Arc::new(
+                RoleRowFilter::new().with_role_predicate("analyst", col("ssn").eq(lit("123"))),
+            ),
+        )];
+
+        let (state, plan) = ctx
+            .sql("SELECT u.id, u.email, upper(ssn) AS ssn FROM users u")
+            .await
+            .unwrap()
+            .into_parts();
+        let roles = vec!["analyst".to_string()];
+        let policy_ctx = PolicyContext {
+            username: "alice",
+            roles: &roles,
+            default_catalog: "datafusion",
+            default_schema: "public",
+            functions: &state,
+        };
+        let plan = apply_table_policies(plan, &row_filters, &masks, &policy_ctx).unwrap();
+        let batches = DataFrame::new(state, plan).collect().await.unwrap();
+
+        // row filter is evaluated on the original ssn value
+        assert_eq!(batches.iter().map(|b| b.num_rows()).sum::<usize>(), 1);
+        let batch = &batches[0];
+        assert!(batch.column(1).is_null(0));
+        assert_eq!(batch.column(2).as_string::<i32>().value(0), "***");
+
+        // masked columns read through subqueries stay masked
+        let (state, plan) = ctx
+            .sql("SELECT (SELECT max(ssn) FROM users WHERE id = 1) AS ssn")
+            .await
+            .unwrap()
+            .into_parts();
+        let policy_ctx = PolicyContext {
+            username: "alice",
+            roles: &roles,
+            default_catalog: "datafusion",
+            default_schema: "public",
+            functions: &state,
+        };
+        let plan = apply_table_policies(plan, &row_filters, &masks, &policy_ctx).unwrap();
+        let batches = DataFrame::new(state, plan).collect().await.unwrap();
+        assert_eq!(batches[0].column(0).as_string::<i32>().value(0), "***");
+    }
+
+    #[test]
+    fn test_statement_rules() {
+        let rules = vec![
+            StatementRule::allow("admin"),
+            StatementRule::deny("public")
+                .with_kind(StatementKind::Copy)
+                .with_pattern(r"(?i)\bsecret\.")
+                .unwrap(),
+            StatementRule::deny("analyst")
+                .with_pattern(r"(?i)\bJOIN\s+\w+\.\w+\.\w+")
+                .unwrap(),
+        ];
+        let check = |sql: &str, role: &str| {
+            let statement = crate::sql::parse(sql).unwrap().remove(0);
+            denying_statement_rule(&rules, &[role.to_string()], &statement).is_some()
+        };
+
+        assert!(check("COPY secret.users TO '/tmp/out.csv'", "analyst"));
+        assert!(!check("COPY secret.users TO '/tmp/out.csv'", "admin"));
+        assert!(!check("COPY public.users TO '/tmp/out.csv'", "analyst"));
+        assert!(!check("SELECT * FROM secret.users", "analyst"));
+        assert!(check(
+            "SELECT * FROM t JOIN other.public.t2 ON t.id = t2.id",
+            "analyst"
+        ));
+        assert!(!check(
+            "SELECT * FROM t JOIN other.public.t2 ON t.id = t2.id",
+            "reporter"
+        ));
+    }
+}
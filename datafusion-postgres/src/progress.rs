@@ -0,0 +1,130 @@
+//! Progress notices of long running queries.
+//!
+//! Queries like large aggregations can run for minutes before producing
+//! their first row. While a query hasn't produced a row, a `NOTICE` with the
+//! elapsed time and the progress of its scans is sent to the client every
+//! interval, so interactive users know it is still running.
+
+use std::pin::pin;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use datafusion::physical_plan::metrics::MetricValue;
+use datafusion::physical_plan::{ExecutionPlan, ExecutionPlanProperties};
+use futures::future::{select, Either};
+use futures::{stream, Sink, SinkExt, StreamExt};
+use pgwire::api::results::QueryResponse;
+use pgwire::error::{ErrorInfo, PgWireError, PgWireResult};
+use pgwire::messages::response::NoticeResponse;
+use pgwire::messages::PgWireBackendMessage;
+
+/// Progress of the scans of a plan, read from the metrics of its leaves
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct ScanProgress {
+    rows: usize,
+    finished_partitions: usize,
+    partitions: usize,
+}
+
+impl ScanProgress {
+    fn of(plan: &dyn ExecutionPlan) -> Option<ScanProgress> {
+        let children = plan.children();
+        if !children.is_empty() {
+            return children
+                .into_iter()
+                .filter_map(|child| Self::of(child.as_ref()))
+                .reduce(|a, b| ScanProgress {
+                    rows: a.rows + b.rows,
+                    finished_partitions: a.finished_partitions + b.finished_partitions,
+                    partitions: a.partitions + b.partitions,
+                });
+        }
+
+        let metrics = plan.metrics()?;
+        // partitions record their end time once they are done
+        let finished_partitions = metrics
+            .iter()
+            .filter(|metric| {
+                matches!(metric.value(), MetricValue::EndTimestamp(end) if end.value().is_some())
+            })
+            .count();
+        Some(ScanProgress {
+            rows: metrics.output_rows().unwrap_or(0),
+            finished_partitions,
+            partitions: plan.output_partitioning().partition_count(),
+        })
+    }
+}
+
+fn progress_message(elapsed: Duration, progress: Option<ScanProgress>) -> String {
+    let mut message = format!("query running for {:.1} s", elapsed.as_secs_f64());
+    if let Some(progress) = progress {
+        message.push_str(&format!(
+            ": {} rows scanned, {} of {} partitions scanned",
+            progress.rows, progress.finished_partitions, progress.partitions
+        ));
+    }
+    message
+}
+
+/// Wait for the first row of `resp`, sending a progress notice to the
+/// client every `interval` until it arrives
+///
+/// `plan` is the physical plan producing the rows, its metrics tell the
+/// progress of the query.
+pub(crate) async fn notify_until_first_row<'a, C>(
+    client: &mut C,
+    resp: QueryResponse<'a>,
+    plan: Arc<dyn ExecutionPlan>,
+    interval: Duration,
+) -> PgWireResult<QueryResponse<'a>>
+where
+    C: Sink<PgWireBackendMessage> + Unpin + Send,
+    PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+{
+    let started = Instant::now();
+    let fields = resp.row_schema();
+    let mut data_rows = resp.data_rows();
+    let mut ticker = tokio::time::interval_at((started + interval).into(), interval);
+
+    let first_row = loop {
+        match select(data_rows.next(), pin!(ticker.tick())).await {
+            Either::Left((row, _)) => break row,
+            Either::Right(_) => {
+                let message = progress_message(started.elapsed(), ScanProgress::of(plan.as_ref()));
+                client
+                    .send(PgWireBackendMessage::NoticeResponse(NoticeResponse::from(
+                        ErrorInfo::new("NOTICE".to_string(), "00000".to_string(), message),
+                    )))
+                    .await?;
+            }
+        }
+    };
+
+    let data_rows = stream::iter(first_row).chain(data_rows);
+    Ok(QueryResponse::new(fields, data_rows))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_progress_message() {
+        assert_eq!(
+            progress_message(Duration::from_millis(12_340), None),
+            "query running for 12.3 s"
+        );
+        assert_eq!(
+            progress_message(
+                Duration::from_secs(90),
+                Some(ScanProgress {
+                    rows: 1500,
+                    finished_partitions: 3,
+                    partitions: 8
+                })
+            ),
+            "query running for 90.0 s: 1500 rows scanned, 3 of 8 partitions scanned"
+        );
+    }
+}
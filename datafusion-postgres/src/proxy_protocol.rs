@@ -0,0 +1,194 @@
+//! The PROXY protocol header load balancers like HAProxy or AWS NLB send
+//! ahead of the connections they forward, carrying the address of the
+//! client they proxy.
+//!
+//! Both the text format of version 1 and the binary format of version 2 are
+//! read. Connections of the proxy itself, like health checks, are sent with
+//! `PROXY UNKNOWN` or the `LOCAL` command and keep their own address.
+
+use std::io::{Error as IOError, ErrorKind};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+/// Time proxies have to send the header of a connection
+pub(crate) const PROXY_HEADER_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Signature starting the header of version 2
+const V2_SIGNATURE: [u8; 12] = [
+    0x0d, 0x0a, 0x0d, 0x0a, 0x00, 0x0d, 0x0a, 0x51, 0x55, 0x49, 0x54, 0x0a,
+];
+
+/// Bytes of the longest header of version 1, with its CRLF
+const V1_MAX_LENGTH: usize = 107;
+
+fn invalid_header(message: &str) -> IOError {
+    IOError::new(
+        ErrorKind::InvalidData,
+        format!("invalid PROXY protocol header: {message}"),
+    )
+}
+
+/// Read the PROXY protocol header at the start of a connection, without
+/// reading past it
+///
+/// Returns the address of the proxied client, `None` for connections of the
+/// proxy itself.
+pub(crate) async fn read_proxy_header<R>(reader: &mut R) -> Result<Option<SocketAddr>, IOError>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut start = [0u8; 6];
+    reader.read_exact(&mut start).await?;
+    if &start == b"PROXY " {
+        read_v1_header(reader).await
+    } else if start == V2_SIGNATURE[..6] {
+        read_v2_header(reader).await
+    } else {
+        Err(invalid_header("missing header"))
+    }
+}
+
+/// Read the rest of a header like `PROXY TCP4 192.0.2.1 198.51.100.1 56324 5432\r\n`
+async fn read_v1_header<R>(reader: &mut R) -> Result<Option<SocketAddr>, IOError>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut line = Vec::new();
+    while !line.ends_with(b"\r\n") {
+        if line.len() + 6 >= V1_MAX_LENGTH {
+            return Err(invalid_header("line too long"));
+        }
+        line.push(reader.read_u8().await?);
+    }
+    let line = std::str::from_utf8(&line[..line.len() - 2])
+        .map_err(|_| invalid_header("line is not ASCII"))?;
+    let fields = line.split(' ').collect::<Vec<_>>();
+    match fields[..] {
+        ["UNKNOWN", ..] => Ok(None),
+        [protocol @ ("TCP4" | "TCP6"), source, _, source_port, _] => {
+            let ip = source
+                .parse::<IpAddr>()
+                .map_err(|_| invalid_header("invalid source address"))?;
+            if ip.is_ipv4() != (protocol == "TCP4") {
+                return Err(invalid_header("source address of another protocol"));
+            }
+            let port = source_port
+                .parse::<u16>()
+                .map_err(|_| invalid_header("invalid source port"))?;
+            Ok(Some(SocketAddr::new(ip, port)))
+        }
+        _ => Err(invalid_header("unknown protocol")),
+    }
+}
+
+/// Read the rest of a binary header of version 2
+async fn read_v2_header<R>(reader: &mut R) -> Result<Option<SocketAddr>, IOError>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut rest = [0u8; 10];
+    reader.read_exact(&mut rest).await?;
+    if rest[..6] != V2_SIGNATURE[6..] {
+        return Err(invalid_header("missing header"));
+    }
+    let (version_command, family) = (rest[6], rest[7]);
+    let length = u16::from_be_bytes([rest[8], rest[9]]) as usize;
+    let mut addresses = vec![0u8; length];
+    reader.read_exact(&mut addresses).await?;
+
+    if version_command >> 4 != 2 {
+        return Err(invalid_header("unsupported version"));
+    }
+    match version_command & 0x0f {
+        // LOCAL
+        0 => return Ok(None),
+        // PROXY
+        1 => {}
+        _ => return Err(invalid_header("unknown command")),
+    }
+    // the addresses of TLVs follow, which are ignored
+    let port = |bytes: &[u8]| u16::from_be_bytes([bytes[0], bytes[1]]);
+    match family >> 4 {
+        // AF_INET
+        1 if length >= 12 => {
+            let ip = Ipv4Addr::new(addresses[0], addresses[1], addresses[2], addresses[3]);
+            Ok(Some(SocketAddr::new(ip.into(), port(&addresses[8..]))))
+        }
+        // AF_INET6
+        2 if length >= 36 => {
+            let octets: [u8; 16] = addresses[..16].try_into().unwrap();
+            let ip = Ipv6Addr::from(octets);
+            Ok(Some(SocketAddr::new(ip.into(), port(&addresses[32..]))))
+        }
+        // AF_UNSPEC and AF_UNIX, which have no client address to take
+        0 | 3 => Ok(None),
+        _ => Err(invalid_header("invalid address")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn read(header: &[u8]) -> (Result<Option<SocketAddr>, IOError>, usize) {
+        let mut reader = header;
+        let result = read_proxy_header(&mut reader).await;
+        (result, reader.len())
+    }
+
+    #[tokio::test]
+    async fn test_v1_header() {
+        let (addr, rest) = read(b"PROXY TCP4 192.0.2.1 198.51.100.1 56324 5432\r\n\x00\x00").await;
+        assert_eq!(addr.unwrap(), Some("192.0.2.1:56324".parse().unwrap()));
+        // the startup message after the header is left unread
+        assert_eq!(rest, 2);
+
+        let (addr, _) = read(b"PROXY TCP6 2001:db8::1 2001:db8::2 4000 5432\r\n").await;
+        assert_eq!(addr.unwrap(), Some("[2001:db8::1]:4000".parse().unwrap()));
+
+        let (addr, _) = read(b"PROXY UNKNOWN\r\n").await;
+        assert_eq!(addr.unwrap(), None);
+
+        for header in [
+            &b"PROXY TCP4 2001:db8::1 198.51.100.1 56324 5432\r\n"[..],
+            b"PROXY TCP4 192.0.2.1 198.51.100.1 port 5432\r\n",
+            b"PROXY UDP4 192.0.2.1 198.51.100.1 56324 5432\r\n",
+            &[b'P'; 200],
+            b"\x00\x00\x00\x08\x04\xd2\x16\x2f",
+        ] {
+            assert!(read(header).await.0.is_err());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_v2_header() {
+        let mut header = V2_SIGNATURE.to_vec();
+        // PROXY over TCP4, with a TLV after the addresses
+        header.extend_from_slice(&[0x21, 0x11, 0, 16]);
+        header.extend_from_slice(&[192, 0, 2, 1, 198, 51, 100, 1, 0xdc, 0x04, 0x15, 0x38]);
+        header.extend_from_slice(&[0x04, 0, 1, 0]);
+        header.extend_from_slice(&[0, 0, 0, 8]);
+        let (addr, rest) = read(&header).await;
+        assert_eq!(addr.unwrap(), Some("192.0.2.1:56324".parse().unwrap()));
+        assert_eq!(rest, 4);
+
+        let mut header = V2_SIGNATURE.to_vec();
+        header.extend_from_slice(&[0x21, 0x21, 0, 36]);
+        header.extend_from_slice(&"2001:db8::1".parse::<Ipv6Addr>().unwrap().octets());
+        header.extend_from_slice(&[0; 16]);
+        header.extend_from_slice(&[0x0f, 0xa0, 0x15, 0x38]);
+        let (addr, _) = read(&header).await;
+        assert_eq!(addr.unwrap(), Some("[2001:db8::1]:4000".parse().unwrap()));
+
+        // LOCAL, like health checks of the proxy
+        let mut header = V2_SIGNATURE.to_vec();
+        header.extend_from_slice(&[0x20, 0x00, 0, 0]);
+        assert_eq!(read(&header).await.0.unwrap(), None);
+
+        let mut header = V2_SIGNATURE.to_vec();
+        header.extend_from_slice(&[0x11, 0x11, 0, 0]);
+        assert!(read(&header).await.0.is_err());
+    }
+}
@@ -0,0 +1,143 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Token bucket rate limit
+///
+/// Allows `per_second` events on average with bursts of up to `burst` events.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimit {
+    pub per_second: f64,
+    pub burst: u32,
+}
+
+impl RateLimit {
+    /// A rate limit of `per_second` events, which must be positive and
+    /// finite
+    pub fn new(per_second: f64, burst: u32) -> Result<Self, String> {
+        if !(per_second.is_finite() && per_second > 0.0) {
+            return Err(format!(
+                "rate limit of {per_second} events per second is not positive"
+            ));
+        }
+        Ok(RateLimit { per_second, burst })
+    }
+
+    /// Time it takes an empty bucket to fill up again, forever for limits
+    /// not built by `new` with no positive rate
+    fn refill_duration(&self) -> Duration {
+        Duration::try_from_secs_f64(self.burst as f64 / self.per_second).unwrap_or(Duration::MAX)
+    }
+}
+
+#[derive(Debug)]
+struct TokenBucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+// Buckets are pruned once there are more keys than this
+const PRUNE_THRESHOLD: usize = 1024;
+
+/// Rate limiter keeping a token bucket per key, like a client address or a
+/// user and client address pair
+#[derive(Debug)]
+pub struct RateLimiter<K> {
+    limit: RateLimit,
+    buckets: Mutex<HashMap<K, TokenBucket>>,
+}
+
+impl<K: Hash + Eq> RateLimiter<K> {
+    pub fn new(limit: RateLimit) -> Self {
+        RateLimiter {
+            limit,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn limit(&self) -> &RateLimit {
+        &self.limit
+    }
+
+    /// Take a token from the bucket of `key`, returns false if it is empty
+    pub fn try_acquire(&self, key: K) -> bool {
+        self.try_acquire_at(key, Instant::now())
+    }
+
+    fn try_acquire_at(&self, key: K, now: Instant) -> bool {
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+
+        if buckets.len() > PRUNE_THRESHOLD {
+            // idle buckets are full again and behave like new ones
+            let refill_duration = self.limit.refill_duration();
+            buckets.retain(|_, bucket| now.duration_since(bucket.updated_at) < refill_duration);
+        }
+
+        let burst = self.limit.burst as f64;
+        let bucket = buckets.entry(key).or_insert(TokenBucket {
+            tokens: burst,
+            updated_at: now,
+        });
+
+        let elapsed = now.duration_since(bucket.updated_at).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.limit.per_second).min(burst);
+        bucket.updated_at = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_bucket() {
+        let limiter = RateLimiter::new(RateLimit::new(2.0, 3).unwrap());
+        let start = Instant::now();
+
+        // burst
+        for _ in 0..3 {
+            assert!(limiter.try_acquire_at("10.0.0.1", start));
+        }
+        assert!(!limiter.try_acquire_at("10.0.0.1", start));
+
+        // other keys have their own bucket
+        assert!(limiter.try_acquire_at("10.0.0.2", start));
+
+        // refilled at 2 tokens per second
+        let later = start + Duration::from_millis(500);
+        assert!(limiter.try_acquire_at("10.0.0.1", later));
+        assert!(!limiter.try_acquire_at("10.0.0.1", later));
+
+        // never more than the burst size
+        let much_later = start + Duration::from_secs(60);
+        for _ in 0..3 {
+            assert!(limiter.try_acquire_at("10.0.0.1", much_later));
+        }
+        assert!(!limiter.try_acquire_at("10.0.0.1", much_later));
+    }
+
+    #[test]
+    fn test_rate_limit_validation() {
+        for per_second in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            assert!(RateLimit::new(per_second, 10).is_err());
+        }
+        // limits with no rate never refill
+        let limit = RateLimit {
+            per_second: 0.0,
+            burst: 1,
+        };
+        assert_eq!(limit.refill_duration(), Duration::MAX);
+        let limiter = RateLimiter::new(limit);
+        let start = Instant::now();
+        assert!(limiter.try_acquire_at("10.0.0.1", start));
+        assert!(!limiter.try_acquire_at("10.0.0.1", start + Duration::from_secs(60)));
+    }
+}
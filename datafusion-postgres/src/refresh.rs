@@ -0,0 +1,200 @@
+//! Refreshing the schema of external tables.
+//!
+//! The schema of a table of files is inferred once, when it's created.
+//! `ALTER TABLE events REFRESH SCHEMA` infers it again from the files of its
+//! location, so columns added to an evolving Parquet dataset show up without
+//! restarting the server, and lists the files anew. The function
+//! `pg_catalog.refresh_table_schema('events')` does the same, for clients
+//! which can only run queries:
+//!
+//! ```sql
+//! SELECT pg_catalog.refresh_table_schema('events');
+//! ```
+
+use std::sync::Arc;
+
+use datafusion::common::TableReference;
+use datafusion::datasource::listing::{ListingTable, ListingTableConfig};
+use datafusion::datasource::TableProvider;
+use datafusion::prelude::SessionContext;
+use datafusion::sql::sqlparser::ast::{
+    Expr, Function, FunctionArg, FunctionArgExpr, FunctionArguments, ObjectName, SelectItem,
+    SetExpr, Statement, Value,
+};
+use datafusion::sql::sqlparser::dialect::PostgreSqlDialect;
+use datafusion::sql::sqlparser::keywords::Keyword;
+use datafusion::sql::sqlparser::parser::{Parser, ParserError};
+use datafusion::sql::sqlparser::tokenizer::Token;
+use pgwire::error::{ErrorInfo, PgWireError, PgWireResult};
+
+use crate::errors::syntax_error;
+use crate::sql::parse_word;
+
+/// The name of the function refreshing the schema of a table
+pub(crate) const REFRESH_TABLE_SCHEMA: &str = "refresh_table_schema";
+
+/// Whether the lowercase query is an `ALTER TABLE ... REFRESH SCHEMA`
+pub(crate) fn is_alter_table_refresh_schema(query_lower: &str) -> bool {
+    let query_lower = query_lower.trim().trim_end_matches(';');
+    let words = query_lower.split_whitespace().collect::<Vec<_>>();
+    words.starts_with(&["alter", "table"]) && words.ends_with(&["refresh", "schema"])
+}
+
+pub(crate) fn parse_alter_table_refresh_schema(sql: &str) -> PgWireResult<ObjectName> {
+    parse_refresh_statement(sql).map_err(|e| syntax_error(e, sql))
+}
+
+fn parse_refresh_statement(sql: &str) -> Result<ObjectName, ParserError> {
+    let dialect = PostgreSqlDialect {};
+    let mut parser = Parser::new(&dialect).try_with_sql(sql)?;
+    parser.expect_keywords(&[Keyword::ALTER, Keyword::TABLE])?;
+    let name = parser.parse_object_name(false)?;
+    if !parse_word(&mut parser, "refresh") {
+        return parser.expected("REFRESH SCHEMA", parser.peek_token());
+    }
+    parser.expect_keyword(Keyword::SCHEMA)?;
+    let _ = parser.consume_token(&Token::SemiColon);
+    parser.expect_token(&Token::EOF)?;
+    Ok(name)
+}
+
+/// The table name of a `SELECT [pg_catalog.]refresh_table_schema('name')`
+/// calling the function alone
+pub(crate) fn refresh_function_argument(statement: &Statement) -> Option<&str> {
+    let Statement::Query(query) = statement else {
+        return None;
+    };
+    let SetExpr::Select(select) = query.body.as_ref() else {
+        return None;
+    };
+    if !select.from.is_empty() || select.selection.is_some() {
+        return None;
+    }
+    let [SelectItem::UnnamedExpr(Expr::Function(Function {
+        name, args, filter, ..
+    }))] = select.projection.as_slice()
+    else {
+        return None;
+    };
+    let name = name
+        .0
+        .iter()
+        .map(|part| part.to_string().to_lowercase())
+        .collect::<Vec<_>>();
+    let is_refresh = match name.as_slice() {
+        [name] => name == REFRESH_TABLE_SCHEMA,
+        [schema, name] => schema == "pg_catalog" && name == REFRESH_TABLE_SCHEMA,
+        _ => false,
+    };
+    if !is_refresh || filter.is_some() {
+        return None;
+    }
+    let FunctionArguments::List(args) = args else {
+        return None;
+    };
+    match args.args.as_slice() {
+        [FunctionArg::Unnamed(FunctionArgExpr::Expr(Expr::Value(value)))] => match &value.value {
+            Value::SingleQuotedString(table) => Some(table),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Infer the schema of the table of files `reference` again, replacing it
+/// in `session_context`
+///
+/// The files of its location are listed anew, and the statistics collected
+/// from them dropped.
+pub(crate) async fn refresh_table_schema(
+    session_context: &SessionContext,
+    reference: TableReference,
+) -> PgWireResult<()> {
+    let provider = session_context
+        .table_provider(reference.clone())
+        .await
+        .map_err(|e| PgWireError::ApiError(Box::new(e)))?;
+    let Some(table) = provider.as_any().downcast_ref::<ListingTable>() else {
+        return Err(PgWireError::UserError(Box::new(ErrorInfo::new(
+            "ERROR".to_string(),
+            "42809".to_string(), // wrong_object_type
+            format!("\"{reference}\" is not a table of files"),
+        ))));
+    };
+
+    let state = session_context.state();
+    let cache_manager = &state.runtime_env().cache_manager;
+    if let Some(list_files_cache) = cache_manager.get_list_files_cache() {
+        list_files_cache.clear();
+    }
+    if let Some(file_statistic_cache) = cache_manager.get_file_statistic_cache() {
+        file_statistic_cache.clear();
+    }
+    let config = ListingTableConfig::new_with_multi_paths(table.table_paths().clone())
+        .with_listing_options(table.options().clone())
+        .infer_schema(&state)
+        .await
+        .map_err(|e| PgWireError::ApiError(Box::new(e)))?;
+    let refreshed = ListingTable::try_new(config)
+        .map_err(|e| PgWireError::ApiError(Box::new(e)))?
+        .with_definition(table.get_table_definition().map(str::to_string));
+
+    session_context
+        .deregister_table(reference.clone())
+        .map_err(|e| PgWireError::ApiError(Box::new(e)))?;
+    session_context
+        .register_table(reference, Arc::new(refreshed))
+        .map_err(|e| PgWireError::ApiError(Box::new(e)))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datafusion::sql::parser::DFParser;
+
+    fn parse_statement(sql: &str) -> Statement {
+        let mut statements = DFParser::parse_sql(sql).unwrap();
+        let datafusion::sql::parser::Statement::Statement(statement) =
+            statements.pop_front().unwrap()
+        else {
+            panic!("expected a SQL statement");
+        };
+        *statement
+    }
+
+    #[test]
+    fn test_parse_refresh_schema() {
+        assert!(is_alter_table_refresh_schema(
+            "alter table public.events refresh  schema;"
+        ));
+        assert!(!is_alter_table_refresh_schema(
+            "alter table events add column refresh_schema int"
+        ));
+        assert_eq!(
+            parse_alter_table_refresh_schema("ALTER TABLE public.events REFRESH SCHEMA;")
+                .unwrap()
+                .to_string(),
+            "public.events"
+        );
+        let Err(PgWireError::UserError(info)) =
+            parse_alter_table_refresh_schema("ALTER TABLE events REFRESH COLUMNS SCHEMA")
+        else {
+            panic!("expected a syntax error");
+        };
+        assert_eq!(info.code, "42601");
+
+        for (sql, table) in [
+            ("SELECT refresh_table_schema('events')", Some("events")),
+            (
+                "select PG_CATALOG.REFRESH_TABLE_SCHEMA('public.events')",
+                Some("public.events"),
+            ),
+            ("SELECT other.refresh_table_schema('events')", None),
+            ("SELECT refresh_table_schema('events'), 1", None),
+            ("SELECT refresh_table_schema(name) FROM tables", None),
+        ] {
+            assert_eq!(refresh_function_argument(&parse_statement(sql)), table);
+        }
+    }
+}
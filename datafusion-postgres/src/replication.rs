@@ -0,0 +1,94 @@
+//! Connections of the streaming replication protocol.
+//!
+//! Clients like Debezium or `pg_recvlogical` connect with the `replication`
+//! startup parameter to run replication commands like `IDENTIFY_SYSTEM` and
+//! `START_REPLICATION`. The server has no write-ahead log to stream, so they
+//! are refused at startup with a FATAL `0A000` error, unless a
+//! [`ReplicationHandler`] registered with
+//! `DfPostgresServerBuilder::replication_handler` or
+//! `DfSessionService::with_replication_handler` serves them, e.g. with a
+//! change stream of the sources of an embedding application.
+
+use async_trait::async_trait;
+use pgwire::api::results::Response;
+use pgwire::error::{ErrorInfo, PgWireError, PgWireResult};
+
+use crate::hooks::HookContext;
+
+/// Startup parameter of the clients asking for a replication connection
+pub const REPLICATION_PARAMETER: &str = "replication";
+
+/// Kind of replication connection a client asks for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplicationMode {
+    /// `replication=true`, physical replication
+    Physical,
+    /// `replication=database`, logical replication of a database, whose
+    /// connections also run SQL statements
+    Logical,
+}
+
+impl ReplicationMode {
+    /// The mode of a value of the `replication` startup parameter, `None`
+    /// for regular connections
+    pub fn from_parameter(value: &str) -> PgWireResult<Option<Self>> {
+        match value.to_lowercase().as_str() {
+            "database" => Ok(Some(ReplicationMode::Logical)),
+            "true" | "on" | "yes" | "1" => Ok(Some(ReplicationMode::Physical)),
+            "false" | "off" | "no" | "0" => Ok(None),
+            _ => Err(PgWireError::UserError(Box::new(ErrorInfo::new(
+                "FATAL".to_string(),
+                "22023".to_string(), // invalid_parameter_value
+                format!("invalid value for parameter \"{REPLICATION_PARAMETER}\": \"{value}\""),
+            )))),
+        }
+    }
+}
+
+/// Serves the replication connections of clients
+///
+/// Replication connections authenticate like other connections, then send
+/// their commands as simple queries.
+#[async_trait]
+pub trait ReplicationHandler: Send + Sync {
+    /// Called with the simple queries of replication connections, like
+    /// `IDENTIFY_SYSTEM` or `START_REPLICATION SLOT s LOGICAL 0/0`, returns
+    /// the response to send, or `None` to run the query as SQL
+    async fn on_command<'a>(
+        &self,
+        context: &HookContext,
+        mode: ReplicationMode,
+        command: &str,
+    ) -> PgWireResult<Option<Response<'a>>>;
+}
+
+/// Error refusing replication connections, when no handler serves them
+pub(crate) fn replication_not_supported() -> PgWireError {
+    PgWireError::UserError(Box::new(ErrorInfo::new(
+        "FATAL".to_string(),
+        "0A000".to_string(), // feature_not_supported
+        "replication connections are not supported".to_string(),
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replication_mode() {
+        assert_eq!(
+            ReplicationMode::from_parameter("database").unwrap(),
+            Some(ReplicationMode::Logical)
+        );
+        assert_eq!(
+            ReplicationMode::from_parameter("On").unwrap(),
+            Some(ReplicationMode::Physical)
+        );
+        assert_eq!(ReplicationMode::from_parameter("false").unwrap(), None);
+        let Err(PgWireError::UserError(info)) = ReplicationMode::from_parameter("maybe") else {
+            panic!("expected a user error");
+        };
+        assert_eq!(info.code, "22023");
+    }
+}
@@ -0,0 +1,439 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use datafusion::arrow::array::RecordBatch;
+use datafusion::arrow::datatypes::SchemaRef;
+use datafusion::catalog::streaming::StreamingTable;
+use datafusion::common::tree_node::{TreeNode, TreeNodeRecursion};
+use datafusion::datasource::MemTable;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::execution::{SendableRecordBatchStream, SessionState, TaskContext};
+use datafusion::logical_expr::{Expr, LogicalPlan, Volatility};
+use datafusion::physical_plan::stream::RecordBatchStreamAdapter;
+use datafusion::physical_plan::streaming::PartitionStream;
+use datafusion::prelude::{DataFrame, SessionContext};
+use datafusion::sql::sqlparser::ast::Statement as SqlStatement;
+use futures::{stream, StreamExt};
+use pgwire::api::results::QueryResponse;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct ResultCacheKey {
+    // results are filtered by the row policies of the user
+    user: String,
+    // connections with session contexts of their own never share results
+    session_id: String,
+    default_catalog: String,
+    default_schema: String,
+    time_zone: String,
+    query: String,
+}
+
+impl ResultCacheKey {
+    /// Key of the result of `query` run by `user` in `state`, with the
+    /// values of its parameters in `query` for bound statements
+    pub(crate) fn new(state: &SessionState, user: &str, query: String) -> Self {
+        let options = state.config().options();
+        ResultCacheKey {
+            user: user.to_string(),
+            session_id: state.session_id().to_string(),
+            default_catalog: options.catalog.default_catalog.clone(),
+            default_schema: options.catalog.default_schema.clone(),
+            time_zone: options.execution.time_zone.clone(),
+            query,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct CachedResult {
+    schema: SchemaRef,
+    batches: Vec<RecordBatch>,
+    size: usize,
+    cached_at: Instant,
+    last_used: u64,
+}
+
+#[derive(Debug, Default)]
+struct Entries {
+    results: HashMap<ResultCacheKey, CachedResult>,
+    size: usize,
+    tick: u64,
+}
+
+impl Entries {
+    fn remove(&mut self, key: &ResultCacheKey) {
+        if let Some(cached) = self.results.remove(key) {
+            self.size -= cached.size;
+        }
+    }
+}
+
+/// Cache of the results of read-only queries
+///
+/// Results are keyed by the SQL of the rewritten statement, the values of
+/// its parameters and the user and default catalog and schema it ran with,
+/// so identical queries, like the ones of a dashboard opened by many
+/// clients, are run once per `ttl` and served from memory afterwards. Only
+/// queries calling no volatile or stable functions, like `now()`, and
+/// reading no system catalog are cached, and results larger than the whole
+/// cache aren't.
+///
+/// The cache is cleared when DDL or DML runs through the server, or a table
+/// is refreshed. Call [`ResultCache::clear`] after changing tables directly
+/// on the `SessionContext`.
+#[derive(Debug)]
+pub struct ResultCache {
+    max_bytes: usize,
+    ttl: Duration,
+    // version of the catalogs and data, results of queries that started
+    // before a change aren't cached
+    version: AtomicU64,
+    entries: Mutex<Entries>,
+}
+
+impl ResultCache {
+    /// Cache up to `max_bytes` of results, for `ttl` each
+    pub fn new(max_bytes: usize, ttl: Duration) -> Self {
+        ResultCache {
+            max_bytes,
+            ttl,
+            version: AtomicU64::new(0),
+            entries: Mutex::new(Entries::default()),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.lock().results.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Bytes of the cached results
+    pub fn size(&self) -> usize {
+        self.lock().size
+    }
+
+    /// Drop all cached results
+    pub fn clear(&self) {
+        let mut entries = self.lock();
+        self.version.fetch_add(1, Ordering::SeqCst);
+        entries.results.clear();
+        entries.size = 0;
+    }
+
+    /// Clear the cache now, and again when the returned guard is dropped,
+    /// for statements changing data as their response is consumed
+    pub(crate) fn invalidate(self: &Arc<Self>) -> Invalidation {
+        self.clear();
+        Invalidation(self.clone())
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Entries> {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// `df` reading the cached result of `key`, or reading the result of `df`
+    /// and caching it
+    ///
+    /// Uncacheable queries are returned as they are.
+    pub(crate) async fn dataframe(
+        &self,
+        session_context: &SessionContext,
+        key: ResultCacheKey,
+        df: DataFrame,
+    ) -> Result<DataFrame> {
+        if !is_cacheable(df.logical_plan()) {
+            return Ok(df);
+        }
+        if let Some((schema, batches)) = self.get(&key) {
+            return session_context.read_table(Arc::new(MemTable::try_new(schema, vec![batches])?));
+        }
+
+        let version = self.version.load(Ordering::SeqCst);
+        let mut stream = df.execute_stream().await?;
+        let schema = stream.schema();
+        let mut batches = Vec::new();
+        let mut size = 0;
+        while let Some(batch) = stream.next().await {
+            let batch = batch?;
+            size += batch.get_array_memory_size();
+            batches.push(batch);
+            if size > self.max_bytes {
+                // too large to cache, the rest of the result is streamed
+                let remaining = stream::iter(batches.into_iter().map(Ok)).chain(stream);
+                let remaining = RemainingResult::new(Box::pin(RecordBatchStreamAdapter::new(
+                    schema.clone(),
+                    remaining,
+                )));
+                return session_context.read_table(Arc::new(StreamingTable::try_new(
+                    schema,
+                    vec![Arc::new(remaining)],
+                )?));
+            }
+        }
+
+        self.insert(key, version, schema.clone(), batches.clone(), size);
+        session_context.read_table(Arc::new(MemTable::try_new(schema, vec![batches])?))
+    }
+
+    fn get(&self, key: &ResultCacheKey) -> Option<(SchemaRef, Vec<RecordBatch>)> {
+        let mut entries = self.lock();
+        let expired = entries
+            .results
+            .get(key)
+            .is_some_and(|cached| cached.cached_at.elapsed() > self.ttl);
+        if expired {
+            entries.remove(key);
+            return None;
+        }
+        entries.tick += 1;
+        let tick = entries.tick;
+        entries.results.get_mut(key).map(|cached| {
+            cached.last_used = tick;
+            (cached.schema.clone(), cached.batches.clone())
+        })
+    }
+
+    fn insert(
+        &self,
+        key: ResultCacheKey,
+        version: u64,
+        schema: SchemaRef,
+        batches: Vec<RecordBatch>,
+        size: usize,
+    ) {
+        let mut entries = self.lock();
+        // the catalogs or data changed while the query ran
+        if self.version.load(Ordering::SeqCst) != version {
+            return;
+        }
+
+        entries.remove(&key);
+        while entries.size + size > self.max_bytes {
+            let least_recently_used = entries
+                .results
+                .iter()
+                .min_by_key(|(_, cached)| cached.last_used)
+                .map(|(key, _)| key.clone());
+            let Some(least_recently_used) = least_recently_used else {
+                break;
+            };
+            entries.remove(&least_recently_used);
+        }
+
+        entries.tick += 1;
+        let last_used = entries.tick;
+        entries.size += size;
+        entries.results.insert(
+            key,
+            CachedResult {
+                schema,
+                batches,
+                size,
+                cached_at: Instant::now(),
+                last_used,
+            },
+        );
+    }
+}
+
+/// Clears the result cache when dropped, see [`ResultCache::invalidate`]
+pub(crate) struct Invalidation(Arc<ResultCache>);
+
+impl Invalidation {
+    /// Hold the guard until the rows of `resp` are consumed
+    pub(crate) fn hold_for<'a>(self, resp: QueryResponse<'a>) -> QueryResponse<'a> {
+        let fields = resp.row_schema();
+        let data_rows = resp.data_rows().map(move |row| {
+            let _invalidation = &self;
+            row
+        });
+        QueryResponse::new(fields, data_rows)
+    }
+}
+
+impl Drop for Invalidation {
+    fn drop(&mut self) {
+        self.0.clear();
+    }
+}
+
+/// Whether running the statement may change the results of cached queries
+pub(crate) fn invalidates_results(statement: &SqlStatement) -> bool {
+    !matches!(
+        statement,
+        SqlStatement::Query(_) | SqlStatement::Explain { .. } | SqlStatement::ShowVariable { .. }
+    )
+}
+
+/// Whether the result of the plan only changes with the catalogs and data
+fn is_cacheable(plan: &LogicalPlan) -> bool {
+    if matches!(
+        plan,
+        LogicalPlan::Dml(_)
+            | LogicalPlan::Ddl(_)
+            | LogicalPlan::Copy(_)
+            | LogicalPlan::Statement(_)
+            | LogicalPlan::Explain(_)
+            | LogicalPlan::Analyze(_)
+            | LogicalPlan::DescribeTable(_)
+    ) {
+        return false;
+    }
+
+    let mut cacheable = true;
+    let _ = plan.apply_with_subqueries(|node| {
+        // system catalogs change with the sessions and settings too
+        if let LogicalPlan::TableScan(scan) = node {
+            if matches!(
+                scan.table_name.schema(),
+                Some("pg_catalog" | "information_schema")
+            ) {
+                cacheable = false;
+            }
+        }
+        node.apply_expressions(|expr| {
+            expr.apply(|expr| {
+                if let Expr::ScalarFunction(function) = expr {
+                    if function.func.signature().volatility != Volatility::Immutable {
+                        cacheable = false;
+                    }
+                }
+                Ok(if cacheable {
+                    TreeNodeRecursion::Continue
+                } else {
+                    TreeNodeRecursion::Stop
+                })
+            })
+        })?;
+        Ok(if cacheable {
+            TreeNodeRecursion::Continue
+        } else {
+            TreeNodeRecursion::Stop
+        })
+    });
+    cacheable
+}
+
+/// The result of a query too large to cache, read once
+struct RemainingResult {
+    schema: SchemaRef,
+    stream: Mutex<Option<SendableRecordBatchStream>>,
+}
+
+impl RemainingResult {
+    fn new(stream: SendableRecordBatchStream) -> Self {
+        RemainingResult {
+            schema: stream.schema(),
+            stream: Mutex::new(Some(stream)),
+        }
+    }
+}
+
+impl fmt::Debug for RemainingResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RemainingResult")
+            .field("schema", &self.schema)
+            .finish()
+    }
+}
+
+impl PartitionStream for RemainingResult {
+    fn schema(&self) -> &SchemaRef {
+        &self.schema
+    }
+
+    fn execute(&self, _ctx: Arc<TaskContext>) -> SendableRecordBatchStream {
+        let stream = self.stream.lock().unwrap_or_else(|e| e.into_inner()).take();
+        stream.unwrap_or_else(|| {
+            Box::pin(RecordBatchStreamAdapter::new(
+                self.schema.clone(),
+                stream::once(async {
+                    Err(DataFusionError::Execution(
+                        "the result of the query was already read".to_string(),
+                    ))
+                }),
+            ))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datafusion::arrow::array::Int32Array;
+    use datafusion::arrow::datatypes::{DataType, Field, Schema};
+
+    async fn cached_rows(cache: &ResultCache, ctx: &SessionContext, sql: &str) -> usize {
+        let key = ResultCacheKey::new(&ctx.state(), "postgres", sql.to_string());
+        let df = cache
+            .dataframe(ctx, key, ctx.sql(sql).await.unwrap())
+            .await
+            .unwrap();
+        df.collect()
+            .await
+            .unwrap()
+            .iter()
+            .map(|batch| batch.num_rows())
+            .sum()
+    }
+
+    fn register_numbers(ctx: &SessionContext, numbers: Vec<i32>) {
+        let schema = Arc::new(Schema::new(vec![Field::new("n", DataType::Int32, false)]));
+        let batch =
+            RecordBatch::try_new(schema, vec![Arc::new(Int32Array::from(numbers))]).unwrap();
+        ctx.deregister_table("t").unwrap();
+        ctx.register_batch("t", batch).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_result_cache() {
+        let ctx = SessionContext::new();
+        register_numbers(&ctx, vec![1, 2]);
+
+        let cache = Arc::new(ResultCache::new(1024 * 1024, Duration::from_secs(60)));
+        assert_eq!(cached_rows(&cache, &ctx, "SELECT n FROM t").await, 2);
+        assert_eq!(cache.len(), 1);
+        assert!(cache.size() > 0);
+        // served from the cache, not from the table
+        register_numbers(&ctx, vec![1, 2, 3]);
+        assert_eq!(cached_rows(&cache, &ctx, "SELECT n FROM t").await, 2);
+
+        // volatile and stable functions and system catalogs aren't cached
+        cached_rows(&cache, &ctx, "SELECT random()").await;
+        cached_rows(&cache, &ctx, "SELECT now()").await;
+        assert_eq!(cache.len(), 1);
+
+        let invalidation = cache.invalidate();
+        assert!(cache.is_empty());
+        assert_eq!(cached_rows(&cache, &ctx, "SELECT 1").await, 1);
+        drop(invalidation);
+        assert!(cache.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_result_cache_bounds() {
+        let ctx = SessionContext::new();
+        // results larger than the cache are streamed, not cached
+        let cache = ResultCache::new(64, Duration::from_secs(60));
+        assert_eq!(
+            cached_rows(&cache, &ctx, "SELECT * FROM generate_series(1, 10000)").await,
+            10000
+        );
+        assert!(cache.is_empty());
+
+        register_numbers(&ctx, vec![1, 2]);
+        let cache = ResultCache::new(1024 * 1024, Duration::ZERO);
+        cached_rows(&cache, &ctx, "SELECT n FROM t").await;
+        register_numbers(&ctx, vec![1, 2, 3]);
+        std::thread::sleep(Duration::from_millis(1));
+        // the expired result is run again, and cached anew
+        assert_eq!(cached_rows(&cache, &ctx, "SELECT n FROM t").await, 3);
+        assert_eq!(cache.len(), 1);
+    }
+}
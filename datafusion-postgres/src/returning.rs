@@ -0,0 +1,189 @@
+//! `INSERT ... RETURNING`, which DataFusion doesn't plan.
+//!
+//! ORMs read the keys and defaults of the rows they insert back with
+//! `INSERT INTO users (name) VALUES ('ada') RETURNING id`. The `INSERT` is
+//! planned without `RETURNING`, the rows it inserts are computed first, and
+//! once inserted they are answered projected with the `RETURNING` items.
+
+use std::sync::Arc;
+
+use datafusion::common::{plan_err, DFSchema, Result, TableReference};
+use datafusion::datasource::{provider_as_source, MemTable};
+use datafusion::execution::context::SessionState;
+use datafusion::logical_expr::{DmlStatement, Expr, LogicalPlan, LogicalPlanBuilder};
+use datafusion::prelude::{DataFrame, SessionContext};
+use datafusion::sql::planner::IdentNormalizer;
+use datafusion::sql::sqlparser::ast::{Insert, SelectItem, Statement};
+
+/// The `INSERT` of `statement` without its `RETURNING` items, and the items,
+/// `None` for other statements
+pub(crate) fn split_returning(statement: &Statement) -> Option<(Statement, Vec<SelectItem>)> {
+    let Statement::Insert(insert) = statement else {
+        return None;
+    };
+    let returning = insert.returning.clone()?;
+    let insert = Insert {
+        returning: None,
+        ..insert.clone()
+    };
+    Some((Statement::Insert(insert), returning))
+}
+
+/// The plan of the rows the `INSERT` plan `insert` inserts, projected with
+/// the `RETURNING` items, which describes the result of the statement
+pub(crate) fn returning_plan(
+    state: &SessionState,
+    insert: LogicalPlan,
+    returning: &[SelectItem],
+) -> Result<LogicalPlan> {
+    let LogicalPlan::Dml(insert) = insert else {
+        return plan_err!("RETURNING is only supported by INSERT");
+    };
+    project_returning(
+        state,
+        insert.input.as_ref().clone(),
+        &insert.table_name,
+        returning,
+    )
+}
+
+fn project_returning(
+    state: &SessionState,
+    rows: LogicalPlan,
+    table: &TableReference,
+    returning: &[SelectItem],
+) -> Result<LogicalPlan> {
+    let rows = LogicalPlanBuilder::from(rows).alias(table.table())?;
+    let exprs = returning_exprs(state, rows.schema(), returning)?;
+    rows.project(exprs)?.build()
+}
+
+fn returning_exprs(
+    state: &SessionState,
+    schema: &DFSchema,
+    returning: &[SelectItem],
+) -> Result<Vec<Expr>> {
+    let normalizer = IdentNormalizer::default();
+    let mut exprs = Vec::with_capacity(returning.len());
+    for item in returning {
+        match item {
+            SelectItem::UnnamedExpr(expr) => {
+                exprs.push(state.create_logical_expr(&expr.to_string(), schema)?);
+            }
+            SelectItem::ExprWithAlias { expr, alias } => {
+                let expr = state.create_logical_expr(&expr.to_string(), schema)?;
+                exprs.push(expr.alias(normalizer.normalize(alias.clone())));
+            }
+            SelectItem::Wildcard(_) | SelectItem::QualifiedWildcard(..) => {
+                exprs.extend(schema.columns().into_iter().map(Expr::Column));
+            }
+        }
+    }
+    Ok(exprs)
+}
+
+/// Insert the rows of the `INSERT` plan `insert`, answering them projected
+/// with the `RETURNING` items
+///
+/// The rows are computed before they are inserted, so that defaults and
+/// volatile functions like `uuid()` answer the values inserted.
+pub(crate) async fn insert_returning(
+    session_context: &SessionContext,
+    insert: LogicalPlan,
+    returning: &[SelectItem],
+) -> Result<DataFrame> {
+    let LogicalPlan::Dml(insert) = insert else {
+        return plan_err!("RETURNING is only supported by INSERT");
+    };
+    let batches = session_context
+        .execute_logical_plan(insert.input.as_ref().clone())
+        .await?
+        .collect()
+        .await?;
+    let schema = match batches.first() {
+        Some(batch) => batch.schema(),
+        None => Arc::new(insert.input.schema().as_arrow().clone()),
+    };
+    let rows = LogicalPlanBuilder::scan(
+        insert.table_name.table(),
+        provider_as_source(Arc::new(MemTable::try_new(schema, vec![batches])?)),
+        None,
+    )?
+    .build()?;
+
+    let table = insert.table_name.clone();
+    let insert = LogicalPlan::Dml(DmlStatement {
+        input: Arc::new(rows.clone()),
+        ..insert
+    });
+    session_context
+        .execute_logical_plan(insert)
+        .await?
+        .collect()
+        .await?;
+
+    let plan = project_returning(&session_context.state(), rows, &table, returning)?;
+    session_context.execute_logical_plan(plan).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql::parse;
+
+    #[tokio::test]
+    async fn test_insert_returning() {
+        let ctx = SessionContext::new();
+        ctx.sql("CREATE TABLE users (id INT, name VARCHAR, active BOOLEAN)")
+            .await
+            .unwrap();
+        let statement = parse(
+            "INSERT INTO users (id, name) VALUES (1, 'ada'), (2, 'grace') \
+             RETURNING id AS key, upper(name) AS shout, *",
+        )
+        .unwrap()
+        .remove(0);
+        let (insert, returning) = split_returning(&statement).unwrap();
+        assert_eq!(
+            insert.to_string(),
+            "INSERT INTO users (id, name) VALUES (1, 'ada'), (2, 'grace')"
+        );
+        assert!(split_returning(&insert).is_none());
+
+        let plan = ctx
+            .state()
+            .statement_to_plan(datafusion::sql::parser::Statement::Statement(Box::new(
+                insert,
+            )))
+            .await
+            .unwrap();
+        let batches = insert_returning(&ctx, plan, &returning)
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+        let expected = [
+            "+-----+-------+----+-------+--------+",
+            "| key | shout | id | name  | active |",
+            "+-----+-------+----+-------+--------+",
+            "| 1   | ADA   | 1  | ada   |        |",
+            "| 2   | GRACE | 2  | grace |        |",
+            "+-----+-------+----+-------+--------+",
+        ];
+        datafusion::assert_batches_eq!(expected, &batches);
+
+        let rows = ctx.sql("SELECT count(*) FROM users").await.unwrap();
+        let batches = rows.collect().await.unwrap();
+        datafusion::assert_batches_eq!(
+            [
+                "+----------+",
+                "| count(*) |",
+                "+----------+",
+                "| 2        |",
+                "+----------+"
+            ],
+            &batches
+        );
+    }
+}
@@ -0,0 +1,548 @@
+//! `ALTER ROLE ... SET` and `ALTER DATABASE ... SET`, the defaults of the
+//! settings of new sessions per role and database.
+//!
+//! ```sql
+//! ALTER DATABASE analytics SET search_path = reporting;
+//! ALTER ROLE analyst SET statement_timeout = '30s';
+//! ALTER ROLE analyst IN DATABASE analytics SET TimeZone = 'Europe/Berlin';
+//! ALTER ROLE ALL SET TimeZone = 'UTC';
+//! ```
+//!
+//! `search_path`, `statement_timeout` and `TimeZone` have defaults, listed
+//! in `pg_db_role_setting`. Like postgres, a session starts with the
+//! defaults of all roles, overridden by those of its database, of its role
+//! and of its role in its database, in this order. Settings the client sends
+//! in the startup packet override them all, and `SET` changes them for the
+//! rest of the session. Sessions already running keep their settings.
+
+use std::collections::BTreeMap;
+use std::sync::RwLock;
+use std::time::Duration;
+
+use datafusion::sql::planner::IdentNormalizer;
+use datafusion::sql::sqlparser::ast::{
+    AlterRoleOperation, Expr, Ident, ObjectName, ResetConfig, SetConfigValue,
+    Statement as SqlStatement, Value,
+};
+use datafusion::sql::sqlparser::dialect::PostgreSqlDialect;
+use datafusion::sql::sqlparser::keywords::Keyword;
+use datafusion::sql::sqlparser::parser::{Parser, ParserError};
+use datafusion::sql::sqlparser::tokenizer::Token;
+use pgwire::error::{ErrorInfo, PgWireError, PgWireResult};
+
+use crate::errors::syntax_error;
+use crate::sql::{parse_name, parse_word};
+use crate::time_zone::time_zone;
+
+/// The settings with defaults per role and database, by their names in
+/// `SHOW` and `pg_settings`
+pub const SESSION_DEFAULTS: &[&str] = &["search_path", "statement_timeout", "TimeZone"];
+
+/// Database and role of defaults, `None` for all of them
+type DbRoleKey = (Option<String>, Option<String>);
+
+/// Defaults of settings of the roles and databases of a server
+///
+/// The handlers attach it to the `SessionConfig` of statements as an
+/// extension, for `pg_db_role_setting` to list the defaults.
+#[derive(Debug, Default)]
+pub struct DbRoleSettings {
+    settings: RwLock<BTreeMap<DbRoleKey, BTreeMap<String, String>>>,
+}
+
+/// The defaults of a database and role, a row of `pg_db_role_setting`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DbRoleSetting {
+    /// The database the defaults apply to, all if `None`
+    pub database: Option<String>,
+    /// The role the defaults apply to, all if `None`
+    pub role: Option<String>,
+    /// Values of the settings by name
+    pub settings: BTreeMap<String, String>,
+}
+
+impl DbRoleSettings {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the default of a setting of sessions of `role` in `database`
+    pub fn set(&self, database: Option<&str>, role: Option<&str>, name: &str, value: &str) {
+        self.settings
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry((database.map(str::to_string), role.map(str::to_string)))
+            .or_default()
+            .insert(name.to_string(), value.to_string());
+    }
+
+    /// Forget the default of the setting of `name`, or of all settings if
+    /// `None`
+    pub fn reset(&self, database: Option<&str>, role: Option<&str>, name: Option<&str>) {
+        let mut settings = self.settings.write().unwrap_or_else(|e| e.into_inner());
+        let key = (database.map(str::to_string), role.map(str::to_string));
+        if let (Some(name), Some(defaults)) = (name, settings.get_mut(&key)) {
+            defaults.remove(name);
+            if !defaults.is_empty() {
+                return;
+            }
+        }
+        settings.remove(&key);
+    }
+
+    /// Forget the defaults of a dropped role
+    pub fn forget_role(&self, role: &str) {
+        self.settings
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .retain(|(_, setting_role), _| setting_role.as_deref() != Some(role));
+    }
+
+    /// Forget the defaults of a dropped database
+    pub fn forget_database(&self, database: &str) {
+        self.settings
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .retain(|(setting_database, _), _| setting_database.as_deref() != Some(database));
+    }
+
+    /// All defaults, ordered by database and role
+    pub fn entries(&self) -> Vec<DbRoleSetting> {
+        self.settings
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .map(|((database, role), settings)| DbRoleSetting {
+                database: database.clone(),
+                role: role.clone(),
+                settings: settings.clone(),
+            })
+            .collect()
+    }
+
+    /// The settings a new session of `role` in `database` starts with, the
+    /// more specific defaults overriding the others
+    pub fn session_defaults(&self, database: &str, role: &str) -> BTreeMap<String, String> {
+        self.session_default_sources(database, role)
+            .into_iter()
+            .map(|(name, (value, _))| (name, value))
+            .collect()
+    }
+
+    /// The settings of `session_defaults` with their source in
+    /// `pg_settings`: `global` for defaults of all roles, `database`, `user`
+    /// or `database user` for those of the database, the role or the role in
+    /// the database
+    pub fn session_default_sources(
+        &self,
+        database: &str,
+        role: &str,
+    ) -> BTreeMap<String, (String, &'static str)> {
+        let settings = self.settings.read().unwrap_or_else(|e| e.into_inner());
+        let database = Some(database.to_string());
+        let role = Some(role.to_string());
+        let mut defaults = BTreeMap::new();
+        for (key, source) in [
+            ((None, None), "global"),
+            ((database.clone(), None), "database"),
+            ((None, role.clone()), "user"),
+            ((database, role), "database user"),
+        ] {
+            if let Some(settings) = settings.get(&key) {
+                defaults.extend(
+                    settings
+                        .iter()
+                        .map(|(name, value)| (name.clone(), (value.clone(), source))),
+                );
+            }
+        }
+        defaults
+    }
+}
+
+/// A change of the defaults of a role or database
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct SettingStatement {
+    /// `ALTER ROLE` or `ALTER DATABASE`
+    pub(crate) command: &'static str,
+    /// The database of `ALTER DATABASE` or `IN DATABASE`, all if `None`
+    pub(crate) database: Option<String>,
+    /// The role of `ALTER ROLE`, all for `ALTER ROLE ALL` and `ALTER
+    /// DATABASE`
+    pub(crate) role: Option<String>,
+    pub(crate) change: SettingChange,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum SettingChange {
+    Set {
+        name: &'static str,
+        value: String,
+    },
+    /// `RESET` of a setting, or of all settings if `None`
+    Reset {
+        name: Option<&'static str>,
+    },
+}
+
+impl SettingStatement {
+    /// The change of `ALTER ROLE ... SET` and `ALTER ROLE ... RESET`, `None`
+    /// if `statement` is none
+    pub(crate) fn from_statement(
+        statement: &SqlStatement,
+    ) -> Option<PgWireResult<SettingStatement>> {
+        let SqlStatement::AlterRole { name, operation } = statement else {
+            return None;
+        };
+        let (change, in_database) = match operation {
+            AlterRoleOperation::Set {
+                config_name,
+                config_value,
+                in_database,
+            } => {
+                let change = setting_name(&config_name.to_string()).and_then(|name| {
+                    match config_value {
+                        SetConfigValue::Value(value) => set_change(name, value),
+                        SetConfigValue::Default => Ok(SettingChange::Reset { name: Some(name) }),
+                        SetConfigValue::FromCurrent => {
+                            Err(PgWireError::UserError(Box::new(ErrorInfo::new(
+                                "ERROR".to_string(),
+                                "0A000".to_string(), // feature_not_supported
+                                "SET FROM CURRENT is not supported for role defaults".to_string(),
+                            ))))
+                        }
+                    }
+                });
+                (change, in_database)
+            }
+            AlterRoleOperation::Reset {
+                config_name,
+                in_database,
+            } => {
+                let change = match config_name {
+                    ResetConfig::ALL => Ok(SettingChange::Reset { name: None }),
+                    ResetConfig::ConfigName(name) => setting_name(&name.to_string())
+                        .map(|name| SettingChange::Reset { name: Some(name) }),
+                };
+                (change, in_database)
+            }
+            _ => return None,
+        };
+        Some(change.map(|change| SettingStatement {
+            command: "ALTER ROLE",
+            database: in_database.as_ref().map(object_name),
+            role: role_name(name),
+            change,
+        }))
+    }
+
+    /// Whether the query is `ALTER DATABASE`, which the SQL parser of
+    /// DataFusion doesn't parse
+    pub(crate) fn matches(query_lower: &str) -> bool {
+        let mut words = query_lower.split_whitespace();
+        words.next() == Some("alter") && words.next() == Some("database")
+    }
+
+    /// Parse `ALTER DATABASE name SET ...` and `ALTER DATABASE name RESET
+    /// ...`
+    pub(crate) fn parse(sql: &str) -> PgWireResult<SettingStatement> {
+        let statement = (|| -> Result<_, ParserError> {
+            let dialect = PostgreSqlDialect {};
+            let mut parser = Parser::new(&dialect).try_with_sql(sql)?;
+            parser.expect_keywords(&[Keyword::ALTER, Keyword::DATABASE])?;
+            let database = parse_name(&mut parser)?;
+            let change = if parser.parse_keyword(Keyword::SET) {
+                let name = parser.parse_object_name(false)?.to_string();
+                if !parser.consume_token(&Token::Eq) {
+                    parser.expect_keyword_is(Keyword::TO)?;
+                }
+                let value = if parser.parse_keyword(Keyword::DEFAULT) {
+                    None
+                } else {
+                    Some(parser.parse_expr()?)
+                };
+                (name, value)
+            } else if parser.parse_keyword(Keyword::RESET) {
+                if parser.parse_keyword(Keyword::ALL) {
+                    (String::new(), None)
+                } else {
+                    (parser.parse_object_name(false)?.to_string(), None)
+                }
+            } else if parse_word(&mut parser, "rename")
+                || parse_word(&mut parser, "owner")
+                || parser.parse_keyword(Keyword::WITH)
+            {
+                return Ok(Err(PgWireError::UserError(Box::new(ErrorInfo::new(
+                    "ERROR".to_string(),
+                    "0A000".to_string(), // feature_not_supported
+                    "ALTER DATABASE only supports SET and RESET".to_string(),
+                )))));
+            } else {
+                return parser.expected("SET or RESET", parser.peek_token());
+            };
+            let _ = parser.consume_token(&Token::SemiColon);
+            parser.expect_token(&Token::EOF)?;
+            Ok(Ok((database, change)))
+        })()
+        .map_err(|e| syntax_error(e, sql))?;
+
+        let (database, (name, value)) = statement?;
+        let change = match (name.as_str(), value) {
+            ("", _) => SettingChange::Reset { name: None },
+            (name, None) => SettingChange::Reset {
+                name: Some(setting_name(name)?),
+            },
+            (name, Some(value)) => set_change(setting_name(name)?, &value)?,
+        };
+        Ok(SettingStatement {
+            command: "ALTER DATABASE",
+            database: Some(database),
+            role: None,
+            change,
+        })
+    }
+}
+
+/// The duration of a `statement_timeout` like `5000`, `500ms`, `5s` or
+/// `1min`, `None` for 0 which disables the timeout
+pub(crate) fn statement_timeout(value: &str) -> Result<Option<Duration>, std::num::ParseIntError> {
+    let value = value.trim().trim_matches(['"', '\'']);
+    if value.is_empty() {
+        return Ok(None);
+    }
+    let millis = if let Some(millis) = value.strip_suffix("ms") {
+        millis.trim().parse::<u64>()?
+    } else if let Some(minutes) = value.strip_suffix("min") {
+        minutes.trim().parse::<u64>()? * 60 * 1000
+    } else if let Some(seconds) = value.strip_suffix('s') {
+        seconds.trim().parse::<u64>()? * 1000
+    } else {
+        value.parse::<u64>()?
+    };
+    Ok((millis > 0).then(|| Duration::from_millis(millis)))
+}
+
+fn set_change(name: &'static str, value: &Expr) -> PgWireResult<SettingChange> {
+    let text = match value {
+        Expr::Value(value) => match &value.value {
+            Value::SingleQuotedString(text) | Value::DoubleQuotedString(text) => text.clone(),
+            value => value.to_string(),
+        },
+        Expr::Identifier(ident) => ident.value.clone(),
+        value => value.to_string(),
+    };
+    let value = match name {
+        "TimeZone" => match time_zone(value)? {
+            Some(zone) => zone,
+            // LOCAL and DEFAULT are the zone of the server
+            None => return Ok(SettingChange::Reset { name: Some(name) }),
+        },
+        "statement_timeout" => {
+            statement_timeout(&text).map_err(|_| invalid_value(name, &text))?;
+            text
+        }
+        _ if text.is_empty() || text.contains(',') => return Err(invalid_value(name, &text)),
+        _ => text,
+    };
+    Ok(SettingChange::Set { name, value })
+}
+
+/// The name of a setting with defaults, by any case of its name
+fn setting_name(name: &str) -> PgWireResult<&'static str> {
+    SESSION_DEFAULTS
+        .iter()
+        .find(|setting| setting.eq_ignore_ascii_case(name))
+        .copied()
+        .ok_or_else(|| {
+            PgWireError::UserError(Box::new(ErrorInfo::new(
+                "ERROR".to_string(),
+                "0A000".to_string(), // feature_not_supported
+                format!(
+                    "parameter \"{name}\" has no defaults per role or database, only {} have",
+                    SESSION_DEFAULTS.join(", ")
+                ),
+            )))
+        })
+}
+
+/// The role of `ALTER ROLE`, `None` for `ALL`
+fn role_name(ident: &Ident) -> Option<String> {
+    if ident.quote_style.is_none() && ident.value.eq_ignore_ascii_case("all") {
+        return None;
+    }
+    Some(IdentNormalizer::new(true).normalize(ident.clone()))
+}
+
+fn object_name(name: &ObjectName) -> String {
+    match name.0.last().and_then(|part| part.as_ident()) {
+        Some(ident) => IdentNormalizer::new(true).normalize(ident.clone()),
+        None => name.to_string(),
+    }
+}
+
+fn invalid_value(name: &str, value: &str) -> PgWireError {
+    PgWireError::UserError(Box::new(ErrorInfo::new(
+        "ERROR".to_string(),
+        "22023".to_string(), // invalid_parameter_value
+        format!("invalid value for parameter \"{name}\": \"{value}\""),
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn alter_role(sql: &str) -> PgWireResult<SettingStatement> {
+        let statement = crate::sql::parse(sql).unwrap().remove(0);
+        SettingStatement::from_statement(&statement).unwrap()
+    }
+
+    fn code(result: PgWireResult<SettingStatement>) -> String {
+        match result {
+            Err(PgWireError::UserError(info)) => info.code,
+            result => panic!("expected an error, got {result:?}"),
+        }
+    }
+
+    #[test]
+    fn test_setting_statements() {
+        assert_eq!(
+            alter_role("ALTER ROLE Analyst IN DATABASE analytics SET timezone = 'UTC'").unwrap(),
+            SettingStatement {
+                command: "ALTER ROLE",
+                database: Some("analytics".to_string()),
+                role: Some("analyst".to_string()),
+                change: SettingChange::Set {
+                    name: "TimeZone",
+                    value: "UTC".to_string(),
+                },
+            }
+        );
+        assert_eq!(
+            alter_role("ALTER ROLE ALL RESET ALL").unwrap(),
+            SettingStatement {
+                command: "ALTER ROLE",
+                database: None,
+                role: None,
+                change: SettingChange::Reset { name: None },
+            }
+        );
+        assert_eq!(
+            alter_role("ALTER ROLE analyst SET statement_timeout TO DEFAULT")
+                .unwrap()
+                .change,
+            SettingChange::Reset {
+                name: Some("statement_timeout")
+            }
+        );
+        assert!(SettingStatement::from_statement(
+            &crate::sql::parse("ALTER ROLE a LOGIN").unwrap()[0]
+        )
+        .is_none());
+        assert_eq!(
+            code(alter_role("ALTER ROLE analyst SET work_mem = '64MB'")),
+            "0A000"
+        );
+        assert_eq!(
+            code(alter_role(
+                "ALTER ROLE analyst SET statement_timeout = 'soon'"
+            )),
+            "22023"
+        );
+        assert_eq!(
+            code(alter_role(
+                "ALTER ROLE analyst SET TimeZone = 'Mars/Olympus'"
+            )),
+            "22023"
+        );
+
+        assert!(SettingStatement::matches(
+            "alter  database analytics set x = 1"
+        ));
+        assert!(!SettingStatement::matches("alter table analytics"));
+        assert_eq!(
+            SettingStatement::parse("ALTER DATABASE Analytics SET search_path TO reporting;")
+                .unwrap(),
+            SettingStatement {
+                command: "ALTER DATABASE",
+                database: Some("analytics".to_string()),
+                role: None,
+                change: SettingChange::Set {
+                    name: "search_path",
+                    value: "reporting".to_string(),
+                },
+            }
+        );
+        assert_eq!(
+            SettingStatement::parse("ALTER DATABASE analytics RESET statement_timeout")
+                .unwrap()
+                .change,
+            SettingChange::Reset {
+                name: Some("statement_timeout")
+            }
+        );
+        assert_eq!(
+            code(SettingStatement::parse("ALTER DATABASE a RENAME TO b")),
+            "0A000"
+        );
+        assert_eq!(
+            code(SettingStatement::parse("ALTER DATABASE a SET")),
+            "42601"
+        );
+    }
+
+    #[test]
+    fn test_session_defaults() {
+        let settings = DbRoleSettings::new();
+        settings.set(None, None, "TimeZone", "UTC");
+        settings.set(Some("analytics"), None, "search_path", "reporting");
+        settings.set(Some("analytics"), None, "TimeZone", "Asia/Tokyo");
+        settings.set(None, Some("analyst"), "TimeZone", "Europe/Berlin");
+        settings.set(
+            Some("analytics"),
+            Some("analyst"),
+            "statement_timeout",
+            "30s",
+        );
+
+        let defaults = settings.session_defaults("analytics", "analyst");
+        assert_eq!(defaults["TimeZone"], "Europe/Berlin");
+        assert_eq!(defaults["search_path"], "reporting");
+        assert_eq!(defaults["statement_timeout"], "30s");
+        assert_eq!(
+            settings.session_defaults("analytics", "viewer")["TimeZone"],
+            "Asia/Tokyo"
+        );
+        assert_eq!(settings.session_defaults("other", "viewer").len(), 1);
+        let sources = settings.session_default_sources("analytics", "analyst");
+        assert_eq!(sources["TimeZone"].1, "user");
+        assert_eq!(sources["search_path"].1, "database");
+        assert_eq!(sources["statement_timeout"].1, "database user");
+        assert_eq!(
+            settings.session_default_sources("other", "viewer")["TimeZone"].1,
+            "global"
+        );
+
+        settings.reset(Some("analytics"), None, Some("TimeZone"));
+        settings.forget_role("analyst");
+        assert_eq!(
+            settings.session_defaults("analytics", "analyst")["TimeZone"],
+            "UTC"
+        );
+        assert_eq!(settings.entries().len(), 2);
+        settings.reset(None, None, None);
+        settings.forget_database("analytics");
+        assert!(settings.entries().is_empty());
+
+        assert_eq!(statement_timeout("0").unwrap(), None);
+        assert_eq!(
+            statement_timeout("'1min'").unwrap(),
+            Some(Duration::from_secs(60))
+        );
+        assert_eq!(
+            statement_timeout("250ms").unwrap(),
+            Some(Duration::from_millis(250))
+        );
+        assert!(statement_timeout("5 hours").is_err());
+    }
+}
@@ -0,0 +1,495 @@
+//! `CREATE ROLE`, `ALTER ROLE` and `DROP ROLE`, managing the users and
+//! roles of the [`AuthManager`](crate::auth::AuthManager) over SQL.
+//!
+//! Like postgres, a role is a user, which may login with the `LOGIN`
+//! attribute. Changes apply to new connections and statements at once and
+//! are shown by `pg_roles`:
+//!
+//! ```sql
+//! CREATE ROLE analyst WITH LOGIN PASSWORD 's3cret' CONNECTION LIMIT 5;
+//! ALTER ROLE analyst CREATEDB;
+//! DROP ROLE IF EXISTS analyst;
+//! ```
+//!
+//! With a credentials file the roles are read from it when the server
+//! starts, and written back to it after each change. It has a line per
+//! role of its name, password and attributes separated by colons, the
+//! password as a SCRAM-SHA-256 verifier of its salt and salted password.
+//! The file is only readable by the server's user:
+//!
+//! ```text
+//! # name:password:attributes
+//! postgres::SUPERUSER LOGIN
+//! analyst:SCRAM-SHA-256$4096:<salt>$<salted password>:LOGIN CREATEDB CONNECTION LIMIT 5
+//! ```
+
+use datafusion::sql::planner::IdentNormalizer;
+use datafusion::sql::sqlparser::ast::{
+    AlterRoleOperation, Expr, Ident, ObjectName, ObjectType, Password, RoleOption,
+    Statement as SqlStatement, Value,
+};
+use pgwire::error::{ErrorInfo, PgWireError, PgWireResult};
+
+/// Attributes of a role set by `CREATE ROLE` and `ALTER ROLE`, `None`
+/// keeps the current value, or the default of a new role
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RoleAttributes {
+    pub superuser: Option<bool>,
+    pub login: Option<bool>,
+    pub create_db: Option<bool>,
+    pub create_role: Option<bool>,
+    pub replication: Option<bool>,
+    pub password: Option<String>,
+    /// Concurrent connections of the role, -1 for no limit
+    pub connection_limit: Option<i32>,
+}
+
+impl RoleAttributes {
+    /// Whether the attributes only change the password, which users may
+    /// do of themselves
+    pub fn is_password_only(&self) -> bool {
+        self == &RoleAttributes {
+            password: self.password.clone(),
+            ..RoleAttributes::default()
+        }
+    }
+}
+
+/// A role statement
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum RoleStatement {
+    Create {
+        names: Vec<String>,
+        if_not_exists: bool,
+        attributes: RoleAttributes,
+        /// Roles the new roles are members of, by `IN ROLE`
+        member_of: Vec<String>,
+    },
+    Alter {
+        name: String,
+        attributes: RoleAttributes,
+    },
+    Drop {
+        names: Vec<String>,
+        if_exists: bool,
+    },
+}
+
+impl RoleStatement {
+    /// The role statement of `statement`, `None` if it is none
+    ///
+    /// `ALTER ROLE ... SET` is no role statement here, as it changes the
+    /// settings of sessions rather than the role.
+    pub(crate) fn from_statement(statement: &SqlStatement) -> Option<PgWireResult<RoleStatement>> {
+        match statement {
+            SqlStatement::CreateRole {
+                names,
+                if_not_exists,
+                login,
+                inherit,
+                bypassrls,
+                password,
+                superuser,
+                create_db,
+                create_role,
+                replication,
+                connection_limit,
+                valid_until,
+                in_role,
+                in_group,
+                role,
+                user,
+                admin,
+                authorization_owner,
+            } => Some((|| {
+                if inherit == &Some(false) {
+                    return Err(unsupported("NOINHERIT"));
+                }
+                if bypassrls == &Some(true) {
+                    return Err(unsupported("BYPASSRLS"));
+                }
+                if valid_until.is_some() {
+                    return Err(unsupported("VALID UNTIL"));
+                }
+                if !role.is_empty() || !user.is_empty() || !admin.is_empty() {
+                    return Err(unsupported("ROLE, USER and ADMIN"));
+                }
+                if authorization_owner.is_some() {
+                    return Err(unsupported("AUTHORIZATION"));
+                }
+                let attributes = RoleAttributes {
+                    superuser: *superuser,
+                    login: *login,
+                    create_db: *create_db,
+                    create_role: *create_role,
+                    replication: *replication,
+                    password: password.as_ref().map(role_password).transpose()?,
+                    connection_limit: connection_limit
+                        .as_ref()
+                        .map(role_connection_limit)
+                        .transpose()?,
+                };
+                Ok(RoleStatement::Create {
+                    names: names.iter().map(role_name).collect(),
+                    if_not_exists: *if_not_exists,
+                    attributes,
+                    member_of: in_role.iter().chain(in_group).map(ident_name).collect(),
+                })
+            })()),
+            SqlStatement::AlterRole { name, operation } => match operation {
+                AlterRoleOperation::WithOptions { options } => Some(role_options(options).map(
+                    |attributes| RoleStatement::Alter {
+                        name: ident_name(name),
+                        attributes,
+                    },
+                )),
+                AlterRoleOperation::Set { .. } | AlterRoleOperation::Reset { .. } => None,
+                _ => Some(Err(unsupported(&operation.to_string()))),
+            },
+            SqlStatement::Drop {
+                object_type: ObjectType::Role,
+                if_exists,
+                names,
+                ..
+            } => Some(Ok(RoleStatement::Drop {
+                names: names.iter().map(role_name).collect(),
+                if_exists: *if_exists,
+            })),
+            _ => None,
+        }
+    }
+
+    /// The command tag of the statement
+    pub(crate) fn tag(&self) -> &'static str {
+        match self {
+            RoleStatement::Create { .. } => "CREATE ROLE",
+            RoleStatement::Alter { .. } => "ALTER ROLE",
+            RoleStatement::Drop { .. } => "DROP ROLE",
+        }
+    }
+}
+
+fn ident_name(ident: &Ident) -> String {
+    IdentNormalizer::new(true).normalize(ident.clone())
+}
+
+fn role_name(name: &ObjectName) -> String {
+    match name.0.last().and_then(|part| part.as_ident()) {
+        Some(ident) => ident_name(ident),
+        None => name.to_string(),
+    }
+}
+
+fn role_options(options: &[RoleOption]) -> PgWireResult<RoleAttributes> {
+    let mut attributes = RoleAttributes::default();
+    for option in options {
+        match option {
+            RoleOption::SuperUser(value) => attributes.superuser = Some(*value),
+            RoleOption::Login(value) => attributes.login = Some(*value),
+            RoleOption::CreateDB(value) => attributes.create_db = Some(*value),
+            RoleOption::CreateRole(value) => attributes.create_role = Some(*value),
+            RoleOption::Replication(value) => attributes.replication = Some(*value),
+            RoleOption::Password(password) => attributes.password = Some(role_password(password)?),
+            RoleOption::ConnectionLimit(limit) => {
+                attributes.connection_limit = Some(role_connection_limit(limit)?)
+            }
+            // roles always inherit the privileges of the roles they are
+            // members of
+            RoleOption::Inherit(true) | RoleOption::BypassRLS(false) => {}
+            RoleOption::Inherit(false) => return Err(unsupported("NOINHERIT")),
+            RoleOption::BypassRLS(true) => return Err(unsupported("BYPASSRLS")),
+            RoleOption::ValidUntil(_) => return Err(unsupported("VALID UNTIL")),
+        }
+    }
+    Ok(attributes)
+}
+
+fn role_password(password: &Password) -> PgWireResult<String> {
+    match password {
+        Password::Password(Expr::Value(value)) => match &value.value {
+            Value::SingleQuotedString(password) | Value::EscapedStringLiteral(password) => {
+                Ok(password.clone())
+            }
+            value => Err(invalid_option("password", value)),
+        },
+        Password::Password(expr) => Err(invalid_option("password", expr)),
+        // the `AuthManager` lets roles without password login with any
+        Password::NullPassword => Err(unsupported("PASSWORD NULL")),
+    }
+}
+
+fn role_connection_limit(limit: &Expr) -> PgWireResult<i32> {
+    // the parser only takes unsigned numbers, so there is no way to set
+    // the -1 of no limit again over SQL
+    let value = match limit {
+        Expr::Value(value) => match &value.value {
+            Value::Number(number, _) => number.parse::<i32>().ok(),
+            _ => None,
+        },
+        _ => None,
+    };
+    value.ok_or_else(|| invalid_option("CONNECTION LIMIT", limit))
+}
+
+fn unsupported(feature: &str) -> PgWireError {
+    PgWireError::UserError(Box::new(ErrorInfo::new(
+        "ERROR".to_string(),
+        "0A000".to_string(), // feature_not_supported
+        format!("{feature} is not supported for roles"),
+    )))
+}
+
+fn invalid_option(option: &str, value: impl std::fmt::Display) -> PgWireError {
+    PgWireError::UserError(Box::new(ErrorInfo::new(
+        "ERROR".to_string(),
+        "22023".to_string(), // invalid_parameter_value
+        format!("invalid value for {option}: {value}"),
+    )))
+}
+
+/// A role of a credentials file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Credentials {
+    pub name: String,
+    pub password: String,
+    pub attributes: RoleAttributes,
+}
+
+/// Parse the roles of a credentials file
+pub fn parse_credentials(text: &str) -> Result<Vec<Credentials>, String> {
+    let mut credentials = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let error = |message: &str| format!("line {}: {message}", number + 1);
+        // passwords may contain colons, names and attributes don't
+        let (Some((name, rest)), Some((_, attributes))) =
+            (line.split_once(':'), line.rsplit_once(':'))
+        else {
+            return Err(error("expected name:password:attributes"));
+        };
+        let Some((password, _)) = rest.rsplit_once(':') else {
+            return Err(error("expected name:password:attributes"));
+        };
+        if name.is_empty() {
+            return Err(error("role name is empty"));
+        }
+
+        let mut role = RoleAttributes {
+            superuser: Some(false),
+            login: Some(false),
+            create_db: Some(false),
+            create_role: Some(false),
+            replication: Some(false),
+            password: None,
+            connection_limit: Some(-1),
+        };
+        let mut words = attributes.split_whitespace();
+        while let Some(word) = words.next() {
+            match word.to_uppercase().as_str() {
+                "SUPERUSER" => role.superuser = Some(true),
+                "LOGIN" => role.login = Some(true),
+                "CREATEDB" => role.create_db = Some(true),
+                "CREATEROLE" => role.create_role = Some(true),
+                "REPLICATION" => role.replication = Some(true),
+                "CONNECTION" => {
+                    let limit = words
+                        .next()
+                        .filter(|word| word.eq_ignore_ascii_case("limit"))
+                        .and_then(|_| words.next())
+                        .and_then(|limit| limit.parse::<i32>().ok());
+                    match limit {
+                        Some(limit) => role.connection_limit = Some(limit),
+                        None => return Err(error("expected CONNECTION LIMIT <number>")),
+                    }
+                }
+                _ => return Err(error(&format!("unknown role attribute \"{word}\""))),
+            }
+        }
+        credentials.push(Credentials {
+            name: name.to_string(),
+            password: password.to_string(),
+            attributes: role,
+        });
+    }
+    Ok(credentials)
+}
+
+/// Reject role names which can't be written to a credentials file, with
+/// its separators or line breaks
+pub fn check_role_name(name: &str) -> PgWireResult<()> {
+    if name.contains([':', '\n', '\r']) {
+        return Err(PgWireError::UserError(Box::new(ErrorInfo::new(
+            "ERROR".to_string(),
+            "42602".to_string(), // invalid_name
+            format!(
+                "role name \"{}\" contains a colon or line break",
+                name.escape_debug()
+            ),
+        ))));
+    }
+    Ok(())
+}
+
+/// Write roles in the format of a credentials file
+pub fn format_credentials(credentials: &[Credentials]) -> String {
+    let mut text = String::from("# name:password:attributes\n");
+    for role in credentials {
+        let attributes = &role.attributes;
+        let mut words = [
+            (attributes.superuser, "SUPERUSER"),
+            (attributes.login, "LOGIN"),
+            (attributes.create_db, "CREATEDB"),
+            (attributes.create_role, "CREATEROLE"),
+            (attributes.replication, "REPLICATION"),
+        ]
+        .into_iter()
+        .filter(|(value, _)| *value == Some(true))
+        .map(|(_, word)| word.to_string())
+        .collect::<Vec<_>>();
+        if let Some(limit) = attributes.connection_limit.filter(|limit| *limit >= 0) {
+            words.push(format!("CONNECTION LIMIT {limit}"));
+        }
+        text.push_str(&format!(
+            "{}:{}:{}\n",
+            role.name,
+            role.password,
+            words.join(" ")
+        ));
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use datafusion::sql::sqlparser::dialect::PostgreSqlDialect;
+    use datafusion::sql::sqlparser::parser::Parser;
+
+    use super::*;
+
+    fn role_statement(sql: &str) -> Option<PgWireResult<RoleStatement>> {
+        let statement = Parser::parse_sql(&PostgreSqlDialect {}, sql)
+            .unwrap()
+            .remove(0);
+        RoleStatement::from_statement(&statement)
+    }
+
+    fn code(result: Option<PgWireResult<RoleStatement>>) -> String {
+        match result {
+            Some(Err(PgWireError::UserError(info))) => info.code,
+            result => panic!("expected an error, got {result:?}"),
+        }
+    }
+
+    #[test]
+    fn test_role_statements() {
+        assert_eq!(
+            role_statement(
+                "CREATE ROLE Analyst WITH LOGIN PASSWORD 's3cret' CONNECTION LIMIT 5 IN ROLE readers"
+            )
+            .unwrap()
+            .unwrap(),
+            RoleStatement::Create {
+                names: vec!["analyst".to_string()],
+                if_not_exists: false,
+                attributes: RoleAttributes {
+                    login: Some(true),
+                    password: Some("s3cret".to_string()),
+                    connection_limit: Some(5),
+                    ..RoleAttributes::default()
+                },
+                member_of: vec!["readers".to_string()],
+            }
+        );
+        let alter =
+            role_statement("ALTER ROLE \"Analyst\" NOSUPERUSER CREATEDB CONNECTION LIMIT 3")
+                .unwrap()
+                .unwrap();
+        assert_eq!(
+            alter,
+            RoleStatement::Alter {
+                name: "Analyst".to_string(),
+                attributes: RoleAttributes {
+                    superuser: Some(false),
+                    create_db: Some(true),
+                    connection_limit: Some(3),
+                    ..RoleAttributes::default()
+                },
+            }
+        );
+        assert_eq!(alter.tag(), "ALTER ROLE");
+        let RoleStatement::Alter { attributes, .. } =
+            role_statement("ALTER ROLE analyst PASSWORD 'new'")
+                .unwrap()
+                .unwrap()
+        else {
+            panic!("expected ALTER ROLE");
+        };
+        assert!(attributes.is_password_only());
+        assert_eq!(
+            role_statement("DROP ROLE IF EXISTS a, b").unwrap().unwrap(),
+            RoleStatement::Drop {
+                names: vec!["a".to_string(), "b".to_string()],
+                if_exists: true,
+            }
+        );
+
+        assert!(role_statement("ALTER ROLE analyst SET search_path = public").is_none());
+        assert!(role_statement("DROP TABLE analyst").is_none());
+        assert_eq!(code(role_statement("ALTER ROLE a RENAME TO b")), "0A000");
+        assert_eq!(code(role_statement("CREATE ROLE a PASSWORD NULL")), "0A000");
+        assert_eq!(
+            code(role_statement("CREATE ROLE a VALID UNTIL '2030-01-01'")),
+            "0A000"
+        );
+        assert_eq!(
+            code(role_statement("CREATE ROLE a CONNECTION LIMIT 99999999999")),
+            "22023"
+        );
+    }
+
+    #[test]
+    fn test_credentials_file() {
+        let text = "# roles\n\
+                    postgres::SUPERUSER LOGIN\n\
+                    \n\
+                    analyst:s3:cret:login createdb CONNECTION LIMIT 5\n\
+                    readers::\n";
+        let credentials = parse_credentials(text).unwrap();
+        assert_eq!(credentials.len(), 3);
+        assert_eq!(credentials[1].name, "analyst");
+        assert_eq!(credentials[1].password, "s3:cret");
+        assert_eq!(credentials[1].attributes.login, Some(true));
+        assert_eq!(credentials[1].attributes.create_db, Some(true));
+        assert_eq!(credentials[1].attributes.superuser, Some(false));
+        assert_eq!(credentials[1].attributes.connection_limit, Some(5));
+        assert_eq!(credentials[2].attributes.login, Some(false));
+
+        // written roles read back the same
+        assert_eq!(
+            parse_credentials(&format_credentials(&credentials)).unwrap(),
+            credentials
+        );
+
+        assert!(parse_credentials("analyst").is_err());
+        assert!(parse_credentials(":password:LOGIN").is_err());
+        assert_eq!(
+            parse_credentials("a::LOGIN\nb::NOLOGIN").unwrap_err(),
+            "line 2: unknown role attribute \"NOLOGIN\""
+        );
+        assert!(parse_credentials("a::CONNECTION LIMIT x").is_err());
+    }
+
+    #[test]
+    fn test_check_role_name() {
+        assert!(check_role_name("analyst").is_ok());
+        for name in ["a:b", "a\nb", "a\rb"] {
+            let Err(PgWireError::UserError(info)) = check_role_name(name) else {
+                panic!("expected an error for {name:?}");
+            };
+            assert_eq!(info.code, "42602");
+        }
+    }
+}
@@ -0,0 +1,261 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use futures::{FutureExt, StreamExt};
+use pgwire::api::results::QueryResponse;
+use pgwire::error::{ErrorInfo, PgWireError, PgWireResult};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::limits::QueryLimits;
+
+/// Admission of statements into execution
+///
+/// Statements of a user wait while the user already runs
+/// `QueryLimits::max_concurrent_statements` statements. With execution slots
+/// configured, every statement also takes `QueryLimits::scheduling_weight`
+/// slots out of a server-wide pool for as long as it runs. Waiting
+/// statements are admitted in arrival order, so a role running heavy batch
+/// queries with a high weight can't starve interactive sessions.
+///
+/// The wait queue can be bounded, statements arriving while it is full are
+/// refused with SQLSTATE 53300, and so can the wait, statements waiting
+/// longer are canceled with SQLSTATE 57014, so bursts of statements fail
+/// fast instead of piling up.
+#[derive(Debug, Default)]
+pub struct StatementScheduler {
+    slots: Option<(Arc<Semaphore>, u32)>,
+    // concurrency limit and running statements of each user
+    users: Mutex<HashMap<String, (usize, Arc<Semaphore>)>>,
+    max_waiting: Option<usize>,
+    waiting: AtomicUsize,
+    wait_timeout: Option<Duration>,
+}
+
+/// Held by a statement until its response is sent
+#[derive(Debug)]
+pub(crate) struct StatementPermit {
+    _user: Option<OwnedSemaphorePermit>,
+    _slots: Option<OwnedSemaphorePermit>,
+}
+
+impl StatementScheduler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Limit the execution slots statements of all users share
+    pub fn with_execution_slots(mut self, slots: u32) -> Self {
+        let slots = slots.max(1);
+        self.slots = Some((Arc::new(Semaphore::new(slots as usize)), slots));
+        self
+    }
+
+    /// Refuse statements arriving while `max_waiting` statements wait
+    pub fn with_max_waiting(mut self, max_waiting: usize) -> Self {
+        self.max_waiting = Some(max_waiting);
+        self
+    }
+
+    /// Cancel statements waiting longer than `timeout`
+    pub fn with_wait_timeout(mut self, timeout: Duration) -> Self {
+        self.wait_timeout = Some(timeout);
+        self
+    }
+
+    /// Wait until the statement of the user may run, failing when the wait
+    /// queue is full or the wait times out
+    pub(crate) async fn acquire(
+        &self,
+        username: &str,
+        limits: &QueryLimits,
+    ) -> PgWireResult<StatementPermit> {
+        let mut admission = Box::pin(self.admit(username, limits));
+        if let Some(permit) = (&mut admission).now_or_never() {
+            return Ok(permit);
+        }
+
+        let _waiting = self.enter_queue()?;
+        match self.wait_timeout {
+            Some(timeout) => tokio::time::timeout(timeout, admission).await.map_err(|_| {
+                PgWireError::UserError(Box::new(ErrorInfo::new(
+                    "ERROR".to_string(),
+                    "57014".to_string(), // query_canceled
+                    format!(
+                        "canceling statement due to admission timeout after {}ms",
+                        timeout.as_millis()
+                    ),
+                )))
+            }),
+            None => Ok(admission.await),
+        }
+    }
+
+    /// Count a statement in the wait queue until the returned guard drops
+    fn enter_queue(&self) -> PgWireResult<WaitingStatement<'_>> {
+        let waiting = self.waiting.fetch_add(1, Ordering::SeqCst);
+        let guard = WaitingStatement(&self.waiting);
+        match self.max_waiting {
+            Some(max_waiting) if waiting >= max_waiting => {
+                Err(PgWireError::UserError(Box::new(ErrorInfo::new(
+                    "ERROR".to_string(),
+                    "53300".to_string(), // too_many_connections
+                    format!(
+                        "too many statements waiting for execution, at most {max_waiting} may wait"
+                    ),
+                ))))
+            }
+            _ => Ok(guard),
+        }
+    }
+
+    async fn admit(&self, username: &str, limits: &QueryLimits) -> StatementPermit {
+        let user = match limits.max_concurrent_statements {
+            Some(limit) => {
+                let semaphore = self.user_semaphore(username, limit.max(1));
+                semaphore.acquire_owned().await.ok()
+            }
+            None => None,
+        };
+
+        let slots = match &self.slots {
+            Some((semaphore, total)) => {
+                // a statement can't take more than the whole pool
+                let weight = limits.scheduling_weight.unwrap_or(1).clamp(1, *total);
+                semaphore.clone().acquire_many_owned(weight).await.ok()
+            }
+            None => None,
+        };
+
+        StatementPermit {
+            _user: user,
+            _slots: slots,
+        }
+    }
+
+    fn user_semaphore(&self, username: &str, limit: usize) -> Arc<Semaphore> {
+        let mut users = self.users.lock().unwrap_or_else(|e| e.into_inner());
+        // drop users without running statements
+        users.retain(|_, (limit, semaphore)| semaphore.available_permits() < *limit);
+        match users.get(username) {
+            Some((current_limit, semaphore)) if *current_limit == limit => semaphore.clone(),
+            // new user or changed limit, statements running under the old
+            // limit are no longer counted
+            _ => {
+                let semaphore = Arc::new(Semaphore::new(limit));
+                users.insert(username.to_string(), (limit, semaphore.clone()));
+                semaphore
+            }
+        }
+    }
+}
+
+/// A statement in the wait queue
+struct WaitingStatement<'a>(&'a AtomicUsize);
+
+impl Drop for WaitingStatement<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl StatementPermit {
+    /// Keep the permit until all rows of the response are sent
+    pub(crate) fn hold_for<'a>(self, resp: QueryResponse<'a>) -> QueryResponse<'a> {
+        let fields = resp.row_schema();
+        let data_rows = resp.data_rows().map(move |row| {
+            let _permit = &self;
+            row
+        });
+        QueryResponse::new(fields, data_rows)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    async fn admitted(
+        scheduler: &StatementScheduler,
+        username: &str,
+        limits: &QueryLimits,
+    ) -> Option<StatementPermit> {
+        tokio::time::timeout(
+            Duration::from_millis(50),
+            scheduler.acquire(username, limits),
+        )
+        .await
+        .ok()
+        .map(Result::unwrap)
+    }
+
+    #[tokio::test]
+    async fn test_user_concurrency_limit() {
+        let scheduler = StatementScheduler::new();
+        let limits = QueryLimits::new().with_max_concurrent_statements(2);
+
+        let first = admitted(&scheduler, "alice", &limits).await.unwrap();
+        let _second = admitted(&scheduler, "alice", &limits).await.unwrap();
+        assert!(admitted(&scheduler, "alice", &limits).await.is_none());
+        // other users have their own limit
+        assert!(admitted(&scheduler, "bob", &limits).await.is_some());
+
+        drop(first);
+        assert!(admitted(&scheduler, "alice", &limits).await.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_weighted_slots() {
+        let scheduler = StatementScheduler::new().with_execution_slots(4);
+        let batch = QueryLimits::new().with_scheduling_weight(3);
+        let interactive = QueryLimits::new();
+
+        let heavy = admitted(&scheduler, "etl", &batch).await.unwrap();
+        let _light = admitted(&scheduler, "alice", &interactive).await.unwrap();
+        assert!(admitted(&scheduler, "bob", &interactive).await.is_none());
+        assert!(admitted(&scheduler, "etl", &batch).await.is_none());
+
+        drop(heavy);
+        // weights above the pool size take the whole pool
+        let limits = QueryLimits::new().with_scheduling_weight(100);
+        assert!(admitted(&scheduler, "etl", &limits).await.is_none());
+        assert!(admitted(&scheduler, "bob", &interactive).await.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_bounded_wait_queue() {
+        let scheduler = Arc::new(
+            StatementScheduler::new()
+                .with_execution_slots(1)
+                .with_max_waiting(1)
+                .with_wait_timeout(Duration::from_millis(100)),
+        );
+        let limits = QueryLimits::new();
+        let running = scheduler.acquire("alice", &limits).await.unwrap();
+
+        let waiting = tokio::spawn({
+            let scheduler = scheduler.clone();
+            async move { scheduler.acquire("bob", &limits).await.map(drop) }
+        });
+        while scheduler.waiting.load(Ordering::SeqCst) == 0 {
+            tokio::task::yield_now().await;
+        }
+        // the queue is full
+        let Err(PgWireError::UserError(info)) = scheduler.acquire("carol", &limits).await else {
+            panic!("expected a user error");
+        };
+        assert_eq!(info.code, "53300");
+
+        // the waiting statement times out
+        let Err(PgWireError::UserError(info)) = waiting.await.unwrap() else {
+            panic!("expected a user error");
+        };
+        assert_eq!(info.code, "57014");
+        assert_eq!(scheduler.waiting.load(Ordering::SeqCst), 0);
+
+        drop(running);
+        assert!(scheduler.acquire("carol", &limits).await.is_ok());
+    }
+}
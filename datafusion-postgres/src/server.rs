@@ -0,0 +1,1141 @@
+//! Postgres server embeddable in larger services.
+//!
+//! [`DfPostgresServer::builder`] configures a server, whose handle serves
+//! connections until [`DfPostgresServer::shutdown`] is called:
+//!
+//! ```no_run
+//! # use std::sync::Arc;
+//! # use datafusion::prelude::SessionContext;
+//! # use datafusion_postgres::server::DfPostgresServer;
+//! # async fn run() -> std::io::Result<()> {
+//! let server = Arc::new(
+//!     DfPostgresServer::builder()
+//!         .session_context(Arc::new(SessionContext::new()))
+//!         .listen("0.0.0.0:5432")
+//!         .build()?,
+//! );
+//! let serving = tokio::spawn({
+//!     let server = server.clone();
+//!     async move { server.serve().await }
+//! });
+//! // ...
+//! server.shutdown();
+//! serving.await??;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::io::{Error as IOError, ErrorKind};
+use std::pin::pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use datafusion::catalog::{
+    CatalogProvider, MemoryCatalogProviderList, SchemaProvider, TableProvider,
+};
+use datafusion::common::{not_impl_err, plan_err, TableReference};
+use datafusion::error::Result;
+use datafusion::prelude::SessionContext;
+use futures::future::{select, Either};
+use log::info;
+use tokio::sync::watch;
+use tokio_rustls::rustls::ServerConfig;
+use tokio_rustls::TlsAcceptor;
+use tokio_util::sync::CancellationToken;
+
+use crate::activity::ActivityRegistry;
+use crate::auth::{AuthManager, AuthSource, Authorizer};
+use crate::cert_auth::CertAuthConfig;
+use crate::events::ConnectionListener;
+use crate::hooks::QueryHook;
+#[cfg(feature = "jwt")]
+use crate::jwt::JwtAuthenticator;
+use crate::plan_cache::PlanCache;
+use crate::replication::ReplicationHandler;
+use crate::result_cache::ResultCache;
+use crate::scheduler::StatementScheduler;
+use crate::session::SessionContextFactory;
+use crate::tenant::TenantResolver;
+use crate::wire_debug::WireDebug;
+use crate::{serve_connections, tls_acceptor, Listener, ServerOptions};
+use crate::{DfSessionService, HandlerFactory};
+use arrow_pg::datatypes::df::EncodeOptions;
+
+/// Builder of a [`DfPostgresServer`]
+#[derive(Default)]
+pub struct DfPostgresServerBuilder {
+    session_context: Option<Arc<SessionContext>>,
+    listen: Option<String>,
+    tls: Option<Arc<ServerConfig>>,
+    /// PEM of the certificates of `tls`, for SCRAM channel binding
+    tls_certificates: Option<Vec<u8>>,
+    auth_source: Option<Arc<dyn AuthSource>>,
+    scram: bool,
+    cert_auth: Option<CertAuthConfig>,
+    auth_manager: Option<Arc<AuthManager>>,
+    authorizer: Option<Arc<dyn Authorizer>>,
+    query_hooks: Vec<Arc<dyn QueryHook>>,
+    connection_listeners: Vec<Arc<dyn ConnectionListener>>,
+    replication_handler: Option<Arc<dyn ReplicationHandler>>,
+    session_context_factory: Option<Arc<dyn SessionContextFactory>>,
+    tenant_resolver: Option<Arc<dyn TenantResolver>>,
+    options: ServerOptions,
+}
+
+impl DfPostgresServerBuilder {
+    /// The `SessionContext` serving the queries, required unless a session
+    /// context factory is set
+    pub fn session_context(mut self, session_context: Arc<SessionContext>) -> Self {
+        self.session_context = Some(session_context);
+        self
+    }
+
+    /// Address to listen to, e.g. `0.0.0.0:5432`, overriding the host and
+    /// port of the options
+    pub fn listen(mut self, addr: impl Into<String>) -> Self {
+        self.listen = Some(addr.into());
+        self
+    }
+
+    /// Encrypt connections with TLS, overriding the certificate and key
+    /// paths of the options
+    pub fn tls(mut self, config: Arc<ServerConfig>) -> Self {
+        self.tls = Some(config);
+        self
+    }
+
+    /// PEM of the server certificates of the TLS config, which SCRAM
+    /// authentication needs to offer channel binding with
+    /// SCRAM-SHA-256-PLUS. Read from the certificate path of the options
+    /// when not set.
+    ///
+    /// Without a TLS config, channel binding is only offered when the
+    /// options serve no SNI certificates and don't reload certificates, as
+    /// clients could be served another certificate than this one.
+    pub fn tls_certificates(mut self, certs_pem: Vec<u8>) -> Self {
+        self.tls_certificates = Some(certs_pem);
+        self
+    }
+
+    /// Authenticate clients with cleartext passwords checked by
+    /// `auth_source`, taking precedence over JSON Web Tokens set in the
+    /// options
+    pub fn auth(mut self, auth_source: Arc<dyn AuthSource>) -> Self {
+        self.auth_source = Some(auth_source);
+        self.scram = false;
+        self.cert_auth = None;
+        self
+    }
+
+    /// Authenticate clients with SCRAM-SHA-256 against the credentials of
+    /// `auth_source`, taking precedence over JSON Web Tokens set in the
+    /// options
+    pub fn scram_auth(mut self, auth_source: Arc<dyn AuthSource>) -> Self {
+        self.auth_source = Some(auth_source);
+        self.scram = true;
+        self.cert_auth = None;
+        self
+    }
+
+    /// Authenticate clients with their TLS client certificates, verified
+    /// against the client CA certificates of the options, as the users of
+    /// `auth_source` the certificates are issued to by `config`
+    pub fn cert_auth(mut self, auth_source: Arc<dyn AuthSource>, config: CertAuthConfig) -> Self {
+        self.auth_source = Some(auth_source);
+        self.scram = false;
+        self.cert_auth = Some(config);
+        self
+    }
+
+    /// Users, roles and policies of the server, a fresh `AuthManager` by
+    /// default
+    pub fn auth_manager(mut self, auth_manager: Arc<AuthManager>) -> Self {
+        self.auth_manager = Some(auth_manager);
+        self
+    }
+
+    /// Authorize statements with `authorizer` instead of the grants and
+    /// statement rules of the `AuthManager`
+    pub fn authorizer(mut self, authorizer: Arc<dyn Authorizer>) -> Self {
+        self.authorizer = Some(authorizer);
+        self
+    }
+
+    /// Call `hook` around every statement, after the hooks added before
+    pub fn query_hook(mut self, hook: Arc<dyn QueryHook>) -> Self {
+        self.query_hooks.push(hook);
+        self
+    }
+
+    /// Tell `listener` when connections open and close and when clients fail
+    /// to authenticate
+    pub fn connection_listener(mut self, listener: Arc<dyn ConnectionListener>) -> Self {
+        self.connection_listeners.push(listener);
+        self
+    }
+
+    /// Serve the connections of clients asking for replication with
+    /// `handler`, they are refused at startup otherwise
+    pub fn replication_handler(mut self, handler: Arc<dyn ReplicationHandler>) -> Self {
+        self.replication_handler = Some(handler);
+        self
+    }
+
+    /// Serve each connection with a `SessionContext` created by `factory`
+    /// from its user and database, the session context is then optional
+    pub fn session_context_factory(mut self, factory: Arc<dyn SessionContextFactory>) -> Self {
+        self.session_context_factory = Some(factory);
+        self
+    }
+
+    /// Restrict each connection to the catalogs, search path and limits of
+    /// the tenant `resolver` resolves from its user and database
+    pub fn tenant_resolver(mut self, resolver: Arc<dyn TenantResolver>) -> Self {
+        self.tenant_resolver = Some(resolver);
+        self
+    }
+
+    /// All other settings of the server
+    pub fn options(mut self, options: ServerOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    pub fn build(self) -> Result<DfPostgresServer, IOError> {
+        let session_context = match (self.session_context, &self.session_context_factory) {
+            (Some(session_context), _) => session_context,
+            (None, Some(_)) => Arc::new(SessionContext::new()),
+            (None, None) => {
+                return Err(IOError::new(
+                    ErrorKind::InvalidInput,
+                    "a SessionContext is required to build the server",
+                ))
+            }
+        };
+        let opts = self.options;
+        let auth_manager = match (self.auth_manager, &opts.credentials_file) {
+            (Some(auth_manager), _) => auth_manager,
+            (None, Some(path)) => Arc::new(AuthManager::new().with_credentials_file(path)?),
+            (None, None) => Arc::new(AuthManager::new()),
+        };
+
+        let activity = Arc::new(
+            ActivityRegistry::new().with_track_activity_query_size(opts.track_activity_query_size),
+        );
+
+        let mut session_service = DfSessionService::new(session_context, auth_manager)
+            .with_encode_options(EncodeOptions {
+                batch_size: opts.result_batch_size,
+                buffer_size: opts.result_buffer_size,
+                max_field_size: opts.max_field_size,
+                ..EncodeOptions::default()
+            })
+            .with_activity_registry(activity.clone());
+        if opts.plan_cache_size > 0 {
+            session_service = session_service.with_plan_cache(Arc::new(PlanCache::new(
+                opts.plan_cache_size,
+                opts.plan_cache_scope,
+            )));
+        }
+        if opts.result_cache_size > 0 {
+            session_service = session_service.with_result_cache(Arc::new(ResultCache::new(
+                opts.result_cache_size,
+                opts.result_cache_ttl,
+            )));
+        }
+        if opts.execution_slots.is_some()
+            || opts.max_waiting_statements.is_some()
+            || opts.statement_wait_timeout.is_some()
+        {
+            let mut scheduler = StatementScheduler::new();
+            if let Some(slots) = opts.execution_slots {
+                scheduler = scheduler.with_execution_slots(slots);
+            }
+            if let Some(max_waiting) = opts.max_waiting_statements {
+                scheduler = scheduler.with_max_waiting(max_waiting);
+            }
+            if let Some(timeout) = opts.statement_wait_timeout {
+                scheduler = scheduler.with_wait_timeout(timeout);
+            }
+            session_service = session_service.with_scheduler(Arc::new(scheduler));
+        }
+        if !opts.hba_rules.is_empty() {
+            session_service = session_service.with_hba_rules(opts.hba_rules.clone());
+        }
+        if let Some(root) = &opts.copy_export_root {
+            session_service = session_service.with_copy_export_root(root);
+        }
+        if let Some(root) = &opts.import_root {
+            session_service = session_service.with_import_root(root);
+        }
+        if let Some(limit) = opts.statement_rate_limit {
+            session_service = session_service.with_statement_rate_limit(limit);
+        }
+        if let Some(interval) = opts.progress_notice_interval {
+            session_service = session_service.with_progress_notices(interval);
+        }
+        if opts.wire_debug != WireDebug::Off {
+            info!("Wire debug mode {} enabled", opts.wire_debug.as_str());
+            session_service = session_service.with_wire_debug(opts.wire_debug);
+        }
+        if let Some(authorizer) = self.authorizer {
+            session_service = session_service.with_authorizer(authorizer);
+        }
+        if let Some(factory) = self.session_context_factory {
+            session_service = session_service.with_session_context_factory(factory);
+        }
+        if let Some(resolver) = self.tenant_resolver {
+            session_service = session_service.with_tenant_resolver(resolver);
+        }
+        for hook in self.query_hooks {
+            session_service = session_service.with_query_hook(hook);
+        }
+        for listener in self.connection_listeners {
+            session_service = session_service.with_connection_listener(listener);
+        }
+        if let Some(handler) = self.replication_handler {
+            session_service = session_service.with_replication_handler(handler);
+        }
+        if let Some(exporter) = &opts.span_exporter {
+            session_service = session_service.with_span_exporter(exporter.clone());
+        }
+        #[allow(unused_mut)]
+        let mut factory = HandlerFactory::new(session_service);
+        #[cfg(feature = "jwt")]
+        if let Some(config) = &opts.jwt {
+            info!(
+                "JWT authentication enabled using keys from {}",
+                config.jwks_url()
+            );
+            factory =
+                factory.with_jwt_authenticator(Arc::new(JwtAuthenticator::new(config.clone())));
+        }
+        if let Some(auth_source) = self.auth_source {
+            if let Some(config) = self.cert_auth {
+                info!("Certificate authentication enabled");
+                factory = factory.with_cert_auth(auth_source, config);
+            } else if self.scram {
+                let certs_pem =
+                    channel_binding_certificates(self.tls_certificates, self.tls.is_some(), &opts)?;
+                if let Some(certs_pem) = certs_pem {
+                    info!("SCRAM-SHA-256 authentication enabled with channel binding");
+                    factory = factory
+                        .with_scram_plus_auth_source(auth_source, &certs_pem)
+                        .map_err(|err| IOError::new(ErrorKind::InvalidInput, err))?;
+                } else {
+                    info!("SCRAM-SHA-256 authentication enabled");
+                    factory = factory.with_scram_auth_source(auth_source);
+                }
+            } else {
+                info!("Password authentication enabled");
+                factory = factory.with_auth_source(auth_source);
+            }
+        }
+
+        let tls_acceptor = match self.tls {
+            Some(config) => {
+                info!("TLS enabled using the given server config");
+                Some(TlsAcceptor::from(config))
+            }
+            None => tls_acceptor(&opts),
+        };
+        let (shutdown, _) = watch::channel(false);
+        Ok(DfPostgresServer {
+            handlers: Arc::new(factory),
+            listen: self
+                .listen
+                .unwrap_or_else(|| format!("{}:{}", opts.host, opts.port)),
+            tls_acceptor,
+            connections: Arc::new(AtomicUsize::new(0)),
+            shutdown,
+            options: opts,
+        })
+    }
+}
+
+/// Handle of a postgres server
+pub struct DfPostgresServer {
+    handlers: Arc<HandlerFactory>,
+    listen: String,
+    tls_acceptor: Option<TlsAcceptor>,
+    connections: Arc<AtomicUsize>,
+    shutdown: watch::Sender<bool>,
+    options: ServerOptions,
+}
+
+impl DfPostgresServer {
+    pub fn builder() -> DfPostgresServerBuilder {
+        DfPostgresServerBuilder::default()
+    }
+
+    /// Accept and serve connections until the server is shut down
+    pub async fn serve(&self) -> Result<(), IOError> {
+        let listener = Listener {
+            addr: self.listen.clone(),
+            tls_acceptor: self.tls_acceptor.clone(),
+            connections: self.connections.clone(),
+            shutdown: self.shutdown.subscribe(),
+        };
+        serve_connections(
+            self.handlers.clone(),
+            &self.options,
+            listener,
+            Some(self.handlers.session_service.clone()),
+        )
+        .await
+    }
+
+    /// Accept and serve connections until `cancellation` is cancelled or the
+    /// server is shut down
+    pub async fn serve_with_cancellation(
+        &self,
+        cancellation: CancellationToken,
+    ) -> Result<(), IOError> {
+        let serving = pin!(self.serve());
+        match select(serving, pin!(cancellation.cancelled())).await {
+            Either::Left((result, _)) => result,
+            Either::Right((_, serving)) => {
+                self.shutdown();
+                serving.await
+            }
+        }
+    }
+
+    /// Stop accepting connections and return from `serve`, open connections
+    /// are served until their clients disconnect
+    pub fn shutdown(&self) {
+        self.shutdown.send_replace(true);
+    }
+
+    /// Number of open connections
+    pub fn connection_count(&self) -> usize {
+        self.connections.load(Ordering::Relaxed)
+    }
+
+    /// The session service, e.g. to inspect the activity of sessions
+    pub fn session_service(&self) -> &Arc<DfSessionService> {
+        &self.handlers.session_service
+    }
+
+    /// Register `table` in the session context while serving connections,
+    /// notifying the sessions listening on
+    /// [`SCHEMA_CHANGED_CHANNEL`](crate::notify::SCHEMA_CHANGED_CHANNEL)
+    pub fn register_table(
+        &self,
+        name: impl Into<TableReference>,
+        table: Arc<dyn TableProvider>,
+    ) -> Result<Option<Arc<dyn TableProvider>>> {
+        let name = name.into();
+        let previous = self.session_context().register_table(name.clone(), table)?;
+        self.catalog_changed(&format!("CREATE TABLE {name}"));
+        Ok(previous)
+    }
+
+    pub fn deregister_table(
+        &self,
+        name: impl Into<TableReference>,
+    ) -> Result<Option<Arc<dyn TableProvider>>> {
+        let name = name.into();
+        let table = self.session_context().deregister_table(name.clone())?;
+        if table.is_some() {
+            self.catalog_changed(&format!("DROP TABLE {name}"));
+        }
+        Ok(table)
+    }
+
+    pub fn register_schema(
+        &self,
+        catalog: &str,
+        name: &str,
+        schema: Arc<dyn SchemaProvider>,
+    ) -> Result<Option<Arc<dyn SchemaProvider>>> {
+        let previous = self.catalog(catalog)?.register_schema(name, schema)?;
+        self.catalog_changed(&format!("CREATE SCHEMA {catalog}.{name}"));
+        Ok(previous)
+    }
+
+    /// Deregister a schema, failing if it has tables unless `cascade`
+    pub fn deregister_schema(
+        &self,
+        catalog: &str,
+        name: &str,
+        cascade: bool,
+    ) -> Result<Option<Arc<dyn SchemaProvider>>> {
+        let schema = self.catalog(catalog)?.deregister_schema(name, cascade)?;
+        if schema.is_some() {
+            self.catalog_changed(&format!("DROP SCHEMA {catalog}.{name}"));
+        }
+        Ok(schema)
+    }
+
+    pub fn register_catalog(
+        &self,
+        name: &str,
+        catalog: Arc<dyn CatalogProvider>,
+    ) -> Option<Arc<dyn CatalogProvider>> {
+        let previous = self.session_context().register_catalog(name, catalog);
+        self.catalog_changed(&format!("CREATE DATABASE {name}"));
+        previous
+    }
+
+    /// Deregister a catalog, supported by the default catalog list of
+    /// DataFusion only
+    pub fn deregister_catalog(&self, name: &str) -> Result<Option<Arc<dyn CatalogProvider>>> {
+        let state = self.session_context().state();
+        let Some(catalogs) = state
+            .catalog_list()
+            .as_any()
+            .downcast_ref::<MemoryCatalogProviderList>()
+        else {
+            return not_impl_err!("Deregistering catalogs of a custom catalog list");
+        };
+        let catalog = catalogs.catalogs.remove(name).map(|(_, catalog)| catalog);
+        if catalog.is_some() {
+            self.catalog_changed(&format!("DROP DATABASE {name}"));
+        }
+        Ok(catalog)
+    }
+
+    fn session_context(&self) -> &Arc<SessionContext> {
+        self.handlers.session_service.default_session_context()
+    }
+
+    fn catalog(&self, name: &str) -> Result<Arc<dyn CatalogProvider>> {
+        match self.session_context().catalog(name) {
+            Some(catalog) => Ok(catalog),
+            None => plan_err!("Catalog {name} not found"),
+        }
+    }
+
+    /// Refresh the cached metadata and notify listening sessions of `change`
+    fn catalog_changed(&self, change: &str) {
+        let service = &self.handlers.session_service;
+        service.catalog_changed(service.default_session_context(), change);
+    }
+}
+
+/// PEM of the certificates SCRAM-SHA-256-PLUS binds connections to, `None`
+/// when connections may be served other certificates, by SNI hostname or
+/// after reloading the certificate files of the options
+fn channel_binding_certificates(
+    tls_certificates: Option<Vec<u8>>,
+    tls_config: bool,
+    opts: &ServerOptions,
+) -> Result<Option<Vec<u8>>, IOError> {
+    if tls_config {
+        return Ok(tls_certificates);
+    }
+    if !opts.tls_sni_certificates.is_empty() || opts.tls_reload {
+        return Ok(None);
+    }
+    match (tls_certificates, &opts.tls_cert_path, &opts.tls_key_path) {
+        (Some(certs_pem), _, _) => Ok(Some(certs_pem)),
+        (None, Some(cert_path), Some(_)) => Ok(Some(std::fs::read(cert_path)?)),
+        _ => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::net::TcpStream;
+
+    use super::*;
+    use crate::tls::SniCertificate;
+
+    #[test]
+    fn test_build_requires_session_context() {
+        assert!(DfPostgresServer::builder().build().is_err());
+    }
+
+    /// Options serving the certificate `server.crt` of a temporary directory
+    fn tls_options(name: &str) -> (ServerOptions, std::path::PathBuf) {
+        let dir =
+            std::env::temp_dir().join(format!("datafusion-postgres-{name}-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let cert_path = dir.join("server.crt");
+        std::fs::write(&cert_path, "certificate").unwrap();
+        let opts = ServerOptions::new()
+            .with_tls_cert_path(Some(cert_path.to_string_lossy().into_owned()))
+            .with_tls_key_path(Some(dir.join("server.key").to_string_lossy().into_owned()))
+            .with_tls_reload(false);
+        (opts, dir)
+    }
+
+    #[test]
+    fn test_channel_binding_with_sni_certificates() {
+        let (opts, dir) = tls_options("sni-binding");
+        assert_eq!(
+            channel_binding_certificates(None, false, &opts).unwrap(),
+            Some(b"certificate".to_vec())
+        );
+
+        // clients of other hostnames are served other certificates
+        let opts = opts.with_tls_sni_certificates(vec![SniCertificate::new(
+            "tenant.example.com",
+            "tenant.crt",
+            "tenant.key",
+        )]);
+        assert_eq!(
+            channel_binding_certificates(None, false, &opts).unwrap(),
+            None
+        );
+        assert_eq!(
+            channel_binding_certificates(Some(b"given".to_vec()), false, &opts).unwrap(),
+            None
+        );
+        // unlike with a TLS config of the application
+        assert_eq!(
+            channel_binding_certificates(Some(b"given".to_vec()), true, &opts).unwrap(),
+            Some(b"given".to_vec())
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_channel_binding_with_reloaded_certificates() {
+        let (opts, dir) = tls_options("reload-binding");
+        assert_eq!(
+            channel_binding_certificates(None, false, &opts).unwrap(),
+            Some(b"certificate".to_vec())
+        );
+
+        // renewed certificates would no longer match the one read here
+        let opts = opts.with_tls_reload(true);
+        assert_eq!(
+            channel_binding_certificates(None, false, &opts).unwrap(),
+            None
+        );
+        assert_eq!(
+            channel_binding_certificates(Some(b"given".to_vec()), false, &opts).unwrap(),
+            None
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_register_while_serving() {
+        use datafusion::arrow::datatypes::Schema;
+        use datafusion::catalog::{MemoryCatalogProvider, MemorySchemaProvider};
+        use datafusion::datasource::empty::EmptyTable;
+
+        let server = DfPostgresServer::builder()
+            .session_context(Arc::new(SessionContext::new()))
+            .build()
+            .unwrap();
+        let context = server.session_service().default_session_context().clone();
+        let table = Arc::new(EmptyTable::new(Arc::new(Schema::empty())));
+
+        assert!(server
+            .register_catalog("app", Arc::new(MemoryCatalogProvider::new()))
+            .is_none());
+        server
+            .register_schema("app", "data", Arc::new(MemorySchemaProvider::new()))
+            .unwrap();
+        server.register_table("app.data.t", table).unwrap();
+        assert!(context.table_exist("app.data.t").unwrap());
+        assert!(server
+            .register_schema("missing", "data", Arc::new(MemorySchemaProvider::new()))
+            .is_err());
+
+        assert!(server.deregister_table("app.data.t").unwrap().is_some());
+        assert!(!context.table_exist("app.data.t").unwrap());
+        assert!(server
+            .deregister_schema("app", "data", false)
+            .unwrap()
+            .is_some());
+        assert!(server.deregister_catalog("app").unwrap().is_some());
+        assert!(context.catalog("app").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_serve_with_cancellation() {
+        use std::sync::atomic::AtomicI32;
+
+        use crate::events::ConnectionEvent;
+
+        // open connections, increased and decreased by the callbacks
+        #[derive(Default)]
+        struct Accounting(AtomicI32);
+
+        impl ConnectionListener for Accounting {
+            fn on_connect(&self, _event: &ConnectionEvent) {
+                self.0.fetch_add(1, Ordering::Relaxed);
+            }
+
+            fn on_disconnect(&self, _event: &ConnectionEvent) {
+                self.0.fetch_sub(1, Ordering::Relaxed);
+            }
+        }
+
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let addr = format!("127.0.0.1:{port}");
+        let accounting = Arc::new(Accounting::default());
+        let server = Arc::new(
+            DfPostgresServer::builder()
+                .session_context(Arc::new(SessionContext::new()))
+                .listen(addr.clone())
+                .connection_listener(accounting.clone())
+                .build()
+                .unwrap(),
+        );
+        let cancellation = CancellationToken::new();
+        let serving = tokio::spawn({
+            let server = server.clone();
+            let cancellation = cancellation.clone();
+            async move { server.serve_with_cancellation(cancellation).await }
+        });
+
+        let mut client = None;
+        for _ in 0..50 {
+            if let Ok(stream) = TcpStream::connect(&addr).await {
+                client = Some(stream);
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        let client = client.expect("server accepts connections");
+        for _ in 0..50 {
+            if accounting.0.load(Ordering::Relaxed) == 1 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(accounting.0.load(Ordering::Relaxed), 1);
+
+        drop(client);
+        cancellation.cancel();
+        serving.await.unwrap().unwrap();
+        for _ in 0..50 {
+            if accounting.0.load(Ordering::Relaxed) == 0 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(accounting.0.load(Ordering::Relaxed), 0);
+    }
+
+    #[tokio::test]
+    async fn test_serve_and_shutdown() {
+        // find a free port
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let addr = format!("127.0.0.1:{port}");
+        let server = Arc::new(
+            DfPostgresServer::builder()
+                .session_context(Arc::new(SessionContext::new()))
+                .listen(addr.clone())
+                .build()
+                .unwrap(),
+        );
+        let serving = tokio::spawn({
+            let server = server.clone();
+            async move { server.serve().await }
+        });
+
+        let mut client = None;
+        for _ in 0..50 {
+            if let Ok(stream) = TcpStream::connect(&addr).await {
+                client = Some(stream);
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        let client = client.expect("server accepts connections");
+        for _ in 0..50 {
+            if server.connection_count() == 1 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(server.connection_count(), 1);
+
+        drop(client);
+        server.shutdown();
+        serving.await.unwrap().unwrap();
+        for _ in 0..50 {
+            if server.connection_count() == 0 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(server.connection_count(), 0);
+    }
+
+    /// Startup message of the protocol `major.minor` with `parameters`
+    fn startup_message(major: u16, minor: u16, parameters: &[(&str, &str)]) -> Vec<u8> {
+        let mut body = [major.to_be_bytes(), minor.to_be_bytes()].concat();
+        for (name, value) in parameters {
+            body.extend_from_slice(format!("{name}\0{value}\0").as_bytes());
+        }
+        body.push(0);
+        [((body.len() + 4) as u32).to_be_bytes().to_vec(), body].concat()
+    }
+
+    /// A server listening on a free port, and a client connected to it
+    async fn serve_and_connect() -> (
+        Arc<DfPostgresServer>,
+        tokio::task::JoinHandle<Result<(), IOError>>,
+        String,
+        TcpStream,
+    ) {
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let addr = format!("127.0.0.1:{port}");
+        let server = Arc::new(
+            DfPostgresServer::builder()
+                .session_context(Arc::new(SessionContext::new()))
+                .listen(addr.clone())
+                .build()
+                .unwrap(),
+        );
+        let serving = tokio::spawn({
+            let server = server.clone();
+            async move { server.serve().await }
+        });
+
+        let mut client = None;
+        for _ in 0..50 {
+            if let Ok(stream) = TcpStream::connect(&addr).await {
+                client = Some(stream);
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        let client = client.expect("server accepts connections");
+        (server, serving, addr, client)
+    }
+
+    #[tokio::test]
+    async fn test_protocol_negotiation() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let (server, serving, addr, mut client) = serve_and_connect().await;
+
+        // GSS encryption is refused, and the client goes on unencrypted
+        let gss_enc_request = [0, 0, 0, 8, 0x04, 0xd2, 0x16, 0x30];
+        client.write_all(&gss_enc_request).await.unwrap();
+        assert_eq!(client.read_u8().await.unwrap(), b'N');
+
+        // a newer minor version with an extension is negotiated down to 3.2
+        let startup = startup_message(3, 5, &[("user", "postgres"), ("_pq_.frob", "on")]);
+        client.write_all(&startup).await.unwrap();
+        assert_eq!(client.read_u8().await.unwrap(), b'v');
+        let mut body = vec![0; client.read_u32().await.unwrap() as usize - 4];
+        client.read_exact(&mut body).await.unwrap();
+        assert_eq!(body, b"\0\0\0\x02\0\0\0\x01_pq_.frob\0");
+        assert_eq!(client.read_u8().await.unwrap(), b'R');
+        drop(client);
+
+        // another major version is refused with an error
+        let mut client = TcpStream::connect(&addr).await.unwrap();
+        client
+            .write_all(&startup_message(4, 0, &[("user", "postgres")]))
+            .await
+            .unwrap();
+        let mut response = Vec::new();
+        client.read_to_end(&mut response).await.unwrap();
+        assert_eq!(response[0], b'E');
+        let response = String::from_utf8_lossy(&response);
+        assert!(response.contains("0A000"));
+        assert!(response.contains("unsupported frontend protocol 4.0"));
+
+        server.shutdown();
+        serving.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_connections_refused() {
+        use crate::rate_limit::RateLimit;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        /// The FATAL error a server refusing a connection answers with
+        async fn refusal(addr: &str, first_bytes: &[u8]) -> String {
+            let mut client = TcpStream::connect(addr).await.unwrap();
+            client.write_all(first_bytes).await.unwrap();
+            let mut response = Vec::new();
+            client.read_to_end(&mut response).await.unwrap();
+            assert_eq!(response[0], b'E');
+            String::from_utf8_lossy(&response).into_owned()
+        }
+
+        let serve = |options: ServerOptions| {
+            let port = std::net::TcpListener::bind("127.0.0.1:0")
+                .unwrap()
+                .local_addr()
+                .unwrap()
+                .port();
+            let addr = format!("127.0.0.1:{port}");
+            let server = Arc::new(
+                DfPostgresServer::builder()
+                    .session_context(Arc::new(SessionContext::new()))
+                    .listen(addr.clone())
+                    .options(options)
+                    .build()
+                    .unwrap(),
+            );
+            let serving = tokio::spawn({
+                let server = server.clone();
+                async move { server.serve().await }
+            });
+            (server, serving, addr)
+        };
+        async fn connect(addr: &str) -> TcpStream {
+            for _ in 0..50 {
+                if let Ok(stream) = TcpStream::connect(addr).await {
+                    return stream;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+            panic!("server accepts connections");
+        }
+
+        // clients over the connection rate limit
+        let (server, serving, addr) = serve(
+            ServerOptions::new()
+                .with_connection_rate_limit(Some(RateLimit::new(0.001, 1).unwrap())),
+        );
+        let client = connect(&addr).await;
+        let response = refusal(&addr, &startup_message(3, 0, &[("user", "postgres")])).await;
+        assert!(response.contains("FATAL"));
+        assert!(response.contains("53300"));
+        assert!(response.contains("connection rate limit exceeded"));
+        drop(client);
+        server.shutdown();
+        serving.await.unwrap().unwrap();
+
+        // and clients without a PROXY protocol header
+        let (server, serving, addr) = serve(ServerOptions::new().with_proxy_protocol(true));
+        drop(connect(&addr).await);
+        let response = refusal(&addr, b"GET / HTTP/1.1\r\n\r\n").await;
+        assert!(response.contains("08P01"));
+        assert!(response.contains("invalid PROXY protocol header"));
+        server.shutdown();
+        serving.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_replication_refused() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let (server, serving, addr, mut client) = serve_and_connect().await;
+
+        // replication connections are refused before authentication
+        let startup = startup_message(3, 0, &[("user", "postgres"), ("replication", "database")]);
+        client.write_all(&startup).await.unwrap();
+        let mut response = Vec::new();
+        client.read_to_end(&mut response).await.unwrap();
+        assert_eq!(response[0], b'E');
+        let response = String::from_utf8_lossy(&response);
+        assert!(response.contains("FATAL"));
+        assert!(response.contains("0A000"));
+
+        // regular connections may say so
+        let mut client = TcpStream::connect(&addr).await.unwrap();
+        let startup = startup_message(3, 0, &[("user", "postgres"), ("replication", "false")]);
+        client.write_all(&startup).await.unwrap();
+        assert_eq!(read_messages_until(&mut client, b'Z').await[0], b'R');
+
+        drop(client);
+        server.shutdown();
+        serving.await.unwrap().unwrap();
+    }
+
+    /// Frontend message of type `tag` with `body`
+    fn frontend_message(tag: u8, body: &[u8]) -> Vec<u8> {
+        let length = (body.len() + 4) as u32;
+        [&[tag][..], &length.to_be_bytes(), body].concat()
+    }
+
+    /// Parse, Bind and Execute messages of the unnamed statement `query`
+    fn execute_messages(query: &str) -> Vec<u8> {
+        [
+            frontend_message(b'P', format!("\0{query}\0\0\0").as_bytes()),
+            frontend_message(b'B', &[0; 8]),
+            frontend_message(b'E', &[0; 5]),
+        ]
+        .concat()
+    }
+
+    /// Types of the backend messages read up to one of type `last`
+    async fn read_messages_until(client: &mut TcpStream, last: u8) -> Vec<u8> {
+        read_messages_and_body_until(client, last).await.0
+    }
+
+    /// Types of the backend messages read up to one of type `last`, with the
+    /// body of that one
+    async fn read_messages_and_body_until(client: &mut TcpStream, last: u8) -> (Vec<u8>, Vec<u8>) {
+        use tokio::io::AsyncReadExt;
+
+        let mut tags = Vec::new();
+        loop {
+            let tag = client.read_u8().await.unwrap();
+            let mut body = vec![0; client.read_u32().await.unwrap() as usize - 4];
+            client.read_exact(&mut body).await.unwrap();
+            tags.push(tag);
+            if tag == last {
+                return (tags, body);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pipelined_extended_queries() {
+        use tokio::io::AsyncWriteExt;
+
+        let (server, serving, _, mut client) = serve_and_connect().await;
+        let startup = startup_message(3, 0, &[("user", "postgres")]);
+        client.write_all(&startup).await.unwrap();
+        read_messages_until(&mut client, b'Z').await;
+
+        // the responses are buffered until Flush, which delivers them without
+        // ending the batch
+        client
+            .write_all(&execute_messages("SELECT 1"))
+            .await
+            .unwrap();
+        let mut buf = [0; 1];
+        let read = tokio::time::timeout(Duration::from_millis(100), client.peek(&mut buf));
+        assert!(read.await.is_err());
+        client
+            .write_all(&frontend_message(b'H', &[]))
+            .await
+            .unwrap();
+        let tags = read_messages_until(&mut client, b'C').await;
+        assert_eq!(tags, b"12DC");
+
+        // the messages after an error are discarded up to Sync
+        let batch = [
+            execute_messages("SELECT * FROM missing"),
+            execute_messages("SELECT 2"),
+            frontend_message(b'H', &[]),
+            frontend_message(b'S', &[]),
+        ]
+        .concat();
+        client.write_all(&batch).await.unwrap();
+        let tags = read_messages_until(&mut client, b'Z').await;
+        assert_eq!(tags, b"EZ");
+
+        // as are those after an error of executing a statement
+        let batch = [
+            execute_messages("SELECT 1 / 0"),
+            execute_messages("SELECT 2"),
+            frontend_message(b'S', &[]),
+        ]
+        .concat();
+        client.write_all(&batch).await.unwrap();
+        let tags = read_messages_until(&mut client, b'Z').await;
+        assert_eq!(tags, b"12EZ");
+
+        // and the next batch runs again
+        let batch = [execute_messages("SELECT 3"), frontend_message(b'S', &[])].concat();
+        client.write_all(&batch).await.unwrap();
+        let tags = read_messages_until(&mut client, b'Z').await;
+        assert_eq!(tags, b"12DCZ");
+
+        drop(client);
+        server.shutdown();
+        serving.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_discard_all() {
+        use tokio::io::AsyncWriteExt;
+
+        let (server, serving, _, mut client) = serve_and_connect().await;
+        let startup = startup_message(3, 0, &[("user", "postgres")]);
+        client.write_all(&startup).await.unwrap();
+        read_messages_until(&mut client, b'Z').await;
+
+        let parse = frontend_message(b'P', b"s1\0SELECT 1\0\0\0");
+        let bind_and_execute = [
+            frontend_message(b'B', b"\0s1\0\0\0\0\0\0\0"),
+            frontend_message(b'E', &[0; 5]),
+            frontend_message(b'S', &[]),
+        ]
+        .concat();
+        client
+            .write_all(&[parse, bind_and_execute.clone()].concat())
+            .await
+            .unwrap();
+        assert_eq!(read_messages_until(&mut client, b'Z').await, b"12DCZ");
+
+        client
+            .write_all(&frontend_message(b'Q', b"DISCARD ALL\0"))
+            .await
+            .unwrap();
+        assert_eq!(read_messages_until(&mut client, b'Z').await, b"CZ");
+
+        // the prepared statement is gone
+        client.write_all(&bind_and_execute).await.unwrap();
+        assert_eq!(read_messages_until(&mut client, b'Z').await, b"EZ");
+
+        drop(client);
+        server.shutdown();
+        serving.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_transaction_status() {
+        use tokio::io::AsyncWriteExt;
+
+        let (server, serving, _, mut client) = serve_and_connect().await;
+        let startup = startup_message(3, 0, &[("user", "postgres")]);
+        client.write_all(&startup).await.unwrap();
+        read_messages_until(&mut client, b'Z').await;
+
+        // the types of the messages answering the simple queries, with the
+        // transaction status of ReadyForQuery
+        let queries = [
+            ("BEGIN; SELECT 1; COMMIT;", &b"CTDCCZ"[..], b'I'),
+            ("BEGIN;", b"CZ", b'T'),
+            ("SELECT * FROM missing; SELECT 1", b"EZ", b'E'),
+            ("ROLLBACK;", b"CZ", b'I'),
+            (
+                "SET DateStyle = 'ISO'; SET extra_float_digits = 3",
+                b"SCCZ",
+                b'I',
+            ),
+        ];
+        for (query, tags, status) in queries {
+            let message = frontend_message(b'Q', format!("{query}\0").as_bytes());
+            client.write_all(&message).await.unwrap();
+            let (read, body) = read_messages_and_body_until(&mut client, b'Z').await;
+            assert_eq!((read.as_slice(), body[0]), (tags, status), "{query}");
+        }
+
+        // transaction statements with modes over the extended protocol
+        let queries = [
+            ("BEGIN ISOLATION LEVEL SERIALIZABLE, READ ONLY", b'T'),
+            ("SET TRANSACTION ISOLATION LEVEL REPEATABLE READ", b'T'),
+            ("COMMIT", b'I'),
+            ("START TRANSACTION READ WRITE", b'T'),
+            ("ROLLBACK", b'I'),
+        ];
+        for (query, status) in queries {
+            let messages = [execute_messages(query), frontend_message(b'S', &[])].concat();
+            client.write_all(&messages).await.unwrap();
+            let (read, body) = read_messages_and_body_until(&mut client, b'Z').await;
+            assert_eq!(
+                (read.as_slice(), body[0]),
+                (&b"12CZ"[..], status),
+                "{query}"
+            );
+        }
+
+        drop(client);
+        server.shutdown();
+        serving.await.unwrap().unwrap();
+    }
+}
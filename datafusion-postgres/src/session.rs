@@ -0,0 +1,252 @@
+//! Session contexts of connections.
+//!
+//! By default all connections share the `SessionContext` of the server. A
+//! [`SessionContextFactory`] instead creates a context for every connection
+//! from its user and database once it is authenticated, e.g. with the
+//! catalogs, UDFs and configuration of a tenant.
+//!
+//! Connections whose database names a catalog other than the default one,
+//! like those created with `CREATE DATABASE`, resolve unqualified names in
+//! that catalog.
+//!
+//! Connections with a `search_path`, e.g. a default of their role or
+//! database, resolve unqualified names in that schema.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, RwLock};
+
+use datafusion::prelude::SessionContext;
+use pgwire::api::{ClientInfo, METADATA_DATABASE, METADATA_USER};
+
+use crate::activity::ActivityRegistry;
+use crate::pg_catalog::setup_pg_stat_backend_functions;
+use crate::tenant::{Tenant, TenantResolver};
+
+/// Metadata key of the schema the connection resolves unqualified names in
+pub(crate) const METADATA_SEARCH_PATH: &str = "search_path";
+
+/// Metadata key of the catalogs the connection is confined to, a JSON array
+/// like the catalogs claim of its token
+pub(crate) const METADATA_SESSION_CATALOGS: &str = "session_catalogs";
+
+/// Authenticated connection a session context is created for
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectionInfo {
+    /// Address of the client, identifying its session
+    pub session: SocketAddr,
+    pub user: Option<String>,
+    pub database: Option<String>,
+    pub application_name: Option<String>,
+}
+
+impl ConnectionInfo {
+    pub(crate) fn new<C: ClientInfo>(client: &C) -> Self {
+        let metadata = client.metadata();
+        ConnectionInfo {
+            session: client.socket_addr(),
+            user: metadata.get(METADATA_USER).cloned(),
+            database: metadata.get(METADATA_DATABASE).cloned(),
+            application_name: metadata.get("application_name").cloned(),
+        }
+    }
+}
+
+/// The catalog of `context` named by the database of a connection, if it
+/// isn't the default catalog
+fn database_catalog<'a>(context: &SessionContext, database: Option<&'a String>) -> Option<&'a str> {
+    let database = database?;
+    let state = context.state_ref();
+    let state = state.read();
+    if state.config_options().catalog.default_catalog == *database {
+        return None;
+    }
+    state
+        .catalog_list()
+        .catalog(database)
+        .map(|_| database.as_str())
+}
+
+/// Creates the `SessionContext` of each connection
+///
+/// Implemented by closures taking a [`ConnectionInfo`]. The context is
+/// created when the connection runs its first statement and serves all its
+/// statements. Like the context of the server, it should be set up with
+/// `pg_catalog::setup_pg_catalog` for clients to list its tables, and
+/// `pg_catalog::setup_system_functions` chooses the system functions of the
+/// connection.
+pub trait SessionContextFactory: Send + Sync {
+    fn create(&self, connection: &ConnectionInfo) -> Arc<SessionContext>;
+}
+
+impl<F> SessionContextFactory for F
+where
+    F: Fn(&ConnectionInfo) -> Arc<SessionContext> + Send + Sync,
+{
+    fn create(&self, connection: &ConnectionInfo) -> Arc<SessionContext> {
+        self(connection)
+    }
+}
+
+/// The session contexts of a session service
+pub(crate) struct SessionContexts {
+    default: Arc<SessionContext>,
+    factory: Option<Arc<dyn SessionContextFactory>>,
+    tenants: Option<Arc<dyn TenantResolver>>,
+    activity: Arc<ActivityRegistry>,
+    connections: RwLock<HashMap<SocketAddr, ConnectionSession>>,
+}
+
+/// The context and tenant of a connection
+#[derive(Clone)]
+struct ConnectionSession {
+    context: Arc<SessionContext>,
+    tenant: Option<Arc<Tenant>>,
+    /// Catalogs the connection is confined to, `None` for all catalogs
+    catalogs: Option<Vec<String>>,
+}
+
+impl SessionContexts {
+    pub(crate) fn new(default: Arc<SessionContext>, activity: Arc<ActivityRegistry>) -> Self {
+        setup_pg_stat_backend_functions(&default, activity.clone());
+        SessionContexts {
+            default,
+            factory: None,
+            tenants: None,
+            activity,
+            connections: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// The same contexts, with a context per connection created by `factory`
+    pub(crate) fn with_factory(&self, factory: Arc<dyn SessionContextFactory>) -> Self {
+        SessionContexts {
+            factory: Some(factory),
+            tenants: self.tenants.clone(),
+            ..SessionContexts::new(self.default.clone(), self.activity.clone())
+        }
+    }
+
+    /// The same contexts, restricted to the tenant `tenants` resolves for
+    /// each connection
+    pub(crate) fn with_tenant_resolver(&self, tenants: Arc<dyn TenantResolver>) -> Self {
+        SessionContexts {
+            factory: self.factory.clone(),
+            tenants: Some(tenants),
+            ..SessionContexts::new(self.default.clone(), self.activity.clone())
+        }
+    }
+
+    /// The same contexts, recording activity in `activity`
+    pub(crate) fn with_activity_registry(&self, activity: Arc<ActivityRegistry>) -> Self {
+        SessionContexts {
+            factory: self.factory.clone(),
+            tenants: self.tenants.clone(),
+            ..SessionContexts::new(self.default.clone(), activity)
+        }
+    }
+
+    pub(crate) fn default_context(&self) -> &Arc<SessionContext> {
+        &self.default
+    }
+
+    /// The context of the connection of `client`, created by the factory on
+    /// first use
+    pub(crate) fn for_client<C: ClientInfo>(&self, client: &C) -> Arc<SessionContext> {
+        match self.connection(client) {
+            Some(connection) => connection.context,
+            None => self.default.clone(),
+        }
+    }
+
+    /// The tenant of the connection of `client`, resolved on first use
+    pub(crate) fn tenant<C: ClientInfo>(&self, client: &C) -> Option<Arc<Tenant>> {
+        self.connection(client)
+            .and_then(|connection| connection.tenant)
+    }
+
+    /// The catalogs the connection of `client` is confined to by its tenant
+    /// and its session, `None` for all catalogs
+    pub(crate) fn catalogs<C: ClientInfo>(&self, client: &C) -> Option<Vec<String>> {
+        self.connection(client)
+            .and_then(|connection| connection.catalogs)
+    }
+
+    /// The session of the connection of `client`, `None` when it shares the
+    /// default context
+    fn connection<C: ClientInfo>(&self, client: &C) -> Option<ConnectionSession> {
+        if self.factory.is_none()
+            && self.tenants.is_none()
+            && database_catalog(&self.default, client.metadata().get(METADATA_DATABASE)).is_none()
+            && !client.metadata().contains_key(METADATA_SEARCH_PATH)
+            && !client.metadata().contains_key(METADATA_SESSION_CATALOGS)
+        {
+            return None;
+        }
+        let addr = client.socket_addr();
+        if let Some(connection) = self
+            .connections
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(&addr)
+        {
+            return Some(connection.clone());
+        }
+
+        let info = ConnectionInfo::new(client);
+        let mut context = match &self.factory {
+            Some(factory) => factory.create(&info),
+            None => self.default.clone(),
+        };
+        if let Some(catalog) = database_catalog(&context, info.database.as_ref()) {
+            let database = Tenant::new().with_default_catalog(catalog);
+            context = Arc::new(database.session_context(&context));
+        }
+        let tenant = self.tenants.as_ref().map(|tenants| {
+            let tenant = tenants.resolve(&info);
+            context = Arc::new(tenant.session_context(&context));
+            Arc::new(tenant)
+        });
+        let mut catalogs = tenant.as_ref().and_then(|tenant| tenant.catalogs.clone());
+        if let Some(session_catalogs) = client.metadata().get(METADATA_SESSION_CATALOGS) {
+            let session_catalogs =
+                serde_json::from_str::<Vec<String>>(session_catalogs).unwrap_or_default();
+            let allowed = match catalogs {
+                Some(catalogs) => catalogs
+                    .into_iter()
+                    .filter(|catalog| session_catalogs.contains(catalog))
+                    .collect(),
+                None => session_catalogs,
+            };
+            let confined = Tenant::new().with_catalogs(allowed.clone());
+            context = Arc::new(confined.session_context(&context));
+            catalogs = Some(allowed);
+        }
+        if let Some(schema) = client.metadata().get(METADATA_SEARCH_PATH) {
+            let search_path = Tenant::new().with_search_path(schema.clone());
+            context = Arc::new(search_path.session_context(&context));
+        }
+        setup_pg_stat_backend_functions(&context, self.activity.clone());
+        let connection = ConnectionSession {
+            context,
+            tenant,
+            catalogs,
+        };
+        Some(
+            self.connections
+                .write()
+                .unwrap_or_else(|e| e.into_inner())
+                .entry(addr)
+                .or_insert(connection)
+                .clone(),
+        )
+    }
+
+    /// Drop the context of a closed connection
+    pub(crate) fn remove(&self, addr: &SocketAddr) {
+        self.connections
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(addr);
+    }
+}
@@ -2,10 +2,13 @@ use std::collections::HashSet;
 use std::ops::ControlFlow;
 use std::sync::Arc;
 
+use datafusion::sql::planner::IdentNormalizer;
+use datafusion::sql::sqlparser::ast::visit_expressions_mut;
 use datafusion::sql::sqlparser::ast::Array;
 use datafusion::sql::sqlparser::ast::ArrayElemTypeDef;
 use datafusion::sql::sqlparser::ast::BinaryOperator;
 use datafusion::sql::sqlparser::ast::CastKind;
+use datafusion::sql::sqlparser::ast::ColumnOption;
 use datafusion::sql::sqlparser::ast::DataType;
 use datafusion::sql::sqlparser::ast::Expr;
 use datafusion::sql::sqlparser::ast::Function;
@@ -13,7 +16,10 @@ use datafusion::sql::sqlparser::ast::FunctionArg;
 use datafusion::sql::sqlparser::ast::FunctionArgExpr;
 use datafusion::sql::sqlparser::ast::FunctionArgumentList;
 use datafusion::sql::sqlparser::ast::FunctionArguments;
+use datafusion::sql::sqlparser::ast::GroupByExpr;
 use datafusion::sql::sqlparser::ast::Ident;
+use datafusion::sql::sqlparser::ast::Join;
+use datafusion::sql::sqlparser::ast::JoinOperator;
 use datafusion::sql::sqlparser::ast::ObjectName;
 use datafusion::sql::sqlparser::ast::ObjectNamePart;
 use datafusion::sql::sqlparser::ast::OrderByKind;
@@ -31,28 +37,92 @@ use datafusion::sql::sqlparser::ast::ValueWithSpan;
 use datafusion::sql::sqlparser::ast::VisitMut;
 use datafusion::sql::sqlparser::ast::VisitorMut;
 use datafusion::sql::sqlparser::dialect::PostgreSqlDialect;
+use datafusion::sql::sqlparser::keywords::Keyword;
 use datafusion::sql::sqlparser::parser::Parser;
 use datafusion::sql::sqlparser::parser::ParserError;
+use datafusion::sql::sqlparser::tokenizer::Token;
 
 mod blacklist;
+mod literals;
+mod normalize;
+mod ordering;
+mod query_id;
+mod temporal;
+mod window;
 pub use blacklist::BlacklistSqlRewriter;
+pub(crate) use literals::escape_backslash_literals;
+pub use normalize::normalize;
+pub use query_id::query_id;
+pub use temporal::{DateOrder, DateStyle, TemporalLiteralRewrite};
+pub use window::RewriteWindowFilter;
 
 pub fn parse(sql: &str) -> Result<Vec<Statement>, ParserError> {
     let dialect = PostgreSqlDialect {};
 
-    Parser::parse_sql(&dialect, sql)
+    let sql = window::remove_frame_exclusions(sql)?;
+    let sql = ordering::rewrite_orderings(&sql);
+    let statements = Parser::parse_sql(&dialect, &sql)?;
+    window::check_window_filters(&statements)?;
+    Ok(statements)
 }
 
-pub fn rewrite(mut s: Statement, rules: &[Arc<dyn SqlStatementRewriteRule>]) -> Statement {
+/// Consume the next token if it is the word `word`, which isn't a keyword of
+/// sqlparser
+pub(crate) fn parse_word(parser: &mut Parser, word: &str) -> bool {
+    match parser.peek_token().token {
+        Token::Word(w) if w.quote_style.is_none() && w.value.eq_ignore_ascii_case(word) => {
+            parser.next_token();
+            true
+        }
+        _ => false,
+    }
+}
+
+pub(crate) fn parse_name(parser: &mut Parser) -> Result<String, ParserError> {
+    Ok(IdentNormalizer::default().normalize(parser.parse_identifier()?))
+}
+
+/// `OPTIONS (name 'value', ...)`
+pub(crate) fn parse_options(parser: &mut Parser) -> Result<Vec<(String, String)>, ParserError> {
+    let mut options = Vec::new();
+    if parser.parse_keyword(Keyword::OPTIONS) {
+        parser.expect_token(&Token::LParen)?;
+        loop {
+            let name = parse_name(parser)?;
+            options.push((name, parser.parse_literal_string()?));
+            if !parser.consume_token(&Token::Comma) {
+                break;
+            }
+        }
+        parser.expect_token(&Token::RParen)?;
+    }
+    Ok(options)
+}
+
+/// Rewrite `s` with `rules`, also returning the notices of the rules about
+/// constructs they dropped
+pub fn rewrite_with_notices(
+    mut s: Statement,
+    rules: &[Arc<dyn SqlStatementRewriteRule>],
+) -> (Statement, Vec<String>) {
+    let mut notices = Vec::new();
     for rule in rules {
-        s = rule.rewrite(s);
+        let (rewritten, rule_notices) = rule.rewrite_with_notices(s);
+        s = rewritten;
+        notices.extend(rule_notices);
     }
 
-    s
+    (s, notices)
 }
 
 pub trait SqlStatementRewriteRule: Send + Sync {
     fn rewrite(&self, s: Statement) -> Statement;
+
+    /// Rewrite `s`, naming the constructs the rule dropped without honoring
+    /// them, so clients can be warned about the changed semantics
+    fn rewrite_with_notices(&self, s: Statement) -> (Statement, Vec<String>) {
+        (self.rewrite(s), Vec::new())
+    }
 }
 
 /// Rewrite rule for adding alias to duplicated projection
@@ -250,14 +320,12 @@ impl ResolveUnqualifiedIdentifer {
 
     fn rewrite_expr(expr: &mut Expr, wildcard_alias: &str, table_aliases: &HashSet<String>) {
         match expr {
-            Expr::Identifier(ident) => {
-                // If the identifier is not a table alias itself, rewrite it.
-                if !table_aliases.contains(&ident.value) {
-                    *expr = Expr::CompoundIdentifier(vec![
-                        Ident::new(wildcard_alias.to_string()),
-                        ident.clone(),
-                    ]);
-                }
+            // If the identifier is not a table alias itself, rewrite it.
+            Expr::Identifier(ident) if !table_aliases.contains(&ident.value) => {
+                *expr = Expr::CompoundIdentifier(vec![
+                    Ident::new(wildcard_alias.to_string()),
+                    ident.clone(),
+                ]);
             }
             Expr::BinaryOp { left, right, .. } => {
                 Self::rewrite_expr(left, wildcard_alias, table_aliases);
@@ -279,6 +347,79 @@ impl SqlStatementRewriteRule for ResolveUnqualifiedIdentifer {
     }
 }
 
+/// Resolve `'name'::regclass` literals to the OID of the relation
+///
+/// The literal is rewritten to a `to_regclass` call, which looks up the OID
+/// assigned to the relation by the pg_catalog OID registry. Must run before
+/// `RemoveUnsupportedTypes`, which drops the remaining `regclass` casts.
+#[derive(Debug)]
+pub struct ResolveRegclassLiteral;
+
+struct ResolveRegclassLiteralVisitor;
+
+impl ResolveRegclassLiteralVisitor {
+    fn to_regclass(name: &str) -> Expr {
+        Expr::Function(Function {
+            name: ObjectName::from(vec![Ident::new("to_regclass")]),
+            args: FunctionArguments::List(FunctionArgumentList {
+                args: vec![FunctionArg::Unnamed(FunctionArgExpr::Expr(Expr::Value(
+                    Value::SingleQuotedString(name.to_string()).with_empty_span(),
+                )))],
+                duplicate_treatment: None,
+                clauses: vec![],
+            }),
+            uses_odbc_syntax: false,
+            parameters: FunctionArguments::None,
+            filter: None,
+            null_treatment: None,
+            over: None,
+            within_group: vec![],
+        })
+    }
+
+    fn is_regclass(data_type: &DataType) -> bool {
+        data_type.to_string().eq_ignore_ascii_case("regclass")
+    }
+}
+
+impl VisitorMut for ResolveRegclassLiteralVisitor {
+    type Break = ();
+
+    fn pre_visit_expr(&mut self, expr: &mut Expr) -> ControlFlow<Self::Break> {
+        match expr {
+            Expr::TypedString {
+                value: Value::SingleQuotedString(name),
+                data_type,
+            } if Self::is_regclass(data_type) => {
+                *expr = Self::to_regclass(name);
+            }
+            Expr::Cast {
+                data_type,
+                expr: value,
+                ..
+            } if Self::is_regclass(data_type) => {
+                if let Expr::Value(ValueWithSpan {
+                    value: Value::SingleQuotedString(name),
+                    ..
+                }) = value.as_ref()
+                {
+                    *expr = Self::to_regclass(name);
+                }
+            }
+            _ => {}
+        }
+
+        ControlFlow::Continue(())
+    }
+}
+
+impl SqlStatementRewriteRule for ResolveRegclassLiteral {
+    fn rewrite(&self, mut statement: Statement) -> Statement {
+        let _ = statement.visit(&mut ResolveRegclassLiteralVisitor);
+        statement
+    }
+}
+
 /// Remove datafusion unsupported type annotations
 #[derive(Debug)]
 pub struct RemoveUnsupportedTypes {
@@ -292,6 +433,9 @@ impl RemoveUnsupportedTypes {
         unsupported_types.insert("regproc".to_owned());
         unsupported_types.insert("regtype".to_owned());
         unsupported_types.insert("regtype[]".to_owned());
+        // json documents are text, see crate::pg_catalog::setup_pg_catalog
+        unsupported_types.insert("json".to_owned());
+        unsupported_types.insert("jsonb".to_owned());
 
         Self { unsupported_types }
     }
@@ -307,26 +451,22 @@ impl VisitorMut for RemoveUnsupportedTypesVisitor<'_> {
     fn pre_visit_expr(&mut self, expr: &mut Expr) -> ControlFlow<Self::Break> {
         match expr {
             // This is the key part: identify constants with type annotations.
-            Expr::TypedString { value, data_type } => {
+            Expr::TypedString { value, data_type }
                 if self
                     .unsupported_types
-                    .contains(data_type.to_string().to_lowercase().as_str())
-                {
-                    *expr =
-                        Expr::Value(Value::SingleQuotedString(value.to_string()).with_empty_span());
-                }
+                    .contains(data_type.to_string().to_lowercase().as_str()) =>
+            {
+                *expr = Expr::Value(Value::SingleQuotedString(value.to_string()).with_empty_span());
             }
             Expr::Cast {
                 data_type,
                 expr: value,
                 ..
-            } => {
-                if self
-                    .unsupported_types
-                    .contains(data_type.to_string().to_lowercase().as_str())
-                {
-                    *expr = *value.clone();
-                }
+            } if self
+                .unsupported_types
+                .contains(data_type.to_string().to_lowercase().as_str()) =>
+            {
+                *expr = *value.clone();
             }
             // Add more match arms for other expression types (e.g., `Function`, `InList`) as needed.
             _ => {}
@@ -453,6 +593,145 @@ impl SqlStatementRewriteRule for RewriteArrayAnyAllOperation {
     }
 }
 
+/// Rewrite `EXTRACT(field FROM value)` to `date_part('field', value)`
+///
+/// DataFusion plans `EXTRACT` with its builtin `date_part`, so this makes it
+/// use the postgres fields of the `date_part` registered by
+/// [`crate::pg_catalog::setup_pg_catalog`].
+#[derive(Debug)]
+pub struct RewriteExtract;
+
+struct RewriteExtractVisitor;
+
+impl VisitorMut for RewriteExtractVisitor {
+    type Break = ();
+
+    fn pre_visit_expr(&mut self, expr: &mut Expr) -> ControlFlow<Self::Break> {
+        if let Expr::Extract {
+            field, expr: value, ..
+        } = expr
+        {
+            let field = field.to_string().to_lowercase();
+            let args = vec![
+                FunctionArg::Unnamed(FunctionArgExpr::Expr(Expr::Value(
+                    Value::SingleQuotedString(field).with_empty_span(),
+                ))),
+                FunctionArg::Unnamed(FunctionArgExpr::Expr(value.as_ref().clone())),
+            ];
+            *expr = Expr::Function(Function {
+                name: ObjectName::from(vec![Ident::new("date_part")]),
+                args: FunctionArguments::List(FunctionArgumentList {
+                    args,
+                    duplicate_treatment: None,
+                    clauses: vec![],
+                }),
+                uses_odbc_syntax: false,
+                parameters: FunctionArguments::None,
+                filter: None,
+                null_treatment: None,
+                over: None,
+                within_group: vec![],
+            });
+        }
+
+        ControlFlow::Continue(())
+    }
+}
+
+impl SqlStatementRewriteRule for RewriteExtract {
+    fn rewrite(&self, mut s: Statement) -> Statement {
+        let _ = s.visit(&mut RewriteExtractVisitor);
+
+        s
+    }
+}
+
+/// Rewrite `jsonb_array_elements(doc)` and `jsonb_array_elements_text(doc)`
+/// of select lists to `unnest`, so they answer a row per element
+///
+/// In select lists the functions answer the arrays of the elements, and in
+/// `FROM` they are table functions, see [`crate::pg_catalog::setup_pg_catalog`].
+#[derive(Debug)]
+pub struct RewriteJsonSetFunctions;
+
+const JSON_SET_FUNCTIONS: [&str; 4] = [
+    "jsonb_array_elements",
+    "json_array_elements",
+    "jsonb_array_elements_text",
+    "json_array_elements_text",
+];
+
+struct RewriteJsonSetFunctionsVisitor;
+
+impl RewriteJsonSetFunctionsVisitor {
+    fn rewrite_set_expr(body: &mut SetExpr) {
+        match body {
+            SetExpr::Select(select) => {
+                for item in select.projection.iter_mut() {
+                    let (expr, alias) = match item {
+                        SelectItem::UnnamedExpr(expr) => (expr, None),
+                        SelectItem::ExprWithAlias { expr, alias } => (expr, Some(alias.clone())),
+                        _ => continue,
+                    };
+                    let Expr::Function(function) = expr else {
+                        continue;
+                    };
+                    let Some(name) = function
+                        .name
+                        .0
+                        .last()
+                        .and_then(ObjectNamePart::as_ident)
+                        .map(|ident| ident.value.to_lowercase())
+                        .filter(|name| JSON_SET_FUNCTIONS.contains(&name.as_str()))
+                    else {
+                        continue;
+                    };
+                    let unnest = Expr::Function(Function {
+                        name: ObjectName::from(vec![Ident::new("unnest")]),
+                        args: FunctionArguments::List(FunctionArgumentList {
+                            args: vec![FunctionArg::Unnamed(FunctionArgExpr::Expr(expr.clone()))],
+                            duplicate_treatment: None,
+                            clauses: vec![],
+                        }),
+                        uses_odbc_syntax: false,
+                        parameters: FunctionArguments::None,
+                        filter: None,
+                        null_treatment: None,
+                        over: None,
+                        within_group: vec![],
+                    });
+                    *item = SelectItem::ExprWithAlias {
+                        expr: unnest,
+                        alias: alias.unwrap_or_else(|| Ident::new(name)),
+                    };
+                }
+            }
+            SetExpr::SetOperation { left, right, .. } => {
+                Self::rewrite_set_expr(left);
+                Self::rewrite_set_expr(right);
+            }
+            _ => {}
+        }
+    }
+}
+
+impl VisitorMut for RewriteJsonSetFunctionsVisitor {
+    type Break = ();
+
+    fn pre_visit_query(&mut self, query: &mut Query) -> ControlFlow<Self::Break> {
+        Self::rewrite_set_expr(&mut query.body);
+        ControlFlow::Continue(())
+    }
+}
+
+impl SqlStatementRewriteRule for RewriteJsonSetFunctions {
+    fn rewrite(&self, mut s: Statement) -> Statement {
+        let _ = s.visit(&mut RewriteJsonSetFunctionsVisitor);
+
+        s
+    }
+}
+
 /// Prepend qualifier to table_name
 ///
 /// Postgres has pg_catalog in search_path by default so it allow access to
@@ -553,10 +832,10 @@ impl VisitorMut for FixArrayLiteralVisitor {
                                 }
                             })
                             .collect();
-                        *expr = Box::new(Expr::Array(Array {
+                        **expr = Expr::Array(Array {
                             elem: elems,
                             named: true,
-                        }));
+                        });
                     }
                 }
             }
@@ -613,6 +892,250 @@ impl SqlStatementRewriteRule for RemoveTableFunctionQualifier {
     }
 }
 
+/// Functions registered with their `pg_catalog` qualifier, which keep it
+const PG_CATALOG_QUALIFIED_FUNCTIONS: &[&str] = &[
+    "pg_get_expr",
+    "pg_get_partkeydef",
+    "pg_get_userbyid",
+    "pg_table_is_visible",
+];
+
+/// Remove the `pg_catalog` qualifier from function calls
+///
+/// Clients like pgbench qualify builtin functions, e.g.
+/// `pg_catalog.array_position(...)`, which the query engine only knows by
+/// their bare name.
+#[derive(Debug)]
+pub struct RemoveFunctionQualifier;
+
+struct RemoveFunctionQualifierVisitor;
+
+impl VisitorMut for RemoveFunctionQualifierVisitor {
+    type Break = ();
+
+    fn pre_visit_expr(&mut self, expr: &mut Expr) -> ControlFlow<Self::Break> {
+        if let Expr::Function(function) = expr {
+            let name = &mut function.name;
+            if let [ObjectNamePart::Identifier(schema), ObjectNamePart::Identifier(function_name)] =
+                &name.0[..]
+            {
+                if schema.value.eq_ignore_ascii_case("pg_catalog")
+                    && !PG_CATALOG_QUALIFIED_FUNCTIONS
+                        .contains(&function_name.value.to_lowercase().as_str())
+                {
+                    name.0.remove(0);
+                }
+            }
+        }
+        ControlFlow::Continue(())
+    }
+}
+
+impl SqlStatementRewriteRule for RemoveFunctionQualifier {
+    fn rewrite(&self, mut s: Statement) -> Statement {
+        let mut visitor = RemoveFunctionQualifierVisitor;
+
+        let _ = s.visit(&mut visitor);
+        s
+    }
+}
+
+/// Inline lateral subqueries that only compute expressions
+///
+/// pgbench finds its tables with
+/// `CROSS JOIN LATERAL (SELECT array_position(..., n.nspname)) AS o(n)`, a
+/// subquery without `FROM` whose outer references the query engine can't
+/// plan. Such joins are removed and the qualified references to their
+/// columns, like `o.n`, are replaced with the expressions of the subquery.
+#[derive(Debug)]
+pub struct InlineLateralProjection;
+
+/// The alias and the named expressions of a `CROSS JOIN LATERAL (SELECT
+/// ...)` without `FROM`, `None` for other joins
+fn lateral_projection(join: &Join) -> Option<(String, Vec<(String, Expr)>)> {
+    let (
+        JoinOperator::CrossJoin,
+        TableFactor::Derived {
+            lateral: true,
+            subquery,
+            alias: Some(alias),
+        },
+    ) = (&join.join_operator, &join.relation)
+    else {
+        return None;
+    };
+    let SetExpr::Select(select) = subquery.body.as_ref() else {
+        return None;
+    };
+    let plain_query = subquery.with.is_none()
+        && subquery.order_by.is_none()
+        && subquery.limit.is_none()
+        && subquery.offset.is_none()
+        && subquery.fetch.is_none();
+    let plain_select = select.from.is_empty()
+        && select.selection.is_none()
+        && select.having.is_none()
+        && select.distinct.is_none()
+        && matches!(&select.group_by, GroupByExpr::Expressions(exprs, _) if exprs.is_empty());
+    if !plain_query || !plain_select {
+        return None;
+    }
+    if !alias.columns.is_empty() && alias.columns.len() != select.projection.len() {
+        return None;
+    }
+
+    let mut columns = Vec::new();
+    for (i, item) in select.projection.iter().enumerate() {
+        let (expr, name) = match item {
+            SelectItem::UnnamedExpr(expr) => (expr, None),
+            SelectItem::ExprWithAlias { expr, alias } => (expr, Some(alias)),
+            _ => return None,
+        };
+        if let Some(name) = alias.columns.get(i).map(|column| &column.name).or(name) {
+            columns.push((name.value.to_lowercase(), expr.clone()));
+        }
+    }
+    Some((alias.name.value.to_lowercase(), columns))
+}
+
+struct InlineLateralProjectionVisitor;
+
+impl VisitorMut for InlineLateralProjectionVisitor {
+    type Break = ();
+
+    fn pre_visit_query(&mut self, query: &mut Query) -> ControlFlow<Self::Break> {
+        let SetExpr::Select(select) = query.body.as_mut() else {
+            return ControlFlow::Continue(());
+        };
+        let mut inlined = Vec::new();
+        for table in &mut select.from {
+            table.joins.retain(|join| match lateral_projection(join) {
+                Some(projection) => {
+                    inlined.push(projection);
+                    false
+                }
+                None => true,
+            });
+        }
+        if inlined.is_empty() {
+            return ControlFlow::Continue(());
+        }
+
+        let inline = |expr: &mut Expr| {
+            if let Expr::CompoundIdentifier(idents) = expr {
+                if let [table, column] = &idents[..] {
+                    let (table, column) = (table.value.to_lowercase(), column.value.to_lowercase());
+                    let replacement = inlined
+                        .iter()
+                        .filter(|(alias, _)| *alias == table)
+                        .flat_map(|(_, columns)| columns)
+                        .find(|(name, _)| *name == column);
+                    if let Some((_, replacement)) = replacement {
+                        *expr = replacement.clone();
+                    }
+                }
+            }
+            ControlFlow::<()>::Continue(())
+        };
+        let _ = visit_expressions_mut(select, inline);
+        if let Some(order_by) = &mut query.order_by {
+            let _ = visit_expressions_mut(order_by, inline);
+        }
+        ControlFlow::Continue(())
+    }
+}
+
+impl SqlStatementRewriteRule for InlineLateralProjection {
+    fn rewrite(&self, mut s: Statement) -> Statement {
+        let mut visitor = InlineLateralProjectionVisitor;
+
+        let _ = s.visit(&mut visitor);
+        s
+    }
+}
+
+/// Remove clauses the query engine can't honor
+///
+/// `COLLATE`, row locking clauses like `FOR UPDATE` and storage parameters
+/// like `WITH (fillfactor = 70)` are dropped, so statements from postgres
+/// applications still run. Each dropped clause is reported as a notice.
+#[derive(Debug)]
+pub struct RemoveUnsupportedClauses;
+
+#[derive(Default)]
+struct RemoveUnsupportedClausesVisitor {
+    notices: Vec<String>,
+}
+
+impl RemoveUnsupportedClausesVisitor {
+    fn ignore(&mut self, construct: impl std::fmt::Display) {
+        self.notices
+            .push(format!("ignoring unsupported {construct}"));
+    }
+}
+
+impl VisitorMut for RemoveUnsupportedClausesVisitor {
+    type Break = ();
+
+    fn pre_visit_query(&mut self, query: &mut Query) -> ControlFlow<Self::Break> {
+        for lock in query.locks.drain(..) {
+            self.ignore(format_args!("FOR {}", lock.lock_type));
+        }
+        ControlFlow::Continue(())
+    }
+
+    fn pre_visit_expr(&mut self, expr: &mut Expr) -> ControlFlow<Self::Break> {
+        if let Expr::Collate {
+            expr: inner,
+            collation,
+        } = expr
+        {
+            self.ignore(format_args!("COLLATE {collation}"));
+            *expr = *inner.clone();
+        }
+        ControlFlow::Continue(())
+    }
+
+    fn pre_visit_statement(&mut self, statement: &mut Statement) -> ControlFlow<Self::Break> {
+        if let Statement::CreateTable(create_table) = statement {
+            for column in &mut create_table.columns {
+                column.options.retain(|option| match &option.option {
+                    ColumnOption::Collation(collation) => {
+                        self.ignore(format_args!("COLLATE {collation}"));
+                        false
+                    }
+                    _ => true,
+                });
+            }
+            if !create_table.with_options.is_empty() {
+                let options = std::mem::take(&mut create_table.with_options);
+                self.ignore(format_args!(
+                    "WITH ({})",
+                    options
+                        .iter()
+                        .map(ToString::to_string)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ));
+            }
+        }
+        ControlFlow::Continue(())
+    }
+}
+
+impl SqlStatementRewriteRule for RemoveUnsupportedClauses {
+    fn rewrite(&self, s: Statement) -> Statement {
+        self.rewrite_with_notices(s).0
+    }
+
+    fn rewrite_with_notices(&self, mut s: Statement) -> (Statement, Vec<String>) {
+        let mut visitor = RemoveUnsupportedClausesVisitor::default();
+
+        let _ = s.visit(&mut visitor);
+        (s, visitor.notices)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -622,7 +1145,7 @@ mod tests {
             let sql = $orig;
             let statement = parse(sql).expect("Failed to parse").remove(0);
 
-            let statement = rewrite(statement, $rules);
+            let statement = rewrite_with_notices(statement, $rules).0;
             assert_eq!(statement.to_string(), $rewt);
         };
     }
@@ -653,7 +1176,7 @@ mod tests {
         let sql = "SELECT n.oid,n.*,d.description FROM pg_catalog.pg_namespace n LEFT OUTER JOIN pg_catalog.pg_description d ON d.objoid=n.oid AND d.objsubid=0 AND d.classoid='pg_namespace' ORDER BY nspsname";
         let statement = parse(sql).expect("Failed to parse").remove(0);
 
-        let statement = rewrite(statement, &rules);
+        let statement = rewrite_with_notices(statement, &rules).0;
         assert_eq!(
             statement.to_string(),
             "SELECT n.oid AS __alias_oid, n.*, d.description FROM pg_catalog.pg_namespace AS n LEFT OUTER JOIN pg_catalog.pg_description AS d ON d.objoid = n.oid AND d.objsubid = 0 AND d.classoid = 'pg_namespace' ORDER BY nspsname"
@@ -684,6 +1207,33 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_resolve_regclass_literal() {
+        let rules: Vec<Arc<dyn SqlStatementRewriteRule>> = vec![
+            Arc::new(ResolveRegclassLiteral),
+            Arc::new(RemoveUnsupportedTypes::new()),
+        ];
+
+        assert_rewrite!(
+            &rules,
+            "SELECT d.description FROM pg_catalog.pg_description d WHERE d.classoid='pg_namespace'::regclass",
+            "SELECT d.description FROM pg_catalog.pg_description AS d WHERE d.classoid = to_regclass('pg_namespace')"
+        );
+
+        assert_rewrite!(
+            &rules,
+            "SELECT attname FROM pg_attribute WHERE attrelid = regclass 'public.users'",
+            "SELECT attname FROM pg_attribute WHERE attrelid = to_regclass('public.users')"
+        );
+
+        // casts of columns are left to RemoveUnsupportedTypes
+        assert_rewrite!(
+            &rules,
+            "SELECT c.oid::regclass FROM pg_class c",
+            "SELECT c.oid FROM pg_class AS c"
+        );
+    }
+
     #[test]
     fn test_remove_unsupported_types() {
         let rules: Vec<Arc<dyn SqlStatementRewriteRule>> =
@@ -714,6 +1264,41 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_extract_to_date_part() {
+        let rules: Vec<Arc<dyn SqlStatementRewriteRule>> = vec![Arc::new(RewriteExtract)];
+
+        assert_rewrite!(
+            &rules,
+            "SELECT EXTRACT(ISODOW FROM created_at), extract(epoch from now() - created_at) FROM orders",
+            "SELECT date_part('isodow', created_at), date_part('epoch', now() - created_at) FROM orders"
+        );
+        assert_rewrite!(
+            &rules,
+            "SELECT * FROM orders WHERE EXTRACT(YEAR FROM EXTRACT(EPOCH FROM day)::timestamp) = 2024",
+            "SELECT * FROM orders WHERE date_part('year', date_part('epoch', day)::TIMESTAMP) = 2024"
+        );
+    }
+
+    #[test]
+    fn test_json_set_functions() {
+        let rules: Vec<Arc<dyn SqlStatementRewriteRule>> = vec![
+            Arc::new(RemoveUnsupportedTypes::new()),
+            Arc::new(RewriteJsonSetFunctions),
+        ];
+
+        assert_rewrite!(
+            &rules,
+            "SELECT id, jsonb_array_elements(doc -> 'tags'), json_array_elements_text(doc::json) AS tag FROM docs",
+            "SELECT id, unnest(jsonb_array_elements(doc -> 'tags')) AS jsonb_array_elements, unnest(json_array_elements_text(doc)) AS tag FROM docs"
+        );
+        assert_rewrite!(
+            &rules,
+            "SELECT value FROM jsonb_array_elements('[1, 2]'::jsonb)",
+            "SELECT value FROM jsonb_array_elements('[1, 2]')"
+        );
+    }
+
     #[test]
     fn test_any_to_array_contains() {
         let rules: Vec<Arc<dyn SqlStatementRewriteRule>> =
@@ -802,4 +1387,70 @@ mod tests {
             "SELECT * FROM pg_get_keywords()"
         );
     }
+
+    #[test]
+    fn test_remove_qualifier_from_function() {
+        let rules: Vec<Arc<dyn SqlStatementRewriteRule>> = vec![Arc::new(RemoveFunctionQualifier)];
+
+        assert_rewrite!(
+            &rules,
+            "SELECT pg_catalog.array_position(pg_catalog.current_schemas(true), 'public'), pg_catalog.pg_get_expr(adbin, adrelid) FROM pg_attrdef",
+            "SELECT array_position(current_schemas(true), 'public'), pg_catalog.pg_get_expr(adbin, adrelid) FROM pg_attrdef"
+        );
+    }
+
+    #[test]
+    fn test_inline_lateral_projection() {
+        let rules: Vec<Arc<dyn SqlStatementRewriteRule>> = vec![Arc::new(InlineLateralProjection)];
+
+        assert_rewrite!(
+            &rules,
+            "SELECT o.n, c.relname FROM pg_class AS c JOIN pg_namespace AS n ON n.oid = c.relnamespace CROSS JOIN LATERAL (SELECT array_position(current_schemas(true), n.nspname)) AS o(n) WHERE o.n IS NOT NULL ORDER BY o.n",
+            "SELECT array_position(current_schemas(true), n.nspname), c.relname FROM pg_class AS c JOIN pg_namespace AS n ON n.oid = c.relnamespace WHERE array_position(current_schemas(true), n.nspname) IS NOT NULL ORDER BY array_position(current_schemas(true), n.nspname)"
+        );
+        // lateral subqueries reading tables are left to the query engine
+        assert_rewrite!(
+            &rules,
+            "SELECT o.n FROM t CROSS JOIN LATERAL (SELECT t.a AS n FROM u) AS o",
+            "SELECT o.n FROM t CROSS JOIN LATERAL (SELECT t.a AS n FROM u) AS o"
+        );
+    }
+
+    #[test]
+    fn test_remove_unsupported_clauses() {
+        let rules: Vec<Arc<dyn SqlStatementRewriteRule>> = vec![Arc::new(RemoveUnsupportedClauses)];
+
+        let rewrite_sql = |sql: &str| {
+            let statement = parse(sql).expect("Failed to parse").remove(0);
+            let (statement, notices) = rewrite_with_notices(statement, &rules);
+            (statement.to_string(), notices)
+        };
+
+        assert_eq!(
+            rewrite_sql("SELECT name FROM t ORDER BY name COLLATE \"C\" FOR UPDATE"),
+            (
+                "SELECT name FROM t ORDER BY name".to_string(),
+                vec![
+                    "ignoring unsupported FOR UPDATE".to_string(),
+                    "ignoring unsupported COLLATE \"C\"".to_string()
+                ]
+            )
+        );
+        assert_eq!(
+            rewrite_sql(
+                "CREATE TABLE t (name TEXT COLLATE \"en_US\" NOT NULL) WITH (fillfactor = 70)"
+            ),
+            (
+                "CREATE TABLE t (name TEXT NOT NULL)".to_string(),
+                vec![
+                    "ignoring unsupported COLLATE \"en_US\"".to_string(),
+                    "ignoring unsupported WITH (fillfactor = 70)".to_string()
+                ]
+            )
+        );
+        assert_eq!(
+            rewrite_sql("SELECT name FROM t"),
+            ("SELECT name FROM t".to_string(), vec![])
+        );
+    }
 }
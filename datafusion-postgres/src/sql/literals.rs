@@ -0,0 +1,127 @@
+use std::borrow::Cow;
+
+fn is_identifier_byte(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || byte == b'_' || byte == b'$' || byte >= 0x80
+}
+
+/// The index past the quoted text starting at the quote `start`, where
+/// doubled quotes and, in escape strings, backslashes escape the next byte
+fn skip_quoted(bytes: &[u8], start: usize, backslash_escapes: bool) -> usize {
+    let quote = bytes[start];
+    let mut idx = start + 1;
+    while idx < bytes.len() {
+        match bytes[idx] {
+            b'\\' if backslash_escapes => idx += 2,
+            byte if byte == quote && bytes.get(idx + 1) == Some(&quote) => idx += 2,
+            byte if byte == quote => return idx + 1,
+            _ => idx += 1,
+        }
+    }
+    bytes.len()
+}
+
+/// The index past the dollar quoted string starting at `start`, if there is
+/// one, like `$$text$$` or `$tag$text$tag$`
+fn skip_dollar_quoted(sql: &str, start: usize) -> Option<usize> {
+    let bytes = sql.as_bytes();
+    let tag_len = bytes[start + 1..]
+        .iter()
+        .position(|byte| !(byte.is_ascii_alphanumeric() || *byte == b'_' || *byte >= 0x80))?;
+    let tag_end = start + 1 + tag_len;
+    if bytes.get(tag_end) != Some(&b'$') || bytes.get(start + 1).is_some_and(u8::is_ascii_digit) {
+        return None;
+    }
+    let tag = &sql[start..=tag_end];
+    let end = sql[tag_end + 1..]
+        .find(tag)
+        .map_or(sql.len(), |idx| tag_end + 1 + idx + tag.len());
+    Some(end)
+}
+
+/// Read the backslashes of `'...'` string literals as escapes, like postgres
+/// does when `standard_conforming_strings` is off
+///
+/// Literals with backslashes become escape strings, so `'it\'s'` is read as
+/// `E'it\'s'`. Escape strings, dollar quoted strings, quoted identifiers and
+/// comments are kept.
+pub(crate) fn escape_backslash_literals(sql: &str) -> Cow<'_, str> {
+    if !sql.contains('\\') {
+        return Cow::Borrowed(sql);
+    }
+    let bytes = sql.as_bytes();
+    let mut escaped = Vec::new();
+    let mut idx = 0;
+    while idx < bytes.len() {
+        let follows_identifier = idx > 0 && is_identifier_byte(bytes[idx - 1]);
+        idx = match bytes[idx] {
+            b'-' if bytes.get(idx + 1) == Some(&b'-') => sql[idx..]
+                .find('\n')
+                .map_or(sql.len(), |newline| idx + newline + 1),
+            b'/' if bytes.get(idx + 1) == Some(&b'*') => sql[idx + 2..]
+                .find("*/")
+                .map_or(sql.len(), |end| idx + 2 + end + 2),
+            b'"' => skip_quoted(bytes, idx, false),
+            b'$' if !follows_identifier => skip_dollar_quoted(sql, idx).unwrap_or(idx + 1),
+            b'\'' => {
+                // E'...', and the prefixed literals like B'...' or U&'...'
+                // which have no backslash escapes
+                let escape_string = follows_identifier
+                    && bytes[idx - 1].eq_ignore_ascii_case(&b'e')
+                    && (idx < 2 || !is_identifier_byte(bytes[idx - 2]));
+                let prefixed = follows_identifier
+                    || (idx >= 2
+                        && bytes[idx - 1] == b'&'
+                        && bytes[idx - 2].eq_ignore_ascii_case(&b'u'));
+                let end = skip_quoted(bytes, idx, escape_string || !prefixed);
+                if !prefixed && bytes[idx..end].contains(&b'\\') {
+                    escaped.push(idx);
+                }
+                end
+            }
+            _ => idx + 1,
+        };
+    }
+    if escaped.is_empty() {
+        return Cow::Borrowed(sql);
+    }
+
+    let mut rewritten = String::with_capacity(sql.len() + escaped.len());
+    let mut end = 0;
+    for start in escaped {
+        rewritten.push_str(&sql[end..start]);
+        rewritten.push('E');
+        end = start;
+    }
+    rewritten.push_str(&sql[end..]);
+    Cow::Owned(rewritten)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_escape_backslash_literals() {
+        assert_eq!(
+            escape_backslash_literals(r"SELECT 'it\'s', 'a\\b', 'plain', 'it''s\n'"),
+            r"SELECT E'it\'s', E'a\\b', 'plain', E'it''s\n'"
+        );
+        // literals of other kinds are kept
+        for sql in [
+            r"SELECT E'a\tb', e'\\'",
+            r"SELECT $$a\b$$, $tag$c\d$tag$",
+            r#"SELECT 1 AS "a\b""#,
+            r"SELECT 1 -- 'a\b'",
+            r"SELECT /* 'a\b' */ 1",
+            r"SELECT B'\', U&'d\0061t\+000061'",
+            "SELECT 'no backslash'",
+        ] {
+            assert_eq!(escape_backslash_literals(sql), sql, "{sql}");
+        }
+
+        let statement = crate::sql::parse(&escape_backslash_literals(r"SELECT 'it\'s\tok'"))
+            .unwrap()
+            .remove(0);
+        assert_eq!(statement.to_string(), "SELECT E'it\\'s\\tok'");
+    }
+}
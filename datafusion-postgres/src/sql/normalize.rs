@@ -0,0 +1,98 @@
+use std::ops::ControlFlow;
+
+use datafusion::sql::sqlparser::ast::{Expr, Statement, Value, VisitMut, VisitorMut};
+
+/// Highest number of the `$n` parameters of a statement
+struct MaxParameterVisitor(usize);
+
+impl VisitorMut for MaxParameterVisitor {
+    type Break = ();
+
+    fn pre_visit_expr(&mut self, expr: &mut Expr) -> ControlFlow<Self::Break> {
+        if let Expr::Value(value) = expr {
+            if let Value::Placeholder(placeholder) = &value.value {
+                if let Some(n) = parameter_number(placeholder) {
+                    self.0 = self.0.max(n);
+                }
+            }
+        }
+
+        ControlFlow::Continue(())
+    }
+}
+
+/// Replace constants with parameters numbered after the existing ones
+struct ReplaceConstantsVisitor {
+    next_parameter: usize,
+}
+
+impl ReplaceConstantsVisitor {
+    fn parameter(&mut self) -> Expr {
+        self.next_parameter += 1;
+        Expr::Value(Value::Placeholder(format!("${}", self.next_parameter)).with_empty_span())
+    }
+}
+
+impl VisitorMut for ReplaceConstantsVisitor {
+    type Break = ();
+
+    fn pre_visit_expr(&mut self, expr: &mut Expr) -> ControlFlow<Self::Break> {
+        match expr {
+            Expr::Value(value) if !matches!(value.value, Value::Placeholder(_)) => {
+                *expr = self.parameter();
+            }
+            // typed literals like `DATE '2024-01-01'` are a single constant
+            Expr::TypedString { .. } => *expr = self.parameter(),
+            _ => {}
+        }
+
+        ControlFlow::Continue(())
+    }
+}
+
+fn parameter_number(placeholder: &str) -> Option<usize> {
+    placeholder.strip_prefix('$')?.parse().ok()
+}
+
+/// Text of a statement with its constants replaced by `$n` parameters, like
+/// the queries of postgres' `pg_stat_statements`
+///
+/// Constants are numbered after the parameters the statement already has,
+/// so executions differing only in constants or parameter values have the
+/// same normalized text.
+pub fn normalize(statement: &Statement) -> String {
+    let mut statement = statement.clone();
+    let mut max_parameter = MaxParameterVisitor(0);
+    let _ = statement.visit(&mut max_parameter);
+    let _ = statement.visit(&mut ReplaceConstantsVisitor {
+        next_parameter: max_parameter.0,
+    });
+    statement.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql::parse;
+
+    fn normalized(sql: &str) -> String {
+        normalize(&parse(sql).unwrap()[0])
+    }
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(
+            normalized("select * from t where id = 42 and name = 'a' limit 10"),
+            "SELECT * FROM t WHERE id = $1 AND name = $2 LIMIT $3"
+        );
+        assert_eq!(
+            normalized("SELECT * FROM t WHERE id = $1 AND day > DATE '2024-01-01'"),
+            "SELECT * FROM t WHERE id = $1 AND day > $2"
+        );
+        assert_eq!(
+            normalized("INSERT INTO t VALUES (1, 'a'), (2, NULL)"),
+            "INSERT INTO t VALUES ($1, $2), ($3, $4)"
+        );
+        assert_eq!(normalized("SELECT a FROM t"), "SELECT a FROM t");
+    }
+}
@@ -0,0 +1,180 @@
+use std::borrow::Cow;
+use std::ops::Range;
+
+use datafusion::sql::sqlparser::dialect::PostgreSqlDialect;
+use datafusion::sql::sqlparser::tokenizer::{Token, TokenWithSpan, Tokenizer};
+
+use super::window::{is_word, offset};
+
+/// The words continuing type names of several words, like
+/// `double precision` or `timestamp with time zone`
+const TYPE_NAME_WORDS: &[&str] = &["precision", "time", "varying", "with", "without", "zone"];
+
+/// The index past the cast `::type` starting at `idx`, if there is one
+fn skip_cast(tokens: &[&TokenWithSpan], idx: usize) -> Option<usize> {
+    let token = |idx: usize| tokens.get(idx).map(|token| &token.token);
+    if token(idx) != Some(&Token::DoubleColon) {
+        return None;
+    }
+    let mut idx = idx + 1;
+    match token(idx) {
+        Some(Token::Word(_)) if !is_word(tokens[idx], "COLLATE") => idx += 1,
+        _ => return None,
+    }
+    while token(idx) == Some(&Token::Period) && matches!(token(idx + 1), Some(Token::Word(_))) {
+        idx += 2;
+    }
+    while tokens
+        .get(idx)
+        .is_some_and(|token| TYPE_NAME_WORDS.iter().any(|word| is_word(token, word)))
+    {
+        idx += 1;
+    }
+    if token(idx) == Some(&Token::LParen) {
+        let close = idx
+            + 1
+            + tokens[idx + 1..]
+                .iter()
+                .position(|token| !matches!(token.token, Token::Number(..) | Token::Comma))?;
+        if token(close) != Some(&Token::RParen) {
+            return None;
+        }
+        idx = close + 1;
+    }
+    while token(idx) == Some(&Token::LBracket) && token(idx + 1) == Some(&Token::RBracket) {
+        idx += 2;
+    }
+    Some(idx)
+}
+
+/// The index past the collation `COLLATE name` starting at `idx`, if there
+/// is one
+fn skip_collation(tokens: &[&TokenWithSpan], idx: usize) -> Option<usize> {
+    let token = |idx: usize| tokens.get(idx).map(|token| &token.token);
+    if !tokens
+        .get(idx)
+        .is_some_and(|token| is_word(token, "COLLATE"))
+        || !matches!(token(idx + 1), Some(Token::Word(_)))
+    {
+        return None;
+    }
+    let mut idx = idx + 2;
+    while token(idx) == Some(&Token::Period) && matches!(token(idx + 1), Some(Token::Word(_))) {
+        idx += 2;
+    }
+    Some(idx)
+}
+
+/// Rewrite the sort options sqlparser doesn't parse into ones it does
+///
+/// - `expr::type COLLATE name`, where sqlparser only parses collations
+///   directly after the operand, becomes `expr COLLATE name::type`, so
+///   [`super::RemoveUnsupportedClauses`] drops the collation with a notice
+/// - `ORDER BY expr USING <` and `USING >` become `ASC` and `DESC`
+///
+/// The rewritten SQL has the length and lines of `sql`, so the locations of
+/// the errors of the parser still point into `sql`.
+pub(super) fn rewrite_orderings(sql: &str) -> Cow<'_, str> {
+    let lowercase = sql.to_ascii_lowercase();
+    if !lowercase.contains("collate") && !lowercase.contains("using") {
+        return Cow::Borrowed(sql);
+    }
+    // errors are left to the parser
+    let Ok(tokens) = Tokenizer::new(&PostgreSqlDialect {}, sql).tokenize_with_location() else {
+        return Cow::Borrowed(sql);
+    };
+    let tokens = tokens
+        .iter()
+        .filter(|token| !matches!(token.token, Token::Whitespace(_)))
+        .collect::<Vec<_>>();
+    let range = |start: usize, end: usize| -> Range<usize> {
+        offset(sql, tokens[start].span.start)..offset(sql, tokens[end - 1].span.end)
+    };
+
+    let mut replacements: Vec<(Range<usize>, String)> = Vec::new();
+    let mut idx = 0;
+    while idx < tokens.len() {
+        if let Some(mut cast_end) = skip_cast(&tokens, idx) {
+            while let Some(end) = skip_cast(&tokens, cast_end) {
+                cast_end = end;
+            }
+            if let Some(collation_end) = skip_collation(&tokens, cast_end) {
+                let cast = range(idx, cast_end);
+                let collation = range(cast_end, collation_end);
+                replacements.push((
+                    cast.start..collation.end,
+                    format!(
+                        "{}{}{}",
+                        &sql[cast.end..collation.start],
+                        &sql[collation.clone()],
+                        &sql[cast]
+                    ),
+                ));
+                idx = collation_end;
+            } else {
+                idx = cast_end;
+            }
+            continue;
+        }
+
+        if is_word(tokens[idx], "USING") {
+            let direction = match tokens.get(idx + 1).map(|token| &token.token) {
+                Some(Token::Lt) => Some("ASC"),
+                Some(Token::Gt) => Some("DESC"),
+                _ => None,
+            };
+            if let Some(direction) = direction {
+                let operator = range(idx, idx + 2);
+                let width = operator.len();
+                replacements.push((operator, format!("{direction:width$}")));
+                idx += 2;
+                continue;
+            }
+        }
+        idx += 1;
+    }
+    if replacements.is_empty() {
+        return Cow::Borrowed(sql);
+    }
+
+    let mut rewritten = String::with_capacity(sql.len());
+    let mut end = 0;
+    for (range, replacement) in replacements {
+        rewritten.push_str(&sql[end..range.start]);
+        rewritten.push_str(&replacement);
+        end = range.end;
+    }
+    rewritten.push_str(&sql[end..]);
+    Cow::Owned(rewritten)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sql::parse;
+
+    #[test]
+    fn test_orderings() {
+        let parse = |sql: &str| parse(sql).unwrap()[0].to_string();
+        assert_eq!(
+            parse("SELECT s::text COLLATE \"C\", t::varchar(10)[] COLLATE pg_catalog.\"default\" FROM t"),
+            "SELECT s COLLATE \"C\"::TEXT, t COLLATE pg_catalog.\"default\"::VARCHAR(10)[] FROM t"
+        );
+        assert_eq!(
+            parse("SELECT x FROM t ORDER BY x USING >, y USING < NULLS FIRST"),
+            "SELECT x FROM t ORDER BY x DESC, y ASC NULLS FIRST"
+        );
+        // the other uses of USING are kept
+        assert_eq!(
+            parse("SELECT x FROM t JOIN u USING (x)"),
+            "SELECT x FROM t JOIN u USING(x)"
+        );
+
+        // the locations of the errors are kept
+        let error =
+            crate::sql::parse("SELECT x::text COLLATE \"C\" FROM t WHERE x USING >").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "sql parser error: Expected: end of statement, found: DESC at Line: 1, Column: 43"
+        );
+    }
+}
@@ -0,0 +1,54 @@
+use datafusion::sql::sqlparser::ast::Statement;
+
+use super::normalize;
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+/// Identifier of a statement, like postgres' `compute_query_id`
+///
+/// The identifier is a 64-bit FNV-1a hash of the normalized text of the
+/// statement, so it is stable across servers and restarts, and is never 0,
+/// which postgres reserves for statements without an identifier.
+pub fn query_id(statement: &Statement) -> i64 {
+    let hash = normalize(statement)
+        .bytes()
+        .fold(FNV_OFFSET_BASIS, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(FNV_PRIME)
+        });
+    match hash as i64 {
+        0 => 1,
+        id => id,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql::parse;
+
+    fn id(sql: &str) -> i64 {
+        query_id(&parse(sql).unwrap()[0])
+    }
+
+    #[test]
+    fn test_query_id() {
+        assert_eq!(
+            id("SELECT * FROM t WHERE id = 1 LIMIT 10"),
+            id("select *   from t where id = 42 limit 5")
+        );
+        assert_eq!(
+            id("SELECT DATE '2024-01-01', 'a'"),
+            id("SELECT DATE '2025-06-30', 'b'")
+        );
+        assert_ne!(id("SELECT a FROM t"), id("SELECT b FROM t"));
+        assert_ne!(id("SELECT 1"), 0);
+        assert_eq!(id("SELECT 1"), id("SELECT 2"));
+        assert_eq!(
+            id("SELECT * FROM t WHERE id = 1"),
+            id("SELECT * FROM t WHERE id = $1")
+        );
+        // the identifier doesn't depend on the process computing it
+        assert_eq!(id("SELECT 1"), 8285963503360393256);
+    }
+}
@@ -0,0 +1,555 @@
+use std::ops::ControlFlow;
+
+use chrono::{NaiveDate, NaiveTime, Timelike};
+use datafusion::sql::sqlparser::ast::{
+    CastKind, DataType, Expr, Statement, TimezoneInfo, Value, VisitMut, VisitorMut,
+};
+use datafusion::sql::sqlparser::dialect::PostgreSqlDialect;
+use datafusion::sql::sqlparser::parser::Parser;
+
+use super::SqlStatementRewriteRule;
+
+/// The order of the day, month and year of dates like `05/01/2024`, set by
+/// the `DateStyle` of the session
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DateOrder {
+    Mdy,
+    Dmy,
+    Ymd,
+}
+
+/// The `DateStyle` of a session: the output format of dates, which results
+/// are always encoded in as `ISO`, and the order of the fields of dates read
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DateStyle {
+    format: &'static str,
+    order: DateOrder,
+}
+
+impl Default for DateStyle {
+    fn default() -> Self {
+        DateStyle {
+            format: "ISO",
+            order: DateOrder::Ymd,
+        }
+    }
+}
+
+impl DateStyle {
+    /// The `DateStyle` `value`, like `ISO, DMY`, `German` or `mdy`, sets in a
+    /// session with `current`, which keeps the part `value` doesn't name
+    ///
+    /// Errors are the details of the postgres errors of invalid values.
+    pub fn parse(value: &str, current: DateStyle) -> Result<Self, String> {
+        let mut format = None;
+        let mut order = None;
+        for word in value
+            .split([',', ' '])
+            .map(|word| word.trim_matches('"'))
+            .filter(|word| !word.is_empty())
+        {
+            match word.to_uppercase().as_str() {
+                "ISO" => set(&mut format, "ISO")?,
+                "SQL" => set(&mut format, "SQL")?,
+                "POSTGRES" => set(&mut format, "Postgres")?,
+                "GERMAN" => set(&mut format, "German")?,
+                "DMY" | "EURO" | "EUROPEAN" => set(&mut order, DateOrder::Dmy)?,
+                "MDY" | "US" | "NONEURO" | "NONEUROPEAN" => set(&mut order, DateOrder::Mdy)?,
+                "YMD" => set(&mut order, DateOrder::Ymd)?,
+                "DEFAULT" => {
+                    let default = DateStyle::default();
+                    set(&mut format, default.format)?;
+                    set(&mut order, default.order)?;
+                }
+                _ => return Err(format!("Unrecognized key word: \"{word}\".")),
+            }
+        }
+        // German dates are day first unless the order is named
+        let order = match (format, order) {
+            (Some("German"), None) => DateOrder::Dmy,
+            (_, order) => order.unwrap_or(current.order),
+        };
+        Ok(DateStyle {
+            format: format.unwrap_or(current.format),
+            order,
+        })
+    }
+
+    pub fn order(&self) -> DateOrder {
+        self.order
+    }
+}
+
+/// Set a part of a `DateStyle` named by a value, which may name it only once
+fn set<T: PartialEq>(slot: &mut Option<T>, value: T) -> Result<(), String> {
+    match slot {
+        Some(other) if *other != value => {
+            Err("Conflicting \"datestyle\" specifications.".to_string())
+        }
+        _ => {
+            *slot = Some(value);
+            Ok(())
+        }
+    }
+}
+
+impl std::fmt::Display for DateStyle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let order = match self.order {
+            DateOrder::Mdy => "MDY",
+            DateOrder::Dmy => "DMY",
+            DateOrder::Ymd => "YMD",
+        };
+        write!(f, "{}, {order}", self.format)
+    }
+}
+
+/// Rewrite string literals cast to `date`, `time`, `timestamp` and
+/// `timestamptz` the way postgres parses them
+///
+/// DataFusion only reads ISO 8601 text, BI tools write `'05/01/2024'::date`
+/// in the order of `DateStyle`, `'2024-01-05 10:00:00+02'::timestamptz` with
+/// offsets in hours, `TIMESTAMP 'January 5, 2024 10:00 PM'` and the special
+/// values `'epoch'`, `'now'`, `'today'`, `'tomorrow'` and `'yesterday'`.
+/// Literals postgres would refuse are left to the errors of DataFusion.
+#[derive(Debug)]
+pub struct TemporalLiteralRewrite {
+    date_order: DateOrder,
+}
+
+impl TemporalLiteralRewrite {
+    pub fn new(date_order: DateOrder) -> Self {
+        Self { date_order }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Temporal {
+    Date,
+    Time,
+    Timestamp,
+    TimestampTz,
+}
+
+impl Temporal {
+    fn of(data_type: &DataType) -> Option<Self> {
+        match data_type {
+            DataType::Date => Some(Temporal::Date),
+            DataType::Time(_, TimezoneInfo::None | TimezoneInfo::WithoutTimeZone) => {
+                Some(Temporal::Time)
+            }
+            DataType::Timestamp(_, TimezoneInfo::None | TimezoneInfo::WithoutTimeZone)
+            | DataType::Datetime(_) => Some(Temporal::Timestamp),
+            DataType::Timestamp(_, TimezoneInfo::WithTimeZone | TimezoneInfo::Tz) => {
+                Some(Temporal::TimestampTz)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// A literal as DataFusion reads it
+#[derive(Debug, PartialEq)]
+enum Literal {
+    Text(String),
+    /// An expression of the current time, like `now()` for `'now'`
+    Expr(&'static str),
+}
+
+fn special_value(text: &str, temporal: Temporal) -> Option<Literal> {
+    let literal = match (text.to_lowercase().as_str(), temporal) {
+        ("epoch", Temporal::Date) => Literal::Text("1970-01-01".to_string()),
+        ("epoch", Temporal::Timestamp) => Literal::Text("1970-01-01 00:00:00".to_string()),
+        ("epoch", Temporal::TimestampTz) => Literal::Text("1970-01-01T00:00:00+00:00".to_string()),
+        ("allballs", Temporal::Time) => Literal::Text("00:00:00".to_string()),
+        ("now", _) => Literal::Expr("now()"),
+        ("today", Temporal::Time)
+        | ("tomorrow", Temporal::Time)
+        | ("yesterday", Temporal::Time) => return None,
+        ("today", _) => Literal::Expr("date_trunc('day', now())"),
+        ("tomorrow", _) => Literal::Expr("date_trunc('day', now()) + INTERVAL '1 day'"),
+        ("yesterday", _) => Literal::Expr("date_trunc('day', now()) - INTERVAL '1 day'"),
+        _ => return None,
+    };
+    Some(literal)
+}
+
+/// The parts of a date and time literal
+#[derive(Debug, Default, PartialEq)]
+struct DateTime {
+    date: Option<NaiveDate>,
+    time: Option<NaiveTime>,
+    /// Offset from UTC, in seconds
+    offset: Option<i32>,
+}
+
+const MONTHS: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+
+fn month_number(name: &str) -> Option<u32> {
+    let name = name.to_lowercase();
+    if name.len() < 3 {
+        return None;
+    }
+    let position = MONTHS.iter().position(|month| name.starts_with(month))?;
+    // the full name or its abbreviation, like `Sept`
+    let full = [
+        "january",
+        "february",
+        "march",
+        "april",
+        "may",
+        "june",
+        "july",
+        "august",
+        "september",
+        "october",
+        "november",
+        "december",
+    ][position];
+    full.starts_with(&name).then_some(position as u32 + 1)
+}
+
+/// Two digit years are the closest to 2020, like postgres
+fn full_year(field: &str) -> Option<i32> {
+    let year = field.parse::<i32>().ok()?;
+    Some(match field.len() {
+        1 | 2 if year < 70 => year + 2000,
+        1 | 2 => year + 1900,
+        _ => year,
+    })
+}
+
+fn parse_date(fields: &[&str], order: DateOrder) -> Option<NaiveDate> {
+    let (year, month, day) = match fields {
+        // 20240105
+        [field] if field.len() == 8 && field.bytes().all(|b| b.is_ascii_digit()) => (
+            field[..4].parse().ok()?,
+            field[4..6].parse().ok()?,
+            field[6..].parse().ok()?,
+        ),
+        [a, b, c] => {
+            let named_month = fields
+                .iter()
+                .position(|field| month_number(field).is_some());
+            if let Some(position) = named_month {
+                // January 5 2024, 5 Jan 2024, 2024 Jan 5
+                let month = month_number(fields[position])?;
+                let others = fields
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, _)| *i != position)
+                    .map(|(_, field)| *field)
+                    .collect::<Vec<_>>();
+                let (year, day) = if others[0].len() > 2 {
+                    (others[0], others[1])
+                } else {
+                    (others[1], others[0])
+                };
+                (full_year(year)?, month, day.parse().ok()?)
+            } else if a.len() > 2 {
+                // the year comes first when it's written in full
+                (full_year(a)?, b.parse().ok()?, c.parse().ok()?)
+            } else if c.len() > 2 || order != DateOrder::Ymd {
+                let (month, day) = match order {
+                    DateOrder::Dmy => (b, a),
+                    DateOrder::Mdy | DateOrder::Ymd => (a, b),
+                };
+                (full_year(c)?, month.parse().ok()?, day.parse().ok()?)
+            } else {
+                (full_year(a)?, b.parse().ok()?, c.parse().ok()?)
+            }
+        }
+        _ => return None,
+    };
+    NaiveDate::from_ymd_opt(year, month, day)
+}
+
+/// `+02`, `+0530`, `-05:30`, `Z`, `UTC` or `GMT`, in seconds east of UTC
+fn parse_offset(text: &str) -> Option<i32> {
+    if ["z", "utc", "gmt"].contains(&text.to_lowercase().as_str()) {
+        return Some(0);
+    }
+    let sign = match text.as_bytes().first()? {
+        b'+' => 1,
+        b'-' => -1,
+        _ => return None,
+    };
+    let digits = text[1..].replace(':', "");
+    if !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let (hours, minutes) = match digits.len() {
+        1 | 2 => (digits.parse::<i32>().ok()?, 0),
+        4 => (digits[..2].parse().ok()?, digits[2..].parse().ok()?),
+        _ => return None,
+    };
+    (hours <= 15 && minutes < 60).then_some(sign * (hours * 3600 + minutes * 60))
+}
+
+fn parse_time(text: &str) -> Option<NaiveTime> {
+    NaiveTime::parse_from_str(text, "%H:%M:%S%.f")
+        .or_else(|_| NaiveTime::parse_from_str(text, "%H:%M"))
+        .ok()
+}
+
+fn parse_date_time(text: &str, order: DateOrder) -> Option<DateTime> {
+    // 2024-01-05T10:00:00 separates the date from the time with a T
+    let text = match text.find(['T', 't']) {
+        Some(i)
+            if i > 0
+                && text.as_bytes()[i - 1].is_ascii_digit()
+                && text.as_bytes().get(i + 1).is_some_and(u8::is_ascii_digit) =>
+        {
+            format!("{} {}", &text[..i], &text[i + 1..])
+        }
+        _ => text.to_string(),
+    };
+
+    let mut date_fields = Vec::new();
+    let mut parsed = DateTime::default();
+    for token in text.split_whitespace() {
+        let token = token.trim_end_matches(',');
+        if parsed.time.is_none() && token.contains(':') {
+            // the offset may follow the time, like 10:00:00+02
+            let (time, offset) = match token.find(['+', '-', 'Z', 'z']) {
+                Some(i) => (&token[..i], Some(parse_offset(&token[i..])?)),
+                None => (token, None),
+            };
+            parsed.time = Some(parse_time(time)?);
+            parsed.offset = offset;
+        } else if let Some(time) = parsed.time.filter(|_| parsed.offset.is_none()) {
+            let hour = time.hour();
+            parsed.time = match token.to_lowercase().as_str() {
+                "am" if hour == 12 => time.with_hour(0),
+                "am" if hour < 12 => Some(time),
+                "pm" if hour < 12 => time.with_hour(hour + 12),
+                "pm" if hour == 12 => Some(time),
+                _ => {
+                    parsed.offset = Some(parse_offset(token)?);
+                    Some(time)
+                }
+            };
+            parsed.time?;
+        } else if parsed.time.is_none() {
+            date_fields.extend(token.split(['-', '/', '.']).filter(|f| !f.is_empty()));
+        } else {
+            return None;
+        }
+    }
+    if !date_fields.is_empty() {
+        parsed.date = Some(parse_date(&date_fields, order)?);
+    }
+    Some(parsed)
+}
+
+fn format_offset(offset: i32) -> String {
+    let sign = if offset < 0 { '-' } else { '+' };
+    let offset = offset.abs();
+    format!("{sign}{:02}:{:02}", offset / 3600, offset % 3600 / 60)
+}
+
+/// The literal DataFusion reads as the value postgres parses of `text`
+fn temporal_literal(text: &str, temporal: Temporal, order: DateOrder) -> Option<Literal> {
+    let text = text.trim();
+    if let Some(literal) = special_value(text, temporal) {
+        return Some(literal);
+    }
+    let parsed = parse_date_time(text, order)?;
+    let time = parsed.time.unwrap_or_default().format("%H:%M:%S%.f");
+    let text = match temporal {
+        Temporal::Date => parsed.date?.format("%Y-%m-%d").to_string(),
+        Temporal::Time => parsed.time?.format("%H:%M:%S%.f").to_string(),
+        // timestamps without time zone ignore the offset
+        Temporal::Timestamp => format!("{} {time}", parsed.date?.format("%Y-%m-%d")),
+        Temporal::TimestampTz => {
+            let offset = parsed.offset.map(format_offset).unwrap_or_default();
+            format!("{}T{time}{offset}", parsed.date?.format("%Y-%m-%d"))
+        }
+    };
+    Some(Literal::Text(text))
+}
+
+struct TemporalLiteralVisitor {
+    date_order: DateOrder,
+}
+
+impl TemporalLiteralVisitor {
+    /// The cast of `text` to `data_type`, `None` for texts and types left as
+    /// they are
+    fn cast(&self, text: &str, data_type: &DataType) -> Option<Expr> {
+        let temporal = Temporal::of(data_type)?;
+        let expr = match temporal_literal(text, temporal, self.date_order)? {
+            Literal::Text(text) => Expr::Value(Value::SingleQuotedString(text).with_empty_span()),
+            Literal::Expr(sql) => Parser::new(&PostgreSqlDialect {})
+                .try_with_sql(sql)
+                .and_then(|mut parser| parser.parse_expr())
+                .ok()?,
+        };
+        Some(Expr::Cast {
+            kind: CastKind::Cast,
+            expr: Box::new(expr),
+            data_type: data_type.clone(),
+            format: None,
+        })
+    }
+}
+
+impl VisitorMut for TemporalLiteralVisitor {
+    type Break = ();
+
+    fn post_visit_expr(&mut self, expr: &mut Expr) -> ControlFlow<Self::Break> {
+        let rewritten = match expr {
+            Expr::TypedString {
+                data_type,
+                value: Value::SingleQuotedString(text),
+            } => self.cast(text, data_type),
+            Expr::Cast {
+                expr: value,
+                data_type,
+                format: None,
+                ..
+            } => match value.as_ref() {
+                Expr::Value(value) => match &value.value {
+                    Value::SingleQuotedString(text) => self.cast(text, data_type),
+                    _ => None,
+                },
+                _ => None,
+            },
+            _ => None,
+        };
+        if let Some(rewritten) = rewritten {
+            *expr = rewritten;
+        }
+
+        ControlFlow::Continue(())
+    }
+}
+
+impl SqlStatementRewriteRule for TemporalLiteralRewrite {
+    fn rewrite(&self, mut statement: Statement) -> Statement {
+        let _ = statement.visit(&mut TemporalLiteralVisitor {
+            date_order: self.date_order,
+        });
+        statement
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql::parse;
+
+    fn rewritten(sql: &str, datestyle: &str) -> String {
+        let rule = TemporalLiteralRewrite::new(
+            DateStyle::parse(datestyle, DateStyle::default())
+                .unwrap()
+                .order(),
+        );
+        rule.rewrite(parse(sql).unwrap().remove(0)).to_string()
+    }
+
+    #[test]
+    fn test_temporal_literals() {
+        for (sql, datestyle, expected) in [
+            (
+                "SELECT '05/01/2024'::date",
+                "ISO, DMY",
+                "SELECT CAST('2024-01-05' AS DATE)",
+            ),
+            (
+                "SELECT '05/01/2024'::date",
+                "ISO, MDY",
+                "SELECT CAST('2024-05-01' AS DATE)",
+            ),
+            (
+                "SELECT DATE 'January 5, 24'",
+                "ISO, MDY",
+                "SELECT CAST('2024-01-05' AS DATE)",
+            ),
+            (
+                "SELECT '2024-01-05 10:00:00+02'::timestamptz",
+                "ISO, MDY",
+                "SELECT CAST('2024-01-05T10:00:00+02:00' AS TIMESTAMPTZ)",
+            ),
+            (
+                "SELECT CAST('2024-01-05 10:00:00+02' AS TIMESTAMP)",
+                "ISO, MDY",
+                "SELECT CAST('2024-01-05 10:00:00' AS TIMESTAMP)",
+            ),
+            (
+                "SELECT TIMESTAMP '5 Jan 2024 10:30 pm'",
+                "ISO, MDY",
+                "SELECT CAST('2024-01-05 22:30:00' AS TIMESTAMP)",
+            ),
+            (
+                "SELECT '2024-01-05T10:00:00.25'::time",
+                "ISO, MDY",
+                "SELECT CAST('10:00:00.250' AS TIME)",
+            ),
+            (
+                "SELECT 'epoch'::timestamp",
+                "ISO, MDY",
+                "SELECT CAST('1970-01-01 00:00:00' AS TIMESTAMP)",
+            ),
+            (
+                "SELECT 'now'::timestamptz",
+                "ISO, MDY",
+                "SELECT CAST(now() AS TIMESTAMPTZ)",
+            ),
+            (
+                "SELECT 'Tomorrow'::date",
+                "ISO, MDY",
+                "SELECT CAST(date_trunc('day', now()) + INTERVAL '1 day' AS DATE)",
+            ),
+            // left to DataFusion
+            (
+                "SELECT '13/13/2024'::date",
+                "ISO, DMY",
+                "SELECT '13/13/2024'::DATE",
+            ),
+            ("SELECT 'today'::text", "ISO, DMY", "SELECT 'today'::TEXT"),
+        ] {
+            assert_eq!(rewritten(sql, datestyle), expected, "{sql}");
+        }
+    }
+
+    #[test]
+    fn test_date_order() {
+        let order = |value: &str| {
+            DateStyle::parse(value, DateStyle::default())
+                .unwrap()
+                .order()
+        };
+        assert_eq!(order("ISO, DMY"), DateOrder::Dmy);
+        assert_eq!(order("German"), DateOrder::Dmy);
+        assert_eq!(order("ISO MDY"), DateOrder::Mdy);
+        assert_eq!(order("ISO"), DateOrder::Ymd);
+
+        let parse = |value: &str, current: &str| {
+            let current = DateStyle::parse(current, DateStyle::default()).unwrap();
+            DateStyle::parse(value, current).map(|style| style.to_string())
+        };
+        assert_eq!(parse("ISO, MDY", "").unwrap(), "ISO, MDY");
+        assert_eq!(parse("iso ymd", "SQL, DMY").unwrap(), "ISO, YMD");
+        // the part a value doesn't name is kept
+        assert_eq!(parse("ISO", "SQL, DMY").unwrap(), "ISO, DMY");
+        assert_eq!(parse("euro", "Postgres, MDY").unwrap(), "Postgres, DMY");
+        assert_eq!(parse("German", "ISO, MDY").unwrap(), "German, DMY");
+        assert_eq!(parse("German, YMD", "").unwrap(), "German, YMD");
+        assert_eq!(parse("DEFAULT", "SQL, DMY").unwrap(), "ISO, YMD");
+        assert_eq!(
+            parse("ISO, SQL", "").unwrap_err(),
+            "Conflicting \"datestyle\" specifications."
+        );
+        assert_eq!(
+            parse("ISO, DMY, MDY", "").unwrap_err(),
+            "Conflicting \"datestyle\" specifications."
+        );
+        assert_eq!(
+            parse("ISO, YYYY", "").unwrap_err(),
+            "Unrecognized key word: \"YYYY\"."
+        );
+    }
+}
@@ -0,0 +1,276 @@
+use std::borrow::Cow;
+use std::ops::ControlFlow;
+
+use datafusion::sql::sqlparser::ast::{
+    CaseWhen, Expr, Function, FunctionArg, FunctionArgExpr, FunctionArguments, Statement, Value,
+    Visit, VisitMut, Visitor, VisitorMut,
+};
+use datafusion::sql::sqlparser::dialect::PostgreSqlDialect;
+use datafusion::sql::sqlparser::parser::ParserError;
+use datafusion::sql::sqlparser::tokenizer::{Location, Token, TokenWithSpan, Tokenizer};
+
+use super::SqlStatementRewriteRule;
+
+/// Aggregates ignoring `NULL` inputs, whose `FILTER (WHERE cond)` is the
+/// same as passing `CASE WHEN cond THEN value END`
+const NULL_IGNORING_AGGREGATES: &[&str] = &[
+    "avg",
+    "bit_and",
+    "bit_or",
+    "bit_xor",
+    "bool_and",
+    "bool_or",
+    "count",
+    "every",
+    "max",
+    "min",
+    "stddev",
+    "stddev_pop",
+    "stddev_samp",
+    "string_agg",
+    "sum",
+    "var_pop",
+    "var_samp",
+    "variance",
+];
+
+fn function_name(function: &Function) -> String {
+    function
+        .name
+        .0
+        .last()
+        .and_then(|part| part.as_ident())
+        .map_or_else(
+            || function.name.to_string(),
+            |ident| ident.value.to_lowercase(),
+        )
+}
+
+/// Whether the `FILTER` of the window function `function` can be rewritten
+/// to its first argument
+fn is_rewritable(function: &Function) -> bool {
+    NULL_IGNORING_AGGREGATES.contains(&function_name(function).as_str())
+        && matches!(
+            &function.args,
+            FunctionArguments::List(list) if matches!(
+                list.args.first(),
+                Some(FunctionArg::Unnamed(FunctionArgExpr::Expr(_) | FunctionArgExpr::Wildcard))
+            )
+        )
+}
+
+/// Rewrite `FILTER (WHERE cond)` of window aggregates to a `CASE` of their
+/// first argument, as DataFusion ignores the filters of window functions
+///
+/// `sum(x) FILTER (WHERE x > 0) OVER w` becomes
+/// `sum(CASE WHEN x > 0 THEN x END) OVER w` and `count(*)` counts
+/// `CASE WHEN cond THEN 1 END`, which is the same for the aggregates
+/// ignoring `NULL` inputs. [`super::parse`] refuses the filters of the other
+/// window functions.
+#[derive(Debug)]
+pub struct RewriteWindowFilter;
+
+struct RewriteWindowFilterVisitor;
+
+impl VisitorMut for RewriteWindowFilterVisitor {
+    type Break = ();
+
+    fn post_visit_expr(&mut self, expr: &mut Expr) -> ControlFlow<Self::Break> {
+        let Expr::Function(function) = expr else {
+            return ControlFlow::Continue(());
+        };
+        if function.over.is_none() || function.filter.is_none() || !is_rewritable(function) {
+            return ControlFlow::Continue(());
+        }
+        let condition = *function.filter.take().unwrap();
+        let FunctionArguments::List(list) = &mut function.args else {
+            return ControlFlow::Continue(());
+        };
+        let value = match &list.args[0] {
+            FunctionArg::Unnamed(FunctionArgExpr::Expr(value)) => value.clone(),
+            _ => Expr::Value(Value::Number("1".to_string(), false).with_empty_span()),
+        };
+        list.args[0] = FunctionArg::Unnamed(FunctionArgExpr::Expr(Expr::Case {
+            operand: None,
+            conditions: vec![CaseWhen {
+                condition,
+                result: value,
+            }],
+            else_result: None,
+        }));
+        ControlFlow::Continue(())
+    }
+}
+
+impl SqlStatementRewriteRule for RewriteWindowFilter {
+    fn rewrite(&self, mut s: Statement) -> Statement {
+        let _ = VisitMut::visit(&mut s, &mut RewriteWindowFilterVisitor);
+
+        s
+    }
+}
+
+struct WindowFilterVisitor;
+
+impl Visitor for WindowFilterVisitor {
+    type Break = String;
+
+    fn pre_visit_expr(&mut self, expr: &Expr) -> ControlFlow<Self::Break> {
+        match expr {
+            Expr::Function(function)
+                if function.over.is_some()
+                    && function.filter.is_some()
+                    && !is_rewritable(function) =>
+            {
+                ControlFlow::Break(function_name(function))
+            }
+            _ => ControlFlow::Continue(()),
+        }
+    }
+}
+
+/// Refuse the `FILTER` of window functions [`RewriteWindowFilter`] can't
+/// rewrite, rather than answering the unfiltered rows
+pub(super) fn check_window_filters(statements: &[Statement]) -> Result<(), ParserError> {
+    for statement in statements {
+        if let ControlFlow::Break(name) = statement.visit(&mut WindowFilterVisitor) {
+            return Err(ParserError::ParserError(format!(
+                "FILTER of the window function {name} is not supported"
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// The byte offset into `sql` of `location`, whose lines and columns count
+/// characters from 1
+pub(super) fn offset(sql: &str, location: Location) -> usize {
+    let line_start = sql
+        .split_inclusive('\n')
+        .take((location.line as usize).saturating_sub(1))
+        .map(str::len)
+        .sum::<usize>();
+    sql[line_start..]
+        .char_indices()
+        .nth((location.column as usize).saturating_sub(1))
+        .map_or(sql.len(), |(offset, _)| line_start + offset)
+}
+
+pub(super) fn is_word(token: &TokenWithSpan, word: &str) -> bool {
+    matches!(&token.token, Token::Word(w) if w.quote_style.is_none() && w.value.eq_ignore_ascii_case(word))
+}
+
+/// Remove the frame exclusions `EXCLUDE NO OTHERS` of window frames, which
+/// sqlparser doesn't parse, refusing the exclusions of rows
+///
+/// The exclusion is blanked out, so the locations of the errors of the
+/// parser still point into `sql`.
+pub(super) fn remove_frame_exclusions(sql: &str) -> Result<Cow<'_, str>, ParserError> {
+    if !sql.to_ascii_lowercase().contains("exclude") {
+        return Ok(Cow::Borrowed(sql));
+    }
+    // errors are left to the parser
+    let Ok(tokens) = Tokenizer::new(&PostgreSqlDialect {}, sql).tokenize_with_location() else {
+        return Ok(Cow::Borrowed(sql));
+    };
+    let tokens = tokens
+        .iter()
+        .filter(|token| !matches!(token.token, Token::Whitespace(_)))
+        .collect::<Vec<_>>();
+
+    let mut blanks = Vec::new();
+    for (idx, token) in tokens.iter().enumerate() {
+        // the exclusion follows the end of the frame
+        let frame_end = idx
+            .checked_sub(1)
+            .map(|prev| tokens[prev])
+            .is_some_and(|prev| {
+                ["ROW", "PRECEDING", "FOLLOWING"]
+                    .iter()
+                    .any(|w| is_word(prev, w))
+            });
+        if !frame_end || !is_word(token, "EXCLUDE") {
+            continue;
+        }
+        let rest = &tokens[idx + 1..];
+        let (end, exclusion) = match rest {
+            [no, others, ..] if is_word(no, "NO") && is_word(others, "OTHERS") => (others, None),
+            [current, row, ..] if is_word(current, "CURRENT") && is_word(row, "ROW") => {
+                (row, Some("EXCLUDE CURRENT ROW"))
+            }
+            [group, ..] if is_word(group, "GROUP") => (group, Some("EXCLUDE GROUP")),
+            [ties, ..] if is_word(ties, "TIES") => (ties, Some("EXCLUDE TIES")),
+            _ => continue,
+        };
+        if let Some(exclusion) = exclusion {
+            return Err(ParserError::ParserError(format!(
+                "frame exclusion {exclusion} is not supported{}",
+                token.span.start
+            )));
+        }
+        blanks.push(offset(sql, token.span.start)..offset(sql, end.span.end));
+    }
+    if blanks.is_empty() {
+        return Ok(Cow::Borrowed(sql));
+    }
+
+    let blanked = sql
+        .char_indices()
+        .map(|(offset, c)| {
+            if c != '\n' && blanks.iter().any(|blank| blank.contains(&offset)) {
+                ' '
+            } else {
+                c
+            }
+        })
+        .collect();
+    Ok(Cow::Owned(blanked))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql::parse;
+
+    #[test]
+    fn test_window_filter() {
+        let rewrite = |sql: &str| {
+            let statement = parse(sql).unwrap().remove(0);
+            RewriteWindowFilter.rewrite(statement).to_string()
+        };
+        assert_eq!(
+            rewrite(
+                "SELECT count(*) FILTER (WHERE x > 1) OVER (PARTITION BY g), \
+                 sum(x) FILTER (WHERE x > 1) OVER w, sum(x) FILTER (WHERE x > 1) FROM t"
+            ),
+            "SELECT count(CASE WHEN x > 1 THEN 1 END) OVER (PARTITION BY g), \
+             sum(CASE WHEN x > 1 THEN x END) OVER w, sum(x) FILTER (WHERE x > 1) FROM t"
+        );
+
+        let error = parse("SELECT array_agg(x) FILTER (WHERE x > 1) OVER () FROM t").unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("FILTER of the window function array_agg"));
+    }
+
+    #[test]
+    fn test_frame_exclusions() {
+        let sql = "SELECT sum(x) OVER (ORDER BY x ROWS BETWEEN 1 PRECEDING AND CURRENT ROW \
+                   EXCLUDE NO OTHERS) FROM t";
+        assert_eq!(
+            parse(sql).unwrap()[0].to_string(),
+            "SELECT sum(x) OVER (ORDER BY x ROWS BETWEEN 1 PRECEDING AND CURRENT ROW) FROM t"
+        );
+
+        let error =
+            parse("SELECT sum(x) OVER (ORDER BY x RANGE UNBOUNDED PRECEDING EXCLUDE TIES) FROM t")
+                .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "sql parser error: frame exclusion EXCLUDE TIES is not supported at Line: 1, Column: 58"
+        );
+
+        // a column named exclude is no exclusion
+        assert!(parse("SELECT exclude FROM t").is_ok());
+    }
+}
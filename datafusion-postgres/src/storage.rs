@@ -0,0 +1,319 @@
+//! Credentials of object stores, managed with SQL.
+//!
+//! `CREATE STORAGE CREDENTIAL` registers the object store of a location with
+//! the credentials, endpoint and region of its options, for the external
+//! tables created on it afterwards:
+//!
+//! ```sql
+//! CREATE STORAGE CREDENTIAL lake LOCATION 's3://lake'
+//!     OPTIONS (region 'eu-west-1', access_key_id '...', secret_access_key '...');
+//! CREATE EXTERNAL TABLE events STORED AS PARQUET LOCATION 's3://lake/events/';
+//! DROP STORAGE CREDENTIAL lake;
+//! ```
+//!
+//! The options are the configuration keys of the `object_store` builders,
+//! like `endpoint` or `aws_session_token`. S3, Google Cloud Storage and Azure
+//! locations need the `aws`, `gcp` and `azure` features. Credentials are kept
+//! by the server only, they aren't listed anywhere.
+
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+use datafusion::prelude::SessionContext;
+use datafusion::sql::sqlparser::dialect::PostgreSqlDialect;
+use datafusion::sql::sqlparser::keywords::Keyword;
+use datafusion::sql::sqlparser::parser::{Parser, ParserError};
+use datafusion::sql::sqlparser::tokenizer::Token;
+use object_store::{parse_url, parse_url_opts, ObjectStoreScheme};
+use pgwire::error::{ErrorInfo, PgWireError, PgWireResult};
+use url::Url;
+
+use crate::errors::syntax_error;
+use crate::sql::{parse_name, parse_options, parse_word};
+
+fn storage_error(code: &str, message: String) -> PgWireError {
+    PgWireError::UserError(Box::new(ErrorInfo::new(
+        "ERROR".to_string(),
+        code.to_string(),
+        message,
+    )))
+}
+
+/// `CREATE STORAGE CREDENTIAL` and `DROP STORAGE CREDENTIAL`
+#[derive(Debug, PartialEq)]
+pub(crate) enum StorageStatement {
+    Create {
+        name: String,
+        if_not_exists: bool,
+        location: String,
+        options: Vec<(String, String)>,
+    },
+    Drop {
+        name: String,
+        if_exists: bool,
+    },
+}
+
+impl StorageStatement {
+    /// Whether the lowercase query is a statement to parse with
+    /// [`StorageStatement::parse`]
+    pub(crate) fn matches(query_lower: &str) -> bool {
+        let mut words = query_lower.split_whitespace();
+        matches!(words.next(), Some("create" | "drop"))
+            && words.take(2).eq(["storage", "credential"])
+    }
+
+    pub(crate) fn parse(sql: &str) -> PgWireResult<Self> {
+        Self::parse_statement(sql).map_err(|e| syntax_error(e, sql))
+    }
+
+    fn parse_statement(sql: &str) -> Result<Self, ParserError> {
+        let dialect = PostgreSqlDialect {};
+        let mut parser = Parser::new(&dialect).try_with_sql(sql)?;
+        let create =
+            parser.expect_one_of_keywords(&[Keyword::CREATE, Keyword::DROP])? == Keyword::CREATE;
+        if !parse_word(&mut parser, "storage") || !parse_word(&mut parser, "credential") {
+            return parser.expected("STORAGE CREDENTIAL", parser.peek_token());
+        }
+        let statement = if create {
+            let if_not_exists =
+                parser.parse_keywords(&[Keyword::IF, Keyword::NOT, Keyword::EXISTS]);
+            let name = parse_name(&mut parser)?;
+            parser.expect_keyword(Keyword::LOCATION)?;
+            StorageStatement::Create {
+                name,
+                if_not_exists,
+                location: parser.parse_literal_string()?,
+                options: parse_options(&mut parser)?,
+            }
+        } else {
+            let if_exists = parser.parse_keywords(&[Keyword::IF, Keyword::EXISTS]);
+            StorageStatement::Drop {
+                name: parse_name(&mut parser)?,
+                if_exists,
+            }
+        };
+        let _ = parser.consume_token(&Token::SemiColon);
+        parser.expect_token(&Token::EOF)?;
+        Ok(statement)
+    }
+}
+
+/// Whether the object stores of `scheme` are built in, with the check of the
+/// options they take
+fn option_check(scheme: &ObjectStoreScheme) -> Option<fn(&str) -> bool> {
+    match scheme {
+        // local and in-memory stores take no options
+        ObjectStoreScheme::Local | ObjectStoreScheme::Memory => Some(|_| false),
+        #[cfg(feature = "aws")]
+        ObjectStoreScheme::AmazonS3 => {
+            Some(|key| key.parse::<object_store::aws::AmazonS3ConfigKey>().is_ok())
+        }
+        #[cfg(feature = "gcp")]
+        ObjectStoreScheme::GoogleCloudStorage => {
+            Some(|key| key.parse::<object_store::gcp::GoogleConfigKey>().is_ok())
+        }
+        #[cfg(feature = "azure")]
+        ObjectStoreScheme::MicrosoftAzure => {
+            Some(|key| key.parse::<object_store::azure::AzureConfigKey>().is_ok())
+        }
+        _ => None,
+    }
+}
+
+/// The locations of the credentials of `CREATE STORAGE CREDENTIAL`
+///
+/// The credentials themselves only live in the object stores built from
+/// them.
+#[derive(Debug, Default)]
+pub(crate) struct StorageCredentials {
+    locations: RwLock<HashMap<String, Url>>,
+}
+
+impl StorageCredentials {
+    /// Register the object store of `location` with `options` in
+    /// `session_context`
+    pub(crate) fn create(
+        &self,
+        session_context: &SessionContext,
+        name: &str,
+        if_not_exists: bool,
+        location: &str,
+        options: &[(String, String)],
+    ) -> PgWireResult<()> {
+        let url = Url::parse(location).map_err(|_| {
+            storage_error(
+                "22023", // invalid_parameter_value
+                format!("invalid storage location \"{location}\""),
+            )
+        })?;
+        let Some(is_valid_option) = ObjectStoreScheme::parse(&url)
+            .ok()
+            .and_then(|(scheme, _)| option_check(&scheme))
+        else {
+            return Err(storage_error(
+                "0A000", // feature_not_supported
+                format!(
+                    "object stores of {} locations are not supported",
+                    url.scheme()
+                ),
+            ));
+        };
+        if let Some((key, _)) = options.iter().find(|(key, _)| !is_valid_option(key)) {
+            return Err(storage_error(
+                "22023", // invalid_parameter_value
+                format!("invalid option \"{key}\" for {} locations", url.scheme()),
+            ));
+        }
+
+        let mut locations = self.locations.write().unwrap();
+        if locations.contains_key(name) {
+            if if_not_exists {
+                return Ok(());
+            }
+            return Err(storage_error(
+                "42710", // duplicate_object
+                format!("storage credential \"{name}\" already exists"),
+            ));
+        }
+        let (store, _) = parse_url_opts(&url, options.iter().map(|(k, v)| (k, v.clone())))
+            .map_err(|e| PgWireError::ApiError(Box::new(e)))?;
+        session_context.register_object_store(&url, Arc::from(store));
+        locations.insert(name.to_string(), url);
+        Ok(())
+    }
+
+    /// Forget the credential `name`, replacing the object store of its
+    /// location with one without credentials
+    pub(crate) fn remove(
+        &self,
+        session_context: &SessionContext,
+        name: &str,
+        if_exists: bool,
+    ) -> PgWireResult<()> {
+        let Some(url) = self.locations.write().unwrap().remove(name) else {
+            if if_exists {
+                return Ok(());
+            }
+            return Err(storage_error(
+                "42704", // undefined_object
+                format!("storage credential \"{name}\" does not exist"),
+            ));
+        };
+        let (store, _) = parse_url(&url).map_err(|e| PgWireError::ApiError(Box::new(e)))?;
+        session_context.register_object_store(&url, Arc::from(store));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datafusion::execution::object_store::ObjectStoreUrl;
+
+    #[test]
+    fn test_parse_storage_statements() {
+        assert!(StorageStatement::matches("create storage  credential lake"));
+        assert!(StorageStatement::matches("drop storage credential lake"));
+        assert!(!StorageStatement::matches("create storage lake"));
+
+        assert_eq!(
+            StorageStatement::parse(
+                "CREATE STORAGE CREDENTIAL IF NOT EXISTS Lake LOCATION 's3://lake' \
+                 OPTIONS (region 'eu-west-1', endpoint 'http://minio:9000')"
+            )
+            .unwrap(),
+            StorageStatement::Create {
+                name: "lake".to_string(),
+                if_not_exists: true,
+                location: "s3://lake".to_string(),
+                options: vec![
+                    ("region".to_string(), "eu-west-1".to_string()),
+                    ("endpoint".to_string(), "http://minio:9000".to_string()),
+                ],
+            }
+        );
+        assert_eq!(
+            StorageStatement::parse("DROP STORAGE CREDENTIAL IF EXISTS lake;").unwrap(),
+            StorageStatement::Drop {
+                name: "lake".to_string(),
+                if_exists: true,
+            }
+        );
+        let Err(PgWireError::UserError(info)) =
+            StorageStatement::parse("CREATE STORAGE CREDENTIAL lake OPTIONS (region 'x')")
+        else {
+            panic!("expected a syntax error");
+        };
+        assert_eq!(info.code, "42601");
+    }
+
+    #[test]
+    fn test_storage_credentials() {
+        let ctx = SessionContext::new();
+        let credentials = StorageCredentials::default();
+        let code = |error: PgWireError| {
+            let PgWireError::UserError(info) = error else {
+                panic!("expected a user error");
+            };
+            info.code
+        };
+
+        credentials
+            .create(&ctx, "scratch", false, "memory:///", &[])
+            .unwrap();
+        let url = ObjectStoreUrl::parse("memory://").unwrap();
+        assert!(ctx.runtime_env().object_store(&url).is_ok());
+        assert_eq!(
+            code(
+                credentials
+                    .create(&ctx, "scratch", false, "memory:///", &[])
+                    .unwrap_err()
+            ),
+            "42710"
+        );
+        // local and in-memory stores take no options
+        let options = [("region".to_string(), "eu-west-1".to_string())];
+        assert_eq!(
+            code(
+                credentials
+                    .create(&ctx, "other", false, "memory:///", &options)
+                    .unwrap_err()
+            ),
+            "22023"
+        );
+        assert_eq!(
+            code(
+                credentials
+                    .create(&ctx, "other", false, "not a url", &[])
+                    .unwrap_err()
+            ),
+            "22023"
+        );
+
+        credentials.remove(&ctx, "scratch", false).unwrap();
+        assert_eq!(
+            code(credentials.remove(&ctx, "scratch", false).unwrap_err()),
+            "42704"
+        );
+        credentials.remove(&ctx, "scratch", true).unwrap();
+    }
+
+    #[cfg(feature = "aws")]
+    #[test]
+    fn test_s3_credentials() {
+        let ctx = SessionContext::new();
+        let credentials = StorageCredentials::default();
+        let options = [
+            ("region".to_string(), "eu-west-1".to_string()),
+            ("access_key_id".to_string(), "key".to_string()),
+            ("secret_access_key".to_string(), "secret".to_string()),
+            ("endpoint".to_string(), "http://localhost:9000".to_string()),
+        ];
+        credentials
+            .create(&ctx, "lake", false, "s3://lake", &options)
+            .unwrap();
+        let url = ObjectStoreUrl::parse("s3://lake").unwrap();
+        assert!(ctx.runtime_env().object_store(&url).is_ok());
+    }
+}
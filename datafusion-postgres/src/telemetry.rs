@@ -0,0 +1,162 @@
+//! Spans of the phases of the postgres protocol.
+//!
+//! Every phase of a request, from startup to the encoding of result rows,
+//! is a `tracing` span named `pg_phase` and carrying the session, user and
+//! database of the client. The `otel.name` field names the span after its
+//! phase for `tracing-opentelemetry`.
+//!
+//! Servers not using `tracing` can still export the spans, e.g. to an
+//! OpenTelemetry collector, by handing a [`SpanExporter`] to
+//! `ServerOptions::with_span_exporter`.
+
+use std::fmt::Debug;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+use futures::StreamExt;
+use pgwire::api::results::QueryResponse;
+use pgwire::api::{ClientInfo, METADATA_DATABASE, METADATA_USER};
+use tracing::{Instrument, Span};
+
+/// Phase of a request
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Phase {
+    /// Startup message and protocol negotiation
+    Startup,
+    /// Authentication of the client
+    Auth,
+    /// Parsing and rewriting of a statement
+    Parse,
+    /// Binding of parameter values to a prepared statement
+    Bind,
+    /// Logical planning and optimization
+    Plan,
+    /// Physical planning and start of the execution
+    Execute,
+    /// Encoding and sending of result rows, while the plan keeps executing
+    Encode,
+}
+
+impl Phase {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Phase::Startup => "startup",
+            Phase::Auth => "auth",
+            Phase::Parse => "parse",
+            Phase::Bind => "bind",
+            Phase::Plan => "plan",
+            Phase::Execute => "execute",
+            Phase::Encode => "encode",
+        }
+    }
+}
+
+/// Finished span of a phase, as handed to a [`SpanExporter`]
+#[derive(Debug, Clone)]
+pub struct PhaseSpanData {
+    pub phase: Phase,
+    /// Address of the client, identifying its session
+    pub session: SocketAddr,
+    pub user: Option<String>,
+    pub database: Option<String>,
+    pub start_time: SystemTime,
+    pub duration: Duration,
+}
+
+/// Hook receiving the span of every finished phase
+///
+/// `export` is called on the connection task, implementations should queue
+/// spans rather than send them inline.
+pub trait SpanExporter: Debug + Send + Sync {
+    fn export(&self, span: PhaseSpanData);
+}
+
+/// Opens phase spans, exporting them if an exporter is set
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Telemetry {
+    exporter: Option<Arc<dyn SpanExporter>>,
+}
+
+impl Telemetry {
+    pub(crate) fn new(exporter: Option<Arc<dyn SpanExporter>>) -> Self {
+        Telemetry { exporter }
+    }
+
+    /// Open the span of `phase` for the session of `client`, it ends when the
+    /// returned guard is dropped
+    pub(crate) fn phase<C: ClientInfo>(&self, client: &C, phase: Phase) -> PhaseSpan {
+        let metadata = client.metadata();
+        let user = metadata.get(METADATA_USER).cloned();
+        let database = metadata.get(METADATA_DATABASE).cloned();
+        let span = tracing::info_span!(
+            "pg_phase",
+            otel.name = phase.as_str(),
+            phase = phase.as_str(),
+            session = %client.socket_addr(),
+            user = user.as_deref(),
+            database = database.as_deref(),
+        );
+        PhaseSpan {
+            span,
+            exporter: self.exporter.clone(),
+            data: PhaseSpanData {
+                phase,
+                session: client.socket_addr(),
+                user,
+                database,
+                start_time: SystemTime::now(),
+                duration: Duration::ZERO,
+            },
+            started: Instant::now(),
+        }
+    }
+}
+
+/// Guard of an open phase span
+#[derive(Debug)]
+pub(crate) struct PhaseSpan {
+    span: Span,
+    exporter: Option<Arc<dyn SpanExporter>>,
+    data: PhaseSpanData,
+    started: Instant,
+}
+
+impl PhaseSpan {
+    /// Run `future` in the span and end the span with it
+    pub(crate) async fn run<F: Future>(self, future: F) -> F::Output {
+        let span = self.span.clone();
+        future.instrument(span).await
+    }
+
+    /// Take the user and database of `client`, which are only known once
+    /// the startup message is handled
+    pub(crate) fn record_session<C: ClientInfo>(&mut self, client: &C) {
+        let metadata = client.metadata();
+        self.data.user = metadata.get(METADATA_USER).cloned();
+        self.data.database = metadata.get(METADATA_DATABASE).cloned();
+        self.span.record("user", self.data.user.as_deref());
+        self.span.record("database", self.data.database.as_deref());
+    }
+
+    /// Keep the span open until all rows of the response are sent
+    pub(crate) fn hold_for<'a>(self, resp: QueryResponse<'a>) -> QueryResponse<'a> {
+        let fields = resp.row_schema();
+        let data_rows = resp.data_rows().map(move |row| {
+            let _phase = &self;
+            row
+        });
+        QueryResponse::new(fields, data_rows)
+    }
+}
+
+impl Drop for PhaseSpan {
+    fn drop(&mut self) {
+        if let Some(exporter) = &self.exporter {
+            let mut data = self.data.clone();
+            data.duration = self.started.elapsed();
+            exporter.export(data);
+        }
+    }
+}
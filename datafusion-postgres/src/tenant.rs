@@ -0,0 +1,156 @@
+//! Routing of connections to tenants.
+//!
+//! A [`TenantResolver`] maps the user and database of a connection to a
+//! [`Tenant`]: the catalogs its statements can see, where unqualified names
+//! resolve and the limits of its statements. Tenants share the catalogs of
+//! the server, so one server can host many of them isolated from each other.
+
+use std::any::Any;
+use std::sync::Arc;
+
+use datafusion::catalog::{CatalogProvider, CatalogProviderList};
+use datafusion::execution::SessionStateBuilder;
+use datafusion::prelude::SessionContext;
+
+use crate::limits::QueryLimits;
+use crate::session::ConnectionInfo;
+
+/// Catalogs, name resolution and limits of the connections of a tenant
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Tenant {
+    /// Catalogs the tenant can query and list in `pg_catalog`, all catalogs
+    /// if `None`
+    pub catalogs: Option<Vec<String>>,
+    /// Catalog unqualified names resolve in, the default catalog of the
+    /// server if `None`
+    pub default_catalog: Option<String>,
+    /// Schema unqualified names resolve in, reported as `search_path`. The
+    /// default schema of the server if `None`.
+    pub search_path: Option<String>,
+    /// Limits of the statements of each user of the tenant, capping the
+    /// limits of their roles
+    pub limits: QueryLimits,
+}
+
+impl Tenant {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_catalogs<I, S>(mut self, catalogs: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.catalogs = Some(catalogs.into_iter().map(Into::into).collect());
+        self
+    }
+
+    pub fn with_default_catalog(mut self, catalog: impl Into<String>) -> Self {
+        self.default_catalog = Some(catalog.into());
+        self
+    }
+
+    pub fn with_search_path(mut self, schema: impl Into<String>) -> Self {
+        self.search_path = Some(schema.into());
+        self
+    }
+
+    pub fn with_limits(mut self, limits: QueryLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// The context of a connection of the tenant, resolving names in the
+    /// catalogs of `base` the tenant can see
+    pub(crate) fn session_context(&self, base: &SessionContext) -> SessionContext {
+        let state = base.state();
+        let mut builder = SessionStateBuilder::new_from_existing(state.clone());
+        if let Some(catalogs) = &self.catalogs {
+            builder = builder.with_catalog_list(Arc::new(TenantCatalogList::new(
+                state.catalog_list().clone(),
+                catalogs.clone(),
+            )));
+        }
+        let mut state = builder.build();
+
+        let options = &mut state.config_mut().options_mut().catalog;
+        if let Some(catalog) = &self.default_catalog {
+            options.default_catalog = catalog.clone();
+        }
+        if let Some(schema) = &self.search_path {
+            options.default_schema = schema.clone();
+        }
+        SessionContext::new_with_state(state)
+    }
+}
+
+/// Resolves the tenant of each connection
+///
+/// Implemented by closures taking a [`ConnectionInfo`]. The tenant is
+/// resolved when the connection runs its first statement, after the
+/// `SessionContextFactory`, if any, created its context.
+pub trait TenantResolver: Send + Sync {
+    fn resolve(&self, connection: &ConnectionInfo) -> Tenant;
+}
+
+impl<F> TenantResolver for F
+where
+    F: Fn(&ConnectionInfo) -> Tenant + Send + Sync,
+{
+    fn resolve(&self, connection: &ConnectionInfo) -> Tenant {
+        self(connection)
+    }
+}
+
+/// The catalogs of a tenant among those of the server
+///
+/// Catalogs created by the tenant outside of its catalogs are not
+/// registered.
+#[derive(Debug)]
+pub(crate) struct TenantCatalogList {
+    inner: Arc<dyn CatalogProviderList>,
+    catalogs: Vec<String>,
+}
+
+impl TenantCatalogList {
+    pub(crate) fn new(inner: Arc<dyn CatalogProviderList>, catalogs: Vec<String>) -> Self {
+        TenantCatalogList { inner, catalogs }
+    }
+
+    fn contains(&self, name: &str) -> bool {
+        self.catalogs.iter().any(|catalog| catalog == name)
+    }
+}
+
+impl CatalogProviderList for TenantCatalogList {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn register_catalog(
+        &self,
+        name: String,
+        catalog: Arc<dyn CatalogProvider>,
+    ) -> Option<Arc<dyn CatalogProvider>> {
+        if !self.contains(&name) {
+            return None;
+        }
+        self.inner.register_catalog(name, catalog)
+    }
+
+    fn catalog_names(&self) -> Vec<String> {
+        self.inner
+            .catalog_names()
+            .into_iter()
+            .filter(|name| self.contains(name))
+            .collect()
+    }
+
+    fn catalog(&self, name: &str) -> Option<Arc<dyn CatalogProvider>> {
+        if !self.contains(name) {
+            return None;
+        }
+        self.inner.catalog(name)
+    }
+}
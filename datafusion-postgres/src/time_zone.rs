@@ -0,0 +1,207 @@
+//! `SET TIME ZONE`, the time zone of a session.
+//!
+//! The zone of a session is one of the names of `pg_timezone_names` or a
+//! fixed offset from UTC, set with `SET TIME ZONE 'Europe/Berlin'`,
+//! `SET timezone TO ...`, a number of hours or an interval like
+//! `INTERVAL '+02:00' HOUR TO MINUTE`. Statements are planned with it as the
+//! time zone of DataFusion, so casts to `timestamptz` read local times in it,
+//! and the `timestamptz` columns of results are shown in it, like postgres.
+
+use datafusion::arrow::datatypes::DataType;
+use datafusion::common::{Column, Result};
+use datafusion::logical_expr::{cast, Expr, LogicalPlan, LogicalPlanBuilder};
+use datafusion::sql::sqlparser::ast::{
+    DateTimeField, Expr as SqlExpr, Interval, OneOrManyWithParens, Statement, UnaryOperator, Value,
+};
+use pgwire::error::{ErrorInfo, PgWireError, PgWireResult};
+
+use crate::pg_catalog::pg_timezone_names::time_zone_name;
+
+/// The zone `statement` sets the session to if it sets the time zone,
+/// `None` for `LOCAL` and `DEFAULT`, which reset it to UTC
+pub(crate) fn time_zone_setting(statement: &Statement) -> Option<PgWireResult<Option<String>>> {
+    let value = match statement {
+        Statement::SetTimeZone { value, .. } => value,
+        Statement::SetVariable {
+            variables: OneOrManyWithParens::One(name),
+            value,
+            ..
+        } if name.to_string().eq_ignore_ascii_case("timezone") => match value.as_slice() {
+            [value] => value,
+            _ => return Some(Err(invalid_time_zone(&statement.to_string()))),
+        },
+        _ => return None,
+    };
+    Some(time_zone(value))
+}
+
+pub(crate) fn time_zone(value: &SqlExpr) -> PgWireResult<Option<String>> {
+    let name = match value {
+        SqlExpr::Value(literal) => match &literal.value {
+            Value::SingleQuotedString(name) | Value::DoubleQuotedString(name) => name.clone(),
+            Value::Number(hours, _) => return hours_offset(hours, value),
+            literal => literal.to_string(),
+        },
+        SqlExpr::Identifier(ident) => ident.value.clone(),
+        SqlExpr::UnaryOp {
+            op: op @ (UnaryOperator::Minus | UnaryOperator::Plus),
+            expr,
+        } => match expr.as_ref() {
+            SqlExpr::Value(number) if matches!(number.value, Value::Number(..)) => {
+                return hours_offset(&format!("{op}{number}"), value);
+            }
+            _ => return Err(invalid_time_zone(&value.to_string())),
+        },
+        SqlExpr::Interval(interval) => return interval_offset(interval),
+        value => return Err(invalid_time_zone(&value.to_string())),
+    };
+    if name.eq_ignore_ascii_case("local") || name.eq_ignore_ascii_case("default") {
+        return Ok(None);
+    }
+    if let Ok(hours) = name.parse::<f64>() {
+        return hours_offset(&hours.to_string(), value);
+    }
+    time_zone_name(&name)
+        .map(|name| Some(name.to_string()))
+        .ok_or_else(|| invalid_time_zone(&name))
+}
+
+/// The zone `hours` east of UTC
+fn hours_offset(hours: &str, value: &SqlExpr) -> PgWireResult<Option<String>> {
+    match hours.parse::<f64>() {
+        Ok(hours) if hours.abs() < 24.0 => Ok(Some(offset((hours * 3600.0).round() as i64))),
+        _ => Err(invalid_time_zone(&value.to_string())),
+    }
+}
+
+/// The zone of `INTERVAL '+02:00' HOUR TO MINUTE` or `INTERVAL '2' HOUR`
+fn interval_offset(interval: &Interval) -> PgWireResult<Option<String>> {
+    let invalid = || invalid_time_zone(&interval.to_string());
+    let SqlExpr::Value(value) = interval.value.as_ref() else {
+        return Err(invalid());
+    };
+    let Value::SingleQuotedString(text) = &value.value else {
+        return Err(invalid());
+    };
+    let text = text.trim();
+    let (sign, digits) = match text.strip_prefix('-') {
+        Some(digits) => (-1, digits),
+        None => (1, text.strip_prefix('+').unwrap_or(text)),
+    };
+    let seconds = match digits.split_once(':') {
+        Some((hours, minutes)) => {
+            let hours = hours.parse::<i64>().map_err(|_| invalid())?;
+            let minutes = minutes.parse::<i64>().map_err(|_| invalid())?;
+            hours * 3600 + minutes * 60
+        }
+        None => {
+            let number = digits.parse::<i64>().map_err(|_| invalid())?;
+            match interval.leading_field {
+                None | Some(DateTimeField::Hour) => number * 3600,
+                Some(DateTimeField::Minute) => number * 60,
+                _ => return Err(invalid()),
+            }
+        }
+    };
+    if seconds >= 24 * 3600 {
+        return Err(invalid());
+    }
+    Ok(Some(offset(sign * seconds)))
+}
+
+/// The fixed offset zone `seconds` east of UTC, like `+05:30`
+fn offset(seconds: i64) -> String {
+    let sign = if seconds < 0 { '-' } else { '+' };
+    let minutes = seconds.abs() / 60;
+    format!("{sign}{:02}:{:02}", minutes / 60, minutes % 60)
+}
+
+fn invalid_time_zone(value: &str) -> PgWireError {
+    PgWireError::UserError(Box::new(ErrorInfo::new(
+        "ERROR".to_string(),
+        "22023".to_string(), // invalid_parameter_value
+        format!("invalid value for parameter \"TimeZone\": \"{value}\""),
+    )))
+}
+
+/// `plan` with its `timestamptz` columns shown in `time_zone`
+pub(crate) fn in_time_zone(plan: LogicalPlan, time_zone: &str) -> Result<LogicalPlan> {
+    let schema = plan.schema().clone();
+    let shown_elsewhere = |data_type: &DataType| match data_type {
+        DataType::Timestamp(_, Some(zone)) => zone.as_ref() != time_zone,
+        _ => false,
+    };
+    if !schema
+        .fields()
+        .iter()
+        .any(|field| shown_elsewhere(field.data_type()))
+    {
+        return Ok(plan);
+    }
+
+    let exprs = schema
+        .iter()
+        .map(|(qualifier, field)| {
+            let column = Expr::Column(Column::from((qualifier, field)));
+            match field.data_type() {
+                DataType::Timestamp(unit, Some(_)) if shown_elsewhere(field.data_type()) => {
+                    cast(column, DataType::Timestamp(*unit, Some(time_zone.into())))
+                        .alias_qualified(qualifier.cloned(), field.name())
+                }
+                _ => column,
+            }
+        })
+        .collect::<Vec<_>>();
+    LogicalPlanBuilder::from(plan).project(exprs)?.build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setting(sql: &str) -> PgWireResult<Option<String>> {
+        let statement = crate::sql::parse(sql).unwrap().remove(0);
+        time_zone_setting(&statement).unwrap()
+    }
+
+    #[test]
+    fn test_time_zone_setting() {
+        let zone = |sql: &str| setting(sql).unwrap();
+        assert_eq!(
+            zone("SET TIME ZONE 'europe/berlin'").as_deref(),
+            Some("Europe/Berlin")
+        );
+        assert_eq!(zone("SET timezone TO 'UTC'").as_deref(), Some("UTC"));
+        assert_eq!(
+            zone("SET timezone = \"America/New_York\"").as_deref(),
+            Some("America/New_York")
+        );
+        assert_eq!(zone("SET TIME ZONE -7").as_deref(), Some("-07:00"));
+        assert_eq!(zone("SET TIME ZONE 5.5").as_deref(), Some("+05:30"));
+        assert_eq!(zone("SET TIME ZONE '3'").as_deref(), Some("+03:00"));
+        assert_eq!(
+            zone("SET TIME ZONE INTERVAL '+02:00' HOUR TO MINUTE").as_deref(),
+            Some("+02:00")
+        );
+        assert_eq!(
+            zone("SET TIME ZONE INTERVAL '-3' HOUR").as_deref(),
+            Some("-03:00")
+        );
+        assert_eq!(zone("SET TIME ZONE LOCAL"), None);
+        assert_eq!(zone("SET TIME ZONE DEFAULT"), None);
+        assert_eq!(zone("SET timezone TO default"), None);
+
+        let statement = crate::sql::parse("SET search_path TO public").unwrap();
+        assert!(time_zone_setting(&statement[0]).is_none());
+
+        let Err(PgWireError::UserError(info)) = setting("SET TIME ZONE 'Mars/Olympus'") else {
+            panic!("expected an invalid time zone");
+        };
+        assert_eq!(info.code, "22023");
+        assert_eq!(
+            info.message,
+            "invalid value for parameter \"TimeZone\": \"Mars/Olympus\""
+        );
+        assert!(setting("SET TIME ZONE 30").is_err());
+    }
+}
@@ -0,0 +1,331 @@
+//! TLS certificates of the server, chosen by the SNI hostname clients connect
+//! to and reloaded when their files change on disk.
+//!
+//! One server can serve several tenant hostnames this way, each with its own
+//! certificate, and renew certificates without a restart. Either makes the
+//! certificate of a connection differ from the one read at startup, so
+//! SCRAM-SHA-256-PLUS channel binding isn't offered with SNI certificates or
+//! reloading.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, Error as IOError, ErrorKind};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use log::{info, warn};
+use rustls_pemfile::{certs, pkcs8_private_keys};
+use rustls_pki_types::{CertificateDer, PrivateKeyDer};
+use tokio_rustls::rustls::crypto::ring;
+use tokio_rustls::rustls::server::{ClientHello, ResolvesServerCert};
+use tokio_rustls::rustls::sign::CertifiedKey;
+
+/// Certificate served to clients connecting to `hostname`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SniCertificate {
+    /// Server name clients send, matched case-insensitively. A leading `*.`
+    /// matches one label, like `*.example.com` for `db.example.com`.
+    pub hostname: String,
+    pub cert_path: String,
+    pub key_path: String,
+}
+
+impl SniCertificate {
+    pub fn new(
+        hostname: impl Into<String>,
+        cert_path: impl Into<String>,
+        key_path: impl Into<String>,
+    ) -> Self {
+        SniCertificate {
+            hostname: hostname.into(),
+            cert_path: cert_path.into(),
+            key_path: key_path.into(),
+        }
+    }
+}
+
+impl FromStr for SniCertificate {
+    type Err = String;
+
+    /// Parse `hostname:cert_path:key_path`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.splitn(3, ':').collect::<Vec<_>>()[..] {
+            [hostname, cert_path, key_path]
+                if !hostname.is_empty() && !cert_path.is_empty() && !key_path.is_empty() =>
+            {
+                Ok(SniCertificate::new(hostname, cert_path, key_path))
+            }
+            _ => Err(format!(
+                "invalid SNI certificate \"{s}\", expected hostname:cert_path:key_path"
+            )),
+        }
+    }
+}
+
+/// Read the certificate chain and PKCS#8 private key of PEM files
+fn load_certified_key(cert_path: &str, key_path: &str) -> Result<CertifiedKey, IOError> {
+    let cert = certs(&mut BufReader::new(File::open(cert_path)?))
+        .collect::<Result<Vec<CertificateDer>, IOError>>()?;
+
+    let key = pkcs8_private_keys(&mut BufReader::new(File::open(key_path)?))
+        .map(|key| key.map(PrivateKeyDer::from))
+        .collect::<Result<Vec<PrivateKeyDer>, IOError>>()?
+        .into_iter()
+        .next()
+        .ok_or_else(|| IOError::new(ErrorKind::InvalidInput, "No private key found"))?;
+
+    CertifiedKey::from_der(cert, key, &ring::default_provider())
+        .map_err(|err| IOError::new(ErrorKind::InvalidInput, err))
+}
+
+/// Modification times of a certificate and key file
+type Modified = (Option<SystemTime>, Option<SystemTime>);
+
+fn modified(cert_path: &str, key_path: &str) -> Modified {
+    let modified = |path| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+    (modified(cert_path), modified(key_path))
+}
+
+/// Certificate and key files, reloaded when they change on disk if `reload`
+#[derive(Debug)]
+struct CertificateFiles {
+    cert_path: String,
+    key_path: String,
+    reload: bool,
+    loaded: Mutex<(Modified, Arc<CertifiedKey>)>,
+}
+
+impl CertificateFiles {
+    fn load(cert_path: &str, key_path: &str, reload: bool) -> Result<Self, IOError> {
+        let modified = modified(cert_path, key_path);
+        let key = load_certified_key(cert_path, key_path)?;
+        Ok(CertificateFiles {
+            cert_path: cert_path.to_string(),
+            key_path: key_path.to_string(),
+            reload,
+            loaded: Mutex::new((modified, Arc::new(key))),
+        })
+    }
+
+    /// The certified key of the files, reloaded if they changed since they
+    /// were last loaded. The previous key is kept if they fail to load, e.g.
+    /// while they're being replaced.
+    fn certified_key(&self) -> Arc<CertifiedKey> {
+        let mut loaded = self.loaded.lock().unwrap_or_else(|e| e.into_inner());
+        if !self.reload {
+            return loaded.1.clone();
+        }
+        let modified = modified(&self.cert_path, &self.key_path);
+        if loaded.0 != modified {
+            loaded.0 = modified;
+            match load_certified_key(&self.cert_path, &self.key_path) {
+                Ok(key) => {
+                    info!("Reloaded TLS certificate {}", self.cert_path);
+                    loaded.1 = Arc::new(key);
+                }
+                Err(e) => warn!(
+                    "Failed to reload TLS certificate {}: {e}. Keeping the previous one.",
+                    self.cert_path
+                ),
+            }
+        }
+        loaded.1.clone()
+    }
+}
+
+/// Chooses the certificate of a TLS handshake by the SNI hostname of the
+/// client, falling back to the default certificate
+#[derive(Debug)]
+pub(crate) struct SniCertResolver {
+    default: Option<CertificateFiles>,
+    /// Certificates by lowercase hostname
+    hosts: HashMap<String, CertificateFiles>,
+}
+
+impl SniCertResolver {
+    pub(crate) fn new(
+        default: Option<(&str, &str)>,
+        sni_certificates: &[SniCertificate],
+        reload: bool,
+    ) -> Result<Self, IOError> {
+        let default = default
+            .map(|(cert_path, key_path)| CertificateFiles::load(cert_path, key_path, reload))
+            .transpose()?;
+        let hosts = sni_certificates
+            .iter()
+            .map(|certificate| {
+                let files =
+                    CertificateFiles::load(&certificate.cert_path, &certificate.key_path, reload)?;
+                Ok((certificate.hostname.to_lowercase(), files))
+            })
+            .collect::<Result<_, IOError>>()?;
+        Ok(SniCertResolver { default, hosts })
+    }
+
+    fn certified_key(&self, server_name: Option<&str>) -> Option<Arc<CertifiedKey>> {
+        let host = server_name.and_then(|name| {
+            let name = name.to_lowercase();
+            self.hosts.get(&name).or_else(|| {
+                let (_, parent) = name.split_once('.')?;
+                self.hosts.get(&format!("*.{parent}"))
+            })
+        });
+        host.or(self.default.as_ref())
+            .map(CertificateFiles::certified_key)
+    }
+}
+
+impl ResolvesServerCert for SniCertResolver {
+    fn resolve(&self, client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        self.certified_key(client_hello.server_name())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use std::time::Duration;
+
+    use x509_certificate::{EcdsaCurve, KeyAlgorithm, X509CertificateBuilder};
+
+    use super::*;
+
+    /// Write a self-signed certificate issued to `common_name` and its key
+    /// to `dir`
+    fn write_certificate(dir: &Path, common_name: &str) -> (String, String) {
+        let mut builder = X509CertificateBuilder::default();
+        builder
+            .subject()
+            .append_common_name_utf8_string(common_name)
+            .unwrap();
+        let (certificate, key_pair) = builder
+            .create_with_random_keypair(KeyAlgorithm::Ecdsa(EcdsaCurve::Secp256r1))
+            .unwrap();
+        let key = pem::Pem::new(
+            "PRIVATE KEY",
+            key_pair.to_pkcs8_one_asymmetric_key_der().to_vec(),
+        );
+        let cert_path = dir.join(format!("{common_name}.crt"));
+        let key_path = dir.join(format!("{common_name}.key"));
+        fs::write(&cert_path, certificate.encode_pem()).unwrap();
+        fs::write(&key_path, pem::encode(&key)).unwrap();
+        (
+            cert_path.to_string_lossy().into_owned(),
+            key_path.to_string_lossy().into_owned(),
+        )
+    }
+
+    fn served_cert(resolver: &SniCertResolver, server_name: Option<&str>) -> Option<Vec<u8>> {
+        resolver
+            .certified_key(server_name)
+            .map(|key| key.cert[0].to_vec())
+    }
+
+    #[test]
+    fn test_sni_certificates() {
+        let dir: PathBuf =
+            std::env::temp_dir().join(format!("datafusion-postgres-tls-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (default_cert, default_key) = write_certificate(&dir, "default");
+        let (tenant_cert, tenant_key) = write_certificate(&dir, "tenant");
+        let (wildcard_cert, wildcard_key) = write_certificate(&dir, "wildcard");
+        let resolver = SniCertResolver::new(
+            Some((&default_cert, &default_key)),
+            &[
+                SniCertificate::new("Tenant.example.com", &tenant_cert, &tenant_key),
+                SniCertificate::new("*.example.org", &wildcard_cert, &wildcard_key),
+            ],
+            true,
+        )
+        .unwrap();
+
+        let pem_der = |path: &str| {
+            certs(&mut BufReader::new(File::open(path).unwrap()))
+                .next()
+                .unwrap()
+                .unwrap()
+                .to_vec()
+        };
+        let tenant = pem_der(&tenant_cert);
+        assert_eq!(
+            served_cert(&resolver, Some("tenant.example.com")),
+            Some(tenant.clone())
+        );
+        let pinned = SniCertResolver::new(
+            None,
+            &[SniCertificate::new(
+                "tenant.example.com",
+                &tenant_cert,
+                &tenant_key,
+            )],
+            false,
+        )
+        .unwrap();
+        assert_eq!(
+            served_cert(&resolver, Some("db.example.org")),
+            Some(pem_der(&wildcard_cert))
+        );
+        assert_eq!(
+            served_cert(&resolver, Some("other.example.com")),
+            Some(pem_der(&default_cert))
+        );
+        assert_eq!(served_cert(&resolver, None), Some(pem_der(&default_cert)));
+
+        // a renewed certificate is served once its files change
+        let (renewed_cert, renewed_key) = write_certificate(&dir, "renewed");
+        fs::copy(&renewed_cert, &tenant_cert).unwrap();
+        fs::copy(&renewed_key, &tenant_key).unwrap();
+        let later = SystemTime::now() + Duration::from_secs(60);
+        for path in [&tenant_cert, &tenant_key] {
+            File::options()
+                .write(true)
+                .open(path)
+                .unwrap()
+                .set_modified(later)
+                .unwrap();
+        }
+        let renewed = pem_der(&renewed_cert);
+        assert_eq!(
+            served_cert(&resolver, Some("tenant.example.com")),
+            Some(renewed.clone())
+        );
+        // unless reloading is off
+        assert_eq!(
+            served_cert(&pinned, Some("tenant.example.com")),
+            Some(tenant)
+        );
+
+        // and kept when the files fail to load
+        fs::write(&tenant_key, "not a key").unwrap();
+        assert_eq!(
+            served_cert(&resolver, Some("tenant.example.com")),
+            Some(renewed)
+        );
+
+        // without a default, unknown hostnames abort the handshake
+        let resolver = SniCertResolver::new(
+            None,
+            &[SniCertificate::new(
+                "*.example.org",
+                &wildcard_cert,
+                &wildcard_key,
+            )],
+            true,
+        )
+        .unwrap();
+        assert_eq!(served_cert(&resolver, Some("example.com")), None);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_parse_sni_certificate() {
+        assert_eq!(
+            "db.example.com:db.crt:db.key".parse::<SniCertificate>(),
+            Ok(SniCertificate::new("db.example.com", "db.crt", "db.key"))
+        );
+        assert!("db.example.com:db.crt".parse::<SniCertificate>().is_err());
+    }
+}
@@ -0,0 +1,335 @@
+//! Logging of the messages of the postgres protocol.
+//!
+//! With wire debugging on, every message exchanged with a client is logged
+//! with its type, length and decoded content, optionally followed by its
+//! bytes in hex, so driver incompatibilities can be debugged without
+//! capturing packets. Password messages are always redacted.
+//!
+//! Debugging is switched on for the whole server with
+//! `ServerOptions::with_wire_debug`, or for a session with the `wire_debug`
+//! startup parameter or `SET wire_debug = on` (`hex` to add the bytes).
+
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::pin::Pin;
+use std::str::FromStr;
+use std::task::{Context, Poll};
+
+use bytes::BytesMut;
+use futures::Sink;
+use pgwire::api::{ClientInfo, ClientPortalStore, PgWireConnectionState};
+use pgwire::messages::response::TransactionStatus;
+use pgwire::messages::startup::SecretKey;
+use pgwire::messages::{Message, PgWireBackendMessage, PgWireFrontendMessage, ProtocolVersion};
+
+/// Metadata key of the session setting
+pub const METADATA_WIRE_DEBUG: &str = "wire_debug";
+
+/// Type of password, SASL and GSSAPI response messages
+const PASSWORD_MESSAGE_TYPE: u8 = b'p';
+
+/// Summaries longer than this are truncated, the hex dump is not
+const MAX_SUMMARY_LENGTH: usize = 512;
+
+/// Wire debugging mode
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WireDebug {
+    #[default]
+    Off,
+    /// Log the type, length and content of messages
+    On,
+    /// Also log the bytes of messages in hex
+    Hex,
+}
+
+impl FromStr for WireDebug {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "off" | "false" | "0" => Ok(WireDebug::Off),
+            "on" | "true" | "1" => Ok(WireDebug::On),
+            "hex" => Ok(WireDebug::Hex),
+            _ => Err(format!("invalid wire debug mode: {s}")),
+        }
+    }
+}
+
+impl WireDebug {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WireDebug::Off => "off",
+            WireDebug::On => "on",
+            WireDebug::Hex => "hex",
+        }
+    }
+
+    /// Mode of a session with `metadata`, whose `wire_debug` setting
+    /// overrides the server mode
+    pub(crate) fn for_session(self, metadata: &HashMap<String, String>) -> WireDebug {
+        metadata
+            .get(METADATA_WIRE_DEBUG)
+            .and_then(|mode| mode.parse().ok())
+            .unwrap_or(self)
+    }
+}
+
+/// Describe a frontend message, `F` followed by its type, length and content
+fn describe_frontend<M: Message + Debug>(mode: WireDebug, message: &M) -> String {
+    let mut buf = BytesMut::new();
+    if message.encode(&mut buf).is_err() {
+        return describe('F', WireDebug::On, None, &[], &format!("{message:?}"));
+    }
+    match M::message_type() {
+        Some(PASSWORD_MESSAGE_TYPE) => describe(
+            'F',
+            WireDebug::On,
+            M::message_type(),
+            &buf,
+            "PasswordMessage <redacted>",
+        ),
+        message_type => describe('F', mode, message_type, &buf, &format!("{message:?}")),
+    }
+}
+
+/// Describe a message of the startup phase, which pgwire hands over decoded
+fn describe_startup(mode: WireDebug, message: &PgWireFrontendMessage) -> String {
+    match message {
+        PgWireFrontendMessage::Startup(message) => describe_frontend(mode, message),
+        PgWireFrontendMessage::CancelRequest(message) => describe_frontend(mode, message),
+        PgWireFrontendMessage::SslRequest(message) => describe_frontend(mode, message),
+        PgWireFrontendMessage::GssEncRequest(message) => describe_frontend(mode, message),
+        PgWireFrontendMessage::PasswordMessageFamily(message) => describe_frontend(mode, message),
+        PgWireFrontendMessage::Terminate(message) => describe_frontend(mode, message),
+        message => describe('F', WireDebug::On, None, &[], &format!("{message:?}")),
+    }
+}
+
+/// Describe a backend message, `B` followed by its type, length and content
+fn describe_backend(mode: WireDebug, message: &PgWireBackendMessage) -> String {
+    // the message inside the variant, e.g. `ReadyForQuery { status: Idle }`
+    let debug = format!("{message:?}");
+    let summary = debug
+        .split_once('(')
+        .and_then(|(_, message)| message.strip_suffix(')'))
+        .unwrap_or(&debug);
+    let mut buf = BytesMut::new();
+    if message.encode(&mut buf).is_err() {
+        return describe('B', WireDebug::On, None, &[], summary);
+    }
+    let message_type = match message {
+        PgWireBackendMessage::SslResponse(_) | PgWireBackendMessage::GssEncResponse(_) => None,
+        _ => buf.first().copied(),
+    };
+    describe('B', mode, message_type, &buf, summary)
+}
+
+fn describe(
+    direction: char,
+    mode: WireDebug,
+    message_type: Option<u8>,
+    bytes: &[u8],
+    summary: &str,
+) -> String {
+    let mut line = direction.to_string();
+    if let Some(message_type) = message_type {
+        line.push_str(&format!(" '{}'", message_type as char));
+    }
+    line.push_str(&format!(" {} ", bytes.len()));
+    match summary.char_indices().nth(MAX_SUMMARY_LENGTH) {
+        Some((end, _)) => {
+            line.push_str(&summary[..end]);
+            line.push_str("...");
+        }
+        None => line.push_str(summary),
+    }
+    if mode == WireDebug::Hex {
+        line.push_str("\n  hex:");
+        for byte in bytes {
+            line.push_str(&format!(" {byte:02x}"));
+        }
+    }
+    line
+}
+
+/// Client logging every backend message sent to it
+pub(crate) struct WireDebugClient<'c, C> {
+    inner: &'c mut C,
+    mode: WireDebug,
+}
+
+impl<'c, C: ClientInfo> WireDebugClient<'c, C> {
+    /// Wrap `client`, in the mode of its session
+    pub(crate) fn new(client: &'c mut C, server_mode: WireDebug) -> Self {
+        let mode = server_mode.for_session(client.metadata());
+        WireDebugClient {
+            inner: client,
+            mode,
+        }
+    }
+
+    /// Log a message received from the client
+    pub(crate) fn log_frontend<M: Message + Debug>(&self, message: &M) {
+        if self.mode != WireDebug::Off {
+            log::info!(
+                "{} {}",
+                self.inner.socket_addr(),
+                describe_frontend(self.mode, message)
+            );
+        }
+    }
+
+    /// Log a message received from the client before it is authenticated
+    pub(crate) fn log_startup(&self, message: &PgWireFrontendMessage) {
+        if self.mode != WireDebug::Off {
+            log::info!(
+                "{} {}",
+                self.inner.socket_addr(),
+                describe_startup(self.mode, message)
+            );
+        }
+    }
+}
+
+impl<C: ClientInfo> ClientInfo for WireDebugClient<'_, C> {
+    fn socket_addr(&self) -> std::net::SocketAddr {
+        self.inner.socket_addr()
+    }
+
+    fn is_secure(&self) -> bool {
+        self.inner.is_secure()
+    }
+
+    fn protocol_version(&self) -> ProtocolVersion {
+        self.inner.protocol_version()
+    }
+
+    fn set_protocol_version(&mut self, version: ProtocolVersion) {
+        self.inner.set_protocol_version(version)
+    }
+
+    fn pid_and_secret_key(&self) -> (i32, SecretKey) {
+        self.inner.pid_and_secret_key()
+    }
+
+    fn set_pid_and_secret_key(&mut self, pid: i32, secret_key: SecretKey) {
+        self.inner.set_pid_and_secret_key(pid, secret_key)
+    }
+
+    fn state(&self) -> PgWireConnectionState {
+        self.inner.state()
+    }
+
+    fn set_state(&mut self, new_state: PgWireConnectionState) {
+        self.inner.set_state(new_state)
+    }
+
+    fn transaction_status(&self) -> TransactionStatus {
+        self.inner.transaction_status()
+    }
+
+    fn set_transaction_status(&mut self, new_status: TransactionStatus) {
+        self.inner.set_transaction_status(new_status)
+    }
+
+    fn metadata(&self) -> &HashMap<String, String> {
+        self.inner.metadata()
+    }
+
+    fn metadata_mut(&mut self) -> &mut HashMap<String, String> {
+        self.inner.metadata_mut()
+    }
+
+    fn client_certificates<'a>(&self) -> Option<&[rustls_pki_types::CertificateDer<'a>]> {
+        self.inner.client_certificates()
+    }
+}
+
+impl<C: ClientPortalStore> ClientPortalStore for WireDebugClient<'_, C> {
+    type PortalStore = C::PortalStore;
+
+    fn portal_store(&self) -> &Self::PortalStore {
+        self.inner.portal_store()
+    }
+}
+
+impl<C> Sink<PgWireBackendMessage> for WireDebugClient<'_, C>
+where
+    C: ClientInfo + Sink<PgWireBackendMessage> + Unpin,
+{
+    type Error = C::Error;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut *self.inner).poll_ready(cx)
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, item: PgWireBackendMessage) -> Result<(), Self::Error> {
+        if self.mode != WireDebug::Off {
+            log::info!(
+                "{} {}",
+                self.inner.socket_addr(),
+                describe_backend(self.mode, &item)
+            );
+        }
+        Pin::new(&mut *self.inner).start_send(item)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut *self.inner).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut *self.inner).poll_close(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pgwire::messages::response::ReadyForQuery;
+    use pgwire::messages::simplequery::Query;
+    use pgwire::messages::startup::{Password, PasswordMessageFamily};
+
+    use super::*;
+
+    #[test]
+    fn test_describe_messages() {
+        let query = Query::new("SELECT 1".to_string());
+        assert_eq!(
+            describe_frontend(WireDebug::On, &query),
+            "F 'Q' 14 Query { query: \"SELECT 1\" }"
+        );
+        assert_eq!(
+            describe_frontend(WireDebug::Hex, &query),
+            "F 'Q' 14 Query { query: \"SELECT 1\" }\n  hex: 51 00 00 00 0d 53 45 4c 45 43 54 20 31 00"
+        );
+
+        let ready =
+            PgWireBackendMessage::ReadyForQuery(ReadyForQuery::new(TransactionStatus::Idle));
+        assert_eq!(
+            describe_backend(WireDebug::Hex, &ready),
+            "B 'Z' 6 ReadyForQuery { status: Idle }\n  hex: 5a 00 00 00 05 49"
+        );
+    }
+
+    #[test]
+    fn test_redact_passwords() {
+        let password = PgWireFrontendMessage::PasswordMessageFamily(
+            PasswordMessageFamily::Password(Password::new("secret".to_string())),
+        );
+        let line = describe_startup(WireDebug::Hex, &password);
+        assert_eq!(line, "F 'p' 12 PasswordMessage <redacted>");
+        assert!(!line.contains("secret"));
+        assert!(!line.contains("73 65 63"));
+    }
+
+    #[test]
+    fn test_session_mode() {
+        let mut metadata = HashMap::new();
+        assert_eq!(WireDebug::On.for_session(&metadata), WireDebug::On);
+        metadata.insert(METADATA_WIRE_DEBUG.to_string(), "hex".to_string());
+        assert_eq!(WireDebug::Off.for_session(&metadata), WireDebug::Hex);
+        metadata.insert(METADATA_WIRE_DEBUG.to_string(), "off".to_string());
+        assert_eq!(WireDebug::Hex.for_session(&metadata), WireDebug::Off);
+        assert!("loud".parse::<WireDebug>().is_err());
+    }
+}
@@ -0,0 +1,56 @@
+use datafusion::arrow::array::AsArray;
+use datafusion::arrow::datatypes::Int64Type;
+use datafusion::prelude::SessionContext;
+use datafusion_postgres::pg_catalog::setup_pg_catalog;
+
+async fn count(ctx: &SessionContext, sql: &str) -> i64 {
+    let batches = ctx.sql(sql).await.unwrap().collect().await.unwrap();
+    batches[0].column(0).as_primitive::<Int64Type>().value(0)
+}
+
+#[tokio::test]
+pub async fn test_namespace_joins() {
+    let ctx = SessionContext::new();
+    ctx.sql("CREATE TABLE t (id INT)").await.unwrap();
+    setup_pg_catalog(&ctx, "datafusion").unwrap();
+
+    // every relation, function and type is in a listed namespace
+    for (table, namespace) in [
+        ("pg_class", "relnamespace"),
+        ("pg_proc", "pronamespace"),
+        ("pg_type", "typnamespace"),
+    ] {
+        let all = count(&ctx, &format!("SELECT count(*) FROM pg_catalog.{table}")).await;
+        let joined = count(
+            &ctx,
+            &format!(
+                "SELECT count(*) FROM pg_catalog.{table} x \
+                 JOIN pg_catalog.pg_namespace n ON n.oid = x.{namespace}"
+            ),
+        )
+        .await;
+        assert!(all > 0);
+        assert_eq!(joined, all, "{table}.{namespace}");
+    }
+
+    assert_eq!(
+        count(
+            &ctx,
+            "SELECT count(*) FROM pg_catalog.pg_class c \
+             JOIN pg_catalog.pg_namespace n ON n.oid = c.relnamespace \
+             WHERE n.nspname = 'public' AND c.relname = 't'",
+        )
+        .await,
+        1
+    );
+    assert_eq!(
+        count(
+            &ctx,
+            "SELECT count(*) FROM pg_catalog.pg_proc p \
+             JOIN pg_catalog.pg_namespace n ON n.oid = p.pronamespace \
+             WHERE n.nspname = 'pg_catalog' AND p.proname = 'version'",
+        )
+        .await,
+        1
+    );
+}
@@ -1,10 +1,13 @@
 use std::{collections::HashMap, sync::Arc};
 
+use datafusion::logical_expr::LogicalPlan;
 use datafusion::prelude::SessionContext;
 use datafusion_postgres::{auth::AuthManager, pg_catalog::setup_pg_catalog, DfSessionService};
 use futures::Sink;
 use pgwire::{
-    api::{ClientInfo, ClientPortalStore, PgWireConnectionState, METADATA_USER},
+    api::{
+        store::MemPortalStore, ClientInfo, ClientPortalStore, PgWireConnectionState, METADATA_USER,
+    },
     messages::{
         response::TransactionStatus, startup::SecretKey, PgWireBackendMessage, ProtocolVersion,
     },
@@ -17,10 +20,10 @@ pub fn setup_handlers() -> DfSessionService {
     DfSessionService::new(Arc::new(session_context), Arc::new(AuthManager::new()))
 }
 
-#[derive(Debug, Default)]
+#[derive(Default)]
 pub struct MockClient {
     metadata: HashMap<String, String>,
-    portal_store: HashMap<String, String>,
+    portal_store: MemPortalStore<(String, LogicalPlan, Vec<String>)>,
 }
 
 impl MockClient {
@@ -30,14 +33,14 @@ impl MockClient {
 
         MockClient {
             metadata,
-            portal_store: HashMap::default(),
+            portal_store: MemPortalStore::default(),
         }
     }
 }
 
 impl ClientInfo for MockClient {
     fn socket_addr(&self) -> std::net::SocketAddr {
-        "127.0.0.1".parse().unwrap()
+        "127.0.0.1:5432".parse().unwrap()
     }
 
     fn is_secure(&self) -> bool {
@@ -82,7 +85,7 @@ impl ClientInfo for MockClient {
 }
 
 impl ClientPortalStore for MockClient {
-    type PortalStore = HashMap<String, String>;
+    type PortalStore = MemPortalStore<(String, LogicalPlan, Vec<String>)>;
     fn portal_store(&self) -> &Self::PortalStore {
         &self.portal_store
     }
@@ -0,0 +1,74 @@
+mod common;
+
+use common::*;
+use std::sync::Arc;
+
+use datafusion_postgres::pgbench::setup_pgbench_tables;
+use pgwire::api::portal::Portal;
+use pgwire::api::query::{ExtendedQueryHandler, SimpleQueryHandler};
+use pgwire::api::results::Response;
+use pgwire::api::stmt::{QueryParser, StoredStatement};
+use pgwire::messages::extendedquery::Bind;
+
+/// Statements of `pgbench -S` before and while it runs its select-only
+/// script
+const PGBENCH_QUERIES: &[&str] = &[
+    "select count(*) from pgbench_branches",
+    "select o.n, p.partstrat, pg_catalog.count(i.inhparent) \
+     from pg_catalog.pg_class as c \
+     join pg_catalog.pg_namespace as n on (n.oid = c.relnamespace) \
+     cross join lateral (select pg_catalog.array_position(pg_catalog.current_schemas(true), n.nspname)) as o(n) \
+     left join pg_catalog.pg_partitioned_table as p on (p.partrelid = c.oid) \
+     left join pg_catalog.pg_inherits as i on (c.oid = i.inhparent) \
+     where c.relname = 'pgbench_accounts' and o.n is not null \
+     group by 1, 2 \
+     order by 1 asc \
+     limit 1",
+    "SELECT abalance FROM pgbench_accounts WHERE aid = 54321;",
+];
+
+#[tokio::test]
+pub async fn test_pgbench_select_only() {
+    env_logger::init();
+    let service = setup_handlers();
+    setup_pgbench_tables(service.default_session_context(), 1).unwrap();
+    let mut client = MockClient::new();
+
+    for query in PGBENCH_QUERIES {
+        SimpleQueryHandler::do_query(&service, &mut client, query)
+            .await
+            .unwrap_or_else(|e| panic!("failed to run sql: {query}\n{e}"));
+    }
+}
+
+#[tokio::test]
+pub async fn test_pgbench_prepared_select() {
+    let service = setup_handlers();
+    setup_pgbench_tables(service.default_session_context(), 1).unwrap();
+    let mut client = MockClient::new();
+
+    // `pgbench -S -M prepared` binds the account `\set` picks to $1, without
+    // declaring its type
+    let statement = service
+        .query_parser()
+        .parse_sql(
+            &client,
+            "SELECT abalance FROM pgbench_accounts WHERE aid = $1;",
+            &[],
+        )
+        .await
+        .unwrap();
+    let statement = Arc::new(StoredStatement::new("P0_1".to_string(), statement, vec![]));
+    let bind = Bind::new(
+        None,
+        Some("P0_1".to_string()),
+        vec![],
+        vec![Some(bytes::Bytes::from_static(b"54321"))],
+        vec![],
+    );
+    let portal = Portal::try_new(&bind, statement).unwrap();
+    let response = ExtendedQueryHandler::do_query(&service, &mut client, &portal, 0)
+        .await
+        .unwrap();
+    assert!(matches!(response, Response::Query(_)));
+}
@@ -5,7 +5,7 @@ set -e
 # Function to cleanup processes
 cleanup() {
     echo "🧹 Cleaning up processes..."
-    for pid in $CSV_PID $TRANSACTION_PID $PARQUET_PID $RBAC_PID $SSL_PID; do
+    for pid in $CSV_PID $TRANSACTION_PID $PARQUET_PID $RBAC_PID $SSL_PID $BINARY_PID $PGBOUNCER_SERVER_PID $PGBOUNCER_PID; do
         if [ ! -z "$pid" ]; then
             kill -9 $pid 2>/dev/null || true
         fi
@@ -170,6 +170,73 @@ else
 fi
 
 kill -9 $SSL_PID 2>/dev/null || true
+sleep 3
+
+# Test 6: Binary parameters
+echo ""
+echo "🔢 Test 6: Binary Parameters"
+echo "----------------------------"
+wait_for_port 5437
+../target/debug/datafusion-postgres-cli -p 5437 --csv delhi:delhiclimate.csv &
+BINARY_PID=$!
+sleep 5
+
+if python3 test_binary_params.py; then
+    echo "✅ Binary parameter test passed"
+else
+    echo "❌ Binary parameter test failed"
+    kill -9 $BINARY_PID 2>/dev/null || true
+    exit 1
+fi
+
+kill -9 $BINARY_PID 2>/dev/null || true
+sleep 3
+
+# Test 7: PgBouncer in transaction pooling mode, when it is installed
+echo ""
+echo "🔁 Test 7: PgBouncer Transaction Pooling"
+echo "----------------------------------------"
+if command -v pgbouncer >/dev/null 2>&1; then
+    wait_for_port 5438
+    wait_for_port 6438
+    ../target/debug/datafusion-postgres-cli -p 5438 --csv delhi:delhiclimate.csv &
+    PGBOUNCER_SERVER_PID=$!
+
+    PGBOUNCER_DIR=$(mktemp -d)
+    echo '"postgres" ""' > "$PGBOUNCER_DIR/userlist.txt"
+    cat > "$PGBOUNCER_DIR/pgbouncer.ini" <<EOF
+[databases]
+public = host=127.0.0.1 port=5438 dbname=public
+
+[pgbouncer]
+listen_addr = 127.0.0.1
+listen_port = 6438
+unix_socket_dir =
+auth_type = trust
+auth_file = $PGBOUNCER_DIR/userlist.txt
+pool_mode = transaction
+default_pool_size = 1
+server_reset_query_always = 1
+ignore_startup_parameters = extra_float_digits,options
+logfile = $PGBOUNCER_DIR/pgbouncer.log
+EOF
+    pgbouncer "$PGBOUNCER_DIR/pgbouncer.ini" &
+    PGBOUNCER_PID=$!
+    sleep 5
+
+    if python3 test_pgbouncer.py; then
+        echo "✅ PgBouncer test passed"
+    else
+        echo "❌ PgBouncer test failed"
+        cat "$PGBOUNCER_DIR/pgbouncer.log"
+        kill -9 $PGBOUNCER_PID $PGBOUNCER_SERVER_PID 2>/dev/null || true
+        exit 1
+    fi
+
+    kill -9 $PGBOUNCER_PID $PGBOUNCER_SERVER_PID 2>/dev/null || true
+else
+    echo "⏭️  pgbouncer not installed, skipping"
+fi
 
 echo ""
 echo "🎉 All enhanced integration tests passed!"
@@ -184,5 +251,7 @@ echo "  ✅ Improved pg_catalog system tables"
 echo "  ✅ PostgreSQL function compatibility"
 echo "  ✅ Role-based access control (RBAC)"
 echo "  ✅ SSL/TLS encryption support"
+echo "  ✅ Binary parameters of the extended protocol"
+echo "  ✅ Transaction pooling with PgBouncer"
 echo ""
 echo "🚀 Ready for secure production PostgreSQL workloads!"
\ No newline at end of file
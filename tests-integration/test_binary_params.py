@@ -0,0 +1,81 @@
+#!/usr/bin/env python3
+"""Test parameters bound in the binary format, sent back as they were bound."""
+
+import uuid
+from datetime import date, datetime, time, timezone
+from decimal import Decimal
+
+import psycopg
+from psycopg.types.json import Jsonb
+
+
+def main():
+    print("🔍 Testing binary parameters...")
+
+    conn = psycopg.connect("host=127.0.0.1 port=5437 user=postgres dbname=public")
+    conn.autocommit = True
+
+    with conn.cursor() as cur:
+        print("\n🔢 Round trips of binary parameters:")
+        test_round_trips(cur)
+
+        print("\n🧾 Binary parameters stored as text:")
+        test_text_stored_types(cur)
+
+        print("\n🔎 Binary parameters in filters:")
+        test_filters(cur)
+
+    conn.close()
+    print("\n✅ All binary parameter tests passed!")
+
+
+def test_round_trips(cur):
+    """Values bound with %b are decoded and sent back unchanged."""
+    values = [
+        ("bool", True),
+        ("int8", 9_007_199_254_740_993),
+        ("float8", -2.25),
+        ("numeric", Decimal("-123.456")),
+        ("text", "héllo"),
+        ("bytea", b"\x00\x01\xff"),
+        ("date", date(2024, 2, 29)),
+        ("time", time(13, 14, 15, 161718)),
+        ("timestamp", datetime(2024, 2, 29, 13, 14, 15, 161718)),
+        ("timestamptz", datetime(2024, 2, 29, 13, 14, 15, tzinfo=timezone.utc)),
+        ("int8[]", [1, None, 3]),
+        ("text[]", ["a", None, "b"]),
+    ]
+    for name, value in values:
+        cur.execute("SELECT %b", [value])
+        result = cur.fetchone()[0]
+        if isinstance(value, bytes):
+            result = bytes(result)
+        assert result == value, f"{name}: {result!r} != {value!r}"
+        print(f"  ✓ {name}: {value!r}")
+
+    cur.execute("SELECT %b IS NULL", [None])
+    assert cur.fetchone()[0] is True
+    print("  ✓ null")
+
+
+def test_text_stored_types(cur):
+    """uuid and jsonb are decoded into their text."""
+    value = uuid.UUID("550e8400-e29b-41d4-a716-446655440000")
+    cur.execute("SELECT %b", [value])
+    assert str(cur.fetchone()[0]) == str(value)
+    print(f"  ✓ uuid: {value}")
+
+    cur.execute("SELECT %b", [Jsonb({"a": 1})])
+    assert cur.fetchone()[0] in ('{"a": 1}', {"a": 1})
+    print('  ✓ jsonb: {"a": 1}')
+
+
+def test_filters(cur):
+    """Binary parameters compare with the columns of tables."""
+    cur.execute("SELECT count(*) FROM delhi WHERE meantemp > %b", [Decimal("10.5")])
+    assert cur.fetchone()[0] > 0
+    print("  ✓ numeric filter")
+
+
+if __name__ == "__main__":
+    main()
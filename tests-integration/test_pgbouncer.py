@@ -0,0 +1,126 @@
+#!/usr/bin/env python3
+"""
+Tests for datafusion-postgres behind PgBouncer in transaction pooling mode.
+Clients share one server connection, which PgBouncer resets with DISCARD ALL
+between them, so statements must not rely on session state across transactions.
+"""
+
+import psycopg
+
+DSN = 'host=127.0.0.1 port=6438 user=postgres dbname=public'
+
+
+def main():
+    print("🔁 Testing PgBouncer Transaction Pooling")
+    print("=" * 50)
+
+    try:
+        print("\n📝 Test 1: Queries Through the Pooler")
+        test_queries()
+
+        print("\n📝 Test 2: Transactions")
+        test_transactions()
+
+        print("\n📝 Test 3: Failed Transactions")
+        test_failed_transaction()
+
+        print("\n📝 Test 4: Multi-Statement Queries")
+        test_multi_statement_queries()
+
+        print("\n📝 Test 5: Interleaved Clients")
+        test_interleaved_clients()
+
+        print("\n✅ All PgBouncer tests passed!")
+        return 0
+
+    except Exception as e:
+        print(f"\n❌ PgBouncer tests failed: {e}")
+        return 1
+
+
+def connect():
+    # unnamed statements only, named ones would not survive a change of
+    # server connection
+    conn = psycopg.connect(DSN, prepare_threshold=None)
+    conn.autocommit = True
+    return conn
+
+
+def test_queries():
+    """Simple and extended queries through the pooler."""
+    with connect() as conn, conn.cursor() as cur:
+        cur.execute('SELECT count(*) FROM delhi')
+        assert cur.fetchone()[0] > 0
+        print("  ✓ Simple query")
+
+        cur.execute('SELECT %s::int + 1', (41,))
+        assert cur.fetchone()[0] == 42
+        print("  ✓ Parameterized query")
+
+
+def test_transactions():
+    """Transaction statements with modes and their status."""
+    with connect() as conn, conn.cursor() as cur:
+        cur.execute('BEGIN ISOLATION LEVEL SERIALIZABLE, READ ONLY')
+        assert conn.info.transaction_status == psycopg.pq.TransactionStatus.INTRANS
+        cur.execute('SHOW transaction_isolation')
+        assert cur.fetchone()[0] == 'serializable'
+        cur.execute('COMMIT')
+        assert conn.info.transaction_status == psycopg.pq.TransactionStatus.IDLE
+        print("  ✓ BEGIN with transaction modes")
+
+    with connect() as conn:
+        conn.autocommit = False
+        with conn.cursor() as cur:
+            cur.execute('SELECT 1')
+            assert cur.fetchone()[0] == 1
+        conn.commit()
+        print("  ✓ Driver managed transaction")
+
+
+def test_failed_transaction():
+    """A failed transaction reports its status and recovers on ROLLBACK."""
+    with connect() as conn, conn.cursor() as cur:
+        cur.execute('BEGIN')
+        try:
+            cur.execute('SELECT * FROM nonexistent_table_xyz')
+            assert False, "Should have failed"
+        except psycopg.Error:
+            pass
+        assert conn.info.transaction_status == psycopg.pq.TransactionStatus.INERROR
+        cur.execute('ROLLBACK')
+        cur.execute('SELECT 1')
+        assert cur.fetchone()[0] == 1
+        print("  ✓ ROLLBACK of failed transaction")
+
+
+def test_multi_statement_queries():
+    """Several statements in one simple query run in turn."""
+    with connect() as conn:
+        conn.execute("SET DateStyle = 'ISO'; SET extra_float_digits = 3")
+        print("  ✓ Batched SET statements")
+
+        conn.execute('BEGIN; SELECT 1; COMMIT;')
+        assert conn.info.transaction_status == psycopg.pq.TransactionStatus.IDLE
+        print("  ✓ Transaction in one query")
+
+
+def test_interleaved_clients():
+    """Clients sharing the server connection don't see each other's state."""
+    with connect() as first, connect() as second:
+        for _ in range(5):
+            for conn in (first, second):
+                cur = conn.execute('SELECT count(*) FROM delhi WHERE meantemp > %s', (20,))
+                assert cur.fetchone()[0] > 0
+        print("  ✓ Interleaved queries")
+
+        with second.cursor() as cur:
+            cur.execute('BEGIN')
+            cur.execute('SHOW transaction_isolation')
+            assert cur.fetchone()[0] == 'read committed'
+            cur.execute('COMMIT')
+        print("  ✓ Session state reset between clients")
+
+
+if __name__ == "__main__":
+    exit(main())